@@ -0,0 +1,289 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md::{
+    atom_feed, dependencies, feed_entry, render_markdown, search_entries, search_widget, FeedEntry,
+    SearchEntry, YamdrOptions,
+};
+use sha2::{Digest, Sha256, Sha384};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct BuildOptions {
+    /// Emit a search index, and include a search widget in every page
+    pub search: bool,
+    /// Resolve wiki links to the files in the input directory
+    pub wiki_links: bool,
+    /// Emit an Atom feed, `feed.xml`, of the documents with a `date`, for a
+    /// site published at this url
+    pub feed: Option<String>,
+    /// Title of the feed
+    pub feed_title: String,
+    /// Also copy assets referenced by the documents to names with a hash of
+    /// their content, and add `integrity` attributes to the scripts in the
+    /// head, so the site can be cached forever
+    pub hash_assets: bool,
+}
+
+/// All files in `dir`, recursively, relative to `dir`. Hidden files and
+/// directories are skipped.
+pub fn walk(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        let entries = fs::read_dir(dir.join(&relative))
+            .unwrap_or_else(|_| panic!("failed to read directory {}", dir.display()));
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = relative.join(name);
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(path),
+                Ok(_) => files.push(path),
+                Err(_) => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Url path of a file relative to the root of the site.
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Relative url from a page back to the root of the site, e.g. `../../`
+/// for `a/b/page.html`.
+fn root_of(path: &Path) -> String {
+    "../".repeat(path.components().count() - 1)
+}
+
+/// Name of a file with a hash of its content, like `logo.3f2a1b4c.png`.
+fn hashed_name(path: &Path, content: &[u8]) -> String {
+    let hash = Sha256::digest(content);
+    let hash: String = hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, hash, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, hash),
+    }
+}
+
+/// Subresource integrity of a script, like `sha384-...`.
+fn integrity(content: &[u8]) -> String {
+    format!("sha384-{}", STANDARD.encode(Sha384::digest(content)))
+}
+
+/// Value of the attribute `name` of an html start tag like `<script src="..."`.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    tag.match_indices(name).find_map(|(start, _)| {
+        if !tag[..start].ends_with(char::is_whitespace) {
+            return None;
+        }
+        let value = tag[start + name.len()..].trim_start().strip_prefix('=')?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        Some(&value[..value.find(quote)?])
+    })
+}
+
+#[cfg(feature = "integrity")]
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url)
+        .timeout(std::time::Duration::from_secs(10))
+        .call()
+        .map_err(|err| err.to_string())?;
+    let mut content = Vec::new();
+    std::io::Read::read_to_end(&mut response.into_reader(), &mut content)
+        .map_err(|err| err.to_string())?;
+    Ok(content)
+}
+
+#[cfg(not(feature = "integrity"))]
+fn download(_url: &str) -> Result<Vec<u8>, String> {
+    Err("remote scripts require the `integrity` feature".into())
+}
+
+/// `head` with `integrity` attributes added to scripts with a `src`. Remote
+/// scripts are downloaded to hash them, with the `integrity` feature, and
+/// site paths are read from `input`. Scripts that already have an
+/// integrity, or that can't be read, are left as they are.
+fn add_integrity(head: &str, input: &Path) -> String {
+    let mut output = String::with_capacity(head.len());
+    let mut rest = head;
+    while let Some(start) = rest.find("<script") {
+        let Some(end) = rest[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        let tag = &rest[start..end];
+        output += &rest[..end];
+        rest = &rest[end..];
+        let src = attribute(tag, "src");
+        let Some(src) = src.filter(|_| attribute(tag, "integrity").is_none()) else {
+            continue;
+        };
+        let content = match src.split_once("://") {
+            Some(("http" | "https", _)) => download(src),
+            Some(_) => Err("unsupported scheme".to_string()),
+            None if src.starts_with("//") => Err("scheme relative url".to_string()),
+            None => {
+                fs::read(input.join(src.trim_start_matches('/'))).map_err(|err| err.to_string())
+            }
+        };
+        match content {
+            Ok(content) => {
+                output += &format!(
+                    r#" integrity="{}" crossorigin="anonymous""#,
+                    integrity(&content)
+                );
+            }
+            Err(err) => eprintln!("failed to read script {} for its integrity: {}", src, err),
+        }
+    }
+    output + rest
+}
+
+/// New names of the assets referenced by the documents in `input`, with a
+/// hash of their content, by their path in `input`.
+fn hash_assets(
+    options: &YamdrOptions,
+    input: &Path,
+    files: &[PathBuf],
+) -> BTreeMap<PathBuf, String> {
+    let is_markdown = |file: &Path| file.extension().and_then(|ext| ext.to_str()) == Some("md");
+    // Assets by their canonical path, as documents can reference them
+    // through `..`
+    let assets: BTreeMap<PathBuf, &PathBuf> = files
+        .iter()
+        .filter(|file| !is_markdown(file))
+        .filter_map(|file| Some((input.join(file).canonicalize().ok()?, file)))
+        .collect();
+    let mut names = BTreeMap::new();
+    for file in files.iter().filter(|file| is_markdown(file)) {
+        let markdown = crate::read_file(&input.join(file).to_string_lossy());
+        let options = YamdrOptions {
+            base_dir: Some(crate::document_dir(&input.join(file))),
+            ..options.clone()
+        };
+        for dependency in dependencies(&options, &markdown) {
+            let asset = dependency
+                .canonicalize()
+                .ok()
+                .and_then(|path| assets.get(&path));
+            let Some(&asset) = asset.filter(|asset| !names.contains_key(**asset)) else {
+                continue;
+            };
+            let content = fs::read(input.join(asset))
+                .unwrap_or_else(|_| panic!("failed to read {}", asset.display()));
+            names.insert(asset.clone(), hashed_name(asset, &content));
+        }
+    }
+    names
+}
+
+/// Render every markdown file in `input` to a html file with the same
+/// relative path in `output`, and copy all other files as they are, and
+/// also to a name with a hash of their content with
+/// `BuildOptions::hash_assets`.
+pub fn build(options: &YamdrOptions, build_options: &BuildOptions, input: &Path, output: &Path) {
+    let files = walk(input);
+    let vault = build_options
+        .wiki_links
+        .then(|| crate::wiki::Vault::new(input));
+    let mut search_index: Vec<SearchEntry> = Vec::new();
+    let mut feed: Vec<FeedEntry> = Vec::new();
+    let mut options = options.clone();
+    let asset_names = match build_options.hash_assets {
+        true => hash_assets(&options, input, &files),
+        false => BTreeMap::new(),
+    };
+    if build_options.hash_assets {
+        options.additional_head = options
+            .additional_head
+            .map(|head| add_integrity(&head, input));
+    }
+
+    for file in files.iter() {
+        let target = output.join(file);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .unwrap_or_else(|_| panic!("failed to create directory {}", parent.display()));
+        }
+        if file.extension().and_then(|ext| ext.to_str()) != Some("md") {
+            // Assets are also kept under their own name, for references
+            // that aren't rewritten, like raw html and stylesheets
+            let hashed = asset_names
+                .get(file)
+                .map(|name| target.with_file_name(name));
+            for target in [Some(target), hashed].into_iter().flatten() {
+                fs::copy(input.join(file), &target)
+                    .unwrap_or_else(|_| panic!("failed to copy {}", file.display()));
+            }
+            continue;
+        }
+
+        let markdown = crate::read_file(&input.join(file).to_string_lossy());
+        let target = target.with_extension("html");
+        let page = file.with_extension("html");
+
+        let mut options = options.clone();
+        options.path = Some(input.join(file));
+        options.base_dir = Some(crate::document_dir(&input.join(file)));
+        options.asset_names = asset_names
+            .iter()
+            .map(|(asset, name)| (input.join(asset), name.clone()))
+            .collect();
+        if let Some(vault) = &vault {
+            options.wiki_links = Some(vault.links(&crate::document_dir(&input.join(file))));
+        }
+        if build_options.search {
+            let root = root_of(&page);
+            let widget = search_widget(&format!("{}search-index.js", root), &root);
+            options.additional_body =
+                Some(options.additional_body.unwrap_or_default() + widget.as_str());
+        }
+        // The search index and the feed are plain text, so encrypted pages
+        // are left out of them
+        let encrypted = options
+            .standalone
+            .as_ref()
+            .is_some_and(|standalone| standalone.encryption.is_some());
+        if build_options.feed.is_some() && !encrypted {
+            feed.extend(feed_entry(&url_path(&page), &markdown));
+        }
+
+        let (meta, html) = render_markdown(&options, &markdown);
+        for error in meta.errors.iter() {
+            eprintln!("{}: {}", file.display(), error);
+        }
+        if build_options.search && !encrypted {
+            search_index.extend(search_entries(&url_path(&page), &meta));
+        }
+        fs::write(&target, html)
+            .unwrap_or_else(|_| panic!("failed to write output to {}", target.display()));
+    }
+
+    if build_options.search {
+        let json = serde_json::to_string(&search_index).expect("failed to serialize search index");
+        fs::write(output.join("search-index.json"), &json).expect("failed to write search index");
+        fs::write(
+            output.join("search-index.js"),
+            format!("window.YAMDR_SEARCH_INDEX = {};\n", json),
+        )
+        .expect("failed to write search index");
+    }
+
+    if let Some(site_url) = &build_options.feed {
+        fs::write(
+            output.join("feed.xml"),
+            atom_feed(&build_options.feed_title, site_url, &feed),
+        )
+        .expect("failed to write feed");
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Open `path` with the default application, like a browser for html.
+pub fn open(path: &Path) -> Result<(), String> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = command
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| format!("failed to open {}: {}", path.display(), err))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("failed to open {}: {}", path.display(), status)),
+    }
+}
+
+/// Clipboard commands to try, in order. The clipboard is owned by these
+/// tools, so the content is kept after yamdr exits.
+fn clipboard_commands() -> &'static [&'static [&'static str]] {
+    if cfg!(target_os = "macos") {
+        &[&["pbcopy"]]
+    } else if cfg!(windows) {
+        &[&["clip"]]
+    } else {
+        &[
+            &["wl-copy"],
+            &["xclip", "-selection", "clipboard"],
+            &["xsel", "--clipboard", "--input"],
+        ]
+    }
+}
+
+/// Copy `text` to the system clipboard.
+pub fn copy(text: &str) -> Result<(), String> {
+    for command in clipboard_commands() {
+        let Ok(mut child) = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        let written = child
+            .stdin
+            .take()
+            .map(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        if child.wait().is_ok_and(|status| status.success()) && written == Some(true) {
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "no clipboard command found, tried {}",
+        clipboard_commands()
+            .iter()
+            .map(|command| command[0])
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
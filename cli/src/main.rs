@@ -1,17 +1,185 @@
+mod build;
+mod desktop;
+mod wiki;
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Query};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
 use axum::response::sse::{Event, KeepAlive, Sse};
-use axum::{routing::get, Router};
+use axum::response::{IntoResponse, Response};
+use axum::{
+    routing::{get, post},
+    Json, Router,
+};
 use clap::{Parser, Subcommand};
-use futures::stream::{self, Stream};
-use md::{render_markdown, Format, StandaloneOptions, YamdrOptions};
+use md::{
+    dependencies, render_blocks, render_blocks_cached, render_markdown, verify, BlockChange,
+    BlockTiming, EncryptionOptions, Format, InterpolationOptions, Locale, NumberFormat,
+    RenderCache, SignatureOptions, SpeechFormat, StandaloneOptions, TranslationFormat,
+    YamdrOptions,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use tokio_stream::StreamExt as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt as _};
 
 #[derive(Parser, Debug)]
 #[command(name = "yamdr", about = "TODO about", long_about = None)]
 struct Args {
     /// Markdown file to parse
     #[arg(short, long)]
-    file: String,
+    file: Option<String>,
+
+    /// Language of the document, used for generated labels and text
+    /// direction, unless set in the document
+    #[arg(long)]
+    lang: Option<String>,
+
+    /// Write numbers and dates in tables and charts for a locale, like
+    /// `de` for 1.234,5 and 31.12.2024
+    #[arg(long)]
+    number_locale: Option<String>,
+
+    /// Make the output only depend on the input, e.g. for snapshot tests
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+
+    /// Only parse plain CommonMark, and only read fenced code blocks with
+    /// a `{...}` header as custom blocks
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Replace `{{variable}}`s in text with values from meta blocks and
+    /// scripts
+    #[arg(long, default_value_t = false)]
+    interpolate: bool,
+
+    /// Fail on undefined variables, implies --interpolate
+    #[arg(long, default_value_t = false)]
+    strict_variables: bool,
+
+    /// Set a variable for `If` blocks, interpolation and scripts, as
+    /// `name=value`. Scripts read it as `vars.name`
+    #[arg(long = "var", visible_alias = "define", value_parser = parse_variable)]
+    variables: Vec<(String, String)>,
+
+    /// Render the Redact blocks for an audience, like `internal`. Can be
+    /// given multiple times
+    #[arg(long = "audience")]
+    audiences: Vec<String>,
+
+    /// Data exported by an earlier `render --export-data`, available to
+    /// scripts as `previous`. A missing file is ignored, like on the first
+    /// run, and `previous` is `()`
+    #[arg(long)]
+    previous: Option<PathBuf>,
+
+    /// Leave TODOs out of the output, for the final version of a document
+    #[arg(long = "final", default_value_t = false)]
+    hide_todos: bool,
+
+    /// Render [[Page Name]] and ![[image.png]] as links to the files with
+    /// those names in the directory of the document, or the input
+    /// directory of build
+    #[arg(long, default_value_t = false)]
+    wiki_links: bool,
+
+    /// Turn bare urls and email addresses into links
+    #[arg(long, default_value_t = false)]
+    autolink: bool,
+
+    /// Replace (c), ... and -- with ©, … and – in html, unless turned off
+    /// in the document
+    #[arg(long, default_value_t = false)]
+    typography: bool,
+
+    /// Number the headings, like 1., 1.1 and 1.1.1, unless turned off in
+    /// the document
+    #[arg(long, default_value_t = false)]
+    number_headings: bool,
+
+    /// Embed a hash of the source in the footer of the html, to check it
+    /// with `verify`
+    #[arg(long, default_value_t = false)]
+    sign: bool,
+
+    /// Also sign the source with the key in this file, implies --sign
+    #[arg(long)]
+    signing_key: Option<PathBuf>,
+
+    /// Encrypt the content of the html with a passphrase, asked for when
+    /// the page is opened. The passphrase is read from YAMDR_PASSPHRASE,
+    /// or from stdin if it isn't set
+    #[arg(long, default_value_t = false)]
+    encrypt: bool,
+
+    /// Stylesheet added to the head of rendered html, after the default
+    /// style
+    #[arg(long)]
+    css: Option<PathBuf>,
+
+    /// Html page to render documents into, with placeholders like
+    /// `{{content}}`, `{{style}}` and `{{title}}`
+    #[arg(long)]
+    template: Option<PathBuf>,
+
+    /// File with html added to the head of rendered html
+    #[arg(long)]
+    head_file: Option<PathBuf>,
+
+    /// File with html added to the start of the body of rendered html
+    #[arg(long)]
+    body_file: Option<PathBuf>,
+
+    /// Check that Rust code in Code blocks with `check: true` compiles,
+    /// with rustc
+    #[arg(long, default_value_t = false)]
+    check_code: bool,
+
+    /// Check code in a language with a command, as `language=command`,
+    /// instead of the default for Rust. The path of a file with the code is
+    /// added to the command. Implies --check-code
+    #[arg(long = "check-command", value_parser = parse_variable)]
+    check_commands: Vec<(String, String)>,
+
+    /// Render the diagrams of Mermaid blocks to svg with mermaid-cli, `mmdc`,
+    /// instead of in the browser
+    #[arg(long, default_value_t = false)]
+    render_mermaid: bool,
+
+    /// Render Mermaid diagrams with a command reading the diagram from
+    /// stdin and writing svg to stdout, instead of `mmdc`. Implies
+    /// --render-mermaid
+    #[arg(long)]
+    mermaid_command: Option<String>,
+
+    /// Make the svgs of graphs and charts smaller
+    #[arg(long, default_value_t = false)]
+    minify_svg: bool,
+
+    /// Make the tables of DynamicTable and Data blocks sortable and
+    /// filterable, unless a block sets `interactive: false`
+    #[arg(long, default_value_t = false)]
+    interactive_tables: bool,
+
+    /// Allow Sql blocks to query this SQLite database, can be repeated.
+    /// Requires the `sqlite` feature
+    #[arg(long = "allow-db")]
+    databases: Vec<PathBuf>,
+
+    /// Allow Metrics blocks to scrape urls on the same host as this url,
+    /// below its path, can be repeated. Requires the `metrics` feature
+    #[arg(long = "allow-metrics")]
+    metrics_urls: Vec<String>,
+
+    /// Fetch the titles of issues and pull requests referenced like #1234
+    /// in documents with a GitHub `repository`. Requires the `issues`
+    /// feature
+    #[arg(long, default_value_t = false)]
+    issue_titles: bool,
 
     #[command(subcommand)]
     command: Commands,
@@ -22,18 +190,274 @@ enum Commands {
     /// Render file to html
     #[command(arg_required_else_help = true)]
     Render {
-        /// output file or "-" for stdout
-        output: String,
+        /// output file or "-" for stdout, may be left out with --open or
+        /// --copy
+        #[arg(required_unless_present_any = ["open", "copy"])]
+        output: Option<String>,
 
         /// Output format
         #[arg(long)]
         format: Option<String>,
+
+        /// Open the output with the default application, like a browser.
+        /// Without an output file, it is written to a temporary file
+        #[arg(long, default_value_t = false)]
+        open: bool,
+
+        /// Copy the output to the clipboard
+        #[arg(long, default_value_t = false)]
+        copy: bool,
+
+        /// Write the Data blocks, the output of DynamicTable blocks and of
+        /// DynamicChart blocks with an id, and values exported by scripts
+        /// with `export_data(name, value)` to a JSON file, to be read by
+        /// other tools, or with --previous in the next render
+        #[arg(long)]
+        export_data: Option<PathBuf>,
     },
     /// Serve rendered file
+    ///
+    /// Besides the rendered document on `/`, the server exposes a small
+    /// JSON api: `GET /api/blocks` returns the blocks of the served file,
+    /// and `POST /api/render` returns the blocks of the markdown sent as
+    /// the request body.
+    ///
+    /// Any other path is served as a static file from the directory of the
+    /// served file, so relative images and links work.
+    ///
+    /// Open `/?debug=timing` to show how long each block took to render,
+    /// and log the slowest blocks.
+    ///
+    /// With `--edit`, `POST /api/paste` converts clipboard content for an
+    /// editor: `text/html` is converted to markdown, like `import`, and
+    /// images are saved next to the served file. The response is the
+    /// markdown to insert, like `![](pasted-1a2b3c.png)`.
     Serve {
         #[arg(long, short, default_value_t = false)]
         watch: bool,
+
+        /// Time every render, like `?debug=timing`, also when watching
+        #[arg(long, default_value_t = false)]
+        profile: bool,
+
+        /// Serve assets under this path prefix instead of "/", and rewrite
+        /// relative urls in the document to point to it
+        #[arg(long)]
+        asset_prefix: Option<String>,
+
+        /// Enable endpoints for editors that write next to the served file
+        #[arg(long, default_value_t = false)]
+        edit: bool,
+    },
+    /// Build a static site from a directory
+    ///
+    /// Every markdown file in the input directory is rendered to html, and
+    /// all other files are copied as they are.
+    Build {
+        /// Directory containing markdown files and assets
+        input: PathBuf,
+
+        /// Directory to write the site to
+        output: PathBuf,
+
+        /// Generate a search index, and add a search field to every page.
+        /// Pages encrypted with `--encrypt` are left out of the index
+        #[arg(long, default_value_t = false)]
+        search: bool,
+
+        /// Generate an Atom feed, feed.xml, of the documents with a `date` in
+        /// their meta blocks, for a site published at this url. Pages
+        /// encrypted with `--encrypt` are left out of the feed
+        #[arg(long, value_name = "SITE_URL")]
+        feed: Option<String>,
+
+        /// Title of the feed, the name of the input directory by default
+        #[arg(long, requires = "feed")]
+        feed_title: Option<String>,
+
+        /// Copy images and other assets referenced by the documents to names
+        /// with a hash of their content, like `logo.3f2a1b4c.png`, and add
+        /// `integrity` attributes to the scripts in --head-file, so the site
+        /// can be cached forever. Remote scripts are only hashed with the
+        /// `integrity` feature. The assets are still copied to their own
+        /// names for references that aren't rewritten, like raw html
+        #[arg(long, default_value_t = false)]
+        hash_assets: bool,
+    },
+    /// Report problems in a file without rendering it to a file
+    ///
+    /// Errors in blocks, unknown or mistyped header fields, deprecated block
+    /// types and undefined variables are listed, one per line. Exits with
+    /// status 1 if there are any.
+    Check,
+    /// List the TODOs of a file
+    ///
+    /// Both `Todo` blocks and `TODO:`s in text are listed, with the heading
+    /// of the section they are in.
+    Todos,
+    /// List the files a document depends on
+    ///
+    /// Images and files read by blocks, like videos and API specs, are
+    /// listed one per line, relative to the current directory.
+    Deps {
+        /// Print a DOT graph from the document to its dependencies
+        #[arg(long, default_value_t = false, conflicts_with = "make")]
+        dot: bool,
+
+        /// Print a make rule for this target, which can also be used as a
+        /// depfile by ninja
+        #[arg(long)]
+        make: Option<String>,
+    },
+    /// Write the code of Code blocks with a `filename` to files
+    ///
+    /// Blocks with the same `filename` are joined, in the order they are
+    /// written. Filenames are relative to the output directory, and can't
+    /// point outside of it.
+    Tangle {
+        /// Directory to write the files to
+        #[arg(long, short, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Convert a document in another format to yamdr markdown
+    ///
+    /// The format is given by the extension of the input: `ipynb` for
+    /// Jupyter notebooks, `org` for org-mode, `rst` for reStructuredText
+    /// and `html` for web pages.
+    Import {
+        /// Document to convert
+        input: PathBuf,
+
+        /// Output file, or stdout if left out
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Check that a rendered html file was rendered from a source file
+    ///
+    /// The html must be rendered with --sign, and if --signing-key is set,
+    /// signed with the same key.
+    Verify {
+        /// Rendered html file
+        html: PathBuf,
+
+        /// Markdown source of the html file
+        source: PathBuf,
+    },
+    /// Compare two versions of a document block by block
+    ///
+    /// Added, removed and changed blocks are listed one per line, prefixed
+    /// with `+`, `-` and `~` and the position of the block, which suits prose
+    /// better than a diff of lines. Exits with status 1 if there are any.
+    Diff {
+        /// Old version of the document
+        old: PathBuf,
+
+        /// New version of the document
+        new: PathBuf,
+
+        /// Also write a html page with the rendered blocks, and the changed
+        /// words of changed blocks marked
+        #[arg(long)]
+        html: Option<PathBuf>,
+    },
+    /// Merge two versions of a document changed from a common base
+    ///
+    /// Paragraphs, lists, tables and custom blocks are merged as a whole, so
+    /// a conflict covers the blocks changed in both versions instead of
+    /// some of their lines. Exits with status 1 if there are conflicts.
+    ///
+    /// To use it as a git merge driver, add `*.md merge=yamdr` to
+    /// `.gitattributes`, and set `merge.yamdr.driver` to
+    /// `yamdr-cli merge %O %A %B -o %A`.
+    Merge {
+        /// Common base of the two versions
+        base: PathBuf,
+
+        /// Our version of the document
+        ours: PathBuf,
+
+        /// Their version of the document
+        theirs: PathBuf,
+
+        /// Output file, or stdout if left out
+        #[arg(long, short)]
+        output: Option<PathBuf>,
     },
+    /// Export the text of a file to be translated
+    ///
+    /// Each paragraph, heading, list, quote and table is a unit, keyed by a
+    /// hash of its markdown. Code, custom and html blocks aren't exported,
+    /// and are kept as they are by `apply`.
+    Extract {
+        /// Output file, or stdout if left out
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Format of the output, `json` or `xliff`. By default the extension
+        /// of the output file, or `json`
+        #[arg(long)]
+        format: Option<String>,
+    },
+    /// Write a translated version of a file, from translations of the text
+    /// exported by `extract`
+    ///
+    /// Blocks that aren't translated, like blocks changed after the text
+    /// was exported, are kept as they are, and counted on stderr.
+    Apply {
+        /// Translations, in a `json` or `xliff` file
+        translations: PathBuf,
+
+        /// Output file, or stdout if left out
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Write a script of a file to be read aloud by a text-to-speech engine
+    ///
+    /// Code isn't read, and images and charts are described by their `alt`
+    /// text.
+    Speak {
+        /// Output file, or stdout if left out
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Format of the output, `ssml` or `txt`. By default the extension of
+        /// the output file, or `ssml`
+        #[arg(long)]
+        format: Option<String>,
+    },
+    /// Compare rendered documents with golden files
+    ///
+    /// Every `name.md` in the directory is rendered and compared with
+    /// `name.html` and `name.expected.md`, the golden files checked in next
+    /// to it.
+    Test {
+        /// Directory containing the documents and golden files
+        dir: PathBuf,
+
+        /// Write the current output as the new golden files
+        #[arg(long, default_value_t = false)]
+        bless: bool,
+    },
+}
+
+impl Args {
+    fn page_files(&self) -> PageFiles {
+        PageFiles {
+            css: self.css.clone(),
+            head: self.head_file.clone(),
+            body: self.body_file.clone(),
+        }
+    }
+
+    fn file(&self) -> String {
+        match &self.file {
+            Some(file) => file.clone(),
+            None => {
+                eprintln!("error: the command requires a file, set with --file");
+                std::process::exit(2);
+            }
+        }
+    }
 }
 
 static HOT_RELOAD_JS: &str = r#"
@@ -46,100 +470,944 @@ eventSource.onmessage = function(e) {
     htmlElement.innerHTML = e.data;
   }
 };
+// Changes to the stylesheet are applied without rendering the document again
+eventSource.addEventListener("css", function(e) {
+  const style = document.getElementById("yamdr-css");
+  if (style) {
+    style.textContent = e.data;
+  }
+});
+// Expensive blocks are sent after the document, and replace their placeholders
+eventSource.addEventListener("block", function(e) {
+  const block = JSON.parse(e.data);
+  const placeholder = document.querySelector(`[data-deferred="${block.id}"]`);
+  if (placeholder) {
+    placeholder.outerHTML = block.html;
+  }
+});
 </script>
 "#;
 
 fn get_modified(file: &str) -> Option<std::time::SystemTime> {
-    std::fs::metadata(file)
-        .ok()
-        .map(|m| m.modified().ok())
-        .flatten()
+    std::fs::metadata(file).ok().and_then(|m| m.modified().ok())
+}
+
+pub fn read_file(file: &str) -> String {
+    fs::read_to_string(file).unwrap_or_else(|_| panic!("failed to read file {}", file))
+}
+
+/// Data of a previous render, from `--previous`, or `None` if the file
+/// doesn't exist yet.
+fn read_previous(path: &Path) -> Option<BTreeMap<String, serde_json::Value>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => panic!("failed to read file {}: {}", path.display(), err),
+    };
+    match serde_json::from_str(&data) {
+        Ok(previous) => Some(previous),
+        Err(err) => {
+            eprintln!("error: invalid data in {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Passphrase for `--encrypt`, from `YAMDR_PASSPHRASE` or stdin, so it
+/// isn't in the shell history.
+fn read_passphrase() -> String {
+    if let Ok(passphrase) = std::env::var("YAMDR_PASSPHRASE") {
+        return passphrase;
+    }
+    eprint!("Passphrase: ");
+    let mut passphrase = String::new();
+    std::io::stdin()
+        .read_line(&mut passphrase)
+        .expect("failed to read passphrase");
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']).to_string();
+    if passphrase.is_empty() {
+        eprintln!("error: empty passphrase");
+        std::process::exit(1);
+    }
+    passphrase
+}
+
+/// Files with css and html added to rendered pages, from `--css`,
+/// `--head-file` and `--body-file`.
+#[derive(Clone, Default)]
+struct PageFiles {
+    css: Option<PathBuf>,
+    head: Option<PathBuf>,
+    body: Option<PathBuf>,
+}
+
+impl PageFiles {
+    fn css(&self) -> Option<String> {
+        self.css
+            .as_ref()
+            .map(|path| read_file(&path.to_string_lossy()))
+    }
+
+    /// `options` with the files added to the head and body, before what is
+    /// already there. The files are read again every time, so changes are
+    /// picked up while serving.
+    fn apply(&self, options: &YamdrOptions) -> YamdrOptions {
+        let mut head = String::new();
+        if let Some(css) = self.css() {
+            head += &format!("<style id=\"yamdr-css\">\n{}\n</style>\n", css);
+        }
+        if let Some(path) = &self.head {
+            head += &read_file(&path.to_string_lossy());
+        }
+        let mut body = self
+            .body
+            .as_ref()
+            .map(|path| read_file(&path.to_string_lossy()))
+            .unwrap_or_default();
+        head += options.additional_head.as_deref().unwrap_or_default();
+        body += options.additional_body.as_deref().unwrap_or_default();
+        YamdrOptions {
+            additional_head: (!head.is_empty()).then_some(head),
+            additional_body: (!body.is_empty()).then_some(body),
+            ..options.clone()
+        }
+    }
+
+    /// Last modification of the stylesheet, and of the head and body files.
+    fn modified(&self) -> [Option<std::time::SystemTime>; 2] {
+        let modified = |path: &Option<PathBuf>| get_modified(&path.as_ref()?.to_string_lossy());
+        [
+            modified(&self.css),
+            modified(&self.head).max(modified(&self.body)),
+        ]
+    }
+}
+
+/// Resolve an url path to a file in `root`. Returns `None` if the file
+/// doesn't exist, or if it is outside of `root`.
+fn resolve_asset(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    let root = root.canonicalize().ok()?;
+    let asset = root.join(path.trim_start_matches('/')).canonicalize().ok()?;
+    (asset.starts_with(&root) && asset.is_file()).then_some(asset)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("vtt") => "text/vtt",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("html" | "htm") => "text/html",
+        Some("md" | "txt" | "csv") => "text/plain; charset=utf-8",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Largest clipboard content accepted by `/api/paste`, like a screenshot.
+const MAX_PASTE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Markdown for clipboard content pasted in an editor. Images are saved in
+/// `dir` with a name from their content, so pasting the same image twice
+/// writes it once.
+async fn paste(dir: &Path, headers: &HeaderMap, body: Bytes) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.split(';').next())
+        .unwrap_or_default()
+        .trim();
+    let extension = match content_type {
+        "text/html" | "text/plain" => {
+            let Ok(text) = String::from_utf8(body.to_vec()) else {
+                return (StatusCode::BAD_REQUEST, "invalid utf-8").into_response();
+            };
+            return match content_type {
+                "text/html" => match md::import_html(&text) {
+                    Ok(markdown) => markdown.into_response(),
+                    Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+                },
+                _ => text.into_response(),
+            };
+        }
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+    };
+    let hash: String = Sha256::digest(&body)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    // An existing file is only reused if it is the same image
+    let mut suffix = 0;
+    let name = loop {
+        let name = match suffix {
+            0 => format!("pasted-{}.{}", hash, extension),
+            _ => format!("pasted-{}-{}.{}", hash, suffix, extension),
+        };
+        match tokio::fs::read(dir.join(&name)).await {
+            Ok(existing) if existing == body => break name,
+            Ok(_) => suffix += 1,
+            Err(_) => {
+                let path = dir.join(&name);
+                if let Err(err) = tokio::fs::write(&path, &body).await {
+                    eprintln!("failed to write {}: {}", path.display(), err);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+                break name;
+            }
+        }
+    };
+    format!("![]({})", name).into_response()
+}
+
+async fn serve_asset(root: &Path, prefix: Option<&str>, uri: &Uri) -> Response {
+    let path = match prefix {
+        Some(prefix) => match uri.path().strip_prefix(prefix) {
+            Some(path) => path,
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => uri.path(),
+    };
+    let Some(asset) = resolve_asset(root, path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match tokio::fs::read(&asset).await {
+        Ok(content) => ([(header::CONTENT_TYPE, content_type(&asset))], content).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Message sent to `/watch` subscribers.
+#[derive(Clone)]
+enum WatchMessage {
+    /// The rendered document, with placeholders for expensive blocks.
+    Document(String),
+    /// A rendered expensive block, replacing the placeholder with `id`.
+    Block { id: usize, html: String },
+    /// The stylesheet from `--css`, when only it has changed.
+    Css(String),
+}
+
+impl WatchMessage {
+    fn to_event(&self) -> Event {
+        match self {
+            WatchMessage::Document(html) => Event::default().data(html),
+            WatchMessage::Block { id, html } => Event::default()
+                .event("block")
+                .data(serde_json::json!({ "id": id, "html": html }).to_string()),
+            WatchMessage::Css(css) => Event::default().event("css").data(css),
+        }
+    }
+}
+
+/// Whether a file was modified, going from `last` to `new`.
+fn is_modified(last: Option<std::time::SystemTime>, new: Option<std::time::SystemTime>) -> bool {
+    match (last, new) {
+        (Some(last), Some(new)) => new > last,
+        (None, Some(_)) => true,
+        _ => false,
+    }
+}
+
+/// Spawn a background task that polls `file`, the page files and the
+/// files the document depends on, like the data files of Data blocks, for
+/// changes, and renders the document once per change. The rendered html is
+/// broadcasted to every subscriber, so the number of open `/watch`
+/// connections doesn't affect how often the document is rendered.
+///
+/// Expensive blocks, like graphs and charts, are left out of the document
+/// at first, so the preview is updated quickly, and are sent one by one
+/// when they are rendered. When only the stylesheet has changed, it is sent
+/// on its own, and the document isn't rendered again.
+fn spawn_watcher(
+    file: String,
+    page_files: PageFiles,
+    options: YamdrOptions,
+) -> broadcast::Sender<WatchMessage> {
+    let (tx, _) = broadcast::channel(16);
+    let sender = tx.clone();
+    let modified = |files: &[PathBuf]| -> Vec<_> {
+        files
+            .iter()
+            .map(|path| get_modified(&path.to_string_lossy()))
+            .collect()
+    };
+    tokio::spawn(async move {
+        let mut last = get_modified(&file);
+        let mut last_page = page_files.modified();
+        let mut watched = dependencies(&options, &read_file(&file));
+        let mut last_watched = modified(&watched);
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let new = get_modified(&file);
+            let new_page = page_files.modified();
+            let [css_modified, page_modified] =
+                [0, 1].map(|i| is_modified(last_page[i], new_page[i]));
+            let new_watched = modified(&watched);
+            let dependencies_modified = last_watched
+                .iter()
+                .zip(&new_watched)
+                .any(|(last, new)| is_modified(*last, *new));
+            let document_modified =
+                is_modified(last, new) || page_modified || dependencies_modified;
+            if !(document_modified || css_modified) {
+                continue;
+            }
+            (last, last_page, last_watched) = (new, new_page, new_watched);
+            if sender.receiver_count() == 0 {
+                continue;
+            }
+            if !document_modified {
+                if let Some(css) = page_files.css() {
+                    let _ = sender.send(WatchMessage::Css(css));
+                }
+                continue;
+            }
+            let file = file.clone();
+            let options = page_files.apply(&options);
+            let (deferred, html, dependencies) = tokio::task::spawn_blocking(move || {
+                let md = read_file(&file);
+                let (meta, html) = render_markdown(&options, &md);
+                if options.timing {
+                    log_timings(&file, &meta.timings);
+                }
+                (meta.deferred, html, dependencies(&options, &md))
+            })
+            .await
+            .expect("rendering task failed");
+            // The document may depend on other files after the change
+            if dependencies != watched {
+                last_watched = modified(&dependencies);
+                watched = dependencies;
+            }
+            // Sending only fails if there are no receivers, which is fine
+            let _ = sender.send(WatchMessage::Document(html));
+            for block in deferred {
+                let (id, html) = tokio::task::spawn_blocking(move || (block.id, block.render()))
+                    .await
+                    .expect("rendering task failed");
+                let _ = sender.send(WatchMessage::Block { id, html });
+            }
+        }
+    });
+    tx
+}
+
+/// Number of blocks listed by `log_timings`.
+const SLOWEST_BLOCKS: usize = 10;
+
+/// Log the total render time and the slowest blocks of a render.
+fn log_timings(file: &str, timings: &[BlockTiming]) {
+    let ms = |timing: &BlockTiming| timing.duration.as_secs_f64() * 1000.0;
+    let mut timings: Vec<&BlockTiming> = timings.iter().collect();
+    timings.sort_by_key(|timing| std::cmp::Reverse(timing.duration));
+    let total: f64 = timings.iter().map(|timing| ms(timing)).sum();
+    eprintln!("{}: rendered in {:.1} ms", file, total);
+    for timing in timings.into_iter().take(SLOWEST_BLOCKS) {
+        match timing.custom_blocks.as_slice() {
+            [] => eprintln!("{:>10.1} ms  block {}", ms(timing), timing.block),
+            blocks => eprintln!(
+                "{:>10.1} ms  block {} ({})",
+                ms(timing),
+                timing.block,
+                blocks.join(", ")
+            ),
+        }
+    }
+}
+
+/// Directory of a document, that relative urls are resolved from.
+fn document_dir(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn parse_variable(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected `name=value`, got `{}`", arg))
+}
+
+/// Commands to check code with, see `--check-code`.
+fn code_checks(args: &Args) -> BTreeMap<String, Vec<String>> {
+    let mut checks = BTreeMap::new();
+    if args.check_code || !args.check_commands.is_empty() {
+        checks.insert(
+            "rust".to_string(),
+            md::RUST_CHECK_COMMAND
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+        );
+    }
+    for (language, command) in args.check_commands.iter() {
+        let command = command.split_whitespace().map(str::to_string).collect();
+        checks.insert(language.clone(), command);
+    }
+    checks
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
+    let signing_key = args.signing_key.as_ref().map(|path| {
+        fs::read(path).unwrap_or_else(|_| panic!("failed to read key {}", path.display()))
+    });
     let mut options = YamdrOptions {
-        standalone: Some(StandaloneOptions {}),
-        additional_head: None,
-        additional_body: None,
-        format: None,
+        standalone: Some(StandaloneOptions {
+            signature: (args.sign || signing_key.is_some()).then(|| SignatureOptions {
+                key: signing_key.clone(),
+            }),
+            encryption: args.encrypt.then(|| EncryptionOptions {
+                passphrase: read_passphrase(),
+            }),
+            template: args
+                .template
+                .as_ref()
+                .map(|path| read_file(&path.to_string_lossy())),
+        }),
+        locale: Locale {
+            numbers: args.number_locale.as_deref().map(NumberFormat::for_locale),
+            ..args
+                .lang
+                .as_deref()
+                .map(Locale::for_lang)
+                .unwrap_or_default()
+        },
+        deterministic: args.deterministic,
+        strict: args.strict,
+        interpolation: (args.interpolate || args.strict_variables).then_some(
+            InterpolationOptions {
+                strict: args.strict_variables,
+            },
+        ),
+        variables: args.variables.iter().cloned().collect(),
+        audiences: args.audiences.clone(),
+        previous: args.previous.as_deref().and_then(read_previous),
+        hide_todos: args.hide_todos,
+        number_headings: args.number_headings,
+        autolink: args.autolink,
+        typography: args.typography.then(Default::default),
+        sql_databases: args.databases.clone(),
+        metrics_urls: args.metrics_urls.clone(),
+        issue_titles: args.issue_titles,
+        code_checks: code_checks(&args),
+        mermaid_command: match &args.mermaid_command {
+            Some(command) => command.split_whitespace().map(str::to_string).collect(),
+            None if args.render_mermaid => md::MERMAID_COMMAND
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            None => Vec::new(),
+        },
+        minify_svg: args.minify_svg,
+        interactive_tables: args.interactive_tables,
+        ..Default::default()
     };
 
+    if let Some(file) = &args.file {
+        let dir = document_dir(Path::new(file));
+        options.path = Some(PathBuf::from(file));
+        options.wiki_links = args.wiki_links.then(|| wiki::Vault::new(&dir).links(&dir));
+        options.base_dir = Some(dir);
+    }
+
     match args.command {
-        Commands::Render { output, format } => {
-            options.format = match format.as_deref().unwrap_or("html") {
+        Commands::Render {
+            ref output,
+            ref format,
+            open,
+            copy,
+            ref export_data,
+        } => {
+            let extension = format.as_deref().unwrap_or("html");
+            options.format = match extension {
                 "md" => Some(Format::Md),
                 "html" => Some(Format::Html),
                 _ => panic!("unsupported format '{:?}'", format),
             };
 
-            let md = fs::read_to_string(&args.file)
-                .expect(&format!("failed to read file {}", args.file));
+            let file = args.file();
+            let md = read_file(&file);
 
-            let (_, html) = render_markdown(&options, &md);
-            if output == "-" {
-                println!("{html}");
+            // Without an output file, the page is opened from a temporary
+            // file, so relative urls are made absolute
+            let output = match output {
+                Some(output) => Some(PathBuf::from(output)),
+                None if open => {
+                    if let Ok(dir) = document_dir(Path::new(&file)).canonicalize() {
+                        options.asset_base = Some(format!("file://{}", dir.display()));
+                    }
+                    let stem = Path::new(&file)
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "document".into());
+                    Some(std::env::temp_dir().join(format!("yamdr-{}.{}", stem, extension)))
+                }
+                None => None,
+            };
+
+            let options = args.page_files().apply(&options);
+            let (meta, html) = render_markdown(&options, &md);
+            for error in meta.errors.iter() {
+                eprintln!("{}", error);
+            }
+            // Other errors are rendered inline, like in the preview
+            let undefined = |error: &md::Error| matches!(error, md::Error::UndefinedVariable(_));
+            if meta.errors.iter().any(undefined) {
+                std::process::exit(1);
+            }
+            if let Some(export_data) = export_data {
+                let data = serde_json::to_string_pretty(&meta.data).unwrap();
+                fs::write(export_data, data).unwrap_or_else(|_| {
+                    panic!("failed to write data to {}", export_data.display())
+                });
+            }
+            if copy {
+                if let Err(err) = desktop::copy(&html) {
+                    eprintln!("error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+            match output {
+                Some(output) if output.as_os_str() == "-" => println!("{html}"),
+                Some(output) => {
+                    fs::write(&output, html).unwrap_or_else(|_| {
+                        panic!("failed to write output to {}", output.display())
+                    });
+                    if open {
+                        if let Err(err) = desktop::open(&output) {
+                            eprintln!("error: {}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        Commands::Verify {
+            ref html,
+            ref source,
+        } => {
+            let html = read_file(&html.to_string_lossy());
+            let source = read_file(&source.to_string_lossy());
+            match verify(&html, &source, signing_key.as_deref()) {
+                Ok(()) => println!("ok"),
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Check => {
+            let file = args.file();
+            let md = read_file(&file);
+            let (meta, _) = render_markdown(&options, &md);
+            for error in meta.errors.iter() {
+                println!("{}: {}", file, error);
+            }
+            if !meta.errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Todos => {
+            let md = read_file(&args.file());
+            let (meta, _) = render_markdown(&options, &md);
+            for todo in meta.todos {
+                match todo.section {
+                    Some(section) => println!("{}: {}", section, todo.text),
+                    None => println!("{}", todo.text),
+                }
+            }
+        }
+        Commands::Deps { dot, ref make } => {
+            let file = args.file();
+            let md = read_file(&file);
+            let dependencies = dependencies(&options, &md);
+            // Spaces are escaped in make rules
+            let escape = |path: &str| path.replace(' ', "\\ ");
+            if dot {
+                println!("digraph dependencies {{");
+                for dependency in dependencies {
+                    println!("  {:?} -> {:?};", file, dependency.to_string_lossy());
+                }
+                println!("}}");
+            } else if let Some(target) = make {
+                let mut rule = format!("{}: {}", escape(target), escape(&file));
+                for dependency in dependencies {
+                    rule += " ";
+                    rule += &escape(&dependency.to_string_lossy());
+                }
+                println!("{}", rule);
             } else {
-                fs::write(&output, html).expect(&format!("failed to write output to {output}"));
+                for dependency in dependencies {
+                    println!("{}", dependency.display());
+                }
             }
         }
-        Commands::Serve { watch } => {
+        Commands::Tangle { ref output } => {
+            let md = read_file(&args.file());
+            let files = md::tangle(&options, &md);
+            // Paths are checked before anything is written
+            if let Some(file) = files.iter().find(|file| {
+                !Path::new(&file.path)
+                    .components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_)))
+            }) {
+                eprintln!("error: `{}` is outside of the output directory", file.path);
+                std::process::exit(1);
+            }
+            for file in files {
+                let path = output.join(&file.path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).unwrap_or_else(|err| {
+                        panic!("failed to create {}: {}", parent.display(), err)
+                    });
+                }
+                fs::write(&path, file.code)
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", path.display(), err));
+                println!("{}", path.display());
+            }
+        }
+        Commands::Import {
+            ref input,
+            ref output,
+        } => {
+            let document = read_file(&input.to_string_lossy());
+            let imported = match input.extension().and_then(|ext| ext.to_str()) {
+                Some("ipynb") => md::import_notebook(&document),
+                Some("org") => md::import_org(&document),
+                Some("rst" | "rest") => md::import_rst(&document),
+                Some("html" | "htm") => md::import_html(&document),
+                _ => {
+                    eprintln!("error: unsupported format of {}", input.display());
+                    std::process::exit(2);
+                }
+            };
+            let md = imported.unwrap_or_else(|err| {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            });
+            match output {
+                Some(output) => fs::write(output, md)
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", output.display(), err)),
+                None => print!("{}", md),
+            }
+        }
+        Commands::Serve {
+            watch,
+            profile,
+            ref asset_prefix,
+            edit,
+        } => {
+            let file = args.file();
+            options.timing = profile;
             if watch {
                 options.additional_head = Some(HOT_RELOAD_JS.to_string());
             }
-            let app = Router::new()
+            let asset_prefix = asset_prefix
+                .as_ref()
+                .map(|prefix| format!("/{}", prefix.trim_matches('/')));
+            options.asset_base = asset_prefix.clone();
+            let asset_root = document_dir(Path::new(&file));
+            let mut app = Router::new()
                 .route("/", {
                     let options = options.clone();
-                    let file = args.file.clone();
-                    get(move || async move {
-                        let md = fs::read_to_string(&file)
-                            .expect(&format!("failed to read file {}", &file));
+                    let page_files = args.page_files();
+                    let file = file.clone();
+                    get(
+                        move |Query(query): Query<HashMap<String, String>>| async move {
+                            let md = read_file(&file);
 
-                        let (_, html) = render_markdown(&options, &md);
+                            let options = YamdrOptions {
+                                timing: options.timing
+                                    || query.get("debug").is_some_and(|debug| debug == "timing"),
+                                ..page_files.apply(&options)
+                            };
+                            let (meta, html) = render_markdown(&options, &md);
+                            if options.timing {
+                                log_timings(&file, &meta.timings);
+                            }
 
-                        axum::response::Html(html)
-                    })
+                            axum::response::Html(html)
+                        },
+                    )
                 })
-                .route("/watch", {
+                .route("/api/blocks", {
                     let options = options.clone();
-                    let file = args.file.clone();
+                    let file = file.clone();
+                    // Unchanged blocks are reused between requests
+                    let cache = Arc::new(Mutex::new(RenderCache::default()));
                     get(move || async move {
-                        let mut last = get_modified(&file);
-                        let stream = stream::repeat_with(move || {
-                            if !watch {
-                                return None;
+                        let md = match tokio::fs::read_to_string(&file).await {
+                            Ok(md) => md,
+                            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                                return StatusCode::NOT_FOUND.into_response();
                             }
-                            let new = get_modified(&file);
-                            if match (last, new) {
-                                (Some(last), Some(new)) if new > last => true,
-                                (None, Some(_)) => true,
-                                _ => false,
-                            } {
-                                last = new;
-                                let md = fs::read_to_string(&file)
-                                    .expect(&format!("failed to read file {}", &file));
-
-                                let (_, html) = render_markdown(&options, &md);
-                                Some(html)
-                            } else {
-                                None
-                            }
-                        })
-                        .throttle(std::time::Duration::from_secs(1))
-                        .filter_map(|a| a)
-                        .map(|a| Ok::<Event, std::convert::Infallible>(Event::default().data(a)));
+                            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                        };
 
-                        Sse::new(stream).keep_alive(KeepAlive::default())
+                        let mut cache = cache.lock().unwrap();
+                        Json(render_blocks_cached(&mut cache, &options, &md)).into_response()
+                    })
+                })
+                .route("/api/render", {
+                    let options = options.clone();
+                    post(move |markdown: String| async move {
+                        Json(render_blocks(&options, &markdown))
+                    })
+                })
+                .fallback({
+                    let options = options.clone();
+                    let page_files = args.page_files();
+                    get(move |uri: Uri| async move {
+                        // Linked documents, like from wiki links, are rendered
+                        // like the served file
+                        let path = uri
+                            .path()
+                            .strip_prefix(asset_prefix.as_deref().unwrap_or(""));
+                        let markdown = path
+                            .and_then(|path| path.strip_suffix(".html"))
+                            .and_then(|page| resolve_asset(&asset_root, &format!("{}.md", page)));
+                        let Some(markdown) = markdown else {
+                            return serve_asset(&asset_root, asset_prefix.as_deref(), &uri).await;
+                        };
+                        let dir = document_dir(&markdown);
+                        let options = YamdrOptions {
+                            path: Some(markdown.clone()),
+                            base_dir: Some(dir.clone()),
+                            wiki_links: options
+                                .wiki_links
+                                .as_ref()
+                                .map(|_| wiki::Vault::new(&asset_root).links(&dir)),
+                            ..page_files.apply(&options)
+                        };
+                        let md = read_file(&markdown.to_string_lossy());
+                        axum::response::Html(render_markdown(&options, &md).1).into_response()
                     })
                 });
 
+            if edit {
+                let dir = document_dir(Path::new(&file));
+                app = app.route(
+                    "/api/paste",
+                    post(move |headers: HeaderMap, body: Bytes| async move {
+                        paste(&dir, &headers, body).await
+                    })
+                    .layer(DefaultBodyLimit::max(MAX_PASTE_SIZE)),
+                );
+            }
+
+            if watch {
+                let watcher = spawn_watcher(
+                    file.clone(),
+                    args.page_files(),
+                    YamdrOptions {
+                        defer_expensive_blocks: true,
+                        ..options.clone()
+                    },
+                );
+                app = app.route(
+                    "/watch",
+                    get(move || async move {
+                        let stream = BroadcastStream::new(watcher.subscribe())
+                            // Lagging receivers only need the latest render
+                            .filter_map(|message| message.ok())
+                            .map(|message| {
+                                Ok::<Event, std::convert::Infallible>(message.to_event())
+                            });
+
+                        Sse::new(stream).keep_alive(KeepAlive::default())
+                    }),
+                );
+            }
+
             let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
                 .await
                 .unwrap();
 
             axum::serve(listener, app).await.unwrap();
         }
+        Commands::Build {
+            ref input,
+            ref output,
+            search,
+            ref feed,
+            ref feed_title,
+            hash_assets,
+        } => {
+            let options = args.page_files().apply(&options);
+            let feed_title = feed_title.clone().unwrap_or_else(|| {
+                input
+                    .canonicalize()
+                    .ok()
+                    .and_then(|input| Some(input.file_name()?.to_string_lossy().into_owned()))
+                    .unwrap_or_default()
+            });
+            let build_options = build::BuildOptions {
+                search,
+                wiki_links: args.wiki_links,
+                feed: feed.clone(),
+                feed_title,
+                hash_assets,
+            };
+            build::build(&options, &build_options, input, output);
+        }
+        Commands::Diff {
+            ref old,
+            ref new,
+            ref html,
+        } => {
+            let old = render_blocks(&options, &read_file(&old.to_string_lossy()));
+            let new = render_blocks(&options, &read_file(&new.to_string_lossy()));
+            let changes = md::diff_blocks(&old, &new);
+            for change in changes.iter() {
+                match *change {
+                    BlockChange::Unchanged { .. } => {}
+                    BlockChange::Added { new: j } => {
+                        println!("+ {}: {}", j + 1, md::block_summary(&new.blocks[j]))
+                    }
+                    BlockChange::Removed { old: i } => {
+                        println!("- {}: {}", i + 1, md::block_summary(&old.blocks[i]))
+                    }
+                    BlockChange::Changed { old: i, new: j } => println!(
+                        "~ {} -> {}: {}",
+                        i + 1,
+                        j + 1,
+                        md::block_summary(&new.blocks[j])
+                    ),
+                }
+            }
+            if let Some(html) = html {
+                fs::write(html, md::diff_html(&old, &new, &changes))
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", html.display(), err));
+            }
+            if changes.iter().any(|change| !change.is_unchanged()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Merge {
+            ref base,
+            ref ours,
+            ref theirs,
+            ref output,
+        } => {
+            let merged = md::merge_documents(
+                &read_file(&base.to_string_lossy()),
+                &read_file(&ours.to_string_lossy()),
+                &read_file(&theirs.to_string_lossy()),
+            );
+            match output {
+                Some(output) => fs::write(output, &merged.markdown)
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", output.display(), err)),
+                None => print!("{}", merged.markdown),
+            }
+            if merged.conflicts > 0 {
+                eprintln!("conflicts: {}", merged.conflicts);
+                std::process::exit(1);
+            }
+        }
+        Commands::Extract {
+            ref output,
+            ref format,
+        } => {
+            let md = read_file(&args.file());
+            let format = format
+                .clone()
+                .or_else(|| {
+                    let extension = output.as_ref()?.extension()?;
+                    Some(extension.to_string_lossy().into_owned())
+                })
+                .unwrap_or_else(|| "json".into());
+            let Some(format) = TranslationFormat::for_extension(&format) else {
+                eprintln!("error: unsupported translation format `{}`", format);
+                std::process::exit(2);
+            };
+            let lang = args.lang.as_deref().unwrap_or("en");
+            let translations = md::extract_translations(&md, format, lang);
+            match output {
+                Some(output) => fs::write(output, translations)
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", output.display(), err)),
+                None => print!("{}", translations),
+            }
+        }
+        Commands::Apply {
+            ref translations,
+            ref output,
+        } => {
+            let md = read_file(&args.file());
+            let extension = translations.extension().unwrap_or_default();
+            let Some(format) = TranslationFormat::for_extension(&extension.to_string_lossy())
+            else {
+                eprintln!("error: unsupported format of {}", translations.display());
+                std::process::exit(2);
+            };
+            let translations = read_file(&translations.to_string_lossy());
+            let translated =
+                md::apply_translations(&md, &translations, format).unwrap_or_else(|err| {
+                    eprintln!("error: {}", err);
+                    std::process::exit(1);
+                });
+            if !translated.untranslated.is_empty() {
+                eprintln!("untranslated blocks: {}", translated.untranslated.len());
+            }
+            match output {
+                Some(output) => fs::write(output, translated.markdown)
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", output.display(), err)),
+                None => print!("{}", translated.markdown),
+            }
+        }
+        Commands::Speak {
+            ref output,
+            ref format,
+        } => {
+            let md = read_file(&args.file());
+            let format = format
+                .clone()
+                .or_else(|| {
+                    let extension = output.as_ref()?.extension()?;
+                    Some(extension.to_string_lossy().into_owned())
+                })
+                .unwrap_or_else(|| "ssml".into());
+            let Some(format) = SpeechFormat::for_extension(&format) else {
+                eprintln!("error: unsupported speech format `{}`", format);
+                std::process::exit(2);
+            };
+            let script = md::read_aloud(&options, &md, format);
+            match output {
+                Some(output) => fs::write(output, script)
+                    .unwrap_or_else(|err| panic!("failed to write {}: {}", output.display(), err)),
+                None => print!("{}", script),
+            }
+        }
+        Commands::Test { ref dir, bless } => {
+            let results = yamdr_testing::run_corpus(dir, &options, bless);
+            for result in results.iter() {
+                println!("{}", result);
+            }
+            let failures = results.iter().filter(|result| result.is_failure()).count();
+            println!("{} passed, {} failed", results.len() - failures, failures);
+            if failures > 0 {
+                std::process::exit(1);
+            }
+        }
     }
 }
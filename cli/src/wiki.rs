@@ -0,0 +1,79 @@
+use md::WikiLinks;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Characters escaped in the path of a link.
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+/// Relative url from `from`, a directory, to `to`, both relative to the
+/// same root.
+fn relative_url(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|c| utf8_percent_encode(&c.as_os_str().to_string_lossy(), PATH).to_string()),
+    );
+    parts.join("/")
+}
+
+/// The files of a directory that wiki links can link to, like a vault of
+/// notes: `[[Page Name]]` links to the rendered `Page Name.md` anywhere in
+/// the directory, and `![[image.png]]` to the file with that name. Targets
+/// can also be paths relative to the directory, like `[[notes/Page Name]]`.
+pub struct Vault {
+    root: PathBuf,
+    /// Paths of the linked files relative to `root`, by target.
+    targets: Arc<HashMap<String, PathBuf>>,
+}
+
+impl Vault {
+    pub fn new(root: &Path) -> Self {
+        let mut targets: HashMap<String, PathBuf> = HashMap::new();
+        for file in crate::build::walk(root) {
+            let (target, linked) = match file.extension().and_then(|ext| ext.to_str()) {
+                Some("md") => (file.with_extension(""), file.with_extension("html")),
+                _ => (file.clone(), file.clone()),
+            };
+            if let Some(name) = target.file_name() {
+                // With several files of the same name, the first one is linked
+                targets
+                    .entry(name.to_string_lossy().into_owned())
+                    .or_insert_with(|| linked.clone());
+            }
+            targets.insert(target.to_string_lossy().into_owned(), linked);
+        }
+        Vault {
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            targets: Arc::new(targets),
+        }
+    }
+
+    /// Wiki links from a document in `document_dir`.
+    pub fn links(&self, document_dir: &Path) -> WikiLinks {
+        let document_dir = document_dir
+            .canonicalize()
+            .ok()
+            .and_then(|dir| Some(dir.strip_prefix(&self.root).ok()?.to_path_buf()))
+            .unwrap_or_default();
+        let targets = Arc::clone(&self.targets);
+        WikiLinks::new(move |target| {
+            let target = target.trim_start_matches('/').trim_end_matches(".md");
+            targets
+                .get(target)
+                .map(|path| relative_url(&document_dir, path))
+        })
+    }
+}
@@ -0,0 +1,87 @@
+//! Benchmarks for rendering large documents.
+//!
+//! Rough performance budget on a modern laptop, in release mode:
+//! - 10k paragraphs: < 50ms
+//! - 500 highlighted code blocks: < 500ms
+//! - 200 tables of 50 rows: < 50ms
+//!
+//! Run with `cargo bench -p md`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use md::{render_blocks, render_markdown, Format, YamdrOptions};
+
+fn paragraphs(n: usize) -> String {
+    (0..n)
+        .map(|i| {
+            format!(
+                "Paragraph {} with **bold**, *italic*, `code` and a [link](http://example.com).\n\n",
+                i
+            )
+        })
+        .collect()
+}
+
+fn code_blocks(n: usize) -> String {
+    (0..n)
+        .map(|i| {
+            format!(
+                r#"```{{"t":"Code","language":"rust"}}
+fn function_{}(a: u32, b: &str) -> String {{
+    let x = a + {};
+    format!("{{}} {{}}", b, x)
+}}
+```
+
+"#,
+                i, i
+            )
+        })
+        .collect()
+}
+
+fn tables(n: usize, rows: usize) -> String {
+    (0..n)
+        .map(|_| {
+            let mut table = "| a | b | c | d | e |\n|---|---|---|---|---|\n".to_string();
+            for row in 0..rows {
+                table += &format!("| {} | two | three | four | five |\n", row);
+            }
+            table + "\n"
+        })
+        .collect()
+}
+
+fn bench(c: &mut Criterion) {
+    let html = YamdrOptions {
+        format: Some(Format::Html),
+        ..Default::default()
+    };
+    let md = YamdrOptions {
+        format: Some(Format::Md),
+        ..Default::default()
+    };
+
+    let documents = [
+        ("10k paragraphs", paragraphs(10_000)),
+        ("500 code blocks", code_blocks(500)),
+        ("200 tables", tables(200, 50)),
+    ];
+
+    for (name, document) in documents.iter() {
+        c.bench_function(&format!("html {}", name), |b| {
+            b.iter(|| render_markdown(&html, black_box(document)))
+        });
+        c.bench_function(&format!("md {}", name), |b| {
+            b.iter(|| render_markdown(&md, black_box(document)))
+        });
+        c.bench_function(&format!("blocks {}", name), |b| {
+            b.iter(|| render_blocks(&Default::default(), black_box(document)))
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench
+}
+criterion_main!(benches);
@@ -0,0 +1,240 @@
+use crate::{CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+use std::collections::BTreeMap;
+
+/// An `Abbreviations` block, that maps abbreviations to their expansions.
+///
+/// ````text
+/// ```{t: Abbreviations}
+/// HTML: HyperText Markup Language
+/// W3C: World Wide Web Consortium
+/// ```
+/// ````
+///
+/// Occurrences of the abbreviations in text after the block are wrapped in
+/// `<abbr>` elements with the expansion as title in html output. The block
+/// isn't rendered to html, and is kept as it is when rendering to markdown.
+#[derive(Debug, Clone)]
+pub struct AbbreviationsBlock {
+    header: CustomBlockHeader,
+    body: String,
+}
+
+impl AbbreviationsBlock {
+    /// Read an `Abbreviations` block, adding its abbreviations to
+    /// `abbreviations`.
+    pub fn read(
+        header: &CustomBlockHeader,
+        body: &str,
+        abbreviations: &mut BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        let parsed: BTreeMap<String, serde_yaml::Value> = match body.trim() {
+            "" => BTreeMap::new(),
+            body => serde_yaml::from_str(body).map_err(|err| err.to_string())?,
+        };
+        for (abbreviation, expansion) in parsed {
+            let expansion = expansion
+                .as_str()
+                .ok_or_else(|| format!("expansion of `{}` is not a string", abbreviation))?;
+            abbreviations.insert(abbreviation, expansion.into());
+        }
+        Ok(AbbreviationsBlock {
+            header: header.clone(),
+            body: body.into(),
+        })
+    }
+}
+
+impl CustomBlock for AbbreviationsBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => Vec::new(),
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.body.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// A part of abbreviated text.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Abbreviation { text: String, title: String },
+}
+
+/// Text containing abbreviations. They are wrapped in `<abbr>` elements in
+/// html output, while markdown output keeps the text as it is written.
+#[derive(Debug, Clone)]
+pub(crate) struct AbbreviatedText {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl CustomBlock for AbbreviatedText {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Md => vec![Event::Text(self.source.as_str().into())],
+            Format::Html => self
+                .parts
+                .iter()
+                .map(|part| match part {
+                    Part::Text(text) => Event::Text(text.as_str().into()),
+                    Part::Abbreviation { text, title } => {
+                        let mut text_escaped = String::new();
+                        escape_html(&mut text_escaped, text).unwrap();
+                        let mut title_escaped = String::new();
+                        escape_html(&mut title_escaped, title).unwrap();
+                        Event::Html(
+                            format!(r#"<abbr title="{}">{}</abbr>"#, title_escaped, text_escaped)
+                                .into(),
+                        )
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+fn is_word_char(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Find the abbreviations in `text`, as whole words. Longer abbreviations are
+/// preferred where they overlap, so `HTML5` isn't read as `HTML`.
+///
+/// Returns `None` if `text` doesn't contain any abbreviations.
+pub(crate) fn abbreviate(
+    text: &str,
+    abbreviations: &BTreeMap<String, String>,
+) -> Option<AbbreviatedText> {
+    if abbreviations.is_empty() {
+        return None;
+    }
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let found = (!is_word_char(text[..i].chars().next_back()))
+            .then(|| {
+                abbreviations
+                    .iter()
+                    .filter(|(abbreviation, _)| {
+                        !abbreviation.is_empty()
+                            && text[i..].starts_with(abbreviation.as_str())
+                            && !is_word_char(text[i + abbreviation.len()..].chars().next())
+                    })
+                    .max_by_key(|(abbreviation, _)| abbreviation.len())
+            })
+            .flatten();
+        match found {
+            Some((abbreviation, title)) => {
+                if start < i {
+                    parts.push(Part::Text(text[start..i].into()));
+                }
+                parts.push(Part::Abbreviation {
+                    text: abbreviation.clone(),
+                    title: title.clone(),
+                });
+                i += abbreviation.len();
+                start = i;
+            }
+            None => i += text[i..].chars().next().unwrap().len_utf8(),
+        }
+    }
+    if start == 0 {
+        return None;
+    }
+    if start < text.len() {
+        parts.push(Part::Text(text[start..].into()));
+    }
+    Some(AbbreviatedText {
+        source: text.into(),
+        parts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn abbreviations() {
+        let mut abbreviations = BTreeMap::new();
+        let header = CustomBlockHeader::empty("Abbreviations".into());
+        let block = AbbreviationsBlock::read(
+            &header,
+            "HTML: HyperText Markup Language\nHTML5: \"HTML, version 5\"\n",
+            &mut abbreviations,
+        )
+        .unwrap();
+        assert!(block.to_events(Format::Html).is_empty());
+        assert_eq!(abbreviations.len(), 2);
+        assert!(AbbreviationsBlock::read(&header, "HTML: [1]", &mut abbreviations).is_err());
+
+        assert!(abbreviate("XHTML and HTMLs", &abbreviations).is_none());
+        let text = abbreviate("HTML5 is HTML", &abbreviations).unwrap();
+        assert_eq!(
+            text.parts,
+            vec![
+                Part::Abbreviation {
+                    text: "HTML5".into(),
+                    title: "HTML, version 5".into()
+                },
+                Part::Text(" is ".into()),
+                Part::Abbreviation {
+                    text: "HTML".into(),
+                    title: "HyperText Markup Language".into()
+                },
+            ]
+        );
+        assert_eq!(
+            text.to_events(Format::Md),
+            vec![Event::Text("HTML5 is HTML".into())]
+        );
+    }
+
+    #[test]
+    fn abbreviations_in_document() {
+        let document = r#"
+The HTML spec.
+
+```{t: Abbreviations}
+HTML: HyperText Markup Language
+W3C: World Wide Web Consortium
+```
+
+The **HTML** spec is maintained by the W3C, not `HTML`.
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("<p>The HTML spec.</p>"));
+        assert!(html.contains(
+            r#"<strong><abbr title="HyperText Markup Language">HTML</abbr></strong> spec is maintained by the <abbr title="World Wide Web Consortium">W3C</abbr>, not <code>HTML</code>"#
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert_eq!(md, render_markdown(&options, &md).1);
+        assert!(md.contains("The **HTML** spec is maintained by the W3C, not `HTML`."));
+    }
+}
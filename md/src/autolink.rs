@@ -0,0 +1,251 @@
+use pulldown_cmark::{CowStr, Event, LinkType, Tag};
+use std::collections::VecDeque;
+
+/// Characters that end a url or an email address.
+fn is_boundary(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`')
+}
+
+/// Length of the url at the start of `text`, without trailing punctuation
+/// that is more likely to belong to the sentence, like in `(see
+/// https://example.com).`
+fn url_len(text: &str) -> usize {
+    let mut url = &text[..text.find(is_boundary).unwrap_or(text.len())];
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '*', '_']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(without) if trimmed.matches('(').count() < trimmed.matches(')').count() => without,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url.len();
+        }
+        url = trimmed;
+    }
+}
+
+fn is_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+    let tld = labels.last().unwrap();
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+        && tld.len() >= 2
+        && tld.chars().all(char::is_alphabetic)
+}
+
+fn host(url: &str) -> &str {
+    url.split(['/', '?', '#']).next().unwrap()
+}
+
+/// The url at the start of `text`, with the url it links to.
+fn link_at(text: &str) -> Option<(&str, String)> {
+    let url = &text[..url_len(text)];
+    if let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        return (!host(rest).is_empty()).then(|| (url, url.to_string()));
+    }
+    (url.starts_with("www.") && is_domain(host(url))).then(|| (url, format!("http://{}", url)))
+}
+
+/// The email address ending with the domain at the start of `text`, given
+/// the text before the `@`.
+fn email<'a>(before: &'a str, text: &'a str) -> Option<(usize, usize)> {
+    let local = before
+        .rfind(|c: char| !(c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '+')))
+        .map_or(0, |i| i + before[i..].chars().next().unwrap().len_utf8());
+    let domain_len = url_len(text);
+    let domain = &text[..domain_len];
+    (local < before.len() && !before[local..].starts_with('.') && is_domain(domain))
+        .then_some((local, domain_len))
+}
+
+/// Events of the urls and email addresses in `text` as links, and of the
+/// text around them.
+fn split_links(text: &str) -> Option<Vec<Event<'static>>> {
+    let mut events = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let at_word_start = match text[..i].chars().next_back() {
+            Some(c) => is_boundary(c) || matches!(c, '(' | '[' | '*' | '_'),
+            None => true,
+        };
+        let link = match &text[i..] {
+            rest if at_word_start => link_at(rest).map(|(url, dest)| (i, url.len(), dest)),
+            rest if rest.starts_with('@') => {
+                email(&text[start..i], &rest[1..]).map(|(local, domain_len)| {
+                    let address = &text[start + local..i + 1 + domain_len];
+                    (start + local, address.len(), format!("mailto:{}", address))
+                })
+            }
+            _ => None,
+        };
+        let Some((link_start, len, dest)) = link else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+            continue;
+        };
+        if link_start > start {
+            events.push(Event::Text(text[start..link_start].to_string().into()));
+        }
+        let tag = Tag::Link(LinkType::Autolink, dest.into(), CowStr::Borrowed(""));
+        events.push(Event::Start(tag.clone()));
+        events.push(Event::Text(
+            text[link_start..link_start + len].to_string().into(),
+        ));
+        events.push(Event::End(tag));
+        start = link_start + len;
+        i = start;
+    }
+    if events.is_empty() {
+        return None;
+    }
+    if start < text.len() {
+        events.push(Event::Text(text[start..].to_string().into()));
+    }
+    Some(events)
+}
+
+/// Turns bare urls, like `https://example.com` and `www.example.com`, and
+/// email addresses in text into links, with `YamdrOptions::autolink`. Text
+/// in code, and in links and images, is left as it is.
+///
+/// The parser splits text at characters like `_` and `[`, so consecutive
+/// text events are joined before looking for urls.
+pub(crate) struct AutolinkEvents<'a, I> {
+    events: I,
+    enabled: bool,
+    queue: VecDeque<Event<'a>>,
+    in_code_block: bool,
+    /// Depth of the links and images the events are in.
+    in_link: usize,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> AutolinkEvents<'a, I> {
+    pub fn new(events: I, enabled: bool) -> Self {
+        AutolinkEvents {
+            events,
+            enabled,
+            queue: VecDeque::new(),
+            in_code_block: false,
+            in_link: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &I {
+        &self.events
+    }
+
+    fn track(&mut self, event: &Event) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            Event::Start(Tag::Link(..) | Tag::Image(..)) => self.in_link += 1,
+            Event::End(Tag::Link(..) | Tag::Image(..)) => self.in_link -= 1,
+            _ => {}
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for AutolinkEvents<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.queue.pop_front() {
+            return Some(event);
+        }
+        let event = self.events.next()?;
+        if !self.enabled {
+            return Some(event);
+        }
+        self.track(&event);
+        let Event::Text(first) = event else {
+            return Some(event);
+        };
+        if self.in_code_block || self.in_link > 0 {
+            return Some(Event::Text(first));
+        }
+        let mut texts = vec![first];
+        let mut next = None;
+        for event in self.events.by_ref() {
+            match event {
+                Event::Text(text) => texts.push(text),
+                event => {
+                    next = Some(event);
+                    break;
+                }
+            }
+        }
+        let text: String = texts.iter().map(|text| text.as_ref()).collect();
+        match split_links(&text) {
+            Some(events) => self.queue.extend(events),
+            None => self.queue.extend(texts.into_iter().map(Event::Text)),
+        }
+        if let Some(next) = next {
+            self.track(&next);
+            self.queue.push_back(next);
+        }
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, YamdrOptions};
+    use pulldown_cmark::{Options, Parser};
+
+    fn render(markdown: &str) -> String {
+        let events = AutolinkEvents::new(Parser::new_ext(markdown, Options::all()), true);
+        crate::html::render(events, 0)
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            render("See https://example.com/a_b_c?q=1. And (www.example.org)!"),
+            "<p>See <a href=\"https://example.com/a_b_c?q=1\">https://example.com/a_b_c?q=1</a>. And (<a href=\"http://www.example.org\">www.example.org</a>)!</p>\n"
+        );
+        assert_eq!(
+            render("[https://example.com](https://example.org) `https://example.com` http:// www.x"),
+            "<p><a href=\"https://example.org\">https://example.com</a> <code>https://example.com</code> http:// www.x</p>\n"
+        );
+        assert_eq!(
+            render("https://en.wikipedia.org/wiki/Rust_(programming_language)"),
+            "<p><a href=\"https://en.wikipedia.org/wiki/Rust_(programming_language)\">https://en.wikipedia.org/wiki/Rust_(programming_language)</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn emails() {
+        assert_eq!(
+            render("Mail first.last+yamdr@example.co.uk, not @someone or a@b."),
+            "<p>Mail <a href=\"mailto:first.last+yamdr@example.co.uk\">first.last+yamdr@example.co.uk</a>, not @someone or a@b.</p>\n"
+        );
+    }
+
+    #[test]
+    fn autolink() {
+        let document = "Docs at https://example.com/docs, questions to help@example.com.\n\n`https://example.com`\n";
+        let options = YamdrOptions {
+            autolink: true,
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(meta.errors.is_empty());
+        assert!(html.contains(
+            r#"Docs at <a href="https://example.com/docs">https://example.com/docs</a>, questions to <a href="mailto:help@example.com">help@example.com</a>."#
+        ));
+        assert!(html.contains("<code>https://example.com</code>"));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..options
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with("Docs at https://example.com/docs, questions to help@example.com."));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{CustomBlock, CustomBlockReader, Format, Result};
+use pulldown_cmark::{escape::escape_html, Event};
+
+const PREFIX: &str = "badge:";
+
+const COLORS: &[&str] = &["green", "yellow", "orange", "red", "blue", "gray"];
+
+/// A small colored badge, written as inline code like `` `badge:stable|green` ``.
+/// The color is optional, and is picked from the label for common statuses
+/// like `deprecated` or `beta` when it's left out.
+#[derive(Debug, Clone)]
+pub struct BadgeBlock {
+    inline: String,
+}
+
+pub struct BadgeBlockReader {}
+
+impl BadgeBlockReader {
+    pub fn initial_state() -> Self {
+        BadgeBlockReader {}
+    }
+}
+
+impl CustomBlockReader for BadgeBlockReader {
+    fn can_read_inline(&self, inline: &str) -> bool {
+        inline
+            .strip_prefix(PREFIX)
+            .and_then(|label| label.chars().next())
+            .is_some_and(|c| !c.is_whitespace())
+    }
+
+    fn read_inline(&mut self, inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
+        Ok(Some(Box::new(BadgeBlock {
+            inline: inline.into(),
+        })))
+    }
+}
+
+/// Color of a badge with `label`, if no color is given.
+fn default_color(label: &str) -> &'static str {
+    match label.to_lowercase().as_str() {
+        "stable" | "done" | "new" => "green",
+        "beta" | "experimental" | "preview" => "yellow",
+        "alpha" | "unstable" => "orange",
+        "deprecated" | "removed" | "breaking" => "red",
+        _ => "gray",
+    }
+}
+
+impl BadgeBlock {
+    /// Label and color of the badge. Colors not in the palette are gray.
+    fn label_and_color(&self) -> (&str, &str) {
+        let badge = &self.inline[PREFIX.len()..];
+        match badge.rsplit_once('|') {
+            Some((label, color)) => {
+                let color = color.trim();
+                let color = COLORS
+                    .iter()
+                    .find(|c| c.eq_ignore_ascii_case(color))
+                    .unwrap_or(&"gray");
+                (label.trim(), color)
+            }
+            None => (badge.trim(), default_color(badge.trim())),
+        }
+    }
+}
+
+impl CustomBlock for BadgeBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let (label, color) = self.label_and_color();
+                let mut escaped = String::new();
+                escape_html(&mut escaped, label).unwrap();
+                vec![Event::Html(
+                    format!(r#"<span class="badge badge-{}">{}</span>"#, color, escaped).into(),
+                )]
+            }
+            Format::Md => vec![Event::Code(self.inline.as_str().into())],
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    fn html(inline: &str) -> String {
+        let mut reader = BadgeBlockReader::initial_state();
+        assert!(reader.can_read_inline(inline));
+        let block = reader.read_inline(inline).unwrap().unwrap();
+        crate::html::render(block.to_events(Format::Html).into_iter(), 0)
+    }
+
+    #[test]
+    fn badges() {
+        assert_eq!(
+            html("badge:stable|green"),
+            r#"<span class="badge badge-green">stable</span>"#
+        );
+        assert_eq!(
+            html("badge:Deprecated"),
+            r#"<span class="badge badge-red">Deprecated</span>"#
+        );
+        assert_eq!(
+            html("badge:v1 | Blue"),
+            r#"<span class="badge badge-blue">v1</span>"#
+        );
+        assert_eq!(
+            html("badge:<b>|#f00"),
+            r#"<span class="badge badge-gray">&lt;b&gt;</span>"#
+        );
+
+        let reader = BadgeBlockReader::initial_state();
+        assert!(!reader.can_read_inline("badge:"));
+        assert!(!reader.can_read_inline("badge: x"));
+    }
+
+    #[test]
+    fn inline_badges() {
+        let document = "## `GET /users` `badge:deprecated`\n";
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(r#"<span class="badge badge-red">deprecated</span>"#));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_markdown(&options, document).1,
+            format!("{}\n", document)
+        );
+    }
+}
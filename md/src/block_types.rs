@@ -0,0 +1,211 @@
+use crate::{
+    header_schema::{edit_distance, FieldType, HeaderField},
+    CustomBlockHeader, EventProcessor, ExtendedEvent,
+};
+
+/// Another name of a custom block type. Blocks written with the alias are
+/// read as blocks of type `t`, and rendered back to markdown with `t`, so
+/// formatting a document also replaces deprecated aliases.
+pub(crate) struct Alias {
+    pub alias: &'static str,
+    pub t: &'static str,
+    /// Whether the alias will be removed, reported as an error that doesn't
+    /// stop the document from being rendered.
+    pub deprecated: bool,
+}
+
+static ALIASES: &[Alias] = &[
+    Alias {
+        alias: "Chart",
+        t: "DynamicChart",
+        deprecated: false,
+    },
+    Alias {
+        alias: "Table",
+        t: "DynamicTable",
+        deprecated: false,
+    },
+    Alias {
+        alias: "Dot",
+        t: "Graph",
+        deprecated: false,
+    },
+    Alias {
+        alias: "Globals",
+        t: "ScriptGlobals",
+        deprecated: true,
+    },
+];
+
+/// The alias `t` is, if it isn't the name of a block type.
+pub(crate) fn alias(t: &str) -> Option<&'static Alias> {
+    ALIASES.iter().find(|alias| alias.alias == t)
+}
+
+/// A block type that is read by the document instead of a
+/// `CustomBlockReader`, since it reads or changes the state of the
+/// document, like its variables and fragments.
+pub(crate) struct Builtin {
+    pub t: &'static str,
+    /// See `CustomBlockReader::header_schema`.
+    pub schema: Option<&'static [HeaderField]>,
+    /// See `CustomBlockReader::reads_empty_block`.
+    pub empty: bool,
+    /// Reads a block of this type with its header and body.
+    pub read: fn(&mut EventProcessor, &CustomBlockHeader, &str) -> Vec<ExtendedEvent<'static>>,
+}
+
+const NAME: &[HeaderField] = &[HeaderField::new("name", FieldType::String)];
+
+static BUILTINS: &[Builtin] = &[
+    // The fields of `External` and `Use` blocks are data and parameters
+    Builtin {
+        t: "External",
+        schema: None,
+        empty: true,
+        read: EventProcessor::read_external,
+    },
+    Builtin {
+        t: "Use",
+        schema: None,
+        empty: true,
+        read: EventProcessor::fragment,
+    },
+    Builtin {
+        t: "Define",
+        schema: Some(NAME),
+        empty: true,
+        read: EventProcessor::fragment,
+    },
+    Builtin {
+        t: "If",
+        schema: Some(&[HeaderField::new("cond", FieldType::String)]),
+        empty: false,
+        read: EventProcessor::read_if,
+    },
+    Builtin {
+        t: "Abbreviations",
+        schema: Some(&[]),
+        empty: false,
+        read: EventProcessor::read_abbreviations,
+    },
+    Builtin {
+        t: "Capture",
+        schema: Some(NAME),
+        empty: false,
+        read: EventProcessor::read_capture,
+    },
+    Builtin {
+        t: "Emit",
+        schema: Some(NAME),
+        empty: true,
+        read: EventProcessor::read_emit,
+    },
+    Builtin {
+        t: "Defaults",
+        schema: Some(&[]),
+        empty: false,
+        read: EventProcessor::read_defaults,
+    },
+    Builtin {
+        t: "ChecklistSummary",
+        schema: Some(&[HeaderField::new("sections", FieldType::Bool)]),
+        empty: true,
+        read: EventProcessor::read_checklist_summary,
+    },
+    Builtin {
+        t: "Todo",
+        schema: Some(&[]),
+        empty: false,
+        read: EventProcessor::read_todo,
+    },
+    Builtin {
+        t: "Redact",
+        schema: Some(&[
+            HeaderField::new("audience", FieldType::String),
+            HeaderField::new("notice", FieldType::Bool),
+        ]),
+        empty: false,
+        read: EventProcessor::read_redact,
+    },
+    Builtin {
+        t: "ForEach",
+        schema: Some(&[HeaderField::new("data", FieldType::String)]),
+        empty: false,
+        read: EventProcessor::read_for_each,
+    },
+];
+
+/// The builtin block type `t`, if it is one.
+pub(crate) fn builtin(t: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.t == t)
+}
+
+/// The block type or alias closest to `t`, out of the builtin types, the
+/// aliases and `types`, if one is close enough to be a typo of it.
+pub(crate) fn suggestion<'a>(t: &str, types: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    BUILTINS
+        .iter()
+        .map(|builtin| builtin.t)
+        .chain(ALIASES.iter().map(|alias| alias.alias))
+        .chain(types)
+        .map(|known| (edit_distance(t, known), known))
+        .filter(|(distance, known)| *distance <= 2.min(known.len() / 2))
+        .min()
+        .map(|(_, known)| known)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, YamdrOptions};
+
+    #[test]
+    fn aliases() {
+        assert_eq!(alias("Chart").map(|alias| alias.t), Some("DynamicChart"));
+        assert!(alias("Globals").unwrap().deprecated);
+        assert!(alias("DynamicChart").is_none());
+        // An alias can't point to another alias
+        assert!(ALIASES.iter().all(|a| alias(a.t).is_none()));
+    }
+
+    #[test]
+    fn suggestions() {
+        let types = || ["Graph", "Code"].into_iter();
+        assert_eq!(suggestion("Grpah", types()), Some("Graph"));
+        assert_eq!(suggestion("Chrat", types()), Some("Chart"));
+        assert_eq!(suggestion("Defualts", types()), Some("Defaults"));
+        assert_eq!(suggestion("Cod", types()), Some("Code"));
+        assert_eq!(suggestion("Spreadsheet", types()), None);
+    }
+
+    #[test]
+    fn block_type_aliases() {
+        let document = r#"
+```{t: Globals}
+fn double(x) { x * 2 }
+```
+
+```{t: Table}
+row(["a", "b"]);
+row([double(1), 4]);
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("<td>2</td>"));
+        assert_eq!(
+            meta.errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec!["block type `Globals` is deprecated, use `ScriptGlobals` instead"]
+        );
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains(r#"```{"t":"DynamicTable"}"#));
+    }
+}
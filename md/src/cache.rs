@@ -0,0 +1,436 @@
+use crate::directives::Directive;
+use crate::elements::{body_elements, ElementKind};
+use crate::frontmatter;
+use crate::limits::{self, truncate_source};
+use crate::math_block::MathSpans;
+use crate::{
+    document_options, render_block, CustomBlockHeader, EventProcessor, ExtendedEvent, Format,
+    MarkdownBlock, MarkdownDocumentBlocks, YamdrOptions, STYLE,
+};
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    /// Hash of the markdown source of the block.
+    source: u64,
+    /// Hash of the sources of all "stateful" blocks before this block, so
+    /// blocks that might depend on the script scope are rendered again if
+    /// any of the blocks that could have changed the scope change.
+    scope: u64,
+}
+
+/// Rendered blocks of a document, keyed by their markdown source and the
+/// "version" of the script scope they were rendered with. Only the blocks of
+/// the last rendered document are kept.
+#[derive(Debug, Clone, Default)]
+pub struct RenderCache {
+    blocks: HashMap<CacheKey, MarkdownBlock>,
+}
+
+impl RenderCache {
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+/// A top level element of a document.
+struct Element<'a> {
+    events: Vec<Event<'a>>,
+    /// Whether the element contains custom blocks, inline code or directives,
+    /// which may read from or change the state of the readers, like the
+    /// script scope.
+    stateful: bool,
+    key: CacheKey,
+}
+
+fn hash<T: Hash>(value: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn is_stateful(event: &Event) -> bool {
+    match event {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(prop))) => {
+            serde_yaml::from_str::<CustomBlockHeader>(prop).is_ok()
+        }
+        Event::Code(_) => true,
+        // Task list items are counted by `ChecklistSummary` blocks
+        Event::TaskListMarker(_) => true,
+        // Inline footnotes, `^[like this]`, are numbered in order
+        Event::Text(text) => text.ends_with('^'),
+        // Directives change how the following custom blocks are read
+        Event::Html(_) => Directive::from_event(event).is_some(),
+        _ => false,
+    }
+}
+
+fn defines_abbreviations(event: &Event) -> bool {
+    match event {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(prop))) => {
+            serde_yaml::from_str::<CustomBlockHeader>(prop)
+                .is_ok_and(|header| header.t == "Abbreviations")
+        }
+        _ => false,
+    }
+}
+
+/// Split a document into its top level elements. Html blocks and thematic
+/// breaks are included in the previous element, the same way as the
+/// separators are placed by `parse_markdown`.
+///
+/// The text of every element after an `Abbreviations` block may contain
+/// its abbreviations, so those elements are stateful as well.
+fn elements<'a>(options: &YamdrOptions, markdown: &'a str, mut scope: u64) -> Vec<Element<'a>> {
+    let mut elements: Vec<(std::ops::Range<usize>, Element)> = Vec::new();
+    let limits = &options.limits;
+    let (markdown, mut truncated) = truncate_source(markdown, limits.max_document_size);
+    let mut starts = body_elements(markdown, 0)
+        .into_iter()
+        .filter(|element| !matches!(element.kind, ElementKind::Html | ElementKind::Rule))
+        .map(|element| element.range.start)
+        .peekable();
+    let mut events = MathSpans::new(
+        Parser::new_ext(markdown, document_options(options)).into_offset_iter(),
+        markdown,
+        !options.strict,
+    );
+    let mut open = Vec::new();
+    let mut abbreviations = false;
+    for (event, range) in events.by_ref().take(limits.max_events) {
+        let start_element = starts.next_if_eq(&range.start).is_some();
+        if start_element || elements.is_empty() {
+            elements.push((
+                range.clone(),
+                Element {
+                    events: Vec::new(),
+                    stateful: abbreviations,
+                    key: CacheKey {
+                        source: 0,
+                        scope: 0,
+                    },
+                },
+            ));
+        }
+        let (element_range, element) = elements.last_mut().unwrap();
+        element_range.end = element_range.end.max(range.end);
+        element.stateful |= is_stateful(&event);
+        abbreviations |= defines_abbreviations(&event);
+        match &event {
+            Event::Start(tag) => open.push(tag.clone()),
+            Event::End(_) => {
+                open.pop();
+            }
+            _ => {}
+        }
+        element.events.push(event);
+    }
+    truncated |= events.next().is_some();
+    if truncated {
+        // Close the tags of the last element, and add the notice as an
+        // element of its own
+        if let Some((_, element)) = elements.last_mut() {
+            element
+                .events
+                .extend(open.into_iter().rev().map(Event::End));
+        }
+        let notice = limits::notice(Format::Html, &options.locale.labels.truncated);
+        elements.push((
+            markdown.len()..markdown.len(),
+            Element {
+                events: vec![notice],
+                stateful: false,
+                key: CacheKey {
+                    source: 0,
+                    scope: 0,
+                },
+            },
+        ));
+    }
+
+    elements
+        .into_iter()
+        .map(|(range, mut element)| {
+            let source = hash(&markdown[range]);
+            element.key = CacheKey {
+                source,
+                scope: if element.stateful { scope } else { 0 },
+            };
+            if element.stateful {
+                scope = hash((scope, source));
+            }
+            element
+        })
+        .collect()
+}
+
+/// Same as `render_blocks`, but reuses blocks in `cache` from earlier
+/// renders, and updates the cache with the blocks of this document.
+///
+/// Blocks without custom blocks or inline code only depend on their own
+/// source, and are only rendered when they aren't in the cache. If any
+/// block with custom blocks or inline code has changed, all such blocks
+/// are rendered again in order, so the script scope is built up the same
+/// way as when rendering the whole document.
+///
+/// The frontmatter of the document, if it has one, is the first block, with
+/// its source as markdown and no html, so it is kept when the blocks are
+/// joined again. The blocks in `cache` must have been rendered with the same
+/// `options`.
+pub fn render_blocks_cached(
+    cache: &mut RenderCache,
+    options: &YamdrOptions,
+    markdown: &str,
+) -> MarkdownDocumentBlocks {
+    // Scripts can read the frontmatter, so it's part of the scope
+    let (frontmatter, markdown) = frontmatter::split(markdown);
+    let scope = frontmatter.as_ref().map_or(0, |f| hash(f.source));
+    let elements = elements(options, markdown, scope);
+    let frontmatter_block = frontmatter.as_ref().map(|frontmatter| MarkdownBlock {
+        id: 0,
+        html: String::new(),
+        markdown: frontmatter.source.into(),
+        external: None,
+    });
+    let first_id = frontmatter_block.is_some() as u16;
+
+    let render_stateful = elements
+        .iter()
+        .any(|element| element.stateful && !cache.blocks.contains_key(&element.key));
+    // The inline footnotes are listed in a block after the last one, which
+    // depends on all the stateful blocks, as footnotes are read in those
+    let footnotes_key = CacheKey {
+        source: hash("footnotes"),
+        scope: hash(
+            elements
+                .iter()
+                .filter(|element| element.stateful)
+                .map(|element| element.key)
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let footnotes_id = first_id + elements.len() as u16;
+
+    // The elements are processed in order, as scripts are run while
+    // processing, but rendering the processed elements can be done in
+    // parallel, see `Format::transform_extended_events`.
+    let mut processor = EventProcessor::new(options);
+    processor.frontmatter(&frontmatter.map(|f| f.fields).unwrap_or_default());
+    let elements: Vec<_> = elements
+        .into_iter()
+        .enumerate()
+        .map(|(id, element)| {
+            let id = first_id + id as u16;
+            let block = match cache.blocks.get(&element.key) {
+                Some(block) if !(element.stateful && render_stateful) => Ok(MarkdownBlock {
+                    id,
+                    ..block.clone()
+                }),
+                _ => Err(element
+                    .events
+                    .into_iter()
+                    .flat_map(|event| processor.process(event))
+                    .collect::<Vec<_>>()),
+            };
+            (id, element.key, block)
+        })
+        .collect();
+
+    let render = |(id, key, block): (u16, CacheKey, std::result::Result<MarkdownBlock, Vec<_>>)| {
+        let block = block.unwrap_or_else(|events| render_block(id, &events));
+        (key, block)
+    };
+    #[cfg(feature = "parallel")]
+    let mut blocks: Vec<_> = {
+        use rayon::prelude::*;
+        elements.into_par_iter().map(render).collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let mut blocks: Vec<_> = elements.into_iter().map(render).collect();
+
+    let footnotes = match cache.blocks.get(&footnotes_key) {
+        Some(block) if !render_stateful => Some(MarkdownBlock {
+            id: footnotes_id,
+            ..block.clone()
+        }),
+        _ => std::mem::take(&mut processor.footnotes)
+            .finish()
+            .map(|footnotes| {
+                render_block(footnotes_id, &[ExtendedEvent::Custom(Box::new(footnotes))])
+            }),
+    };
+    blocks.extend(footnotes.map(|block| (footnotes_key, block)));
+
+    let rendered = blocks
+        .iter()
+        .map(|(key, block)| (*key, block.clone()))
+        .collect();
+    let blocks = frontmatter_block
+        .into_iter()
+        .chain(blocks.into_iter().map(|(_, block)| block))
+        .collect();
+
+    cache.blocks = rendered;
+
+    MarkdownDocumentBlocks {
+        css: STYLE.into(),
+        blocks,
+        cache: RenderCache::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_blocks;
+
+    #[test]
+    fn same_output_as_uncached() {
+        let document = r#"
+# Header
+
+```{"t":"Script"}
+let x = 2;
+```
+
+Text with `_x + 1_`.
+
+- List
+- List
+
+<div>Html</div>
+
+***
+"#;
+        let mut cache = RenderCache::default();
+        let cached = render_blocks_cached(&mut cache, &Default::default(), document);
+        let uncached = render_blocks(&Default::default(), document);
+        assert_eq!(cached.blocks.len(), uncached.blocks.len());
+        for (a, b) in cached.blocks.iter().zip(uncached.blocks.iter()) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.html, b.html);
+            assert_eq!(a.markdown, b.markdown);
+        }
+        assert_eq!(cache.len(), 4);
+
+        let again = render_blocks_cached(&mut cache, &Default::default(), document);
+        assert_eq!(again.blocks[2].markdown, "Text with `_x + 1 // > 3_`.\n\n");
+    }
+
+    #[test]
+    fn changed_script_rerenders_dependent_blocks() {
+        let mut cache = RenderCache::default();
+        let document = "```{\"t\":\"Script\"}\nlet x = 2;\n```\n\nA paragraph.\n\n`_x * 1_`\n";
+        let blocks = render_blocks_cached(&mut cache, &Default::default(), document);
+        assert_eq!(blocks.blocks[2].markdown, "`_x * 1 // > 2_`\n\n");
+
+        let document = "```{\"t\":\"Script\"}\nlet x = 5;\n```\n\nA paragraph.\n\n`_x * 1_`\n";
+        let blocks = render_blocks_cached(&mut cache, &Default::default(), document);
+        assert_eq!(blocks.blocks[2].markdown, "`_x * 1 // > 5_`\n\n");
+    }
+
+    #[test]
+    fn quotes_are_kept_as_written() {
+        let mut cache = RenderCache::default();
+        let blocks =
+            render_blocks_cached(&mut cache, &Default::default(), "\"Quoted\" -- text...\n");
+        assert_eq!(blocks.blocks[0].markdown, "\"Quoted\" -- text...\n\n");
+        assert_eq!(
+            blocks.blocks[0].html,
+            "<p>&quot;Quoted&quot; -- text...</p>\n"
+        );
+    }
+
+    #[test]
+    fn rerender_reuses_unchanged_blocks() {
+        let mut blocks = render_blocks(
+            &Default::default(),
+            "First paragraph.\n\nSecond paragraph.\n",
+        );
+        assert_eq!(blocks.cache.len(), 2);
+        blocks.blocks[1].markdown = "Changed paragraph.\n\n".into();
+        blocks.rerender(&Default::default());
+        assert_eq!(blocks.blocks.len(), 2);
+        assert_eq!(blocks.blocks[0].html, "<p>First paragraph.</p>\n");
+        assert_eq!(blocks.blocks[1].html, "<p>Changed paragraph.</p>\n");
+        assert_eq!(blocks.cache.len(), 2);
+    }
+
+    #[test]
+    fn changed_abbreviations_rerender_text() {
+        let mut blocks = render_blocks(
+            &Default::default(),
+            "```{t: Abbreviations}\nCPU: Processor\n```\n\nThe CPU.\n",
+        );
+        assert!(blocks.blocks[1]
+            .html
+            .contains(r#"<abbr title="Processor">"#));
+        blocks.blocks[0].markdown =
+            "```{t: Abbreviations}\nCPU: Central processing unit\n```\n".into();
+        blocks.rerender(&Default::default());
+        assert!(blocks.blocks[1]
+            .html
+            .contains(r#"<abbr title="Central processing unit">"#));
+    }
+
+    #[test]
+    fn ticked_tasks_rerender_checklist_summary() {
+        let mut blocks = render_blocks(
+            &Default::default(),
+            "```{t: ChecklistSummary}\n```\n\n- [ ] a\n- [ ] b\n",
+        );
+        assert!(blocks.blocks[0].html.contains("0/2"));
+        blocks.blocks[1].markdown = "- [x] a\n- [x] b\n".into();
+        blocks.rerender(&Default::default());
+        assert!(blocks.blocks[0].html.contains("2/2"));
+    }
+
+    #[test]
+    fn removed_footnote_renumbers_later_footnotes() {
+        let mut blocks = render_blocks(&Default::default(), "A^[first].\n\nB^[second].\n");
+        assert!(blocks.blocks[1]
+            .html
+            .contains(r##"<a href="#fn-inline-2">2</a>"##));
+        blocks.blocks[0].markdown = "A.\n\n".into();
+        blocks.rerender(&Default::default());
+        assert!(blocks.blocks[1]
+            .html
+            .contains(r##"<a href="#fn-inline-1">1</a>"##));
+        assert_eq!(blocks.blocks.len(), 3);
+        assert!(blocks.blocks[2]
+            .html
+            .contains(r#"<li id="fn-inline-1">second"#));
+        assert!(!blocks.blocks[2].html.contains("first"));
+
+        // Reused with the blocks the footnotes are in
+        blocks.blocks[0].markdown = "C.\n\n".into();
+        blocks.rerender(&Default::default());
+        assert_eq!(blocks.blocks.len(), 3);
+        assert!(blocks.blocks[2]
+            .html
+            .contains(r#"<li id="fn-inline-1">second"#));
+    }
+
+    #[test]
+    fn rerender_keeps_frontmatter() {
+        let mut blocks = render_blocks(
+            &Default::default(),
+            "---\ntitle: T\n---\n\nTitle is `_meta.title_`\n",
+        );
+        assert_eq!(blocks.blocks.len(), 2);
+        assert_eq!(blocks.blocks[0].markdown, "---\ntitle: T\n---\n");
+        assert_eq!(blocks.blocks[0].html, "");
+        assert_eq!(blocks.blocks[1].id, 1);
+        assert!(blocks.blocks[1].markdown.contains("// > T"));
+        blocks.rerender(&Default::default());
+        assert_eq!(blocks.blocks[0].markdown, "---\ntitle: T\n---\n");
+        assert!(blocks.blocks[1].markdown.contains("// > T"));
+    }
+}
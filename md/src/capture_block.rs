@@ -0,0 +1,214 @@
+use crate::{
+    interpolation::interpolate, utils::header_field, CustomBlock, CustomBlockHeader, Format,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Rendered html of the `Capture` blocks of a document, by name.
+pub(crate) type Captures = Arc<RwLock<BTreeMap<String, String>>>;
+
+/// A `Capture` block, that renders its markdown body where it is, and
+/// keeps the html to be rendered again by `Emit` blocks with the same name.
+///
+/// ````text
+/// ```{t: Capture, name: summary}
+/// > **Summary:** {{passed}} of {{total}} checks passed.
+/// ```
+///
+/// ```{t: Emit, name: summary}
+/// ```
+/// ````
+///
+/// An `Emit` block can come before the `Capture` block, like a summary at
+/// the top of a report that is computed at the end of it. Variables in the
+/// body are replaced as in `Use` blocks, and it is read like the rest of the
+/// document, so it can contain other blocks. Both are kept as they are when
+/// rendering to markdown.
+#[derive(Debug, Clone)]
+pub struct CaptureBlock {
+    header: CustomBlockHeader,
+    name: String,
+    body: String,
+    /// The body with variables replaced.
+    output: String,
+    /// The output rendered to html.
+    html: String,
+}
+
+fn name(header: &CustomBlockHeader) -> Result<String, String> {
+    header_field(header, "name").ok_or_else(|| format!("missing `name` in {} block", header.t))
+}
+
+fn fenced<'a>(header: &CustomBlockHeader, body: &'a str) -> Vec<Event<'a>> {
+    let props: CowStr = serde_json::to_string(header).unwrap().into();
+    let mut events = vec![Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+        props.clone(),
+    )))];
+    if !body.is_empty() {
+        events.push(Event::Text(body.into()));
+    }
+    events.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))));
+    events
+}
+
+impl CaptureBlock {
+    /// Read a `Capture` block, replacing the variables in its body with
+    /// `variable`.
+    pub fn read(
+        header: &CustomBlockHeader,
+        body: &str,
+        variable: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let output = match interpolate(body, false, variable) {
+            Some(text) => text.text(),
+            None => body.to_string(),
+        };
+        Ok(CaptureBlock {
+            header: header.clone(),
+            name: name(header)?,
+            body: body.into(),
+            output,
+            html: String::new(),
+        })
+    }
+
+    /// Render the body to html with `render`, and add it to `captures`.
+    pub(crate) fn capture(
+        &mut self,
+        captures: &Captures,
+        render: impl FnOnce(&str) -> String,
+    ) -> Result<(), String> {
+        self.html = render(&self.output);
+        let mut captures = captures.write().unwrap();
+        if captures
+            .insert(self.name.clone(), self.html.clone())
+            .is_some()
+        {
+            return Err(format!("`{}` is already captured", self.name));
+        }
+        Ok(())
+    }
+}
+
+impl CustomBlock for CaptureBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => vec![Event::Html(self.html.as_str().into())],
+            Format::Md => fenced(&self.header, &self.body),
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// An `Emit` block, see `CaptureBlock`. The captures are shared with the
+/// `EventProcessor`, so they are complete when the block is rendered.
+#[derive(Debug, Clone)]
+pub struct EmitBlock {
+    header: CustomBlockHeader,
+    name: String,
+    captures: Captures,
+}
+
+impl EmitBlock {
+    pub fn read(header: &CustomBlockHeader, captures: &Captures) -> Result<Self, String> {
+        Ok(EmitBlock {
+            header: header.clone(),
+            name: name(header)?,
+            captures: Arc::clone(captures),
+        })
+    }
+
+    /// Name of the capture the block renders.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl CustomBlock for EmitBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => match self.captures.read().unwrap().get(&self.name) {
+                Some(html) => vec![Event::Html(html.clone().into())],
+                None => Vec::new(),
+            },
+            Format::Md => fenced(&self.header, ""),
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn capture_and_emit() {
+        let captures = Captures::default();
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Emit, name: box}").unwrap();
+        let emit = EmitBlock::read(&header, &captures).unwrap();
+        assert!(emit.to_events(Format::Html).is_empty());
+
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Capture, name: box}").unwrap();
+        let variable = |path: &str| (path == "total").then(|| "3".to_string());
+        let mut capture = CaptureBlock::read(&header, "**{{total}}** checks\n", variable).unwrap();
+        assert!(capture.capture(&captures, str::to_uppercase).is_ok());
+        let html = crate::html::render(emit.to_events(Format::Html).into_iter(), 0);
+        assert_eq!(html, "**3** CHECKS\n");
+
+        let mut capture = CaptureBlock::read(&header, "again", variable).unwrap();
+        assert!(capture.capture(&captures, str::to_uppercase).is_err());
+        let header = CustomBlockHeader::empty("Emit".into());
+        assert!(EmitBlock::read(&header, &captures).is_err());
+    }
+
+    #[test]
+    fn capture_and_emit_in_document() {
+        let document = r#"
+```{t: Emit, name: summary}
+```
+
+# Results
+
+```{t: Capture, name: summary}
+**All** checks passed. `badge:passed`
+```
+
+```{t: Emit, name: missing}
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert_eq!(
+            html.matches(
+                r#"<p><strong>All</strong> checks passed. <span class="badge badge-gray">passed</span></p>"#
+            )
+                .count(),
+            2
+        );
+        assert!(html.find("<strong>All").unwrap() < html.find("Results").unwrap());
+        assert_eq!(
+            meta.errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec!["error while reading custom block: `nothing is captured as `missing``"]
+        );
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains(r#"```{"t":"Emit","name":"summary"}"#));
+        assert_eq!(md.matches("**All** checks passed.").count(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{CustomBlock, CustomBlockHeader, CustomBlockReader, Error, Format, Result};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, Options, Parser, Tag};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Card {
+    title: String,
+    /// Markdown content of the card.
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    image: Option<String>,
+    /// Alt text of the image, the title is used if not set.
+    #[serde(default)]
+    alt: Option<String>,
+    #[serde(default)]
+    link: Option<String>,
+}
+
+/// A grid of cards, for landing and index pages.
+///
+/// ````text
+/// ```{t: Cards}
+/// - title: Getting started
+///   body: Install yamdr and render your *first* document.
+///   image: images/start.png
+///   link: getting-started.html
+/// ```
+/// ````
+#[derive(Debug, Clone)]
+pub struct CardsBlock {
+    header: CustomBlockHeader,
+    input: String,
+    cards: Vec<Card>,
+}
+
+pub struct CardsBlockReader {}
+
+impl CardsBlockReader {
+    pub fn initial_state() -> Self {
+        CardsBlockReader {}
+    }
+}
+
+impl CustomBlockReader for CardsBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Cards"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Cards"]
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let cards = serde_yaml::from_str(input)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to parse block: {}", err)))?;
+        Ok(Some(Box::new(CardsBlock {
+            header: header.clone(),
+            input: input.into(),
+            cards,
+        })))
+    }
+}
+
+impl Card {
+    fn to_events(&self) -> Vec<Event<'_>> {
+        let mut events = vec![Event::Html(r#"<div class="card">"#.into())];
+        if let Some(image) = &self.image {
+            let image = Tag::Image(LinkType::Inline, image.as_str().into(), "".into());
+            let alt = self.alt.as_deref().unwrap_or(&self.title);
+            events.extend([
+                Event::Start(image.clone()),
+                Event::Text(alt.into()),
+                Event::End(image),
+            ]);
+        }
+        let heading = Tag::Heading(HeadingLevel::H3, None, Vec::new());
+        events.push(Event::Start(heading.clone()));
+        match &self.link {
+            Some(link) => {
+                let link = Tag::Link(LinkType::Inline, link.as_str().into(), "".into());
+                events.extend([
+                    Event::Start(link.clone()),
+                    Event::Text(self.title.as_str().into()),
+                    Event::End(link),
+                ]);
+            }
+            None => events.push(Event::Text(self.title.as_str().into())),
+        }
+        events.push(Event::End(heading));
+        events.extend(Parser::new_ext(&self.body, Options::all()));
+        events.push(Event::Html("</div>".into()));
+        events
+    }
+}
+
+impl CustomBlock for CardsBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut events = vec![Event::Html(r#"<div class="cards">"#.into())];
+                events.extend(self.cards.iter().flat_map(Card::to_events));
+                events.push(Event::Html("</div>".into()));
+                events
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cards() {
+        let input = r#"
+- title: Getting started
+  body: Render your *first* document.
+  image: start.png
+  link: start.html
+- title: Reference
+"#;
+        let mut reader = CardsBlockReader::initial_state();
+        let block = reader
+            .read_block(&CustomBlockHeader::empty("Cards".into()), input)
+            .unwrap()
+            .unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.starts_with(
+            r#"<div class="cards"><div class="card"><img src="start.png" alt="Getting started" />"#
+        ));
+        assert!(html.contains(r#"<h3><a href="start.html">Getting started</a></h3>"#));
+        assert!(html.contains("<p>Render your <em>first</em> document.</p>"));
+        assert!(html.contains("<h3>Reference</h3>\n</div></div>"));
+
+        let header = CustomBlockHeader::empty("Cards".into());
+        assert!(reader.read_block(&header, "- body: no title").is_err());
+    }
+}
@@ -0,0 +1,485 @@
+use crate::{
+    graph_block::GraphBlock,
+    licenses_block::parse_cargo_metadata,
+    utils::{build_table, header_field, local_files},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// A package of a `Cargo.lock`.
+#[derive(Debug, Clone, Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    source: Option<String>,
+    /// Written as `name`, or `name version` when several versions of the
+    /// package are locked.
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+impl Lockfile {
+    /// The locked package of a dependency of `from`, written like in
+    /// `LockedPackage::dependencies`.
+    fn find(&self, dependency: &str, from: Option<&LockedPackage>) -> Option<&LockedPackage> {
+        let mut parts = dependency.split(' ');
+        let name = parts.next()?;
+        let version = parts.next().or_else(|| {
+            // The version of the package `from` depends on, if several are locked
+            from?
+                .dependencies
+                .iter()
+                .filter_map(|d| d.strip_prefix(name)?.strip_prefix(' '))
+                .find_map(|d| d.split(' ').next())
+        });
+        self.package
+            .iter()
+            .find(|p| p.name == name && version.is_none_or(|v| p.version == v))
+    }
+}
+
+/// A direct dependency from a `Cargo.toml`.
+#[derive(Debug, Clone, PartialEq)]
+struct Dependency {
+    name: String,
+    requirement: String,
+    dev: bool,
+}
+
+/// The package name and direct dependencies of a `Cargo.toml`.
+fn parse_manifest(manifest: &str) -> std::result::Result<(String, Vec<Dependency>), String> {
+    let manifest: toml::Table = toml::from_str(manifest).map_err(|err| err.to_string())?;
+    let name = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str)
+        .ok_or("no `[package]` with a `name`")?;
+    let mut dependencies = Vec::new();
+    for (table, dev) in [
+        ("dependencies", false),
+        ("build-dependencies", false),
+        ("dev-dependencies", true),
+    ] {
+        let Some(table) = manifest.get(table).and_then(toml::Value::as_table) else {
+            continue;
+        };
+        for (key, value) in table {
+            let field = |name| value.get(name).and_then(toml::Value::as_str);
+            let requirement = match value {
+                toml::Value::String(requirement) => requirement.as_str(),
+                _ if field("path").is_some() => "path",
+                _ if value.get("workspace").is_some() => "workspace",
+                _ => field("version").or_else(|| field("git")).unwrap_or("*"),
+            };
+            dependencies.push(Dependency {
+                // A renamed dependency is locked with the name of the package
+                name: field("package").unwrap_or(key).to_string(),
+                requirement: requirement.to_string(),
+                dev,
+            });
+        }
+    }
+    Ok((name.to_string(), dependencies))
+}
+
+/// Licenses by package name and version, from the output of
+/// `cargo metadata --format-version 1`.
+fn parse_licenses(
+    metadata: &str,
+) -> std::result::Result<BTreeMap<(String, String), String>, String> {
+    Ok(parse_cargo_metadata(metadata)?
+        .into_iter()
+        .filter_map(|p| Some(((p.name, p.version), p.license?)))
+        .collect())
+}
+
+/// The dependencies of a Cargo package, from its `Cargo.toml` and the
+/// `Cargo.lock` of the package or its workspace, for architecture docs.
+///
+/// ````text
+/// ```{t: CargoDeps, path: Cargo.toml, view: table, metadata: metadata.json}
+/// ```
+/// ````
+///
+/// With `view: graph`, the default, the dependencies are laid out as a
+/// graph like `Graph` blocks, `depth` levels deep, 1 if it isn't set. With
+/// `view: table`, the direct dependencies are listed with their locked
+/// versions, and their licenses if `metadata` is the output of
+/// `cargo metadata --format-version 1`. Dev-dependencies are only included
+/// with `dev: true`. The lockfile is looked for next to the manifest and
+/// in the directories above it, unless `lockfile` is set.
+#[derive(Debug, Clone)]
+pub struct CargoDepsBlock {
+    header: CustomBlockHeader,
+    input: String,
+    /// Name, locked version and license of the direct dependencies.
+    rows: Vec<[String; 3]>,
+    graph: Option<GraphBlock>,
+    labels: Labels,
+}
+
+pub struct CargoDepsBlockReader {
+    base_dir: Option<PathBuf>,
+    labels: Labels,
+}
+
+impl CargoDepsBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        CargoDepsBlockReader {
+            base_dir: options.base_dir.clone(),
+            labels: options.locale.labels.clone(),
+        }
+    }
+
+    fn full_path(&self, path: &str) -> PathBuf {
+        match &self.base_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        let full_path = self.full_path(path);
+        if !full_path.is_file() {
+            return Err(Error::MissingFile(path.into()));
+        }
+        std::fs::read_to_string(full_path)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err)))
+    }
+
+    /// The `lockfile` of `header`, or the first `Cargo.lock` next to the
+    /// manifest or in a directory above it, like cargo looks for it,
+    /// relative to the base directory.
+    fn lockfile(&self, header: &CustomBlockHeader) -> Option<String> {
+        if let Some(lockfile) = header_field(header, "lockfile") {
+            return Some(lockfile);
+        }
+        let manifest = header_field(header, "path")?;
+        let full_path = self.full_path(&manifest).canonicalize().ok()?;
+        // The directory relative to the base directory, and how far above
+        // the base directory it is, like for the lockfile of a workspace
+        let mut dir = Path::new(&manifest).parent()?.to_path_buf();
+        let mut up = PathBuf::new();
+        for full_dir in full_path.ancestors().skip(1) {
+            if full_dir.join("Cargo.lock").is_file() {
+                let lockfile = up.join(&dir).join("Cargo.lock");
+                return Some(lockfile.to_string_lossy().into_owned());
+            }
+            if !dir.pop() {
+                up.push("..");
+            }
+        }
+        None
+    }
+}
+
+/// A graph of the packages `root` depends on, `depth` levels deep, in the
+/// dot language.
+fn dependency_graph(
+    lockfile: &Lockfile,
+    root: &LockedPackage,
+    direct: &[&LockedPackage],
+    depth: usize,
+) -> String {
+    let id = |p: &LockedPackage| format!("\"{} {}\"", p.name, p.version);
+    let mut edges = BTreeSet::new();
+    let mut level: Vec<&LockedPackage> = Vec::new();
+    for package in direct {
+        edges.insert((id(root), id(package)));
+        level.push(package);
+    }
+    let mut seen: BTreeSet<String> = level.iter().map(|p| id(p)).collect();
+    for _ in 1..depth {
+        let mut next = Vec::new();
+        for package in level {
+            for dependency in package.dependencies.iter() {
+                let Some(dependency) = lockfile.find(dependency, Some(package)) else {
+                    continue;
+                };
+                edges.insert((id(package), id(dependency)));
+                if seen.insert(id(dependency)) {
+                    next.push(dependency);
+                }
+            }
+        }
+        level = next;
+    }
+    let mut dot = String::from("digraph {\n");
+    for (from, to) in edges {
+        dot += &format!("  {} -> {};\n", from, to);
+    }
+    dot += "}\n";
+    dot
+}
+
+impl CustomBlockReader for CargoDepsBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "CargoDeps"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["CargoDeps"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("path", FieldType::String),
+            HeaderField::new("lockfile", FieldType::String),
+            HeaderField::new("metadata", FieldType::String),
+            HeaderField::new("view", FieldType::String),
+            HeaderField::new("depth", FieldType::Integer),
+            HeaderField::new("dev", FieldType::Bool),
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("accessibility", FieldType::String),
+            HeaderField::new("scale", FieldType::Scalar),
+            HeaderField::new("max_width", FieldType::Scalar),
+            HeaderField::new("fit", FieldType::Bool),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let path = header_field(header, "path")
+            .ok_or_else(|| Error::CustomBlockRead("missing `path` in CargoDeps block".into()))?;
+        let (name, dependencies) = parse_manifest(&self.read_file(&path)?).map_err(|err| {
+            Error::CustomBlockRead(format!("failed to parse `{}`: {}", path, err))
+        })?;
+        let lockfile_path = self
+            .lockfile(header)
+            .ok_or_else(|| Error::CustomBlockRead(format!("no Cargo.lock found for `{}`", path)))?;
+        let lockfile: Lockfile =
+            toml::from_str(&self.read_file(&lockfile_path)?).map_err(|err| {
+                Error::CustomBlockRead(format!("failed to parse `{}`: {}", lockfile_path, err))
+            })?;
+        let licenses = match header_field(header, "metadata") {
+            Some(metadata) => parse_licenses(&self.read_file(&metadata)?).map_err(|err| {
+                Error::CustomBlockRead(format!("failed to parse `{}`: {}", metadata, err))
+            })?,
+            None => BTreeMap::new(),
+        };
+        let dev = header
+            .fields
+            .get("dev")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false);
+        let root = lockfile
+            .package
+            .iter()
+            .find(|p| p.name == name && p.source.is_none())
+            .ok_or_else(|| {
+                Error::CustomBlockRead(format!("`{}` isn't in `{}`", name, lockfile_path))
+            })?;
+        let mut direct: Vec<(&Dependency, Option<&LockedPackage>)> = Vec::new();
+        for dependency in dependencies.iter().filter(|d| dev || !d.dev) {
+            if direct.iter().all(|(d, _)| d.name != dependency.name) {
+                direct.push((dependency, lockfile.find(&dependency.name, Some(root))));
+            }
+        }
+        direct.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        let rows = direct
+            .iter()
+            .map(|(dependency, locked)| match locked {
+                Some(locked) => [
+                    locked.name.clone(),
+                    locked.version.clone(),
+                    licenses
+                        .get(&(locked.name.clone(), locked.version.clone()))
+                        .cloned()
+                        .unwrap_or_default(),
+                ],
+                None => [
+                    dependency.name.clone(),
+                    dependency.requirement.clone(),
+                    String::new(),
+                ],
+            })
+            .collect();
+        let graph = match header_field(header, "view").as_deref() {
+            None | Some("graph") => {
+                let depth = header
+                    .fields
+                    .get("depth")
+                    .and_then(serde_yaml::Value::as_u64)
+                    .unwrap_or(1) as usize;
+                let direct: Vec<_> = direct.iter().filter_map(|(_, locked)| *locked).collect();
+                let dot = dependency_graph(&lockfile, root, &direct, depth);
+                Some(GraphBlock::generated(header, dot)?)
+            }
+            Some("table") => None,
+            Some(view) => {
+                return Err(Error::CustomBlockRead(format!(
+                    "unknown view `{}` in CargoDeps block, expected graph or table",
+                    view
+                )))
+            }
+        };
+        Ok(Some(Box::new(CargoDepsBlock {
+            header: header.clone(),
+            input: input.into(),
+            rows,
+            graph,
+            labels: self.labels.clone(),
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        let mut files = local_files(header, &["path", "metadata"]);
+        files.extend(self.lockfile(header));
+        files
+    }
+}
+
+impl CustomBlock for CargoDepsBlock {
+    fn is_expensive(&self) -> bool {
+        self.graph.is_some()
+    }
+
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match (format, &self.graph) {
+            (Format::Html, Some(graph)) => graph.to_events(format),
+            (Format::Html, None) => {
+                let head = self
+                    .labels
+                    .cargo_deps
+                    .iter()
+                    .map(|label| label.as_str().into())
+                    .collect();
+                let rows = self
+                    .rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| CowStr::from(cell.as_str())).collect())
+                    .collect();
+                build_table(head, rows)
+            }
+            (Format::Md, _) => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{custom_block, render_html};
+
+    fn options() -> YamdrOptions {
+        YamdrOptions {
+            base_dir: Some(PathBuf::from(env!("CARGO_MANIFEST_DIR"))),
+            ..Default::default()
+        }
+    }
+
+    fn render(header: &str) -> Result<String> {
+        render_html(&options(), &custom_block(header, ""))
+    }
+
+    #[test]
+    fn manifest() {
+        let (name, dependencies) = parse_manifest(
+            r#"
+[package]
+name = "app"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+local = { path = "../local" }
+yaml = { package = "serde_yaml", version = "0.9" }
+
+[dev-dependencies]
+criterion = "0.5"
+"#,
+        )
+        .unwrap();
+        assert_eq!(name, "app");
+        let dependency = |name: &str, requirement: &str, dev| Dependency {
+            name: name.into(),
+            requirement: requirement.into(),
+            dev,
+        };
+        assert_eq!(
+            dependencies,
+            vec![
+                dependency("local", "path", false),
+                dependency("serde", "1.0", false),
+                dependency("serde_yaml", "0.9", false),
+                dependency("criterion", "0.5", true),
+            ]
+        );
+        assert!(parse_manifest("[workspace]\nmembers = []").is_err());
+    }
+
+    #[test]
+    fn cargo_deps() {
+        // This crate, with the lockfile of the workspace
+        assert_eq!(
+            CargoDepsBlockReader::new(&options())
+                .files(&serde_yaml::from_str("{t: CargoDeps, path: Cargo.toml}").unwrap()),
+            vec!["Cargo.toml", "../Cargo.lock"]
+        );
+        let html = render("{t: CargoDeps, path: Cargo.toml, view: table}").unwrap();
+        assert!(html.contains("<th>Crate</th><th>Version</th><th>License</th>"));
+        assert!(html.contains("<tr><td>csv</td><td>1."));
+        assert!(!html.contains("criterion"));
+        assert!(
+            render("{t: CargoDeps, path: Cargo.toml, view: table, dev: true}")
+                .unwrap()
+                .contains("<td>criterion</td>")
+        );
+
+        let html = render("{t: CargoDeps, path: Cargo.toml, alt: Dependencies of md}").unwrap();
+        assert!(html.contains("<svg"));
+        assert!(html.contains("md 0.1.0"));
+
+        assert!(render("{t: CargoDeps, path: Cargo.toml, view: list}").is_err());
+        assert!(matches!(
+            render("{t: CargoDeps, path: missing/Cargo.toml}"),
+            Err(Error::MissingFile(_))
+        ));
+    }
+
+    #[test]
+    fn licenses() {
+        let metadata = r#"{"packages": [
+            {"name": "serde", "version": "1.0.1", "license": "MIT OR Apache-2.0"},
+            {"name": "app", "version": "0.1.0", "license": null}
+        ]}"#;
+        assert_eq!(
+            parse_licenses(metadata).unwrap(),
+            BTreeMap::from([(
+                ("serde".to_string(), "1.0.1".to_string()),
+                "MIT OR Apache-2.0".to_string()
+            )])
+        );
+    }
+}
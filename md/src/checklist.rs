@@ -0,0 +1,212 @@
+use crate::{CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+use std::sync::{Arc, RwLock};
+
+/// Progress of the task-list items (`- [x] ...`) of a document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checklist {
+    pub done: usize,
+    pub total: usize,
+    /// Progress of the items in each section, in the order of the sections.
+    /// Items before the first heading are only counted in the totals.
+    pub sections: Vec<ChecklistSection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecklistSection {
+    /// Heading of the section.
+    pub section: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Checklist {
+    pub(crate) fn item(&mut self, done: bool, section: Option<&String>) {
+        self.total += 1;
+        self.done += done as usize;
+        let Some(section) = section else {
+            return;
+        };
+        if self.sections.last().map(|last| &last.section) != Some(section) {
+            self.sections.push(ChecklistSection {
+                section: section.clone(),
+                done: 0,
+                total: 0,
+            });
+        }
+        let last = self.sections.last_mut().unwrap();
+        last.total += 1;
+        last.done += done as usize;
+    }
+}
+
+/// A `ChecklistSummary` block, with the progress of all task-list items of
+/// the document, also the ones after the block, and of each section.
+///
+/// ````text
+/// ```{t: ChecklistSummary}
+/// ```
+/// ````
+///
+/// The checklist is shared with the `EventProcessor`, which counts the
+/// items while reading the document, so it is complete when the block is
+/// rendered. The breakdown by section is left out with `sections: false`.
+#[derive(Debug, Clone)]
+pub(crate) struct ChecklistSummaryBlock {
+    header: CustomBlockHeader,
+    checklist: Arc<RwLock<Checklist>>,
+}
+
+impl ChecklistSummaryBlock {
+    pub fn new(header: &CustomBlockHeader, checklist: Arc<RwLock<Checklist>>) -> Self {
+        ChecklistSummaryBlock {
+            header: header.clone(),
+            checklist,
+        }
+    }
+}
+
+fn progress_html(label: &str, done: usize, total: usize) -> String {
+    let mut html = String::from(r#"<div class="progress">"#);
+    if !label.is_empty() {
+        html += r#"<span class="progress-label">"#;
+        escape_html(&mut html, label).unwrap();
+        html += "</span>";
+    }
+    html += &format!(
+        r#"<progress class="progress" value="{0}" max="{1}" aria-label="{0}/{1}">{0}/{1}</progress><span class="progress-value">{0}/{1}</span></div>"#,
+        done,
+        // An empty progress element would be indeterminate
+        total.max(1)
+    );
+    html
+}
+
+impl CustomBlock for ChecklistSummaryBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let checklist = self.checklist.read().unwrap();
+                let mut html = String::from(r#"<div class="checklist-summary">"#);
+                html += &progress_html("", checklist.done, checklist.total);
+                let sections = self
+                    .header
+                    .fields
+                    .get("sections")
+                    .and_then(serde_yaml::Value::as_bool)
+                    .unwrap_or(true);
+                if sections {
+                    for section in &checklist.sections {
+                        html += &progress_html(&section.section, section.done, section.total);
+                    }
+                }
+                html += "</div>\n";
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn count_items() {
+        let mut checklist = Checklist::default();
+        let (setup, usage) = ("Setup".to_string(), "Usage".to_string());
+        checklist.item(true, None);
+        checklist.item(true, Some(&setup));
+        checklist.item(false, Some(&setup));
+        checklist.item(false, Some(&usage));
+        assert_eq!(
+            checklist,
+            Checklist {
+                done: 2,
+                total: 4,
+                sections: vec![
+                    ChecklistSection {
+                        section: setup,
+                        done: 1,
+                        total: 2
+                    },
+                    ChecklistSection {
+                        section: usage,
+                        done: 0,
+                        total: 1
+                    },
+                ],
+            }
+        );
+
+        let block = ChecklistSummaryBlock::new(
+            &serde_yaml::from_str("{t: ChecklistSummary, sections: false}").unwrap(),
+            Arc::new(RwLock::new(checklist)),
+        );
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert_eq!(html.matches("<progress").count(), 1);
+        assert!(html.contains(r#"<span class="progress-value">2/4</span>"#));
+    }
+
+    #[test]
+    fn checklist_summary() {
+        let document = r#"
+- [x] Outline
+
+```{t: ChecklistSummary}
+```
+
+# Setup
+
+- [x] Install
+- [ ] Configure
+
+# Usage
+
+- [ ] Write *docs*
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert_eq!(meta.checklist.done, 2);
+        assert_eq!(meta.checklist.total, 4);
+        assert_eq!(
+            meta.checklist.sections,
+            vec![
+                ChecklistSection {
+                    section: "Setup".into(),
+                    done: 1,
+                    total: 2
+                },
+                ChecklistSection {
+                    section: "Usage".into(),
+                    done: 0,
+                    total: 1
+                },
+            ]
+        );
+        assert!(html.contains(r#"<span class="progress-value">2/4</span>"#));
+        assert!(html.contains(
+            r#"<span class="progress-label">Setup</span><progress class="progress" value="1" max="2""#
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("```{\"t\":\"ChecklistSummary\"}\n```"));
+        assert_eq!(md, render_markdown(&options, &md).1);
+    }
+}
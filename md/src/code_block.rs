@@ -1,5 +1,15 @@
-use crate::{CustomBlock, CustomBlockHeader, CustomBlockReader, Format, Result};
+use crate::{
+    utils::header_field, CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType,
+    Format, HeaderField, Result, YamdrOptions,
+};
 use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter};
 
 static HIGHLIGHT_NAMES: &[&str] = &[
@@ -23,18 +33,88 @@ static HIGHLIGHT_NAMES: &[&str] = &[
     "variable.parameter",
 ];
 
+/// Command that checks that Rust code compiles, without building it, for
+/// `YamdrOptions::code_checks`.
+pub const RUST_CHECK_COMMAND: &[&str] = &[
+    "rustc",
+    "--edition",
+    "2021",
+    "--emit=metadata",
+    "--crate-type=lib",
+];
+
 #[derive(Debug, Clone)]
 pub struct CodeBlock {
     header: CustomBlockHeader,
     code: String,
+    /// Output of the check command, if the code was checked and the check
+    /// failed.
+    check_failure: Option<String>,
 }
 
-pub struct CodeBlockReader {}
+/// Reads `Code` blocks. Blocks with the same `continue` name, like
+/// `{t: Code, language: rust, continue: parser}`, are parts of one snippet,
+/// that are displayed separately, but checked together: a block with
+/// `check: true` is checked with the code of the earlier blocks of the
+/// snippet before its own.
+pub struct CodeBlockReader {
+    checks: BTreeMap<String, Vec<String>>,
+    /// The code read so far of each snippet, by name.
+    snippets: BTreeMap<String, String>,
+}
 
 impl CodeBlockReader {
-    pub fn initial_state() -> Self {
-        CodeBlockReader {}
+    pub fn new(options: &YamdrOptions) -> Self {
+        CodeBlockReader {
+            checks: options.code_checks.clone(),
+            snippets: BTreeMap::new(),
+        }
+    }
+}
+
+/// Extension of the file the code is written to when it's checked, so
+/// tools that look at it, like `rustc` for the crate name, are happy.
+fn extension(language: &str) -> &str {
+    match language {
+        "rust" => "rs",
+        "javascript" => "js",
+        "python" => "py",
+        language => language,
+    }
+}
+
+/// Run `command` with a file containing `code` as the last argument, from
+/// an empty directory, returning what it printed if it fails.
+fn check(command: &[String], language: &str, code: &str) -> Result<Option<String>> {
+    static CHECKS: AtomicUsize = AtomicUsize::new(0);
+    let Some((program, args)) = command.split_first() else {
+        return Ok(None);
+    };
+    let dir = std::env::temp_dir().join(format!(
+        "yamdr-check-{}-{}",
+        std::process::id(),
+        CHECKS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = format!("snippet.{}", extension(language));
+    let run = || {
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(&file), code)?;
+        Command::new(program)
+            .args(args)
+            .arg(&file)
+            .current_dir(&dir)
+            .output()
+    };
+    let output = run();
+    let _ = std::fs::remove_dir_all(&dir);
+    let output = output
+        .map_err(|err| Error::CustomBlockRead(format!("couldn't run `{}`: {}", program, err)))?;
+    if output.status.success() {
+        return Ok(None);
     }
+    let mut printed = String::from_utf8_lossy(&output.stderr).into_owned();
+    printed += &String::from_utf8_lossy(&output.stdout);
+    Ok(Some(printed.trim_end().to_string()))
 }
 
 impl CustomBlockReader for CodeBlockReader {
@@ -42,48 +122,142 @@ impl CustomBlockReader for CodeBlockReader {
         header.t == "Code"
     }
 
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Code"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("filename", FieldType::String),
+            HeaderField::new("language", FieldType::String),
+            HeaderField::new("numbers", FieldType::Bool),
+            HeaderField::new("numbers_start_at", FieldType::Integer),
+            HeaderField::new("tab_width", FieldType::Integer),
+            HeaderField::new("dedent", FieldType::Bool),
+            HeaderField::new("check", FieldType::Bool),
+            HeaderField::new("continue", FieldType::String),
+        ];
+        Some(SCHEMA)
+    }
+
     fn read_block(
         &mut self,
         header: &CustomBlockHeader,
         input: &str,
     ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let check_with = header
+            .fields
+            .get("check")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false)
+            .then(|| header_field(header, "language"))
+            .flatten()
+            .and_then(|language| Some((self.checks.get(&language)?, language)));
+        let code = match header_field(header, "continue") {
+            Some(name) => {
+                let snippet = self.snippets.entry(name).or_default();
+                if !snippet.is_empty() && !snippet.ends_with('\n') {
+                    snippet.push('\n');
+                }
+                *snippet += input;
+                snippet.as_str()
+            }
+            None => input,
+        };
+        let check_failure = match check_with {
+            Some((command, language)) => check(command, &language, code)?,
+            None => None,
+        };
         Ok(Some(Box::new(CodeBlock {
             header: header.clone(),
             code: input.into(),
+            check_failure,
         })))
     }
 }
 
 impl CustomBlock for CodeBlock {
-    fn to_events(&self, format: Format) -> Vec<Event<'static>> {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
         match format {
             Format::Html => {
-                let filename = self.header.fields.get("filename").and_then(serde_yaml::Value::as_str);
-                let language = self.header.fields.get("language").and_then(serde_yaml::Value::as_str);
-                let numbered = self.header.fields.get("numbers").and_then(serde_yaml::Value::as_bool);
-                let numbers_start_at = self.header.fields.get("numbers_start_at").and_then(serde_yaml::Value::as_u64);
+                let filename = self
+                    .header
+                    .fields
+                    .get("filename")
+                    .and_then(serde_yaml::Value::as_str);
+                let language = self
+                    .header
+                    .fields
+                    .get("language")
+                    .and_then(serde_yaml::Value::as_str);
+                let numbered = self
+                    .header
+                    .fields
+                    .get("numbers")
+                    .and_then(serde_yaml::Value::as_bool);
+                let numbers_start_at = self
+                    .header
+                    .fields
+                    .get("numbers_start_at")
+                    .and_then(serde_yaml::Value::as_u64);
+                let numbered = numbered.unwrap_or(filename.is_some());
+                let filename = filename.map(|filename| {
+                    let mut escaped = String::new();
+                    escape_html(&mut escaped, filename).unwrap();
+                    escaped
+                });
                 let open_tags = format!(
-                    r#"<div><pre data-file="{}" class="codeblock language-{}"><code class="{}">"#,
-                    filename.unwrap_or(""),
+                    r#"<div><pre data-file="{0}"{1} class="codeblock language-{2}"><code class="{3}">"#,
+                    filename.as_deref().unwrap_or(""),
+                    filename
+                        .as_ref()
+                        .map(|filename| format!(r#" aria-label="{}""#, filename))
+                        .unwrap_or_default(),
                     language.unwrap_or("none"),
-                    if numbered
-                        .unwrap_or(filename.is_some()) { "numbered" } else { "" },
+                    if numbered { "numbered" } else { "" },
                 );
                 let numbers_start_at = numbers_start_at.unwrap_or(1);
-                let mut events = vec![Event::Html(open_tags.into())];
-                let code = highlight(&self.code, language, true);
+                let tab_width = self
+                    .header
+                    .fields
+                    .get("tab_width")
+                    .and_then(serde_yaml::Value::as_u64)
+                    .map_or_else(|| default_tab_width(language), |width| width as usize);
+                let dedent = self
+                    .header
+                    .fields
+                    .get("dedent")
+                    .and_then(serde_yaml::Value::as_bool)
+                    .unwrap_or(false);
+                let code = normalize_indentation(&self.code, tab_width, dedent);
+                let code = highlight(&code, language, true);
+                // The whole block is written to one string, instead of one event per line
+                let mut html = open_tags;
+                html.reserve(code.len() + code.len() / 4);
                 for (i, line) in code.lines().enumerate() {
-                    let line = format!(
-                        r#"<span data-linenumber="{}|">{}</span>{}"#,
-                        i as u64 + numbers_start_at,
-                        line,
-                        "\n"
-                    );
-                    events.push(Event::Html(line.into()));
+                    html += r#"<span class="line">"#;
+                    // Line numbers are hidden from screen readers, and can't be selected,
+                    // so they aren't included when copying the code
+                    if numbered {
+                        write!(
+                            html,
+                            r#"<span class="linenumber" aria-hidden="true">{}|</span>"#,
+                            i as u64 + numbers_start_at,
+                        )
+                        .unwrap();
+                    }
+                    html += line;
+                    html += "</span>\n";
+                }
+                html += r#"</code></pre>"#;
+                if let Some(failure) = &self.check_failure {
+                    html += r#"<pre class="code-check" role="alert">"#;
+                    escape_html(&mut html, failure).unwrap();
+                    html += "</pre>";
                 }
-                events.push(Event::Html(r#"</code></pre></div>"#.into()));
+                html += "</div>";
 
-                events
+                vec![Event::Html(html.into())]
             }
             Format::Md => {
                 let props: pulldown_cmark::CowStr =
@@ -93,7 +267,7 @@ impl CustomBlock for CodeBlock {
                 let mut events = vec![Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
                     props.clone(),
                 )))];
-                events.push(Event::Text(self.code.clone().into()));
+                events.push(Event::Text(self.code.as_str().into()));
                 events.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))));
 
                 events
@@ -102,43 +276,118 @@ impl CustomBlock for CodeBlock {
     }
 }
 
-pub fn highlight_config(lang: &str) -> Option<HighlightConfiguration> {
+/// Width of a tab in code in `language`, unless set with `tab_width` in
+/// the header.
+fn default_tab_width(language: Option<&str>) -> usize {
+    match language {
+        Some("make" | "makefile") => 8,
+        _ => 4,
+    }
+}
+
+/// `code` with tabs expanded to spaces, to the next multiple of
+/// `tab_width`, and with the indentation all lines have in common removed
+/// if `dedent` is set. Blank lines don't count towards the common
+/// indentation. Done before highlighting, so tabs are rendered the same in
+/// every browser and snippets pasted from indented code start at the left.
+fn normalize_indentation(code: &str, tab_width: usize, dedent: bool) -> Cow<'_, str> {
+    if !code.contains('\t') && !dedent {
+        return Cow::Borrowed(code);
+    }
+    let lines: Vec<String> = code
+        .split('\n')
+        .map(|line| match line.contains('\t') && tab_width > 0 {
+            true => {
+                let mut expanded = String::with_capacity(line.len() + tab_width);
+                let mut column = 0;
+                for c in line.chars() {
+                    if c == '\t' {
+                        let spaces = tab_width - column % tab_width;
+                        expanded += &" ".repeat(spaces);
+                        column += spaces;
+                    } else {
+                        expanded.push(c);
+                        column += 1;
+                    }
+                }
+                expanded
+            }
+            false => line.to_string(),
+        })
+        .collect();
+    let indentation = match dedent {
+        true => lines
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start_matches(' ').len())
+            .min()
+            .unwrap_or(0),
+        false => 0,
+    };
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|line| line.get(indentation..).unwrap_or_else(|| line.trim_start()))
+        .collect();
+    Cow::Owned(lines.join("\n"))
+}
+
+fn configured(mut config: HighlightConfiguration) -> HighlightConfiguration {
+    config.configure(HIGHLIGHT_NAMES);
+    config
+}
+
+/// Highlight configuration for a language, if it's supported. Building a
+/// configuration compiles the highlight queries of the language, so each
+/// configuration is built the first time it's used, and then shared by all
+/// code blocks.
+pub fn highlight_config(lang: &str) -> Option<&'static HighlightConfiguration> {
+    static RUST: OnceLock<HighlightConfiguration> = OnceLock::new();
+    static GO: OnceLock<HighlightConfiguration> = OnceLock::new();
+    static JAVASCRIPT: OnceLock<HighlightConfiguration> = OnceLock::new();
     match lang {
-        "rust" => Some(
-            HighlightConfiguration::new(
-                tree_sitter_rust::language(),
-                tree_sitter_rust::HIGHLIGHT_QUERY,
-                "",
-                "",
+        "rust" => Some(RUST.get_or_init(|| {
+            configured(
+                HighlightConfiguration::new(
+                    tree_sitter_rust::language(),
+                    tree_sitter_rust::HIGHLIGHT_QUERY,
+                    "",
+                    "",
+                )
+                .unwrap(),
             )
-            .unwrap(),
-        ),
-        "go" => Some(
-            HighlightConfiguration::new(
-                tree_sitter_go::language(),
-                tree_sitter_go::HIGHLIGHT_QUERY,
-                "",
-                "",
+        })),
+        "go" => Some(GO.get_or_init(|| {
+            configured(
+                HighlightConfiguration::new(
+                    tree_sitter_go::language(),
+                    tree_sitter_go::HIGHLIGHT_QUERY,
+                    "",
+                    "",
+                )
+                .unwrap(),
             )
-            .unwrap(),
-        ),
-        "javascript" => Some(
-            HighlightConfiguration::new(
-                tree_sitter_javascript::language(),
-                tree_sitter_javascript::HIGHLIGHT_QUERY,
-                "",
-                "",
+        })),
+        "javascript" => Some(JAVASCRIPT.get_or_init(|| {
+            configured(
+                HighlightConfiguration::new(
+                    tree_sitter_javascript::language(),
+                    tree_sitter_javascript::HIGHLIGHT_QUERY,
+                    "",
+                    "",
+                )
+                .unwrap(),
             )
-            .unwrap(),
-        ),
+        })),
         _ => None,
     }
 }
 
-pub fn highlight(code: &String, lang: Option<&str>, escape: bool) -> String {
-    let mut highlighter = Highlighter::new();
+thread_local! {
+    static HIGHLIGHTER: RefCell<Highlighter> = RefCell::new(Highlighter::new());
+}
 
-    let mut config = if let Some(config) = lang.and_then(highlight_config) {
+pub fn highlight(code: &str, lang: Option<&str>, escape: bool) -> String {
+    let config = if let Some(config) = lang.and_then(highlight_config) {
         config
     } else {
         if escape {
@@ -146,16 +395,24 @@ pub fn highlight(code: &String, lang: Option<&str>, escape: bool) -> String {
             escape_html(&mut escaped, code).unwrap();
             return escaped;
         }
-        return code.clone();
+        return code.to_string();
     };
 
-    config.configure(HIGHLIGHT_NAMES);
+    HIGHLIGHTER
+        .with(|highlighter| highlight_with(&mut highlighter.borrow_mut(), config, code, escape))
+}
 
+fn highlight_with(
+    highlighter: &mut Highlighter,
+    config: &HighlightConfiguration,
+    code: &str,
+    escape: bool,
+) -> String {
     let highlights = highlighter
-        .highlight(&config, code.as_bytes(), None, |_| None)
+        .highlight(config, code.as_bytes(), None, |_| None)
         .unwrap();
 
-    let mut highlighted = String::new();
+    let mut highlighted = String::with_capacity(code.len() * 2);
 
     let mut current_highlight: Option<usize> = None;
 
@@ -194,3 +451,120 @@ pub fn highlight(code: &String, lang: Option<&str>, escape: bool) -> String {
 
     highlighted
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_highlight_config() {
+        let a = highlight_config("rust").unwrap() as *const _;
+        let b = highlight_config("rust").unwrap() as *const _;
+        assert_eq!(a, b);
+        assert!(highlight_config("cobol").is_none());
+
+        let code = "fn main() {}";
+        let first = highlight(code, Some("rust"), true);
+        assert!(first.contains(r#"<span class="_keyword">fn</span>"#));
+        assert_eq!(highlight(code, Some("rust"), true), first);
+        assert_eq!(highlight("a < b", None, true), "a &lt; b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checks_code() {
+        let command = [
+            "sh",
+            "-c",
+            "grep -q ok \"$0\" || { echo \"<$0> is not ok\" >&2; exit 1; }",
+        ];
+        let mut reader = CodeBlockReader::new(&YamdrOptions {
+            code_checks: [(
+                "sh".to_string(),
+                command.iter().map(|arg| arg.to_string()).collect(),
+            )]
+            .into(),
+            ..Default::default()
+        });
+        let render = |reader: &mut CodeBlockReader, header: &str, code: &str| {
+            let header: CustomBlockHeader = serde_yaml::from_str(header).unwrap();
+            let block = reader.read_block(&header, code).unwrap().unwrap();
+            crate::html::render(block.to_events(Format::Html).into_iter(), 0)
+        };
+        let html = render(
+            &mut reader,
+            "{t: Code, language: sh, check: true}",
+            "fail\n",
+        );
+        assert!(html.contains(
+            r#"<pre class="code-check" role="alert">&lt;snippet.sh&gt; is not ok</pre>"#
+        ));
+        let html = render(&mut reader, "{t: Code, language: sh, check: true}", "ok\n");
+        assert!(!html.contains("code-check"));
+        let html = render(&mut reader, "{t: Code, language: sh}", "fail\n");
+        assert!(!html.contains("code-check"));
+        let html = render(
+            &mut reader,
+            "{t: Code, language: rust, check: true}",
+            "fail\n",
+        );
+        assert!(!html.contains("code-check"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checks_snippets() {
+        // Fails unless both `first` and `second` are in the file
+        let command = ["sh", "-c", "grep -q first \"$0\" && grep -q second \"$0\""];
+        let mut reader = CodeBlockReader::new(&YamdrOptions {
+            code_checks: [(
+                "sh".to_string(),
+                command.iter().map(|arg| arg.to_string()).collect(),
+            )]
+            .into(),
+            ..Default::default()
+        });
+        let mut failed = |header: &str, code: &str| {
+            let header: CustomBlockHeader = serde_yaml::from_str(header).unwrap();
+            let block = reader.read_block(&header, code).unwrap().unwrap();
+            let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+            // Only the code of the block itself is displayed
+            assert!(html.contains(&format!(
+                r#"<code class=""><span class="line">{}</span>"#,
+                code
+            )));
+            html.contains("code-check")
+        };
+        assert!(failed(
+            "{t: Code, language: sh, check: true, continue: a}",
+            "first"
+        ));
+        assert!(failed("{t: Code, language: sh, check: true}", "second"));
+        assert!(!failed(
+            "{t: Code, language: sh, check: true, continue: a}",
+            "second"
+        ));
+        assert!(failed(
+            "{t: Code, language: sh, check: true, continue: b}",
+            "second"
+        ));
+    }
+
+    #[test]
+    fn normalizes_indentation() {
+        assert_eq!(
+            normalize_indentation("\tif x {\n\t\ty\n", 4, false),
+            "    if x {\n        y\n"
+        );
+        assert_eq!(normalize_indentation("a\tb", 4, false), "a   b");
+        assert_eq!(
+            normalize_indentation("    fn a() {\n\n        b();\n    }\n", 4, true),
+            "fn a() {\n\n    b();\n}\n"
+        );
+        assert_eq!(normalize_indentation("\tx\n  y", 2, true), "x\ny");
+        assert!(matches!(
+            normalize_indentation("  x", 4, false),
+            Cow::Borrowed("  x")
+        ));
+    }
+}
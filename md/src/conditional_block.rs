@@ -0,0 +1,228 @@
+use crate::{utils::yaml_to_dynamic, CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+use std::collections::BTreeMap;
+
+/// A block with a markdown body that is only rendered if the `cond` field
+/// of the header, a script expression, is true.
+///
+/// ````text
+/// ```{t: If, cond: "format == 'html' && audience == 'internal'"}
+/// Only shown in the internal html version.
+/// ```
+/// ````
+///
+/// The expression can use `format`, the format that is rendered to, and the
+/// variables of the document, see `YamdrOptions::variables`, where undefined
+/// variables are `()`. Strings may be written in single quotes, to fit in a
+/// yaml header. The body is read like the rest of the document, so it can
+/// contain other blocks. When rendering to markdown the block is always kept
+/// as it is.
+#[derive(Debug, Clone)]
+pub struct ConditionalBlock {
+    header: CustomBlockHeader,
+    body: String,
+    visible: bool,
+    /// The body rendered to html, if it is visible.
+    html: String,
+}
+
+impl ConditionalBlock {
+    /// Read the block, evaluating the condition with `variables`. Fails if
+    /// the condition is missing or isn't a boolean expression.
+    pub fn read(
+        header: &CustomBlockHeader,
+        body: &str,
+        format: Format,
+        variables: &BTreeMap<String, serde_yaml::Value>,
+    ) -> Result<Self, String> {
+        let cond = header
+            .fields
+            .get("cond")
+            .and_then(serde_yaml::Value::as_str)
+            .ok_or_else(|| "missing `cond` in If block".to_string())?;
+        let visible = eval_condition(cond, format, variables)?;
+        Ok(ConditionalBlock {
+            header: header.clone(),
+            body: body.into(),
+            visible,
+            html: String::new(),
+        })
+    }
+
+    /// Render the body to html with `render`, if it is visible, so a hidden
+    /// body doesn't run any scripts.
+    pub(crate) fn render(&mut self, render: impl FnOnce(&str) -> String) {
+        if self.visible {
+            self.html = render(&self.body);
+        }
+    }
+}
+
+/// Replace single quoted strings with double quoted ones, as single quotes
+/// are characters in scripts.
+fn quote_strings(cond: &str) -> String {
+    let mut output = String::with_capacity(cond.len());
+    let mut quote = None;
+    let mut escaped = false;
+    for c in cond.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (Some('\''), '"') => {
+                output.push_str("\\\"");
+                continue;
+            }
+            _ => {}
+        }
+        output.push(match (quote, c) {
+            (Some('\''), '\'') | (None, '\'') => '"',
+            _ => c,
+        });
+    }
+    output
+}
+
+fn eval_condition(
+    cond: &str,
+    format: Format,
+    variables: &BTreeMap<String, serde_yaml::Value>,
+) -> Result<bool, String> {
+    let mut scope = Scope::new();
+    for (name, value) in variables {
+        scope.push_constant_dynamic(name.as_str(), yaml_to_dynamic(value));
+    }
+    let format = match format {
+        Format::Html => "html",
+        Format::Md => "md",
+    };
+    scope.push_constant("format", format);
+    let engine = Engine::new();
+    let ast = engine
+        .compile_expression(quote_strings(cond))
+        .map_err(|err| format!("invalid condition `{}`: {}", cond, err))?;
+    loop {
+        match engine.eval_ast_with_scope::<bool>(&mut scope, &ast) {
+            // Undefined variables are `()`, so conditions on variables that
+            // aren't set are false instead of failing
+            Err(err) => match *err {
+                EvalAltResult::ErrorVariableNotFound(name, _) => {
+                    scope.push_constant_dynamic(name, Dynamic::UNIT);
+                }
+                err => return Err(format!("invalid condition `{}`: {}", cond, err)),
+            },
+            Ok(visible) => return Ok(visible),
+        }
+    }
+}
+
+impl CustomBlock for ConditionalBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html if self.visible => vec![Event::Html(self.html.as_str().into())],
+            Format::Html => Vec::new(),
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.body.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error, YamdrOptions};
+
+    #[test]
+    fn single_quotes() {
+        assert_eq!(quote_strings("a == 'b'"), r#"a == "b""#);
+        assert_eq!(
+            quote_strings(r#"'say "hi"' + "it's""#),
+            r#""say \"hi\"" + "it's""#
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        let header: CustomBlockHeader =
+            serde_yaml::from_str(r#"{t: If, cond: "format == 'html' && audience == 'web'"}"#)
+                .unwrap();
+        let variables = serde_yaml::from_str("{audience: web}").unwrap();
+        let block = ConditionalBlock::read(&header, "*Shown*\n", Format::Html, &variables);
+        assert!(block.unwrap().visible);
+        let block = ConditionalBlock::read(&header, "*Shown*\n", Format::Md, &variables);
+        assert!(!block.unwrap().visible);
+        let block = ConditionalBlock::read(&header, "", Format::Html, &BTreeMap::new());
+        assert!(!block.unwrap().visible);
+
+        let header = CustomBlockHeader::empty("If".into());
+        assert!(ConditionalBlock::read(&header, "", Format::Html, &variables).is_err());
+        let header: CustomBlockHeader = serde_yaml::from_str(r#"{t: If, cond: "1 + 1"}"#).unwrap();
+        assert!(ConditionalBlock::read(&header, "", Format::Html, &variables).is_err());
+    }
+
+    #[test]
+    fn conditional_blocks() {
+        let document = r#"
+```{t: External, meta: true, lang: en}
+draft: true
+```
+
+```{t: If, cond: "format == 'html' && audience == 'internal'"}
+Internal *notes*.
+```
+
+```{t: If, cond: "draft"}
+Draft.
+```
+
+```{t: If, cond: "not a condition"}
+Invalid.
+```
+
+````{t: If, cond: "draft"}
+"Nested" $x^2$ `badge:draft`
+
+```{t: Graph}
+digraph { a -> b }
+```
+````
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(!html.contains("Internal"));
+        assert!(html.contains("<p>Draft.</p>"));
+        // Smart punctuation is left to the typography option, like elsewhere
+        assert!(html.contains("<p>&quot;Nested&quot; <math>"));
+        assert!(html.contains(r#"<span class="badge badge-gray">draft</span>"#));
+        assert!(html.contains("<svg"));
+        assert!(!html.contains("Invalid"));
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::CustomBlockRead(_)]
+        ));
+
+        let mut options = YamdrOptions {
+            variables: [("audience".to_string(), "internal".to_string())].into(),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains("<p>Internal <em>notes</em>.</p>"));
+
+        options.format = Some(Format::Md);
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("```{\"t\":\"If\",\"cond\":\"format == 'html' && audience == 'internal'\"}\nInternal *notes*.\n```"));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{block_types, CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use std::collections::BTreeMap;
+
+/// Default header fields of each block type.
+pub(crate) type Defaults = BTreeMap<String, BTreeMap<String, serde_yaml::Value>>;
+
+/// A `Defaults` block, that sets default header fields for blocks of a type.
+///
+/// ````text
+/// ```{t: Defaults}
+/// Code: {numbers: true}
+/// DynamicChart: {alt: Chart of the data above}
+/// ```
+/// ````
+///
+/// The defaults apply to the blocks after the `Defaults` block, and the
+/// fields in the header of a block take precedence. They are only applied
+/// in html output, so rendering to markdown keeps the headers as they are
+/// written. Later `Defaults` blocks add to and override earlier ones.
+#[derive(Debug, Clone)]
+pub struct DefaultsBlock {
+    header: CustomBlockHeader,
+    body: String,
+}
+
+impl DefaultsBlock {
+    /// Read a `Defaults` block, adding its fields to `defaults`.
+    pub fn read(
+        header: &CustomBlockHeader,
+        body: &str,
+        defaults: &mut Defaults,
+    ) -> Result<Self, String> {
+        let parsed: Defaults = match body.trim() {
+            "" => BTreeMap::new(),
+            body => serde_yaml::from_str(body).map_err(|err| err.to_string())?,
+        };
+        for (t, fields) in parsed {
+            if let Some(field) = ["t", "id"].iter().find(|f| fields.contains_key(**f)) {
+                return Err(format!("`{}` can't have a default, in {}", field, t));
+            }
+            let t = block_types::alias(&t).map_or(t, |alias| alias.t.into());
+            defaults.entry(t).or_default().extend(fields);
+        }
+        Ok(DefaultsBlock {
+            header: header.clone(),
+            body: body.into(),
+        })
+    }
+}
+
+/// `header` with the defaults for its type added, for the fields it
+/// doesn't set itself.
+pub(crate) fn apply(defaults: &Defaults, mut header: CustomBlockHeader) -> CustomBlockHeader {
+    if let Some(fields) = defaults.get(&header.t) {
+        for (name, value) in fields {
+            header
+                .fields
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+    header
+}
+
+impl CustomBlock for DefaultsBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => Vec::new(),
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.body.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn merge_defaults() {
+        let mut defaults = Defaults::new();
+        let header = CustomBlockHeader::empty("Defaults".into());
+        DefaultsBlock::read(
+            &header,
+            "Code: {numbers: true, language: rust}",
+            &mut defaults,
+        )
+        .unwrap();
+        DefaultsBlock::read(
+            &header,
+            "Code: {language: go}\nChart: {alt: A chart}",
+            &mut defaults,
+        )
+        .unwrap();
+        assert!(DefaultsBlock::read(&header, "Code: {id: a}", &mut defaults).is_err());
+
+        let code: CustomBlockHeader = serde_yaml::from_str("{t: Code, numbers: false}").unwrap();
+        let code = apply(&defaults, code);
+        assert_eq!(code.fields["numbers"], serde_yaml::Value::Bool(false));
+        assert_eq!(code.fields["language"], serde_yaml::Value::from("go"));
+        assert!(defaults["DynamicChart"].contains_key("alt"));
+    }
+
+    #[test]
+    fn defaults() {
+        let document = r#"
+```{t: Code}
+a
+```
+
+```{t: Defaults}
+Code: {filename: main.rs}
+```
+
+```{t: Code}
+b
+```
+
+```{t: Code, filename: lib.rs}
+c
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty());
+        assert_eq!(html.matches(r#"data-file="main.rs""#).count(), 1);
+        assert_eq!(html.matches(r#"data-file="lib.rs""#).count(), 1);
+        assert_eq!(html.matches("data-file=\"\"").count(), 1);
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert_eq!(md.matches("main.rs").count(), 1);
+    }
+}
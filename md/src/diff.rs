@@ -0,0 +1,287 @@
+use crate::{MarkdownBlock, MarkdownDocumentBlocks};
+use pulldown_cmark::escape::escape_html;
+use serde::{Deserialize, Serialize};
+
+/// How a block of a document changed from an old version of the document,
+/// with the indices of the block in `MarkdownDocumentBlocks::blocks` of the
+/// old and new versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum BlockChange {
+    Unchanged { old: usize, new: usize },
+    Added { new: usize },
+    Removed { old: usize },
+    Changed { old: usize, new: usize },
+}
+
+impl BlockChange {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, BlockChange::Unchanged { .. })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// The edits turning `old` into `new`, from their longest common
+/// subsequence.
+pub(crate) fn edits<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // lengths[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            edits.push(Edit::Delete(i));
+            i += 1;
+        } else {
+            edits.push(Edit::Insert(j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// The markdown of a block, or the header and body of external blocks,
+/// which aren't rerendered.
+fn block_source(block: &MarkdownBlock) -> String {
+    match &block.external {
+        Some(external) => format!(
+            "{}\n{}",
+            serde_json::to_string(&external.head).unwrap_or_default(),
+            external.body
+        ),
+        None => block.markdown.clone(),
+    }
+}
+
+/// Pair up removed and added blocks as changed blocks, and report the rest
+/// as removed or added.
+fn flush(changes: &mut Vec<BlockChange>, removed: &mut Vec<usize>, added: &mut Vec<usize>) {
+    let paired = removed.len().min(added.len());
+    changes.extend(
+        removed
+            .iter()
+            .zip(added.iter())
+            .map(|(&old, &new)| BlockChange::Changed { old, new }),
+    );
+    changes.extend(
+        removed
+            .drain(..)
+            .skip(paired)
+            .map(|old| BlockChange::Removed { old }),
+    );
+    changes.extend(
+        added
+            .drain(..)
+            .skip(paired)
+            .map(|new| BlockChange::Added { new }),
+    );
+}
+
+/// Compare the blocks of two versions of a document, from `render_blocks`.
+///
+/// Blocks that are removed and added between the same unchanged blocks are
+/// paired up as changed blocks, in order, and the rest are reported as
+/// removed or added.
+pub fn diff_blocks(old: &MarkdownDocumentBlocks, new: &MarkdownDocumentBlocks) -> Vec<BlockChange> {
+    let old_sources: Vec<_> = old.blocks.iter().map(block_source).collect();
+    let new_sources: Vec<_> = new.blocks.iter().map(block_source).collect();
+
+    let mut changes = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for edit in edits(&old_sources, &new_sources) {
+        match edit {
+            Edit::Equal(old, new) => {
+                flush(&mut changes, &mut removed, &mut added);
+                changes.push(BlockChange::Unchanged { old, new });
+            }
+            Edit::Delete(old) => removed.push(old),
+            Edit::Insert(new) => added.push(new),
+        }
+    }
+    flush(&mut changes, &mut removed, &mut added);
+    changes
+}
+
+/// A one line summary of a block, its first line of text.
+pub fn block_summary(block: &MarkdownBlock) -> String {
+    let source = block_source(block);
+    let line = source.lines().map(str::trim).find(|line| !line.is_empty());
+    let line = line.unwrap_or_default();
+    match line.char_indices().nth(60) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Words and the whitespace between them, so whitespace is kept when the
+/// words are diffed.
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut whitespace = None;
+    for (i, c) in text.char_indices() {
+        if whitespace != Some(c.is_whitespace()) {
+            if i > start {
+                words.push(&text[start..i]);
+            }
+            start = i;
+            whitespace = Some(c.is_whitespace());
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Html of the markdown of a changed block, with removed words in `<del>`
+/// and added words in `<ins>`.
+fn word_diff_html(old: &str, new: &str) -> String {
+    let (old, new) = (words(old), words(new));
+    let mut html = String::new();
+    let mut tag = "";
+    for edit in edits(&old, &new) {
+        let (next, word) = match edit {
+            Edit::Equal(_, j) => ("", new[j]),
+            Edit::Delete(i) => ("del", old[i]),
+            Edit::Insert(j) => ("ins", new[j]),
+        };
+        if next != tag {
+            if !tag.is_empty() {
+                html += &format!("</{}>", tag);
+            }
+            if !next.is_empty() {
+                html += &format!("<{}>", next);
+            }
+            tag = next;
+        }
+        escape_html(&mut html, word).unwrap();
+    }
+    if !tag.is_empty() {
+        html += &format!("</{}>", tag);
+    }
+    html
+}
+
+static DIFF_CSS: &str = r#"
+.diff-added, .diff-removed, .diff-changed {
+  margin: 1em 0;
+  padding: 0 0.5em;
+  border-left: 4px solid;
+}
+.diff-added { border-color: #2da44e; background: #e6ffec; }
+.diff-removed { border-color: #cf222e; background: #ffebe9; }
+.diff-changed { border-color: #bf8700; white-space: pre-wrap; font-family: monospace; }
+.diff-changed del { background: #ffcecb; }
+.diff-changed ins { background: #aceebb; text-decoration: none; }
+.diff-unchanged { opacity: 0.6; }
+"#;
+
+/// A html page showing the changes from `diff_blocks`: added and removed
+/// blocks are rendered, and the markdown of changed blocks is shown with
+/// the changed words marked.
+pub fn diff_html(
+    old: &MarkdownDocumentBlocks,
+    new: &MarkdownDocumentBlocks,
+    changes: &[BlockChange],
+) -> String {
+    let mut body = String::new();
+    for change in changes {
+        let (class, html) = match *change {
+            BlockChange::Unchanged { new: j, .. } => ("unchanged", new.blocks[j].html.clone()),
+            BlockChange::Added { new: j } => ("added", new.blocks[j].html.clone()),
+            BlockChange::Removed { old: i } => ("removed", old.blocks[i].html.clone()),
+            BlockChange::Changed { old: i, new: j } => (
+                "changed",
+                word_diff_html(&block_source(&old.blocks[i]), &block_source(&new.blocks[j])),
+            ),
+        };
+        body += &format!("<div class=\"diff-{}\">{}</div>\n", class, html);
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>{}{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        new.css, DIFF_CSS, body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_blocks;
+
+    #[test]
+    fn lcs_edits() {
+        assert_eq!(
+            edits(&["a", "b", "c"], &["a", "c", "d"]),
+            vec![
+                Edit::Equal(0, 0),
+                Edit::Delete(1),
+                Edit::Equal(2, 1),
+                Edit::Insert(2)
+            ]
+        );
+        assert_eq!(edits::<&str>(&[], &["a"]), vec![Edit::Insert(0)]);
+    }
+
+    #[test]
+    fn block_changes() {
+        let old = render_blocks(
+            &Default::default(),
+            "# Title\n\nFirst paragraph.\n\nSecond paragraph.\n\nRemoved.\n",
+        );
+        let new = render_blocks(
+            &Default::default(),
+            "# Title\n\nFirst paragraph, edited.\n\nSecond paragraph.\n",
+        );
+        let changes = diff_blocks(&old, &new);
+        assert_eq!(
+            changes,
+            vec![
+                BlockChange::Unchanged { old: 0, new: 0 },
+                BlockChange::Changed { old: 1, new: 1 },
+                BlockChange::Unchanged { old: 2, new: 2 },
+                BlockChange::Removed { old: 3 },
+            ]
+        );
+        assert_eq!(block_summary(&old.blocks[3]), "Removed.");
+
+        let html = diff_html(&old, &new, &changes);
+        assert!(html.contains("First <del>paragraph.</del><ins>paragraph, edited.</ins>"));
+        assert!(html.contains("<div class=\"diff-removed\"><p>Removed.</p>"));
+
+        let newer = render_blocks(
+            &Default::default(),
+            "# Title\n\nFirst paragraph, edited.\n\nAdded.\n\nSecond paragraph.\n",
+        );
+        assert_eq!(diff_blocks(&new, &newer)[2], BlockChange::Added { new: 2 });
+    }
+
+    #[test]
+    fn word_diff() {
+        assert_eq!(
+            word_diff_html("a <b> c", "a <b>  d"),
+            "a &lt;b&gt;<del> c</del><ins>  d</ins>"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::Format;
+use pulldown_cmark::{CowStr, Event};
+use std::ops::Range;
+
+/// A directive comment, like `<!-- yamdr:raw -->`, that changes how the
+/// region of the document after it is rendered, until a matching
+/// `<!-- yamdr:end -->`. Directives are only read at the top level of the
+/// document, and regions may be nested.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Directive {
+    /// `yamdr:disable-scripts`, script blocks and inline scripts are not
+    /// run, and are rendered as normal code.
+    DisableScripts,
+    /// `yamdr:disable-blocks`, fenced code blocks and inline code are never
+    /// read as custom blocks.
+    DisableBlocks,
+    /// `yamdr:raw`, custom blocks are disabled, and the region is kept as
+    /// it is written when rendering to markdown, instead of being formatted.
+    Raw,
+    /// `yamdr:end`, ends the innermost region.
+    End,
+}
+
+impl Directive {
+    pub fn parse(html: &str) -> Option<Directive> {
+        let name = html
+            .trim()
+            .strip_prefix("<!--")?
+            .strip_suffix("-->")?
+            .trim()
+            .strip_prefix("yamdr:")?;
+        match name {
+            "disable-scripts" => Some(Directive::DisableScripts),
+            "disable-blocks" => Some(Directive::DisableBlocks),
+            "raw" => Some(Directive::Raw),
+            "end" => Some(Directive::End),
+            _ => None,
+        }
+    }
+
+    pub fn from_event(event: &Event) -> Option<Directive> {
+        match event {
+            Event::Html(html) => Directive::parse(html),
+            _ => None,
+        }
+    }
+}
+
+/// The active directives at some point of a document.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Directives {
+    stack: Vec<Directive>,
+    /// Nesting level of the events, as directives are only read at the top
+    /// level.
+    level: usize,
+}
+
+impl Directives {
+    /// Update the active directives with `event`. Returns the directive of
+    /// the event, if any.
+    pub fn event(&mut self, event: &Event) -> Option<Directive> {
+        match event {
+            Event::Start(_) => self.level += 1,
+            Event::End(_) => self.level -= 1,
+            _ => {}
+        }
+        let directive = Directive::from_event(event).filter(|_| self.level == 0)?;
+        match directive {
+            Directive::End => {
+                self.stack.pop();
+            }
+            directive => self.stack.push(directive),
+        }
+        Some(directive)
+    }
+
+    pub fn scripts_disabled(&self) -> bool {
+        self.stack.contains(&Directive::DisableScripts)
+    }
+
+    pub fn blocks_disabled(&self) -> bool {
+        self.stack
+            .iter()
+            .any(|directive| matches!(directive, Directive::DisableBlocks | Directive::Raw))
+    }
+
+    pub fn raw(&self) -> bool {
+        self.stack.contains(&Directive::Raw)
+    }
+}
+
+/// Iterator that, when rendering to markdown, replaces the events of each
+/// `yamdr:raw` region with one html event containing the source of the
+/// region, so it is written as it is. The directive comments themselves are
+/// kept, so the region is still raw when rendered again.
+pub(crate) struct RawRegions<'a, I> {
+    events: I,
+    source: &'a str,
+    format: Format,
+    directives: Directives,
+    /// Start of the source of the current raw region.
+    start: Option<usize>,
+    /// The event ending the current region, returned after its source.
+    end: Option<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> RawRegions<'a, I> {
+    pub fn new(events: I, source: &'a str, format: Format) -> Self {
+        RawRegions {
+            events,
+            source,
+            format,
+            directives: Directives::default(),
+            start: None,
+            end: None,
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> Iterator for RawRegions<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(end) = self.end.take() {
+            return Some(end);
+        }
+        loop {
+            let Some((event, range)) = self.events.next() else {
+                // A region that isn't ended runs to the end of the document
+                let start = self.start.take()?;
+                return Some(Event::Html(CowStr::Borrowed(&self.source[start..])));
+            };
+            if self.format == Format::Html {
+                return Some(event);
+            }
+            self.directives.event(&event);
+            match (self.start, self.directives.raw()) {
+                (None, true) => {
+                    self.start = Some(range.end);
+                    return Some(event);
+                }
+                (Some(start), false) => {
+                    self.start = None;
+                    self.end = Some(event);
+                    return Some(Event::Html(CowStr::Borrowed(
+                        &self.source[start..range.start],
+                    )));
+                }
+                (Some(_), true) => {}
+                (None, false) => return Some(event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn parse_directive() {
+        assert_eq!(
+            Directive::parse("<!-- yamdr:raw -->\n"),
+            Some(Directive::Raw)
+        );
+        assert_eq!(Directive::parse("<!--yamdr:end-->"), Some(Directive::End));
+        assert_eq!(Directive::parse("<!-- yamdr:unknown -->"), None);
+        assert_eq!(Directive::parse("<!-- raw -->"), None);
+    }
+
+    #[test]
+    fn disable_scripts() {
+        let document = r#"
+```{t: Script}
+let x = 1 + 1;
+```
+
+<!-- yamdr:disable-scripts -->
+
+```{t: Script}
+let y = 2 + 2;
+```
+
+Inline `_x + 1_`.
+
+```{t: Graph}
+digraph D { A -> B; }
+```
+
+<!-- yamdr:end -->
+
+Inline `_x + 1_`.
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert_eq!(html.matches(r#"<div class="script">"#).count(), 1);
+        assert!(html.contains("<code>_x + 1_</code>"));
+        assert!(html.contains("inline-script"));
+        assert!(html.contains("<svg"));
+    }
+
+    #[test]
+    fn raw_region() {
+        let document = r#"<!-- yamdr:raw -->
+
+```{t: Graph}
+digraph D { A -> B; }
+```
+
+*   Kept   as
+*   written
+
+<!-- yamdr:end -->
+
+*   Formatted
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(!html.contains("<svg"));
+        assert!(html.contains("digraph D { A -&gt; B; }"));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with("<!-- yamdr:raw -->\n\n```{t: Graph}\n"));
+        assert!(md.contains("*   Kept   as\n*   written\n\n<!-- yamdr:end -->\n"));
+        assert!(md.ends_with("- Formatted\n\n"));
+        assert_eq!(render_markdown(&options, &md).1, md);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{frontmatter, utils::header_field, CustomBlockHeader};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use std::ops::Range;
+
+/// What a top level element of a document is, from its first event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ElementKind {
+    Frontmatter,
+    /// A code block, or a custom block.
+    CodeBlock,
+    Html,
+    Rule,
+    /// A paragraph, heading, list, quote or table.
+    Text,
+}
+
+/// A top level element of a document, like the blocks of `render_blocks`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Element {
+    pub kind: ElementKind,
+    /// Range of the source of the element, without the blank lines after
+    /// it.
+    pub range: Range<usize>,
+    /// The `id` in the header of a custom block.
+    pub id: Option<String>,
+}
+
+/// Split the body of a document, after its frontmatter, into its top level
+/// elements. The lines of an html block are a single element, and thematic
+/// breaks are elements of their own. `offset` is added to the ranges.
+pub(crate) fn body_elements(body: &str, offset: usize) -> Vec<Element> {
+    let mut elements: Vec<Element> = Vec::new();
+    let mut level = 0;
+    for (event, range) in Parser::new_ext(body, Options::all()).into_offset_iter() {
+        if level == 0 {
+            let range = offset + range.start..offset + range.end;
+            // The lines of an html block are separate events
+            match elements.last_mut() {
+                Some(html)
+                    if matches!(event, Event::Html(_))
+                        && html.kind == ElementKind::Html
+                        && html.range.end == range.start =>
+                {
+                    html.range.end = range.end;
+                }
+                _ => {
+                    let (kind, id) = match &event {
+                        Event::Start(Tag::CodeBlock(kind)) => {
+                            let id = match kind {
+                                CodeBlockKind::Fenced(prop) => {
+                                    serde_yaml::from_str::<CustomBlockHeader>(prop)
+                                        .ok()
+                                        .and_then(|header| header_field(&header, "id"))
+                                }
+                                CodeBlockKind::Indented => None,
+                            };
+                            (ElementKind::CodeBlock, id)
+                        }
+                        Event::Html(_) => (ElementKind::Html, None),
+                        Event::Rule => (ElementKind::Rule, None),
+                        _ => (ElementKind::Text, None),
+                    };
+                    elements.push(Element { kind, range, id });
+                }
+            }
+        }
+        match event {
+            Event::Start(_) => level += 1,
+            Event::End(_) => level -= 1,
+            _ => {}
+        }
+    }
+    for element in elements.iter_mut() {
+        let source = &body[element.range.start - offset..element.range.end - offset];
+        element.range.end = element.range.start + source.trim_end().len();
+    }
+    elements
+}
+
+/// Split a document into its top level elements, with the frontmatter as
+/// an element of its own, see `body_elements`.
+pub(crate) fn elements(markdown: &str) -> Vec<Element> {
+    let (frontmatter, body) = frontmatter::split(markdown);
+    let offset = markdown.len() - body.len();
+    let frontmatter = frontmatter.map(|_| Element {
+        kind: ElementKind::Frontmatter,
+        range: 0..offset,
+        id: None,
+    });
+    frontmatter
+        .into_iter()
+        .chain(body_elements(body, offset))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        let markdown = "---\ntitle: a\n---\n# Title\n\nA\n<div>\nx\n</div>\n\n***\n```{t: Code, id: main}\nfn main() {}\n```\n\n- a\n- b\n";
+        let kinds: Vec<_> = elements(markdown)
+            .into_iter()
+            .map(|element| (element.kind, &markdown[element.range], element.id))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ElementKind::Frontmatter, "---\ntitle: a\n---\n", None),
+                (ElementKind::Text, "# Title", None),
+                (ElementKind::Text, "A", None),
+                (ElementKind::Html, "<div>\nx\n</div>", None),
+                (ElementKind::Rule, "***", None),
+                (
+                    ElementKind::CodeBlock,
+                    "```{t: Code, id: main}\nfn main() {}\n```",
+                    Some("main".into())
+                ),
+                (ElementKind::Text, "- a\n- b", None),
+            ]
+        );
+        assert!(elements("").is_empty());
+    }
+}
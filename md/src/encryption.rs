@@ -0,0 +1,148 @@
+use crate::i18n::Labels;
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pulldown_cmark::escape::escape_html;
+use sha2::Sha256;
+
+/// Iterations of PBKDF2 deriving the key from the passphrase, as
+/// recommended by OWASP for PBKDF2-HMAC-SHA256.
+const ITERATIONS: u32 = 600_000;
+
+/// Options for encrypting the content of standalone html with a
+/// passphrase, so it can be published where anyone can download it, but
+/// only be read by those given the passphrase.
+#[derive(Clone, Default)]
+pub struct EncryptionOptions {
+    pub passphrase: String,
+}
+
+/// Decrypts the content with the WebCrypto api when the passphrase is
+/// submitted, and runs the scripts in it, which aren't run when inserted
+/// with `innerHTML`.
+const DECRYPT_JS: &str = r#"
+(() => {
+  const form = document.getElementById("encrypted");
+  const bytes = (data) => Uint8Array.from(atob(data), (c) => c.charCodeAt(0));
+  form.addEventListener("submit", async (event) => {
+    event.preventDefault();
+    const passphrase = new TextEncoder().encode(form.elements.passphrase.value);
+    try {
+      const material = await crypto.subtle.importKey("raw", passphrase, "PBKDF2", false, ["deriveKey"]);
+      const key = await crypto.subtle.deriveKey(
+        { name: "PBKDF2", salt: bytes(form.dataset.salt), iterations: +form.dataset.iterations, hash: "SHA-256" },
+        material,
+        { name: "AES-GCM", length: 256 },
+        false,
+        ["decrypt"],
+      );
+      const content = await crypto.subtle.decrypt(
+        { name: "AES-GCM", iv: bytes(form.dataset.iv) },
+        key,
+        bytes(form.querySelector("script").textContent.trim()),
+      );
+      const container = form.parentElement;
+      container.innerHTML = new TextDecoder().decode(content);
+      for (const script of container.querySelectorAll("script")) {
+        const run = document.createElement("script");
+        for (const attribute of script.attributes) {
+          run.setAttribute(attribute.name, attribute.value);
+        }
+        run.textContent = script.textContent;
+        script.replaceWith(run);
+      }
+    } catch {
+      form.querySelector(".error").hidden = false;
+    }
+  });
+})();
+"#;
+
+fn encrypt_with(options: &EncryptionOptions, iterations: u32, content: &str) -> [String; 3] {
+    let mut salt = [0u8; 16];
+    let mut iv = [0u8; 12];
+    getrandom::getrandom(&mut salt).expect("failed to generate salt");
+    getrandom::getrandom(&mut iv).expect("failed to generate iv");
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(options.passphrase.as_bytes(), &salt, iterations, &mut key);
+    let encrypted = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&iv), content.as_bytes())
+        .expect("failed to encrypt content");
+    [salt.as_slice(), &iv, &encrypted].map(|bytes| STANDARD.encode(bytes))
+}
+
+fn encrypted_with(
+    options: &EncryptionOptions,
+    iterations: u32,
+    labels: &Labels,
+    content: &str,
+) -> String {
+    let [salt, iv, encrypted] = encrypt_with(options, iterations, content);
+    let label = |html: &mut String, text: &str| escape_html(html, text).unwrap();
+    let mut html = format!(
+        r#"<form id="encrypted" class="encrypted" data-salt="{}" data-iv="{}" data-iterations="{}"><label for="passphrase">"#,
+        salt, iv, iterations
+    );
+    label(&mut html, &labels.encrypted[0]);
+    html += r#"</label> <input id="passphrase" name="passphrase" type="password" autocomplete="off" autofocus> <button type="submit">"#;
+    label(&mut html, &labels.encrypted[1]);
+    html += r#"</button><p class="error" role="alert" hidden>"#;
+    label(&mut html, &labels.encrypted[2]);
+    html += r#"</p><script type="application/octet-stream">"#;
+    html += &encrypted;
+    html += "</script></form>\n<script>";
+    html += DECRYPT_JS;
+    html += "</script>";
+    html
+}
+
+/// A form asking for the passphrase in place of `content`, which is
+/// encrypted with AES-256-GCM, using a key derived from the passphrase with
+/// PBKDF2, and decrypted in the browser when the passphrase is submitted.
+pub(crate) fn encrypted(options: &EncryptionOptions, labels: &Labels, content: &str) -> String {
+    encrypted_with(options, ITERATIONS, labels, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute<'a>(html: &'a str, name: &str) -> &'a str {
+        let start = html.find(&format!(r#" {}=""#, name)).unwrap() + name.len() + 3;
+        &html[start..start + html[start..].find('"').unwrap()]
+    }
+
+    #[test]
+    fn decrypts_with_passphrase() {
+        let options = EncryptionOptions {
+            passphrase: "correct horse".into(),
+        };
+        let html = encrypted_with(&options, 1000, &Labels::default(), "<p>Secret</p>");
+        assert!(!html.contains("Secret"));
+        assert!(html.contains("Enter the passphrase to read this document."));
+        assert_eq!(attribute(&html, "data-iterations"), "1000");
+
+        let start = html
+            .find(r#"<script type="application/octet-stream">"#)
+            .unwrap()
+            + 40;
+        let end = start + html[start..].find("</script>").unwrap();
+        let bytes = |data: &str| STANDARD.decode(data).unwrap();
+        let decrypt = |passphrase: &str| {
+            let mut key = [0u8; 32];
+            let salt = bytes(attribute(&html, "data-salt"));
+            pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &salt, 1000, &mut key);
+            Aes256Gcm::new(&key.into())
+                .decrypt(
+                    Nonce::from_slice(&bytes(attribute(&html, "data-iv"))),
+                    bytes(&html[start..end]).as_slice(),
+                )
+                .ok()
+        };
+        assert_eq!(decrypt("correct horse").unwrap(), b"<p>Secret</p>");
+        assert!(decrypt("wrong").is_none());
+
+        // A new salt and iv every time
+        let other = encrypted_with(&options, 1000, &Labels::default(), "<p>Secret</p>");
+        assert_ne!(attribute(&html, "data-iv"), attribute(&other, "data-iv"));
+    }
+}
@@ -2,14 +2,47 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("custom block type `{0}` is not implemented")]
-    CustomBlockNotImplemented(String),
+    #[error(
+        "custom block type `{0}` is not implemented{}",
+        .1.as_ref().map(|t| format!(", did you mean `{}`?", t)).unwrap_or_default()
+    )]
+    CustomBlockNotImplemented(String, Option<String>),
 
     #[error("error while reading custom block: `{0}`")]
     CustomBlockRead(String),
 
     #[error("reader called with unsupported block type: `{0}`")]
     UnsupportedBlockType(String),
+
+    #[error("invalid block header: {0}")]
+    InvalidHeader(String),
+
+    #[error("block type `{0}` is deprecated, use `{1}` instead")]
+    DeprecatedBlockType(String, String),
+
+    #[error("variable `{0}` is not defined")]
+    UndefinedVariable(String),
+
+    #[error("failed to import document: {0}")]
+    Import(String),
+
+    #[error("invalid translations: {0}")]
+    Translation(String),
+
+    #[error("file `{0}` does not exist")]
+    MissingFile(String),
+
+    #[error("the document has no signature")]
+    MissingSignature,
+
+    #[error("the document was not rendered from this source")]
+    SourceMismatch,
+
+    #[error("the document was changed after it was rendered")]
+    ContentMismatch,
+
+    #[error("the signature of the document is not valid")]
+    InvalidSignature,
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,136 @@
+use crate::{
+    frontmatter, interpolation::lookup_yaml, meta_fields, parse_markdown, search, YamdrOptions,
+};
+use pulldown_cmark::escape::escape_html;
+use serde::{Deserialize, Serialize};
+
+/// A document of a site in its feed, from the fields of its meta blocks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedEntry {
+    /// Path of the rendered document, relative to the root of the site.
+    pub path: String,
+    /// The `title` field, or the first heading of the document.
+    pub title: String,
+    /// The `date` field, as an RFC 3339 timestamp.
+    pub date: String,
+    /// The `summary` field, or the `description` field.
+    pub summary: Option<String>,
+}
+
+/// `2024-05-01` or `2024-05-01T12:00:00Z` as an RFC 3339 timestamp.
+fn timestamp(date: &str) -> Option<String> {
+    let day = date.get(..10)?;
+    let valid = day.char_indices().all(|(i, c)| match i {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    });
+    match (valid, date.len()) {
+        (false, _) => None,
+        (true, 10) => Some(format!("{}T00:00:00Z", day)),
+        (true, _) if date[10..].starts_with('T') => Some(date.to_string()),
+        _ => None,
+    }
+}
+
+/// The feed entry of a markdown document, if it has a `date` in its meta
+/// blocks. Documents without a date, like an index page, aren't posts.
+pub fn feed_entry(path: &str, markdown: &str) -> Option<FeedEntry> {
+    let parsed_markdown = parse_markdown(&YamdrOptions::default(), markdown);
+    let (frontmatter, _) = frontmatter::split(markdown);
+    let fields = meta_fields(frontmatter.as_ref(), &parsed_markdown);
+    let date = timestamp(&lookup_yaml(&fields, "date")?)?;
+    let title = lookup_yaml(&fields, "title")
+        .or_else(|| {
+            search::block_texts(&parsed_markdown)
+                .into_iter()
+                .find_map(|block| block.heading)
+        })
+        .unwrap_or_else(|| path.to_string());
+    Some(FeedEntry {
+        path: path.into(),
+        title,
+        date,
+        summary: lookup_yaml(&fields, "summary").or_else(|| lookup_yaml(&fields, "description")),
+    })
+}
+
+fn element(xml: &mut String, name: &str, text: &str) {
+    *xml += &format!("<{}>", name);
+    escape_html(&mut *xml, text).unwrap();
+    *xml += &format!("</{}>", name);
+}
+
+/// An Atom feed of `entries`, newest first. `site_url` is the url the site
+/// is published at, which the paths of the entries are relative to.
+pub fn atom_feed(title: &str, site_url: &str, entries: &[FeedEntry]) -> String {
+    let site_url = site_url.trim_end_matches('/');
+    let mut entries: Vec<&FeedEntry> = entries.iter().collect();
+    entries.sort_by(|a, b| b.date.cmp(&a.date).then(a.path.cmp(&b.path)));
+    let updated = entries.first().map_or("1970-01-01T00:00:00Z", |e| &e.date);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml += "<feed xmlns=\"http://www.w3.org/2005/Atom\">";
+    element(&mut xml, "title", title);
+    element(&mut xml, "id", &format!("{}/", site_url));
+    element(&mut xml, "updated", updated);
+    xml += "<link href=\"";
+    escape_html(&mut xml, &format!("{}/feed.xml", site_url)).unwrap();
+    xml += "\" rel=\"self\"/>\n";
+    for entry in entries {
+        let url = format!("{}/{}", site_url, entry.path);
+        xml += "<entry>";
+        element(&mut xml, "title", &entry.title);
+        element(&mut xml, "id", &url);
+        xml += "<link href=\"";
+        escape_html(&mut xml, &url).unwrap();
+        xml += "\"/>";
+        element(&mut xml, "updated", &entry.date);
+        if let Some(summary) = &entry.summary {
+            element(&mut xml, "summary", summary);
+        }
+        xml += "</entry>\n";
+    }
+    xml += "</feed>\n";
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries() {
+        let post = r#"
+```{t: External, meta: true, date: 2024-05-01, summary: Fish & chips}
+```
+
+# First post
+"#;
+        let entry = feed_entry("posts/first.html", post).unwrap();
+        assert_eq!(entry.title, "First post");
+        assert_eq!(entry.date, "2024-05-01T00:00:00Z");
+        assert!(feed_entry("index.html", "# Index\n").is_none());
+
+        let later = FeedEntry {
+            path: "posts/second.html".into(),
+            title: "Second".into(),
+            date: "2024-06-01T00:00:00Z".into(),
+            summary: None,
+        };
+        let xml = atom_feed("Blog", "https://example.com/", &[entry, later]);
+        assert!(xml.contains("<id>https://example.com/posts/first.html</id>"));
+        assert!(xml.contains("<summary>Fish &amp; chips</summary>"));
+        assert!(xml.contains("<updated>2024-06-01T00:00:00Z</updated><link"));
+        assert!(xml.find("Second").unwrap() < xml.find("First post").unwrap());
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            timestamp("2024-05-01T08:30:00+02:00").unwrap(),
+            "2024-05-01T08:30:00+02:00"
+        );
+        assert!(timestamp("May 1st").is_none());
+        assert!(timestamp("2024-05-01 08:30").is_none());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{html, CustomBlock, Format};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// A reference to an inline footnote, written like `^[this]`. It's rendered
+/// as a numbered link to the footnote in html output, where the footnotes
+/// are listed at the end of the document, see `FootnotesBlock`. Markdown
+/// output keeps the footnote inline.
+#[derive(Debug, Clone)]
+pub(crate) struct FootnoteReference {
+    number: usize,
+    events: Vec<Event<'static>>,
+}
+
+impl CustomBlock for FootnoteReference {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => vec![Event::Html(
+                format!(
+                    r##"<sup class="footnote-reference" id="fnref-inline-{0}"><a href="#fn-inline-{0}">{0}</a></sup>"##,
+                    self.number
+                )
+                .into(),
+            )],
+            Format::Md => {
+                let mut events = vec![Event::Html("^[".into())];
+                events.extend(self.events.iter().cloned());
+                events.push(Event::Html("]".into()));
+                events
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// The inline footnotes of a document, listed at the end of html output.
+#[derive(Debug, Clone)]
+pub(crate) struct FootnotesBlock {
+    footnotes: Vec<Vec<Event<'static>>>,
+}
+
+impl CustomBlock for FootnotesBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        if format == Format::Md {
+            return Vec::new();
+        }
+        let mut output = String::from(r#"<section class="footnotes"><hr><ol>"#);
+        for (i, events) in self.footnotes.iter().enumerate() {
+            output += &format!(r#"<li id="fn-inline-{}">"#, i + 1);
+            output += &html::render(events.iter().cloned(), 0);
+            output += &format!(
+                r##" <a href="#fnref-inline-{}" class="footnote-backref">↩</a></li>"##,
+                i + 1
+            );
+        }
+        output += "</ol></section>\n";
+        vec![Event::Html(output.into())]
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+fn owned_str(s: &CowStr) -> CowStr<'static> {
+    s.to_string().into()
+}
+
+fn owned_tag(tag: &Tag) -> Option<Tag<'static>> {
+    Some(match tag {
+        Tag::Emphasis => Tag::Emphasis,
+        Tag::Strong => Tag::Strong,
+        Tag::Strikethrough => Tag::Strikethrough,
+        Tag::Link(kind, dest, title) => Tag::Link(*kind, owned_str(dest), owned_str(title)),
+        Tag::Image(kind, dest, title) => Tag::Image(*kind, owned_str(dest), owned_str(title)),
+        _ => return None,
+    })
+}
+
+/// An owned copy of an event that can be part of an inline footnote, or
+/// `None` for events that end it, like the end of the paragraph.
+fn owned_event(event: &Event) -> Option<Event<'static>> {
+    Some(match event {
+        Event::Text(text) => Event::Text(owned_str(text)),
+        Event::Code(code) => Event::Code(owned_str(code)),
+        Event::Html(html) => Event::Html(owned_str(html)),
+        Event::SoftBreak => Event::SoftBreak,
+        Event::HardBreak => Event::HardBreak,
+        Event::Start(tag) => Event::Start(owned_tag(tag)?),
+        Event::End(tag) => Event::End(owned_tag(tag)?),
+        _ => return None,
+    })
+}
+
+/// Reads inline footnotes from the events of a document. Unmatched
+/// brackets are separate text events, so a footnote starts with a text
+/// ending with `^` followed by a `[`, and ends at the matching `]`.
+#[derive(Default)]
+pub(crate) struct InlineFootnotes {
+    /// The last text ended with `^`, that was left out of it.
+    pub caret: bool,
+    /// Events of the footnote being read, and the depth of brackets in it.
+    current: Option<(Vec<Event<'static>>, usize)>,
+    footnotes: Vec<Vec<Event<'static>>>,
+}
+
+impl InlineFootnotes {
+    pub fn reading(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn start(&mut self) {
+        self.current = Some((Vec::new(), 0));
+    }
+
+    /// Read an event of the current footnote. Returns the reference when the
+    /// footnote ends, or, if the event can't be part of a footnote, the
+    /// events read so far, that turned out not to be one.
+    pub fn event(
+        &mut self,
+        event: &Event,
+    ) -> Result<Option<FootnoteReference>, Vec<Event<'static>>> {
+        let (events, depth) = self.current.as_mut().unwrap();
+        match event {
+            Event::Text(text) if text.as_ref() == "[" => *depth += 1,
+            Event::Text(text) if text.as_ref() == "]" => match depth.checked_sub(1) {
+                Some(d) => *depth = d,
+                None => {
+                    let (events, _) = self.current.take().unwrap();
+                    self.footnotes.push(events.clone());
+                    return Ok(Some(FootnoteReference {
+                        number: self.footnotes.len(),
+                        events,
+                    }));
+                }
+            },
+            _ => {}
+        }
+        match owned_event(event) {
+            Some(event) => {
+                events.push(event);
+                Ok(None)
+            }
+            None => Err(self.current.take().unwrap().0),
+        }
+    }
+
+    /// The list of footnotes, if there are any.
+    pub fn finish(self) -> Option<FootnotesBlock> {
+        (!self.footnotes.is_empty()).then_some(FootnotesBlock {
+            footnotes: self.footnotes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn inline_footnotes() {
+        let mut footnotes = InlineFootnotes::default();
+        footnotes.start();
+        for event in [Event::Text("a ".into()), Event::Text("[".into())] {
+            assert!(footnotes.event(&event).unwrap().is_none());
+        }
+        assert!(footnotes.event(&Event::Text("]".into())).unwrap().is_none());
+        let reference = footnotes.event(&Event::Text("]".into())).unwrap().unwrap();
+        assert_eq!(reference.number, 1);
+        assert_eq!(
+            html::render(reference.to_events(Format::Md).into_iter(), 0),
+            "^[a []]"
+        );
+
+        footnotes.start();
+        footnotes.event(&Event::Text("b".into())).unwrap();
+        let unread = footnotes.event(&Event::End(Tag::Paragraph)).unwrap_err();
+        assert_eq!(unread, vec![Event::Text("b".into())]);
+        assert!(!footnotes.reading());
+
+        let block = footnotes.finish().unwrap();
+        assert_eq!(
+            html::render(block.to_events(Format::Html).into_iter(), 0),
+            "<section class=\"footnotes\"><hr><ol><li id=\"fn-inline-1\">a [] <a href=\"#fnref-inline-1\" class=\"footnote-backref\">↩</a></li></ol></section>\n"
+        );
+    }
+
+    #[test]
+    fn inline_footnotes_in_document() {
+        let document = "Text^[A *footnote* with [a link](https://example.com).] and 2^3, [x]^[b].\n\nNot ^[closed\n";
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(
+            r##"<p>Text<sup class="footnote-reference" id="fnref-inline-1"><a href="#fn-inline-1">1</a></sup> and 2^3, [x]<sup class="footnote-reference" id="fnref-inline-2"><a href="#fn-inline-2">2</a></sup>.</p>"##
+        ));
+        assert!(html.contains("<p>Not ^[closed</p>"));
+        assert!(html.contains(
+            r##"<li id="fn-inline-1">A <em>footnote</em> with <a href="https://example.com">a link</a>. <a href="#fnref-inline-1" class="footnote-backref">↩</a></li><li id="fn-inline-2">b"##
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with("Text^[A *footnote* with [a link](https://example.com).] and"));
+        assert_eq!(md, render_markdown(&options, &md).1);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{interpolation::interpolate, CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use std::collections::BTreeMap;
+
+/// A block with a markdown body that is repeated once for each row of a
+/// `Data` block, with `{{row.field}}` replaced by the fields of the row.
+///
+/// ````text
+/// ```{t: ForEach, data: team}
+/// - **{{row.name}}**, {{row.role}}
+/// ```
+/// ````
+///
+/// Other `{{variables}}` are replaced like in interpolated text, and the
+/// rows are read like the rest of the document, so they can contain other
+/// blocks. When rendering to markdown the block is kept as it is.
+#[derive(Debug, Clone)]
+pub struct ForEachBlock {
+    header: CustomBlockHeader,
+    body: String,
+    /// The body repeated for each row, with the variables replaced.
+    output: String,
+    /// The output rendered to html.
+    html: String,
+}
+
+impl ForEachBlock {
+    /// Read the block, looking up the rows of the data set named by the
+    /// `data` field with `data`, and other variables with `variable`.
+    pub fn read(
+        header: &CustomBlockHeader,
+        body: &str,
+        data: impl Fn(&str) -> Option<Vec<BTreeMap<String, String>>>,
+        variable: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let name = header
+            .fields
+            .get("data")
+            .and_then(serde_yaml::Value::as_str)
+            .ok_or_else(|| "missing `data` in ForEach block".to_string())?;
+        let rows = data(name).ok_or_else(|| format!("data `{}` is not defined", name))?;
+        let output = rows
+            .iter()
+            .map(|row| {
+                let lookup = |path: &str| match path.strip_prefix("row.") {
+                    Some(field) => row.get(field).cloned(),
+                    None => variable(path),
+                };
+                match interpolate(body, false, lookup) {
+                    Some(text) => text.text(),
+                    None => body.to_string(),
+                }
+            })
+            .collect();
+        Ok(ForEachBlock {
+            header: header.clone(),
+            body: body.into(),
+            output,
+            html: String::new(),
+        })
+    }
+
+    /// Render the rows to html with `render`.
+    pub(crate) fn render(&mut self, render: impl FnOnce(&str) -> String) {
+        self.html = render(&self.output);
+    }
+}
+
+impl CustomBlock for ForEachBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => vec![Event::Html(self.html.as_str().into())],
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.body.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error, YamdrOptions};
+
+    #[test]
+    fn repeat_rows() {
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: ForEach, data: team}").unwrap();
+        let data = |name: &str| {
+            (name == "team").then(|| {
+                vec![
+                    [("name".to_string(), "Ada".to_string())].into(),
+                    [("name".to_string(), "Grace".to_string())].into(),
+                ]
+            })
+        };
+        let variable = |path: &str| (path == "team").then(|| "Core".to_string());
+        let mut block = ForEachBlock::read(
+            &header,
+            "- {{row.name}} ({{team}}){{row.x}}\n",
+            data,
+            variable,
+        )
+        .unwrap();
+        assert_eq!(block.output, "- Ada (Core)\n- Grace (Core)\n");
+        block.render(str::to_uppercase);
+        assert_eq!(
+            crate::html::render(block.to_events(Format::Html).into_iter(), 0),
+            "- ADA (CORE)\n- GRACE (CORE)\n"
+        );
+
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: ForEach, data: missing}").unwrap();
+        assert!(ForEachBlock::read(&header, "", data, variable).is_err());
+    }
+
+    #[test]
+    fn foreach_blocks() {
+        let document = r#"
+```{t: Data}
+name: team
+data:
+- name: Ada
+  role: Engineer
+- name: Grace
+  role: Admiral
+```
+
+```{t: ForEach, data: team}
+## {{row.name}}
+
+*{{row.role}}* `badge:{{row.name}}`
+```
+
+```{t: ForEach, data: missing}
+Missing.
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("<h2>Ada</h2>\n<p><em>Engineer</em> <span class=\"badge badge-gray\">Ada</span></p>\n<h2>Grace</h2>"));
+        assert!(!html.contains("Missing"));
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::CustomBlockRead(_)]
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("```{\"t\":\"ForEach\",\"data\":\"team\"}\n## {{row.name}}\n"));
+        assert_eq!(render_markdown(&options, &md).1, md);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{
+    interpolation::{interpolate, yaml_to_string},
+    CustomBlock, CustomBlockHeader, Format,
+};
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use std::collections::BTreeMap;
+
+/// A `Define` block, that registers its markdown body as a fragment called
+/// `name`, or a `Use` block, that renders a fragment defined earlier in the
+/// document.
+///
+/// ````text
+/// ```{t: Define, name: warning-footer}
+/// > **Warning:** {{what}} is not supported.
+/// ```
+///
+/// ```{t: Use, name: warning-footer, what: Windows}
+/// ```
+/// ````
+///
+/// The other fields of a `Use` block are parameters, replacing `{{name}}`s
+/// in the fragment, before other variables. The fragment is read like the
+/// rest of the document where it is used, so it can contain other blocks.
+/// `Define` blocks aren't rendered to html, and both are kept as they are
+/// when rendering to markdown.
+#[derive(Debug, Clone)]
+pub struct FragmentBlock {
+    header: CustomBlockHeader,
+    body: String,
+    /// The fragment with the parameters replaced, empty for `Define` blocks.
+    output: String,
+    /// The output rendered to html.
+    html: String,
+}
+
+impl FragmentBlock {
+    fn name(header: &CustomBlockHeader) -> Result<&str, String> {
+        header
+            .fields
+            .get("name")
+            .and_then(serde_yaml::Value::as_str)
+            .ok_or_else(|| format!("missing `name` in {} block", header.t))
+    }
+
+    /// Read a `Define` block, adding the fragment to `fragments`.
+    pub fn define(
+        header: &CustomBlockHeader,
+        body: &str,
+        fragments: &mut BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        fragments.insert(Self::name(header)?.into(), body.into());
+        Ok(FragmentBlock {
+            header: header.clone(),
+            body: body.into(),
+            output: String::new(),
+            html: String::new(),
+        })
+    }
+
+    /// Read a `Use` block, instantiating a fragment from `fragments` with the
+    /// parameters of the header, and other variables from `variable`.
+    pub fn use_fragment(
+        header: &CustomBlockHeader,
+        body: &str,
+        fragments: &BTreeMap<String, String>,
+        variable: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let name = Self::name(header)?;
+        let fragment = fragments
+            .get(name)
+            .ok_or_else(|| format!("fragment `{}` is not defined", name))?;
+        let lookup = |path: &str| match header.fields.get(path) {
+            Some(value) if path != "name" => Some(yaml_to_string(value)),
+            _ => variable(path),
+        };
+        let output = match interpolate(fragment, false, lookup) {
+            Some(text) => text.text(),
+            None => fragment.clone(),
+        };
+        Ok(FragmentBlock {
+            header: header.clone(),
+            body: body.into(),
+            output,
+            html: String::new(),
+        })
+    }
+
+    /// Render the fragment of a `Use` block to html with `render`.
+    pub(crate) fn render(&mut self, render: impl FnOnce(&str) -> String) {
+        if !self.output.is_empty() {
+            self.html = render(&self.output);
+        }
+    }
+}
+
+impl CustomBlock for FragmentBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html if self.html.is_empty() => Vec::new(),
+            Format::Html => vec![Event::Html(self.html.as_str().into())],
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.body.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error, YamdrOptions};
+
+    #[test]
+    fn define_and_use() {
+        let mut fragments = BTreeMap::new();
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Define, name: footer}").unwrap();
+        let block = FragmentBlock::define(&header, "*{{what}}* by {{author}}\n", &mut fragments);
+        assert!(block.unwrap().to_events(Format::Html).is_empty());
+
+        let variable = |path: &str| (path == "author").then(|| "Ada".to_string());
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Use, name: footer, what: Notes}").unwrap();
+        let block = FragmentBlock::use_fragment(&header, "", &fragments, variable).unwrap();
+        assert_eq!(block.output, "*Notes* by Ada\n");
+
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Use, name: missing}").unwrap();
+        assert!(FragmentBlock::use_fragment(&header, "", &fragments, variable).is_err());
+        let header = CustomBlockHeader::empty("Define".into());
+        assert!(FragmentBlock::define(&header, "", &mut fragments).is_err());
+    }
+
+    #[test]
+    fn fragments() {
+        let document = r#"
+```{t: Define, name: warning}
+> **Warning:** {{what}} is {{state}}.
+```
+
+```{t: Use, name: warning, what: Windows, state: unsupported}
+```
+
+```{t: Use, name: warning, what: Linux, state: experimental}
+```
+
+```{t: Use, name: missing}
+```
+
+````{t: Define, name: itself}
+`badge:loop`
+
+```{t: Use, name: itself}
+```
+````
+
+```{t: Use, name: itself}
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert_eq!(html.matches("<blockquote>").count(), 2);
+        assert!(html.contains("<strong>Warning:</strong> Windows is unsupported."));
+        assert!(html.contains("<strong>Warning:</strong> Linux is experimental."));
+        // Fragments are read like the rest of the document, but can't use
+        // themselves forever
+        assert!(html.contains(r#"<span class="badge badge-gray">loop</span>"#));
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::CustomBlockRead(_), Error::CustomBlockRead(nested)]
+                if nested == "block bodies are nested too deeply"
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("```{\"t\":\"Use\",\"name\":\"warning\",\"state\":\"unsupported\",\"what\":\"Windows\"}\n```\n"));
+        assert_eq!(render_markdown(&options, &md).1, md);
+    }
+}
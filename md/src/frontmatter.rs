@@ -0,0 +1,129 @@
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+
+/// A YAML frontmatter section at the start of a document, between `---`
+/// lines, as written by most static site generators.
+///
+/// ```text
+/// ---
+/// title: Quarterly report
+/// author: Kari Nordmann
+/// date: 2024-03-01
+/// ---
+/// ```
+///
+/// The section is closed by `---` or `...`. Its fields are read the same
+/// way as the fields of meta blocks, and are available to scripts as
+/// `meta`. When rendering to markdown it is written back as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Frontmatter<'a> {
+    /// The section as it is written, with the delimiters and the line
+    /// break after them.
+    pub source: &'a str,
+    pub fields: Mapping,
+}
+
+impl Frontmatter<'_> {
+    /// The fields with string keys, like the fields of a meta block.
+    pub fn string_fields(&self) -> BTreeMap<String, Value> {
+        string_fields(&self.fields).collect()
+    }
+}
+
+/// The fields of a yaml mapping with string keys.
+pub(crate) fn string_fields(fields: &Mapping) -> impl Iterator<Item = (String, Value)> + '_ {
+    fields
+        .iter()
+        .filter_map(|(key, value)| Some((key.as_str()?.to_string(), value.clone())))
+}
+
+/// The frontmatter of `markdown`, if it starts with one, and the rest of
+/// the document. A section that isn't a yaml mapping is left in the
+/// document, as it could be a thematic break.
+pub(crate) fn split(markdown: &str) -> (Option<Frontmatter<'_>>, &str) {
+    let Some(first_line) = markdown.split_inclusive('\n').next() else {
+        return (None, markdown);
+    };
+    if first_line.trim_end() != "---" {
+        return (None, markdown);
+    }
+    let mut end = first_line.len();
+    for line in markdown[first_line.len()..].split_inclusive('\n') {
+        let body = &markdown[first_line.len()..end];
+        end += line.len();
+        if !matches!(line.trim_end(), "---" | "...") {
+            continue;
+        }
+        let fields = match serde_yaml::from_str(body) {
+            Ok(Value::Mapping(fields)) => fields,
+            Ok(Value::Null) => Mapping::new(),
+            _ => return (None, markdown),
+        };
+        let frontmatter = Frontmatter {
+            source: &markdown[..end],
+            fields,
+        };
+        return (Some(frontmatter), &markdown[end..]);
+    }
+    (None, markdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, StandaloneOptions, YamdrOptions};
+
+    #[test]
+    fn split_frontmatter() {
+        let markdown = "---\ntitle: Report\ntags: [a, b]\n---\n# Report\n";
+        let (frontmatter, rest) = split(markdown);
+        let frontmatter = frontmatter.unwrap();
+        assert_eq!(
+            frontmatter.source,
+            "---\ntitle: Report\ntags: [a, b]\n---\n"
+        );
+        assert_eq!(frontmatter.fields["title"], Value::from("Report"));
+        assert_eq!(rest, "# Report\n");
+
+        let (frontmatter, rest) = split("---\r\n...");
+        assert_eq!(frontmatter.unwrap().fields, Mapping::new());
+        assert_eq!(rest, "");
+
+        // Thematic breaks and unclosed sections aren't frontmatter
+        for markdown in ["---\n\nText\n\n---\n", "---\ntitle: a\n", "# ---\n---\n"] {
+            assert_eq!(split(markdown), (None, markdown));
+        }
+    }
+
+    #[test]
+    fn frontmatter() {
+        let document = "---\ntitle: Quarterly report\nauthor: Kari\ndate: 2024-03-01  # draft\n---\n\n# Results\n\n`_meta.author + \" \" + meta.date_`\n";
+        let options = YamdrOptions {
+            standalone: Some(StandaloneOptions {
+                template: Some(
+                    "<title>{{title}}</title><p>{{meta.author}}, {{meta.date}}</p>{{content}}"
+                        .into(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert_eq!(meta.frontmatter["author"], serde_yaml::Value::from("Kari"));
+        assert!(html.starts_with("<title>Quarterly report</title><p>Kari, 2024-03-01</p>"));
+        assert!(html.contains("// &gt; Kari 2024-03-01</code>"));
+        assert!(!html.contains("<hr"));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with(
+            "---\ntitle: Quarterly report\nauthor: Kari\ndate: 2024-03-01  # draft\n---\n# Results"
+        ));
+        let (meta, _) = render_markdown(&options, "# Title\n");
+        assert!(meta.frontmatter.is_empty());
+    }
+}
@@ -1,12 +1,19 @@
-use crate::{CustomBlock, CustomBlockHeader, CustomBlockReader, Error, Format, Result};
+use crate::{
+    svg_size::SvgSize,
+    utils::{accessible_svg, data_fallback},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Result,
+};
 use layout::backends::svg::SVGWriter;
 use layout::gv;
+use layout::gv::parser::ast;
 use pulldown_cmark::{CodeBlockKind, Event, Tag};
 
 #[derive(Debug, Clone)]
 pub struct GraphBlock {
+    header: CustomBlockHeader,
     input: String,
-    output: String,
+    size: SvgSize,
 }
 
 pub struct GraphBlockReader {}
@@ -22,41 +29,121 @@ impl CustomBlockReader for GraphBlockReader {
         header.t == "Graph"
     }
 
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Graph"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("accessibility", FieldType::String),
+            HeaderField::new("scale", FieldType::Scalar),
+            HeaderField::new("max_width", FieldType::Scalar),
+            HeaderField::new("fit", FieldType::Bool),
+        ];
+        Some(SCHEMA)
+    }
+
     fn read_block(
         &mut self,
-        _header: &CustomBlockHeader,
+        header: &CustomBlockHeader,
         input: &str,
     ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let size = SvgSize::from_header(header).map_err(Error::CustomBlockRead)?;
+        // The graph is only parsed here, to report errors while reading,
+        // the layout is done when rendering, which may happen in parallel
         match gv::DotParser::new(input).process() {
-            Ok(g) => {
-                let mut gb = gv::GraphBuilder::new();
-                gb.visit_graph(&g);
-                let mut graph = gb.get();
-                let mut svg = SVGWriter::new();
-                graph.do_it(false, false, false, &mut svg);
-                let output = svg.finalize();
-                Ok(Some(Box::new(GraphBlock {
-                    input: input.into(),
-                    output,
-                })))
-            }
+            Ok(_) => Ok(Some(Box::new(GraphBlock {
+                header: header.clone(),
+                input: input.into(),
+                size,
+            }))),
             Err(err) => Err(Error::CustomBlockRead(err)),
         }
     }
 }
 
+impl GraphBlock {
+    /// A graph generated by another block, like the dependencies of a
+    /// `CargoDeps` block, sized and labelled by the header of that block.
+    pub(crate) fn generated(header: &CustomBlockHeader, dot: String) -> Result<Self> {
+        let size = SvgSize::from_header(header).map_err(Error::CustomBlockRead)?;
+        gv::DotParser::new(&dot)
+            .process()
+            .map_err(Error::CustomBlockRead)?;
+        Ok(GraphBlock {
+            header: header.clone(),
+            input: dot,
+            size,
+        })
+    }
+
+    fn to_svg(&self) -> String {
+        let g = gv::DotParser::new(&self.input)
+            .process()
+            .expect("graph is parsed when read");
+        let mut gb = gv::GraphBuilder::new();
+        gb.visit_graph(&g);
+        let mut graph = gb.get();
+        let mut svg = SVGWriter::new();
+        graph.do_it(false, false, false, &mut svg);
+        self.size.apply(&svg.finalize())
+    }
+
+    /// The edges of the graph as a table, see `utils::data_fallback`.
+    fn data_fallback(&self) -> Option<String> {
+        fn edges(graph: &ast::Graph, rows: &mut Vec<Vec<String>>) {
+            for stmt in graph.list.list.iter() {
+                match stmt {
+                    ast::Stmt::Edge(edge) => {
+                        let label = edge
+                            .list
+                            .iter()
+                            .find(|(name, _)| name == "label")
+                            .map(|(_, label)| label.clone())
+                            .unwrap_or_default();
+                        let mut from = &edge.from;
+                        for (to, _) in edge.to.iter() {
+                            rows.push(vec![from.name.clone(), to.name.clone(), label.clone()]);
+                            from = to;
+                        }
+                    }
+                    ast::Stmt::SubGraph(graph) => edges(graph, rows),
+                    _ => {}
+                }
+            }
+        }
+        data_fallback(&self.header, &["From", "To", "Label"], || {
+            let g = gv::DotParser::new(&self.input)
+                .process()
+                .expect("graph is parsed when read");
+            let mut rows = Vec::new();
+            edges(&g, &mut rows);
+            rows
+        })
+    }
+}
+
 impl CustomBlock for GraphBlock {
-    fn to_events(&self, format: Format) -> Vec<Event<'static>> {
+    fn is_expensive(&self) -> bool {
+        true
+    }
+
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
         match format {
             Format::Html => {
-                vec![Event::Html(self.output.clone().into())]
+                let mut html = accessible_svg(self.to_svg(), &self.header);
+                html += &self.data_fallback().unwrap_or_default();
+                vec![Event::Html(html.into())]
             }
             Format::Md => {
-                let props = r#"{"t": "Graph"}"#;
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
                 vec![
-                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.into()))),
-                    Event::Text(self.input.clone().into()),
-                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props.into()))),
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
                 ]
             }
         }
@@ -88,7 +175,7 @@ digraph D {
 ```
 
 "#,
-            r#"```{"t": "Graph"}
+            r#"```{"t":"Graph"}
 digraph D {
 
   A;
@@ -111,13 +198,26 @@ digraph D {
         )];
         let format = crate::Format::Md;
         for (document, expected) in documents {
-            let parsed_markdown = crate::parse_markdown(document);
+            let parsed_markdown = crate::parse_markdown(&Default::default(), document);
             let events = parsed_markdown
                 .iter()
                 .flat_map(|ee| format.transform_extended_event(ee));
-            let output = format.render(events);
+            let output = format.render(events, 0);
 
             assert_eq!(expected, output);
         }
     }
+
+    #[test]
+    fn identical_graphs_have_unique_ids() {
+        let graph = "```{t: Graph}\ndigraph { a -> b }\n```\n\n";
+        let (_, html) = crate::render_markdown(&Default::default(), &graph.repeat(2));
+        let ids: Vec<_> = html
+            .match_indices(r#" id="s"#)
+            .map(|(i, _)| html[i..].split('"').nth(1).unwrap())
+            .collect();
+        let unique: std::collections::BTreeSet<_> = ids.iter().collect();
+        assert_eq!(ids.len(), 6);
+        assert_eq!(unique.len(), 6);
+    }
 }
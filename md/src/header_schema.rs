@@ -0,0 +1,202 @@
+use crate::CustomBlockHeader;
+use serde_yaml::Value;
+
+/// Type of the value of a header field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    String,
+    /// A string or a number, like `width: 50%` or `width: 400`.
+    Scalar,
+    Integer,
+    Bool,
+    /// A list of strings, like `queries: [up, http_requests_total]`.
+    List,
+}
+
+impl FieldType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldType::String => value.is_string(),
+            FieldType::Scalar => value.is_string() || value.is_number(),
+            FieldType::Integer => value.is_u64(),
+            FieldType::Bool => value.is_bool(),
+            FieldType::List => value
+                .as_sequence()
+                .is_some_and(|values| values.iter().all(Value::is_string)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::String => "a string",
+            FieldType::Scalar => "a string or a number",
+            FieldType::Integer => "a positive integer",
+            FieldType::Bool => "true or false",
+            FieldType::List => "a list of strings",
+        }
+    }
+}
+
+/// A field a block type reads from its header, see
+/// `CustomBlockReader::header_schema`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaderField {
+    pub name: &'static str,
+    pub ty: FieldType,
+}
+
+impl HeaderField {
+    pub const fn new(name: &'static str, ty: FieldType) -> Self {
+        HeaderField { name, ty }
+    }
+}
+
+/// Fields every block can have.
+const COMMON_FIELDS: &[HeaderField] = &[
+    HeaderField::new("t", FieldType::String),
+    HeaderField::new("id", FieldType::String),
+];
+
+/// Number of single character edits between `a` and `b`.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + (ca != *cb) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Unknown and mistyped fields of `header`, compared with the fields of
+/// `schema`. Unknown fields come with the closest known field as a
+/// suggestion, if there is one that is close enough.
+pub(crate) fn check(header: &CustomBlockHeader, schema: &[HeaderField]) -> Vec<String> {
+    let fields = || COMMON_FIELDS.iter().chain(schema);
+    let mut problems = Vec::new();
+    for (name, value) in header.fields.iter() {
+        match fields().find(|field| field.name == name) {
+            Some(field) if !field.ty.matches(value) => problems.push(format!(
+                "`{}` of {} block should be {}",
+                name,
+                header.t,
+                field.ty.name()
+            )),
+            Some(_) => {}
+            None => {
+                let suggestion = fields()
+                    .map(|field| (edit_distance(name, field.name), field.name))
+                    .filter(|(distance, field)| *distance <= 2.min(field.len() / 2))
+                    .min();
+                problems.push(match suggestion {
+                    Some((_, field)) => format!(
+                        "unknown field `{}` in {} block, did you mean `{}`?",
+                        name, header.t, field
+                    ),
+                    None => format!("unknown field `{}` in {} block", name, header.t),
+                });
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, YamdrOptions};
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("number", "numbers"), 1);
+        assert_eq!(edit_distance("lable", "label"), 2);
+        assert_eq!(edit_distance("", "id"), 2);
+    }
+
+    #[test]
+    fn check_header() {
+        let schema = [
+            HeaderField::new("numbers", FieldType::Bool),
+            HeaderField::new("width", FieldType::Scalar),
+        ];
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Code, id: a, number: true, width: 40, color: red}").unwrap();
+        assert_eq!(
+            check(&header, &schema),
+            vec![
+                "unknown field `color` in Code block",
+                "unknown field `number` in Code block, did you mean `numbers`?",
+            ]
+        );
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Code, numbers: yes, width: 50%}").unwrap();
+        assert_eq!(
+            check(&header, &schema),
+            vec!["`numbers` of Code block should be true or false"]
+        );
+    }
+
+    #[test]
+    fn header_schema() {
+        let document = r#"
+```{t: Code, number: true, language: rust}
+fn main() {}
+```
+
+```{t: Sql, db: data.sqlite, limit: all}
+SELECT 1
+```
+
+```{t: Script, hidden: true, id: setup}
+let x = 1;
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("fn"));
+        let errors: Vec<String> = meta.errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            errors[..2],
+            [
+                "invalid block header: unknown field `number` in Code block, did you mean `numbers`?",
+                "invalid block header: `limit` of Sql block should be a positive integer",
+            ]
+        );
+        // The Sql block itself fails, since the database isn't allowed
+        assert_eq!(errors.len(), 3);
+
+        let document = r#"
+```{t: If, cond: "true", cnod: "false"}
+Shown
+```
+
+```{t: Grpah}
+digraph { a -> b }
+```
+
+```{t: Emit, nmae: x}
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("Shown"));
+        let errors: Vec<String> = meta.errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "invalid block header: unknown field `cnod` in If block, did you mean `cond`?",
+                "custom block type `Grpah` is not implemented, did you mean `Graph`?",
+                "invalid block header: unknown field `nmae` in Emit block, did you mean `name`?",
+                "error while reading custom block: `missing `name` in Emit block`",
+            ]
+        );
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("```{\"t\":\"Grpah\"}\ndigraph { a -> b }\n```"));
+    }
+}
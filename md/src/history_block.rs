@@ -0,0 +1,252 @@
+use crate::{
+    utils::build_table, CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType,
+    Format, HeaderField, Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// A change of a document, listed in a History block.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct HistoryEntry {
+    date: String,
+    author: String,
+    subject: String,
+}
+
+/// A table of the recent changes of the document. When the `git` feature is
+/// enabled and the document is in a git checkout, see `YamdrOptions::path`,
+/// the changes are read from the commits changing the document. Otherwise
+/// they are read from the body of the block:
+///
+/// ````text
+/// ```{t: History, limit: 5}
+/// - date: 2024-03-01
+///   author: Ada
+///   subject: First draft
+/// ```
+/// ````
+#[derive(Debug, Clone)]
+pub struct HistoryBlock {
+    header: CustomBlockHeader,
+    input: String,
+    entries: Vec<HistoryEntry>,
+    labels: Labels,
+}
+
+pub struct HistoryBlockReader {
+    path: Option<PathBuf>,
+    labels: Labels,
+}
+
+impl HistoryBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        HistoryBlockReader {
+            path: options.path.clone(),
+            labels: options.locale.labels.clone(),
+        }
+    }
+}
+
+impl CustomBlockReader for HistoryBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "History"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["History"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[HeaderField::new("limit", FieldType::Integer)];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let limit = header
+            .fields
+            .get("limit")
+            .and_then(serde_yaml::Value::as_u64)
+            .unwrap_or(10) as usize;
+        let entries = match self
+            .path
+            .as_deref()
+            .and_then(|path| git_history(path, limit))
+        {
+            Some(entries) => entries,
+            None if input.trim().is_empty() => Vec::new(),
+            None => serde_yaml::from_str::<Vec<HistoryEntry>>(input)
+                .map_err(|err| Error::CustomBlockRead(format!("failed to parse block: {}", err)))?
+                .into_iter()
+                .take(limit)
+                .collect(),
+        };
+        Ok(Some(Box::new(HistoryBlock {
+            header: header.clone(),
+            input: input.into(),
+            entries,
+            labels: self.labels.clone(),
+        })))
+    }
+}
+
+impl CustomBlock for HistoryBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let head = self
+                    .labels
+                    .history
+                    .iter()
+                    .map(|label| label.as_str().into());
+                let rows = self
+                    .entries
+                    .iter()
+                    .map(|entry| {
+                        [&entry.date, &entry.author, &entry.subject]
+                            .map(|cell| CowStr::from(cell.as_str()))
+                            .into()
+                    })
+                    .collect();
+                build_table(head.collect(), rows)
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// The last `limit` commits changing the file at `path`, or `None` if it
+/// isn't in a git checkout.
+#[cfg(feature = "git")]
+fn git_history(path: &Path, limit: usize) -> Option<Vec<HistoryEntry>> {
+    let path = path.canonicalize().ok()?;
+    let repo = git2::Repository::discover(path.parent()?).ok()?;
+    let path = path
+        .strip_prefix(repo.workdir()?.canonicalize().ok()?)
+        .ok()?;
+    let mut revwalk = repo.revwalk().ok()?;
+    revwalk.push_head().ok()?;
+    revwalk.set_sorting(git2::Sort::TIME).ok()?;
+    let blob = |commit: &git2::Commit| {
+        let tree = commit.tree().ok()?;
+        tree.get_path(path).ok().map(|entry| entry.id())
+    };
+    let mut entries = Vec::new();
+    for oid in revwalk {
+        if entries.len() == limit {
+            break;
+        }
+        let commit = repo.find_commit(oid.ok()?).ok()?;
+        let current = blob(&commit);
+        let changed = match commit.parent_count() {
+            0 => current.is_some(),
+            _ => commit.parents().all(|parent| blob(&parent) != current),
+        };
+        if !changed {
+            continue;
+        }
+        entries.push(HistoryEntry {
+            date: crate::utils::date(commit.time().seconds()),
+            author: commit.author().name().unwrap_or_default().into(),
+            subject: commit.summary().unwrap_or_default().into(),
+        });
+    }
+    Some(entries)
+}
+
+#[cfg(not(feature = "git"))]
+fn git_history(_path: &Path, _limit: usize) -> Option<Vec<HistoryEntry>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_markdown;
+
+    #[test]
+    fn dates() {
+        use crate::utils::date;
+
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_709_251_199), "2024-02-29");
+    }
+
+    #[test]
+    fn manual_entries() {
+        let input = r#"
+- date: 2024-03-02
+  author: Grace
+  subject: Add <results>
+- date: 2024-03-01
+  author: Ada
+  subject: First draft
+"#;
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: History, limit: 1}").unwrap();
+        let mut reader = HistoryBlockReader::new(&Default::default());
+        let block = reader.read_block(&header, input).unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains("<th>Date</th><th>Author</th><th>Change</th>"));
+        assert!(html.contains("<td>2024-03-02</td><td>Grace</td><td>Add &lt;results&gt;</td>"));
+        assert!(!html.contains("Ada"));
+
+        assert!(reader.read_block(&header, "not: [entries").is_err());
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn git_entries() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib.rs");
+        // The crate isn't always built from a git checkout
+        if let Some(entries) = git_history(&path, 2) {
+            assert!(entries.len() <= 2);
+            assert!(entries.iter().all(|entry| entry.date.len() == 10));
+        }
+    }
+
+    #[test]
+    fn history() {
+        let document = r#"
+```{t: History}
+- date: 2024-03-01
+  author: Ada
+  subject: First draft
+```
+
+```{t: History}
+```
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("<td>2024-03-01</td><td>Ada</td><td>First draft</td>"));
+        assert_eq!(html.matches("<table>").count(), 2);
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("```{\"t\":\"History\"}\n```\n"));
+        assert_eq!(render_markdown(&options, &md).1, md);
+    }
+}
@@ -1,7 +1,7 @@
 use pulldown_cmark::{html, Event};
 
-pub fn render<'a>(events: impl Iterator<Item = Event<'a>>) -> String {
-    let mut html_output = String::new();
+pub fn render<'a>(events: impl Iterator<Item = Event<'a>>, capacity: usize) -> String {
+    let mut html_output = String::with_capacity(capacity);
     html::push_html(&mut html_output, events);
     html_output
 }
@@ -0,0 +1,166 @@
+use crate::{
+    utils::{css_length, header_field},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Result,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+
+/// Raw html, like an embedded widget, rendered in a sandboxed iframe, so
+/// its styles and scripts can't reach the rest of the page.
+///
+/// ````text
+/// ```{t: Html, height: 300, title: Interactive map}
+/// <div id="map"></div>
+/// <script src="https://example.com/map.js"></script>
+/// ```
+/// ````
+///
+/// Scripts in the iframe are allowed, unless `scripts: false`, but the
+/// iframe is never given the origin of the page, so they can't read its
+/// cookies or its content. The iframe is 100% wide and 150px high by
+/// default.
+#[derive(Debug, Clone)]
+pub struct HtmlBlock {
+    header: CustomBlockHeader,
+    input: String,
+    width: String,
+    height: String,
+    title: Option<String>,
+    scripts: bool,
+}
+
+pub struct HtmlBlockReader {}
+
+impl HtmlBlockReader {
+    pub fn initial_state() -> Self {
+        HtmlBlockReader {}
+    }
+}
+
+impl CustomBlockReader for HtmlBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Html"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Html"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("width", FieldType::Scalar),
+            HeaderField::new("height", FieldType::Scalar),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("scripts", FieldType::Bool),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let length = |name: &str, default: &str| {
+            let length = header_field(header, name).unwrap_or_else(|| default.into());
+            css_length(&length).ok_or_else(|| {
+                Error::CustomBlockRead(format!("invalid {} `{}` in Html block", name, length))
+            })
+        };
+        Ok(Some(Box::new(HtmlBlock {
+            header: header.clone(),
+            input: input.into(),
+            width: length("width", "100%")?,
+            height: length("height", "150")?,
+            title: header_field(header, "title"),
+            scripts: header
+                .fields
+                .get("scripts")
+                .and_then(serde_yaml::Value::as_bool)
+                .unwrap_or(true),
+        })))
+    }
+}
+
+impl CustomBlock for HtmlBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut html = format!(
+                    r#"<iframe class="html-block" sandbox="{}" loading="lazy" style="width: {}; height: {}""#,
+                    if self.scripts { "allow-scripts" } else { "" },
+                    self.width,
+                    self.height,
+                );
+                if let Some(title) = &self.title {
+                    html += r#" title=""#;
+                    escape_html(&mut html, title).unwrap();
+                    html += "\"";
+                }
+                html += r#" srcdoc=""#;
+                escape_html(&mut html, &self.input).unwrap();
+                html += "\"></iframe>\n";
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{custom_block, render_html};
+    use crate::{render_markdown, YamdrOptions};
+
+    fn render(header: &str, input: &str) -> Result<String> {
+        render_html(&Default::default(), &custom_block(header, input))
+    }
+
+    #[test]
+    fn sandboxed() {
+        let html = render(
+            "{t: Html, height: 300, title: A \"map\"}",
+            "<p class=\"x\">a & b</p>\n",
+        )
+        .unwrap();
+        assert_eq!(
+            html,
+            "<iframe class=\"html-block\" sandbox=\"allow-scripts\" loading=\"lazy\" style=\"width: 100%; height: 300px\" title=\"A &quot;map&quot;\" srcdoc=\"&lt;p class=&quot;x&quot;&gt;a &amp; b&lt;/p&gt;\n\"></iframe>\n"
+        );
+        let html = render("{t: Html, scripts: false, width: 50%}", "<script></script>").unwrap();
+        assert!(html.contains(r#"sandbox="" loading="lazy" style="width: 50%; height: 150px""#));
+        assert!(render("{t: Html, height: \"1px; color: red\"}", "<p></p>").is_err());
+    }
+
+    #[test]
+    fn html_blocks() {
+        let document = "```{t: Html, height: 200}\n<b>widget</b>\n```\n";
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty());
+        assert!(html.contains(r#"srcdoc="&lt;b&gt;widget&lt;/b&gt;"#));
+        assert!(!html.contains("<b>widget</b>"));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert_eq!(
+            md,
+            "```{\"t\":\"Html\",\"height\":200}\n<b>widget</b>\n```\n\n"
+        );
+    }
+}
@@ -0,0 +1,622 @@
+use crate::CustomBlockHeader;
+use serde::{Deserialize, Serialize};
+
+/// Strings that yamdr generates itself, and that should follow the
+/// language of the document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Labels {
+    /// Heading of the index column of Data tables.
+    pub index_column: String,
+    /// Prefix of figure captions, followed by the figure number.
+    pub figure: String,
+    /// Summary of collapsed blocks that are hidden without a title.
+    pub details: String,
+    /// Estimated reading time, where `{}` is replaced with the minutes.
+    pub reading_time: String,
+    /// Notice shown where a document was cut because it is too large.
+    pub truncated: String,
+    /// Notice shown instead of a graph or chart that is too large.
+    pub too_large: String,
+    /// Headings of the date, author and change columns of History blocks.
+    pub history: [String; 3],
+    /// Description of star ratings, where the `{}`s are replaced with the
+    /// rating and the number of stars.
+    pub rating: String,
+    /// Headings of the method, path, description, parameters and responses
+    /// columns of OpenApi blocks.
+    pub api: [String; 5],
+    /// Headings of the property, type, required and description columns of
+    /// Schema blocks.
+    pub schema: [String; 4],
+    /// Headings of the date, description, account, amount and balance
+    /// columns of Ledger blocks.
+    pub ledger: [String; 5],
+    /// Notice shown instead of a Redact block for another audience.
+    pub redacted: String,
+    /// Prompt, button and error of the passphrase form of encrypted
+    /// documents.
+    pub encrypted: [String; 3],
+    /// Toggle showing every row of a table with `max_rows`, where `{}` is
+    /// replaced with the number of rows.
+    pub show_all: String,
+    /// Placeholder of the filter field of interactive tables.
+    pub filter_rows: String,
+    /// Name of the page links of tables with `page_size`, and the buttons
+    /// to the previous and next page.
+    pub pages: [String; 3],
+    /// Headings of the field, count, mean, median, standard deviation,
+    /// min and max columns of Summary blocks.
+    pub summary: [String; 7],
+    /// Headings of the file, lines, covered lines and coverage columns of
+    /// Coverage blocks, and the name of the row with the total.
+    pub coverage: [String; 5],
+    /// Summary of TestReport blocks, where the `{}`s are replaced with the
+    /// number of tests, failed tests and skipped tests, and the heading of
+    /// the list of failing tests.
+    pub test_report: [String; 2],
+    /// Headings of the metric, labels and value columns of Metrics blocks.
+    pub metrics: [String; 3],
+    /// Headings of the crate, version and license columns of CargoDeps
+    /// tables.
+    pub cargo_deps: [String; 3],
+    /// Heading of the packages without a known license in Licenses blocks.
+    pub unknown_license: String,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Labels::for_lang("en")
+    }
+}
+
+impl Labels {
+    /// Built in labels for a language, falling back to english for
+    /// unknown languages.
+    pub fn for_lang(lang: &str) -> Self {
+        let (
+            index_column,
+            figure,
+            details,
+            reading_time,
+            truncated,
+            too_large,
+            history,
+            rating,
+            api,
+            schema,
+            ledger,
+            redacted,
+            encrypted,
+            show_all,
+            filter_rows,
+            pages,
+            summary,
+            coverage,
+            test_report,
+            metrics,
+            cargo_deps,
+            unknown_license,
+        ) = match lang.split(['-', '_']).next() {
+            Some("nb" | "nn" | "no") => (
+                "Nr.",
+                "Figur",
+                "Detaljer",
+                "{} min lesetid",
+                "Dokumentet er for stort, og er avkortet her.",
+                "Figuren er for stor til å vises.",
+                ["Dato", "Forfatter", "Endring"],
+                "{} av {} stjerner",
+                ["Metode", "Sti", "Beskrivelse", "Parametere", "Svar"],
+                ["Egenskap", "Type", "Påkrevd", "Beskrivelse"],
+                ["Dato", "Beskrivelse", "Konto", "Beløp", "Saldo"],
+                "Denne delen er ikke tilgjengelig.",
+                [
+                    "Skriv inn passordet for å lese dokumentet.",
+                    "Åpne",
+                    "Feil passord.",
+                ],
+                "Vis alle {} rader",
+                "Filtrer rader",
+                ["Sider", "Forrige", "Neste"],
+                [
+                    "Felt",
+                    "Antall",
+                    "Gjennomsnitt",
+                    "Median",
+                    "Standardavvik",
+                    "Min",
+                    "Maks",
+                ],
+                ["Fil", "Linjer", "Dekket", "Dekning", "Totalt"],
+                ["{} tester, {} feilet, {} hoppet over", "Feilende tester"],
+                ["Metrikk", "Etiketter", "Verdi"],
+                ["Pakke", "Versjon", "Lisens"],
+                "Ukjent lisens",
+            ),
+            Some("de") => (
+                "Nr.",
+                "Abbildung",
+                "Details",
+                "{} Min. Lesezeit",
+                "Das Dokument ist zu groß und wurde hier gekürzt.",
+                "Die Abbildung ist zu groß, um angezeigt zu werden.",
+                ["Datum", "Autor", "Änderung"],
+                "{} von {} Sternen",
+                ["Methode", "Pfad", "Beschreibung", "Parameter", "Antworten"],
+                ["Eigenschaft", "Typ", "Erforderlich", "Beschreibung"],
+                ["Datum", "Beschreibung", "Konto", "Betrag", "Saldo"],
+                "Dieser Abschnitt ist nicht verfügbar.",
+                [
+                    "Geben Sie das Passwort ein, um das Dokument zu lesen.",
+                    "Öffnen",
+                    "Falsches Passwort.",
+                ],
+                "Alle {} Zeilen anzeigen",
+                "Zeilen filtern",
+                ["Seiten", "Zurück", "Weiter"],
+                [
+                    "Feld",
+                    "Anzahl",
+                    "Mittelwert",
+                    "Median",
+                    "Standardabweichung",
+                    "Min",
+                    "Max",
+                ],
+                ["Datei", "Zeilen", "Abgedeckt", "Abdeckung", "Gesamt"],
+                [
+                    "{} Tests, {} fehlgeschlagen, {} übersprungen",
+                    "Fehlgeschlagene Tests",
+                ],
+                ["Metrik", "Labels", "Wert"],
+                ["Crate", "Version", "Lizenz"],
+                "Unbekannte Lizenz",
+            ),
+            Some("fr") => (
+                "N°",
+                "Figure",
+                "Détails",
+                "{} min de lecture",
+                "Le document est trop volumineux et a été tronqué ici.",
+                "La figure est trop volumineuse pour être affichée.",
+                ["Date", "Auteur", "Modification"],
+                "{} sur {} étoiles",
+                ["Méthode", "Chemin", "Description", "Paramètres", "Réponses"],
+                ["Propriété", "Type", "Requis", "Description"],
+                ["Date", "Description", "Compte", "Montant", "Solde"],
+                "Cette section n'est pas disponible.",
+                [
+                    "Saisissez le mot de passe pour lire ce document.",
+                    "Ouvrir",
+                    "Mot de passe incorrect.",
+                ],
+                "Afficher les {} lignes",
+                "Filtrer les lignes",
+                ["Pages", "Précédent", "Suivant"],
+                [
+                    "Champ",
+                    "Nombre",
+                    "Moyenne",
+                    "Médiane",
+                    "Écart type",
+                    "Min",
+                    "Max",
+                ],
+                ["Fichier", "Lignes", "Couvertes", "Couverture", "Total"],
+                ["{} tests, {} en échec, {} ignorés", "Tests en échec"],
+                ["Métrique", "Étiquettes", "Valeur"],
+                ["Crate", "Version", "Licence"],
+                "Licence inconnue",
+            ),
+            Some("es") => (
+                "N.º",
+                "Figura",
+                "Detalles",
+                "{} min de lectura",
+                "El documento es demasiado grande y se ha truncado aquí.",
+                "La figura es demasiado grande para mostrarse.",
+                ["Fecha", "Autor", "Cambio"],
+                "{} de {} estrellas",
+                ["Método", "Ruta", "Descripción", "Parámetros", "Respuestas"],
+                ["Propiedad", "Tipo", "Obligatorio", "Descripción"],
+                ["Fecha", "Descripción", "Cuenta", "Importe", "Saldo"],
+                "Esta sección no está disponible.",
+                [
+                    "Introduce la contraseña para leer este documento.",
+                    "Abrir",
+                    "Contraseña incorrecta.",
+                ],
+                "Mostrar las {} filas",
+                "Filtrar filas",
+                ["Páginas", "Anterior", "Siguiente"],
+                [
+                    "Campo",
+                    "Recuento",
+                    "Media",
+                    "Mediana",
+                    "Desviación típica",
+                    "Mín",
+                    "Máx",
+                ],
+                ["Archivo", "Líneas", "Cubiertas", "Cobertura", "Total"],
+                ["{} pruebas, {} fallidas, {} omitidas", "Pruebas fallidas"],
+                ["Métrica", "Etiquetas", "Valor"],
+                ["Crate", "Versión", "Licencia"],
+                "Licencia desconocida",
+            ),
+            Some("ar") => (
+                "رقم",
+                "شكل",
+                "تفاصيل",
+                "{} دقيقة قراءة",
+                "المستند كبير جدًا، وتم اقتطاعه هنا.",
+                "الشكل كبير جدًا بحيث لا يمكن عرضه.",
+                ["التاريخ", "المؤلف", "التغيير"],
+                "{} من {} نجوم",
+                ["الطريقة", "المسار", "الوصف", "المعاملات", "الاستجابات"],
+                ["الخاصية", "النوع", "مطلوب", "الوصف"],
+                ["التاريخ", "الوصف", "الحساب", "المبلغ", "الرصيد"],
+                "هذا القسم غير متاح.",
+                [
+                    "أدخل كلمة المرور لقراءة هذا المستند.",
+                    "فتح",
+                    "كلمة المرور غير صحيحة.",
+                ],
+                "عرض كل الصفوف ({})",
+                "تصفية الصفوف",
+                ["الصفحات", "السابق", "التالي"],
+                [
+                    "الحقل",
+                    "العدد",
+                    "المتوسط",
+                    "الوسيط",
+                    "الانحراف المعياري",
+                    "الأدنى",
+                    "الأقصى",
+                ],
+                ["الملف", "الأسطر", "المغطاة", "التغطية", "الإجمالي"],
+                ["{} اختبارات، {} فشلت، {} تم تخطيها", "الاختبارات الفاشلة"],
+                ["المقياس", "التسميات", "القيمة"],
+                ["الحزمة", "الإصدار", "الترخيص"],
+                "ترخيص غير معروف",
+            ),
+            Some("he") => (
+                "מס׳",
+                "איור",
+                "פרטים",
+                "{} דקות קריאה",
+                "המסמך גדול מדי, והוא קוצר כאן.",
+                "האיור גדול מדי להצגה.",
+                ["תאריך", "מחבר", "שינוי"],
+                "{} מתוך {} כוכבים",
+                ["שיטה", "נתיב", "תיאור", "פרמטרים", "תגובות"],
+                ["מאפיין", "סוג", "חובה", "תיאור"],
+                ["תאריך", "תיאור", "חשבון", "סכום", "יתרה"],
+                "חלק זה אינו זמין.",
+                [
+                    "הזינו את הסיסמה כדי לקרוא את המסמך.",
+                    "פתיחה",
+                    "סיסמה שגויה.",
+                ],
+                "הצגת כל {} השורות",
+                "סינון שורות",
+                ["עמודים", "הקודם", "הבא"],
+                [
+                    "שדה",
+                    "כמות",
+                    "ממוצע",
+                    "חציון",
+                    "סטיית תקן",
+                    "מינימום",
+                    "מקסימום",
+                ],
+                ["קובץ", "שורות", "מכוסות", "כיסוי", "סה״כ"],
+                ["{} בדיקות, {} נכשלו, {} דולגו", "בדיקות שנכשלו"],
+                ["מדד", "תוויות", "ערך"],
+                ["חבילה", "גרסה", "רישיון"],
+                "רישיון לא ידוע",
+            ),
+            _ => (
+                "#",
+                "Figure",
+                "Details",
+                "{} min read",
+                "The document is too large, and was truncated here.",
+                "The figure is too large to be shown.",
+                ["Date", "Author", "Change"],
+                "{} out of {} stars",
+                ["Method", "Path", "Description", "Parameters", "Responses"],
+                ["Property", "Type", "Required", "Description"],
+                ["Date", "Description", "Account", "Amount", "Balance"],
+                "This section is not available.",
+                [
+                    "Enter the passphrase to read this document.",
+                    "Open",
+                    "Wrong passphrase.",
+                ],
+                "Show all {} rows",
+                "Filter rows",
+                ["Pages", "Previous", "Next"],
+                [
+                    "Field",
+                    "Count",
+                    "Mean",
+                    "Median",
+                    "Std. dev.",
+                    "Min",
+                    "Max",
+                ],
+                ["File", "Lines", "Covered", "Coverage", "Total"],
+                ["{} tests, {} failed, {} skipped", "Failing tests"],
+                ["Metric", "Labels", "Value"],
+                ["Crate", "Version", "License"],
+                "Unknown license",
+            ),
+        };
+        Labels {
+            index_column: index_column.into(),
+            figure: figure.into(),
+            details: details.into(),
+            reading_time: reading_time.into(),
+            truncated: truncated.into(),
+            too_large: too_large.into(),
+            history: history.map(String::from),
+            rating: rating.into(),
+            api: api.map(String::from),
+            schema: schema.map(String::from),
+            ledger: ledger.map(String::from),
+            redacted: redacted.into(),
+            encrypted: encrypted.map(String::from),
+            show_all: show_all.into(),
+            filter_rows: filter_rows.into(),
+            pages: pages.map(String::from),
+            summary: summary.map(String::from),
+            coverage: coverage.map(String::from),
+            test_report: test_report.map(String::from),
+            metrics: metrics.map(String::from),
+            cargo_deps: cargo_deps.map(String::from),
+            unknown_license: unknown_license.into(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateOrder {
+    Ymd,
+    Dmy,
+    Mdy,
+}
+
+/// How numbers and dates are written in a locale, like `1.234,5` and
+/// `31.12.2024` in german. Used for the cells of Data and DynamicTable
+/// blocks, and the axis labels of charts.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NumberFormat {
+    pub decimal: char,
+    /// Separator between groups of thousands. Only numbers with five or
+    /// more digits before the decimal separator are grouped, so years are
+    /// written as they are.
+    pub thousands: char,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+}
+
+impl NumberFormat {
+    /// Number format of a locale like `de`, `en-GB` or `nb_NO`, falling
+    /// back to american english for unknown languages.
+    pub fn for_locale(locale: &str) -> Self {
+        let mut parts = locale.split(['-', '_']);
+        let lang = parts.next().unwrap_or_default().to_lowercase();
+        let region = parts.next().map(str::to_uppercase);
+        let (decimal, thousands, date_order, date_separator) =
+            match (lang.as_str(), region.as_deref()) {
+                ("en", Some("US") | None) => ('.', ',', DateOrder::Mdy, '/'),
+                ("en", _) => ('.', ',', DateOrder::Dmy, '/'),
+                ("de", _) => (',', '.', DateOrder::Dmy, '.'),
+                ("nb" | "nn" | "no", _) => (',', '\u{a0}', DateOrder::Dmy, '.'),
+                ("fr", _) => (',', '\u{202f}', DateOrder::Dmy, '/'),
+                ("es", _) => (',', '.', DateOrder::Dmy, '/'),
+                ("ar", _) => ('.', ',', DateOrder::Dmy, '/'),
+                ("he", _) => ('.', ',', DateOrder::Dmy, '.'),
+                _ => ('.', ',', DateOrder::Mdy, '/'),
+            };
+        NumberFormat {
+            decimal,
+            thousands,
+            date_order,
+            date_separator,
+        }
+    }
+
+    /// Number format of a block, from the `locale` field of its header,
+    /// or `default`.
+    pub fn for_header(header: &CustomBlockHeader, default: Option<Self>) -> Option<Self> {
+        match header
+            .fields
+            .get("locale")
+            .and_then(serde_yaml::Value::as_str)
+        {
+            Some(locale) => Some(NumberFormat::for_locale(locale)),
+            None => default,
+        }
+    }
+
+    pub fn number(&self, number: f64) -> String {
+        self.cell(&number.to_string())
+            .unwrap_or_else(|| number.to_string())
+    }
+
+    /// `cell` written in this format, if it is a number, like `-1234.5`,
+    /// or an ISO date, like `2024-12-31`.
+    pub fn cell(&self, cell: &str) -> Option<String> {
+        self.localize_number(cell)
+            .or_else(|| self.localize_date(cell))
+    }
+
+    fn localize_number(&self, cell: &str) -> Option<String> {
+        let (sign, unsigned) = match cell.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", cell),
+        };
+        let (integer, fraction) = match unsigned.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !digits(integer) || !fraction.is_none_or(digits) {
+            return None;
+        }
+        let mut output = sign.to_string();
+        for (i, c) in integer.chars().enumerate() {
+            if integer.len() > 4 && i > 0 && (integer.len() - i) % 3 == 0 {
+                output.push(self.thousands);
+            }
+            output.push(c);
+        }
+        if let Some(fraction) = fraction {
+            output.push(self.decimal);
+            output += fraction;
+        }
+        Some(output)
+    }
+
+    fn localize_date(&self, cell: &str) -> Option<String> {
+        let mut parts = cell.splitn(3, '-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        let valid = |s: &str, len: usize, max: u32| {
+            s.len() == len
+                && s.bytes().all(|b| b.is_ascii_digit())
+                && s.parse::<u32>().is_ok_and(|n| n <= max)
+        };
+        if !(valid(year, 4, 9999) && valid(month, 2, 12) && valid(day, 2, 31)) {
+            return None;
+        }
+        let parts = match self.date_order {
+            DateOrder::Ymd => [year, month, day],
+            DateOrder::Dmy => [day, month, year],
+            DateOrder::Mdy => [month, day, year],
+        };
+        Some(parts.join(&self.date_separator.to_string()))
+    }
+}
+
+/// Language, text direction and generated labels of a document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Locale {
+    pub lang: String,
+    pub dir: Direction,
+    pub labels: Labels,
+    /// How numbers and dates in tables and charts are written. They are
+    /// written as they are without it. Blocks can use another locale with
+    /// a `locale` field in their header, like `{t: Data, locale: de}`.
+    pub numbers: Option<NumberFormat>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::for_lang("en")
+    }
+}
+
+impl Locale {
+    /// Locale with the built in labels and the text direction of `lang`.
+    pub fn for_lang(lang: &str) -> Self {
+        let dir = match lang.split(['-', '_']).next() {
+            Some("ar" | "he" | "fa" | "ur") => Direction::Rtl,
+            _ => Direction::Ltr,
+        };
+        Locale {
+            lang: lang.into(),
+            dir,
+            labels: Labels::for_lang(lang),
+            numbers: None,
+        }
+    }
+
+    /// Apply the `lang` and `dir` fields of a meta block, if set.
+    pub fn with_document_options(
+        &self,
+        fields: &std::collections::BTreeMap<String, serde_yaml::Value>,
+    ) -> Self {
+        let mut locale = match fields.get("lang").and_then(serde_yaml::Value::as_str) {
+            Some(lang) if lang != self.lang => Locale {
+                numbers: self.numbers,
+                ..Locale::for_lang(lang)
+            },
+            _ => self.clone(),
+        };
+        if let Some(dir) = fields
+            .get("dir")
+            .and_then(|dir| serde_yaml::from_value(dir.clone()).ok())
+        {
+            locale.dir = dir;
+        }
+        locale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_formats() {
+        let de = NumberFormat::for_locale("de-DE");
+        assert_eq!(de.cell("-1234567.25").as_deref(), Some("-1.234.567,25"));
+        assert_eq!(de.cell("2024").as_deref(), Some("2024"));
+        assert_eq!(de.cell("2024-12-31").as_deref(), Some("31.12.2024"));
+        assert_eq!(de.cell("12 apples"), None);
+        assert_eq!(de.cell("2024-13-01"), None);
+        assert_eq!(de.number(0.5), "0,5");
+
+        let en = NumberFormat::for_locale("en");
+        assert_eq!(en.cell("12345").as_deref(), Some("12,345"));
+        assert_eq!(en.cell("2024-12-31").as_deref(), Some("12/31/2024"));
+        let gb = NumberFormat::for_locale("en_GB");
+        assert_eq!(gb.cell("2024-12-31").as_deref(), Some("31/12/2024"));
+        assert_eq!(
+            NumberFormat::for_locale("fr").cell("10000.5").as_deref(),
+            Some("10\u{202f}000,5")
+        );
+    }
+
+    #[test]
+    fn document_options() {
+        let fields = serde_yaml::from_str("{lang: nb, dir: rtl}").unwrap();
+        let locale = Locale::default().with_document_options(&fields);
+        assert_eq!(locale.lang, "nb");
+        assert_eq!(locale.dir, Direction::Rtl);
+        assert_eq!(locale.labels.figure, "Figur");
+        assert_eq!(locale.labels.index_column, "Nr.");
+
+        let locale = Locale::default().with_document_options(&Default::default());
+        assert_eq!(locale, Locale::default());
+    }
+
+    #[test]
+    fn rtl_languages() {
+        assert_eq!(Locale::for_lang("ar").dir, Direction::Rtl);
+        assert_eq!(Locale::for_lang("he-IL").dir, Direction::Rtl);
+        assert_eq!(Locale::for_lang("en-US").dir, Direction::Ltr);
+    }
+}
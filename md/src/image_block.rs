@@ -0,0 +1,197 @@
+use crate::{
+    utils::{css_length, header_field, local_files},
+    AssetUrls, CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format,
+    HeaderField, Result, YamdrOptions,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+
+/// An image in a `<figure>`, with a caption and size, loaded lazily. Plain
+/// markdown images are rendered as they are.
+///
+/// ````text
+/// ```{t: Image, src: chart.png, width: 50%, caption: Sales per month}
+/// ```
+/// ````
+#[derive(Debug, Clone)]
+pub struct ImageBlock {
+    header: CustomBlockHeader,
+    input: String,
+    src: String,
+    alt: String,
+    width: Option<String>,
+    caption: Option<String>,
+}
+
+pub struct ImageBlockReader {
+    assets: AssetUrls,
+}
+
+impl ImageBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        ImageBlockReader {
+            assets: AssetUrls::new(options),
+        }
+    }
+}
+
+impl CustomBlockReader for ImageBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Image"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Image"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("src", FieldType::String),
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("caption", FieldType::String),
+            HeaderField::new("width", FieldType::Scalar),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let src = header_field(header, "src")
+            .ok_or_else(|| Error::CustomBlockRead("missing `src` in Image block".into()))?;
+        let src = self.assets.rewrite(&src).unwrap_or(src);
+        let width = match header_field(header, "width") {
+            Some(width) => Some(css_length(&width).ok_or_else(|| {
+                Error::CustomBlockRead(format!("invalid width `{}` in Image block", width))
+            })?),
+            None => None,
+        };
+        let caption = header_field(header, "caption");
+        let alt = header_field(header, "alt")
+            .or_else(|| caption.clone())
+            .unwrap_or_default();
+        Ok(Some(Box::new(ImageBlock {
+            header: header.clone(),
+            input: input.into(),
+            src,
+            alt,
+            width,
+            caption,
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["src"])
+    }
+}
+
+impl CustomBlock for ImageBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let escaped = |text: &str| {
+                    let mut escaped = String::new();
+                    escape_html(&mut escaped, text).unwrap();
+                    escaped
+                };
+                let mut html = String::from(r#"<figure class="image""#);
+                if let Some(width) = &self.width {
+                    html += &format!(r#" style="width: {}""#, width);
+                }
+                html += &format!(
+                    r#"><img src="{}" alt="{}" loading="lazy" />"#,
+                    escaped(&self.src),
+                    escaped(&self.alt)
+                );
+                if let Some(caption) = &self.caption {
+                    html += &format!("<figcaption>{}</figcaption>", escaped(caption));
+                }
+                html += "</figure>\n";
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_markdown;
+    use crate::utils::{custom_block, render_html};
+
+    fn html(options: &YamdrOptions, header: &str) -> Result<String> {
+        render_html(options, &custom_block(header, ""))
+    }
+
+    #[test]
+    fn figure() {
+        let options = YamdrOptions::default();
+        assert_eq!(
+            html(
+                &options,
+                r#"{t: Image, src: a.png, width: 50%, caption: "Sales <2024>"}"#
+            )
+            .unwrap(),
+            r#"<figure class="image" style="width: 50%"><img src="a.png" alt="Sales &lt;2024&gt;" loading="lazy" /><figcaption>Sales &lt;2024&gt;</figcaption></figure>
+"#
+        );
+        assert!(html(&options, "{t: Image, src: a.png, width: 300}")
+            .unwrap()
+            .contains(r#"style="width: 300px""#));
+        assert!(html(
+            &options,
+            r#"{t: Image, src: a.png, width: "1px; color: red"}"#
+        )
+        .is_err());
+        assert!(html(&options, "{t: Image, width: 50%}").is_err());
+
+        let options = YamdrOptions {
+            asset_base: Some("/assets".into()),
+            ..Default::default()
+        };
+        assert!(html(&options, "{t: Image, src: a.png}")
+            .unwrap()
+            .contains(r#"src="/assets/a.png""#));
+    }
+
+    #[test]
+    fn image_blocks() {
+        let document = r#"
+```{t: Image, src: chart.png, width: 50%, caption: Sales}
+```
+
+![Plain](plain.png)
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(r#"<figure class="image" style="width: 50%"><img src="chart.png""#));
+        assert!(html.contains(r#"<img src="plain.png" alt="Plain" />"#));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with(
+            "```{\"t\":\"Image\",\"caption\":\"Sales\",\"src\":\"chart.png\",\"width\":\"50%\"}\n```\n"
+        ));
+        assert_eq!(render_markdown(&options, &md).1, md);
+    }
+}
@@ -0,0 +1,1523 @@
+use crate::{CustomBlockHeader, Error, Result};
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A fenced custom block, with a fence longer than any fence in `body`.
+fn fenced_block(header: &CustomBlockHeader, body: &str) -> String {
+    fenced(&serde_json::to_string(header).unwrap(), body)
+}
+
+/// A `Code` block, or a plain code block without a language.
+fn code_block(language: Option<&str>, code: &str) -> String {
+    match language.filter(|language| !language.is_empty()) {
+        Some(language) => fenced_block(&header("Code", &[("language", language)]), code),
+        None => fenced("", code),
+    }
+}
+
+fn fenced(info: &str, body: &str) -> String {
+    let longest = body
+        .lines()
+        .map(|line| line.trim_start().chars().take_while(|c| *c == '`').count())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    let mut block = format!("{}{}\n", fence, info);
+    block += body;
+    if !body.is_empty() && !body.ends_with('\n') {
+        block.push('\n');
+    }
+    block += &fence;
+    block.push('\n');
+    block
+}
+
+fn header(t: &str, fields: &[(&str, &str)]) -> CustomBlockHeader {
+    let mut header = CustomBlockHeader::empty(t.into());
+    for (name, value) in fields {
+        header.fields.insert(name.to_string(), (*value).into());
+    }
+    header
+}
+
+/// Text of a notebook, which is either a string or a list of lines.
+#[derive(Debug, Deserialize, Default)]
+#[serde(untagged)]
+enum Text {
+    #[default]
+    Missing,
+    String(String),
+    Lines(Vec<String>),
+}
+
+impl Text {
+    fn text(&self) -> String {
+        match self {
+            Text::Missing => String::new(),
+            Text::String(text) => text.clone(),
+            Text::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Notebook {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cell {
+    cell_type: String,
+    #[serde(default)]
+    source: Text,
+    #[serde(default)]
+    outputs: Vec<Output>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Output {
+    output_type: String,
+    #[serde(default)]
+    text: Text,
+    #[serde(default)]
+    data: serde_json::Map<String, Value>,
+    #[serde(default)]
+    ename: String,
+    #[serde(default)]
+    evalue: String,
+}
+
+/// Image types of outputs that are kept as images, in order of preference.
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif"];
+
+impl Output {
+    fn data(&self, mime: &str) -> Option<String> {
+        serde_json::from_value::<Text>(self.data.get(mime)?.clone())
+            .ok()
+            .map(|text| text.text())
+    }
+
+    /// Text of the output, to be written as `// >` comments below the code.
+    fn text(&self) -> Option<String> {
+        match self.output_type.as_str() {
+            "stream" => Some(self.text.text()),
+            "error" => Some(format!("{}: {}", self.ename, self.evalue)),
+            _ => self.data("text/plain"),
+        }
+    }
+}
+
+/// Convert a Jupyter notebook to a yamdr document. Markdown and raw cells
+/// are kept as they are, and code cells become `Code` blocks in the
+/// language of the notebook, with their text output as `// >` comments
+/// below the code. Images in the output become `Image` blocks, with the
+/// image in a data url, and html, like tables, becomes `Html` blocks.
+pub fn import_notebook(notebook: &str) -> Result<String> {
+    let notebook: Notebook = serde_json::from_str(notebook)
+        .map_err(|err| Error::Import(format!("invalid notebook: {}", err)))?;
+    let language = ["/language_info/name", "/kernelspec/language"]
+        .iter()
+        .find_map(|pointer| notebook.metadata.pointer(pointer)?.as_str())
+        .unwrap_or("python");
+    let mut blocks = Vec::new();
+    for cell in notebook.cells {
+        let source = cell.source.text();
+        if cell.cell_type != "code" {
+            if !source.trim().is_empty() {
+                blocks.push(format!("{}\n", source.trim_end()));
+            }
+            continue;
+        }
+        let mut code = source.trim_end().to_string();
+        let mut media = Vec::new();
+        for output in cell.outputs.iter() {
+            if let Some((mime, image)) = IMAGE_TYPES
+                .iter()
+                .find_map(|mime| Some((mime, output.data(mime)?)))
+            {
+                let src = format!("data:{};base64,{}", mime, image.replace('\n', ""));
+                media.push(fenced_block(&header("Image", &[("src", &src)]), ""));
+            } else if let Some(html) = output.data("text/html") {
+                media.push(fenced_block(&header("Html", &[]), &html));
+            } else if let Some(text) = output.text() {
+                for line in text.trim_end().lines() {
+                    code += &format!("\n// > {}", line);
+                }
+            }
+        }
+        if !code.is_empty() {
+            blocks.push(fenced_block(
+                &header("Code", &[("language", language)]),
+                &code,
+            ));
+        }
+        blocks.extend(media);
+    }
+    Ok(blocks.join("\n"))
+}
+
+/// Lines of markdown, written block by block.
+#[derive(Default)]
+struct Lines {
+    lines: Vec<String>,
+}
+
+impl Lines {
+    fn push(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+    }
+
+    /// End the current block, like a paragraph.
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    /// A block that can't continue a paragraph, like a table or fenced
+    /// code, separated from what comes before and after it.
+    fn block(&mut self, block: &str) {
+        self.blank();
+        self.lines
+            .extend(block.trim_end().lines().map(String::from));
+        self.lines.push(String::new());
+    }
+
+    fn finish(mut self) -> String {
+        while self.lines.last().is_some_and(|line| line.is_empty()) {
+            self.lines.pop();
+        }
+        let mut markdown = self.lines.join("\n");
+        markdown.push('\n');
+        markdown
+    }
+}
+
+/// `body` after a meta block with `meta`, like the title of the document.
+fn with_meta(meta: &BTreeMap<String, String>, body: String) -> String {
+    if meta.is_empty() {
+        return body;
+    }
+    let mut meta_header = header("External", &[]);
+    meta_header.fields.insert("meta".into(), true.into());
+    let fields = serde_yaml::to_string(meta).unwrap();
+    format!("{}\n{}", fenced_block(&meta_header, &fields), body)
+}
+
+/// A markdown table, with the first row as the head.
+fn table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let row = |cells: &[String]| {
+        let cells: Vec<&str> = (0..columns)
+            .map(|i| cells.get(i).map_or("", String::as_str))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut table = vec![row(&rows[0]), row(&vec!["---".to_string(); columns])];
+    table.extend(rows[1..].iter().map(|cells| row(cells)));
+    table.join("\n")
+}
+
+/// Lines with their common indentation removed.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut text = String::new();
+    for line in lines {
+        text += line.get(indent..).unwrap_or("").trim_end();
+        text.push('\n');
+    }
+    text.trim_matches('\n').to_string() + "\n"
+}
+
+/// Position of the closing `marker` of inline markup that starts at the
+/// beginning of `text`, which must not start or end with whitespace.
+fn closing_marker(text: &str, marker: char) -> Option<usize> {
+    let inner = text.strip_prefix(marker)?;
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    inner.match_indices(marker).find_map(|(i, _)| {
+        let before = inner[..i].chars().next_back()?;
+        let after = inner[i + 1..].chars().next();
+        let ends = after.is_none_or(|c| c.is_whitespace() || "-.,:;!?'\")]}".contains(c));
+        (!before.is_whitespace() && ends).then_some(i + 1)
+    })
+}
+
+/// Org links and markup in a line of text as markdown.
+fn org_inline(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut at_word_start = true;
+    while let Some(c) = rest.chars().next() {
+        if let Some(link) = rest.strip_prefix("[[") {
+            if let Some(end) = link.find("]]") {
+                let (url, description) = match link[..end].split_once("][") {
+                    Some((url, description)) => (url, Some(description)),
+                    None => (&link[..end], None),
+                };
+                let url = url.strip_prefix("file:").unwrap_or(url);
+                output += &match description {
+                    Some(description) => format!("[{}]({})", org_inline(description), url),
+                    None if url.contains("://") => format!("<{}>", url),
+                    None => format!("[{0}]({0})", url),
+                };
+                rest = &link[end + 2..];
+                at_word_start = false;
+                continue;
+            }
+        }
+        let markup = match c {
+            '*' => Some(("**", false)),
+            '/' => Some(("*", false)),
+            '+' => Some(("~~", false)),
+            '=' | '~' => Some(("`", true)),
+            _ => None,
+        };
+        if let (Some((md, verbatim)), true) = (markup, at_word_start) {
+            if let Some(end) = closing_marker(rest, c) {
+                let inner = &rest[1..end];
+                output += md;
+                output += &if verbatim {
+                    inner.into()
+                } else {
+                    org_inline(inner)
+                };
+                output += md;
+                rest = &rest[end + 1..];
+                at_word_start = false;
+                continue;
+            }
+        }
+        output.push(c);
+        at_word_start = c.is_whitespace() || "-({'\"".contains(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    output
+}
+
+/// The marker and text of a list item, like `- item` or `1) item`.
+fn list_item<'a>(line: &'a str, bullets: &str) -> Option<(String, &'a str)> {
+    let (marker, text) = line.split_once(' ')?;
+    if marker.len() == 1 && bullets.contains(marker) {
+        return Some(("-".into(), text));
+    }
+    let number = marker
+        .strip_suffix('.')
+        .or_else(|| marker.strip_suffix(')'))?;
+    match number {
+        "#" => Some(("1.".into(), text)),
+        number if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => {
+            Some((format!("{}.", number), text))
+        }
+        _ => None,
+    }
+}
+
+/// Convert a basic org-mode document to a yamdr document: headings, lists,
+/// tables, source and example blocks, quotes, links and markup. The title,
+/// author and date are put in a meta block, and other settings, comments
+/// and drawers are left out.
+pub fn import_org(org: &str) -> Result<String> {
+    let mut md = Lines::default();
+    let mut meta = BTreeMap::new();
+    let lines: Vec<&str> = org.lines().collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+        let upper = trimmed.to_uppercase();
+        i += 1;
+
+        if let Some(kind) = upper.strip_prefix("#+BEGIN_") {
+            let kind = kind
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let end = format!("#+END_{}", kind);
+            let start = i;
+            while i < lines.len() && !lines[i].trim().to_uppercase().starts_with(&end) {
+                i += 1;
+            }
+            // Lines starting with `*` or `#+` are escaped with a comma
+            let body: Vec<String> = lines[start..i.min(lines.len())]
+                .iter()
+                .map(|line| {
+                    let indent = line.len() - line.trim_start().len();
+                    match line[indent..].strip_prefix(',') {
+                        Some(escaped) if escaped.starts_with('*') || escaped.starts_with("#+") => {
+                            format!("{}{}", &line[..indent], escaped)
+                        }
+                        _ => line.to_string(),
+                    }
+                })
+                .collect();
+            i += 1;
+            let body = dedent(&body.iter().map(String::as_str).collect::<Vec<_>>());
+            match kind.as_str() {
+                "SRC" => {
+                    let language = trimmed.split_whitespace().nth(1);
+                    md.block(&code_block(language, &body));
+                }
+                "EXAMPLE" => md.block(&code_block(None, &body)),
+                "QUOTE" => {
+                    md.blank();
+                    for line in body.lines() {
+                        md.push(format!("> {}", org_inline(line)).trim_end().to_string());
+                    }
+                    md.blank();
+                }
+                _ => {
+                    md.blank();
+                    body.lines().for_each(|line| md.push(org_inline(line)));
+                    md.blank();
+                }
+            }
+            continue;
+        }
+        if let Some(keyword) = trimmed.strip_prefix("#+") {
+            if let Some((name, value)) = keyword.split_once(':') {
+                let name = name.to_lowercase();
+                if matches!(name.as_str(), "title" | "author" | "date") && !value.trim().is_empty()
+                {
+                    meta.insert(name, value.trim().to_string());
+                }
+            }
+            continue;
+        }
+        if trimmed == "#" || trimmed.starts_with("# ") {
+            continue;
+        }
+        if trimmed == ":PROPERTIES:" || trimmed == ":LOGBOOK:" {
+            while i < lines.len() && lines[i].trim() != ":END:" {
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        if trimmed.starts_with('|') {
+            let start = i - 1;
+            while i < lines.len() && lines[i].trim().starts_with('|') {
+                i += 1;
+            }
+            let rows: Vec<Vec<String>> = lines[start..i]
+                .iter()
+                .map(|line| line.trim())
+                .filter(|line| !line.starts_with("|-"))
+                .map(|line| {
+                    let line = line.trim_start_matches('|').trim_end_matches('|');
+                    line.split('|')
+                        .map(|cell| org_inline(cell.trim()))
+                        .collect()
+                })
+                .collect();
+            if !rows.is_empty() {
+                md.block(&table(&rows));
+            }
+            continue;
+        }
+        let stars = line.chars().take_while(|c| *c == '*').count();
+        if stars > 0 && line[stars..].starts_with(' ') {
+            md.blank();
+            md.push(format!(
+                "{} {}",
+                "#".repeat(stars.min(6)),
+                org_inline(line[stars..].trim())
+            ));
+            md.blank();
+            continue;
+        }
+        if trimmed.len() >= 5 && trimmed.chars().all(|c| c == '-') {
+            md.block("---");
+            continue;
+        }
+        let indent = &line[..line.len() - line.trim_start().len()];
+        // `*` is only a bullet when indented, as it starts headings
+        let bullets = if indent.is_empty() { "-+" } else { "-+*" };
+        if let Some((marker, text)) = list_item(trimmed, bullets) {
+            let text = match text.split_once(' ') {
+                Some(("[X]", rest)) => format!("[x] {}", rest),
+                Some(("[-]", rest)) => format!("[ ] {}", rest),
+                _ => text.to_string(),
+            };
+            let text = match (marker.as_str(), text.split_once(" :: ")) {
+                ("-", Some((term, description))) => {
+                    format!("**{}**: {}", org_inline(term), org_inline(description))
+                }
+                _ => org_inline(&text),
+            };
+            md.push(format!("{}{} {}", indent, marker, text));
+            continue;
+        }
+        match trimmed.is_empty() {
+            true => md.blank(),
+            false => md.push(format!("{}{}", indent, org_inline(trimmed))),
+        }
+    }
+    Ok(with_meta(&meta, md.finish()))
+}
+
+/// Whether `line` is a reST heading underline or overline, like `=====`.
+fn rst_adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let c = line.chars().next()?;
+    (line.len() >= 3 && c.is_ascii_punctuation() && line.chars().all(|d| d == c)).then_some(c)
+}
+
+/// reST links, literals and roles in a line of text as markdown.
+fn rst_inline(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['`', ':']) {
+        output += &rest[..start];
+        rest = &rest[start..];
+        if let Some(literal) = rest.strip_prefix("``") {
+            if let Some(end) = literal.find("``") {
+                output += &format!("`{}`", &literal[..end]);
+                rest = &literal[end + 2..];
+                continue;
+            }
+        }
+        // Roles, like :code:`x` or :ref:`text <target>`, are kept as literals
+        if let Some(role) = rest.strip_prefix(':') {
+            let name_len = role
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':'))
+                .unwrap_or(role.len());
+            if name_len > 1 && role[..name_len].ends_with(':') && role[name_len..].starts_with('`')
+            {
+                let interpreted = &role[name_len + 1..];
+                if let Some(end) = interpreted.find('`') {
+                    output += &format!("`{}`", &interpreted[..end]);
+                    rest = &interpreted[end + 1..];
+                    continue;
+                }
+            }
+            output.push(':');
+            rest = role;
+            continue;
+        }
+        let interpreted = &rest[1..];
+        let Some(end) = interpreted.find('`') else {
+            output += rest;
+            rest = "";
+            break;
+        };
+        let text = &interpreted[..end];
+        let after = &interpreted[end + 1..];
+        let reference = after.strip_prefix("__").or_else(|| after.strip_prefix('_'));
+        match (reference, text.rfind(" <")) {
+            (Some(after), Some(url_start)) if text.ends_with('>') => {
+                let url = &text[url_start + 2..text.len() - 1];
+                output += &format!("[{}]({})", text[..url_start].trim(), url);
+                rest = after;
+            }
+            (Some(after), _) => {
+                output += &format!("[{}]", text);
+                rest = after;
+            }
+            (None, _) => {
+                output += &format!("*{}*", text);
+                rest = after;
+            }
+        }
+    }
+    output + rest
+}
+
+/// Options of a reST directive, like `:alt:`, with the lines of its content
+/// and the index of the line after it.
+fn rst_directive_body<'a>(
+    lines: &[&'a str],
+    start: usize,
+) -> (Vec<(&'a str, &'a str)>, Vec<&'a str>, usize) {
+    let mut i = start;
+    let mut options = Vec::new();
+    while i < lines.len() {
+        let line = lines[i].trim();
+        match line.strip_prefix(':').and_then(|line| line.split_once(':')) {
+            Some((name, value)) if lines[i].starts_with(char::is_whitespace) => {
+                options.push((name, value.trim()));
+                i += 1;
+            }
+            _ => break,
+        }
+    }
+    let (body, end) = rst_indented(lines, i);
+    (options, body, end)
+}
+
+/// Lines indented below `start`, with the index of the line after them.
+fn rst_indented<'a>(lines: &[&'a str], start: usize) -> (Vec<&'a str>, usize) {
+    let mut i = start;
+    while i < lines.len()
+        && (lines[i].trim().is_empty() || lines[i].starts_with(char::is_whitespace))
+    {
+        i += 1;
+    }
+    // Trailing blank lines belong to what comes after
+    let mut end = i;
+    while end > start && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    (lines[start..end].to_vec(), end)
+}
+
+/// Column ranges of a simple table border, like `=====  =====`.
+fn rst_simple_columns(border: &str) -> Option<Vec<(usize, usize)>> {
+    if !border.starts_with('=') || !border.chars().all(|c| c == '=' || c == ' ') {
+        return None;
+    }
+    let mut columns = Vec::new();
+    let mut start = None;
+    for (i, c) in border.char_indices().chain([(border.len(), ' ')]) {
+        match (c, start) {
+            ('=', None) => start = Some(i),
+            (' ', Some(s)) => {
+                columns.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    (columns.len() > 1).then_some(columns)
+}
+
+/// Rows of a simple table, with the index of the line after it.
+fn rst_simple_table(lines: &[&str], start: usize) -> Option<(Vec<Vec<String>>, usize)> {
+    let columns = rst_simple_columns(lines[start].trim_end())?;
+    let cells = |line: &str| -> Vec<String> {
+        columns
+            .iter()
+            .enumerate()
+            .map(|(n, (start, end))| {
+                // The last column extends to the end of the line
+                let end = if n == columns.len() - 1 {
+                    line.len()
+                } else {
+                    *end
+                };
+                line.get(*start..end.min(line.len()))
+                    .unwrap_or("")
+                    .trim()
+                    .to_string()
+            })
+            .collect()
+    };
+    let mut rows = Vec::new();
+    let mut i = start + 1;
+    let mut borders = 1;
+    while i < lines.len() && borders < 3 {
+        let line = lines[i].trim_end();
+        i += 1;
+        if rst_simple_columns(line).is_some() {
+            borders += 1;
+            // Without a head, the table ends at the second border
+            if borders == 2 && lines.get(i).is_none_or(|l| l.trim().is_empty()) {
+                break;
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        rows.push(cells(line).iter().map(|c| rst_inline(c)).collect());
+    }
+    (!rows.is_empty()).then_some((rows, i))
+}
+
+/// Rows of a grid table, with the index of the line after it. Cells that
+/// span several lines are joined with spaces.
+fn rst_grid_table(lines: &[&str], start: usize) -> Option<(Vec<Vec<String>>, usize)> {
+    let border = lines[start].trim();
+    if !border.starts_with("+-") || !border.ends_with('+') {
+        return None;
+    }
+    let indent = lines[start].len() - lines[start].trim_start().len();
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut i = start + 1;
+    while i < lines.len() {
+        let line = lines[i].get(indent..).unwrap_or("").trim_end();
+        if line.starts_with('+') {
+            if !row.is_empty() {
+                rows.push(row.iter().map(|cell| rst_inline(cell.trim())).collect());
+                row.clear();
+            }
+        } else if line.starts_with('|') {
+            let cells = line
+                .trim_start_matches('|')
+                .trim_end_matches('|')
+                .split('|');
+            for (n, cell) in cells.enumerate() {
+                if row.len() <= n {
+                    row.push(String::new());
+                }
+                if !cell.trim().is_empty() {
+                    row[n] += " ";
+                    row[n] += cell.trim();
+                }
+            }
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    (!rows.is_empty()).then_some((rows, i))
+}
+
+/// Convert a basic reStructuredText document to a yamdr document:
+/// sections, lists, grid and simple tables, literal and code blocks,
+/// images, admonitions and links. Headings get their level from the order
+/// their underline styles appear in, like in reST, and link targets become
+/// link reference definitions at the end of the document.
+pub fn import_rst(rst: &str) -> Result<String> {
+    let mut md = Lines::default();
+    let mut styles: Vec<(char, bool)> = Vec::new();
+    let mut targets = Vec::new();
+    let lines: Vec<&str> = rst.lines().collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+
+        // Sections, with an underline and optionally an overline
+        let next = lines.get(i + 1).copied().unwrap_or("");
+        let heading = match rst_adornment(line) {
+            Some(c)
+                if !next.trim().is_empty()
+                    && lines.get(i + 2).and_then(|l| rst_adornment(l)) == Some(c) =>
+            {
+                Some((c, true, next.trim(), 3))
+            }
+            None if !trimmed.is_empty() && !line.starts_with(' ') => match rst_adornment(next) {
+                Some(c) if next.trim_end().len() >= trimmed.chars().count() => {
+                    Some((c, false, trimmed, 2))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some((c, overline, title, len)) = heading {
+            let level = match styles.iter().position(|style| *style == (c, overline)) {
+                Some(level) => level + 1,
+                None => {
+                    styles.push((c, overline));
+                    styles.len()
+                }
+            };
+            md.blank();
+            md.push(format!(
+                "{} {}",
+                "#".repeat(level.min(6)),
+                rst_inline(title)
+            ));
+            md.blank();
+            i += len;
+            continue;
+        }
+        if rst_adornment(line).is_some() && trimmed.len() >= 4 && next.trim().is_empty() {
+            md.block("---");
+            i += 1;
+            continue;
+        }
+        if let Some((rows, end)) = rst_grid_table(&lines, i).or_else(|| rst_simple_table(&lines, i))
+        {
+            md.block(&table(&rows));
+            i = end;
+            continue;
+        }
+
+        if let Some(explicit) = trimmed
+            .strip_prefix(".. ")
+            .filter(|_| !line.starts_with(' '))
+        {
+            if let Some(target) = explicit.strip_prefix('_') {
+                if let Some((name, url)) = target.split_once(": ") {
+                    targets.push(format!("[{}]: {}", name.trim_matches('`'), url.trim()));
+                }
+                i += 1;
+                continue;
+            }
+            let directive = explicit
+                .split_once("::")
+                .map(|(name, argument)| (name.trim(), argument.trim()));
+            let (options, body, end) = rst_directive_body(&lines, i + 1);
+            i = end;
+            let option = |name: &str| {
+                options
+                    .iter()
+                    .find(|(option, _)| *option == name)
+                    .map(|(_, value)| *value)
+            };
+            match directive {
+                Some(("code-block" | "code" | "sourcecode", language)) => {
+                    md.block(&code_block(Some(language), &dedent(&body)));
+                }
+                Some(("image" | "figure", src)) => {
+                    md.blank();
+                    md.push(format!("![{}]({})", option("alt").unwrap_or(""), src));
+                    let caption = dedent(&body);
+                    if !caption.trim().is_empty() {
+                        md.blank();
+                        caption.lines().for_each(|line| md.push(rst_inline(line)));
+                    }
+                    md.blank();
+                }
+                Some((
+                    kind @ ("note" | "tip" | "hint" | "important" | "warning" | "caution"
+                    | "danger" | "attention" | "error" | "admonition"),
+                    argument,
+                )) => {
+                    let title = match kind {
+                        "admonition" => argument.to_string(),
+                        _ => kind[..1].to_uppercase() + &kind[1..],
+                    };
+                    let mut text = match kind {
+                        "admonition" => String::new(),
+                        _ => argument.to_string(),
+                    };
+                    let body = dedent(&body);
+                    for line in body.lines() {
+                        text.push('\n');
+                        text += line;
+                    }
+                    md.blank();
+                    let mut lines = text.trim().lines();
+                    md.push(
+                        format!(
+                            "> **{}:** {}",
+                            title,
+                            rst_inline(lines.next().unwrap_or(""))
+                        )
+                        .trim_end()
+                        .to_string(),
+                    );
+                    for line in lines {
+                        md.push(format!("> {}", rst_inline(line)).trim_end().to_string());
+                    }
+                    md.blank();
+                }
+                // Comments and unsupported directives are left out
+                _ => {}
+            }
+            continue;
+        }
+
+        let indent = &line[..line.len() - line.trim_start().len()];
+        if let Some((marker, text)) = list_item(trimmed, "-*+•") {
+            md.push(format!("{}{} {}", indent, marker, rst_inline(text)));
+            i += 1;
+            continue;
+        }
+        if trimmed.is_empty() {
+            md.blank();
+            i += 1;
+            continue;
+        }
+
+        // A paragraph ending with `::` is followed by a literal block
+        let (text, literal) = match trimmed.strip_suffix("::") {
+            Some(text) if text.trim().is_empty() => ("", true),
+            Some(text) if text.ends_with(' ') => (text.trim_end(), true),
+            Some(text) => (&trimmed[..text.len() + 1], true),
+            None => (trimmed, false),
+        };
+        if !text.is_empty() {
+            md.push(format!("{}{}", indent, rst_inline(text)));
+        }
+        i += 1;
+        if literal {
+            let (body, end) = rst_indented(&lines, i);
+            if !body.is_empty() {
+                md.block(&code_block(None, &dedent(&body)));
+            }
+            i = end;
+        }
+    }
+    if !targets.is_empty() {
+        md.block(&targets.join("\n"));
+    }
+    Ok(md.finish())
+}
+
+/// Elements that are converted as blocks, and end the paragraph before them.
+const HTML_BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Elements that aren't content, and are left out.
+const HTML_SKIPPED: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "iframe", "svg", "button", "select",
+];
+
+/// Text of an html text node as markdown, with whitespace collapsed and
+/// characters that are markdown syntax escaped.
+fn html_text(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut previous = ' ';
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if !output.ends_with(' ') {
+                    output.push(' ');
+                }
+                previous = ' ';
+                continue;
+            }
+            // Underscores within words aren't emphasis
+            '_' if previous.is_alphanumeric()
+                && chars.peek().is_some_and(|c| c.is_alphanumeric()) => {}
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' => output.push('\\'),
+            _ => {}
+        }
+        output.push(c);
+        previous = c;
+    }
+    output
+}
+
+/// Inline code, with a fence longer than any backticks in `code`.
+fn inline_code(code: &str) -> String {
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+    match longest {
+        0 => format!("{0}{1}{0}", fence, code),
+        _ => format!("{0} {1} {0}", fence, code),
+    }
+}
+
+/// Language of a `pre` element, from a `language-` or `lang-` class of it
+/// or of the `code` element in it.
+fn html_code_language(pre: ElementRef) -> Option<String> {
+    std::iter::once(pre)
+        .chain(
+            pre.child_elements()
+                .filter(|child| child.value().name() == "code"),
+        )
+        .flat_map(|element| element.value().classes())
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .map(String::from)
+}
+
+/// Html converted to markdown, block by block.
+#[derive(Default)]
+struct HtmlImport {
+    md: Lines,
+    /// Inline content of the current paragraph, with hard line breaks as
+    /// `\n`.
+    paragraph: String,
+    /// In a list item, where blocks aren't separated by blank lines, so the
+    /// list stays tight.
+    tight: bool,
+}
+
+impl HtmlImport {
+    fn blank(&mut self) {
+        if !self.tight {
+            self.md.blank();
+        }
+    }
+
+    fn block(&mut self, block: &str) {
+        self.end_paragraph();
+        match self.tight {
+            true => block.trim_end().lines().for_each(|line| self.md.push(line)),
+            false => self.md.block(block),
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        let paragraph = std::mem::take(&mut self.paragraph);
+        let lines: Vec<String> = paragraph
+            .split('\n')
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        let lines: Vec<&str> = {
+            let start = lines.iter().position(|line| !line.is_empty());
+            let end = lines.iter().rposition(|line| !line.is_empty());
+            match (start, end) {
+                (Some(start), Some(end)) => lines[start..=end].iter().map(String::as_str).collect(),
+                _ => return,
+            }
+        };
+        self.blank();
+        for (i, line) in lines.iter().enumerate() {
+            match i + 1 < lines.len() {
+                true => self.md.push(format!("{}\\", line)),
+                false => self.md.push(*line),
+            }
+        }
+        self.blank();
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                self.paragraph += &html_text(text);
+            } else if let Some(child) = ElementRef::wrap(child) {
+                let name = child.value().name();
+                if HTML_SKIPPED.contains(&name) {
+                    continue;
+                }
+                match HTML_BLOCKS.contains(&name) {
+                    true => self.element(child),
+                    false => self.paragraph += &html_inline(child),
+                }
+            }
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                let text = html_inline(element).replace('\n', " ");
+                self.block(&format!("{} {}", "#".repeat(level), text.trim()));
+            }
+            "p" => {
+                self.end_paragraph();
+                self.children(element);
+                self.end_paragraph();
+            }
+            "hr" => self.block("---"),
+            "pre" => {
+                let code: String = element.text().collect();
+                let code = code.strip_prefix('\n').unwrap_or(&code);
+                let language = html_code_language(element);
+                self.block(&code_block(language.as_deref(), code.trim_end()));
+            }
+            "ul" | "ol" => {
+                self.end_paragraph();
+                self.blank();
+                self.list(element);
+                self.blank();
+            }
+            "dl" => {
+                self.end_paragraph();
+                self.blank();
+                let mut term = String::new();
+                for child in element.child_elements() {
+                    let text = html_inline(child).replace('\n', " ");
+                    match child.value().name() {
+                        "dt" => term = text.trim().to_string(),
+                        "dd" => self.md.push(format!("- **{}**: {}", term, text.trim())),
+                        _ => {}
+                    }
+                }
+                self.blank();
+            }
+            "table" => self.table(element),
+            "blockquote" => {
+                let mut quote = HtmlImport::default();
+                quote.children(element);
+                quote.end_paragraph();
+                let quote = quote.md.finish();
+                let quote: Vec<String> = quote
+                    .lines()
+                    .map(|line| format!("> {}", line).trim_end().to_string())
+                    .collect();
+                self.block(&quote.join("\n"));
+            }
+            _ => {
+                self.end_paragraph();
+                self.children(element);
+                self.end_paragraph();
+            }
+        }
+    }
+
+    fn list(&mut self, list: ElementRef) {
+        let ordered = list.value().name() == "ol";
+        let start: usize = list
+            .attr("start")
+            .and_then(|start| start.parse().ok())
+            .unwrap_or(1);
+        let items = list
+            .child_elements()
+            .filter(|item| item.value().name() == "li");
+        for (n, item) in items.enumerate() {
+            let marker = match ordered {
+                true => format!("{}.", start + n),
+                false => "-".to_string(),
+            };
+            let mut content = HtmlImport {
+                tight: true,
+                ..Default::default()
+            };
+            content.children(item);
+            content.end_paragraph();
+            let content = content.md.finish();
+            let padding = " ".repeat(marker.len() + 1);
+            for (i, line) in content.trim_end().lines().enumerate() {
+                match (i, line.is_empty()) {
+                    (0, _) => self.md.push(format!("{} {}", marker, line)),
+                    (_, true) => self.md.push(""),
+                    (_, false) => self.md.push(format!("{}{}", padding, line)),
+                }
+            }
+        }
+    }
+
+    fn table(&mut self, element: ElementRef) {
+        let mut rows = Vec::new();
+        let mut caption = None;
+        let sections = element.child_elements().flat_map(|child| {
+            match matches!(child.value().name(), "thead" | "tbody" | "tfoot") {
+                true => child.child_elements().collect(),
+                false => vec![child],
+            }
+        });
+        for row in sections {
+            match row.value().name() {
+                "caption" => caption = Some(html_inline(row)),
+                "tr" => {
+                    let mut cells = Vec::new();
+                    for cell in row.child_elements() {
+                        if !matches!(cell.value().name(), "td" | "th") {
+                            continue;
+                        }
+                        let text = html_inline(cell).replace('\n', " ").replace('|', "\\|");
+                        cells.push(text.split_whitespace().collect::<Vec<_>>().join(" "));
+                        let span: usize = cell
+                            .attr("colspan")
+                            .and_then(|span| span.parse().ok())
+                            .unwrap_or(1);
+                        cells.extend((1..span.min(100)).map(|_| String::new()));
+                    }
+                    rows.push(cells);
+                }
+                _ => {}
+            }
+        }
+        if let Some(caption) = caption {
+            self.paragraph += &caption;
+            self.end_paragraph();
+        }
+        if !rows.is_empty() {
+            self.block(&table(&rows));
+        }
+    }
+}
+
+/// Inline html, like links and emphasis, as markdown.
+fn html_inline(element: ElementRef) -> String {
+    let content = || {
+        let mut content = String::new();
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                content += &html_text(text);
+            } else if let Some(child) = ElementRef::wrap(child) {
+                if !HTML_SKIPPED.contains(&child.value().name()) {
+                    content += &html_inline(child);
+                }
+            }
+        }
+        content
+    };
+    // Markup around whitespace isn't markdown, so it is kept outside
+    let wrap = |marker: &str| {
+        let content = content();
+        let trimmed = content.trim();
+        match trimmed.is_empty() {
+            true => content,
+            false => {
+                let start = &content[..content.len() - content.trim_start().len()];
+                let end = &content[content.trim_end().len()..];
+                format!("{}{}{}{}{}", start, marker, trimmed, marker, end)
+            }
+        }
+    };
+    match element.value().name() {
+        "strong" | "b" => wrap("**"),
+        "em" | "i" | "cite" => wrap("*"),
+        "del" | "s" | "strike" => wrap("~~"),
+        "code" | "kbd" | "samp" | "tt" => {
+            let code: String = element.text().collect();
+            inline_code(&code.split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+        "br" => "\n".to_string(),
+        "img" => {
+            let src = element.attr("src").unwrap_or_default();
+            let alt = element.attr("alt").unwrap_or_default();
+            format!("![{}]({})", html_text(alt).trim(), src)
+        }
+        "a" => {
+            let text = content();
+            match element.attr("href") {
+                Some(href) if !href.starts_with("javascript:") && !href.starts_with('#') => {
+                    match text.trim() {
+                        "" => format!("<{}>", href),
+                        text => match element.attr("title") {
+                            Some(title) => {
+                                format!("[{}]({} \"{}\")", text, href, title.replace('"', "\\\""))
+                            }
+                            None => format!("[{}]({})", text, href),
+                        },
+                    }
+                }
+                _ => text,
+            }
+        }
+        _ => content(),
+    }
+}
+
+/// Convert an html page to a yamdr document: headings, paragraphs, lists,
+/// tables, code blocks, quotes, images and links. Only the `main` element
+/// of the page is converted if it has one, or the `article` if there is
+/// exactly one, so navigation around the content is left out. The title of
+/// the page is put in a meta block.
+pub fn import_html(html: &str) -> Result<String> {
+    let document = Html::parse_document(html);
+    let select = |selector: &str| {
+        let selector = Selector::parse(selector).unwrap();
+        document.select(&selector).collect::<Vec<_>>()
+    };
+    let main = select("main");
+    let articles = select("article");
+    let content = match (main.first(), articles.as_slice()) {
+        (Some(main), _) => *main,
+        (None, [article]) => *article,
+        _ => select("body")
+            .first()
+            .copied()
+            .unwrap_or_else(|| document.root_element()),
+    };
+    let mut import = HtmlImport::default();
+    import.element(content);
+    import.end_paragraph();
+    let body = import.md.finish();
+
+    let mut meta = BTreeMap::new();
+    if let Some(title) = select("title").first() {
+        let title = title.text().collect::<String>();
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !title.is_empty() {
+            meta.insert("title".to_string(), title);
+        }
+    }
+    Ok(with_meta(&meta, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notebook() {
+        let notebook = r##"{
+  "metadata": {"kernelspec": {"language": "python"}},
+  "nbformat": 4,
+  "cells": [
+    {"cell_type": "markdown", "metadata": {}, "source": ["# Analysis\n", "\n", "Some *text*."]},
+    {"cell_type": "code", "metadata": {}, "execution_count": 1, "source": "x = 1\nprint(x)\nx + 1",
+     "outputs": [
+       {"output_type": "stream", "name": "stdout", "text": ["1\n"]},
+       {"output_type": "execute_result", "data": {"text/plain": ["2"]}, "metadata": {}}
+     ]},
+    {"cell_type": "code", "metadata": {}, "source": [], "outputs": [
+       {"output_type": "display_data", "data": {"image/png": "iVBORw0K\nGgo=\n", "text/plain": ["<Figure>"]}, "metadata": {}},
+       {"output_type": "execute_result", "data": {"text/html": ["<table></table>"], "text/plain": ["df"]}, "metadata": {}}
+     ]},
+    {"cell_type": "code", "metadata": {}, "source": "print(\"\"\"\n```\n\"\"\")\n1 / 0", "outputs": [
+       {"output_type": "error", "ename": "ZeroDivisionError", "evalue": "division by zero", "traceback": []}
+     ]}
+  ]
+}"##;
+        assert_eq!(
+            import_notebook(notebook).unwrap(),
+            r#"# Analysis
+
+Some *text*.
+
+```{"t":"Code","language":"python"}
+x = 1
+print(x)
+x + 1
+// > 1
+// > 2
+```
+
+```{"t":"Image","src":"data:image/png;base64,iVBORw0KGgo="}
+```
+
+```{"t":"Html"}
+<table></table>
+```
+
+````{"t":"Code","language":"python"}
+print("""
+```
+""")
+1 / 0
+// > ZeroDivisionError: division by zero
+````
+"#
+        );
+        assert!(import_notebook("{}").is_err());
+    }
+
+    #[test]
+    fn org() {
+        let org = r#"#+TITLE: Report
+#+OPTIONS: toc:nil
+# A comment
+
+* Intro
+  :PROPERTIES:
+  :ID: intro
+  :END:
+Some *bold*, /italic/, =verbatim= and +struck+ text, a [[https://example.com][link]], 2*3*4 and a/b/c.
+** Lists
+- [X] done
+  + nested
+1) first
+- term :: description
+
+| Name | Value |
+|------+-------|
+| a    | 1     |
+
+#+BEGIN_SRC rust
+  fn main() {}
+  ,* not a heading
+#+END_SRC
+#+BEGIN_QUOTE
+Quoted
+#+END_QUOTE
+"#;
+        assert_eq!(
+            import_org(org).unwrap(),
+            r#"```{"t":"External","meta":true}
+title: Report
+```
+
+# Intro
+
+Some **bold**, *italic*, `verbatim` and ~~struck~~ text, a [link](https://example.com), 2*3*4 and a/b/c.
+
+## Lists
+
+- [x] done
+  - nested
+1. first
+- **term**: description
+
+| Name | Value |
+| --- | --- |
+| a | 1 |
+
+```{"t":"Code","language":"rust"}
+fn main() {}
+* not a heading
+```
+
+> Quoted
+"#
+        );
+    }
+
+    #[test]
+    fn rst() {
+        let rst = r#"=====
+Title
+=====
+
+Section
+=======
+
+Some ``literal``, `a link <https://example.com>`_, `target`_ and :code:`x` text.
+
+Sub
+---
+
+* one
+
+#. first
+
+Example::
+
+    fn main() {}
+
+.. code-block:: python
+   :linenos:
+
+   print(1)
+
+.. note:: Be careful
+   with this.
+
+.. image:: pic.png
+   :alt: A picture
+
+.. A comment
+
++------+-------+
+| Name | Value |
++======+=======+
+| a    | 1     |
+|      | more  |
++------+-------+
+
+=====  =====
+A      B
+=====  =====
+1      2
+=====  =====
+
+Other
+=======
+
+.. _target: https://example.com/target
+"#;
+        assert_eq!(
+            import_rst(rst).unwrap(),
+            r#"# Title
+
+## Section
+
+Some `literal`, [a link](https://example.com), [target] and `x` text.
+
+### Sub
+
+- one
+
+1. first
+
+Example:
+
+```
+fn main() {}
+```
+
+```{"t":"Code","language":"python"}
+print(1)
+```
+
+> **Note:** Be careful
+> with this.
+
+![A picture](pic.png)
+
+| Name | Value |
+| --- | --- |
+| a | 1 more |
+
+| A | B |
+| --- | --- |
+| 1 | 2 |
+
+## Other
+
+[target]: https://example.com/target
+"#
+        );
+    }
+
+    #[test]
+    fn html() {
+        let html = r#"<!doctype html>
+<html><head><title> A   page </title><style>p {}</style></head>
+<body>
+<nav><a href="/">Home</a></nav>
+<main>
+<h1>Hello <em>world</em></h1>
+<p>Some <strong>bold</strong>, <code>a_b</code>, a <a href="https://example.com">link</a>,
+snake_case, 2*3 and a<br>break.</p>
+<ul>
+  <li>one</li>
+  <li>two
+    <ol><li>three</li></ol>
+  </li>
+</ul>
+<pre><code class="language-rust">fn main() {}
+</code></pre>
+<table>
+  <thead><tr><th>Name</th><th>Value</th></tr></thead>
+  <tbody><tr><td>a | b</td><td>1</td></tr><tr><td colspan="2">wide</td></tr></tbody>
+</table>
+<blockquote><p>Quoted</p></blockquote>
+<img src="pic.png" alt="A picture">
+<script>alert(1)</script>
+</main>
+</body></html>"#;
+        assert_eq!(
+            import_html(html).unwrap(),
+            r#"```{"t":"External","meta":true}
+title: A page
+```
+
+# Hello *world*
+
+Some **bold**, `a_b`, a [link](https://example.com), snake_case, 2\*3 and a\
+break.
+
+- one
+- two
+  1. three
+
+```{"t":"Code","language":"rust"}
+fn main() {}
+```
+
+| Name | Value |
+| --- | --- |
+| a \| b | 1 |
+| wide |  |
+
+> Quoted
+
+![A picture](pic.png)
+"#
+        );
+    }
+}
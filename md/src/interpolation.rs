@@ -0,0 +1,260 @@
+use crate::{CustomBlock, Format};
+use pulldown_cmark::{escape::escape_html, Event};
+use std::collections::BTreeMap;
+
+/// Options for `{{variable}}` interpolation in text, see
+/// `YamdrOptions::interpolation`.
+#[derive(Debug, Clone, Default)]
+pub struct InterpolationOptions {
+    /// Undefined variables are rendered as errors, and reported in
+    /// `Meta::errors`, instead of being rendered as empty text.
+    pub strict: bool,
+}
+
+/// A part of interpolated text.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// A variable that isn't defined, in strict mode.
+    Undefined(String),
+}
+
+/// Text containing `{{variable}}`s. The variables are replaced in html
+/// output, while markdown output keeps the text as it is written.
+#[derive(Debug, Clone)]
+pub(crate) struct InterpolatedText {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl InterpolatedText {
+    /// Names of the undefined variables in the text, in strict mode.
+    pub fn undefined(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Undefined(name) => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// The text with the variables replaced, undefined variables left out.
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Text(text) => Some(text.as_str()),
+                Part::Undefined(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl CustomBlock for InterpolatedText {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Md => vec![Event::Text(self.source.as_str().into())],
+            Format::Html => self
+                .parts
+                .iter()
+                .map(|part| match part {
+                    Part::Text(text) => Event::Text(text.as_str().into()),
+                    Part::Undefined(name) => {
+                        let mut escaped = String::new();
+                        escape_html(&mut escaped, name).unwrap();
+                        Event::Html(
+                            format!(
+                                r#"<span class="error" role="alert">{{{{{}}}}}</span>"#,
+                                escaped
+                            )
+                            .into(),
+                        )
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').all(|segment| {
+            !segment.is_empty() && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+}
+
+/// Replace the `{{variable}}`s in `text` with the values `lookup` returns.
+/// Variables may be paths like `{{author.name}}`. Text in triple braces,
+/// `{{{like this}}}`, is written as `{{like this}}` without replacing it.
+///
+/// Returns `None` if `text` doesn't contain any variables.
+pub(crate) fn interpolate(
+    text: &str,
+    strict: bool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<InterpolatedText> {
+    if !text.contains("{{") {
+        return None;
+    }
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        current += &rest[..start];
+        rest = &rest[start..];
+        if let Some(end) = rest.strip_prefix("{{{").and_then(|r| r.find("}}}")) {
+            current += "{{";
+            current += &rest[3..3 + end];
+            current += "}}";
+            rest = &rest[3 + end + 3..];
+            continue;
+        }
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+        let name = rest[2..end].trim();
+        if !is_variable_name(name) {
+            current += &rest[..2];
+            rest = &rest[2..];
+            continue;
+        }
+        match lookup(name) {
+            Some(value) => current += &value,
+            None if strict => {
+                parts.push(Part::Text(std::mem::take(&mut current)));
+                parts.push(Part::Undefined(name.into()));
+            }
+            None => {}
+        }
+        rest = &rest[end + 2..];
+    }
+    current += rest;
+    parts.push(Part::Text(current));
+    parts.retain(|part| part != &Part::Text(String::new()));
+    Some(InterpolatedText {
+        source: text.into(),
+        parts,
+    })
+}
+
+/// Render a yaml value as text, strings without quotes.
+pub(crate) fn yaml_to_string(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(s) => s.clone(),
+        serde_yaml::Value::Null => String::new(),
+        value => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+/// Look up the value of a path like `author.name` in the fields of meta
+/// blocks.
+pub(crate) fn lookup_yaml(
+    fields: &BTreeMap<String, serde_yaml::Value>,
+    path: &str,
+) -> Option<String> {
+    let mut segments = path.split('.');
+    let mut value = fields.get(segments.next()?)?;
+    for segment in segments {
+        value = value.get(segment)?;
+    }
+    Some(yaml_to_string(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error, YamdrOptions};
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "name" => Some("World".into()),
+            "a.b" => Some("nested".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn interpolate_text() {
+        let html = |text: &str, strict: bool| {
+            let text = interpolate(text, strict, lookup).unwrap();
+            crate::html::render(text.to_events(Format::Html).into_iter(), 0)
+        };
+        assert!(interpolate("No variables {here}", false, lookup).is_none());
+        assert_eq!(html("Hello {{name}}!", false), "Hello World!");
+        assert_eq!(html("{{ a.b }} and {{missing}}.", false), "nested and .");
+        assert_eq!(html("Literal {{{name}}}", false), "Literal {{name}}");
+        assert_eq!(
+            html("Not {{a variable}} {{", false),
+            "Not {{a variable}} {{"
+        );
+        assert_eq!(
+            html("Hello {{missing}}", true),
+            r#"Hello <span class="error" role="alert">{{missing}}</span>"#
+        );
+
+        let text = interpolate("{{name}} {{missing}}", true, lookup).unwrap();
+        assert_eq!(text.undefined().collect::<Vec<_>>(), vec!["missing"]);
+        assert_eq!(
+            text.to_events(Format::Md),
+            vec![Event::Text("{{name}} {{missing}}".into())]
+        );
+    }
+
+    #[test]
+    fn yaml_lookup() {
+        let fields = serde_yaml::from_str("{title: Report, author: {name: Ada}, n: 3}").unwrap();
+        assert_eq!(lookup_yaml(&fields, "title").as_deref(), Some("Report"));
+        assert_eq!(lookup_yaml(&fields, "author.name").as_deref(), Some("Ada"));
+        assert_eq!(lookup_yaml(&fields, "n").as_deref(), Some("3"));
+        assert_eq!(lookup_yaml(&fields, "author.age"), None);
+    }
+
+    #[test]
+    fn interpolation() {
+        let document = r#"
+```{t: External, meta: true, lang: en}
+title: Report
+author:
+  name: Ada
+```
+
+```{t: Script}
+let total = 40 + 2;
+```
+
+# {{title}} by {{author.name}}
+
+Total: **{{total}}**, `{{total}}`, {{{total}}} and {{missing}}.
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("{{title}}"));
+        assert!(meta.errors.is_empty());
+
+        let mut options = YamdrOptions {
+            interpolation: Some(InterpolationOptions::default()),
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains("Report by Ada</h1>"));
+        assert!(html.contains("<strong>42</strong>, <code>{{total}}</code>, {{total}} and .</p>"));
+        assert!(meta.errors.is_empty());
+
+        options.interpolation = Some(InterpolationOptions { strict: true });
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<span class="error" role="alert">{{missing}}</span>"#));
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::UndefinedVariable(name)] if name == "missing"
+        ));
+
+        options.format = Some(Format::Md);
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("# {{title}} by {{author.name}}\n"));
+    }
+}
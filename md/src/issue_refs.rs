@@ -0,0 +1,178 @@
+use pulldown_cmark::{Event, LinkType, Tag};
+use std::collections::HashMap;
+
+/// The issue or pull request number of a reference at the start of `text`,
+/// like `#1234` or `GH-1234`, with the length of the reference.
+fn reference_at(text: &str) -> Option<(u64, usize)> {
+    let digits = text
+        .strip_prefix('#')
+        .or_else(|| text.strip_prefix("GH-"))?;
+    let len = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let end = text.len() - digits.len() + len;
+    // Not a reference if it's part of a word, like `#1a` or `#12_000`
+    let ends_word = !text[end..].starts_with(|c: char| c.is_alphanumeric() || c == '_');
+    let number = digits[..len].parse().ok().filter(|_| ends_word)?;
+    Some((number, end))
+}
+
+#[cfg(feature = "issues")]
+fn fetch_title(repository: &str, number: u64) -> Option<String> {
+    let path = repository.strip_prefix("https://github.com/")?;
+    let url = format!("https://api.github.com/repos/{}/issues/{}", path, number);
+    let issue = ureq::get(&url)
+        .timeout(std::time::Duration::from_secs(10))
+        .call()
+        .ok()?
+        .into_string()
+        .ok()?;
+    let issue: serde_json::Value = serde_json::from_str(&issue).ok()?;
+    issue["title"].as_str().map(String::from)
+}
+
+#[cfg(not(feature = "issues"))]
+fn fetch_title(_repository: &str, _number: u64) -> Option<String> {
+    None
+}
+
+/// Turns references to issues and pull requests in the text of html
+/// events, like `#1234` and `GH-1234`, into links to them in the
+/// `repository` of the document, a frontmatter or meta block field like
+/// `https://github.com/owner/repo`. Text in code, and in links, is left as
+/// it is.
+///
+/// With `YamdrOptions::issue_titles` and the `issues` feature, the titles
+/// of issues in GitHub repositories are fetched and added as the titles of
+/// the links. References to issues that can't be fetched are still linked.
+pub(crate) struct IssueRefEvents {
+    repository: String,
+    fetch_titles: bool,
+    titles: HashMap<u64, Option<String>>,
+    in_code_block: bool,
+    in_link: usize,
+}
+
+impl IssueRefEvents {
+    pub fn new(repository: &str, fetch_titles: bool) -> Self {
+        IssueRefEvents {
+            repository: repository.trim_end_matches('/').to_string(),
+            fetch_titles,
+            titles: HashMap::new(),
+            in_code_block: false,
+            in_link: 0,
+        }
+    }
+
+    fn title(&mut self, number: u64) -> String {
+        if !self.fetch_titles {
+            return String::new();
+        }
+        let repository = &self.repository;
+        self.titles
+            .entry(number)
+            .or_insert_with(|| fetch_title(repository, number))
+            .clone()
+            .unwrap_or_default()
+    }
+
+    fn split_references(&mut self, text: &str) -> Option<Vec<Event<'static>>> {
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < text.len() {
+            let at_word_start = text[..i]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !(c.is_alphanumeric() || matches!(c, '_' | '&' | '/')));
+            let Some((number, len)) = reference_at(&text[i..]).filter(|_| at_word_start) else {
+                i += text[i..].chars().next().unwrap().len_utf8();
+                continue;
+            };
+            if i > start {
+                events.push(Event::Text(text[start..i].to_string().into()));
+            }
+            let dest = format!("{}/issues/{}", self.repository, number);
+            let tag = Tag::Link(LinkType::Inline, dest.into(), self.title(number).into());
+            events.extend([
+                Event::Start(tag.clone()),
+                Event::Text(text[i..i + len].to_string().into()),
+                Event::End(tag),
+            ]);
+            start = i + len;
+            i = start;
+        }
+        if events.is_empty() {
+            return None;
+        }
+        if start < text.len() {
+            events.push(Event::Text(text[start..].to_string().into()));
+        }
+        Some(events)
+    }
+
+    pub fn events<'a>(&mut self, event: Event<'a>) -> Vec<Event<'a>> {
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            Event::Start(Tag::Link(..) | Tag::Image(..)) => self.in_link += 1,
+            Event::End(Tag::Link(..) | Tag::Image(..)) => self.in_link -= 1,
+            Event::Text(text) if !self.in_code_block && self.in_link == 0 => {
+                if let Some(events) = self.split_references(text) {
+                    return events;
+                }
+            }
+            _ => {}
+        }
+        vec![event]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, YamdrOptions};
+    use pulldown_cmark::{Options, Parser};
+
+    fn render(markdown: &str) -> String {
+        let mut refs = IssueRefEvents::new("https://github.com/owner/repo/", false);
+        let events = Parser::new_ext(markdown, Options::all()).flat_map(|e| refs.events(e));
+        crate::html::render(events, 0)
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(reference_at("#12, and"), Some((12, 3)));
+        assert_eq!(reference_at("GH-1234"), Some((1234, 7)));
+        assert_eq!(reference_at("#12a"), None);
+        assert_eq!(reference_at("# 12"), None);
+        assert_eq!(reference_at("GH-"), None);
+
+        assert_eq!(
+            render("Fixes #12 and GH-3 (#4)."),
+            "<p>Fixes <a href=\"https://github.com/owner/repo/issues/12\">#12</a> and <a href=\"https://github.com/owner/repo/issues/3\">GH-3</a> (<a href=\"https://github.com/owner/repo/issues/4\">#4</a>).</p>\n"
+        );
+        assert_eq!(
+            render("a#1 `#2` [#3](x) https://example.com/#4"),
+            "<p>a#1 <code>#2</code> <a href=\"x\">#3</a> https://example.com/#4</p>\n"
+        );
+    }
+
+    #[test]
+    fn issue_refs() {
+        let document = "---\nrepository: https://github.com/JonasBak/yamdr\n---\n# Release notes\n\n- Fix tables (#12, GH-7)\n";
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(
+            "Fix tables (<a href=\"https://github.com/JonasBak/yamdr/issues/12\">#12</a>, <a href=\"https://github.com/JonasBak/yamdr/issues/7\">GH-7</a>)"
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("- Fix tables (#12, GH-7)"));
+        let (_, html) = render_markdown(&Default::default(), "Fix #12\n");
+        assert!(!html.contains("<a"));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{CustomBlock, CustomBlockReader, Format, Result};
+use pulldown_cmark::{escape::escape_html, Event};
+
+const PREFIX: &str = "kbd:";
+
+/// A keyboard shortcut, written as inline code like `` `kbd:Ctrl+Shift+P` ``.
+/// Chords of a sequence are separated by spaces, like `kbd:Ctrl+K Ctrl+S`.
+#[derive(Debug, Clone)]
+pub struct KbdBlock {
+    inline: String,
+}
+
+pub struct KbdBlockReader {}
+
+impl KbdBlockReader {
+    pub fn initial_state() -> Self {
+        KbdBlockReader {}
+    }
+}
+
+impl CustomBlockReader for KbdBlockReader {
+    fn can_read_inline(&self, inline: &str) -> bool {
+        inline
+            .strip_prefix(PREFIX)
+            .and_then(|keys| keys.chars().next())
+            .is_some_and(|key| !key.is_whitespace())
+    }
+
+    fn read_inline(&mut self, inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
+        Ok(Some(Box::new(KbdBlock {
+            inline: inline.into(),
+        })))
+    }
+}
+
+/// Split a chord like `Ctrl++` into its keys, where a `+` that isn't
+/// between two keys is the plus key.
+fn keys(chord: &str) -> Vec<&str> {
+    let mut keys = Vec::new();
+    let mut rest = chord;
+    while !rest.is_empty() {
+        match rest[1..].find('+') {
+            Some(i) => {
+                keys.push(&rest[..i + 1]);
+                rest = &rest[i + 2..];
+            }
+            None => {
+                keys.push(rest);
+                rest = "";
+            }
+        }
+    }
+    keys
+}
+
+impl CustomBlock for KbdBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let chords: Vec<String> = self.inline[PREFIX.len()..]
+                    .split_whitespace()
+                    .map(|chord| {
+                        keys(chord)
+                            .into_iter()
+                            .map(|key| {
+                                let mut escaped = String::new();
+                                escape_html(&mut escaped, key).unwrap();
+                                format!("<kbd>{}</kbd>", escaped)
+                            })
+                            .collect::<Vec<_>>()
+                            .join("+")
+                    })
+                    .collect();
+                vec![Event::Html(
+                    format!(r#"<kbd class="shortcut">{}</kbd>"#, chords.join(" ")).into(),
+                )]
+            }
+            Format::Md => vec![Event::Code(self.inline.as_str().into())],
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn shortcuts() {
+        assert_eq!(keys("Ctrl+Shift+P"), vec!["Ctrl", "Shift", "P"]);
+        assert_eq!(keys("Ctrl++"), vec!["Ctrl", "+"]);
+        assert_eq!(keys("+"), vec!["+"]);
+
+        let mut reader = KbdBlockReader::initial_state();
+        assert!(!reader.can_read_inline("kbd:"));
+        assert!(!reader.can_read_inline("kbd: u8"));
+        assert!(!reader.can_read_inline("let kbd: u8"));
+        let block = reader.read_inline("kbd:Ctrl+K <S>").unwrap().unwrap();
+        assert_eq!(
+            crate::html::render(block.to_events(Format::Html).into_iter(), 0),
+            r#"<kbd class="shortcut"><kbd>Ctrl</kbd>+<kbd>K</kbd> <kbd>&lt;S&gt;</kbd></kbd>"#
+        );
+        assert_eq!(
+            block.to_events(Format::Md),
+            vec![Event::Code("kbd:Ctrl+K <S>".into())]
+        );
+    }
+
+    #[test]
+    fn inline_shortcuts() {
+        let document = "Open the palette with `kbd:Ctrl+Shift+P`, not `kbd: u8`.\n";
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(
+            r#"with <kbd class="shortcut"><kbd>Ctrl</kbd>+<kbd>Shift</kbd>+<kbd>P</kbd></kbd>, not <code>kbd: u8</code>"#
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_markdown(&options, document).1,
+            format!("{}\n", document)
+        );
+    }
+}
@@ -0,0 +1,499 @@
+use crate::{
+    utils::{build_table, header_field},
+    CustomBlock, CustomBlockHeader, Format, Labels,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use std::collections::BTreeMap;
+
+/// Amounts that differ less than this are equal, as amounts are floats.
+const EPSILON: f64 = 1e-9;
+
+/// Beancount directives that are accepted, but don't affect the ledger.
+const IGNORED_DIRECTIVES: &[&str] = &["open", "close", "commodity"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Posting {
+    account: String,
+    amount: f64,
+    commodity: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Transaction {
+    date: String,
+    description: String,
+    postings: Vec<Posting>,
+}
+
+/// Amounts per commodity.
+type Amounts = BTreeMap<String, f64>;
+
+/// A double-entry ledger, in a subset of the ledger or beancount format.
+/// Transactions start with a date, followed by indented postings of an
+/// account and an amount, and must balance. One posting of a transaction
+/// may leave out the amount, which is then the rest of the transaction.
+///
+/// ````text
+/// ```{t: Ledger, name: budget}
+/// 2024-01-05 * "Groceries"
+///   expenses:food   42.50 EUR
+///   assets:bank
+/// ```
+/// ````
+///
+/// Renders a balance table and a register of the postings, or only one of
+/// them with `show: balance` or `show: register`. The register can be
+/// limited to the accounts starting with `account`. The balances, also of
+/// parent accounts like `expenses`, are available to scripts in a constant
+/// called `name`, or `ledger`, like `ledger.balances["expenses"]`.
+#[derive(Debug, Clone)]
+pub struct LedgerBlock {
+    header: CustomBlockHeader,
+    input: String,
+    transactions: Vec<Transaction>,
+    precision: usize,
+    labels: Labels,
+}
+
+/// Amount and commodity of an amount like `42.50 EUR`, `$-3` or `1,000`.
+fn parse_amount(amount: &str) -> Option<(f64, String, usize)> {
+    let is_number = |c: char| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+');
+    let start = amount.find(is_number)?;
+    let end = start
+        + amount[start..]
+            .find(|c| !is_number(c))
+            .unwrap_or(amount.len() - start);
+    let number = amount[start..end].replace(',', "");
+    let commodity = format!("{}{}", &amount[..start], &amount[end..]);
+    let precision = number
+        .split_once('.')
+        .map_or(0, |(_, decimals)| decimals.len());
+    Some((
+        number.parse().ok()?,
+        commodity.trim().to_string(),
+        precision,
+    ))
+}
+
+/// Whether `line` starts with a date like `2024-01-05` or `2024/01/05`.
+fn starts_with_date(line: &str) -> bool {
+    let date = line.split_whitespace().next().unwrap_or_default();
+    date.len() == 10
+        && date.chars().enumerate().all(|(i, c)| match i {
+            4 | 7 => c == '-' || c == '/',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// The transactions of a ledger, and the largest number of decimals of the
+/// amounts.
+fn parse(input: &str) -> Result<(Vec<Transaction>, usize), String> {
+    let mut transactions: Vec<(usize, Transaction, Option<usize>)> = Vec::new();
+    let mut precision = 0;
+    let mut ignoring = false;
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split(';').next().unwrap_or_default().trim_end();
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            if !starts_with_date(line) {
+                return Err(format!("expected a date on line {}", line_number));
+            }
+            let (date, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            ignoring = IGNORED_DIRECTIVES.contains(&rest.split_whitespace().next().unwrap_or(""));
+            if ignoring {
+                continue;
+            }
+            let description = rest
+                .trim()
+                .trim_start_matches(['*', '!'])
+                .trim()
+                .replace('"', "");
+            transactions.push((
+                line_number,
+                Transaction {
+                    date: date.replace('/', "-"),
+                    description,
+                    postings: Vec::new(),
+                },
+                None,
+            ));
+            continue;
+        }
+        if ignoring {
+            continue;
+        }
+        let Some((_, transaction, elided)) = transactions.last_mut() else {
+            return Err(format!(
+                "posting without a transaction on line {}",
+                line_number
+            ));
+        };
+        let (account, amount) = line
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((line.trim(), ""));
+        let (amount, commodity) = match amount.trim() {
+            "" if elided.is_some() => {
+                return Err(format!(
+                    "more than one posting without an amount on line {}",
+                    line_number
+                ))
+            }
+            "" => {
+                *elided = Some(transaction.postings.len());
+                (0.0, String::new())
+            }
+            amount => {
+                let (amount, commodity, decimals) = parse_amount(amount)
+                    .ok_or_else(|| format!("invalid amount on line {}", line_number))?;
+                precision = precision.max(decimals);
+                (amount, commodity)
+            }
+        };
+        transaction.postings.push(Posting {
+            account: account.into(),
+            amount,
+            commodity,
+        });
+    }
+
+    transactions
+        .into_iter()
+        .map(|(line_number, mut transaction, elided)| {
+            if transaction.postings.len() < 2 {
+                return Err(format!(
+                    "transaction on line {} needs at least two postings",
+                    line_number
+                ));
+            }
+            let mut sums = Amounts::new();
+            for (i, posting) in transaction.postings.iter().enumerate() {
+                if elided != Some(i) {
+                    *sums.entry(posting.commodity.clone()).or_default() += posting.amount;
+                }
+            }
+            if let Some(elided) = elided {
+                if sums.len() > 1 {
+                    return Err(format!(
+                        "the missing amount on line {} is ambiguous with several commodities",
+                        line_number
+                    ));
+                }
+                let (commodity, sum) = sums.pop_first().unwrap_or_default();
+                transaction.postings[elided].amount = -sum;
+                transaction.postings[elided].commodity = commodity;
+                return Ok(transaction);
+            }
+            match sums.iter().find(|(_, sum)| sum.abs() > EPSILON) {
+                Some((commodity, sum)) => Err(format!(
+                    "transaction on line {} doesn't balance, it's off by {}",
+                    line_number,
+                    format_amount(*sum, commodity, precision)
+                )),
+                None => Ok(transaction),
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(|transactions| (transactions, precision))
+}
+
+/// An amount with its commodity, where symbols like `$` go before the number.
+fn format_amount(amount: f64, commodity: &str, precision: usize) -> String {
+    let number = format!("{:.*}", precision, amount.abs());
+    // Amounts that round to zero are shown without a sign
+    let sign = match amount < 0.0 && number.contains(|c: char| ('1'..='9').contains(&c)) {
+        true => "-",
+        false => "",
+    };
+    match commodity {
+        "" => format!("{}{}", sign, number),
+        c if c.chars().any(char::is_alphanumeric) => format!("{}{} {}", sign, number, c),
+        c => format!("{}{}{}", sign, c, number),
+    }
+}
+
+impl LedgerBlock {
+    pub fn read(
+        header: &CustomBlockHeader,
+        input: &str,
+        labels: &Labels,
+    ) -> Result<LedgerBlock, String> {
+        let (transactions, precision) = parse(input)?;
+        Ok(LedgerBlock {
+            header: header.clone(),
+            input: input.into(),
+            transactions,
+            precision,
+            labels: labels.clone(),
+        })
+    }
+
+    /// Name of the constant with the balances in scripts.
+    pub fn name(&self) -> String {
+        header_field(&self.header, "name").unwrap_or_else(|| "ledger".into())
+    }
+
+    /// Balances of the accounts that have postings, and, with `parents`,
+    /// also of the accounts they belong to.
+    fn balances(&self, parents: bool) -> BTreeMap<&str, Amounts> {
+        let mut balances: BTreeMap<&str, Amounts> = BTreeMap::new();
+        for posting in self.transactions.iter().flat_map(|t| &t.postings) {
+            let account = posting.account.as_str();
+            let mut accounts = vec![account];
+            if parents {
+                accounts.extend(account.match_indices(':').map(|(i, _)| &account[..i]));
+            }
+            for account in accounts {
+                *balances
+                    .entry(account)
+                    .or_default()
+                    .entry(posting.commodity.clone())
+                    .or_default() += posting.amount;
+            }
+        }
+        balances
+    }
+
+    fn format_amounts(&self, amounts: &Amounts) -> String {
+        let amounts: Vec<String> = amounts
+            .iter()
+            .filter(|(_, amount)| amount.abs() > EPSILON)
+            .map(|(commodity, amount)| format_amount(*amount, commodity, self.precision))
+            .collect();
+        match amounts.is_empty() {
+            true => format_amount(0.0, "", self.precision),
+            false => amounts.join(", "),
+        }
+    }
+
+    /// The balances for scripts, as a map of `balances` of accounts and the
+    /// number of `transactions`. Balances are numbers, or maps of
+    /// commodities to numbers for accounts with several commodities.
+    pub fn totals(&self) -> rhai::Map {
+        let balances: rhai::Map = self
+            .balances(true)
+            .into_iter()
+            .map(|(account, amounts)| {
+                let value: rhai::Dynamic = match amounts.len() {
+                    1 => amounts.into_values().sum::<f64>().into(),
+                    _ => amounts
+                        .into_iter()
+                        .map(|(commodity, amount)| (commodity.into(), amount.into()))
+                        .collect::<rhai::Map>()
+                        .into(),
+                };
+                (account.into(), value)
+            })
+            .collect();
+        let mut totals = rhai::Map::new();
+        totals.insert("balances".into(), balances.into());
+        totals.insert(
+            "transactions".into(),
+            (self.transactions.len() as rhai::INT).into(),
+        );
+        totals
+    }
+
+    fn balance_table(&self) -> Vec<Event<'_>> {
+        let [_, _, account, _, balance] = &self.labels.ledger;
+        let rows = self
+            .balances(false)
+            .into_iter()
+            .map(|(account, amounts)| vec![account.into(), self.format_amounts(&amounts).into()])
+            .collect();
+        build_table(vec![account.as_str().into(), balance.as_str().into()], rows)
+    }
+
+    fn register_table(&self) -> Vec<Event<'_>> {
+        let filter = header_field(&self.header, "account");
+        let mut running = Amounts::new();
+        let mut rows = Vec::new();
+        for transaction in &self.transactions {
+            let mut first = true;
+            for posting in &transaction.postings {
+                if filter
+                    .as_ref()
+                    .is_some_and(|filter| !posting.account.starts_with(filter.as_str()))
+                {
+                    continue;
+                }
+                *running.entry(posting.commodity.clone()).or_default() += posting.amount;
+                let (date, description): (CowStr, CowStr) = match std::mem::take(&mut first) {
+                    true => (
+                        transaction.date.as_str().into(),
+                        transaction.description.as_str().into(),
+                    ),
+                    false => ("".into(), "".into()),
+                };
+                rows.push(vec![
+                    date,
+                    description,
+                    posting.account.as_str().into(),
+                    format_amount(posting.amount, &posting.commodity, self.precision).into(),
+                    self.format_amounts(&running).into(),
+                ]);
+            }
+        }
+        let head = self
+            .labels
+            .ledger
+            .iter()
+            .map(|label| label.as_str().into())
+            .collect();
+        build_table(head, rows)
+    }
+}
+
+impl CustomBlock for LedgerBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let show = header_field(&self.header, "show");
+                let mut events = vec![Event::Html(r#"<div class="ledger">"#.into())];
+                if show.as_deref() != Some("register") {
+                    events.extend(self.balance_table());
+                }
+                if show.as_deref() != Some("balance") {
+                    events.extend(self.register_table());
+                }
+                events.push(Event::Html("</div>\n".into()));
+                events
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error, InterpolationOptions, YamdrOptions};
+
+    const LEDGER: &str = r#"
+2024-01-01 open assets:bank
+2024-01-05 * "Groceries"
+  expenses:food     42.50 EUR ; lunch included
+  assets:bank
+2024/01/06 Salary
+  assets:bank      1,000 EUR
+  income:salary   -1,000 EUR
+2024-01-07 Books
+  expenses:books    $12
+  assets:cash      $-12
+"#;
+
+    #[test]
+    fn parse_ledger() {
+        let (transactions, precision) = parse(LEDGER).unwrap();
+        assert_eq!(precision, 2);
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(transactions[0].description, "Groceries");
+        assert_eq!(
+            transactions[0].postings[1],
+            Posting {
+                account: "assets:bank".into(),
+                amount: -42.5,
+                commodity: "EUR".into(),
+            }
+        );
+        assert_eq!(transactions[1].date, "2024-01-06");
+        assert_eq!(transactions[2].postings[1].commodity, "$");
+
+        for (ledger, error) in [
+            ("2024-01-01 A\n  a  1\n  b  -2\n", "off by -1"),
+            ("2024-01-01 A\n  a  1\n", "at least two postings"),
+            ("2024-01-01 A\n  a\n  b\n", "more than one posting"),
+            ("2024-01-01 A\n  a  1 EUR\n  b  1 USD\n  c\n", "ambiguous"),
+            ("  a  1\n", "without a transaction"),
+            ("A\n", "expected a date"),
+            ("2024-01-01 A\n  a  many\n  b\n", "invalid amount"),
+        ] {
+            let err = parse(ledger).unwrap_err();
+            assert!(err.contains(error), "{}", err);
+        }
+    }
+
+    #[test]
+    fn amounts() {
+        assert_eq!(format_amount(-3.0, "$", 2), "-$3.00");
+        assert_eq!(format_amount(1000.0, "EUR", 0), "1000 EUR");
+        assert_eq!(format_amount(-0.0000001, "", 1), "0.0");
+        assert_eq!(parse_amount("$-3.5"), Some((-3.5, "$".into(), 1)));
+    }
+
+    #[test]
+    fn render_tables() {
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Ledger, account: assets}").unwrap();
+        let block = LedgerBlock::read(&header, LEDGER, &Labels::default()).unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains("<td>assets:bank</td><td>957.50 EUR</td>"));
+        assert!(html.contains("<td>assets:cash</td><td>-$12.00</td>"));
+        assert!(html.contains(
+            "<td>2024-01-06</td><td>Salary</td><td>assets:bank</td><td>1000.00 EUR</td><td>957.50 EUR</td>"
+        ));
+        assert!(!html.contains("<td>income:salary</td><td>-1000.00 EUR</td><td>"));
+
+        let totals = block.totals();
+        let balances = totals["balances"].read_lock::<rhai::Map>().unwrap();
+        assert_eq!(balances["expenses:food"].as_float(), Ok(42.5));
+        assert!(balances["expenses"].is_map());
+        assert_eq!(block.name(), "ledger");
+    }
+
+    #[test]
+    fn ledger() {
+        let document = r#"
+```{t: Ledger, name: budget}
+2024-01-05 Groceries
+  expenses:food   42.50
+  assets:bank
+2024-01-06 Rent
+  expenses:rent   800
+  assets:bank
+```
+
+Spent {{budget.transactions}} times, `_budget.balances["expenses"]_`.
+
+```{t: Ledger}
+2024-01-05 Typo
+  expenses:food   42.50
+  assets:bank    -40
+```
+"#;
+        let options = YamdrOptions {
+            interpolation: Some(InterpolationOptions::default()),
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains("<td>assets:bank</td><td>-842.50</td>"));
+        assert!(html.contains("Spent 2 times"));
+        assert!(html.contains("// &gt; 842.5"));
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::CustomBlockRead(err)] if err.contains("off by 2.50")
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert_eq!(md, render_markdown(&options, &md).1);
+    }
+}
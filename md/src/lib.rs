@@ -1,20 +1,144 @@
+mod abbreviation_block;
+mod autolink;
+mod badge_block;
+mod block_types;
+mod cache;
+mod capture_block;
+mod cards_block;
+mod cargo_deps_block;
+mod checklist;
 mod code_block;
+mod conditional_block;
+mod defaults_block;
+mod diff;
+mod directives;
+mod elements;
+mod encryption;
 mod errors;
+mod feed;
+mod footnote;
+mod foreach_block;
+mod fragment_block;
+mod frontmatter;
 mod graph_block;
+mod header_schema;
+mod history_block;
 mod html;
+mod html_block;
+mod i18n;
+mod image_block;
+mod import;
+mod interpolation;
+mod issue_refs;
+mod kbd_block;
+mod ledger_block;
+mod licenses_block;
+mod limits;
+mod log_block;
+mod math_block;
+mod mathml;
 mod md;
+mod merge;
+mod mermaid_block;
+mod metrics_block;
+mod numbering;
+mod openapi_block;
 mod plotters_block;
+mod progress_block;
+mod rating_block;
+mod read_aloud;
+mod redact_block;
+mod report_block;
+mod rustdoc_block;
+mod schema_block;
 mod script_block;
+mod search;
+mod seo;
+mod sequence_block;
+mod signature;
+mod sql_block;
+mod stats;
+mod summary_block;
+mod svg_minify;
+mod svg_size;
+mod todo;
+mod translation;
+mod typography;
 mod utils;
+mod video_block;
+mod wiki_links;
 
+use abbreviation_block::AbbreviationsBlock;
+use autolink::AutolinkEvents;
+use badge_block::BadgeBlockReader;
+pub use cache::{render_blocks_cached, RenderCache};
+use capture_block::{CaptureBlock, Captures, EmitBlock};
+use cards_block::CardsBlockReader;
+use cargo_deps_block::CargoDepsBlockReader;
+use checklist::ChecklistSummaryBlock;
+pub use checklist::{Checklist, ChecklistSection};
 use code_block::CodeBlockReader;
+pub use code_block::RUST_CHECK_COMMAND;
+use conditional_block::ConditionalBlock;
+use defaults_block::{Defaults, DefaultsBlock};
+pub use diff::{block_summary, diff_blocks, diff_html, BlockChange};
+use directives::{Directives, RawRegions};
+pub use encryption::EncryptionOptions;
 pub use errors::*;
+pub use feed::{atom_feed, feed_entry, FeedEntry};
+use footnote::InlineFootnotes;
+use foreach_block::ForEachBlock;
+use fragment_block::FragmentBlock;
+use frontmatter::Frontmatter;
 use graph_block::GraphBlockReader;
+pub use header_schema::{FieldType, HeaderField};
+use history_block::HistoryBlockReader;
+use html_block::HtmlBlockReader;
+pub use i18n::{DateOrder, Direction, Labels, Locale, NumberFormat};
+use image_block::ImageBlockReader;
+pub use import::{import_html, import_notebook, import_org, import_rst};
+pub use interpolation::InterpolationOptions;
+use issue_refs::IssueRefEvents;
+use kbd_block::KbdBlockReader;
+use licenses_block::LicensesBlockReader;
+pub use limits::Limits;
+use log_block::LogBlockReader;
+use math_block::{MathBlockReader, MathSpans};
+pub use merge::{merge_documents, MergedDocument};
+use mermaid_block::MermaidBlockReader;
+pub use mermaid_block::MERMAID_COMMAND;
+use metrics_block::MetricsBlockReader;
+use openapi_block::OpenApiBlockReader;
+pub use plotters_block::ChartTheme;
 use plotters_block::PlottersBlockReader;
-use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use progress_block::ProgressBlockReader;
+use pulldown_cmark::{Alignment, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use rating_block::RatingBlockReader;
+pub use read_aloud::{read_aloud, SpeechFormat};
+use redact_block::RedactBlock;
+use report_block::ReportBlockReader;
+use rustdoc_block::RustDocBlockReader;
+use schema_block::SchemaBlockReader;
 use script_block::ScriptBlockReader;
+pub use search::{search_entries, search_widget, SearchEntry};
+use sequence_block::SequenceBlockReader;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+pub use signature::{verify, SignatureOptions};
+use sql_block::SqlBlockReader;
+pub use stats::{BlockStats, DocumentStats, SectionStats, TextStats};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+pub use todo::Todo;
+use todo::{split_todo, TodoBlock};
+pub use translation::{
+    apply_translations, extract_translations, TranslatedDocument, TranslationFormat,
+};
+pub use typography::Typography;
+use typography::TypographyEvents;
+use video_block::VideoBlockReader;
+use wiki_links::WikiLinkEvents;
+pub use wiki_links::WikiLinks;
 
 /// Trait that represents a reader/processor for one or more types
 /// of custom blocks. Multiple readers may be able to process the same
@@ -28,6 +152,19 @@ pub trait CustomBlockReader {
         false
     }
 
+    /// The block types the reader reads, used to suggest a type when a
+    /// block has a type that no reader can read.
+    fn block_types(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Whether a block with `header` is read even if it has no content,
+    /// like an image with only a `src`. Other blocks without content are
+    /// left out.
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        false
+    }
+
     /// Process a block and return a CustomBlock.
     ///
     /// TODO: Option should be removed to always support "rerendering".
@@ -52,6 +189,48 @@ pub trait CustomBlockReader {
     fn read_inline(&mut self, _inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
         unimplemented!()
     }
+
+    /// Whether the reader runs scripts, in which case it isn't used in
+    /// regions where scripts are disabled with `<!-- yamdr:disable-scripts -->`.
+    fn runs_scripts(&self) -> bool {
+        false
+    }
+
+    /// Value of a variable, like `name` or `author.name`, from the state of
+    /// the reader, used for `{{variable}}` interpolation in text.
+    fn variable(&self, _path: &str) -> Option<String> {
+        None
+    }
+
+    /// Rows of the data set called `name`, like a `Data` block read by the
+    /// reader, used by `ForEach` blocks.
+    fn data(&self, _name: &str) -> Option<Vec<BTreeMap<String, String>>> {
+        None
+    }
+
+    /// Data read or generated by the reader, by name, to be compared with
+    /// in the next render, see `Meta::data`.
+    fn exported_data(&self) -> BTreeMap<String, serde_json::Value> {
+        BTreeMap::new()
+    }
+
+    /// Called with the fields of the frontmatter of the document, empty if
+    /// it doesn't have one, before any block is read.
+    fn frontmatter(&mut self, _fields: &serde_yaml::Mapping) {}
+
+    /// Local files that a block with `header` reads or links to, like
+    /// images, relative to `YamdrOptions::base_dir`, see `dependencies`.
+    fn files(&self, _header: &CustomBlockHeader) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The fields that a block with `header` reads from its header, besides
+    /// `t` and `id`. Unknown and mistyped fields are reported as errors
+    /// that don't stop the document from being rendered. Blocks without a
+    /// schema aren't checked.
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        None
+    }
 }
 
 /// Trait that represents a custom block that "extends" normal markdown
@@ -62,14 +241,22 @@ pub trait CustomBlockReader {
 /// again, should produce the same result. This is to ensure that markdown
 /// to markdown rendering can be used as a formatter for yamdr documents,
 /// without changing the semantics of the document.
-pub trait CustomBlock {
+pub trait CustomBlock: Send + Sync {
     /// Render the block as a list of `pulldown_cmark::Event`s.
     ///
     /// Takes a `Format`, as implementations may differ when rendering to
     /// markdown or HTML. The `Vec` may be empty if nothing should be
     /// rendered, but this should only be for `Format::Html` (because of
-    /// rerendering).
-    fn to_events(&self, format: Format) -> Vec<Event<'static>>;
+    /// rerendering). The events may borrow from the block, so content that
+    /// is stored in the block doesn't have to be copied.
+    fn to_events(&self, format: Format) -> Vec<Event<'_>>;
+
+    /// Whether rendering the block as html is expensive, like laying out
+    /// graphs or drawing charts. Expensive blocks can be rendered later, see
+    /// `YamdrOptions::defer_expensive_blocks`.
+    fn is_expensive(&self) -> bool {
+        false
+    }
 
     /// This is a utility function that is used with
     /// `utils::custom_block_downcast` for easier testing.
@@ -86,25 +273,98 @@ pub enum ExtendedEvent<'a> {
     Separator(u16),
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Format {
     Html,
     Md,
 }
 
+/// The events an `ExtendedEvent` is transformed to. Standard events are
+/// passed through without allocating, see `borrow_event`.
+enum TransformedEvents<'a> {
+    Standard(Option<Event<'a>>),
+    Custom(std::vec::IntoIter<Event<'a>>),
+}
+
+impl<'a> Iterator for TransformedEvents<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        match self {
+            TransformedEvents::Standard(event) => event.take(),
+            TransformedEvents::Custom(events) => events.next(),
+        }
+    }
+}
+
+/// `event` with its strings borrowed from it instead of cloned, tags of
+/// tables and headings with classes still copy their small vectors.
+fn borrow_event<'a>(event: &'a Event<'a>) -> Event<'a> {
+    fn borrow<'a>(s: &'a CowStr<'a>) -> CowStr<'a> {
+        CowStr::Borrowed(s)
+    }
+    fn borrow_tag<'a>(tag: &'a Tag<'a>) -> Tag<'a> {
+        match tag {
+            Tag::CodeBlock(CodeBlockKind::Fenced(info)) => {
+                Tag::CodeBlock(CodeBlockKind::Fenced(borrow(info)))
+            }
+            Tag::FootnoteDefinition(label) => Tag::FootnoteDefinition(borrow(label)),
+            Tag::Link(t, url, title) => Tag::Link(*t, borrow(url), borrow(title)),
+            Tag::Image(t, url, title) => Tag::Image(*t, borrow(url), borrow(title)),
+            tag => tag.clone(),
+        }
+    }
+    match event {
+        Event::Start(tag) => Event::Start(borrow_tag(tag)),
+        Event::End(tag) => Event::End(borrow_tag(tag)),
+        Event::Text(s) => Event::Text(borrow(s)),
+        Event::Code(s) => Event::Code(borrow(s)),
+        Event::Html(s) => Event::Html(borrow(s)),
+        Event::FootnoteReference(s) => Event::FootnoteReference(borrow(s)),
+        event => event.clone(),
+    }
+}
+
 impl Format {
-    fn transform_extended_event<'a>(self, ee: &'a ExtendedEvent<'a>) -> Vec<Event<'a>> {
+    fn transform_extended_event<'a>(self, ee: &'a ExtendedEvent<'a>) -> TransformedEvents<'a> {
         match ee {
-            ExtendedEvent::Standard(e) => vec![e.clone()],
-            ExtendedEvent::Custom(c) => c.to_events(self),
-            ExtendedEvent::Separator(_) => vec![],
-            ExtendedEvent::External(_) => todo!(),
+            ExtendedEvent::Standard(e) => TransformedEvents::Standard(Some(borrow_event(e))),
+            ExtendedEvent::Custom(c) => TransformedEvents::Custom(c.to_events(self).into_iter()),
+            ExtendedEvent::Separator(_) => TransformedEvents::Standard(None),
+            ExtendedEvent::External(external) => match self {
+                Format::Html => TransformedEvents::Standard(None),
+                Format::Md => TransformedEvents::Custom(external.to_events().into_iter()),
+            },
+        }
+    }
+    /// Transform each of `ees`, see `transform_extended_event`. Custom blocks
+    /// are independent of each other once they are read (scripts are run
+    /// in order while parsing), so with the `parallel` feature they are
+    /// rendered in parallel.
+    fn transform_extended_events<'a>(
+        self,
+        ees: &'a [ExtendedEvent<'a>],
+    ) -> Vec<TransformedEvents<'a>> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            ees.par_iter()
+                .with_min_len(64)
+                .map(|ee| self.transform_extended_event(ee))
+                .collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            ees.iter()
+                .map(|ee| self.transform_extended_event(ee))
+                .collect()
         }
     }
-    fn render<'a>(self, events: impl Iterator<Item = Event<'a>>) -> String {
+    /// Render events, preallocating `capacity` bytes for the output.
+    fn render<'a>(self, events: impl Iterator<Item = Event<'a>>, capacity: usize) -> String {
         match self {
-            Format::Html => html::render(events),
-            Format::Md => md::render(events),
+            Format::Html => html::render(events, capacity),
+            Format::Md => md::render(events, capacity),
         }
     }
 }
@@ -146,10 +406,266 @@ pub static STYLE: &str = r#"
     }
     .script-output {
     }
+    div.deferred {
+      min-height: 200px;
+      background-color: #f4f4f4;
+    }
     .error {
         background-color: red;
         padding: 10px;
     }
+    .todo {
+      background-color: #fff3b0;
+    }
+    div.todo {
+      border-left: 4px solid #e0b000;
+      padding: 0px 10px;
+    }
+    .cards {
+      display: grid;
+      grid-template-columns: repeat(auto-fill, minmax(240px, 1fr));
+      gap: 1em;
+    }
+    .card {
+      border: 1px solid #dcdcdc;
+      border-radius: 4px;
+      padding: 0px 1em;
+      overflow: hidden;
+    }
+    .card > img {
+      display: block;
+      width: calc(100% + 2em);
+      margin: 0px -1em;
+      object-fit: cover;
+    }
+    kbd.shortcut {
+      white-space: nowrap;
+    }
+    kbd.shortcut > kbd {
+      font-family: monospace;
+      font-size: 0.85em;
+      padding: 1px 5px;
+      border: 1px solid #b4b4b4;
+      border-bottom-width: 2px;
+      border-radius: 4px;
+      background-color: #f4f4f4;
+    }
+    span.badge {
+      display: inline-block;
+      padding: 0px 8px;
+      border-radius: 1em;
+      font-size: 0.75em;
+      font-weight: bold;
+      line-height: 1.6;
+      vertical-align: middle;
+      white-space: nowrap;
+      color: white;
+    }
+    span.badge-green {
+      background-color: #2da44e;
+    }
+    span.badge-yellow {
+      background-color: #d4a72c;
+    }
+    span.badge-orange {
+      background-color: #e16f24;
+    }
+    span.badge-red {
+      background-color: #cf222e;
+    }
+    span.badge-blue {
+      background-color: #0969da;
+    }
+    span.badge-gray {
+      background-color: #6e7781;
+    }
+    div.progress {
+      display: flex;
+      align-items: center;
+      gap: 0.5em;
+      margin: 1em 0px;
+    }
+    div.progress > progress {
+      flex-grow: 1;
+    }
+    .progress-value {
+      font-size: 0.9em;
+      min-width: 3em;
+      text-align: right;
+    }
+    div.checklist-summary > div.progress:not(:first-child) {
+      margin: 0.25em 0px 0.25em 1em;
+    }
+    div.block-timing {
+      float: right;
+      font-family: monospace;
+      font-size: 0.75em;
+      color: #6e7781;
+    }
+    pre.code-check {
+      white-space: pre-wrap;
+      padding: 10px;
+      border-left: 4px solid #cf222e;
+      background-color: #fff0f0;
+      font-size: 0.85em;
+    }
+    span.heading-number {
+      color: #6e7781;
+    }
+    svg.stars {
+      vertical-align: text-bottom;
+    }
+    iframe.html-block {
+      display: block;
+      max-width: 100%;
+      margin: 1em auto;
+      border: 1px solid #dcdcdc;
+    }
+    div.table-scroll {
+      max-width: 100%;
+      overflow-x: auto;
+    }
+    input.show-all {
+      position: absolute;
+      opacity: 0;
+    }
+    input.show-all:not(:checked) ~ div.table-scroll table:not(.filtered) tr.more,
+    input.show-all:checked ~ label.show-all {
+      display: none;
+    }
+    label.show-all {
+      cursor: pointer;
+      text-decoration: underline;
+    }
+    input.show-all:focus-visible ~ label.show-all {
+      outline: 2px solid;
+    }
+    input.table-filter {
+      display: block;
+      margin: 1em 0 0.5em;
+      padding: 0.25em 0.5em;
+      font: inherit;
+    }
+    table[data-interactive] th button {
+      border: none;
+      background: none;
+      padding: 0;
+      font: inherit;
+      color: inherit;
+      cursor: pointer;
+    }
+    table[data-interactive] th[aria-sort="ascending"] button::after {
+      content: " ▲";
+    }
+    table[data-interactive] th[aria-sort="descending"] button::after {
+      content: " ▼";
+    }
+    table[data-interactive] tr[hidden] {
+      display: none;
+    }
+    table.paged tbody:not(:first-of-type):not(:target),
+    table.paged:has(tbody:target) tbody:not(:target) {
+      display: none;
+    }
+    nav.table-pages {
+      display: flex;
+      flex-wrap: wrap;
+      gap: 0.5em;
+      align-items: center;
+    }
+    @media (max-width: 600px) {
+      table.stacked thead {
+        position: absolute;
+        width: 1px;
+        height: 1px;
+        overflow: hidden;
+        clip-path: inset(50%);
+      }
+      table.stacked tr,
+      table.stacked td {
+        display: block;
+      }
+      table.stacked tr {
+        margin-bottom: 1em;
+      }
+      table.stacked td::before {
+        content: attr(data-label);
+        font-weight: bold;
+        margin-inline-end: 1em;
+      }
+    }
+    table.chart-data {
+      position: absolute;
+      width: 1px;
+      height: 1px;
+      overflow: hidden;
+      clip-path: inset(50%);
+      white-space: nowrap;
+    }
+    p.redacted {
+      color: #6a6a6a;
+      font-style: italic;
+    }
+    div.test-report {
+      border-inline-start: 4px solid #2e7d32;
+      padding-inline-start: 1em;
+    }
+    div.test-report.failed {
+      border-color: #c62828;
+    }
+    pre.log .log-line::before {
+      content: attr(data-line);
+      display: inline-block;
+      min-width: 3em;
+      margin-inline-end: 1em;
+      text-align: end;
+      color: #6a6a6a;
+    }
+    pre.log time {
+      color: #1565c0;
+    }
+    div.test-report pre {
+      white-space: pre-wrap;
+    }
+    section.licenses ul {
+      columns: 2;
+    }
+    section.licenses li {
+      break-inside: avoid;
+    }
+    video.video {
+      display: block;
+      max-width: 100%;
+      margin: 1em auto;
+    }
+    figure.image {
+      margin: 1em auto;
+      max-width: 100%;
+    }
+    figure.image > img {
+      width: 100%;
+    }
+    figure.image > figcaption {
+      text-align: center;
+      font-size: 0.9em;
+    }
+    section.footnotes {
+      margin-top: 2em;
+      font-size: 0.9em;
+    }
+    form.encrypted {
+      margin: 4em 0;
+      text-align: center;
+    }
+    form.encrypted > p.error {
+      color: #c0392b;
+    }
+    footer.signature {
+      margin-top: 2em;
+      font-size: 0.75em;
+      color: gray;
+      overflow-wrap: anywhere;
+    }
 
 
     pre {
@@ -158,6 +674,17 @@ pub static STYLE: &str = r#"
       font-size: 0.85em;
       padding: 5px 0px;
     }
+    pre.mermaid {
+      text-align: center;
+      font-size: 1em;
+    }
+    div.math {
+      overflow-x: auto;
+      margin: 1em 0px;
+    }
+    code.math-error {
+      color: #b00020;
+    }
     pre.codeblock {
       white-space: pre;
       padding: 10px 0px;
@@ -181,199 +708,1369 @@ pub static STYLE: &str = r#"
       margin-bottom: 2px;
       font-size: 1em;
     }
-    code.numbered > span::before {
-      content: attr(data-linenumber);
+    code.numbered > span.line > span.linenumber {
       text-align: right;
       color: var(--codeblock-linenumber);
       min-width: 3em;
       display: inline-block;
+      user-select: none;
     }
 "#;
 
-#[derive(Clone)]
-pub struct StandaloneOptions {}
+#[derive(Clone, Default)]
+pub struct StandaloneOptions {
+    /// Embed a hash of the source in a footer, see `verify`.
+    pub signature: Option<SignatureOptions>,
+    /// Encrypt the content with a passphrase, so it is only shown when the
+    /// passphrase is entered. The meta tags, like the description, are left
+    /// out, as they could reveal the content.
+    pub encryption: Option<EncryptionOptions>,
+    /// Html page to render the document into, instead of the built in one.
+    /// The placeholders `{{style}}`, `{{head}}`, `{{body}}`, `{{content}}`,
+    /// `{{title}}`, `{{lang}}` and `{{dir}}` are replaced, as well as
+    /// `{{meta.field}}` with fields of the meta blocks. The title is the
+    /// `title` field of the meta blocks, or the first heading.
+    /// `{{meta_tags}}` is replaced with the `<title>`, description, Open
+    /// Graph and Twitter card tags the built in page has in its head.
+    pub template: Option<String>,
+}
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct YamdrOptions {
     pub standalone: Option<StandaloneOptions>,
     pub additional_head: Option<String>,
     pub additional_body: Option<String>,
     pub format: Option<Format>,
+    /// Prefix prepended to relative link and image urls in html output,
+    /// useful when assets are served from somewhere else than the document.
+    pub asset_base: Option<String>,
+    /// New file names of assets that are renamed in the output, like to
+    /// names with a hash of their content, by their path resolved from
+    /// `base_dir`. Relative link and image urls pointing to them are
+    /// changed to the new name in html output.
+    pub asset_names: BTreeMap<PathBuf, String>,
+    /// Language, text direction and generated labels. `lang` and `dir` can
+    /// be overridden by the document in a meta block.
+    pub locale: Locale,
+    /// Insert the estimated reading time below the first heading in html
+    /// output.
+    pub reading_time: bool,
+    /// Leave expensive custom blocks, like graphs and charts, out of html
+    /// output, and emit an empty placeholder for each of them instead. The
+    /// blocks are returned in `Meta::deferred`, to be rendered separately.
+    pub defer_expensive_blocks: bool,
+    /// Limits on the size of the document and its output.
+    pub limits: Limits,
+    /// Make the output only depend on the input, for snapshot testing.
+    /// Time is frozen in scripts, so `timestamp()` always returns the same
+    /// time, and no time elapses.
+    pub deterministic: bool,
+    /// Only parse plain CommonMark, without extensions like tables and
+    /// footnotes. Fenced code blocks are only read as custom blocks if the
+    /// info string is a `{...}` header, and inline code is never read as a
+    /// custom block, so yamdr can be used as a predictable markdown formatter.
+    pub strict: bool,
+    /// Replace `{{variable}}`s in text with values from meta blocks and the
+    /// script scope in html output.
+    pub interpolation: Option<InterpolationOptions>,
+    /// Variables set from outside the document, like `--var` on the command
+    /// line. They are available to `If` conditions and interpolation, and
+    /// are overridden by the fields of meta blocks. Scripts get them as
+    /// strings in the `vars` map, like `vars.customer`.
+    pub variables: BTreeMap<String, String>,
+    /// Audiences the document is rendered for, like `internal`, see
+    /// `Redact` blocks. Blocks for other audiences are left out of html
+    /// output.
+    pub audiences: Vec<String>,
+    /// `Meta::data` of a previous render, available to scripts as
+    /// `previous`, like `previous.sales`, so reports can compare with the
+    /// last run. Without it, `previous` is `()`.
+    pub previous: Option<BTreeMap<String, serde_json::Value>>,
+    /// Leave `Todo` blocks and `TODO:`s in text out of html output, for the
+    /// final version of a document. They are still listed in `Meta::todos`.
+    pub hide_todos: bool,
+    /// Path of the document being rendered, used to read its history from
+    /// git for History blocks, with the `git` feature.
+    pub path: Option<PathBuf>,
+    /// Directory relative urls in the document are resolved from, used to
+    /// check that files referenced by blocks, like videos, exist.
+    pub base_dir: Option<PathBuf>,
+    /// SQLite databases that Sql blocks are allowed to query, with the
+    /// `sqlite` feature. The `db` of a block is resolved from `base_dir`.
+    pub sql_databases: Vec<PathBuf>,
+    /// Urls that Metrics blocks are allowed to scrape, with the `metrics`
+    /// feature, like `http://localhost:9090/`. A url is allowed if it has
+    /// the same scheme, host and port as one of them, and its path is
+    /// below the path of it.
+    pub metrics_urls: Vec<String>,
+    /// Measure how long each top level element takes to read and render,
+    /// see `Meta::timings`, and show the duration above the element in
+    /// html output.
+    pub timing: bool,
+    /// Prefix headings with hierarchical numbers, like `1.`, `1.1` and
+    /// `1.1.1`, in html output. The numbers are also in
+    /// `SectionStats::number`. Can be overridden by the document with
+    /// `number_headings` in a meta block.
+    pub number_headings: bool,
+    /// Render `[[Page Name]]` and `![[image.png]]` as links and images in
+    /// html output, with urls from the resolver. Links to targets that
+    /// can't be resolved are reported in `Meta::errors`.
+    pub wiki_links: Option<WikiLinks>,
+    /// Turn bare urls, like `https://example.com` and `www.example.com`,
+    /// and email addresses in text into links in html output. Rendering to
+    /// markdown keeps the text as it is.
+    pub autolink: bool,
+    /// Typographic replacements, like `(c)` with `©` and `--` with `–`, in
+    /// the text of html output. Can be turned on, with the default
+    /// replacements, or off by the document with `typography` in a meta
+    /// block.
+    pub typography: Option<Typography>,
+    /// Size and colors of the charts of Plotters, DynamicChart and Metrics
+    /// blocks, unless set in the header of the block.
+    pub chart_theme: ChartTheme,
+    /// Fetch the titles of the issues and pull requests that documents
+    /// with a `repository` reference, like `#1234`, from GitHub, with the
+    /// `issues` feature, see `IssueRefEvents`.
+    pub issue_titles: bool,
+    /// Commands that `Code` blocks with `check: true` are checked with when
+    /// they are read, by language, like `RUST_CHECK_COMMAND` for `rust`.
+    /// The code is written to a file in an empty directory, and the path of
+    /// the file is added as the last argument. If the command fails, what
+    /// it printed is rendered below the code. Code in other languages isn't
+    /// checked.
+    pub code_checks: BTreeMap<String, Vec<String>>,
+    /// Command rendering the diagrams of Mermaid blocks to svg when they are
+    /// read, like `MERMAID_COMMAND`. The diagram is written to its stdin,
+    /// and the svg read from its stdout. Without a command, or if it isn't
+    /// installed, the diagrams are rendered in the browser by mermaid.js,
+    /// with a script included after the first of them.
+    pub mermaid_command: Vec<String>,
+    /// Make the svgs of graphs and charts smaller in html output, by
+    /// removing what isn't rendered, rounding coordinates and moving
+    /// repeated styles to classes.
+    pub minify_svg: bool,
+    /// Make the tables of `DynamicTable` and `Data` blocks sortable and
+    /// filterable in html output, with a small script included after the
+    /// first of them. Blocks can turn it on or off with `interactive` in
+    /// their header.
+    pub interactive_tables: bool,
+}
+
+pub struct Meta {
+    /// Word counts and reading time of the document, its blocks and sections.
+    /// Empty when rendering to markdown.
+    pub stats: DocumentStats,
+    /// Blocks left out of the output, see `YamdrOptions::defer_expensive_blocks`.
+    pub deferred: Vec<DeferredBlock>,
+    /// Errors in the document that didn't stop it from being rendered, like
+    /// undefined variables with strict interpolation.
+    pub errors: Vec<Error>,
+    /// `Todo` blocks and `TODO:`s in text, in the order they are written.
+    pub todos: Vec<Todo>,
+    /// Progress of the task-list items of the document, in total and by
+    /// section.
+    pub checklist: Checklist,
+    /// Custom blocks with an `id` in their header, by id, see
+    /// `BlockAnchor`.
+    pub anchors: BTreeMap<String, BlockAnchor>,
+    /// How long each top level element took, with `YamdrOptions::timing`.
+    pub timings: Vec<BlockTiming>,
+    /// Rows of the `Data` blocks by name, the output of `DynamicTable` and
+    /// `DynamicChart` blocks with an `id` by id, and of `DynamicTable`
+    /// blocks without one as `table-1`, `table-2` and so on, and values
+    /// exported by scripts with `export_data(name, value)`. Can be saved,
+    /// and given to scripts of the next render as `YamdrOptions::previous`.
+    pub data: BTreeMap<String, serde_json::Value>,
+    /// Fields of the YAML frontmatter at the start of the document, empty
+    /// if it doesn't have one. The fields are also read like the fields of
+    /// meta blocks, for the title and page template.
+    pub frontmatter: serde_yaml::Mapping,
+    /// Plain text of the top level blocks, for `search_entries`.
+    pub(crate) texts: Vec<search::BlockText>,
+}
+
+/// How long a top level element took to read, including running its
+/// scripts, and to render. Deferred blocks are only timed while read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTiming {
+    /// Id of the top level element, see `MarkdownBlock::id`.
+    pub block: u16,
+    /// Types of the custom blocks in the element, like `DynamicChart`.
+    pub custom_blocks: Vec<String>,
+    pub duration: std::time::Duration,
+}
+
+/// A custom block with an `id`, like `{t: Graph, id: my-chart}`. In html,
+/// the block is wrapped in an element with the id, so `#my-chart` links to
+/// it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockAnchor {
+    /// Id of the top level element the block is in, see `MarkdownBlock::id`.
+    pub block: u16,
+    /// Type of the block, like `Graph`.
+    pub t: String,
+}
+
+/// What was found while processing a document, besides the events, for
+/// `Meta`.
+struct Diagnostics {
+    errors: Vec<Error>,
+    todos: Vec<Todo>,
+    checklist: Checklist,
+    anchors: BTreeMap<String, BlockAnchor>,
+    timings: Vec<BlockTiming>,
+    data: BTreeMap<String, serde_json::Value>,
+}
+
+/// A custom block that was left out of the output of `render_markdown`, and
+/// replaced by a placeholder element with a `data-deferred` attribute set
+/// to `id`.
+pub struct DeferredBlock {
+    pub id: usize,
+    block: Box<dyn CustomBlock>,
+    max_size: usize,
+    too_large: String,
+    minify_svg: bool,
 }
 
-pub struct Meta {}
+impl DeferredBlock {
+    fn placeholder(id: usize) -> String {
+        format!(r#"<div class="deferred" data-deferred="{}"></div>"#, id)
+    }
+
+    /// Render the block as html, to replace the placeholder with.
+    pub fn render(&self) -> String {
+        let events = limits::limit_block_size(
+            minify_svgs(self.block.to_events(Format::Html), self.minify_svg),
+            self.max_size,
+            &self.too_large,
+        );
+        Format::Html.render(events.into_iter(), 0)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CustomBlockHeader {
     pub t: String,
 
     #[serde(flatten)]
-    pub fields: HashMap<String, serde_yaml::Value>,
+    pub fields: BTreeMap<String, serde_yaml::Value>,
+
+    /// Number of custom blocks before this one in the document, set when
+    /// the block is read, so ids in its output can be unique.
+    #[serde(skip)]
+    pub index: usize,
 }
 
 impl CustomBlockHeader {
     pub fn empty(t: String) -> Self {
         CustomBlockHeader {
             t,
-            fields: HashMap::new(),
+            fields: BTreeMap::new(),
+            index: 0,
         }
     }
 }
 
-fn parse_markdown(markdown: &str) -> Vec<ExtendedEvent> {
-    let md_options = Options::all();
+/// Processes `pulldown_cmark::Event`s, and turns custom blocks and inline
+/// code into `ExtendedEvent::Custom`s using the registered readers. The
+/// readers keep their state (like the script scope) between events, so a
+/// document should be processed in order by the same processor.
+struct EventProcessor {
+    readers: Vec<Box<dyn CustomBlockReader>>,
+    current_custom_block: Option<CustomBlockHeader>,
+    current_custom_block_read: bool,
+    strict: bool,
+    directives: Directives,
+    interpolation: Option<InterpolationOptions>,
+    /// Fields of the meta blocks read so far, used as variables.
+    variables: BTreeMap<String, serde_yaml::Value>,
+    in_code_block: bool,
+    format: Format,
+    /// Fragments registered by `Define` blocks so far.
+    fragments: BTreeMap<String, String>,
+    /// Abbreviations and their expansions from `Abbreviations` blocks so far.
+    abbreviations: BTreeMap<String, String>,
+    /// Html of `Capture` blocks, shared with `Emit` blocks.
+    captures: Captures,
+    /// Names of the captures of `Emit` blocks so far, checked when the
+    /// whole document is read.
+    emitted: Vec<String>,
+    /// Default header fields of each block type from `Defaults` blocks so
+    /// far.
+    defaults: Defaults,
+    footnotes: InlineFootnotes,
+    errors: Vec<Error>,
+    hide_todos: bool,
+    todos: Vec<Todo>,
+    /// Task-list items so far, shared with `ChecklistSummary` blocks.
+    checklist: Arc<RwLock<Checklist>>,
+    /// Blocks with an `id` so far, and the top level element being read.
+    anchors: BTreeMap<String, BlockAnchor>,
+    element: u16,
+    /// With `YamdrOptions::timing`, when the current top level element was
+    /// started, and the elements read so far.
+    element_timing: Option<(std::time::Instant, Vec<String>)>,
+    timings: Option<Vec<BlockTiming>>,
+    /// Text of the heading being read, if any, and of the last heading, the
+    /// section TODOs are in.
+    heading: Option<String>,
+    section: Option<String>,
+    /// Nesting level in the last TODO, if it is inline and its text goes on
+    /// until the end of the paragraph or other element it's in.
+    todo_level: Option<usize>,
+    /// Audiences the document is rendered for, and the label of the notice
+    /// of `Redact` blocks that are left out.
+    audiences: Vec<String>,
+    redacted: String,
+    /// Options of the markdown parser for the document, also used for the
+    /// bodies of blocks, see `render_body`.
+    markdown_options: Options,
+    /// Number of block bodies being rendered, limited to `MAX_BODY_DEPTH`.
+    body_depth: usize,
+    /// Number of custom blocks read so far, see `CustomBlockHeader::index`.
+    custom_blocks: usize,
+}
 
-    let mut readers: Vec<Box<dyn CustomBlockReader>> = vec![
-        Box::new(ScriptBlockReader::initial_state()),
-        Box::new(CodeBlockReader::initial_state()),
-        Box::new(PlottersBlockReader::initial_state()),
-        Box::new(GraphBlockReader::initial_state()),
-    ];
+/// How deep block bodies can be nested, so a fragment that uses itself
+/// doesn't recurse forever.
+const MAX_BODY_DEPTH: usize = 16;
 
-    let mut current_custom_block: Option<CustomBlockHeader> = None;
+impl EventProcessor {
+    fn new(options: &YamdrOptions) -> Self {
+        EventProcessor {
+            readers: vec![
+                Box::new(ScriptBlockReader::new(options)),
+                Box::new(CodeBlockReader::new(options)),
+                Box::new(PlottersBlockReader::new(options)),
+                Box::new(GraphBlockReader::initial_state()),
+                Box::new(MermaidBlockReader::new(options)),
+                Box::new(SequenceBlockReader::initial_state()),
+                Box::new(HistoryBlockReader::new(options)),
+                Box::new(CardsBlockReader::initial_state()),
+                Box::new(ImageBlockReader::new(options)),
+                Box::new(VideoBlockReader::new(options)),
+                Box::new(HtmlBlockReader::initial_state()),
+                Box::new(OpenApiBlockReader::new(options)),
+                Box::new(SchemaBlockReader::new(options)),
+                Box::new(RustDocBlockReader::new(options)),
+                Box::new(SqlBlockReader::new(options)),
+                Box::new(KbdBlockReader::initial_state()),
+                Box::new(MathBlockReader::initial_state()),
+                Box::new(BadgeBlockReader::initial_state()),
+                Box::new(ProgressBlockReader::initial_state()),
+                Box::new(RatingBlockReader::new(options)),
+                Box::new(ReportBlockReader::new(options)),
+                Box::new(MetricsBlockReader::new(options)),
+                Box::new(LogBlockReader::new(options)),
+                Box::new(CargoDepsBlockReader::new(options)),
+                Box::new(LicensesBlockReader::new(options)),
+            ],
+            current_custom_block: None,
+            current_custom_block_read: false,
+            strict: options.strict,
+            directives: Directives::default(),
+            interpolation: options.interpolation.clone(),
+            variables: options
+                .variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.as_str().into()))
+                .collect(),
+            in_code_block: false,
+            format: options.format.unwrap_or(Format::Html),
+            fragments: BTreeMap::new(),
+            abbreviations: BTreeMap::new(),
+            captures: Captures::default(),
+            emitted: Vec::new(),
+            defaults: Defaults::new(),
+            footnotes: InlineFootnotes::default(),
+            errors: Vec::new(),
+            hide_todos: options.hide_todos,
+            todos: Vec::new(),
+            checklist: Default::default(),
+            anchors: BTreeMap::new(),
+            element: 0,
+            element_timing: None,
+            timings: options.timing.then(Vec::new),
+            heading: None,
+            section: None,
+            todo_level: None,
+            audiences: options.audiences.clone(),
+            redacted: options.locale.labels.redacted.clone(),
+            markdown_options: document_options(options),
+            body_depth: 0,
+            custom_blocks: 0,
+        }
+    }
 
-    let mut level = 0;
-    let mut element_i = 0;
+    /// Make the fields of the frontmatter of the document available as
+    /// variables and to the readers, before any block is read.
+    fn frontmatter(&mut self, fields: &serde_yaml::Mapping) {
+        self.variables.extend(frontmatter::string_fields(fields));
+        for reader in self.readers.iter_mut() {
+            reader.frontmatter(fields);
+        }
+    }
 
-    let parser = Parser::new_ext(markdown, md_options)
-        .flat_map(|event| {
-            match &event {
-                Event::Start(_) => {
-                    level += 1;
-                    if level == 1 {
-                        return vec![
-                            Event::Start(Tag::FootnoteDefinition(
-                                format!("yamdr:{}", element_i).into(),
-                            )),
-                            event,
-                        ];
-                    }
-                }
-                Event::End(_) => {
-                    level -= 1;
-                    if level == 0 {
-                        element_i += 1;
-                        return vec![
-                            event,
-                            Event::End(Tag::FootnoteDefinition(
-                                format!("yamdr:{}", element_i - 1).into(),
-                            )),
-                        ];
-                    }
-                }
-                _ => {}
-            };
-            vec![event]
-        })
-        .flat_map(|event| match &event {
-            Event::Start(Tag::FootnoteDefinition(id)) if id.as_ref().starts_with("yamdr:") => {
-                vec![ExtendedEvent::Separator(str::parse(&id[6..]).unwrap())]
+    fn external<'a>(&mut self, external: ExternalBlock) -> Vec<ExtendedEvent<'a>> {
+        if external.is_meta() {
+            self.variables.extend(external.fields());
+        }
+        vec![ExtendedEvent::External(external)]
+    }
+
+    fn fragment<'a>(&mut self, header: &CustomBlockHeader, body: &str) -> Vec<ExtendedEvent<'a>> {
+        let block = match header.t.as_str() {
+            "Define" => FragmentBlock::define(header, body, &mut self.fragments),
+            _ => FragmentBlock::use_fragment(header, body, &self.fragments, |path| {
+                self.variable(path)
+            }),
+        };
+        let block = block.map(|mut block| {
+            if self.format == Format::Html {
+                block.render(|output| self.render_body(output));
             }
-            Event::End(Tag::FootnoteDefinition(id)) if id.as_ref().starts_with("yamdr:") => {
+            block
+        });
+        self.builtin_events(block)
+    }
+
+    /// The events of a builtin block, or none if it couldn't be read.
+    fn builtin_events<'a>(
+        &mut self,
+        block: std::result::Result<impl CustomBlock + 'static, String>,
+    ) -> Vec<ExtendedEvent<'a>> {
+        match block {
+            Ok(block) => vec![ExtendedEvent::Custom(Box::new(block))],
+            Err(err) => {
+                self.errors.push(Error::CustomBlockRead(err));
                 Vec::new()
             }
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(prop))) => {
-                match serde_yaml::from_str::<CustomBlockHeader>(prop) {
-                    Ok(block) => {
-                        current_custom_block = Some(block);
-                        Vec::new()
-                    }
-                    Err(_) => {
-                        vec![ExtendedEvent::Standard(event)]
-                    }
-                }
-            }
-            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => {
-                if current_custom_block.is_some() {
-                    current_custom_block = None;
-                    Vec::new()
-                } else {
-                    vec![ExtendedEvent::Standard(event)]
-                }
-            }
-            Event::Text(text) if current_custom_block.is_some() => {
-                let custom_block_header = current_custom_block.as_ref().unwrap();
-                if custom_block_header.t == "External" {
-                    return vec![ExtendedEvent::External(ExternalBlock {
-                        body: text.to_string(),
-                        head: custom_block_header.fields.clone(),
-                    })];
-                }
-                match readers
-                    .iter_mut()
-                    .find(|reader| reader.can_read_block(custom_block_header))
-                    .map(|reader| reader.read_block(custom_block_header, text))
-                {
-                    Some(Ok(Some(block))) => {
-                        vec![ExtendedEvent::Custom(block)]
-                    }
-                    Some(Ok(None)) => Vec::new(),
-                    Some(Err(_err)) => {
-                        todo!("error reading block")
-                    }
-                    None => {
-                        todo!("error custom block not implemented")
-                    }
-                }
+        }
+    }
+
+    fn read_external<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let external = ExternalBlock {
+            body: body.to_string(),
+            head: header.fields.clone(),
+        };
+        self.external(external)
+    }
+
+    fn read_if<'a>(&mut self, header: &CustomBlockHeader, body: &str) -> Vec<ExtendedEvent<'a>> {
+        let block = ConditionalBlock::read(header, body, self.format, &self.variables);
+        let block = block.map(|mut block| {
+            if self.format == Format::Html {
+                block.render(|body| self.render_body(body));
             }
-            Event::Code(code) => {
-                match readers
-                    .iter_mut()
-                    .find(|reader| reader.can_read_inline(code))
-                    .map(|reader| reader.read_inline(code))
-                {
-                    Some(Ok(Some(block))) => {
-                        vec![ExtendedEvent::Custom(block)]
-                    }
-                    Some(Ok(None)) => Vec::new(),
-                    Some(Err(_err)) => {
-                        todo!("error reading inline")
-                    }
-                    None => {
-                        vec![ExtendedEvent::Standard(event)]
-                    }
-                }
+            block
+        });
+        self.builtin_events(block)
+    }
+
+    fn read_redact<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let block = RedactBlock::read(header, body, &self.audiences, &self.redacted);
+        let block = block.map(|mut block| {
+            if self.format == Format::Html {
+                block.render(|body| self.render_body(body));
             }
-            _ => vec![ExtendedEvent::Standard(event)],
+            block
         });
+        self.builtin_events(block)
+    }
 
-    parser.collect()
-}
+    fn read_abbreviations<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let block = AbbreviationsBlock::read(header, body, &mut self.abbreviations);
+        self.builtin_events(block)
+    }
 
-pub fn render_markdown(options: &YamdrOptions, markdown: &str) -> (Meta, String) {
-    let format = options.format.unwrap_or(Format::Html);
+    fn read_capture<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let captures = Arc::clone(&self.captures);
+        let block = CaptureBlock::read(header, body, |path| self.variable(path));
+        let block = block.and_then(|mut block| {
+            block.capture(&captures, |output| match self.format {
+                Format::Html => self.render_body(output),
+                Format::Md => String::new(),
+            })?;
+            Ok(block)
+        });
+        self.builtin_events(block)
+    }
 
-    let parsed_markdown = parse_markdown(markdown);
-    let parser = parsed_markdown
-        .iter()
-        .flat_map(|ee| format.transform_extended_event(ee));
+    fn read_emit<'a>(&mut self, header: &CustomBlockHeader, _body: &str) -> Vec<ExtendedEvent<'a>> {
+        let block = EmitBlock::read(header, &self.captures);
+        if let Ok(block) = &block {
+            self.emitted.push(block.name().into());
+        }
+        self.builtin_events(block)
+    }
 
-    let mut output = format.render(parser);
+    fn read_defaults<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let block = DefaultsBlock::read(header, body, &mut self.defaults);
+        self.builtin_events(block)
+    }
 
-    if format == Format::Html {
-        if options.standalone.is_some() {
-            output = format!(
-                r#"
-<!DOCTYPE html>
-<html>
-    <head>
-        <style>
-            {}
-        </style>
-        {}
-    </head>
-    <body>
-        {}
-        <div class="content">
-            {}
-        </div>
-    </body>
-</html>"#,
-                STYLE,
-                options.additional_head.as_deref().unwrap_or(""),
-                options.additional_body.as_deref().unwrap_or(""),
-                output
+    fn read_checklist_summary<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        _body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let block = ChecklistSummaryBlock::new(header, Arc::clone(&self.checklist));
+        self.builtin_events(Ok(block))
+    }
+
+    fn read_todo<'a>(&mut self, header: &CustomBlockHeader, body: &str) -> Vec<ExtendedEvent<'a>> {
+        let todo = TodoBlock::block(header, body, self.hide_todos);
+        self.todo(todo)
+    }
+
+    fn read_for_each<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        body: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let block = ForEachBlock::read(
+            header,
+            body,
+            |name| self.readers.iter().find_map(|reader| reader.data(name)),
+            |path| self.variable(path),
+        );
+        let block = block.map(|mut block| {
+            if self.format == Format::Html {
+                block.render(|output| self.render_body(output));
+            }
+            block
+        });
+        self.builtin_events(block)
+    }
+
+    /// Render the markdown body of a block, like an `If` block, to html. The
+    /// body is read like the rest of the document, with the same readers and
+    /// script scope, so it can contain custom blocks, inline code, footnotes
+    /// and variables.
+    fn render_body(&mut self, body: &str) -> String {
+        if self.body_depth == MAX_BODY_DEPTH {
+            self.errors.push(Error::CustomBlockRead(
+                "block bodies are nested too deeply".into(),
+            ));
+            return String::new();
+        }
+        // The body is read from inside the fenced block it is in
+        let outer = (
+            self.current_custom_block.take(),
+            std::mem::take(&mut self.current_custom_block_read),
+            std::mem::take(&mut self.in_code_block),
+        );
+        self.body_depth += 1;
+        let parser = Parser::new_ext(body, self.markdown_options).into_offset_iter();
+        let events: Vec<_> = MathSpans::new(parser, body, !self.strict)
+            .flat_map(|(event, _)| self.process(event))
+            .collect();
+        self.body_depth -= 1;
+        (
+            self.current_custom_block,
+            self.current_custom_block_read,
+            self.in_code_block,
+        ) = outer;
+        let events = events
+            .iter()
+            .flat_map(|ee| Format::Html.transform_extended_event(ee));
+        Format::Html.render(events, body.len())
+    }
+
+    /// Value of a variable, from the readers (like the script scope) or the
+    /// meta blocks.
+    fn variable(&self, path: &str) -> Option<String> {
+        self.readers
+            .iter()
+            .find_map(|reader| reader.variable(path))
+            .or_else(|| interpolation::lookup_yaml(&self.variables, path))
+    }
+
+    fn todo<'a>(&mut self, todo: TodoBlock) -> Vec<ExtendedEvent<'a>> {
+        self.todo_level = todo.is_inline().then_some(0);
+        self.todos.push(todo.todo(self.section.clone()));
+        vec![ExtendedEvent::Custom(Box::new(todo))]
+    }
+
+    /// Text outside of code, where `TODO:`s are found and variables are
+    /// replaced.
+    fn text<'a>(&mut self, text: CowStr<'a>) -> Vec<ExtendedEvent<'a>> {
+        let text = match text.strip_suffix('^') {
+            Some(before) if !self.strict && !self.directives.blocks_disabled() => {
+                self.footnotes.caret = true;
+                if before.is_empty() {
+                    return Vec::new();
+                }
+                match text {
+                    CowStr::Borrowed(text) => CowStr::Borrowed(&text[..text.len() - 1]),
+                    _ => before.to_string().into(),
+                }
+            }
+            _ => text,
+        };
+        if let Some(heading) = &mut self.heading {
+            heading.push_str(&text);
+        }
+        let todo = match self.strict || self.directives.blocks_disabled() {
+            true => None,
+            false => split_todo(&text),
+        };
+        let Some((before, todo)) = todo else {
+            return self.interpolate(Event::Text(text));
+        };
+        let todo = TodoBlock::inline(todo, self.hide_todos);
+        let mut events = match before {
+            "" => Vec::new(),
+            before => self.interpolate(Event::Text(before.to_string().into())),
+        };
+        events.extend(self.todo(todo));
+        events
+    }
+
+    fn interpolate<'a>(&mut self, event: Event<'a>) -> Vec<ExtendedEvent<'a>> {
+        let (Some(options), Event::Text(text)) = (&self.interpolation, &event) else {
+            return self.abbreviate(event);
+        };
+        match interpolation::interpolate(text, options.strict, |path| self.variable(path)) {
+            Some(text) => {
+                self.errors.extend(
+                    text.undefined()
+                        .map(|name| Error::UndefinedVariable(name.into())),
+                );
+                vec![ExtendedEvent::Custom(Box::new(text))]
+            }
+            None => self.abbreviate(event),
+        }
+    }
+
+    /// Wrap the abbreviations defined so far in text, see `AbbreviationsBlock`.
+    fn abbreviate<'a>(&self, event: Event<'a>) -> Vec<ExtendedEvent<'a>> {
+        let Event::Text(text) = &event else {
+            return vec![ExtendedEvent::Standard(event)];
+        };
+        match abbreviation_block::abbreviate(text, &self.abbreviations) {
+            Some(text) => vec![ExtendedEvent::Custom(Box::new(text))],
+            None => vec![ExtendedEvent::Standard(event)],
+        }
+    }
+
+    /// Replace an alias of a block type with the type, see `block_types`.
+    fn resolve_alias(&mut self, mut header: CustomBlockHeader) -> CustomBlockHeader {
+        if let Some(alias) = block_types::alias(&header.t) {
+            if alias.deprecated {
+                self.errors
+                    .push(Error::DeprecatedBlockType(header.t.clone(), alias.t.into()));
+            }
+            header.t = alias.t.into();
+        }
+        header
+    }
+
+    /// Read the content of a fenced custom block, wrapped in an element with
+    /// the `id` of the header in html, if it has one.
+    fn custom_block<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        text: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        if let Some((_, custom_blocks)) = &mut self.element_timing {
+            custom_blocks.push(header.t.clone());
+        }
+        let events = self.read_custom_block(header, text);
+        let Some(id) = utils::header_field(header, "id") else {
+            return events;
+        };
+        if id.is_empty() || id.contains(char::is_whitespace) {
+            self.errors.push(Error::CustomBlockRead(format!(
+                "invalid id `{}` of {} block",
+                id, header.t
+            )));
+            return events;
+        }
+        if self.anchors.contains_key(&id) {
+            self.errors.push(Error::CustomBlockRead(format!(
+                "duplicate block id `{}`",
+                id
+            )));
+            return events;
+        }
+        self.anchors.insert(
+            id.clone(),
+            BlockAnchor {
+                block: self.element,
+                t: header.t.clone(),
+            },
+        );
+        match events.as_slice() {
+            [ExtendedEvent::Custom(_)] if self.format == Format::Html => {
+                let mut open = String::from(r#"<div class="block-anchor" id=""#);
+                pulldown_cmark::escape::escape_html(&mut open, &id).unwrap();
+                open += r#"">"#;
+                let mut wrapped = vec![ExtendedEvent::Standard(Event::Html(open.into()))];
+                wrapped.extend(events);
+                wrapped.push(ExtendedEvent::Standard(Event::Html("</div>\n".into())));
+                wrapped
+            }
+            _ => events,
+        }
+    }
+
+    fn read_custom_block<'a>(
+        &mut self,
+        header: &CustomBlockHeader,
+        text: &str,
+    ) -> Vec<ExtendedEvent<'a>> {
+        let builtin = block_types::builtin(&header.t);
+        let schema = match builtin {
+            Some(builtin) => builtin.schema,
+            None => self
+                .readers
+                .iter()
+                .find(|reader| reader.can_read_block(header))
+                .and_then(|reader| reader.header_schema(header)),
+        };
+        if let Some(schema) = schema {
+            self.errors.extend(
+                header_schema::check(header, schema)
+                    .into_iter()
+                    .map(Error::InvalidHeader),
             );
+        }
+        if let Some(builtin) = builtin {
+            return (builtin.read)(self, header, text);
+        }
+        let reader = self
+            .readers
+            .iter_mut()
+            .find(|reader| reader.can_read_block(header));
+        match reader.map(|reader| reader.read_block(header, text)) {
+            Some(Ok(Some(block))) => {
+                vec![ExtendedEvent::Custom(block)]
+            }
+            Some(Ok(None)) => Vec::new(),
+            Some(Err(err)) => {
+                self.errors.push(err);
+                Vec::new()
+            }
+            None => {
+                let types = self
+                    .readers
+                    .iter()
+                    .flat_map(|r| r.block_types().iter().copied());
+                let suggestion = block_types::suggestion(&header.t, types).map(String::from);
+                self.errors.push(Error::CustomBlockNotImplemented(
+                    header.t.clone(),
+                    suggestion,
+                ));
+                // Kept as it is when formatting, so the block isn't lost
+                match self.format {
+                    Format::Html => Vec::new(),
+                    Format::Md => {
+                        let props: CowStr = serde_json::to_string(header).unwrap().into();
+                        let kind = CodeBlockKind::Fenced(props);
+                        [
+                            Event::Start(Tag::CodeBlock(kind.clone())),
+                            Event::Text(text.to_string().into()),
+                            Event::End(Tag::CodeBlock(kind)),
+                        ]
+                        .into_iter()
+                        .map(ExtendedEvent::Standard)
+                        .collect()
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a block with `header` and no content is read, see
+    /// `CustomBlockReader::reads_empty_block`. Blocks of unknown types are
+    /// read, so they are reported.
+    fn reads_empty_block(&self, header: &CustomBlockHeader) -> bool {
+        match block_types::builtin(&header.t) {
+            Some(builtin) => builtin.empty,
+            None => self
+                .readers
+                .iter()
+                .find(|reader| reader.can_read_block(header))
+                .is_none_or(|reader| reader.reads_empty_block(header)),
+        }
+    }
+
+    fn process<'a>(&mut self, event: Event<'a>) -> Vec<ExtendedEvent<'a>> {
+        if std::mem::take(&mut self.footnotes.caret) {
+            if matches!(&event, Event::Text(text) if text.as_ref() == "[") {
+                self.footnotes.start();
+                return Vec::new();
+            }
+            let mut events = vec![ExtendedEvent::Standard(Event::Text("^".into()))];
+            events.extend(self.process(event));
+            return events;
+        }
+        if !self.footnotes.reading() {
+            return self.process_event(event);
+        }
+        match self.footnotes.event(&event) {
+            Ok(Some(reference)) => vec![ExtendedEvent::Custom(Box::new(reference))],
+            Ok(None) => Vec::new(),
+            Err(unread) => {
+                let mut events = vec![ExtendedEvent::Standard(Event::Text("^[".into()))];
+                events.extend(unread.into_iter().map(ExtendedEvent::Standard));
+                events.extend(self.process_event(event));
+                events
+            }
+        }
+    }
+
+    fn process_event<'a>(&mut self, event: Event<'a>) -> Vec<ExtendedEvent<'a>> {
+        self.directives.event(&event);
+        let scripts_disabled = self.directives.scripts_disabled();
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            Event::Start(Tag::Heading(..)) => self.heading = Some(String::new()),
+            Event::End(Tag::Heading(..)) => self.section = self.heading.take(),
+            Event::TaskListMarker(done) => {
+                let mut checklist = self.checklist.write().unwrap();
+                checklist.item(*done, self.section.as_ref());
+            }
+            _ => {}
+        }
+        if let Some(level) = self.todo_level {
+            self.todo_level = match &event {
+                Event::Start(_) => Some(level + 1),
+                Event::End(_) => level.checked_sub(1),
+                _ => Some(level),
+            };
+            if let Event::Text(text) | Event::Code(text) = &event {
+                let todo = self.todos.last_mut().unwrap();
+                todo.text = format!("{} {}", todo.text, text.trim());
+            }
+            if self.todo_level.is_some() && self.hide_todos && self.format == Format::Html {
+                return Vec::new();
+            }
+        }
+        match &event {
+            Event::Start(Tag::FootnoteDefinition(id)) if id.as_ref().starts_with("yamdr:") => {
+                self.element = str::parse(&id[6..]).unwrap();
+                if self.timings.is_some() {
+                    self.element_timing = Some((std::time::Instant::now(), Vec::new()));
+                }
+                vec![ExtendedEvent::Separator(self.element)]
+            }
+            Event::End(Tag::FootnoteDefinition(id)) if id.as_ref().starts_with("yamdr:") => {
+                if let (Some(timings), Some((start, custom_blocks))) =
+                    (&mut self.timings, self.element_timing.take())
+                {
+                    timings.push(BlockTiming {
+                        block: self.element,
+                        custom_blocks,
+                        duration: start.elapsed(),
+                    });
+                }
+                Vec::new()
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(prop)))
+                if (self.strict && !is_header(prop)) || self.directives.blocks_disabled() =>
+            {
+                vec![ExtendedEvent::Standard(event)]
+            }
+            Event::Code(code) if self.strict || self.directives.blocks_disabled() => {
+                // Formulas are written as they are
+                match math_block::span_source(code) {
+                    Some(source) => vec![ExtendedEvent::Standard(Event::Text(
+                        source.to_string().into(),
+                    ))],
+                    None => vec![ExtendedEvent::Standard(event)],
+                }
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(prop))) => {
+                match serde_yaml::from_str::<CustomBlockHeader>(prop)
+                    .map(|header| self.resolve_alias(header))
+                    .map(|header| match self.format {
+                        Format::Html => defaults_block::apply(&self.defaults, header),
+                        Format::Md => header,
+                    }) {
+                    Ok(mut block)
+                        if !(scripts_disabled
+                            && self.readers.iter().any(|reader| {
+                                reader.can_read_block(&block) && reader.runs_scripts()
+                            })) =>
+                    {
+                        block.index = self.custom_blocks;
+                        self.custom_blocks += 1;
+                        self.current_custom_block = Some(block);
+                        self.current_custom_block_read = false;
+                        Vec::new()
+                    }
+                    _ => vec![ExtendedEvent::Standard(event)],
+                }
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => {
+                match self.current_custom_block.take() {
+                    // Blocks without content that still carry their header
+                    Some(header)
+                        if !self.current_custom_block_read && self.reads_empty_block(&header) =>
+                    {
+                        self.custom_block(&header, "")
+                    }
+                    Some(_) => Vec::new(),
+                    None => vec![ExtendedEvent::Standard(event)],
+                }
+            }
+            Event::Text(text) if self.current_custom_block.is_some() => {
+                self.current_custom_block_read = true;
+                let header = self.current_custom_block.clone().unwrap();
+                self.custom_block(&header, text)
+            }
+            Event::Code(code) => {
+                match self
+                    .readers
+                    .iter_mut()
+                    .find(|reader| {
+                        reader.can_read_inline(code) && !(reader.runs_scripts() && scripts_disabled)
+                    })
+                    .map(|reader| reader.read_inline(code))
+                {
+                    Some(Ok(Some(block))) => {
+                        vec![ExtendedEvent::Custom(block)]
+                    }
+                    Some(Ok(None)) => Vec::new(),
+                    Some(Err(err)) => {
+                        self.errors.push(err);
+                        vec![ExtendedEvent::Standard(event)]
+                    }
+                    None => {
+                        vec![ExtendedEvent::Standard(event)]
+                    }
+                }
+            }
+            Event::Text(text) if !self.in_code_block && !self.directives.raw() => {
+                self.text(text.clone())
+            }
+            _ => vec![ExtendedEvent::Standard(event)],
+        }
+    }
+}
+
+/// Options of the markdown parser for the document. Smart punctuation is
+/// left to `YamdrOptions::typography`, so the text is kept as it is written
+/// when rendering to markdown.
+fn document_options(options: &YamdrOptions) -> Options {
+    match options.strict {
+        true => Options::empty(),
+        false => Options::all() - Options::ENABLE_SMART_PUNCTUATION,
+    }
+}
+
+/// Whether a fence info string is written as a `{...}` header, the only
+/// form of header that is read in `YamdrOptions::strict` mode.
+fn is_header(info: &str) -> bool {
+    let info = info.trim();
+    info.starts_with('{') && info.ends_with('}')
+}
+
+fn parse_markdown<'a>(options: &YamdrOptions, markdown: &'a str) -> Vec<ExtendedEvent<'a>> {
+    parse_markdown_with_diagnostics(options, markdown).0
+}
+
+/// Same as `parse_markdown`, but also returns the errors, TODOs, task-list
+/// items and block ids that were found while processing the document, see
+/// `Meta`.
+fn parse_markdown_with_diagnostics<'a>(
+    options: &YamdrOptions,
+    markdown: &'a str,
+) -> (Vec<ExtendedEvent<'a>>, Diagnostics) {
+    let md_options = document_options(options);
+
+    let mut processor = EventProcessor::new(options);
+    let (frontmatter, markdown) = frontmatter::split(markdown);
+    processor.frontmatter(&frontmatter.map(|f| f.fields).unwrap_or_default());
+
+    let mut level = 0;
+    let mut element_i = 0;
+
+    let (markdown, mut truncated) =
+        limits::truncate_source(markdown, options.limits.max_document_size);
+    let (wiki_links, autolink) = match options.format.unwrap_or(Format::Html) {
+        Format::Html => (options.wiki_links.clone(), options.autolink),
+        Format::Md => (None, false),
+    };
+    let mut events = AutolinkEvents::new(
+        WikiLinkEvents::new(
+            limits::TruncateEvents::new(
+                RawRegions::new(
+                    MathSpans::new(
+                        Parser::new_ext(markdown, md_options).into_offset_iter(),
+                        markdown,
+                        !options.strict,
+                    ),
+                    markdown,
+                    options.format.unwrap_or(Format::Html),
+                ),
+                options.limits.max_events,
+            ),
+            wiki_links,
+        ),
+        autolink,
+    );
+
+    let parser = events
+        .by_ref()
+        .flat_map(|event| {
+            match &event {
+                Event::Start(_) => {
+                    level += 1;
+                    if level == 1 {
+                        return vec![
+                            Event::Start(Tag::FootnoteDefinition(
+                                format!("yamdr:{}", element_i).into(),
+                            )),
+                            event,
+                        ];
+                    }
+                }
+                Event::End(_) => {
+                    level -= 1;
+                    if level == 0 {
+                        element_i += 1;
+                        return vec![
+                            event,
+                            Event::End(Tag::FootnoteDefinition(
+                                format!("yamdr:{}", element_i - 1).into(),
+                            )),
+                        ];
+                    }
+                }
+                _ => {}
+            };
+            vec![event]
+        })
+        .flat_map(|event| processor.process(event));
+
+    let mut parsed: Vec<_> = parser.collect();
+    if let Some(footnotes) = std::mem::take(&mut processor.footnotes).finish() {
+        parsed.push(ExtendedEvent::Separator(element_i as u16));
+        parsed.push(ExtendedEvent::Custom(Box::new(footnotes)));
+    }
+    truncated |= events.inner().inner().truncated;
+    processor.errors.extend(
+        events
+            .inner()
+            .missing
+            .iter()
+            .map(|target| Error::MissingFile(target.clone())),
+    );
+    if truncated {
+        let id = parsed
+            .iter()
+            .filter(|ee| matches!(ee, ExtendedEvent::Separator(_)))
+            .count();
+        parsed.push(ExtendedEvent::Separator(id as u16));
+        parsed.push(ExtendedEvent::Standard(limits::notice(
+            options.format.unwrap_or(Format::Html),
+            &options.locale.labels.truncated,
+        )));
+    }
+    for name in processor.emitted.iter() {
+        if !processor.captures.read().unwrap().contains_key(name) {
+            processor.errors.push(Error::CustomBlockRead(format!(
+                "nothing is captured as `{}`",
+                name
+            )));
+        }
+    }
+    let diagnostics = Diagnostics {
+        checklist: processor.checklist.read().unwrap().clone(),
+        errors: processor.errors,
+        todos: processor.todos,
+        anchors: processor.anchors,
+        timings: processor.timings.unwrap_or_default(),
+        data: processor
+            .readers
+            .iter()
+            .flat_map(|reader| reader.exported_data())
+            .collect(),
+    };
+    (parsed, diagnostics)
+}
+
+pub fn render_markdown(options: &YamdrOptions, markdown: &str) -> (Meta, String) {
+    let format = options.format.unwrap_or(Format::Html);
+
+    let (mut parsed_markdown, diagnostics) = parse_markdown_with_diagnostics(options, markdown);
+    let (frontmatter, _) = frontmatter::split(markdown);
+    let meta_fields = meta_fields(frontmatter.as_ref(), &parsed_markdown);
+    let locale = document_locale(options, frontmatter.as_ref(), &parsed_markdown);
+    let mut heading_numbers = meta_fields
+        .get("number_headings")
+        .and_then(serde_yaml::Value::as_bool)
+        .unwrap_or(options.number_headings)
+        .then(|| {
+            numbering::HeadingNumbers::new(parsed_markdown.iter().filter_map(|ee| match ee {
+                ExtendedEvent::Standard(event) => Some(event),
+                _ => None,
+            }))
+        })
+        .filter(|_| format == Format::Html);
+    let typography = match meta_fields
+        .get("typography")
+        .and_then(serde_yaml::Value::as_bool)
+    {
+        Some(true) => Some(options.typography.clone().unwrap_or_default()),
+        Some(false) => None,
+        None => options.typography.clone(),
+    }
+    .filter(|_| format == Format::Html);
+    let mut typography = typography.as_ref().map(TypographyEvents::new);
+    let mut issue_refs = meta_fields
+        .get("repository")
+        .and_then(serde_yaml::Value::as_str)
+        .filter(|_| format == Format::Html)
+        .map(|repository| IssueRefEvents::new(repository, options.issue_titles));
+    let mut deferred = Vec::new();
+    if format == Format::Html && options.defer_expensive_blocks {
+        for ee in parsed_markdown.iter_mut() {
+            if !matches!(ee, ExtendedEvent::Custom(block) if block.is_expensive()) {
+                continue;
+            }
+            let id = deferred.len();
+            let placeholder =
+                ExtendedEvent::Standard(Event::Html(DeferredBlock::placeholder(id).into()));
+            if let ExtendedEvent::Custom(block) = std::mem::replace(ee, placeholder) {
+                deferred.push(DeferredBlock {
+                    id,
+                    block,
+                    max_size: options.limits.max_svg_size,
+                    too_large: locale.labels.too_large.clone(),
+                    minify_svg: options.minify_svg,
+                });
+            }
+        }
+    }
+    // The events are transformed once, and the text of the blocks is
+    // collected from the html events while transforming, so the statistics
+    // don't need a separate pass over the document.
+    let mut texts = search::BlockTextCollector::default();
+    let mut events = Vec::with_capacity(parsed_markdown.len());
+    let mut timings = diagnostics.timings;
+    let transformed = match options.timing {
+        // Each event is transformed on its own, to time the elements
+        true => {
+            let mut element = None;
+            let transformed = parsed_markdown
+                .iter()
+                .map(|ee| {
+                    if let ExtendedEvent::Separator(id) = ee {
+                        element = timings.iter().position(|timing| timing.block == *id);
+                    }
+                    let start = std::time::Instant::now();
+                    let transformed = format.transform_extended_event(ee);
+                    if let Some(i) = element {
+                        timings[i].duration += start.elapsed();
+                    }
+                    transformed
+                })
+                .collect();
+            transformed
+        }
+        false => format.transform_extended_events(&parsed_markdown),
+    };
+    for (ee, transformed) in parsed_markdown.iter().zip(transformed) {
+        if let ExtendedEvent::Separator(id) = ee {
+            texts.separator(*id);
+            // Added after the text is collected, so it's not counted
+            if let (Format::Html, Some(timing)) =
+                (format, timings.iter().find(|timing| timing.block == *id))
+            {
+                events.push(Event::Html(
+                    format!(
+                        "<div class=\"block-timing\" aria-hidden=\"true\">{:.1} ms</div>\n",
+                        timing.duration.as_secs_f64() * 1000.0
+                    )
+                    .into(),
+                ));
+            }
+        }
+        let transformed = if format == Format::Html {
+            limit_expensive_block(
+                ee,
+                transformed,
+                &options.limits,
+                &locale.labels,
+                options.minify_svg,
+            )
+        } else {
+            transformed
+        };
+        let transformed: Box<dyn Iterator<Item = Event>> = match &mut issue_refs {
+            Some(issue_refs) => Box::new(
+                transformed
+                    .into_iter()
+                    .flat_map(|event| issue_refs.events(event)),
+            ),
+            None => Box::new(transformed.into_iter()),
+        };
+        for event in transformed {
+            let event = match &mut typography {
+                Some(typography) => typography.event(event),
+                None => event,
+            };
+            if format == Format::Html {
+                texts.event(&event);
+            }
+            let number = match (&event, &mut heading_numbers) {
+                (Event::Start(Tag::Heading(level, ..)), Some(numbers)) => {
+                    Some(numbers.next(*level))
+                }
+                _ => None,
+            };
+            events.push(event);
+            // Not collected as text, so it's not counted or searched
+            if let Some(number) = number {
+                texts.number(&number);
+                events.push(Event::Html(
+                    format!("<span class=\"heading-number\">{}</span> ", number).into(),
+                ));
+            }
+        }
+    }
+    // Formatting a document doesn't need its stats, and collecting them
+    // would render every custom block to html as well
+    let texts = match format {
+        Format::Html => texts.finish(),
+        Format::Md => Vec::new(),
+    };
+    let stats = stats::document_stats(&texts);
+    let mut reading_time = (format == Format::Html && options.reading_time).then(|| {
+        Event::Html(
+            format!(
+                "<p class=\"reading-time\">{}</p>\n",
+                locale
+                    .labels
+                    .reading_time
+                    .replace("{}", &stats.reading_time_minutes.to_string())
+            )
+            .into(),
+        )
+    });
+    let asset_urls = AssetUrls::new(options);
+    let parser = events
+        .into_iter()
+        .flat_map(|event| match event {
+            Event::End(Tag::Heading(..)) if reading_time.is_some() => {
+                vec![event, reading_time.take().unwrap()]
+            }
+            // Wide tables are scrolled instead of overflowing the page
+            Event::Start(Tag::Table(_)) if format == Format::Html => {
+                vec![Event::Html(r#"<div class="table-scroll">"#.into()), event]
+            }
+            Event::End(Tag::Table(_)) if format == Format::Html => {
+                vec![event, Event::Html("</div>\n".into())]
+            }
+            event => vec![event],
+        })
+        .map(|event| match format {
+            Format::Html => rewrite_asset_urls(&asset_urls, event),
+            Format::Md => event,
+        })
+        .map(|event| match (format, locale.dir) {
+            (Format::Html, Direction::Rtl) => mirror_table_alignment(event),
+            _ => event,
+        });
+
+    let mut output = format.render(parser, markdown.len() + markdown.len() / 2);
+    if let (Format::Md, Some(frontmatter)) = (format, &frontmatter) {
+        output.insert_str(0, frontmatter.source);
+    }
+
+    if format == Format::Html {
+        if let Some(standalone) = &options.standalone {
+            let title = document_title(&meta_fields, &stats);
+            let no_fields = BTreeMap::new();
+            let (title, meta_fields, meta_tags) = match &standalone.encryption {
+                // The title and fields would leak the content through the
+                // template, so only the encrypted content is written
+                Some(encryption) => {
+                    output = encryption::encrypted(encryption, &locale.labels, &output);
+                    (None, &no_fields, String::new())
+                }
+                None => {
+                    let meta_tags = seo::meta_tags(
+                        &meta_fields,
+                        title.as_deref(),
+                        options.asset_base.as_deref(),
+                    );
+                    (title, &meta_fields, meta_tags)
+                }
+            };
+            // Outside the encrypted content, so it can be verified without
+            // the passphrase
+            if standalone.signature.is_some() {
+                output += signature::PLACEHOLDER;
+            }
+            output = match &standalone.template {
+                Some(template) => {
+                    let page = Page {
+                        options,
+                        locale: &locale,
+                        meta_fields,
+                        title: title.as_deref(),
+                        meta_tags: &meta_tags,
+                        content: &output,
+                    };
+                    page.render_template(template)
+                }
+                None => format!(
+                    r#"
+<!DOCTYPE html>
+<html lang="{}" dir="{}">
+    <head>
+        {}
+        <style>
+            {}
+        </style>
+        {}
+    </head>
+    <body>
+        {}
+        <div class="content">
+            {}
+        </div>
+    </body>
+</html>"#,
+                    locale.lang,
+                    locale.dir.as_str(),
+                    meta_tags,
+                    STYLE,
+                    options.additional_head.as_deref().unwrap_or(""),
+                    options.additional_body.as_deref().unwrap_or(""),
+                    output
+                ),
+            };
+            if let Some(signature) = &standalone.signature {
+                output = signature::sign(signature, markdown, &output);
+            }
         } else {
             output = format!(
                 r#"
@@ -381,25 +2078,367 @@ pub fn render_markdown(options: &YamdrOptions, markdown: &str) -> (Meta, String)
 {}
 </style>
 {}
-<div class="content">
+<div class="content" lang="{}" dir="{}">
 {}
 </div>"#,
                 STYLE,
                 options.additional_body.as_deref().unwrap_or(""),
+                locale.lang,
+                locale.dir.as_str(),
                 output
             );
         }
     }
 
-    let meta = Meta {};
+    let meta = Meta {
+        stats,
+        deferred,
+        errors: diagnostics.errors,
+        todos: diagnostics.todos,
+        checklist: diagnostics.checklist,
+        anchors: diagnostics.anchors,
+        timings,
+        data: diagnostics.data,
+        frontmatter: frontmatter.map(|f| f.fields).unwrap_or_default(),
+        texts,
+    };
 
     (meta, output)
 }
 
+/// The locale of a document, `YamdrOptions::locale` with the `lang` and
+/// `dir` of the frontmatter and the meta blocks of the document.
+fn document_locale(
+    options: &YamdrOptions,
+    frontmatter: Option<&Frontmatter>,
+    parsed: &[ExtendedEvent],
+) -> Locale {
+    parsed
+        .iter()
+        .filter_map(|ee| match ee {
+            ExtendedEvent::External(external) if external.is_meta() => Some(external),
+            _ => None,
+        })
+        .fold(
+            options.locale.with_document_options(
+                &frontmatter
+                    .map(Frontmatter::string_fields)
+                    .unwrap_or_default(),
+            ),
+            |locale, external| locale.with_document_options(&external.head),
+        )
+}
+
+/// The fields of the frontmatter and the meta blocks of a document, where
+/// the meta blocks win over the frontmatter.
+fn meta_fields(
+    frontmatter: Option<&Frontmatter>,
+    parsed: &[ExtendedEvent],
+) -> BTreeMap<String, serde_yaml::Value> {
+    let mut fields = frontmatter
+        .map(Frontmatter::string_fields)
+        .unwrap_or_default();
+    fields.extend(
+        parsed
+            .iter()
+            .filter_map(|ee| match ee {
+                ExtendedEvent::External(external) if external.is_meta() => Some(external),
+                _ => None,
+            })
+            .flat_map(ExternalBlock::fields),
+    );
+    fields
+}
+
+/// The `title` field of the meta blocks, or the first heading.
+fn document_title(
+    meta_fields: &BTreeMap<String, serde_yaml::Value>,
+    stats: &DocumentStats,
+) -> Option<String> {
+    interpolation::lookup_yaml(meta_fields, "title")
+        .or_else(|| stats.sections.iter().find_map(|s| s.heading.clone()))
+}
+
+/// What goes into a standalone page besides the style.
+struct Page<'a> {
+    options: &'a YamdrOptions,
+    locale: &'a Locale,
+    meta_fields: &'a BTreeMap<String, serde_yaml::Value>,
+    title: Option<&'a str>,
+    meta_tags: &'a str,
+    content: &'a str,
+}
+
+impl Page<'_> {
+    /// Fill in the placeholders of a page template, see
+    /// `StandaloneOptions::template`.
+    fn render_template(&self, template: &str) -> String {
+        let escaped = |text: &str| {
+            let mut escaped = String::new();
+            pulldown_cmark::escape::escape_html(&mut escaped, text).unwrap();
+            escaped
+        };
+        let options = self.options;
+        let lookup = |name: &str| match name {
+            "style" => Some(STYLE.to_string()),
+            "head" => Some(options.additional_head.clone().unwrap_or_default()),
+            "body" => Some(options.additional_body.clone().unwrap_or_default()),
+            "content" => Some(self.content.to_string()),
+            "meta_tags" => Some(self.meta_tags.to_string()),
+            "lang" => Some(escaped(&self.locale.lang)),
+            "dir" => Some(self.locale.dir.as_str().to_string()),
+            "title" => self.title.map(escaped),
+            name => interpolation::lookup_yaml(self.meta_fields, name.strip_prefix("meta.")?)
+                .map(|value| escaped(&value)),
+        };
+        match interpolation::interpolate(template, false, lookup) {
+            Some(page) => page.text(),
+            None => template.to_string(),
+        }
+    }
+}
+
+/// Files a document depends on, like images and files read by custom
+/// blocks, in the order they are referenced, resolved from
+/// `YamdrOptions::base_dir`. The document is only parsed, not rendered, so
+/// scripts aren't run.
+pub fn dependencies(options: &YamdrOptions, markdown: &str) -> Vec<PathBuf> {
+    let (_, markdown) = frontmatter::split(markdown);
+    let md_options = document_options(options);
+    let readers = EventProcessor::new(options).readers;
+    let mut files = Vec::new();
+    for event in Parser::new_ext(markdown, md_options) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                if !options.strict || is_header(&info) =>
+            {
+                let Ok(header) = serde_yaml::from_str::<CustomBlockHeader>(&info) else {
+                    continue;
+                };
+                if let Some(reader) = readers.iter().find(|r| r.can_read_block(&header)) {
+                    files.extend(reader.files(&header));
+                }
+            }
+            Event::Start(Tag::Image(_, url, _)) if is_relative_url(&url) => {
+                files.push(url.split(['?', '#']).next().unwrap_or_default().to_string());
+            }
+            _ => {}
+        }
+    }
+    let mut dependencies: Vec<PathBuf> = Vec::new();
+    for file in files {
+        let path = match &options.base_dir {
+            Some(base_dir) => base_dir.join(file),
+            None => PathBuf::from(file),
+        };
+        if !dependencies.contains(&path) {
+            dependencies.push(path);
+        }
+    }
+    dependencies
+}
+
+/// A file written from the `Code` blocks of a document, see `tangle`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TangledFile {
+    /// The `filename` of the blocks.
+    pub path: String,
+    pub code: String,
+}
+
+/// The code of the `Code` blocks with a `filename` in their header, by
+/// file, in the order the files first appear. Blocks with the same
+/// `filename` are joined in the order they are written, so a file can be
+/// explained piece by piece. The code is kept as it is written, without
+/// expanding tabs, so files like makefiles stay valid. Like
+/// `dependencies`, the document is only parsed, not rendered.
+pub fn tangle(options: &YamdrOptions, markdown: &str) -> Vec<TangledFile> {
+    let (_, markdown) = frontmatter::split(markdown);
+    let mut files: Vec<TangledFile> = Vec::new();
+    let mut current: Option<usize> = None;
+    for event in Parser::new_ext(markdown, document_options(options)) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                if !options.strict || is_header(&info) =>
+            {
+                let Ok(header) = serde_yaml::from_str::<CustomBlockHeader>(&info) else {
+                    continue;
+                };
+                let t = block_types::alias(&header.t).map_or(header.t.as_str(), |alias| alias.t);
+                let Some(path) = utils::header_field(&header, "filename").filter(|_| t == "Code")
+                else {
+                    continue;
+                };
+                current = Some(match files.iter().position(|file| file.path == path) {
+                    Some(i) => i,
+                    None => {
+                        files.push(TangledFile {
+                            path,
+                            code: String::new(),
+                        });
+                        files.len() - 1
+                    }
+                });
+            }
+            Event::Text(text) => {
+                if let Some(i) = current {
+                    let code = &mut files[i].code;
+                    if !code.is_empty() && !code.ends_with('\n') {
+                        code.push('\n');
+                    }
+                    *code += &text;
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => current = None,
+            _ => {}
+        }
+    }
+    files
+}
+
+/// Returns `url` prefixed with `base` if it is a relative url, that is
+/// not absolute, scheme relative, an anchor, or an url with a scheme.
+pub fn rewrite_asset_url(base: &str, url: &str) -> Option<String> {
+    is_relative_url(url).then(|| format!("{}/{}", base.trim_end_matches('/'), url))
+}
+
+/// Rewrites relative urls of assets in html output, with
+/// `YamdrOptions::asset_names` and `YamdrOptions::asset_base`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AssetUrls {
+    base: Option<String>,
+    base_dir: PathBuf,
+    names: BTreeMap<PathBuf, String>,
+}
+
+impl AssetUrls {
+    pub(crate) fn new(options: &YamdrOptions) -> Self {
+        AssetUrls {
+            base: options.asset_base.clone(),
+            base_dir: options.base_dir.clone().unwrap_or_default(),
+            names: options
+                .asset_names
+                .iter()
+                .map(|(path, name)| (utils::normalize_path(path), name.clone()))
+                .collect(),
+        }
+    }
+
+    /// `url` with the new name of the file it points to, and prefixed with
+    /// the base, if it is a relative url that is changed.
+    pub(crate) fn rewrite(&self, url: &str) -> Option<String> {
+        if !is_relative_url(url) {
+            return None;
+        }
+        let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+        let renamed = self
+            .names
+            .get(&utils::normalize_path(&self.base_dir.join(path)))
+            .map(|name| {
+                let dir = path.rfind('/').map_or("", |i| &path[..=i]);
+                format!("{}{}{}", dir, name, suffix)
+            });
+        match &self.base {
+            Some(base) => rewrite_asset_url(base, renamed.as_deref().unwrap_or(url)),
+            None => renamed,
+        }
+    }
+}
+
+/// Whether `url` is relative to the document, and not absolute, a fragment
+/// or with a scheme like `https:`.
+pub(crate) fn is_relative_url(url: &str) -> bool {
+    let has_scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| !scheme.is_empty() && !scheme.contains('/'))
+        .unwrap_or(false);
+    !(url.is_empty() || url.starts_with('/') || url.starts_with('#') || has_scheme)
+}
+
+/// Markdown table alignment is written as left/right, but is meant as
+/// start/end of the line, so it is mirrored for right-to-left documents.
+fn mirror_table_alignment(event: Event) -> Event {
+    let mirror = |alignment: &Vec<Alignment>| {
+        alignment
+            .iter()
+            .map(|alignment| match alignment {
+                Alignment::Left => Alignment::Right,
+                Alignment::Right => Alignment::Left,
+                alignment => *alignment,
+            })
+            .collect()
+    };
+    match event {
+        Event::Start(Tag::Table(alignment)) => Event::Start(Tag::Table(mirror(&alignment))),
+        Event::End(Tag::Table(alignment)) => Event::End(Tag::Table(mirror(&alignment))),
+        event => event,
+    }
+}
+
+fn rewrite_asset_urls<'a>(assets: &AssetUrls, event: Event<'a>) -> Event<'a> {
+    match event {
+        Event::Start(Tag::Image(kind, url, title)) => {
+            let url = assets.rewrite(&url).map(Into::into).unwrap_or(url);
+            Event::Start(Tag::Image(kind, url, title))
+        }
+        Event::End(Tag::Image(kind, url, title)) => {
+            let url = assets.rewrite(&url).map(Into::into).unwrap_or(url);
+            Event::End(Tag::Image(kind, url, title))
+        }
+        Event::Start(Tag::Link(kind, url, title)) => {
+            let url = assets.rewrite(&url).map(Into::into).unwrap_or(url);
+            Event::Start(Tag::Link(kind, url, title))
+        }
+        Event::End(Tag::Link(kind, url, title)) => {
+            let url = assets.rewrite(&url).map(Into::into).unwrap_or(url);
+            Event::End(Tag::Link(kind, url, title))
+        }
+        event => event,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExternalBlock {
     pub body: String,
-    pub head: HashMap<String, serde_yaml::Value>,
+    pub head: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl ExternalBlock {
+    /// Whether this is a "meta block", containing metadata about the
+    /// document.
+    pub fn is_meta(&self) -> bool {
+        self.head
+            .get("meta")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// The fields of the header, followed by the fields of the body if it
+    /// is a yaml mapping.
+    fn fields(&self) -> Vec<(String, serde_yaml::Value)> {
+        let mut fields: Vec<_> = self.head.clone().into_iter().collect();
+        if let Ok(serde_yaml::Value::Mapping(body)) = serde_yaml::from_str(&self.body) {
+            fields.extend(
+                body.into_iter()
+                    .filter_map(|(key, value)| Some((key.as_str()?.to_string(), value))),
+            );
+        }
+        fields
+    }
+
+    fn to_events(&self) -> Vec<Event<'_>> {
+        let header = CustomBlockHeader {
+            t: "External".into(),
+            fields: self.head.clone(),
+            index: 0,
+        };
+        let props: pulldown_cmark::CowStr = serde_json::to_string(&header).unwrap().into();
+        vec![
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+            Event::Text(self.body.as_str().into()),
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+        ]
+    }
 }
 
 /// Representaion of a "top level" block of a markdown document. Contains
@@ -420,19 +2459,101 @@ pub struct MarkdownBlock {
 pub struct MarkdownDocumentBlocks {
     pub css: String,
     pub blocks: Vec<MarkdownBlock>,
+    /// Rendered blocks from the last render, reused by `rerender`.
+    #[serde(skip)]
+    pub cache: RenderCache,
 }
 
 impl MarkdownDocumentBlocks {
     /// Rerender the contents of the markdown in each block. Useful when editing
-    /// block by block, instead of entire documents.
-    pub fn rerender(&mut self) {
+    /// block by block, instead of entire documents. Blocks that haven't changed
+    /// (and doesn't depend on script blocks that have changed) are reused
+    /// instead of rendered again. `options` should be the ones the blocks were
+    /// rendered with.
+    pub fn rerender(&mut self, options: &YamdrOptions) {
         let markdown_document = self
             .blocks
             .iter()
             .map(|block| block.markdown.as_str())
             .collect::<Vec<&str>>()
             .join("\n");
-        *self = render_blocks(&markdown_document);
+        let mut cache = std::mem::take(&mut self.cache);
+        *self = render_blocks_cached(&mut cache, options, &markdown_document);
+        self.cache = cache;
+    }
+}
+
+/// The html events with svgs minified, see `YamdrOptions::minify_svg`.
+fn minify_svgs(events: Vec<Event>, minify: bool) -> Vec<Event> {
+    if !minify {
+        return events;
+    }
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Html(html) if html.contains("<svg") => {
+                Event::Html(svg_minify::minify(&html).into())
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Replace the html of an expensive custom block with a notice if it is
+/// larger than `limits` allow, after minifying its svgs if `minify_svg`.
+fn limit_expensive_block<'a>(
+    ee: &ExtendedEvent,
+    transformed: TransformedEvents<'a>,
+    limits: &Limits,
+    labels: &Labels,
+    minify_svg: bool,
+) -> TransformedEvents<'a> {
+    match ee {
+        ExtendedEvent::Custom(block) if block.is_expensive() => TransformedEvents::Custom(
+            limits::limit_block_size(
+                minify_svgs(transformed.collect(), minify_svg),
+                limits.max_svg_size,
+                &labels.too_large,
+            )
+            .into_iter(),
+        ),
+        _ => transformed,
+    }
+}
+
+/// Render the events of one top level element as a `MarkdownBlock`.
+fn render_block(id: u16, events: &[ExtendedEvent]) -> MarkdownBlock {
+    let html = Format::Html;
+    let md = Format::Md;
+    if let [ExtendedEvent::External(external)] = events {
+        return MarkdownBlock {
+            id,
+            html: "".into(),
+            markdown: "".into(), // TODO rerendering
+            external: Some(external.clone()),
+        };
+    }
+    let html = html.render(
+        events.iter().flat_map(|ee| {
+            limit_expensive_block(
+                ee,
+                html.transform_extended_event(ee),
+                &Limits::default(),
+                &Labels::default(),
+                false,
+            )
+        }),
+        128,
+    );
+    let markdown = md.render(
+        events.iter().flat_map(|ee| md.transform_extended_event(ee)),
+        128,
+    );
+    MarkdownBlock {
+        id,
+        html,
+        markdown,
+        external: None,
     }
 }
 
@@ -446,50 +2567,11 @@ impl MarkdownDocumentBlocks {
 /// To build the complete html or markdown document, the `html` or `markdown` fields of
 /// each block can be joined. The `id` might be useful if you need to find out which
 /// block some html or markdown came from.
-pub fn render_blocks(markdown: &str) -> MarkdownDocumentBlocks {
-    let html = Format::Html;
-    let md = Format::Md;
-    let blocks = parse_markdown(markdown)
-        .into_iter()
-        .fold(Vec::new(), |mut acc, x| {
-            match x {
-                ExtendedEvent::Separator(id) => {
-                    acc.push((id, Vec::new()));
-                }
-                _ => {
-                    acc.last_mut().unwrap().1.push(x);
-                }
-            }
-            acc
-        })
-        .into_iter()
-        .map(|(id, events)| {
-            if let &[ExtendedEvent::External(external)] = &events.as_slice() {
-                return MarkdownBlock {
-                    id,
-                    html: "".into(),
-                    markdown: "".into(), // TODO rerendering
-                    external: Some(external.clone()),
-                };
-            }
-            let html = html.render(
-                events
-                    .iter()
-                    .flat_map(|ee| html.transform_extended_event(ee)),
-            );
-            let markdown = md.render(events.iter().flat_map(|ee| md.transform_extended_event(ee)));
-            MarkdownBlock {
-                id,
-                html,
-                markdown,
-                external: None,
-            }
-        })
-        .collect();
-    MarkdownDocumentBlocks {
-        css: STYLE.into(),
-        blocks,
-    }
+pub fn render_blocks(options: &YamdrOptions, markdown: &str) -> MarkdownDocumentBlocks {
+    let mut cache = RenderCache::default();
+    let mut blocks = render_blocks_cached(&mut cache, options, markdown);
+    blocks.cache = cache;
+    blocks
 }
 
 #[cfg(test)]
@@ -516,7 +2598,7 @@ New paragraph
 External block
 ```
 "#;
-        let blocks = render_blocks(document);
+        let blocks = render_blocks(&Default::default(), document);
         assert_eq!(blocks.blocks.len(), 6);
         assert_eq!(
             blocks.blocks[0].markdown,
@@ -561,6 +2643,79 @@ Code block
         assert_eq!(external.head.get("test").unwrap().as_i64(), Some(123),);
     }
 
+    #[test]
+    fn test_rewrite_asset_url() {
+        assert_eq!(
+            rewrite_asset_url("/assets/", "img/a.png").as_deref(),
+            Some("/assets/img/a.png")
+        );
+        assert_eq!(rewrite_asset_url("/assets", "/img/a.png"), None);
+        assert_eq!(rewrite_asset_url("/assets", "#header"), None);
+        assert_eq!(rewrite_asset_url("/assets", "https://example.com"), None);
+        assert_eq!(rewrite_asset_url("/assets", "mailto:a@example.com"), None);
+
+        let options = YamdrOptions {
+            asset_base: Some("/assets".into()),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, "![image](a.png) [link](https://example.com)");
+        assert!(html.contains(r#"src="/assets/a.png""#));
+        assert!(html.contains(r#"href="https://example.com""#));
+    }
+
+    #[test]
+    fn test_document_locale() {
+        let document = r#"
+```{t: External, meta: true, lang: he}
+```
+
+| a | b |
+|:--|--:|
+| 1 | 2 |
+"#;
+        let options = YamdrOptions {
+            standalone: Some(StandaloneOptions::default()),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<html lang="he" dir="rtl">"#));
+        assert!(html.contains(r#"<th style="text-align: right">a</th>"#));
+        assert!(html.contains(r#"<th style="text-align: left">b</th>"#));
+    }
+
+    #[test]
+    fn test_render_blocks_with_options() {
+        let document = "```{t: Redact, audience: internal}\nMargins.\n```\n";
+        let options = YamdrOptions {
+            audiences: vec!["internal".into()],
+            ..Default::default()
+        };
+        let mut blocks = render_blocks(&options, document);
+        assert_eq!(blocks.blocks[0].html, "<p>Margins.</p>\n");
+        blocks.rerender(&options);
+        assert_eq!(blocks.blocks[0].html, "<p>Margins.</p>\n");
+        assert_eq!(
+            render_blocks(&Default::default(), document).blocks[0].html,
+            ""
+        );
+    }
+
+    #[test]
+    fn test_standard_events_are_borrowed() {
+        let link = Tag::Link(
+            pulldown_cmark::LinkType::Inline,
+            "https://example.com/a/long/enough/url".to_string().into(),
+            "".into(),
+        );
+        let ee = ExtendedEvent::Standard(Event::Start(link.clone()));
+        let events: Vec<_> = Format::Html.transform_extended_event(&ee).collect();
+        assert_eq!(events, vec![Event::Start(link)]);
+        assert!(matches!(
+            events[0],
+            Event::Start(Tag::Link(_, CowStr::Borrowed(_), _))
+        ));
+    }
+
     #[test]
     fn test_rerender_markdown_document_blocks() {
         let document = r#"
@@ -577,13 +2732,657 @@ New paragraph
 - List
 - List
 "#;
-        let mut blocks = render_blocks(document);
+        let mut blocks = render_blocks(&Default::default(), document);
         assert_eq!(blocks.blocks.len(), 5);
         blocks.blocks[1].markdown = r#"A changed paragraph.
 
 New paragraph in same block"#
             .to_string();
-        blocks.rerender();
+        blocks.rerender(&Default::default());
         assert_eq!(blocks.blocks.len(), 6);
     }
+
+    #[test]
+    fn test_blocks_keep_document_order() {
+        let document: String = (0..200)
+            .map(|i| {
+                format!(
+                    "```{{\"t\":\"Script\"}}\nlet x = {};\n```\n\n`_x * 1_`\n\n```{{\"t\":\"Code\",\"language\":\"rust\"}}\nlet y = {};\n```\n\n",
+                    i, i
+                )
+            })
+            .collect();
+        let (_, html) = render_markdown(&Default::default(), &document);
+        let mut position = 0;
+        for i in 0..200 {
+            let output = format!(r#"<code class="inline-script">x * 1 // &gt; {}</code>"#, i);
+            let code = format!(r#"<span class="_constant">{}</span>"#, i);
+            position += html[position..].find(&output).unwrap();
+            position += html[position..].find(&code).unwrap();
+        }
+
+        let blocks = render_blocks(&Default::default(), &document);
+        assert_eq!(blocks.blocks.len(), 600);
+        for (i, block) in blocks.blocks.iter().enumerate() {
+            assert_eq!(block.id as usize, i);
+        }
+        assert_eq!(blocks.blocks[598].markdown, "`_x * 1 // > 199_`\n\n");
+    }
+
+    #[test]
+    fn test_defer_expensive_blocks() {
+        let document = r#"
+# Header
+
+```{"t":"Graph"}
+digraph D { A -> B; }
+```
+
+```{"t":"Code"}
+Code block
+```
+"#;
+        let options = YamdrOptions {
+            defer_expensive_blocks: true,
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<div class="deferred" data-deferred="0"></div>"#));
+        assert!(!html.contains("<svg"));
+        assert!(html.contains("Code block"));
+        assert_eq!(meta.deferred.len(), 1);
+        assert_eq!(meta.deferred[0].id, 0);
+        assert!(meta.deferred[0].render().contains("<svg"));
+
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("<svg"));
+        assert!(meta.deferred.is_empty());
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        let document = r#"
+```{"t":"Graph","z":1,"a":2,"m":3}
+digraph D { A -> B; }
+```
+
+```{"t":"Graph"}
+digraph D { C -> D; }
+```
+"#;
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains(r#"{"t":"Graph","a":2,"m":3,"z":1}"#));
+
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert_eq!(html, render_markdown(&Default::default(), document).1);
+        let ids: Vec<_> = html
+            .match_indices(r#"id=""#)
+            .map(|(i, _)| &html[i..i + 20])
+            .collect();
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(ids.len(), unique.len());
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let document = r#"
+```t: Graph
+digraph D { A -> B; }
+```
+
+```{"t":"Graph"}
+digraph D { C -> D; }
+```
+
+| not | a table |
+|---|---|
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert!(html.contains("<table>"));
+
+        let options = YamdrOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert!(html.contains("digraph D { A -&gt; B; }"));
+        assert!(!html.contains("<table>"));
+    }
+
+    #[test]
+    fn test_block_ids() {
+        let document = r#"
+See [the chart](#my-chart).
+
+```{t: Progress, value: 3, max: 4, id: my-chart}
+```
+
+```{t: Progress, value: 1, id: my-chart}
+```
+
+```{t: Progress, value: 1, id: "not valid"}
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(r#"<div class="block-anchor" id="my-chart"><div class="progress">"#));
+        assert_eq!(html.matches("block-anchor").count(), 1);
+        assert_eq!(
+            meta.anchors,
+            BTreeMap::from([(
+                "my-chart".to_string(),
+                BlockAnchor {
+                    block: 1,
+                    t: "Progress".into()
+                }
+            )])
+        );
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::CustomBlockRead(duplicate), Error::CustomBlockRead(invalid)]
+                if duplicate.contains("duplicate") && invalid.contains("invalid id")
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains(r#""id":"my-chart""#));
+        assert!(!md.contains("block-anchor"));
+    }
+
+    #[test]
+    fn test_timing() {
+        let document = r#"
+# Report
+
+```{t: Script}
+let x = 1;
+```
+"#;
+        let (untimed, html) = render_markdown(&Default::default(), document);
+        assert!(untimed.timings.is_empty());
+        assert!(!html.contains(r#"<div class="block-timing""#));
+
+        let options = YamdrOptions {
+            timing: true,
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert_eq!(
+            meta.timings
+                .iter()
+                .map(|timing| (timing.block, timing.custom_blocks.clone()))
+                .collect::<Vec<_>>(),
+            vec![(0, vec![]), (1, vec!["Script".to_string()])]
+        );
+        assert_eq!(html.matches(r#"<div class="block-timing""#).count(), 2);
+        assert_eq!(meta.stats, untimed.stats);
+    }
+
+    #[test]
+    fn test_block_outputs() {
+        let document = r#"
+```{t: DynamicTable, id: sales}
+row(["Month", "Sales"]);
+row(["May", 10]);
+row(["June", 32]);
+```
+
+```{t: DynamicChart, id: trend}
+plot([[0, 1], [1, 3]]);
+```
+
+```{t: Script}
+let sales = block("sales");
+debug(sales.columns);
+debug(sales.rows.len());
+debug(block("trend").series[0][1]);
+```
+
+There were _block("sales").rows.len()_ months.
+
+```{t: Script}
+block("missing");
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("[&quot;Month&quot;, &quot;Sales&quot;]"));
+        assert!(html.contains("[1.0, 3.0]"));
+        assert!(html.contains("// &gt; 2"));
+        assert_eq!(meta.errors.len(), 1);
+        assert!(meta.errors[0]
+            .to_string()
+            .contains("no output of a block with id `missing`"));
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let document = r#"
+![Logo](img/logo.png?v=2) ![Remote](https://example.com/a.png)
+
+```{t: Video, src: media/demo.mp4, poster: img/logo.png, subtitles: /subs.vtt}
+```
+
+```{t: OpenApi, path: api.yaml}
+```
+
+```rust
+fn main() {}
+```
+"#;
+        let options = YamdrOptions {
+            base_dir: Some(PathBuf::from("docs")),
+            ..Default::default()
+        };
+        assert_eq!(
+            dependencies(&options, document),
+            vec![
+                PathBuf::from("docs/img/logo.png"),
+                PathBuf::from("docs/media/demo.mp4"),
+                PathBuf::from("docs/api.yaml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_standalone_template() {
+        let template = "<html lang=\"{{lang}}\"><title>{{title}}</title><style>{{style}}</style>\
+            {{head}}<body>{{body}}<main>{{content}}</main><p>{{meta.author}}{{meta.missing}}</p></body></html>";
+        let mut options = YamdrOptions {
+            standalone: Some(StandaloneOptions {
+                template: Some(template.into()),
+                ..Default::default()
+            }),
+            additional_head: Some("<meta name=\"x\">".into()),
+            ..Default::default()
+        };
+        let document = r#"
+```{t: External, meta: true, lang: de}
+author: A & B
+```
+
+# Intro {{title}}
+"#;
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.starts_with(r#"<html lang="de"><title>Intro {{title}}</title><style>"#));
+        assert!(html.contains(r#"</style><meta name="x"><body><main><h1>Intro {{title}}</h1>"#));
+        assert!(html.ends_with("</main><p>A &amp; B</p></body></html>"));
+
+        options.standalone = Some(StandaloneOptions {
+            template: Some(template.into()),
+            encryption: Some(EncryptionOptions {
+                passphrase: "secret".into(),
+            }),
+            signature: Some(SignatureOptions { key: None }),
+        });
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.starts_with(r#"<html lang="de"><title></title><style>"#));
+        assert!(html.contains(r#"<form id="encrypted""#));
+        assert!(html.contains(r#"</script><footer class="signature""#));
+        assert!(html.ends_with("</footer></main><p></p></body></html>"));
+        assert!(!html.contains("Intro") && !html.contains("A &amp; B"));
+        assert!(verify(&html, document, None).is_ok());
+
+        options.standalone = Some(StandaloneOptions::default());
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn test_meta_tags() {
+        let document = r#"
+```{t: External, meta: true, title: Release notes, description: What's new}
+```
+
+# Version 2
+"#;
+        let options = YamdrOptions {
+            standalone: Some(StandaloneOptions {
+                template: Some("<head>{{meta_tags}}</head>".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<meta property="og:title" content="Release notes">"#));
+        assert!(html.contains(r#"<meta name="description" content="What's new">"#));
+        assert!(html.contains("<title>Release notes</title>\n</head>"));
+
+        let options = YamdrOptions {
+            standalone: Some(StandaloneOptions::default()),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, "# Version 2\n");
+        assert!(html.contains("<title>Version 2</title>"));
+        assert!(html.contains(r#"<meta name="twitter:card" content="summary">"#));
+    }
+    #[test]
+    fn test_script_variables() {
+        let document = r#"
+```{t: Script}
+let greeting = "Hello " + vars.customer;
+debug(greeting);
+```
+
+Rendered for `_vars.customer_`.
+"#;
+        let options = YamdrOptions {
+            variables: [("customer".to_string(), "ACME".to_string())].into(),
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(meta.errors.is_empty());
+        assert!(html.contains("// &gt; &quot;Hello ACME&quot;"));
+        assert!(html.contains("// &gt; ACME"));
+    }
+
+    #[test]
+    fn test_tangle() {
+        let document = r#"
+# Build
+
+```{t: Code, filename: Makefile}
+build:
+	cargo build
+```
+
+```{t: Code, language: rust, filename: src/main.rs}
+fn main() {}
+```
+
+```{t: Code}
+not tangled
+```
+
+```{t: Code, filename: Makefile}
+test:
+	cargo test
+```
+"#;
+        assert_eq!(
+            tangle(&Default::default(), document),
+            vec![
+                TangledFile {
+                    path: "Makefile".into(),
+                    code: "build:\n\tcargo build\ntest:\n\tcargo test\n".into(),
+                },
+                TangledFile {
+                    path: "src/main.rs".into(),
+                    code: "fn main() {}\n".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_locale() {
+        let document = r#"```{t: Data}
+name: sales
+data:
+  - {day: 2024-12-31, amount: "12345.5"}
+```
+
+```{t: DynamicTable, locale: en-GB}
+row(["day", "amount"]);
+row(["2024-12-31", 0.25]);
+```
+"#;
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("<td>12345.5</td><td>2024-12-31</td>"));
+
+        let options = YamdrOptions {
+            locale: Locale {
+                numbers: Some(NumberFormat::for_locale("de")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains("<td>12.345,5</td><td>31.12.2024</td>"));
+        assert!(html.contains("<td>31/12/2024</td><td>0.25</td>"));
+
+        let (_, md) = render_markdown(
+            &YamdrOptions {
+                format: Some(Format::Md),
+                ..options
+            },
+            document,
+        );
+        assert!(md.contains("12345.5"));
+    }
+
+    #[test]
+    fn test_previous_data() {
+        let document = r#"```{t: Data}
+name: sales
+data:
+  - {region: north, amount: "120"}
+```
+
+```{t: DynamicTable, id: totals}
+row(["total"]);
+row([sales.reduce(|sum, row| sum + parse_int(row.amount), 0)]);
+```
+
+```{t: Script}
+if previous == () { debug("first run") } else { debug(parse_int(sales[0].amount) - parse_int(previous.sales[0].amount)) }
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains("// &gt; &quot;first run&quot;"));
+        assert_eq!(
+            serde_json::to_value(&meta.data).unwrap(),
+            serde_json::json!({
+                "sales": [{"amount": "120", "region": "north"}],
+                "totals": {"columns": ["total"], "rows": [["120"]]},
+            })
+        );
+
+        let mut previous = meta.data;
+        previous.insert(
+            "sales".into(),
+            serde_json::json!([{"amount": "100", "region": "north"}]),
+        );
+        let options = YamdrOptions {
+            previous: Some(previous),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains("// &gt; 20"));
+    }
+
+    #[test]
+    fn test_export_data() {
+        let document = r#"```{t: DynamicTable}
+row(["a", "b"]);
+row([1, 2]);
+```
+
+```{t: Script}
+export_data("summary", #{total: 3, ok: true});
+```
+
+```{t: DynamicChart, id: trend}
+plot([[0, 1], [1, 2]]);
+```
+"#;
+        let (meta, _) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert_eq!(
+            serde_json::to_value(&meta.data).unwrap(),
+            serde_json::json!({
+                "table-1": {"columns": ["a", "b"], "rows": [["1", "2"]]},
+                "summary": {"total": 3, "ok": true},
+                "trend": {"series": [[[0.0, 1.0], [1.0, 2.0]]]},
+            })
+        );
+    }
+
+    #[test]
+    fn test_asset_names() {
+        let options = YamdrOptions {
+            base_dir: Some(PathBuf::from("site/posts")),
+            asset_names: BTreeMap::from([(
+                PathBuf::from("site/img/./logo.png"),
+                "logo.3f2a1b4c.png".to_string(),
+            )]),
+            asset_base: Some("/assets".into()),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(
+            &options,
+            "![logo](../img/logo.png#top) [other](other.png)\n\n```{t: Image, src: ../img/logo.png}\n```\n",
+        );
+        assert!(html.contains(r#"<img src="/assets/../img/logo.3f2a1b4c.png#top""#));
+        assert!(html.contains(r#"href="/assets/other.png""#));
+        assert!(html.contains(r#"src="/assets/../img/logo.3f2a1b4c.png" alt="""#));
+    }
+
+    #[test]
+    fn test_wide_tables() {
+        let document = r#"| a | b |
+| - | - |
+| 1 | 2 |
+
+```{t: DynamicTable, stacked: true, max_rows: 2}
+row(["name", "amount"]);
+row(["a", 1]);
+row(["b", 2]);
+row(["c", 3]);
+```
+
+```{t: Data, max_rows: 5}
+name: small
+data:
+  - {x: 1}
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains("<div class=\"table-scroll\"><table><thead><tr><th>a</th>"));
+        assert!(html.contains(
+            r#"<table class="stacked"><thead><tr><th>name</th><th>amount</th></tr></thead>"#
+        ));
+        assert!(html.contains(r#"<tr><td data-label="name">b</td><td data-label="amount">2</td></tr><tr class="more"><td data-label="name">c</td>"#));
+        assert!(html.contains(r#"<label for="rows-"#));
+        assert!(html.contains(r#"class="show-all">Show all 3 rows</label></div>"#));
+        // Not more rows than `max_rows`, so there is nothing to toggle
+        assert!(html.contains(r#"<div class="table-scroll"><table><thead><tr><th>#</th><th>x</th></tr></thead><tbody><tr><td>1</td><td>1</td></tr></tbody></table></div>"#));
+        assert_eq!(html.matches("<label for=").count(), 1);
+
+        let (_, md) = render_markdown(
+            &YamdrOptions {
+                format: Some(Format::Md),
+                ..Default::default()
+            },
+            document,
+        );
+        assert!(!md.contains("table-scroll"));
+        assert!(md.contains("// > | c | 3 |"));
+    }
+
+    #[test]
+    fn test_interactive_tables() {
+        let md = r#"
+```{t: DynamicTable, interactive: true, locale: de}
+row(["name", "amount"]);
+row(["b", 1234.5]);
+row(["a", 2]);
+```
+
+```{t: Data, max_rows: 1}
+name: rows
+data:
+- x: "1"
+- x: "2"
+```
+"#;
+        use crate::utils::TABLE_SCRIPT;
+        let options = YamdrOptions {
+            interactive_tables: true,
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, md);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains(
+            r#"<table data-interactive data-filter="Filter rows"><thead><tr><th>name</th><th>amount</th></tr></thead>"#
+        ));
+        assert!(html.contains(r#"<td>b</td><td data-value="1234.5">1234,5</td>"#));
+        assert!(html
+            .contains(r#"<table data-interactive data-filter="Filter rows" data-max-rows="1">"#));
+        assert_eq!(html.matches(TABLE_SCRIPT).count(), 1);
+        assert!(html.find("1234,5").unwrap() < html.find(TABLE_SCRIPT).unwrap());
+
+        let md = "```{t: Data, interactive: false}\nname: rows\ndata:\n- x: 1\n```\n";
+        let (_, html) = render_markdown(&options, md);
+        assert!(!html.contains("<table data-interactive"));
+        assert!(!html.contains(TABLE_SCRIPT));
+    }
+
+    #[test]
+    fn test_paged_tables() {
+        let md = r#"
+```{t: Data, page_size: 2}
+name: rows
+data:
+  - {x: a}
+  - {x: b}
+  - {x: c}
+```
+
+```{t: DynamicTable, page_size: 2, max_rows: 1, interactive: true}
+row(["x"]);
+row([1]);
+row([2]);
+row([3]);
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), md);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        let id = html.split(r#"<tbody id=""#).nth(1).unwrap();
+        let id = &id[..id.find("-1\"").unwrap()];
+        assert!(html.contains(&format!(
+            r#"<table class="paged"><thead><tr><th>#</th><th>x</th></tr></thead><tbody id="{0}-1"><tr><td>1</td><td>a</td></tr><tr><td>2</td><td>b</td></tr></tbody><tbody id="{0}-2"><tr><td>3</td><td>c</td></tr></tbody></table></div>"#,
+            id
+        )));
+        assert!(html.contains(&format!(
+            r##"<nav class="table-pages" aria-label="Pages" data-previous="Previous" data-next="Next"><a href="#{0}-1">1</a><a href="#{0}-2">2</a></nav>"##,
+            id
+        )));
+        // Pages replace the show all toggle
+        assert!(html.contains(
+            r#"<table class="paged" data-interactive data-filter="Filter rows" data-page-size="2">"#
+        ));
+        assert!(!html.contains(r#"class="show-all">"#));
+        assert!(!html.contains(r#"class="more""#));
+    }
+
+    #[test]
+    fn test_data_file() {
+        let dir = std::env::temp_dir().join(format!("yamdr-data-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("sales.csv"), "region,amount\nnorth,3\n").unwrap();
+        let options = YamdrOptions {
+            base_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let md = "```{t: Data, name: sales, path: sales.csv}\n```\n\n`_sales[0].amount_`\n";
+        let (meta, html) = render_markdown(&options, md);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains("<tr><td>1</td><td>3</td><td>north</td></tr>"));
+        assert!(html.contains("sales[0].amount // &gt; 3"));
+        assert_eq!(dependencies(&options, md), vec![dir.join("sales.csv")]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
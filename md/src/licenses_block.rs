@@ -0,0 +1,383 @@
+use crate::{
+    utils::{header_field, local_files},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, Tag};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// A package with its license, from `cargo metadata` or an SPDX document.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Package {
+    pub name: String,
+    pub version: String,
+    /// An SPDX license expression, like `MIT OR Apache-2.0`.
+    pub license: Option<String>,
+    pub authors: Vec<String>,
+    pub repository: Option<String>,
+    /// Whether the package is a part of the project rather than a third
+    /// party package, like the members of a workspace.
+    pub local: bool,
+}
+
+/// The packages of the output of `cargo metadata --format-version 1`.
+pub(crate) fn parse_cargo_metadata(metadata: &str) -> std::result::Result<Vec<Package>, String> {
+    #[derive(Deserialize)]
+    struct MetadataPackage {
+        name: String,
+        version: String,
+        license: Option<String>,
+        #[serde(default)]
+        authors: Vec<String>,
+        repository: Option<String>,
+        homepage: Option<String>,
+        source: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Metadata {
+        packages: Vec<MetadataPackage>,
+    }
+    let metadata: Metadata = serde_json::from_str(metadata).map_err(|err| err.to_string())?;
+    Ok(metadata
+        .packages
+        .into_iter()
+        .map(|p| Package {
+            name: p.name,
+            version: p.version,
+            license: p.license,
+            authors: p.authors,
+            repository: p.repository.or(p.homepage),
+            local: p.source.is_none(),
+        })
+        .collect())
+}
+
+/// The packages of an SPDX 2 document in the JSON format, like the
+/// software bills of materials generated by most build tools.
+fn parse_spdx(spdx: &str) -> std::result::Result<Vec<Package>, String> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct SpdxPackage {
+        #[serde(rename = "SPDXID", default)]
+        id: String,
+        name: String,
+        #[serde(default)]
+        version_info: String,
+        license_concluded: Option<String>,
+        license_declared: Option<String>,
+        supplier: Option<String>,
+        homepage: Option<String>,
+        download_location: Option<String>,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Document {
+        #[serde(default)]
+        packages: Vec<SpdxPackage>,
+        #[serde(default)]
+        document_describes: Vec<String>,
+    }
+    let document: Document = serde_json::from_str(spdx).map_err(|err| err.to_string())?;
+    // Values that aren't known are written as `NOASSERTION` or `NONE`
+    let known =
+        |value: Option<String>| value.filter(|v| !matches!(v.as_str(), "NOASSERTION" | "NONE"));
+    Ok(document
+        .packages
+        .into_iter()
+        .map(|p| Package {
+            local: document.document_describes.contains(&p.id),
+            name: p.name,
+            version: p.version_info,
+            license: known(p.license_concluded).or_else(|| known(p.license_declared)),
+            authors: known(p.supplier)
+                .map(|supplier| {
+                    let supplier = supplier
+                        .strip_prefix("Organization:")
+                        .or_else(|| supplier.strip_prefix("Person:"))
+                        .unwrap_or(&supplier);
+                    vec![supplier.trim().to_string()]
+                })
+                .unwrap_or_default(),
+            repository: known(p.homepage)
+                .or_else(|| known(p.download_location))
+                .filter(|url| url.starts_with("https://") || url.starts_with("http://")),
+        })
+        .collect())
+}
+
+/// An author without the email address, as in `Name <name@example.com>`.
+fn author_name(author: &str) -> &str {
+    match author.split_once('<') {
+        Some((name, _)) if !name.trim().is_empty() => name.trim(),
+        _ => author,
+    }
+}
+
+/// Third party notices for release documents: the packages a project
+/// depends on, grouped by license, read when the document is rendered.
+///
+/// ````text
+/// ```{t: Licenses, metadata: metadata.json}
+/// ```
+/// ````
+///
+/// `metadata` is the output of `cargo metadata --format-version 1`, and
+/// `spdx` an SPDX document in the JSON format, one of them must be set.
+/// Packages of the project itself, like the members of a workspace or the
+/// packages an SPDX document describes, are left out unless `local: true`.
+#[derive(Debug, Clone)]
+pub struct LicensesBlock {
+    header: CustomBlockHeader,
+    input: String,
+    /// Packages by license, where `None` is packages without a known
+    /// license, which are listed last.
+    licenses: BTreeMap<Option<String>, Vec<Package>>,
+    labels: Labels,
+}
+
+pub struct LicensesBlockReader {
+    base_dir: Option<PathBuf>,
+    labels: Labels,
+}
+
+impl LicensesBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        LicensesBlockReader {
+            base_dir: options.base_dir.clone(),
+            labels: options.locale.labels.clone(),
+        }
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        let full_path = match &self.base_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => PathBuf::from(path),
+        };
+        if !full_path.is_file() {
+            return Err(Error::MissingFile(path.into()));
+        }
+        std::fs::read_to_string(full_path)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err)))
+    }
+}
+
+impl CustomBlockReader for LicensesBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Licenses"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Licenses"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("metadata", FieldType::String),
+            HeaderField::new("spdx", FieldType::String),
+            HeaderField::new("local", FieldType::Bool),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let (path, packages) = match (
+            header_field(header, "metadata"),
+            header_field(header, "spdx"),
+        ) {
+            (Some(path), None) => {
+                let packages = parse_cargo_metadata(&self.read_file(&path)?);
+                (path, packages)
+            }
+            (None, Some(path)) => {
+                let packages = parse_spdx(&self.read_file(&path)?);
+                (path, packages)
+            }
+            _ => {
+                return Err(Error::CustomBlockRead(
+                    "expected either `metadata` or `spdx` in Licenses block".into(),
+                ))
+            }
+        };
+        let packages = packages.map_err(|err| {
+            Error::CustomBlockRead(format!("failed to parse `{}`: {}", path, err))
+        })?;
+        let local = header
+            .fields
+            .get("local")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false);
+        let mut licenses: BTreeMap<Option<String>, Vec<Package>> = BTreeMap::new();
+        for package in packages.into_iter().filter(|p| local || !p.local) {
+            licenses
+                .entry(package.license.clone())
+                .or_default()
+                .push(package);
+        }
+        for packages in licenses.values_mut() {
+            packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+            packages.dedup();
+        }
+        Ok(Some(Box::new(LicensesBlock {
+            header: header.clone(),
+            input: input.into(),
+            licenses,
+            labels: self.labels.clone(),
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["metadata", "spdx"])
+    }
+}
+
+impl LicensesBlock {
+    fn package_events<'a>(package: &'a Package, events: &mut Vec<Event<'a>>) {
+        events.push(Event::Start(Tag::Item));
+        let name = format!("{} {}", package.name, package.version);
+        match &package.repository {
+            Some(repository) => {
+                let link = Tag::Link(LinkType::Inline, repository.as_str().into(), "".into());
+                events.extend([
+                    Event::Start(link.clone()),
+                    Event::Text(name.into()),
+                    Event::End(link),
+                ]);
+            }
+            None => events.push(Event::Text(name.into())),
+        }
+        if !package.authors.is_empty() {
+            let authors: Vec<&str> = package.authors.iter().map(|a| author_name(a)).collect();
+            events.push(Event::Text(format!(", {}", authors.join(", ")).into()));
+        }
+        events.push(Event::End(Tag::Item));
+    }
+}
+
+impl CustomBlock for LicensesBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut events = vec![Event::Html(r#"<section class="licenses">"#.into())];
+                // Packages without a known license last
+                let licenses = self.licenses.iter().filter(|(l, _)| l.is_some());
+                let unknown = self.licenses.iter().filter(|(l, _)| l.is_none());
+                for (license, packages) in licenses.chain(unknown) {
+                    let heading = Tag::Heading(HeadingLevel::H3, None, Vec::new());
+                    let license = license.as_deref().unwrap_or(&self.labels.unknown_license);
+                    events.extend([
+                        Event::Start(heading.clone()),
+                        Event::Text(license.into()),
+                        Event::End(heading),
+                        Event::Start(Tag::List(None)),
+                    ]);
+                    for package in packages {
+                        Self::package_events(package, &mut events);
+                    }
+                    events.push(Event::End(Tag::List(None)));
+                }
+                events.push(Event::Html("</section>".into()));
+                events
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"{"packages": [
+        {"name": "serde", "version": "1.0.1", "license": "MIT OR Apache-2.0",
+         "authors": ["David Tolnay <dtolnay@gmail.com>"],
+         "repository": "https://github.com/serde-rs/serde",
+         "source": "registry+https://github.com/rust-lang/crates.io-index"},
+        {"name": "private", "version": "0.2.0", "license": null, "authors": [],
+         "source": "registry+https://github.com/rust-lang/crates.io-index"},
+        {"name": "app", "version": "0.1.0", "license": "MIT", "authors": [], "source": null}
+    ]}"#;
+
+    #[test]
+    fn spdx() {
+        let spdx = r#"{
+            "spdxVersion": "SPDX-2.3",
+            "documentDescribes": ["SPDXRef-app"],
+            "packages": [
+                {"SPDXID": "SPDXRef-app", "name": "app", "versionInfo": "1.0.0",
+                 "licenseConcluded": "MIT", "downloadLocation": "NOASSERTION"},
+                {"SPDXID": "SPDXRef-zlib", "name": "zlib", "versionInfo": "1.3",
+                 "licenseConcluded": "NOASSERTION", "licenseDeclared": "Zlib",
+                 "supplier": "Organization: zlib", "downloadLocation": "https://zlib.net"}
+            ]
+        }"#;
+        let packages = parse_spdx(spdx).unwrap();
+        assert_eq!(
+            packages[1],
+            Package {
+                name: "zlib".into(),
+                version: "1.3".into(),
+                license: Some("Zlib".into()),
+                authors: vec!["zlib".into()],
+                repository: Some("https://zlib.net".into()),
+                local: false,
+            }
+        );
+        assert!(packages[0].local);
+        assert_eq!(packages[0].repository, None);
+    }
+
+    #[test]
+    fn licenses_html() {
+        let dir = std::env::temp_dir().join(format!("yamdr-licenses-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("metadata.json"), METADATA).unwrap();
+        let mut reader = LicensesBlockReader::new(&YamdrOptions {
+            base_dir: Some(dir),
+            ..Default::default()
+        });
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Licenses, metadata: metadata.json}").unwrap();
+        let block = reader.read_block(&header, "").unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert_eq!(
+            html,
+            "<section class=\"licenses\">\n<h3>MIT OR Apache-2.0</h3>\n<ul>\n<li><a href=\"https://github.com/serde-rs/serde\">serde 1.0.1</a>, David Tolnay</li>\n</ul>\n<h3>Unknown license</h3>\n<ul>\n<li>private 0.2.0</li>\n</ul>\n</section>"
+        );
+
+        for header in [
+            "{t: Licenses}",
+            "{t: Licenses, metadata: metadata.json, spdx: sbom.json}",
+        ] {
+            let header: CustomBlockHeader = serde_yaml::from_str(header).unwrap();
+            assert!(reader.read_block(&header, "").is_err());
+        }
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Licenses, spdx: missing.json}").unwrap();
+        assert!(matches!(
+            reader.read_block(&header, ""),
+            Err(Error::MissingFile(_))
+        ));
+    }
+}
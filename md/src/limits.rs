@@ -0,0 +1,174 @@
+use crate::Format;
+use pulldown_cmark::{escape::escape_html, Event, Tag};
+
+/// Limits that keep huge documents from exhausting memory, e.g. when
+/// rendering user input in the server or wasm. Instead of failing, the
+/// output is truncated, or an oversized block is left out, and a notice is
+/// rendered in its place.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Max size of the markdown source in bytes, anything after is dropped.
+    pub max_document_size: usize,
+    /// Max number of parsed markdown events, anything after is dropped.
+    pub max_events: usize,
+    /// Max size in bytes of the html of a single graph or chart.
+    pub max_svg_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_document_size: 8 * 1024 * 1024,
+            max_events: 1_000_000,
+            max_svg_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Limits {
+            max_document_size: usize::MAX,
+            max_events: usize::MAX,
+            max_svg_size: usize::MAX,
+        }
+    }
+}
+
+/// Cut `markdown` to at most `max` bytes, at the end of a line if possible.
+/// Returns whether anything was cut.
+pub(crate) fn truncate_source(markdown: &str, max: usize) -> (&str, bool) {
+    if markdown.len() <= max {
+        return (markdown, false);
+    }
+    let mut end = max;
+    while !markdown.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = markdown[..end].rfind('\n').map_or(end, |i| i + 1);
+    (&markdown[..end], true)
+}
+
+/// Iterator that stops after `max` events. When it stops early, the tags
+/// that are still open are closed, so the output stays well formed.
+pub(crate) struct TruncateEvents<'a, I> {
+    events: I,
+    remaining: usize,
+    open: Vec<Tag<'a>>,
+    pub truncated: bool,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> TruncateEvents<'a, I> {
+    pub fn new(events: I, max: usize) -> Self {
+        TruncateEvents {
+            events,
+            remaining: max,
+            open: Vec::new(),
+            truncated: false,
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for TruncateEvents<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if self.truncated {
+            return self.open.pop().map(Event::End);
+        }
+        let event = self.events.next()?;
+        if self.remaining == 0 {
+            self.truncated = true;
+            return self.open.pop().map(Event::End);
+        }
+        self.remaining -= 1;
+        match &event {
+            Event::Start(tag) => self.open.push(tag.clone()),
+            Event::End(_) => {
+                self.open.pop();
+            }
+            _ => {}
+        }
+        Some(event)
+    }
+}
+
+/// A notice rendered in place of content that was left out because of the
+/// limits.
+pub(crate) fn notice(format: Format, message: &str) -> Event<'static> {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, message).unwrap();
+    match format {
+        Format::Html => {
+            Event::Html(format!(r#"<div class="error" role="alert">{}</div>"#, escaped).into())
+        }
+        Format::Md => Event::Html(format!("<!-- {} -->\n", escaped).into()),
+    }
+}
+
+/// Replace the events of a block with a notice if its html is larger than
+/// `max` bytes.
+pub(crate) fn limit_block_size<'a>(
+    events: Vec<Event<'a>>,
+    max: usize,
+    message: &str,
+) -> Vec<Event<'a>> {
+    let size: usize = events
+        .iter()
+        .map(|event| match event {
+            Event::Html(html) | Event::Text(html) => html.len(),
+            _ => 0,
+        })
+        .sum();
+    if size > max {
+        vec![notice(Format::Html, message)]
+    } else {
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn truncate_at_line() {
+        assert_eq!(truncate_source("abc\ndef\n", 100), ("abc\ndef\n", false));
+        assert_eq!(truncate_source("abc\ndef\n", 6), ("abc\n", true));
+        assert_eq!(truncate_source("æøå", 3), ("æ", true));
+    }
+
+    #[test]
+    fn truncated_document() {
+        let options = YamdrOptions {
+            limits: Limits {
+                max_events: 8,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let document = "# Header\n\n- One\n- Two\n- Three\n- Four\n\nLast paragraph\n";
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains("<li>One</li>"));
+        assert!(!html.contains("Four"));
+        assert!(!html.contains("Last paragraph"));
+        assert!(html.contains("</ul>"));
+        assert!(html.contains(r#"<div class="error" role="alert">"#));
+    }
+
+    #[test]
+    fn oversized_svg() {
+        let options = YamdrOptions {
+            limits: Limits {
+                max_svg_size: 100,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let document = "```{\"t\":\"Graph\"}\ndigraph D { A -> B; }\n```\n";
+        let (_, html) = render_markdown(&options, document);
+        assert!(!html.contains("<svg"));
+        assert!(html.contains(r#"<div class="error" role="alert">"#));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::{
+    utils::{header_field, local_files, resolve_file},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Result, YamdrOptions,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+use std::path::PathBuf;
+
+/// A line of the excerpt, with its line number in the file.
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Text(usize, String),
+    /// Lines left out between two groups of matches and their context.
+    Gap,
+}
+
+/// Length of the timestamp at the start of `line`, like
+/// `2024-03-01T12:00:00.123Z`, `2024-03-01 12:00:00,123` or `12:00:00`,
+/// optionally in brackets.
+fn timestamp_len(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    // Whether `pattern` is at `i`, where `0` is any digit
+    let at = |i: usize, pattern: &str| {
+        pattern
+            .bytes()
+            .enumerate()
+            .all(|(j, p)| match bytes.get(i + j) {
+                Some(b) if p == b'0' => b.is_ascii_digit(),
+                Some(b) => *b == p,
+                None => false,
+            })
+    };
+    let digits = |mut i: usize| {
+        while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+            i += 1;
+        }
+        i
+    };
+    let bracket = bytes.first() == Some(&b'[');
+    let mut i = bracket as usize;
+    if at(i, "0000-00-00") {
+        i += 10;
+        if matches!(bytes.get(i), Some(b'T' | b' ')) && at(i + 1, "00:00:00") {
+            i += 9;
+        }
+    } else if at(i, "00:00:00") {
+        i += 8;
+    } else {
+        return None;
+    }
+    // Fractions of a second and time zone
+    if matches!(bytes.get(i), Some(b'.' | b',')) && at(i + 1, "0") {
+        i = digits(i + 1);
+    }
+    match bytes.get(i) {
+        Some(b'Z') => i += 1,
+        Some(b'+' | b'-') if at(i + 1, "00:00") => i += 6,
+        Some(b'+' | b'-') if at(i + 1, "0000") => i += 5,
+        _ => {}
+    }
+    if bracket {
+        if bytes.get(i) != Some(&b']') {
+            return None;
+        }
+        i += 1;
+    }
+    Some(i)
+}
+
+/// The lines of `log` to show: the last `tail` lines, if set, and of those
+/// only the lines containing `grep`, with `context` lines around them.
+fn excerpt(
+    log: &str,
+    grep: Option<&str>,
+    ignore_case: bool,
+    context: usize,
+    tail: Option<usize>,
+) -> Vec<Line> {
+    let lines: Vec<&str> = log.lines().collect();
+    let start = tail.map_or(0, |tail| lines.len().saturating_sub(tail));
+    let Some(grep) = grep else {
+        return (start..lines.len())
+            .map(|i| Line::Text(i + 1, lines[i].to_string()))
+            .collect();
+    };
+    let mut shown = vec![false; lines.len()];
+    for i in start..lines.len() {
+        if !match_ranges(lines[i], grep, ignore_case).is_empty() {
+            let end = (i + context + 1).min(lines.len());
+            shown[i.saturating_sub(context).max(start)..end].fill(true);
+        }
+    }
+    let mut excerpt = Vec::new();
+    for i in start..lines.len() {
+        if !shown[i] {
+            continue;
+        }
+        if i > start && !shown[i - 1] && !excerpt.is_empty() {
+            excerpt.push(Line::Gap);
+        }
+        excerpt.push(Line::Text(i + 1, lines[i].to_string()));
+    }
+    excerpt
+}
+
+/// Ranges of `line` that contain `grep`.
+fn match_ranges(line: &str, grep: &str, ignore_case: bool) -> Vec<std::ops::Range<usize>> {
+    if grep.is_empty() {
+        return Vec::new();
+    }
+    let (haystack, needle) = match ignore_case {
+        // Only ascii is folded, so the byte offsets stay the same
+        true => (line.to_ascii_lowercase(), grep.to_ascii_lowercase()),
+        false => (line.to_string(), grep.to_string()),
+    };
+    haystack
+        .match_indices(&needle)
+        .map(|(start, _)| start..start + needle.len())
+        .collect()
+}
+
+/// An excerpt of a log file, read when the document is rendered, so
+/// incident reports can embed the evidence.
+///
+/// ````text
+/// ```{t: Log, path: app.log, grep: ERROR, context: 2, tail: 200}
+/// ```
+/// ````
+///
+/// Only the last `tail` lines of the file are read, if it is set. With
+/// `grep`, only the lines containing it are shown, with `context` lines
+/// before and after them, like `grep -C`, and matches are highlighted.
+/// `ignore_case: true` matches without regard to case. Timestamps at the
+/// start of lines are highlighted, and lines keep their line numbers.
+#[derive(Debug, Clone)]
+pub struct LogBlock {
+    header: CustomBlockHeader,
+    input: String,
+    grep: Option<String>,
+    ignore_case: bool,
+    lines: Vec<Line>,
+}
+
+pub struct LogBlockReader {
+    base_dir: Option<PathBuf>,
+}
+
+impl LogBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        LogBlockReader {
+            base_dir: options.base_dir.clone(),
+        }
+    }
+
+    fn read_log(&self, path: &str) -> Result<String> {
+        let full_path = resolve_file(self.base_dir.as_deref(), path)?;
+        // Logs aren't always valid utf-8, like with binary payloads
+        std::fs::read(full_path)
+            .map(|log| String::from_utf8_lossy(&log).into_owned())
+            .map_err(|err| Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err)))
+    }
+}
+
+impl CustomBlockReader for LogBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Log"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Log"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("path", FieldType::String),
+            HeaderField::new("grep", FieldType::Scalar),
+            HeaderField::new("ignore_case", FieldType::Bool),
+            HeaderField::new("context", FieldType::Integer),
+            HeaderField::new("tail", FieldType::Integer),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let log = match header_field(header, "path") {
+            Some(path) => self.read_log(&path)?,
+            None if input.trim().is_empty() => {
+                return Err(Error::CustomBlockRead("missing `path` in Log block".into()))
+            }
+            None => input.to_string(),
+        };
+        let number = |name| {
+            header
+                .fields
+                .get(name)
+                .and_then(serde_yaml::Value::as_u64)
+                .map(|n| n as usize)
+        };
+        let grep = header_field(header, "grep");
+        let ignore_case = header
+            .fields
+            .get("ignore_case")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false);
+        let lines = excerpt(
+            &log,
+            grep.as_deref(),
+            ignore_case,
+            number("context").unwrap_or(0),
+            number("tail"),
+        );
+        Ok(Some(Box::new(LogBlock {
+            header: header.clone(),
+            input: input.into(),
+            grep,
+            ignore_case,
+            lines,
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["path"])
+    }
+}
+
+impl LogBlock {
+    fn line_html(&self, html: &mut String, line: &str) {
+        let time = timestamp_len(line).unwrap_or(0);
+        if time > 0 {
+            *html += "<time>";
+            escape_html(&mut *html, &line[..time]).unwrap();
+            *html += "</time>";
+        }
+        let ranges = match &self.grep {
+            Some(grep) => match_ranges(line, grep, self.ignore_case),
+            None => Vec::new(),
+        };
+        let mut position = time;
+        for range in ranges {
+            if range.start < position {
+                continue;
+            }
+            escape_html(&mut *html, &line[position..range.start]).unwrap();
+            *html += "<mark>";
+            escape_html(&mut *html, &line[range.clone()]).unwrap();
+            *html += "</mark>";
+            position = range.end;
+        }
+        escape_html(&mut *html, &line[position..]).unwrap();
+    }
+}
+
+impl CustomBlock for LogBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut html = r#"<pre class="log"><code>"#.to_string();
+                for line in self.lines.iter() {
+                    match line {
+                        Line::Text(number, text) => {
+                            html += &format!(r#"<span class="log-line" data-line="{}">"#, number);
+                            self.line_html(&mut html, text);
+                            html += "</span>\n";
+                        }
+                        Line::Gap => html += "<span class=\"log-gap\">--</span>\n",
+                    }
+                }
+                html += "</code></pre>\n";
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "2024-03-01T12:00:00Z INFO starting
+2024-03-01T12:00:01Z INFO listening
+2024-03-01T12:00:02Z ERROR db <timeout>
+2024-03-01T12:00:03Z INFO retrying
+2024-03-01T12:00:04Z INFO ok
+2024-03-01T12:00:05Z INFO ok
+2024-03-01T12:00:06Z Error again";
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp_len("2024-03-01T12:00:00.123+01:00 a"), Some(29));
+        assert_eq!(timestamp_len("2024-03-01 12:00:00,123 a"), Some(23));
+        assert_eq!(timestamp_len("[12:00:00] a"), Some(10));
+        assert_eq!(timestamp_len("2024-03-01 a"), Some(10));
+        assert_eq!(timestamp_len("[12:00] a"), None);
+        assert_eq!(timestamp_len("INFO 12:00:00"), None);
+    }
+
+    #[test]
+    fn excerpts() {
+        let numbers = |lines: Vec<Line>| -> Vec<Option<usize>> {
+            lines
+                .into_iter()
+                .map(|line| match line {
+                    Line::Text(n, _) => Some(n),
+                    Line::Gap => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            numbers(excerpt(LOG, Some("ERROR"), false, 1, None)),
+            vec![Some(2), Some(3), Some(4)]
+        );
+        assert_eq!(
+            numbers(excerpt(LOG, Some("error"), true, 1, None)),
+            vec![Some(2), Some(3), Some(4), None, Some(6), Some(7)]
+        );
+        assert_eq!(
+            numbers(excerpt(LOG, Some("INFO"), false, 0, Some(4))),
+            vec![Some(4), Some(5), Some(6)]
+        );
+        assert_eq!(
+            numbers(excerpt(LOG, None, false, 0, Some(1))),
+            vec![Some(7)]
+        );
+    }
+
+    #[test]
+    fn log_html() {
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Log, grep: ERROR, context: 0}").unwrap();
+        let block = LogBlockReader::new(&Default::default())
+            .read_block(&header, LOG)
+            .unwrap()
+            .unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert_eq!(
+            html,
+            "<pre class=\"log\"><code><span class=\"log-line\" data-line=\"3\"><time>2024-03-01T12:00:02Z</time> <mark>ERROR</mark> db &lt;timeout&gt;</span>\n</code></pre>\n"
+        );
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Log, path: missing.log}").unwrap();
+        assert!(matches!(
+            LogBlockReader::new(&Default::default()).read_block(&header, ""),
+            Err(Error::MissingFile(_))
+        ));
+    }
+
+    #[test]
+    fn paths_outside_base_dir() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut reader = LogBlockReader::new(&YamdrOptions {
+            base_dir: Some(dir.join("src")),
+            ..Default::default()
+        });
+        let manifest = dir.join("Cargo.toml");
+        for path in ["lib.rs", "../Cargo.toml", &manifest.to_string_lossy()] {
+            let header = CustomBlockHeader {
+                t: "Log".into(),
+                fields: [("path".to_string(), path.into())].into(),
+                index: 0,
+            };
+            let read = reader.read_block(&header, "");
+            match path {
+                "lib.rs" => assert!(read.is_ok()),
+                _ => assert!(
+                    matches!(read, Err(Error::CustomBlockRead(err)) if err.contains("outside")),
+                    "{}",
+                    path
+                ),
+            }
+        }
+    }
+}
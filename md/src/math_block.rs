@@ -0,0 +1,439 @@
+use crate::{
+    mathml::to_mathml, CustomBlock, CustomBlockHeader, CustomBlockReader, Error, Format,
+    HeaderField, Result,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// A displayed LaTeX formula, rendered to MathML.
+///
+/// ````text
+/// ```{t: Math}
+/// \int_0^1 x^2 \, dx = \frac{1}{3}
+/// ```
+/// ````
+///
+/// Formulas in text are written between `$`s, like `$e^{i\pi} = -1$`, or
+/// `$$`s to display them. They are found by `MathSpans`, and read as inline
+/// custom blocks. Rendering to markdown writes the formulas as they are.
+#[derive(Debug, Clone)]
+pub struct MathBlock {
+    header: Option<CustomBlockHeader>,
+    source: String,
+    html: String,
+}
+
+pub struct MathBlockReader {}
+
+impl MathBlockReader {
+    pub fn initial_state() -> Self {
+        MathBlockReader {}
+    }
+}
+
+/// Put before the source of the formulas found by `MathSpans`, so code
+/// spans like `` `$x$` `` aren't read as formulas. The parser replaces null
+/// characters in the document, so code spans can't start with one.
+const MARKER: char = '\0';
+
+/// The source of a formula found by `MathSpans`, like `$x$`, if `code` is
+/// one.
+pub(crate) fn span_source(code: &str) -> Option<&str> {
+    code.strip_prefix(MARKER)
+}
+
+/// The formula of inline math found by `MathSpans`, like `$x$` or `$$x$$`,
+/// and whether it is displayed.
+fn inline_formula(inline: &str) -> Option<(&str, bool)> {
+    let inline = span_source(inline)?;
+    let (tex, display) = match inline.strip_prefix("$$").and_then(|i| i.strip_suffix("$$")) {
+        Some(tex) => (tex, true),
+        None => (inline.strip_prefix('$')?.strip_suffix('$')?, false),
+    };
+    (!tex.trim().is_empty() && !tex.starts_with(char::is_whitespace)).then_some((tex, display))
+}
+
+impl CustomBlockReader for MathBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Math"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Math"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        Some(&[])
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let mathml = to_mathml(input, true).map_err(Error::CustomBlockRead)?;
+        Ok(Some(Box::new(MathBlock {
+            header: Some(header.clone()),
+            source: input.into(),
+            html: format!(r#"<div class="math">{}</div>"#, mathml),
+        })))
+    }
+
+    fn can_read_inline(&self, inline: &str) -> bool {
+        inline_formula(inline).is_some()
+    }
+
+    fn read_inline(&mut self, inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
+        let (tex, display) = inline_formula(inline).expect("checked by can_read_inline");
+        let inline = span_source(inline).unwrap();
+        // Formulas that can't be converted are shown as they are written
+        let html = to_mathml(tex, display).unwrap_or_else(|err| {
+            let mut html = String::from(r#"<code class="math-error" title=""#);
+            escape_html(&mut html, &err).unwrap();
+            html += "\">";
+            escape_html(&mut html, inline).unwrap();
+            html += "</code>";
+            html
+        });
+        Ok(Some(Box::new(MathBlock {
+            header: None,
+            source: inline.into(),
+            html,
+        })))
+    }
+}
+
+impl CustomBlock for MathBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match (format, &self.header) {
+            (Format::Html, _) => vec![Event::Html(self.html.as_str().into())],
+            (Format::Md, Some(header)) => {
+                let props: CowStr = serde_json::to_string(header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.source.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+            (Format::Md, None) => vec![Event::Html(self.source.as_str().into())],
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// End of the inline math starting with the `$` at `start` of `source`, if
+/// it is the start of a formula. Like in pandoc, the opening `$` must be
+/// followed by a non-space, and the closing `$` preceded by a non-space and
+/// not followed by a digit, so amounts like `$5 and $10` aren't formulas.
+/// Formulas between `$$`s can contain spaces, formulas between `$`s can't
+/// contain other `$`s, and formulas don't continue over blank lines.
+pub(crate) fn formula_end(source: &str, start: usize) -> Option<usize> {
+    if start > 0 && matches!(source.as_bytes()[start - 1], b'\\' | b'$') {
+        return None;
+    }
+    let delimiter = match source[start..].starts_with("$$") {
+        true => "$$",
+        false => "$",
+    };
+    let content = start + delimiter.len();
+    if delimiter == "$" && source[content..].starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut chars = source[content..].char_indices();
+    let mut previous = None;
+    while let Some((i, c)) = chars.next() {
+        let i = content + i;
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' if source[..i].trim_end_matches([' ', '\t']).ends_with('\n') => return None,
+            '$' if i > content && source[i..].starts_with(delimiter) => {
+                let end = i + delimiter.len();
+                let closes = delimiter == "$$"
+                    || (!previous.is_some_and(char::is_whitespace)
+                        && !source[end..].starts_with(|c: char| c.is_ascii_digit()));
+                match closes {
+                    true => return Some(end),
+                    // Like in `$5 or $10, and $x$`, where only `$x$` is a
+                    // formula
+                    false if delimiter == "$" => return None,
+                    false => {}
+                }
+            }
+            _ => {}
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+/// Whether an event can be in the middle of inline math. The parser splits
+/// text at characters like `_` and `*`, and reads some of them as emphasis.
+fn in_formula(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Text(_)
+            | Event::SoftBreak
+            | Event::Code(_)
+            | Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough)
+            | Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough)
+    )
+}
+
+/// Finds inline math, like `$x^2$`, in the events of a document, and
+/// replaces the events of each formula with an inline code event with its
+/// source after a marker, which `MathBlockReader` reads. The formula is taken from the
+/// source, as the parser reads `_` and `*` in formulas as emphasis, and
+/// backslashes as escapes.
+pub(crate) struct MathSpans<'a, I> {
+    events: I,
+    source: &'a str,
+    enabled: bool,
+    /// Events read ahead while looking for the end of a formula, and the
+    /// text after the end of a formula.
+    pending: VecDeque<(Event<'a>, Range<usize>)>,
+    in_code_block: bool,
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> MathSpans<'a, I> {
+    pub fn new(events: I, source: &'a str, enabled: bool) -> Self {
+        MathSpans {
+            events,
+            source,
+            enabled,
+            pending: VecDeque::new(),
+            in_code_block: false,
+        }
+    }
+
+    fn pull(&mut self) -> Option<(Event<'a>, Range<usize>)> {
+        self.pending.pop_front().or_else(|| self.events.next())
+    }
+
+    /// Whether the formula from `start` to `end` can be read from the
+    /// events after the text ending at `text_end`, that are then consumed,
+    /// with the text after the formula put back.
+    fn take_formula(&mut self, text_end: usize, end: usize) -> bool {
+        if end <= text_end {
+            if end < text_end {
+                let tail = &self.source[end..text_end];
+                self.pending
+                    .push_front((Event::Text(tail.into()), end..text_end));
+            }
+            return true;
+        }
+        let mut taken = Vec::new();
+        while let Some((event, range)) = self.pull() {
+            if range.start >= end {
+                self.pending.push_front((event, range));
+                break;
+            }
+            let closes = range.end >= end;
+            taken.push((event, range));
+            if closes {
+                break;
+            }
+        }
+        let mut depth = 0;
+        let balanced = taken.iter().all(|(event, _)| {
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                _ => {}
+            }
+            depth >= 0 && in_formula(event)
+        }) && depth == 0;
+        let last = taken.last().filter(|(event, range)| match event {
+            Event::Text(text) => range.end >= end && text.ends_with(&self.source[end..range.end]),
+            _ => false,
+        });
+        match last {
+            Some((_, range)) if balanced => {
+                let last_end = range.end;
+                if end < last_end {
+                    let tail = &self.source[end..last_end];
+                    self.pending
+                        .push_front((Event::Text(tail.into()), end..last_end));
+                }
+                true
+            }
+            _ => {
+                for event in taken.into_iter().rev() {
+                    self.pending.push_front(event);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> Iterator for MathSpans<'a, I> {
+    type Item = (Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<(Event<'a>, Range<usize>)> {
+        let (event, range) = self.pull()?;
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            _ => {}
+        }
+        let source = self.source;
+        let is_source = match &event {
+            Event::Text(text) => text.as_ref() == &source[range.clone()],
+            _ => false,
+        };
+        if !self.enabled || self.in_code_block || !is_source {
+            return Some((event, range));
+        }
+        let text = &source[range.clone()];
+        for (i, _) in text.match_indices('$') {
+            let start = range.start + i;
+            let Some(end) = formula_end(source, start) else {
+                continue;
+            };
+            if !self.take_formula(range.end, end) {
+                continue;
+            }
+            let code = (
+                Event::Code(format!("{}{}", MARKER, &source[start..end]).into()),
+                start..end,
+            );
+            if start == range.start {
+                return Some(code);
+            }
+            self.pending.push_front(code);
+            return Some((
+                Event::Text(CowStr::Borrowed(&text[..i])),
+                range.start..start,
+            ));
+        }
+        Some((event, range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+    use pulldown_cmark::{Options, Parser};
+
+    fn spans(markdown: &str) -> Vec<Event<'_>> {
+        MathSpans::new(
+            Parser::new_ext(markdown, Options::all()).into_offset_iter(),
+            markdown,
+            true,
+        )
+        .map(|(event, _)| event)
+        .collect()
+    }
+
+    #[test]
+    fn find_spans() {
+        assert_eq!(
+            spans("Where $a_1 * b_2 * c$ and $$\\{x\\}$$."),
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text("Where ".into()),
+                Event::Code("\0$a_1 * b_2 * c$".into()),
+                Event::Text(" and ".into()),
+                Event::Code("\0$$\\{x\\}$$".into()),
+                Event::Text(".".into()),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+        assert_eq!(
+            spans("$5 or $10, and $x$"),
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text("$5 or $10, and ".into()),
+                Event::Code("\0$x$".into()),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+        // Amounts, escaped dollars, code, spaces and blank lines
+        for markdown in [
+            "It costs $5 and $10.",
+            "Not \\$x$ either",
+            "`$x$` and $ x$",
+            "$a\n\nb$",
+            "```\n$x$\n```",
+            "[$x](y) $",
+        ] {
+            let events: Vec<Event> = Parser::new_ext(markdown, Options::all()).collect();
+            assert_eq!(spans(markdown), events, "{}", markdown);
+        }
+    }
+
+    #[test]
+    fn read() {
+        let mut reader = MathBlockReader::initial_state();
+        assert!(reader.can_read_inline("\0$x$"));
+        assert!(reader.can_read_inline("\0$$x$$"));
+        assert!(!reader.can_read_inline("\0$"));
+        assert!(!reader.can_read_inline("\0$ x$"));
+        assert!(!reader.can_read_inline("\0$PATH"));
+        // Code spans
+        assert!(!reader.can_read_inline("$x$"));
+
+        let block = reader.read_inline("\0$x^2$").unwrap().unwrap();
+        assert_eq!(
+            crate::html::render(block.to_events(Format::Html).into_iter(), 0),
+            r#"<math><semantics><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow><annotation encoding="application/x-tex">x^2</annotation></semantics></math>"#
+        );
+        assert_eq!(
+            block.to_events(Format::Md),
+            vec![Event::Html("$x^2$".into())]
+        );
+
+        let block = reader.read_inline("\0$\\oops$").unwrap().unwrap();
+        assert_eq!(
+            crate::html::render(block.to_events(Format::Html).into_iter(), 0),
+            r#"<code class="math-error" title="unknown command `\oops`">$\oops$</code>"#
+        );
+
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Math}").unwrap();
+        let html = reader.read_block(&header, "a \\\\ b\n").unwrap().unwrap();
+        assert!(
+            crate::html::render(html.to_events(Format::Html).into_iter(), 0)
+                .starts_with(r#"<div class="math"><math display="block">"#)
+        );
+        assert!(reader.read_block(&header, "\\frac{1}{").is_err());
+    }
+
+    #[test]
+    fn math() {
+        let document = "The area is $\\pi r_1^2$ for *r* and $5 or $10.\n\n```{t: Math}\n\\sum_{i=1}^n i = \\frac{n(n+1)}{2}\n```\n";
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty());
+        assert!(html.contains(
+            "The area is <math><semantics><mrow><mi>π</mi><msubsup><mi>r</mi><mn>1</mn><mn>2</mn></msubsup></mrow>"
+        ));
+        assert!(html.contains("for <em>r</em> and $5 or $10."));
+        assert!(html.contains(r#"<div class="math"><math display="block">"#));
+
+        // Formulas are written as they are
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with("The area is $\\pi r_1^2$ for *r* and"));
+        assert!(md.contains("\\sum_{i=1}^n i = \\frac{n(n+1)}{2}\n```"));
+
+        // Code spans and escaped dollars aren't formulas
+        let document = "Not `$y$` or \\$x$.\n";
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains("Not <code>$y$</code> or $x$."));
+        let (_, md) = render_markdown(&options, document);
+        assert_eq!(md, "Not `$y$` or \\$x$.\n\n");
+
+        let (meta, _) = render_markdown(&Default::default(), "```{t: Math}\n\\nope\n```\n");
+        assert_eq!(meta.errors.len(), 1);
+    }
+}
@@ -0,0 +1,828 @@
+use pulldown_cmark::escape::escape_html;
+
+/// A token of a LaTeX math expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A command like `\frac`, or `\,` for a command of one symbol.
+    Command(String),
+    Letter(char),
+    Number(String),
+    Symbol(char),
+    Open,
+    Close,
+    Superscript,
+    Subscript,
+    Ampersand,
+}
+
+/// The tokens of `tex`, with the offset of each of them.
+fn tokenize(tex: &str) -> Vec<(Token, usize)> {
+    let mut tokens = Vec::new();
+    let mut chars = tex.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let token = match c {
+            '\\' => {
+                let mut name = String::new();
+                while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_alphabetic()) {
+                    name.push(c);
+                    chars.next();
+                }
+                if name.is_empty() {
+                    name.extend(chars.next().map(|(_, c)| c));
+                }
+                Token::Command(name)
+            }
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '^' => Token::Superscript,
+            '_' => Token::Subscript,
+            '&' => Token::Ampersand,
+            c if c.is_whitespace() => continue,
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&(_, c)) = chars
+                    .peek()
+                    .filter(|(_, c)| c.is_ascii_digit() || *c == '.')
+                {
+                    number.push(c);
+                    chars.next();
+                }
+                Token::Number(number)
+            }
+            c if c.is_alphabetic() => Token::Letter(c),
+            c => Token::Symbol(c),
+        };
+        tokens.push((token, offset));
+    }
+    tokens
+}
+
+fn escaped(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).unwrap();
+    escaped
+}
+
+fn mo(op: &str) -> String {
+    format!("<mo>{}</mo>", escaped(op))
+}
+
+fn mi(identifier: &str) -> String {
+    format!("<mi>{}</mi>", escaped(identifier))
+}
+
+fn mrow(items: Vec<String>) -> String {
+    match items.len() {
+        1 => items.into_iter().next().unwrap(),
+        _ => format!("<mrow>{}</mrow>", items.concat()),
+    }
+}
+
+fn greek(name: &str) -> Option<char> {
+    let letter = match name {
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        _ => return None,
+    };
+    Some(letter)
+}
+
+/// Symbols that are identifiers, like `\infty`.
+fn identifier(name: &str) -> Option<&'static str> {
+    let symbol = match name {
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "emptyset" | "varnothing" => "∅",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    let op = match name {
+        "times" => "×",
+        "cdot" => "⋅",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        "forall" => "∀",
+        "exists" => "∃",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "mid" => "|",
+        "|" => "‖",
+        "{" => "{",
+        "}" => "}",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "angle" => "∠",
+        "prime" => "′",
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Large operators, with whether their scripts are limits above and below.
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    let op = match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Named functions, with whether their scripts are limits.
+fn function(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "limsup" | "liminf" => Some(true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "arg" | "deg" | "dim" | "ker" | "hom"
+        | "Pr" => Some(false),
+        _ => None,
+    }
+}
+
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+    let accent = match name {
+        "hat" => ("^", false),
+        "widehat" => ("^", true),
+        "bar" => ("‾", false),
+        "overline" => ("‾", true),
+        "vec" => ("→", false),
+        "overrightarrow" => ("→", true),
+        "dot" => ("˙", false),
+        "ddot" => ("¨", false),
+        "tilde" => ("~", false),
+        "widetilde" => ("~", true),
+        _ => return None,
+    };
+    Some(accent)
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    let width = match name {
+        "," => "0.1667em",
+        ":" | ">" => "0.2222em",
+        ";" => "0.2778em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        "!" => "-0.1667em",
+        _ => return None,
+    };
+    Some(width)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Font {
+    Bold,
+    DoubleStruck,
+    Script,
+    Roman,
+}
+
+impl Font {
+    fn for_command(name: &str) -> Option<Self> {
+        match name {
+            "mathbf" | "boldsymbol" => Some(Font::Bold),
+            "mathbb" => Some(Font::DoubleStruck),
+            "mathcal" => Some(Font::Script),
+            "mathrm" => Some(Font::Roman),
+            _ => None,
+        }
+    }
+
+    /// `c` in the font, from the Mathematical Alphanumeric Symbols block,
+    /// or the letterlike symbols that are used instead of its holes.
+    fn apply(self, c: char) -> char {
+        let exception = match (self, c) {
+            (Font::DoubleStruck, 'C') => Some('ℂ'),
+            (Font::DoubleStruck, 'H') => Some('ℍ'),
+            (Font::DoubleStruck, 'N') => Some('ℕ'),
+            (Font::DoubleStruck, 'P') => Some('ℙ'),
+            (Font::DoubleStruck, 'Q') => Some('ℚ'),
+            (Font::DoubleStruck, 'R') => Some('ℝ'),
+            (Font::DoubleStruck, 'Z') => Some('ℤ'),
+            (Font::Script, 'B') => Some('ℬ'),
+            (Font::Script, 'E') => Some('ℰ'),
+            (Font::Script, 'F') => Some('ℱ'),
+            (Font::Script, 'H') => Some('ℋ'),
+            (Font::Script, 'I') => Some('ℐ'),
+            (Font::Script, 'L') => Some('ℒ'),
+            (Font::Script, 'M') => Some('ℳ'),
+            (Font::Script, 'R') => Some('ℛ'),
+            _ => None,
+        };
+        if let Some(exception) = exception {
+            return exception;
+        }
+        let (upper, lower, digit) = match self {
+            Font::Bold => (0x1D400, Some(0x1D41A), Some(0x1D7CE)),
+            Font::DoubleStruck => (0x1D538, Some(0x1D552), Some(0x1D7D8)),
+            Font::Script => (0x1D49C, None, None),
+            Font::Roman => return c,
+        };
+        let code = match c {
+            'A'..='Z' => Some(upper + (c as u32 - 'A' as u32)),
+            'a'..='z' => lower.map(|lower| lower + (c as u32 - 'a' as u32)),
+            '0'..='9' => digit.map(|digit| digit + (c as u32 - '0' as u32)),
+            _ => None,
+        };
+        code.and_then(char::from_u32).unwrap_or(c)
+    }
+}
+
+struct Parser<'a> {
+    tex: &'a str,
+    tokens: Vec<Token>,
+    offsets: Vec<usize>,
+    i: usize,
+    font: Option<Font>,
+}
+
+/// Where a row of a table ends.
+#[derive(Debug, PartialEq)]
+enum RowEnd {
+    Cell,
+    Row,
+    End,
+}
+
+type Table = Vec<Vec<String>>;
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.i)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.i).cloned();
+        self.i += 1;
+        token
+    }
+
+    fn expect_group(&mut self, command: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Open) => {
+                let group = self.group_text()?;
+                Ok(group)
+            }
+            _ => Err(format!("expected `{{` after `\\{}`", command)),
+        }
+    }
+
+    /// The source until the closing `}`, for commands like `\text`.
+    fn group_text(&mut self) -> Result<String, String> {
+        let start = self.offsets[self.i - 1] + 1;
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some(Token::Close) if depth == 0 => {
+                    return Ok(self.tex[start..self.offsets[self.i - 1]].to_string())
+                }
+                Some(Token::Close) => depth -= 1,
+                Some(Token::Open) => depth += 1,
+                Some(_) => {}
+                None => return Err("missing `}`".into()),
+            }
+        }
+    }
+
+    /// A row of atoms until `}`, `&`, `\\`, `\end`, `\right` or the end.
+    fn row(&mut self) -> Result<Vec<String>, String> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Close | Token::Ampersand) => return Ok(items),
+                Some(Token::Command(name)) if matches!(name.as_str(), "\\" | "end" | "right") => {
+                    return Ok(items)
+                }
+                _ => items.push(self.scripted()?),
+            }
+        }
+    }
+
+    /// An argument of a command or script: a group or a single atom.
+    fn argument(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Open) => {
+                self.next();
+                let row = self.row()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(mrow(row)),
+                    _ => Err("missing `}`".into()),
+                }
+            }
+            // Only the first digit of a number is an argument, as in `\frac12`
+            Some(Token::Number(number)) if number.chars().count() > 1 => {
+                let first = number.chars().next().unwrap();
+                self.tokens[self.i] = Token::Number(number[first.len_utf8()..].to_string());
+                Ok(format!("<mn>{}</mn>", first))
+            }
+            Some(_) => self.atom().map(|(atom, _)| atom),
+            None => Err("missing argument".into()),
+        }
+    }
+
+    /// An atom with its superscripts and subscripts, if any.
+    fn scripted(&mut self) -> Result<String, String> {
+        let (base, limits) = self.atom()?;
+        let (mut sub, mut sup) = (None, None);
+        loop {
+            match self.peek() {
+                Some(Token::Subscript) if sub.is_none() => {
+                    self.next();
+                    sub = Some(self.argument()?);
+                }
+                Some(Token::Superscript) if sup.is_none() => {
+                    self.next();
+                    sup = Some(self.argument()?);
+                }
+                Some(Token::Subscript | Token::Superscript) => {
+                    return Err("double subscript or superscript".into())
+                }
+                Some(Token::Symbol('\'')) if sup.is_none() => {
+                    self.next();
+                    sup = Some(mo("′"));
+                }
+                _ => break,
+            }
+        }
+        let (under, over, both) = match limits {
+            true => ("munder", "mover", "munderover"),
+            false => ("msub", "msup", "msubsup"),
+        };
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{under}>{base}{sub}</{under}>"),
+            (None, Some(sup)) => format!("<{over}>{base}{sup}</{over}>"),
+            (Some(sub), Some(sup)) => format!("<{both}>{base}{sub}{sup}</{both}>"),
+        })
+    }
+
+    fn letter(&self, c: char) -> String {
+        match self.font {
+            Some(Font::Roman) => format!(r#"<mi mathvariant="normal">{}</mi>"#, c),
+            Some(font) => mi(&font.apply(c).to_string()),
+            None => mi(&c.to_string()),
+        }
+    }
+
+    /// A single atom, with whether its scripts are limits.
+    fn atom(&mut self) -> Result<(String, bool), String> {
+        let token = self.next().ok_or("missing argument")?;
+        let atom = match token {
+            Token::Letter(c) => self.letter(c),
+            Token::Number(number) => match self.font {
+                Some(font) => format!(
+                    "<mn>{}</mn>",
+                    number.chars().map(|c| font.apply(c)).collect::<String>()
+                ),
+                None => format!("<mn>{}</mn>", number),
+            },
+            Token::Symbol(c) => {
+                let op = match c {
+                    '-' => '−',
+                    '*' => '∗',
+                    c => c,
+                };
+                mo(&op.to_string())
+            }
+            Token::Open => {
+                let row = self.row()?;
+                match self.next() {
+                    Some(Token::Close) => mrow(row),
+                    _ => return Err("missing `}`".into()),
+                }
+            }
+            Token::Close => return Err("unexpected `}`".into()),
+            Token::Superscript | Token::Subscript => {
+                return Err("script without a base".into());
+            }
+            Token::Ampersand => return Err("unexpected `&`".into()),
+            Token::Command(name) => return self.command(&name),
+        };
+        Ok((atom, false))
+    }
+
+    fn command(&mut self, name: &str) -> Result<(String, bool), String> {
+        if let Some(c) = greek(name) {
+            return Ok((mi(&c.to_string()), false));
+        }
+        if let Some(symbol) = identifier(name) {
+            return Ok((mi(symbol), false));
+        }
+        if let Some(op) = operator(name) {
+            return Ok((mo(op), false));
+        }
+        if let Some((op, limits)) = large_operator(name) {
+            return Ok((format!(r#"<mo largeop="true">{}</mo>"#, op), limits));
+        }
+        if let Some(limits) = function(name) {
+            return Ok((mi(name), limits));
+        }
+        if let Some(width) = space(name) {
+            return Ok((format!(r#"<mspace width="{}"/>"#, width), false));
+        }
+        if let Some(font) = Font::for_command(name) {
+            let outer = self.font.replace(font);
+            let argument = self.argument();
+            self.font = outer;
+            return Ok((argument?, false));
+        }
+        if let Some((accent, stretchy)) = accent(name) {
+            let base = self.argument()?;
+            return Ok((
+                format!(
+                    r#"<mover accent="true">{}<mo stretchy="{}">{}</mo></mover>"#,
+                    base, stretchy, accent
+                ),
+                false,
+            ));
+        }
+        let atom = match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let n = self.argument()?;
+                let k = self.argument()?;
+                format!(
+                    r#"<mrow><mo>(</mo><mfrac linethickness="0">{}{}</mfrac><mo>)</mo></mrow>"#,
+                    n, k
+                )
+            }
+            "sqrt" => {
+                let index = match self.peek() {
+                    Some(Token::Symbol('[')) => {
+                        self.next();
+                        let mut index = Vec::new();
+                        while !matches!(self.peek(), Some(Token::Symbol(']')) | None) {
+                            index.push(self.scripted()?);
+                        }
+                        self.next().ok_or("missing `]`")?;
+                        Some(mrow(index))
+                    }
+                    _ => None,
+                };
+                let radicand = self.argument()?;
+                match index {
+                    Some(index) => format!("<mroot>{}{}</mroot>", radicand, index),
+                    None => format!("<msqrt>{}</msqrt>", radicand),
+                }
+            }
+            "underline" => {
+                let base = self.argument()?;
+                format!(
+                    r#"<munder accentunder="true">{}<mo stretchy="true">_</mo></munder>"#,
+                    base
+                )
+            }
+            "operatorname" => mi(&self.expect_group(name)?),
+            "text" | "textrm" | "mbox" => {
+                let text = self.expect_group(name)?;
+                format!("<mtext>{}</mtext>", escaped(&text))
+            }
+            "left" => {
+                let open = self.delimiter()?;
+                let row = self.row()?;
+                match self.next() {
+                    Some(Token::Command(name)) if name == "right" => {}
+                    _ => return Err("`\\left` without `\\right`".into()),
+                }
+                let close = self.delimiter()?;
+                let fence = |d: String| match d.is_empty() {
+                    true => String::new(),
+                    false => format!(r#"<mo stretchy="true">{}</mo>"#, escaped(&d)),
+                };
+                format!(
+                    "<mrow>{}{}{}</mrow>",
+                    fence(open),
+                    row.concat(),
+                    fence(close)
+                )
+            }
+            "begin" => return self.environment().map(|table| (table, false)),
+            "$" | "%" | "#" | "&" | "_" => mo(name),
+            name => return Err(format!("unknown command `\\{}`", name)),
+        };
+        Ok((atom, false))
+    }
+
+    /// A delimiter after `\left` or `\right`, empty for `.`.
+    fn delimiter(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Symbol('.')) | Some(Token::Number(_)) => Ok(String::new()),
+            Some(Token::Symbol(c)) => Ok(c.to_string()),
+            Some(Token::Command(name)) => operator(&name)
+                .map(String::from)
+                .ok_or_else(|| format!("unknown delimiter `\\{}`", name)),
+            _ => Err("missing delimiter".into()),
+        }
+    }
+
+    /// The rows of cells of a table, until `\end` or the end.
+    fn table(&mut self) -> Result<Table, String> {
+        let mut rows = vec![Vec::new()];
+        loop {
+            let cell = self.row()?;
+            rows.last_mut().unwrap().push(mrow(cell));
+            let end = match self.peek() {
+                Some(Token::Ampersand) => RowEnd::Cell,
+                Some(Token::Command(name)) if name == "\\" => RowEnd::Row,
+                Some(Token::Close) => return Err("unexpected `}`".into()),
+                Some(Token::Command(name)) if name == "right" => {
+                    return Err("`\\right` without `\\left`".into())
+                }
+                _ => RowEnd::End,
+            };
+            match end {
+                RowEnd::Cell => {
+                    self.next();
+                }
+                RowEnd::Row => {
+                    self.next();
+                    rows.push(Vec::new());
+                }
+                RowEnd::End => break,
+            }
+        }
+        // A line break after the last row doesn't start another one
+        if rows.len() > 1
+            && rows
+                .last()
+                .is_some_and(|row| row.len() == 1 && row[0] == "<mrow></mrow>")
+        {
+            rows.pop();
+        }
+        Ok(rows)
+    }
+
+    fn environment(&mut self) -> Result<String, String> {
+        let name = self.expect_group("begin")?;
+        let rows = self.table()?;
+        match self.next() {
+            Some(Token::Command(end)) if end == "end" => {}
+            _ => return Err(format!("missing `\\end{{{}}}`", name)),
+        }
+        let end = self.expect_group("end")?;
+        if end != name {
+            return Err(format!("`\\begin{{{}}}` ended by `\\end{{{}}}`", name, end));
+        }
+        let (open, close, align) = match name.as_str() {
+            "matrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left")),
+            "aligned" | "align" | "align*" | "split" => ("", "", Some("right left")),
+            "gathered" | "gather" | "gather*" => ("", "", None),
+            name => return Err(format!("unknown environment `{}`", name)),
+        };
+        let table = mtable(rows, align);
+        let fence = |d: &str| match d.is_empty() {
+            true => String::new(),
+            false => format!(r#"<mo stretchy="true">{}</mo>"#, d),
+        };
+        Ok(format!(
+            "<mrow>{}{}{}</mrow>",
+            fence(open),
+            table,
+            fence(close)
+        ))
+    }
+}
+
+fn mtable(rows: Table, align: Option<&str>) -> String {
+    let mut table = match align {
+        Some(align) => format!(r#"<mtable columnalign="{}">"#, align),
+        None => "<mtable>".to_string(),
+    };
+    for row in rows {
+        table += "<mtr>";
+        for cell in row {
+            table += &format!("<mtd>{}</mtd>", cell);
+        }
+        table += "</mtr>";
+    }
+    table += "</mtable>";
+    table
+}
+
+/// Convert a LaTeX math expression, like `\frac{a}{2}`, to MathML. Lines
+/// of displayed math can be separated by `\\`, and aligned at `&`.
+///
+/// Only the commonly used part of LaTeX math is supported: fractions,
+/// roots, scripts, Greek letters, operators and relations, accents, fonts,
+/// `\left` and `\right`, `\text`, and matrix, cases and aligned
+/// environments. Unknown commands are errors.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let (tokens, offsets) = tokenize(tex).into_iter().unzip();
+    let mut parser = Parser {
+        tex,
+        tokens,
+        offsets,
+        i: 0,
+        font: None,
+    };
+    let rows = parser.table()?;
+    match parser.peek() {
+        None => {}
+        Some(Token::Command(name)) if name == "end" => return Err("unexpected `\\end`".into()),
+        Some(token) => return Err(format!("unexpected {:?}", token)),
+    }
+    let math = match rows.as_slice() {
+        [row] if row.len() == 1 => row[0].clone(),
+        _ => mtable(rows, Some("right left")),
+    };
+    let mut annotation = String::new();
+    escape_html(&mut annotation, tex.trim()).unwrap();
+    Ok(format!(
+        r#"<math{}><semantics>{}<annotation encoding="application/x-tex">{}</annotation></semantics></math>"#,
+        if display { r#" display="block""# } else { "" },
+        match math.starts_with("<mrow>") {
+            true => math,
+            false => format!("<mrow>{}</mrow>", math),
+        },
+        annotation
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The MathML without the `<math>` and `<semantics>` elements.
+    fn convert(tex: &str) -> String {
+        let mathml = to_mathml(tex, false).unwrap();
+        let start = mathml.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+        mathml[start..end].to_string()
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            convert("x^2 + 1"),
+            "<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mn>1</mn></mrow>"
+        );
+        assert_eq!(
+            convert(r"\frac{a-b}{2}"),
+            "<mrow><mfrac><mrow><mi>a</mi><mo>−</mo><mi>b</mi></mrow><mn>2</mn></mfrac></mrow>"
+        );
+        assert_eq!(
+            convert(r"\sum_{i=1}^n i"),
+            r#"<mrow><munderover><mo largeop="true">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><mi>i</mi></mrow>"#
+        );
+        assert_eq!(
+            convert(r"\sqrt[3]{x}\alpha\mathbb{R}"),
+            "<mrow><mroot><mi>x</mi><mn>3</mn></mroot><mi>α</mi><mi>ℝ</mi></mrow>"
+        );
+        assert_eq!(
+            convert(r"\text{if } x < y"),
+            "<mrow><mtext>if </mtext><mi>x</mi><mo>&lt;</mo><mi>y</mi></mrow>"
+        );
+        assert_eq!(
+            convert(r"\left( \frac12 \right."),
+            r#"<mrow><mo stretchy="true">(</mo><mfrac><mn>1</mn><mn>2</mn></mfrac></mrow>"#
+        );
+        assert_eq!(
+            convert(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            r#"<mrow><mo stretchy="true">(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable><mo stretchy="true">)</mo></mrow>"#
+        );
+        assert_eq!(
+            convert(r"\operatorname{sgn} x"),
+            "<mrow><mi>sgn</mi><mi>x</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn display() {
+        let mathml = to_mathml(r"a &= b \\ &= c \\", true).unwrap();
+        assert!(mathml.starts_with(r#"<math display="block"><semantics><mrow><mtable columnalign="right left"><mtr><mtd><mi>a</mi></mtd>"#));
+        assert_eq!(mathml.matches("<mtr>").count(), 2);
+        assert!(mathml.ends_with(
+            r#"<annotation encoding="application/x-tex">a &amp;= b \\ &amp;= c \\</annotation></semantics></math>"#
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            to_mathml(r"\foo", false),
+            Err("unknown command `\\foo`".into())
+        );
+        assert_eq!(to_mathml(r"\frac{a", false), Err("missing `}`".into()));
+        assert_eq!(to_mathml("a}", false), Err("unexpected `}`".into()));
+        assert_eq!(
+            to_mathml("x^2^3", false),
+            Err("double subscript or superscript".into())
+        );
+        assert_eq!(
+            to_mathml(r"\begin{matrix} a \end{cases}", false),
+            Err(r"`\begin{matrix}` ended by `\end{cases}`".into())
+        );
+    }
+}
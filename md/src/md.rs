@@ -1,127 +1,568 @@
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Tag};
-
-fn start_tag(tag: &Tag, parent_tags: &[Tag], event_count: u64) -> String {
-    match tag {
-        Tag::Heading(HeadingLevel::H1, _, _) => "# ".into(),
-        Tag::Heading(HeadingLevel::H2, _, _) => "## ".into(),
-        Tag::Heading(HeadingLevel::H3, _, _) => "### ".into(),
-        Tag::Heading(HeadingLevel::H4, _, _) => "#### ".into(),
-        Tag::Heading(HeadingLevel::H5, _, _) => "##### ".into(),
-        Tag::Heading(HeadingLevel::H6, _, _) => "###### ".into(),
-        Tag::CodeBlock(CodeBlockKind::Fenced(props)) => format!("```{}\n", props),
-        Tag::TableCell => "| ".into(),
-        Tag::Item => {
-            let level = parent_tags
-                .iter()
-                .filter(|tag| matches!(tag, Tag::Item))
-                .count();
-            let indent = (if event_count as usize + level == 1 {
-                ""
-            } else {
-                "\n"
-            })
-            .to_string()
-                + ("  ".repeat(level)).as_str();
-            match parent_tags.last() {
-                Some(Tag::List(Some(i))) => format!("{}{}. ", indent, i + event_count - 1),
-                Some(Tag::List(None)) => format!("{}- ", indent),
-                _ => unreachable!(),
+use crate::math_block::formula_end;
+use pulldown_cmark::{Alignment, CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, Tag};
+
+/// A container block, like a block quote or list item. Its prefix is
+/// written at the start of every line inside it.
+struct Container {
+    /// Written instead of `prefix` on the first line, like a list marker.
+    first: Option<String>,
+    prefix: String,
+    /// Whether blocks in the container are written without blank lines
+    /// between them, as in a tight list.
+    tight: bool,
+    has_blocks: bool,
+    /// Marker character of the previous block, if it was a list. Adjacent
+    /// lists must use different markers to not be joined into one list.
+    previous_list: Option<char>,
+}
+
+impl Container {
+    fn new(first: Option<String>, prefix: String, tight: bool) -> Self {
+        Container {
+            first,
+            prefix,
+            tight,
+            has_blocks: false,
+            previous_list: None,
+        }
+    }
+}
+
+struct List {
+    number: Option<u64>,
+    marker: char,
+}
+
+/// Writes markdown line by line, prefixing each line with the prefixes of
+/// the containers it is inside.
+struct Writer {
+    output: String,
+    containers: Vec<Container>,
+    /// Whether nothing, not even the prefixes, is written on the current line.
+    line_start: bool,
+}
+
+impl Writer {
+    fn write(&mut self, s: &str) {
+        for line in s.split_inclusive('\n') {
+            if self.line_start {
+                let prefix = self.take_prefix();
+                if line == "\n" {
+                    self.output += prefix.trim_end();
+                } else {
+                    self.output += &prefix;
+                }
+            }
+            self.output += line;
+            self.line_start = line.ends_with('\n');
+        }
+    }
+
+    fn take_prefix(&mut self) -> String {
+        self.containers
+            .iter_mut()
+            .map(|container| container.first.take().unwrap_or(container.prefix.clone()))
+            .collect()
+    }
+
+    fn marker_pending(&self) -> bool {
+        self.containers.iter().any(|c| c.first.is_some())
+    }
+
+    fn newline(&mut self) {
+        if !self.line_start {
+            self.write("\n");
+        }
+    }
+
+    /// Start a new block in the current container, separated from the
+    /// previous block by a blank line unless the container is tight.
+    fn begin_block(&mut self) {
+        self.newline();
+        let container = self.containers.last().unwrap();
+        if container.has_blocks && !container.tight {
+            self.write("\n");
+        }
+        let container = self.containers.last_mut().unwrap();
+        container.has_blocks = true;
+        container.previous_list = None;
+    }
+
+    /// End a container, writing its marker if it is empty.
+    fn end_container(&mut self) {
+        if !self.containers.last().unwrap().has_blocks {
+            let prefix = self.take_prefix();
+            self.output += prefix.trim_end();
+            self.output += "\n";
+            self.line_start = true;
+        }
+        self.newline();
+        self.containers.pop();
+    }
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+    )
+}
+
+fn heading_marker(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "# ",
+        HeadingLevel::H2 => "## ",
+        HeadingLevel::H3 => "### ",
+        HeadingLevel::H4 => "#### ",
+        HeadingLevel::H5 => "##### ",
+        HeadingLevel::H6 => "###### ",
+    }
+}
+
+/// Index of the event ending the tag started at `start`.
+fn end_of(events: &[Event], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, event) in events.iter().enumerate().skip(start) {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
             }
+            _ => {}
+        }
+    }
+    events.len()
+}
+
+/// A list is tight if none of its items contain paragraphs, as the text of
+/// items in tight lists isn't wrapped in paragraphs.
+fn is_tight(events: &[Event], start: usize) -> bool {
+    let mut depth = 0;
+    for event in &events[start + 1..end_of(events, start)] {
+        match event {
+            Event::Start(Tag::Paragraph) if depth == 1 => return false,
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
         }
-        Tag::Link(_, _, _) => "[".into(),
-        Tag::Strong => "**".into(),
-        Tag::Emphasis => "*".into(),
-        Tag::Strikethrough => "~~".into(),
-        _ => "".into(),
     }
+    true
+}
+
+fn longest_run(s: &str, c: char) -> usize {
+    s.split(|x| x != c).map(str::len).max().unwrap_or(0)
 }
 
-fn end_tag(tag: &Tag, parent_tags: &[Tag], _event_count: u64) -> String {
-    match tag {
-        Tag::Heading(_, None, _) => "\n\n".into(),
-        Tag::Heading(_, Some(id), _) => format!(" {{ #{} }}\n\n", id),
-        Tag::CodeBlock(CodeBlockKind::Fenced(_)) => "```\n\n".into(),
-        Tag::CodeBlock(CodeBlockKind::Indented) => "\n".into(),
-        Tag::Table(_) => "\n".into(),
-        Tag::TableHead => {
-            let Some(Tag::Table(table)) = parent_tags.last() else {
-                panic!();
-            };
-            "|\n".to_string()
-                + table
-                    .iter()
-                    .map(|_| "|---".to_string())
-                    .collect::<String>()
-                    .as_str()
-                + "|\n"
+fn code_span(code: &str, table: bool) -> String {
+    let ticks = "`".repeat(longest_run(code, '`') + 1);
+    let pad = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+    let pad = if pad { " " } else { "" };
+    let code = if table {
+        code.replace('|', "\\|")
+    } else {
+        code.to_string()
+    };
+    format!("{0}{1}{2}{1}{0}", ticks, pad, code)
+}
+
+/// Whether `s` starts with something that would be read as an entity or
+/// numeric character reference.
+fn is_entity(s: &str) -> bool {
+    let Some(end) = s.find(';') else {
+        return false;
+    };
+    let name = s[1..end].strip_prefix('#').unwrap_or(&s[1..end]);
+    !name.is_empty() && name.len() <= 32 && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn link_destination(dest: &str) -> String {
+    let mut output = String::with_capacity(dest.len() + 2);
+    let bracketed = dest.is_empty() || dest.contains(|c: char| c == ' ' || c.is_control());
+    if bracketed {
+        output.push('<');
+    }
+    for (i, c) in dest.char_indices() {
+        let escape = match c {
+            '\\' | '<' | '>' => true,
+            '(' | ')' => !bracketed,
+            '&' => is_entity(&dest[i..]),
+            _ => false,
+        };
+        if escape {
+            output.push('\\');
         }
-        Tag::TableRow => "|\n".into(),
-        Tag::TableCell => " ".into(),
-        Tag::List(_) if !matches!(parent_tags.last(), Some(Tag::Item)) => "\n\n".into(),
-        Tag::Link(_, dest, _) => format!("]({})", dest),
-        Tag::Paragraph => "\n\n".into(),
-        Tag::Strong => "**".into(),
-        Tag::Emphasis => "*".into(),
-        Tag::Strikethrough => "~~".into(),
-        _ => "".into(),
+        output.push(c);
     }
+    if bracketed {
+        output.push('>');
+    }
+    output
 }
 
-pub fn render<'a>(events: impl Iterator<Item = Event<'a>>) -> String {
-    let mut md_output = String::new();
+fn link_title(title: &str) -> String {
+    let mut output = String::with_capacity(title.len() + 3);
+    output += " \"";
+    for (i, c) in title.char_indices() {
+        if c == '"' || c == '\\' || (c == '&' && is_entity(&title[i..])) {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+    output.push('"');
+    output
+}
 
-    let mut tag_stack = Vec::new();
-    let mut event_count = Vec::new();
+/// Escape characters in `text` that would otherwise be read as markdown.
+/// Some characters, like `#` and `-`, only have to be escaped at the
+/// start of a line. Line breaks, and whitespace that would be stripped at
+/// the start of a line, are written as character references.
+fn escape_text(text: &str, line_start: bool, table: bool, heading: bool) -> String {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut output = String::with_capacity(text.len() + 8);
+    let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        let before = i.checked_sub(1).map(|i| chars[i].1);
+        let after = chars.get(i + 1).map(|(_, c)| *c);
+        if c == '\n' || (line_start && i == 0 && (c == ' ' || c == '\t')) {
+            output += &format!("&#{};", c as u32);
+            continue;
+        }
+        let escape = match c {
+            '\\' | '`' | '[' | ']' | '<' => true,
+            // Emphasis can't start or end between whitespace, and `_` can't
+            // start or end emphasis inside a word.
+            '*' | '_' => match (before, after) {
+                (Some(b), Some(a)) if b.is_whitespace() && a.is_whitespace() => false,
+                (Some(b), Some(a)) if c == '_' => !(b.is_alphanumeric() && a.is_alphanumeric()),
+                _ => true,
+            },
+            '&' => is_entity(&text[offset..]),
+            '|' => table,
+            '~' => (line_start && i == 0) || after == Some('~') || before == Some('~'),
+            '#' => (line_start && i == 0) || (heading && i + 1 == chars.len()),
+            '>' | '-' | '+' | '=' => line_start && i == 0,
+            '.' | ')' => line_start && i == digits && digits > 0 && digits < 10,
+            // `$` that would start inline math, see `MathSpans`
+            '$' => formula_end(text, offset).is_some(),
+            _ => false,
+        };
+        if escape {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+    output
+}
 
+/// Merge adjacent text events, so text can be escaped with the characters
+/// around it in mind.
+fn merge_text<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut merged: Vec<Event<'a>> = Vec::new();
     for event in events {
-        if let Some(n) = event_count.last_mut() {
-            *n += 1;
+        match (merged.last_mut(), event) {
+            (Some(Event::Text(previous)), Event::Text(text)) => {
+                *previous = CowStr::from(format!("{}{}", previous, text));
+            }
+            (_, event) => merged.push(event),
+        }
+    }
+    merged
+}
+
+pub fn render<'a>(events: impl Iterator<Item = Event<'a>>, capacity: usize) -> String {
+    let events = merge_text(events);
+
+    let mut writer = Writer {
+        output: String::with_capacity(capacity),
+        containers: vec![Container::new(None, String::new(), false)],
+        line_start: true,
+    };
+    let mut tag_stack: Vec<&Tag> = Vec::new();
+    let mut lists: Vec<List> = Vec::new();
+    // The fence of each open code block, or `None` if it is indented
+    let mut fences: Vec<Option<String>> = Vec::new();
+    let mut emphasis: Vec<&str> = Vec::new();
+    let mut setext = false;
+    let mut table_alignments: &[Alignment] = &[];
+    // Text directly in a tight list item, which isn't wrapped in a paragraph
+    let mut implicit_paragraph = false;
+    let mut html_block = false;
+
+    for (i, event) in events.iter().enumerate() {
+        let innermost_block = tag_stack.iter().rev().find(|tag| !is_inline(tag)).copied();
+        let in_html_block = std::mem::take(&mut html_block);
+        let is_block = match event {
+            Event::Start(tag) | Event::End(tag) => !is_inline(tag),
+            Event::Html(html) => {
+                !matches!(
+                    innermost_block,
+                    Some(Tag::Paragraph | Tag::Heading(..) | Tag::TableCell)
+                ) && (html.ends_with('\n') || !matches!(innermost_block, Some(Tag::Item)))
+            }
+            Event::Rule => true,
+            _ => false,
+        };
+        if !is_block && matches!(innermost_block, Some(Tag::Item)) && !implicit_paragraph {
+            writer.begin_block();
+            implicit_paragraph = true;
+        }
+        if matches!(event, Event::Start(tag) if !is_inline(tag)) {
+            implicit_paragraph = false;
         }
+
         match event {
             Event::Start(tag) => {
-                md_output +=
-                    start_tag(&tag, &tag_stack, event_count.last().copied().unwrap_or(0)).as_str();
+                match tag {
+                    Tag::Paragraph => writer.begin_block(),
+                    Tag::Heading(level, _, _) => {
+                        writer.begin_block();
+                        setext = matches!(level, HeadingLevel::H1 | HeadingLevel::H2)
+                            && events[i..end_of(&events, i)]
+                                .iter()
+                                .any(|e| matches!(e, Event::SoftBreak | Event::HardBreak));
+                        if !setext {
+                            writer.write(heading_marker(*level));
+                        }
+                    }
+                    Tag::BlockQuote => {
+                        writer.begin_block();
+                        writer
+                            .containers
+                            .push(Container::new(None, "> ".into(), false));
+                    }
+                    // An indented code block following a list would be read as
+                    // part of the last list item, so it is fenced instead.
+                    Tag::CodeBlock(CodeBlockKind::Indented)
+                        if writer.containers.last().unwrap().previous_list.is_none() =>
+                    {
+                        writer.begin_block();
+                        writer
+                            .containers
+                            .push(Container::new(None, "    ".into(), false));
+                        fences.push(None);
+                    }
+                    Tag::CodeBlock(kind) => {
+                        writer.begin_block();
+                        let info = match kind {
+                            CodeBlockKind::Fenced(info) => info.as_ref(),
+                            CodeBlockKind::Indented => "",
+                        };
+                        let code = events[i..end_of(&events, i)]
+                            .iter()
+                            .filter_map(|e| match e {
+                                Event::Text(text) => Some(text.as_ref()),
+                                _ => None,
+                            })
+                            .collect::<String>();
+                        let c = if info.contains('`') { '~' } else { '`' };
+                        let fence = c.to_string().repeat(longest_run(&code, c).max(2) + 1);
+                        writer.write(&fence);
+                        writer.write(info);
+                        writer.write("\n");
+                        fences.push(Some(fence));
+                    }
+                    Tag::List(start) => {
+                        let previous = writer.containers.last().unwrap().previous_list;
+                        writer.begin_block();
+                        let marker = match (start, previous) {
+                            (None, Some('-')) => '*',
+                            (None, _) => '-',
+                            (Some(_), Some('.')) => ')',
+                            (Some(_), _) => '.',
+                        };
+                        lists.push(List {
+                            number: *start,
+                            marker,
+                        });
+                        writer.containers.push(Container::new(
+                            None,
+                            String::new(),
+                            is_tight(&events, i),
+                        ));
+                    }
+                    Tag::Item => {
+                        writer.begin_block();
+                        let list = lists.last_mut().unwrap();
+                        let marker = match &mut list.number {
+                            Some(n) => {
+                                *n += 1;
+                                format!("{}{} ", *n - 1, list.marker)
+                            }
+                            None => format!("{} ", list.marker),
+                        };
+                        let tight = writer.containers.last().unwrap().tight;
+                        let prefix = " ".repeat(marker.len());
+                        writer
+                            .containers
+                            .push(Container::new(Some(marker), prefix, tight));
+                    }
+                    Tag::FootnoteDefinition(id) => {
+                        writer.begin_block();
+                        writer.containers.push(Container::new(
+                            Some(format!("[^{}]: ", id)),
+                            "    ".into(),
+                            false,
+                        ));
+                    }
+                    Tag::Table(alignments) => {
+                        writer.begin_block();
+                        table_alignments = alignments;
+                    }
+                    Tag::TableHead | Tag::TableRow => {}
+                    Tag::TableCell => writer.write("| "),
+                    Tag::Emphasis => {
+                        // `**` would be read as strong emphasis
+                        let marker = if writer.output.ends_with('*') {
+                            "_"
+                        } else {
+                            "*"
+                        };
+                        writer.write(marker);
+                        emphasis.push(marker);
+                    }
+                    Tag::Strong => writer.write("**"),
+                    Tag::Strikethrough => writer.write("~~"),
+                    Tag::Link(LinkType::Autolink | LinkType::Email, _, _) => writer.write("<"),
+                    Tag::Link(_, _, _) => writer.write("["),
+                    Tag::Image(_, _, _) => writer.write("!["),
+                }
                 tag_stack.push(tag);
-                event_count.push(0);
             }
             Event::End(tag) => {
                 tag_stack.pop();
-                event_count.pop();
-                md_output +=
-                    end_tag(&tag, &tag_stack, event_count.last().copied().unwrap_or(0)).as_str();
-            }
-            Event::Text(text) => match (tag_stack.first(), tag_stack.last()) {
-                (_, Some(Tag::CodeBlock(CodeBlockKind::Indented))) => {
-                    md_output += &format!("    {}", text);
+                match tag {
+                    Tag::Paragraph => writer.newline(),
+                    Tag::Heading(level, id, classes) => {
+                        if id.is_some() || !classes.is_empty() {
+                            let attributes = id
+                                .iter()
+                                .map(|id| format!("#{}", id))
+                                .chain(classes.iter().map(|class| format!(".{}", class)))
+                                .collect::<Vec<_>>();
+                            writer.write(&format!(" {{ {} }}", attributes.join(" ")));
+                        }
+                        writer.newline();
+                        if setext {
+                            writer.write(match level {
+                                HeadingLevel::H1 => "===\n",
+                                _ => "---\n",
+                            });
+                        }
+                    }
+                    Tag::BlockQuote => writer.end_container(),
+                    Tag::CodeBlock(_) => {
+                        writer.newline();
+                        match fences.pop().unwrap() {
+                            Some(fence) => {
+                                writer.write(&fence);
+                                writer.newline();
+                            }
+                            None => {
+                                writer.containers.pop();
+                            }
+                        }
+                    }
+                    Tag::List(_) => {
+                        writer.containers.pop();
+                        let list = lists.pop().unwrap();
+                        writer.containers.last_mut().unwrap().previous_list = Some(list.marker);
+                    }
+                    Tag::Item | Tag::FootnoteDefinition(_) => {
+                        implicit_paragraph = false;
+                        writer.end_container();
+                    }
+                    Tag::Table(_) => {}
+                    Tag::TableHead => {
+                        writer.write("|\n");
+                        for alignment in table_alignments {
+                            writer.write(match alignment {
+                                Alignment::None => "|---",
+                                Alignment::Left => "|:---",
+                                Alignment::Center => "|:---:",
+                                Alignment::Right => "|---:",
+                            });
+                        }
+                        writer.write("|\n");
+                    }
+                    Tag::TableRow => writer.write("|\n"),
+                    Tag::TableCell => writer.write(" "),
+                    Tag::Emphasis => writer.write(emphasis.pop().unwrap()),
+                    Tag::Strong => writer.write("**"),
+                    Tag::Strikethrough => writer.write("~~"),
+                    Tag::Link(LinkType::Autolink | LinkType::Email, _, _) => writer.write(">"),
+                    Tag::Link(_, dest, title) | Tag::Image(_, dest, title) => {
+                        writer.write("](");
+                        writer.write(&link_destination(dest));
+                        if !title.is_empty() {
+                            writer.write(&link_title(title));
+                        }
+                        writer.write(")");
+                    }
                 }
-                (Some(Tag::BlockQuote), _) => {
-                    md_output += &format!("> {}", text);
+            }
+            Event::Text(text) => match innermost_block {
+                Some(Tag::CodeBlock(_)) => writer.write(text),
+                _ if matches!(
+                    tag_stack.last(),
+                    Some(Tag::Link(LinkType::Autolink | LinkType::Email, _, _))
+                ) =>
+                {
+                    writer.write(text)
                 }
                 _ => {
-                    md_output += &text;
+                    let mut escaped = escape_text(
+                        text,
+                        writer.line_start,
+                        matches!(innermost_block, Some(Tag::TableCell)),
+                        matches!(innermost_block, Some(Tag::Heading(..))),
+                    );
+                    // `![` would start an image
+                    if escaped.ends_with('!')
+                        && matches!(events.get(i + 1), Some(Event::Start(Tag::Link(..))))
+                    {
+                        escaped.insert(escaped.len() - 1, '\\');
+                    }
+                    writer.write(&escaped);
                 }
             },
-            Event::Code(text) => {
-                md_output += "`";
-                md_output += &text;
-                md_output += "`";
-            }
-            Event::SoftBreak => {
-                md_output += "\n";
-            }
-            Event::HardBreak => {
-                md_output += "\n\n";
+            Event::Code(code) => writer.write(&code_span(
+                code,
+                matches!(innermost_block, Some(Tag::TableCell)),
+            )),
+            Event::Html(html) => {
+                if is_block && !in_html_block {
+                    writer.begin_block();
+                }
+                html_block = is_block;
+                writer.write(html);
             }
+            Event::SoftBreak => writer.write("\n"),
+            Event::HardBreak => writer.write("\\\n"),
             Event::Rule => {
-                md_output += "-----\n";
-            }
-            Event::Html(html) => {
-                md_output += &html;
+                writer.begin_block();
+                // A list item containing only `-----` would be read as a rule
+                writer.write(if writer.marker_pending() {
+                    "___"
+                } else {
+                    "-----"
+                });
+                writer.newline();
             }
-            _ => todo!("{:?}", event),
+            Event::FootnoteReference(id) => writer.write(&format!("[^{}]", id)),
+            Event::TaskListMarker(checked) => writer.write(if *checked { "[x] " } else { "[ ] " }),
         }
     }
 
+    let mut md_output = writer.output;
+    // A trailing blank line would be part of an html block
+    if md_output.ends_with('\n') && !md_output.ends_with("\n\n") && !html_block {
+        md_output.push('\n');
+    }
     md_output
 }
 
@@ -227,7 +668,7 @@ code block with props
         let md_options = Options::all();
         for tag in tags {
             let parser = Parser::new_ext(tag, md_options);
-            let output = render(parser);
+            let output = render(parser, 0);
             assert_eq!(tag, output);
         }
     }
@@ -288,7 +729,7 @@ block
         let md_options = Options::all();
         for document in documents {
             let parser = Parser::new_ext(document, md_options);
-            let output = render(parser);
+            let output = render(parser, 0);
             assert_eq!(document, output);
         }
     }
@@ -0,0 +1,319 @@
+use crate::diff::{edits, Edit};
+use crate::elements::{elements, Element, ElementKind};
+use std::ops::Range;
+
+/// The result of `merge_documents`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedDocument {
+    /// The merged document, with conflicting blocks between conflict
+    /// markers, like the ones written by git.
+    pub markdown: String,
+    pub conflicts: usize,
+}
+
+/// The source of a top level element of a document.
+struct Source<'a> {
+    markdown: &'a str,
+    /// The `id` in the header of a custom block.
+    id: Option<String>,
+}
+
+/// Split a document into the source of its top level elements, the same
+/// elements as the blocks of `render_blocks`, with the frontmatter as an
+/// element of its own. Each element includes the blank lines after it, so
+/// the elements joined are the document.
+fn element_sources(markdown: &str) -> Vec<Source<'_>> {
+    let mut elements = elements(markdown);
+    let offset = match elements.first() {
+        Some(element) if element.kind == ElementKind::Frontmatter => element.range.end,
+        _ => 0,
+    };
+    // Text before the first element, like link definitions, is part of it
+    match elements
+        .iter_mut()
+        .find(|element| element.kind != ElementKind::Frontmatter)
+    {
+        Some(first) => first.range.start = offset,
+        None if offset < markdown.len() => elements.push(Element {
+            kind: ElementKind::Text,
+            range: offset..markdown.len(),
+            id: None,
+        }),
+        None => {}
+    }
+    let ends: Vec<_> = elements
+        .iter()
+        .skip(1)
+        .map(|element| element.range.start)
+        .chain([markdown.len()])
+        .collect();
+    elements
+        .into_iter()
+        .zip(ends)
+        .map(|(element, end)| Source {
+            markdown: &markdown[element.range.start..end],
+            id: element.id,
+        })
+        .collect()
+}
+
+/// The sources compared when merging, as differences in the blank lines
+/// between elements aren't changes.
+fn keys<'a>(sources: &[Source<'a>]) -> Vec<&'a str> {
+    sources
+        .iter()
+        .map(|source| source.markdown.trim_end())
+        .collect()
+}
+
+/// What elements are matched by when aligning the versions of a document.
+#[derive(PartialEq)]
+enum Identity<'a> {
+    /// The `id` of a custom block, so a block is matched to its edited
+    /// version.
+    Id(&'a str),
+    Source(&'a str),
+}
+
+fn identities<'a>(sources: &'a [Source], keys: &[&'a str]) -> Vec<Identity<'a>> {
+    sources
+        .iter()
+        .zip(keys)
+        .map(|(source, key)| match &source.id {
+            Some(id) => Identity::Id(id),
+            None => Identity::Source(key),
+        })
+        .collect()
+}
+
+/// For each element of `base`, the index of the same element in `other`,
+/// if it is unchanged, or a custom block with the same id.
+fn unchanged(base: &[Identity], other: &[Identity]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for edit in edits(base, other) {
+        if let Edit::Equal(i, j) = edit {
+            matches[i] = Some(j);
+        }
+    }
+    matches
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+/// A merged document while it is written.
+struct Merge<'a> {
+    ours: &'a [&'a str],
+    theirs: &'a [&'a str],
+    merged: MergedDocument,
+    /// The last element written, if it wasn't a conflict.
+    last: Option<(Side, usize)>,
+}
+
+impl<'a> Merge<'a> {
+    fn push(&mut self, side: Side, index: usize) {
+        let source = match side {
+            Side::Ours => self.ours[index],
+            Side::Theirs => self.theirs[index],
+        };
+        // Elements that didn't follow each other might not be separated by
+        // a blank line, like the last element of a document and a new one
+        let markdown = &mut self.merged.markdown;
+        let follows = index > 0 && self.last == Some((side, index - 1));
+        if !markdown.is_empty() && !follows {
+            while !markdown.ends_with("\n\n") {
+                markdown.push('\n');
+            }
+        }
+        markdown.push_str(source);
+        self.last = Some((side, index));
+    }
+
+    fn push_conflict(&mut self, ours: Range<usize>, theirs: Range<usize>) {
+        let markdown = &mut self.merged.markdown;
+        if !markdown.is_empty() {
+            while !markdown.ends_with("\n\n") {
+                markdown.push('\n');
+            }
+        }
+        for (marker, side) in [
+            ("<<<<<<< ours\n", &self.ours[ours]),
+            ("=======\n", &self.theirs[theirs]),
+        ] {
+            markdown.push_str(marker);
+            let side = side.concat();
+            if !side.trim_end().is_empty() {
+                markdown.push_str(side.trim_end());
+                markdown.push('\n');
+            }
+        }
+        markdown.push_str(">>>>>>> theirs\n\n");
+        self.merged.conflicts += 1;
+        self.last = None;
+    }
+}
+
+/// A three-way merge of two versions of a document, `ours` and `theirs`,
+/// both changed from `base`, for example as a git merge driver.
+///
+/// The documents are merged by top level element instead of by line, like
+/// the blocks of `render_blocks`, matched by their source, or by the `id`
+/// of custom blocks. Elements changed in only one of the versions are taken
+/// from that version. If the same elements are changed differently in both
+/// versions, both are kept between conflict markers, so a conflict covers
+/// whole paragraphs, tables and custom blocks.
+pub fn merge_documents(base: &str, ours: &str, theirs: &str) -> MergedDocument {
+    let (base, ours, theirs) = (
+        element_sources(base),
+        element_sources(ours),
+        element_sources(theirs),
+    );
+    let (base_keys, ours_keys, theirs_keys) = (keys(&base), keys(&ours), keys(&theirs));
+    let base_ids = identities(&base, &base_keys);
+    let in_ours = unchanged(&base_ids, &identities(&ours, &ours_keys));
+    let in_theirs = unchanged(&base_ids, &identities(&theirs, &theirs_keys));
+    let ours: Vec<_> = ours.iter().map(|source| source.markdown).collect();
+    let theirs: Vec<_> = theirs.iter().map(|source| source.markdown).collect();
+
+    let mut merge = Merge {
+        ours: &ours,
+        theirs: &theirs,
+        merged: MergedDocument {
+            markdown: String::new(),
+            conflicts: 0,
+        },
+        last: None,
+    };
+    // Picks the version of elements base[i..b], as ours[j..o] and
+    // theirs[k..t]
+    let resolve =
+        |merge: &mut Merge, (i, b): (usize, usize), ours: Range<usize>, theirs: Range<usize>| {
+            let ours_changed = ours_keys[ours.clone()] != base_keys[i..b];
+            let theirs_changed = theirs_keys[theirs.clone()] != base_keys[i..b];
+            if !theirs_changed || ours_keys[ours.clone()] == theirs_keys[theirs.clone()] {
+                ours.for_each(|o| merge.push(Side::Ours, o));
+            } else if !ours_changed {
+                theirs.for_each(|t| merge.push(Side::Theirs, t));
+            } else {
+                merge.push_conflict(ours, theirs);
+            }
+        };
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // The next element that is in both versions, or the end of all of
+        // them
+        let stable = (i..base.len()).find_map(|b| Some((b, in_ours[b]?, in_theirs[b]?)));
+        let (b, o, t) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        if b - i == o - j && b - i == t - k {
+            // Elements edited in place are merged one by one, so edits of
+            // neighbouring paragraphs don't conflict
+            for n in 0..b - i {
+                resolve(
+                    &mut merge,
+                    (i + n, i + n + 1),
+                    j + n..j + n + 1,
+                    k + n..k + n + 1,
+                );
+            }
+        } else {
+            resolve(&mut merge, (i, b), j..o, k..t);
+        }
+
+        if b == base.len() {
+            break;
+        }
+        // A custom block with an id might be edited in either version
+        resolve(&mut merge, (b, b + 1), o..o + 1, t..t + 1);
+        (i, j, k) = (b + 1, o + 1, t + 1);
+    }
+    merge.merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements() {
+        let markdown = "---\ntitle: a\n---\n[link]: x\n# Title\nText\nmore.\n\n```{\"t\":\"Log\"}\n```\n\n- a\n- b";
+        let sources = |markdown| -> Vec<&str> {
+            element_sources(markdown)
+                .iter()
+                .map(|source| source.markdown)
+                .collect()
+        };
+        assert_eq!(
+            sources(markdown),
+            vec![
+                "---\ntitle: a\n---\n",
+                "[link]: x\n# Title\n",
+                "Text\nmore.\n\n",
+                "```{\"t\":\"Log\"}\n```\n\n",
+                "- a\n- b"
+            ]
+        );
+        assert_eq!(sources(markdown).concat(), markdown);
+        assert!(sources("").is_empty());
+        assert_eq!(sources("A\n\n<div>x</div>"), vec!["A\n\n", "<div>x</div>"]);
+    }
+
+    #[test]
+    fn merge() {
+        let base = "# Title\n\nFirst.\n\nSecond.\n\nThird.\n";
+        let ours = "# Title\n\nFirst, edited.\n\nSecond.\n\nThird.\n";
+        let theirs = "# New title\n\nFirst.\n\nSecond.\n\nThird.\n\nFourth.";
+        assert_eq!(
+            merge_documents(base, ours, theirs),
+            MergedDocument {
+                markdown: "# New title\n\nFirst, edited.\n\nSecond.\n\nThird.\n\nFourth.".into(),
+                conflicts: 0,
+            }
+        );
+
+        // The same edit in both versions isn't a conflict
+        assert_eq!(merge_documents(base, ours, ours).markdown, ours);
+
+        let theirs = "# Title\n\nFirst, also edited.\n\nSecond.\n";
+        let merged = merge_documents(base, ours, theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.markdown,
+            "# Title\n\n<<<<<<< ours\nFirst, edited.\n=======\nFirst, also edited.\n>>>>>>> theirs\n\nSecond.\n\n"
+        );
+
+        // An html block isn't part of the paragraph before it
+        let base = "A\n\n<div>x</div>\n";
+        let ours = "A, edited.\n\n<div>x</div>\n";
+        let theirs = "A\n\n<div>y</div>\n";
+        assert_eq!(
+            merge_documents(base, ours, theirs),
+            MergedDocument {
+                markdown: "A, edited.\n\n<div>y</div>\n".into(),
+                conflicts: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn merge_by_id() {
+        let base = "A\n\n```{t: Log, id: log}\nfirst\n```\n\nB\n";
+        // Both the block and the paragraphs around it are edited
+        let ours = "A, edited.\n\n```{t: Log, id: log}\nfirst\nsecond\n```\n\nB\n";
+        let theirs = "A\n\n```{t: Log, id: log}\nfirst\n```\n\nB, edited.\n";
+        assert_eq!(
+            merge_documents(base, ours, theirs),
+            MergedDocument {
+                markdown: "A, edited.\n\n```{t: Log, id: log}\nfirst\nsecond\n```\n\nB, edited.\n"
+                    .into(),
+                conflicts: 0,
+            }
+        );
+
+        let theirs = "A\n\n```{t: Log, id: log}\nother\n```\n\nB\n";
+        assert_eq!(merge_documents(base, ours, theirs).conflicts, 1);
+    }
+}
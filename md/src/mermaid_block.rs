@@ -0,0 +1,257 @@
+use crate::{
+    svg_size::SvgSize, utils::accessible_svg, CustomBlock, CustomBlockHeader, CustomBlockReader,
+    Error, FieldType, Format, HeaderField, Result, YamdrOptions,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Command rendering Mermaid diagrams to svg with mermaid-cli, for
+/// `YamdrOptions::mermaid_command`.
+pub const MERMAID_COMMAND: &[&str] = &["mmdc", "--input", "-", "--output", "-", "--quiet"];
+
+/// Script rendering the diagrams of Mermaid blocks in the browser, included
+/// after the first block that isn't rendered to svg.
+const MERMAID_SCRIPT: &str = r#"<script type="module">
+import mermaid from "https://cdn.jsdelivr.net/npm/mermaid@11/dist/mermaid.esm.min.mjs";
+mermaid.initialize({ startOnLoad: true });
+</script>"#;
+
+/// A Mermaid diagram, like a flowchart or a sequence diagram.
+///
+/// ````text
+/// ```{t: Mermaid, alt: Login flow}
+/// sequenceDiagram
+///     Browser->>Server: POST /login
+///     Server-->>Browser: 302 /home
+/// ```
+/// ````
+///
+/// With `YamdrOptions::mermaid_command`, the diagram is rendered to svg
+/// when the block is read. Otherwise, or if the command isn't installed,
+/// the diagram is rendered in the browser by mermaid.js.
+#[derive(Debug, Clone)]
+pub struct MermaidBlock {
+    header: CustomBlockHeader,
+    input: String,
+    size: SvgSize,
+    svg: Option<String>,
+    /// Whether `MERMAID_SCRIPT` is included after the diagram.
+    script: bool,
+}
+
+pub struct MermaidBlockReader {
+    command: Vec<String>,
+    /// Whether `MERMAID_SCRIPT` has been included by a block already.
+    script: bool,
+}
+
+impl MermaidBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        MermaidBlockReader {
+            command: options.mermaid_command.clone(),
+            script: false,
+        }
+    }
+}
+
+/// Render `diagram` to svg with `command`, which reads the diagram from
+/// stdin and writes the svg to stdout. `None` if the command isn't found.
+fn render_svg(command: &[String], diagram: &str) -> Result<Option<String>> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(None);
+    };
+    let spawned = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(Error::CustomBlockRead(format!(
+                "couldn't run `{}`: {}",
+                program, err
+            )))
+        }
+    };
+    // Written from another thread, so a command writing before it has read
+    // all of stdin doesn't block
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let diagram = diagram.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(diagram.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|err| Error::CustomBlockRead(format!("couldn't run `{}`: {}", program, err)))?;
+    let _ = writer.join();
+    if !output.status.success() {
+        let printed = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::CustomBlockRead(format!(
+            "`{}` failed: {}",
+            program,
+            printed.trim_end()
+        )));
+    }
+    let svg = String::from_utf8_lossy(&output.stdout);
+    match svg.find("<svg") {
+        Some(start) => Ok(Some(svg[start..].trim_end().to_string())),
+        None => Err(Error::CustomBlockRead(format!(
+            "`{}` didn't write an svg",
+            program
+        ))),
+    }
+}
+
+impl CustomBlockReader for MermaidBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Mermaid"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Mermaid"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("accessibility", FieldType::String),
+            HeaderField::new("scale", FieldType::Scalar),
+            HeaderField::new("max_width", FieldType::Scalar),
+            HeaderField::new("fit", FieldType::Bool),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let size = SvgSize::from_header(header).map_err(Error::CustomBlockRead)?;
+        let svg = render_svg(&self.command, input)?;
+        let script = svg.is_none() && !self.script;
+        self.script |= script;
+        Ok(Some(Box::new(MermaidBlock {
+            header: header.clone(),
+            input: input.into(),
+            size,
+            svg,
+            script,
+        })))
+    }
+}
+
+impl CustomBlock for MermaidBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let html = match &self.svg {
+                    Some(svg) => accessible_svg(self.size.apply(svg), &self.header),
+                    None => {
+                        let mut html = String::from(r#"<pre class="mermaid""#);
+                        if let Some(alt) = self.header.fields.get("alt").and_then(|v| v.as_str()) {
+                            html += r#" role="img" aria-label=""#;
+                            escape_html(&mut html, alt).unwrap();
+                            html += "\"";
+                        }
+                        html += ">";
+                        escape_html(&mut html, &self.input).unwrap();
+                        html += "</pre>";
+                        if self.script {
+                            html += MERMAID_SCRIPT;
+                        }
+                        html
+                    }
+                };
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{custom_block, render_html};
+
+    #[test]
+    fn client_side() {
+        let options = YamdrOptions {
+            mermaid_command: vec!["yamdr-no-such-command".into()],
+            ..Default::default()
+        };
+        let document = format!(
+            "{}\n{}",
+            custom_block("{t: Mermaid, alt: Flow}", "graph TD\n  A-->B\n"),
+            custom_block("{t: Mermaid}", "graph TD\n  A-->B\n")
+        );
+        let html = render_html(&options, &document).unwrap();
+        assert!(html.starts_with(
+            r#"<pre class="mermaid" role="img" aria-label="Flow">graph TD
+  A--&gt;B
+</pre><script type="module">"#
+        ));
+        // The script is only included once
+        assert_eq!(html.matches("<pre class=\"mermaid\"").count(), 2);
+        assert_eq!(html.matches("<script").count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn server_side() {
+        let command = |script: &str| YamdrOptions {
+            mermaid_command: vec!["sh".into(), "-c".into(), script.into()],
+            ..Default::default()
+        };
+        let options =
+            command(r#"grep -q sequenceDiagram && echo '<svg width="10" height="10"></svg>'"#);
+        let html = render_html(
+            &options,
+            &custom_block("{t: Mermaid, title: Login}", "sequenceDiagram\n"),
+        )
+        .unwrap();
+        assert!(html.starts_with("<svg"));
+        assert!(html.contains("<title"));
+        assert!(!html.contains("<script"));
+        assert!(render_html(&options, &custom_block("{t: Mermaid}", "graph TD\n")).is_err());
+
+        let options = command("echo 'Parse error' >&2; exit 1");
+        match render_html(&options, &custom_block("{t: Mermaid}", "graph TD\n")) {
+            Err(Error::CustomBlockRead(err)) => assert_eq!(err, "`sh` failed: Parse error"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn render_markdown() {
+        let document = "```{t: Mermaid, alt: Flow}\ngraph TD\n  A-->B\n```\n";
+        let (_, md) = crate::render_markdown(
+            &YamdrOptions {
+                format: Some(Format::Md),
+                ..Default::default()
+            },
+            document,
+        );
+        assert_eq!(
+            md,
+            "```{\"t\":\"Mermaid\",\"alt\":\"Flow\"}\ngraph TD\n  A-->B\n```\n\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use pulldown_cmark::{Event, HeadingLevel, Tag};
+
+/// Hierarchical numbers of the headings of a document, like `1.`, `1.1` and
+/// `1.1.1`, see `YamdrOptions::number_headings`. The highest level of
+/// heading in the document is numbered `1.`, `2.`, and so on, so a document
+/// without a `#` heading starts at `##`.
+pub(crate) struct HeadingNumbers {
+    top: usize,
+    counters: [usize; 6],
+}
+
+impl HeadingNumbers {
+    /// Numbers for the headings in `events`. Headings of a higher level
+    /// than the ones in `events`, like from custom blocks, are numbered as
+    /// top level headings.
+    pub fn new<'a>(events: impl IntoIterator<Item = &'a Event<'a>>) -> Self {
+        let top = events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Start(Tag::Heading(level, ..)) => Some(*level as usize),
+                _ => None,
+            })
+            .min()
+            .unwrap_or(1);
+        HeadingNumbers {
+            top,
+            counters: [0; 6],
+        }
+    }
+
+    /// Number of the next heading, at `level`.
+    pub fn next(&mut self, level: HeadingLevel) -> String {
+        let level = level as usize;
+        self.counters[level - 1] += 1;
+        self.counters[level..].fill(0);
+        let numbers = &self.counters[self.top.min(level) - 1..level];
+        match numbers {
+            [number] => format!("{}.", number),
+            numbers => numbers
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join("."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, YamdrOptions};
+
+    #[test]
+    fn numbers() {
+        let start = Event::Start(Tag::Heading(HeadingLevel::H2, None, vec![]));
+        let mut numbers = HeadingNumbers::new([&start]);
+        assert_eq!(numbers.next(HeadingLevel::H2), "1.");
+        assert_eq!(numbers.next(HeadingLevel::H3), "1.1");
+        assert_eq!(numbers.next(HeadingLevel::H4), "1.1.1");
+        assert_eq!(numbers.next(HeadingLevel::H3), "1.2");
+        assert_eq!(numbers.next(HeadingLevel::H2), "2.");
+        assert_eq!(numbers.next(HeadingLevel::H4), "2.0.1");
+        assert_eq!(numbers.next(HeadingLevel::H3), "2.1");
+    }
+
+    #[test]
+    fn heading_numbers() {
+        let document = r#"
+## Setup
+
+### Install
+
+## Usage
+"#;
+        let options = YamdrOptions {
+            number_headings: true,
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<h2><span class="heading-number">1.</span> Setup</h2>"#));
+        assert!(html.contains(r#"<h3><span class="heading-number">1.1</span> Install</h3>"#));
+        assert!(html.contains(r#"<h2><span class="heading-number">2.</span> Usage</h2>"#));
+        assert_eq!(
+            meta.stats
+                .sections
+                .iter()
+                .map(|section| section.number.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("1."), Some("1.1"), Some("2.")]
+        );
+        assert_eq!(meta.stats.sections[0].heading.as_deref(), Some("Setup"));
+
+        let document = format!(
+            "```{{t: External, meta: true, number_headings: false}}\n```\n{}",
+            document
+        );
+        let (meta, html) = render_markdown(&options, &document);
+        assert!(!html.contains(r#"<span class="heading-number">"#));
+        assert_eq!(meta.stats.sections[1].number, None);
+
+        let md = render_markdown(
+            &YamdrOptions {
+                format: Some(Format::Md),
+                ..options
+            },
+            "## Setup\n",
+        )
+        .1;
+        assert_eq!(md.trim(), "## Setup");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{
+    utils::{build_table, header_field, local_files, resolve_ref as resolve},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+struct Parameter {
+    name: String,
+    #[serde(rename = "in")]
+    location: String,
+    #[serde(default)]
+    required: bool,
+}
+
+/// An operation of an OpenAPI spec, as a row of the table.
+#[derive(Debug, Clone, PartialEq)]
+struct Endpoint {
+    method: String,
+    path: String,
+    description: String,
+    parameters: String,
+    responses: String,
+}
+
+/// A table of the endpoints of an OpenAPI spec, so API docs are generated
+/// from the spec instead of being written by hand. The spec is read from
+/// `path`, relative to `YamdrOptions::base_dir`, or from the body of the
+/// block. Only operations with `tag` are listed, if it is set.
+///
+/// ````text
+/// ```{t: OpenApi, path: api.yaml, tag: users}
+/// ```
+/// ````
+#[derive(Debug, Clone)]
+pub struct OpenApiBlock {
+    header: CustomBlockHeader,
+    input: String,
+    endpoints: Vec<Endpoint>,
+    labels: Labels,
+}
+
+pub struct OpenApiBlockReader {
+    base_dir: Option<PathBuf>,
+    labels: Labels,
+}
+
+impl OpenApiBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        OpenApiBlockReader {
+            base_dir: options.base_dir.clone(),
+            labels: options.locale.labels.clone(),
+        }
+    }
+
+    fn read_spec(&self, path: &str) -> Result<String> {
+        let full_path = match &self.base_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => PathBuf::from(path),
+        };
+        if !full_path.is_file() {
+            return Err(Error::MissingFile(path.into()));
+        }
+        std::fs::read_to_string(full_path)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err)))
+    }
+}
+
+fn parameter(spec: &Value, value: &Value) -> Option<String> {
+    let parameter = Parameter::deserialize(resolve(spec, value)).ok()?;
+    Some(match parameter.required {
+        true => format!("{} ({}, required)", parameter.name, parameter.location),
+        false => format!("{} ({})", parameter.name, parameter.location),
+    })
+}
+
+fn endpoints(spec: &Value, tag: Option<&str>) -> Vec<Endpoint> {
+    let Some(paths) = spec.get("paths").and_then(Value::as_mapping) else {
+        return Vec::new();
+    };
+    let mut endpoints = Vec::new();
+    for (path, item) in paths {
+        let (Some(path), item) = (path.as_str(), resolve(spec, item)) else {
+            continue;
+        };
+        let shared_parameters = item.get("parameters").and_then(Value::as_sequence);
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+            let tags = operation.get("tags").and_then(Value::as_sequence);
+            if let Some(tag) = tag {
+                if !tags.is_some_and(|tags| tags.iter().any(|t| t.as_str() == Some(tag))) {
+                    continue;
+                }
+            }
+            let text = |name: &str| operation.get(name).and_then(Value::as_str);
+            let mut parameters: Vec<String> = shared_parameters
+                .into_iter()
+                .chain(operation.get("parameters").and_then(Value::as_sequence))
+                .flatten()
+                .filter_map(|value| parameter(spec, value))
+                .collect();
+            if let Some(body) = operation.get("requestBody") {
+                let required = resolve(spec, body).get("required").and_then(Value::as_bool);
+                parameters.push(match required {
+                    Some(true) => "body (required)".into(),
+                    _ => "body".into(),
+                });
+            }
+            let responses = operation
+                .get("responses")
+                .and_then(Value::as_mapping)
+                .into_iter()
+                .flatten()
+                .map(|(status, response)| {
+                    let status = match status {
+                        Value::Number(n) => n.to_string(),
+                        status => status.as_str().unwrap_or_default().to_string(),
+                    };
+                    match resolve(spec, response)
+                        .get("description")
+                        .and_then(Value::as_str)
+                    {
+                        Some(description) => format!("{} {}", status, description),
+                        None => status,
+                    }
+                })
+                .collect::<Vec<_>>();
+            endpoints.push(Endpoint {
+                method: method.to_uppercase(),
+                path: path.into(),
+                description: text("summary")
+                    .or_else(|| text("description"))
+                    .unwrap_or_default()
+                    .into(),
+                parameters: parameters.join(", "),
+                responses: responses.join(", "),
+            });
+        }
+    }
+    endpoints
+}
+
+impl CustomBlockReader for OpenApiBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "OpenApi"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["OpenApi"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("path", FieldType::String),
+            HeaderField::new("tag", FieldType::String),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let spec = match header_field(header, "path") {
+            Some(path) => self.read_spec(&path)?,
+            None if input.trim().is_empty() => {
+                return Err(Error::CustomBlockRead(
+                    "missing `path` in OpenApi block".into(),
+                ))
+            }
+            None => input.to_string(),
+        };
+        let spec: Value = serde_yaml::from_str(&spec)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to parse spec: {}", err)))?;
+        let tag = header_field(header, "tag");
+        Ok(Some(Box::new(OpenApiBlock {
+            header: header.clone(),
+            input: input.into(),
+            endpoints: endpoints(&spec, tag.as_deref()),
+            labels: self.labels.clone(),
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["path"])
+    }
+}
+
+impl CustomBlock for OpenApiBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let head = self.labels.api.iter().map(|label| label.as_str().into());
+                let rows = self
+                    .endpoints
+                    .iter()
+                    .map(|endpoint| {
+                        [
+                            &endpoint.method,
+                            &endpoint.path,
+                            &endpoint.description,
+                            &endpoint.parameters,
+                            &endpoint.responses,
+                        ]
+                        .map(|cell| CowStr::from(cell.as_str()))
+                        .into()
+                    })
+                    .collect();
+                build_table(head.collect(), rows)
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"
+openapi: 3.0.0
+paths:
+  /users/{id}:
+    parameters:
+      - {name: id, in: path, required: true}
+    get:
+      tags: [users]
+      summary: Get a user
+      parameters:
+        - $ref: "#/components/parameters/fields"
+      responses:
+        200: {description: OK}
+        404: {$ref: "#/components/responses/NotFound"}
+    put:
+      tags: [users]
+      description: Update a user
+      requestBody: {required: true}
+      responses:
+        default: {description: Error}
+  /health:
+    get:
+      responses:
+        200: {description: OK}
+components:
+  parameters:
+    fields: {name: fields, in: query}
+  responses:
+    NotFound: {description: Not found}
+"##;
+
+    #[test]
+    fn endpoint_table() {
+        let spec: Value = serde_yaml::from_str(SPEC).unwrap();
+        assert_eq!(endpoints(&spec, None).len(), 3);
+        assert_eq!(
+            endpoints(&spec, Some("users")),
+            vec![
+                Endpoint {
+                    method: "GET".into(),
+                    path: "/users/{id}".into(),
+                    description: "Get a user".into(),
+                    parameters: "id (path, required), fields (query)".into(),
+                    responses: "200 OK, 404 Not found".into(),
+                },
+                Endpoint {
+                    method: "PUT".into(),
+                    path: "/users/{id}".into(),
+                    description: "Update a user".into(),
+                    parameters: "id (path, required), body (required)".into(),
+                    responses: "default Error".into(),
+                },
+            ]
+        );
+
+        let mut reader = OpenApiBlockReader::new(&Default::default());
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: OpenApi, tag: users}").unwrap();
+        let block = reader.read_block(&header, SPEC).unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains(
+            "<th>Method</th><th>Path</th><th>Description</th><th>Parameters</th><th>Responses</th>"
+        ));
+        assert!(html.contains("<td>GET</td><td>/users/{id}</td><td>Get a user</td>"));
+
+        assert!(reader.read_block(&header, "").is_err());
+        assert!(reader.read_block(&header, "paths: [").is_err());
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: OpenApi, path: missing.yaml}").unwrap();
+        assert!(matches!(
+            reader.read_block(&header, ""),
+            Err(Error::MissingFile(_))
+        ));
+    }
+}
@@ -1,26 +1,404 @@
-use crate::{CustomBlock, CustomBlockHeader, CustomBlockReader, Error, Format, Result};
+use crate::{
+    utils::{accessible_svg, data_fallback, date, datetime, header_field, parse_timestamp},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    NumberFormat, Result, YamdrOptions,
+};
+use plotters::coord::{
+    ranged1d::{AsRangedCoord, ValueFormatter},
+    Shift,
+};
 use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
 use pulldown_cmark::{CodeBlockKind, Event, Tag};
 use serde::{Deserialize, Serialize};
 
-static COLORS: &[RGBColor] = &[RED, GREEN, BLUE, YELLOW, MAGENTA, CYAN, BLACK];
+/// Size and colors of charts, see `YamdrOptions::chart_theme`, so they
+/// can match the theme of the page. Blocks drawing charts can override
+/// them with the `width`, `height`, `palette` and `background` fields of
+/// their header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartTheme {
+    pub width: u32,
+    pub height: u32,
+    /// Colors of the series of line charts and the slices of pie charts,
+    /// in order, written like `#1f77b4` or `#f80`.
+    pub palette: Vec<String>,
+    /// Color the chart is drawn on, transparent if not set.
+    pub background: Option<String>,
+}
+
+impl Default for ChartTheme {
+    fn default() -> Self {
+        let palette = [
+            "#ff0000", "#00ff00", "#0000ff", "#ffff00", "#ff00ff", "#00ffff", "#000000",
+        ];
+        ChartTheme {
+            width: 600,
+            height: 400,
+            palette: palette.map(String::from).to_vec(),
+            background: None,
+        }
+    }
+}
+
+/// A color written like `#1f77b4` or `#f80`.
+fn parse_color(color: &str) -> Option<RGBColor> {
+    let hex = color.strip_prefix('#')?;
+    let channel = |i: usize, len: usize| {
+        let value = u8::from_str_radix(hex.get(i * len..(i + 1) * len)?, 16).ok()?;
+        Some(if len == 1 { value * 17 } else { value })
+    };
+    let len = match hex.len() {
+        3 => 1,
+        6 => 2,
+        _ => return None,
+    };
+    Some(RGBColor(
+        channel(0, len)?,
+        channel(1, len)?,
+        channel(2, len)?,
+    ))
+}
+
+impl ChartTheme {
+    /// The theme of a block, from the fields of its header, or `default`.
+    pub fn for_header(
+        header: &CustomBlockHeader,
+        default: &ChartTheme,
+    ) -> std::result::Result<Self, String> {
+        let mut theme = default.clone();
+        for (name, size) in [("width", &mut theme.width), ("height", &mut theme.height)] {
+            if let Some(value) = header.fields.get(name) {
+                *size = value
+                    .as_u64()
+                    .filter(|value| (1..=4096).contains(value))
+                    .ok_or_else(|| format!("`{}` must be from 1 to 4096", name))?
+                    as u32;
+            }
+        }
+        if let Some(palette) = header.fields.get("palette") {
+            theme.palette = serde_yaml::from_value(palette.clone())
+                .map_err(|_| "`palette` must be a list of colors".to_string())?;
+        }
+        if let Some(background) = header_field(header, "background") {
+            theme.background = Some(background);
+        }
+        let colors = theme.palette.iter().chain(&theme.background);
+        if let Some(color) = colors.clone().find(|color| parse_color(color).is_none()) {
+            return Err(format!(
+                "invalid color `{}`, expected like `#1f77b4`",
+                color
+            ));
+        }
+        if theme.palette.is_empty() {
+            return Err("`palette` can't be empty".into());
+        }
+        Ok(theme)
+    }
+
+    fn colors(&self) -> Vec<RGBColor> {
+        self.palette
+            .iter()
+            .map(|color| parse_color(color).unwrap_or(BLACK))
+            .collect()
+    }
+
+    /// An svg drawing area of the size of the theme, filled with its
+    /// background.
+    fn drawing_area<'a>(&self, svg: &'a mut String) -> DrawingArea<SVGBackend<'a>, Shift> {
+        let root = SVGBackend::with_string(svg, (self.width, self.height)).into_drawing_area();
+        if let Some(background) = self.background.as_deref().and_then(parse_color) {
+            root.fill(&background).unwrap();
+        }
+        root
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlottersBlock {
+    header: CustomBlockHeader,
+    chart: PlottersChart,
+    numbers: Option<NumberFormat>,
+    theme: ChartTheme,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
-pub enum PlottersBlock {
+pub enum PlottersChart {
     LineChart {
         title: String,
         range_x: Option<(f32, f32)>,
         range_y: Option<(f32, f32)>,
         data: Vec<Vec<(f32, f32)>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trendline: Option<Trendline>,
+        #[serde(default, skip_serializing_if = "Scale::is_linear")]
+        x_scale: Scale,
+        #[serde(default, skip_serializing_if = "Scale::is_linear")]
+        y_scale: Scale,
+        /// The unix timestamp of `x = 0`, when the x values are written as
+        /// dates, like `2024-03-01` or `2024-03-01T12:00:00Z`. The x values
+        /// are then seconds since the earliest of the dates, see
+        /// `time_values`.
+        #[serde(skip)]
+        x_time: Option<i64>,
+    },
+    /// Slices of a circle sized by their share of the total, drawn from
+    /// the top and clockwise, with a hole in the middle if `hole` is set,
+    /// as a fraction of the radius.
+    PieChart {
+        title: String,
+        slices: Vec<PieSlice>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hole: Option<f32>,
+        /// Whether each slice is labelled with its share of the total.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        percentages: bool,
     },
 }
 
-pub struct PlottersBlockReader {}
+/// How values are placed along an axis, where each power of ten is the
+/// same distance apart on a `log` scale, for values that span several
+/// orders of magnitude, like benchmark timings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scale {
+    #[default]
+    Linear,
+    Log,
+}
+
+impl Scale {
+    fn is_linear(&self) -> bool {
+        *self == Scale::Linear
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PieSlice {
+    pub label: String,
+    pub value: f32,
+}
+
+/// The x values of the points of a line chart, and of its `range_x`, in
+/// the yaml of the chart.
+fn x_values(chart: &mut serde_yaml::Value) -> Vec<&mut serde_yaml::Value> {
+    let mut values = Vec::new();
+    let Some(chart) = chart.as_mapping_mut() else {
+        return values;
+    };
+    for (key, value) in chart.iter_mut() {
+        match (key.as_str(), value) {
+            (Some("data"), serde_yaml::Value::Sequence(series)) => {
+                let points = series
+                    .iter_mut()
+                    .filter_map(serde_yaml::Value::as_sequence_mut)
+                    .flatten();
+                let xs = points.filter_map(|point| point.as_sequence_mut()?.first_mut());
+                values.extend(xs);
+            }
+            (Some("range_x"), serde_yaml::Value::Sequence(range)) => values.extend(range),
+            _ => {}
+        }
+    }
+    values
+}
+
+/// Replaces x values written as dates in the yaml of a line chart with
+/// seconds since the earliest of them, which is returned, so they can be
+/// drawn on a time axis.
+fn time_values(chart: &mut serde_yaml::Value) -> std::result::Result<Option<i64>, String> {
+    let mut xs = x_values(chart);
+    if !xs.iter().any(|x| x.is_string()) {
+        return Ok(None);
+    }
+    let mut timestamps = Vec::with_capacity(xs.len());
+    for x in xs.iter() {
+        let timestamp = match x.as_str() {
+            Some(date) => parse_timestamp(date).ok_or_else(|| format!("invalid date `{}`", date)),
+            None => Err("x values must be all dates or all numbers".into()),
+        };
+        timestamps.push(timestamp?);
+    }
+    let start = timestamps.iter().copied().min().unwrap_or_default();
+    for (x, timestamp) in xs.iter_mut().zip(timestamps) {
+        **x = ((timestamp - start) as f64).into();
+    }
+    Ok(Some(start))
+}
+
+/// Labels of the axes of a line chart, see `Axes::draw`.
+struct Axes<'a> {
+    colors: &'a [RGBColor],
+    x_label: &'a dyn Fn(&f32) -> String,
+    y_label: &'a dyn Fn(&f32) -> String,
+    /// Use the labels of plotters, written like `1.5`.
+    default_labels: bool,
+}
+
+impl Axes<'_> {
+    /// Draws the series of a line chart on `root`, with the `x` and `y`
+    /// coordinates, which can be linear or logarithmic.
+    fn draw<X, Y>(
+        &self,
+        root: &DrawingArea<SVGBackend, Shift>,
+        title: &str,
+        (x, y): (X, Y),
+        data: &[Vec<(f32, f32)>],
+        trendline: &Option<Trendline>,
+    ) where
+        X: AsRangedCoord<Value = f32>,
+        Y: AsRangedCoord<Value = f32>,
+        X::CoordDescType: ValueFormatter<f32>,
+        Y::CoordDescType: ValueFormatter<f32>,
+    {
+        let mut chart = ChartBuilder::on(root)
+            .caption(title, ("sans-serif", 40).into_font())
+            .margin(10)
+            .set_left_and_bottom_label_area_size(20)
+            .build_cartesian_2d(x, y)
+            .unwrap();
+
+        let mut mesh = chart.configure_mesh();
+        mesh.x_labels(5).y_labels(5);
+        if !self.default_labels {
+            mesh.x_label_formatter(self.x_label)
+                .y_label_formatter(self.y_label);
+        }
+        mesh.draw().unwrap();
+
+        for (i, points) in data.iter().enumerate() {
+            let color = self.colors[i % self.colors.len()];
+            chart
+                .draw_series(LineSeries::new(points.clone(), &color))
+                .unwrap();
+            if let Some(trendline) = trendline {
+                chart
+                    .draw_series(LineSeries::new(
+                        trendline.points(points),
+                        color.mix(0.5).stroke_width(3),
+                    ))
+                    .unwrap();
+            }
+        }
+    }
+}
+
+/// A line fitted to each series of a chart, drawn over it, written as
+/// `linear` for a least squares fit, or `moving_average(n)` for the
+/// average of the last `n` points.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Trendline {
+    Linear,
+    MovingAverage(usize),
+}
+
+impl TryFrom<String> for Trendline {
+    type Error = String;
+
+    fn try_from(trendline: String) -> std::result::Result<Self, String> {
+        let window = trendline
+            .strip_prefix("moving_average(")
+            .and_then(|window| window.strip_suffix(')'))
+            .map(|window| window.trim().parse::<usize>());
+        match (trendline.as_str(), window) {
+            ("linear", _) => Ok(Trendline::Linear),
+            (_, Some(Ok(window))) if window > 0 => Ok(Trendline::MovingAverage(window)),
+            _ => Err(format!(
+                "invalid trendline `{}`, expected `linear` or `moving_average(n)`",
+                trendline
+            )),
+        }
+    }
+}
+
+impl From<Trendline> for String {
+    fn from(trendline: Trendline) -> String {
+        match trendline {
+            Trendline::Linear => "linear".into(),
+            Trendline::MovingAverage(window) => format!("moving_average({})", window),
+        }
+    }
+}
+
+/// Parameters of a line `y = slope * x + intercept` fitted to points, with
+/// the coefficient of determination, where 1 is a perfect fit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    pub r2: f64,
+}
+
+/// Least squares fit of a line to `points`, if there are at least two
+/// different x values.
+pub fn linear_fit(points: &[(f32, f32)]) -> Option<LinearFit> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| *x as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| *y as f64).sum::<f64>() / n;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        let (dx, dy) = (*x as f64 - mean_x, *y as f64 - mean_y);
+        sxx += dx * dx;
+        sxy += dx * dy;
+        syy += dy * dy;
+    }
+    if points.len() < 2 || sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some(LinearFit {
+        slope,
+        intercept: mean_y - slope * mean_x,
+        // A horizontal line through horizontal points fits perfectly
+        r2: match syy {
+            0.0 => 1.0,
+            _ => sxy * sxy / (sxx * syy),
+        },
+    })
+}
+
+impl Trendline {
+    /// Points of the trendline of a series.
+    pub fn points(&self, points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        match self {
+            Trendline::Linear => {
+                let Some(fit) = linear_fit(points) else {
+                    return Vec::new();
+                };
+                let xs = points.iter().map(|(x, _)| *x);
+                let (min, max) = (
+                    xs.clone().fold(f32::MAX, f32::min),
+                    xs.fold(f32::MIN, f32::max),
+                );
+                [min, max]
+                    .into_iter()
+                    .map(|x| (x, (fit.slope * x as f64 + fit.intercept) as f32))
+                    .collect()
+            }
+            Trendline::MovingAverage(window) => points
+                .windows(*window)
+                .map(|window| {
+                    let sum: f32 = window.iter().map(|(_, y)| y).sum();
+                    (window[window.len() - 1].0, sum / window.len() as f32)
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct PlottersBlockReader {
+    numbers: Option<NumberFormat>,
+    theme: ChartTheme,
+}
 
 impl PlottersBlockReader {
-    pub fn initial_state() -> Self {
-        PlottersBlockReader {}
+    pub fn new(options: &YamdrOptions) -> Self {
+        PlottersBlockReader {
+            numbers: options.locale.numbers,
+            theme: options.chart_theme.clone(),
+        }
     }
 }
 
@@ -29,6 +407,24 @@ impl CustomBlockReader for PlottersBlockReader {
         header.t == "Plotters"
     }
 
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Plotters"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("accessibility", FieldType::String),
+            HeaderField::new("locale", FieldType::String),
+            HeaderField::new("width", FieldType::Integer),
+            HeaderField::new("height", FieldType::Integer),
+            HeaderField::new("palette", FieldType::List),
+            HeaderField::new("background", FieldType::String),
+        ];
+        Some(SCHEMA)
+    }
+
     fn read_block(
         &mut self,
         header: &CustomBlockHeader,
@@ -37,88 +433,275 @@ impl CustomBlockReader for PlottersBlockReader {
         if header.t != "Plotters" {
             todo!("unsupported block type")
         }
-        let data = serde_yaml::from_str::<PlottersBlock>(input).map_err(|e| {
-            Error::CustomBlockRead(format!("failed to parse block: {}", e))
-        })?;
-        Ok(Some(Box::new(data)))
+        let mut chart = serde_yaml::from_str(input)
+            .map_err(|e| Error::CustomBlockRead(format!("failed to parse block: {}", e)))?;
+        let time = time_values(&mut chart).map_err(Error::CustomBlockRead)?;
+        let mut chart = serde_yaml::from_value::<PlottersChart>(chart)
+            .map_err(|e| Error::CustomBlockRead(format!("failed to parse block: {}", e)))?;
+        if let PlottersChart::LineChart { x_time, .. } = &mut chart {
+            *x_time = time;
+        }
+        chart.validate().map_err(Error::CustomBlockRead)?;
+        Ok(Some(Box::new(PlottersBlock {
+            header: header.clone(),
+            chart,
+            numbers: NumberFormat::for_header(header, self.numbers),
+            theme: ChartTheme::for_header(header, &self.theme).map_err(Error::CustomBlockRead)?,
+        })))
     }
 }
 
 impl CustomBlock for PlottersBlock {
-    fn to_events(&self, format: Format) -> Vec<Event<'static>> {
-        match (self, format) {
-            (_, Format::Md) => {
+    fn is_expensive(&self) -> bool {
+        true
+    }
+
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Md => {
                 let props: pulldown_cmark::CowStr =
-                    serde_json::to_string(&CustomBlockHeader::empty("Plotters".into()))
-                        .unwrap()
-                        .into();
+                    serde_json::to_string(&self.header).unwrap().into();
                 let mut events = vec![Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
                     props.clone(),
                 )))];
-                let body = serde_yaml::to_string(self).unwrap();
+                let mut body = serde_yaml::to_value(&self.chart).unwrap();
+                if let PlottersChart::LineChart {
+                    x_time: Some(time), ..
+                } = &self.chart
+                {
+                    for x in x_values(&mut body) {
+                        *x = datetime(*time + x.as_f64().unwrap_or(0.0).round() as i64).into();
+                    }
+                }
+                let body = serde_yaml::to_string(&body).unwrap();
                 events.push(Event::Text(body.into()));
                 events.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))));
 
                 events
             }
-            (
-                PlottersBlock::LineChart {
-                    title,
-                    range_x,
-                    range_y,
-                    data,
-                },
-                Format::Html,
-            ) => {
+            Format::Html => {
+                let svg = self.chart.to_svg(self.numbers.as_ref(), &self.theme);
+                let mut html = accessible_svg(svg, &self.header);
+                html += &self.chart.data_fallback(&self.header).unwrap_or_default();
+                vec![Event::Html(html.into())]
+            }
+        }
+    }
+}
+
+impl PlottersChart {
+    /// Errors in the data of the chart that can't be drawn.
+    fn validate(&self) -> std::result::Result<(), String> {
+        match self {
+            PlottersChart::LineChart {
+                data,
+                x_scale,
+                y_scale,
+                ..
+            } => {
+                let points = || data.iter().flatten();
+                if *x_scale == Scale::Log && points().any(|(x, _)| *x <= 0.0)
+                    || *y_scale == Scale::Log && points().any(|(_, y)| *y <= 0.0)
+                {
+                    return Err("values on a log scale must be positive".into());
+                }
+                Ok(())
+            }
+            PlottersChart::PieChart { slices, hole, .. } => {
+                if slices
+                    .iter()
+                    .any(|slice| slice.value < 0.0 || slice.value.is_nan())
+                {
+                    return Err("pie chart slices can't be negative".into());
+                }
+                if slices.iter().all(|slice| slice.value == 0.0) {
+                    return Err("pie chart has no slices with a value".into());
+                }
+                match hole {
+                    Some(hole) if !(0.0..1.0).contains(hole) => {
+                        Err(format!("invalid hole `{}`, expected 0 to 1", hole))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// The points of the chart as a table, see `utils::data_fallback`.
+    pub fn data_fallback(&self, header: &CustomBlockHeader) -> Option<String> {
+        match self {
+            PlottersChart::LineChart { data, x_time, .. } => {
+                let x = move |x: &f32| match x_time {
+                    Some(time) => datetime(time + x.round() as i64),
+                    None => x.to_string(),
+                };
+                let rows = || {
+                    data.iter()
+                        .enumerate()
+                        .flat_map(|(i, points)| {
+                            points.iter().map(move |(x_value, y)| {
+                                vec![(i + 1).to_string(), x(x_value), y.to_string()]
+                            })
+                        })
+                        .collect()
+                };
+                data_fallback(header, &["Series", "x", "y"], rows)
+            }
+            PlottersChart::PieChart { slices, .. } => {
+                let rows = || {
+                    slices
+                        .iter()
+                        .map(|slice| vec![slice.label.clone(), slice.value.to_string()])
+                        .collect()
+                };
+                data_fallback(header, &["Label", "Value"], rows)
+            }
+        }
+    }
+
+    /// Render the chart as an svg string, with the axis labels written in
+    /// `numbers` if given.
+    pub fn to_svg(&self, numbers: Option<&NumberFormat>, theme: &ChartTheme) -> String {
+        let colors = theme.colors();
+        match self {
+            PlottersChart::LineChart {
+                title,
+                range_x,
+                range_y,
+                data,
+                trendline,
+                x_scale,
+                y_scale,
+                x_time,
+            } => {
+                let mut svg = String::new();
+                {
+                    let root = theme.drawing_area(&mut svg);
+                    // From 0, or from the smallest value on a log scale
+                    let range = |range: &Option<(f32, f32)>,
+                                 scale: &Scale,
+                                 value: fn(&(f32, f32)) -> f32| {
+                        range.unwrap_or_else(|| {
+                            let values = data.iter().flatten().map(value);
+                            let max = values.clone().fold(0.0, f32::max);
+                            match scale {
+                                Scale::Linear => (0.0, max),
+                                Scale::Log => (values.fold(max, f32::min), max),
+                            }
+                        })
+                    };
+                    let range_x = range(range_x, x_scale, |(x, _)| *x);
+                    let range_y = range(range_y, y_scale, |(_, y)| *y);
+
+                    let number = |value: &f32| match numbers {
+                        Some(numbers) => numbers.number(*value as f64),
+                        None => value.to_string(),
+                    };
+                    // Times of day for series shorter than two days
+                    let time = |value: &f32| {
+                        let timestamp = x_time.unwrap_or(0) + value.round() as i64;
+                        match range_x.1 - range_x.0 < 2.0 * 86400.0 {
+                            true => datetime(timestamp)
+                                .get(11..16)
+                                .unwrap_or("00:00")
+                                .to_string(),
+                            false => date(timestamp),
+                        }
+                    };
+                    let x_label: &dyn Fn(&f32) -> String = match x_time {
+                        Some(_) => &time,
+                        None => &number,
+                    };
+                    let axes = Axes {
+                        colors: &colors,
+                        x_label,
+                        y_label: &number,
+                        default_labels: numbers.is_none() && x_time.is_none(),
+                    };
+                    let (x, y) = (range_x.0..range_x.1, range_y.0..range_y.1);
+                    match (x_scale, y_scale) {
+                        (Scale::Linear, Scale::Linear) => {
+                            axes.draw(&root, title, (x, y), data, trendline)
+                        }
+                        (Scale::Log, Scale::Linear) => {
+                            axes.draw(&root, title, (x.log_scale(), y), data, trendline)
+                        }
+                        (Scale::Linear, Scale::Log) => {
+                            axes.draw(&root, title, (x, y.log_scale()), data, trendline)
+                        }
+                        (Scale::Log, Scale::Log) => axes.draw(
+                            &root,
+                            title,
+                            (x.log_scale(), y.log_scale()),
+                            data,
+                            trendline,
+                        ),
+                    }
+                }
+                svg
+            }
+            PlottersChart::PieChart {
+                title,
+                slices,
+                hole,
+                percentages,
+            } => {
                 let mut svg = String::new();
                 {
-                    let root = SVGBackend::with_string(&mut svg, (600, 400)).into_drawing_area();
-
-                    let range_x = range_x.unwrap_or_else(|| {
-                        (
-                            0.0,
-                            data.iter()
-                                .flatten()
-                                .map(|(x, _y)| x)
-                                .max_by(|a, b| a.partial_cmp(b).unwrap())
-                                .copied()
-                                .unwrap_or(0.0),
-                        )
-                    });
-                    let range_y = range_y.unwrap_or_else(|| {
-                        (
-                            0.0,
-                            data.iter()
-                                .flatten()
-                                .map(|(_x, y)| y)
-                                .max_by(|a, b| a.partial_cmp(b).unwrap())
-                                .copied()
-                                .unwrap_or(0.0),
-                        )
-                    });
-
-                    let mut chart = ChartBuilder::on(&root)
-                        .caption(title, ("sans-serif", 40).into_font())
-                        .margin(10)
-                        .set_left_and_bottom_label_area_size(20)
-                        .build_cartesian_2d(range_x.0..range_x.1, range_y.0..range_y.1)
-                        .unwrap();
-
-                    chart
-                        .configure_mesh()
-                        .x_labels(5)
-                        .y_labels(5)
-                        .draw()
-                        .unwrap();
-
-                    for (i, points) in data.iter().enumerate() {
-                        let color = COLORS[i % COLORS.len()];
-                        chart
-                            .draw_series(LineSeries::new(points.clone(), &color))
+                    let root = theme.drawing_area(&mut svg);
+                    let root = root.titled(title, ("sans-serif", 40)).unwrap();
+                    let (width, height) = root.dim_in_pixel();
+                    let center = (width as i32 / 2, height as i32 / 2);
+                    // Room for the labels around the circle
+                    let radius = (width.min(height) as f64 / 2.0 - 30.0).max(10.0);
+                    let sizes: Vec<f64> = slices.iter().map(|slice| slice.value as f64).collect();
+                    let slice_colors: Vec<RGBColor> = (0..slices.len())
+                        .map(|i| colors[i % colors.len()])
+                        .collect();
+                    let labels: Vec<&str> =
+                        slices.iter().map(|slice| slice.label.as_str()).collect();
+                    let mut pie = Pie::new(&center, &radius, &sizes, &slice_colors, &labels);
+                    pie.start_angle(-90.0);
+                    pie.label_style(("sans-serif", 16).into_font());
+                    root.draw(&pie).unwrap();
+
+                    let hole = hole.unwrap_or(0.0) as f64 * radius;
+                    if hole > 0.0 {
+                        let background = theme.background.as_deref().and_then(parse_color);
+                        let fill = background.unwrap_or(WHITE).filled();
+                        root.draw(&Circle::new(center, hole as i32, fill)).unwrap();
+                    }
+                    if *percentages {
+                        // Drawn here rather than by `Pie`, in the middle of
+                        // the ring when there is a hole
+                        let total: f64 = sizes.iter().sum();
+                        let style = ("sans-serif", 16)
+                            .into_font()
+                            .color(&BLACK)
+                            .pos(Pos::new(HPos::Center, VPos::Center));
+                        let mut start = 0.0;
+                        for size in sizes.iter() {
+                            let share = size / total;
+                            let theta = (start + share / 2.0) * std::f64::consts::TAU
+                                - std::f64::consts::FRAC_PI_2;
+                            start += share;
+                            let distance = (radius + hole) / 2.0;
+                            let position = (
+                                center.0 + (distance * theta.cos()).round() as i32,
+                                center.1 + (distance * theta.sin()).round() as i32,
+                            );
+                            let share = format!("{:.1}", share * 100.0);
+                            let label = numbers.and_then(|numbers| numbers.cell(&share));
+                            root.draw_text(
+                                &format!("{}%", label.unwrap_or(share)),
+                                &style,
+                                position,
+                            )
                             .unwrap();
+                        }
                     }
                 }
-                vec![Event::Html(svg.into())]
+                svg
             }
         }
     }
@@ -126,6 +709,26 @@ impl CustomBlock for PlottersBlock {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn render_html_with_alt() {
+        let document = r#"```{"t":"Plotters","alt":"A straight line"}
+type: LineChart
+title: test
+data:
+- [[0, 0], [1, 1]]
+```
+"#;
+        let format = crate::Format::Html;
+        let parsed_markdown = crate::parse_markdown(&Default::default(), document);
+        let events = parsed_markdown
+            .iter()
+            .flat_map(|ee| format.transform_extended_event(ee));
+        let output = format.render(events, 0);
+
+        assert!(output.contains(r#"role="img" aria-label="A straight line""#));
+        assert!(output.contains("<title>A straight line</title>"));
+    }
+
     #[test]
     fn render_markdown() {
         let documents = [(
@@ -165,13 +768,183 @@ data:
         )];
         let format = crate::Format::Md;
         for (document, expected) in documents {
-            let parsed_markdown = crate::parse_markdown(document);
+            let parsed_markdown = crate::parse_markdown(&Default::default(), document);
             let events = parsed_markdown
                 .iter()
                 .flat_map(|ee| format.transform_extended_event(ee));
-            let output = format.render(events);
+            let output = format.render(events, 0);
 
             assert_eq!(expected, output);
         }
     }
+
+    #[test]
+    fn trendlines() {
+        use super::*;
+
+        let linear: Trendline = serde_yaml::from_str("linear").unwrap();
+        assert_eq!(linear, Trendline::Linear);
+        let average: Trendline = serde_yaml::from_str("moving_average(2)").unwrap();
+        assert_eq!(average, Trendline::MovingAverage(2));
+        assert_eq!(String::from(average), "moving_average(2)");
+        assert!(serde_yaml::from_str::<Trendline>("moving_average(0)").is_err());
+        assert!(serde_yaml::from_str::<Trendline>("cubic").is_err());
+
+        let points = [(0.0, 1.0), (1.0, 3.0), (2.0, 5.0), (3.0, 7.0)];
+        let fit = linear_fit(&points).unwrap();
+        assert_eq!((fit.slope, fit.intercept, fit.r2), (2.0, 1.0, 1.0));
+        assert_eq!(linear.points(&points), vec![(0.0, 1.0), (3.0, 7.0)]);
+        assert_eq!(
+            average.points(&points),
+            vec![(1.0, 2.0), (2.0, 4.0), (3.0, 6.0)]
+        );
+        assert_eq!(linear_fit(&[(1.0, 1.0), (1.0, 2.0)]), None);
+    }
+
+    #[test]
+    fn pie_chart() {
+        use super::*;
+
+        let input = "type: PieChart
+title: Languages
+slices:
+- {label: Rust, value: 3}
+- {label: Go, value: 1}
+hole: 0.5
+percentages: true
+";
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Plotters, alt: Languages}").unwrap();
+        let block = PlottersBlockReader::new(&Default::default())
+            .read_block(&header, input)
+            .unwrap()
+            .unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains(">\nRust\n</text>"));
+        assert!(html.contains(">\n75.0%\n</text>"));
+        assert!(html.contains(r#"<circle cx="300" cy="#));
+
+        let markdown = crate::html::render(block.to_events(Format::Md).into_iter(), 0);
+        assert!(markdown.contains("slices:\n- label: Rust\n  value: 3.0\n"));
+        assert!(markdown.contains("hole: 0.5\npercentages: true\n"));
+
+        for input in [
+            "{type: PieChart, title: a, slices: [{label: a, value: -1}]}",
+            "{type: PieChart, title: a, slices: [{label: a, value: 0}]}",
+            "{type: PieChart, title: a, slices: [{label: a, value: 1}], hole: 1}",
+        ] {
+            assert!(PlottersBlockReader::new(&Default::default())
+                .read_block(&header, input)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn scales_and_time_axis() {
+        use super::*;
+        use crate::utils::{datetime, parse_timestamp};
+
+        assert_eq!(parse_timestamp("1970-01-02"), Some(86400));
+        assert_eq!(parse_timestamp("2024-02-29T23:59:59Z"), Some(1_709_251_199));
+        assert_eq!(
+            parse_timestamp("2024-03-01 01:00:00.5+01:00"),
+            Some(1_709_251_200)
+        );
+        assert_eq!(
+            parse_timestamp("2024-03-01T01:00-0130"),
+            Some(1_709_260_200)
+        );
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+        assert_eq!(parse_timestamp("2024-03-01T12"), None);
+        assert_eq!(datetime(1_709_251_200), "2024-03-01");
+        assert_eq!(datetime(1_709_296_200), "2024-03-01T12:30:00Z");
+
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Plotters}").unwrap();
+        let read =
+            |input: &str| PlottersBlockReader::new(&Default::default()).read_block(&header, input);
+        let input = "type: LineChart
+title: Deploys
+range_x: null
+range_y: null
+data:
+- [[2024-03-01, 1], ['2024-03-03T12:00:00Z', 4], [2024-03-02, 2]]
+y_scale: log
+";
+        let block = read(input).unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains("2024-03-02"));
+        let markdown = crate::html::render(block.to_events(Format::Md).into_iter(), 0);
+        assert!(markdown.contains("- - - 2024-03-01\n    - 1.0\n  - - 2024-03-03T12:00:00Z\n"));
+        assert!(markdown.contains("y_scale: log\n"));
+        assert!(!markdown.contains("x_scale"));
+
+        assert!(read("{type: LineChart, title: a, range_x: null, range_y: null, data: [[[2024-03-01, 1], [2, 1]]]}").is_err());
+        assert!(read("{type: LineChart, title: a, range_x: null, range_y: null, data: [[[0, 1]]], x_scale: log}").is_err());
+    }
+
+    #[test]
+    fn chart_theme() {
+        use super::*;
+
+        let options = YamdrOptions {
+            chart_theme: ChartTheme {
+                background: Some("#202020".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let input =
+            "{type: LineChart, title: a, range_x: null, range_y: null, data: [[[0, 1], [1, 2]]]}";
+        let header: CustomBlockHeader = serde_yaml::from_str(
+            "{t: Plotters, width: 300, height: 200, palette: ['#1f77b4', '#f80']}",
+        )
+        .unwrap();
+        let block = PlottersBlockReader::new(&options)
+            .read_block(&header, input)
+            .unwrap()
+            .unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains(r#"width="300" height="200""#));
+        assert!(html.contains(r##"fill="#202020""##));
+        assert!(html.contains(r##"stroke="#1F77B4""##));
+
+        assert_eq!(parse_color("#f80"), Some(RGBColor(255, 136, 0)));
+        for header in [
+            "{t: Plotters, width: 0}",
+            "{t: Plotters, palette: []}",
+            "{t: Plotters, palette: [red]}",
+            "{t: Plotters, background: '#12345'}",
+        ] {
+            let header: CustomBlockHeader = serde_yaml::from_str(header).unwrap();
+            assert!(ChartTheme::for_header(&header, &Default::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn chart_accessibility() {
+        let document = r#"```{t: Plotters, title: Growth, alt: "A line from 0 to 2", accessibility: verbose}
+type: LineChart
+title: test
+data:
+  - [[0, 0], [1, 2]]
+```
+
+```{t: Graph, title: Pipeline, accessibility: verbose}
+digraph { build -> test -> deploy [label="ok"] }
+```
+
+```{t: Graph, alt: Pipeline}
+digraph { a -> b }
+```
+"#;
+        let (meta, html) = crate::render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains(
+            r#"role="img" aria-label="Growth"><title>Growth</title><desc>A line from 0 to 2</desc>"#
+        ));
+        assert!(html.contains(r#"<table class="chart-data"><caption>Growth</caption><thead><tr><th>Series</th><th>x</th><th>y</th></tr></thead><tbody><tr><td>1</td><td>0</td><td>0</td></tr><tr><td>1</td><td>1</td><td>2</td></tr></tbody></table>"#));
+        assert!(html.contains(r#"<caption>Pipeline</caption><thead><tr><th>From</th><th>To</th><th>Label</th></tr></thead><tbody><tr><td>build</td><td>test</td><td>ok</td></tr><tr><td>test</td><td>deploy</td><td>ok</td></tr></tbody>"#));
+        assert!(html.contains(r#"aria-label="Pipeline"><title>Pipeline</title>"#));
+        assert_eq!(html.matches("<table").count(), 2);
+    }
 }
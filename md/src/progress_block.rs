@@ -0,0 +1,248 @@
+use crate::{
+    utils::header_field, CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType,
+    Format, HeaderField, Result,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+
+const PREFIX: &str = "progress:";
+
+/// A progress bar, written as a block with an optional label, or as inline
+/// code like `` `progress:70/100` `` or `` `progress:70%` ``.
+///
+/// ````text
+/// ```{t: Progress, value: 70, max: 100, label: Migration}
+/// ```
+/// ````
+#[derive(Debug, Clone)]
+pub struct ProgressBlock {
+    source: Source,
+    value: f64,
+    max: f64,
+    label: Option<String>,
+}
+
+/// What the block was read from, to render it the same way to markdown.
+#[derive(Debug, Clone)]
+enum Source {
+    Block {
+        header: CustomBlockHeader,
+        input: String,
+    },
+    Inline(String),
+}
+
+pub struct ProgressBlockReader {}
+
+impl ProgressBlockReader {
+    pub fn initial_state() -> Self {
+        ProgressBlockReader {}
+    }
+}
+
+fn parse_number(number: &str) -> Option<f64> {
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Value and max of inline progress, like `70/100` or `70%`.
+fn parse_inline(progress: &str) -> Option<(f64, f64)> {
+    let (value, max) = match progress.strip_suffix('%') {
+        Some(value) => (parse_number(value)?, 100.0),
+        None => {
+            let (value, max) = progress.split_once('/')?;
+            (parse_number(value)?, parse_number(max)?)
+        }
+    };
+    (max > 0.0).then_some((value, max))
+}
+
+impl CustomBlockReader for ProgressBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Progress"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Progress"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("value", FieldType::Scalar),
+            HeaderField::new("max", FieldType::Scalar),
+            HeaderField::new("label", FieldType::String),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let number = |name: &str| match header_field(header, name) {
+            Some(field) => parse_number(&field).map(Some).ok_or_else(|| {
+                Error::CustomBlockRead(format!("invalid `{}` in Progress block", name))
+            }),
+            None => Ok(None),
+        };
+        let value = number("value")?
+            .ok_or_else(|| Error::CustomBlockRead("missing `value` in Progress block".into()))?;
+        let max = number("max")?.unwrap_or(100.0);
+        if max <= 0.0 {
+            return Err(Error::CustomBlockRead(
+                "`max` in Progress block must be positive".into(),
+            ));
+        }
+        Ok(Some(Box::new(ProgressBlock {
+            source: Source::Block {
+                header: header.clone(),
+                input: input.into(),
+            },
+            value,
+            max,
+            label: header_field(header, "label"),
+        })))
+    }
+
+    fn can_read_inline(&self, inline: &str) -> bool {
+        inline.strip_prefix(PREFIX).and_then(parse_inline).is_some()
+    }
+
+    fn read_inline(&mut self, inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
+        let (value, max) = parse_inline(&inline[PREFIX.len()..]).unwrap();
+        Ok(Some(Box::new(ProgressBlock {
+            source: Source::Inline(inline.into()),
+            value,
+            max,
+            label: None,
+        })))
+    }
+}
+
+impl ProgressBlock {
+    fn percent(&self) -> f64 {
+        (self.value / self.max * 100.0).clamp(0.0, 100.0).round()
+    }
+
+    fn progress_html(&self, label: &str) -> String {
+        format!(
+            r#"<progress class="progress" value="{}" max="{}" aria-label="{}">{}%</progress>"#,
+            self.value.clamp(0.0, self.max),
+            self.max,
+            label,
+            self.percent()
+        )
+    }
+}
+
+impl CustomBlock for ProgressBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match (format, &self.source) {
+            (Format::Html, Source::Inline(_)) => {
+                let label = format!("{}%", self.percent());
+                vec![Event::Html(self.progress_html(&label).into())]
+            }
+            (Format::Html, Source::Block { .. }) => {
+                let mut label = String::new();
+                if let Some(text) = &self.label {
+                    escape_html(&mut label, text).unwrap();
+                }
+                let mut html = String::from(r#"<div class="progress">"#);
+                if !label.is_empty() {
+                    html += &format!(r#"<span class="progress-label">{}</span>"#, label);
+                }
+                let percent = format!("{}%", self.percent());
+                if label.is_empty() {
+                    label = percent.clone();
+                }
+                html += &self.progress_html(&label);
+                html += &format!(r#"<span class="progress-value">{}</span>"#, percent);
+                html += "</div>\n";
+                vec![Event::Html(html.into())]
+            }
+            (Format::Md, Source::Inline(inline)) => vec![Event::Code(inline.as_str().into())],
+            (Format::Md, Source::Block { header, input }) => {
+                let props: CowStr = serde_json::to_string(header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    fn html(block: Box<dyn CustomBlock>) -> String {
+        crate::html::render(block.to_events(Format::Html).into_iter(), 0)
+    }
+
+    #[test]
+    fn progress() {
+        let mut reader = ProgressBlockReader::initial_state();
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Progress, value: 35, max: 50, label: <Migration>}").unwrap();
+        assert_eq!(
+            html(reader.read_block(&header, "").unwrap().unwrap()),
+            r#"<div class="progress"><span class="progress-label">&lt;Migration&gt;</span><progress class="progress" value="35" max="50" aria-label="&lt;Migration&gt;">70%</progress><span class="progress-value">70%</span></div>
+"#
+        );
+        for header in [
+            "{t: Progress}",
+            "{t: Progress, value: a lot}",
+            "{t: Progress, value: 1, max: 0}",
+        ] {
+            let header: CustomBlockHeader = serde_yaml::from_str(header).unwrap();
+            assert!(reader.read_block(&header, "").is_err());
+        }
+
+        assert!(reader.can_read_inline("progress:70%"));
+        assert!(!reader.can_read_inline("progress:70"));
+        assert!(!reader.can_read_inline("progress:1/0"));
+        assert_eq!(
+            html(reader.read_inline("progress:3/4").unwrap().unwrap()),
+            r#"<progress class="progress" value="3" max="4" aria-label="75%">75%</progress>"#
+        );
+        assert!(html(reader.read_inline("progress:120%").unwrap().unwrap())
+            .contains(r#"value="100" max="100""#));
+    }
+
+    #[test]
+    fn progress_in_document() {
+        let document = r#"
+```{t: Progress, value: 70, label: Migration}
+```
+
+Tests pass in `progress:45/60` of the services.
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty());
+        assert!(html.contains(r#"<span class="progress-label">Migration</span>"#));
+        assert!(html.contains(r#"in <progress class="progress" value="45" max="60""#));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("`progress:45/60`"));
+        assert_eq!(md, render_markdown(&options, &md).1);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    utils::{scope_svg_ids, svg_with_alt},
+    CustomBlock, CustomBlockReader, Format, Result, YamdrOptions,
+};
+use pulldown_cmark::Event;
+
+const PREFIX: &str = "stars:";
+
+const MAX_STARS: f64 = 10.0;
+
+const STAR: &str = "10,1 12.9,7 19.5,7.6 14.5,12 16,18.5 10,15.1 4,18.5 5.5,12 0.5,7.6 7.1,7";
+
+/// A star rating, written as inline code like `` `stars:3.5/5` ``, or
+/// `` `stars:4` `` for a rating out of five stars. It's rendered as an
+/// inline svg, described with the `rating` label for screen readers.
+#[derive(Debug, Clone)]
+pub struct RatingBlock {
+    inline: String,
+    rating: f64,
+    stars: f64,
+    label: String,
+    /// Number of ratings before this one, see `scope_svg_ids`.
+    index: usize,
+}
+
+pub struct RatingBlockReader {
+    label: String,
+    ratings: usize,
+}
+
+impl RatingBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        RatingBlockReader {
+            label: options.locale.labels.rating.clone(),
+            ratings: 0,
+        }
+    }
+}
+
+/// Rating and number of stars of a rating like `3.5/5`.
+fn parse_rating(rating: &str) -> Option<(f64, f64)> {
+    let (rating, stars) = rating.split_once('/').unwrap_or((rating, "5"));
+    let rating = rating.trim().parse::<f64>().ok()?;
+    let stars = stars.trim().parse::<u8>().ok()? as f64;
+    ((1.0..=MAX_STARS).contains(&stars) && (0.0..=stars).contains(&rating))
+        .then_some((rating, stars))
+}
+
+impl CustomBlockReader for RatingBlockReader {
+    fn can_read_inline(&self, inline: &str) -> bool {
+        inline.strip_prefix(PREFIX).and_then(parse_rating).is_some()
+    }
+
+    fn read_inline(&mut self, inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
+        let (rating, stars) = parse_rating(&inline[PREFIX.len()..]).unwrap();
+        self.ratings += 1;
+        Ok(Some(Box::new(RatingBlock {
+            inline: inline.into(),
+            rating,
+            stars,
+            label: self.label.clone(),
+            index: self.ratings - 1,
+        })))
+    }
+}
+
+impl RatingBlock {
+    fn svg(&self) -> String {
+        let mut svg = format!(
+            r#"<svg class="stars" xmlns="http://www.w3.org/2000/svg" width="{0}em" height="1em" viewBox="0 0 {1} 20">"#,
+            self.stars,
+            self.stars * 20.0
+        );
+        for i in 0..self.stars as usize {
+            let fill = (self.rating - i as f64).clamp(0.0, 1.0);
+            let color = match fill {
+                f if f >= 1.0 => "#e3a008".to_string(),
+                f if f <= 0.0 => "#d0d0d0".to_string(),
+                f => {
+                    let percent = (f * 100.0).round();
+                    svg += &format!(
+                        r##"<linearGradient id="star{0}"><stop offset="{0}%" stop-color="#e3a008"/><stop offset="{0}%" stop-color="#d0d0d0"/></linearGradient>"##,
+                        percent
+                    );
+                    format!("url(#star{})", percent)
+                }
+            };
+            svg += &format!(
+                r#"<polygon transform="translate({},0)" fill="{}" points="{}"/>"#,
+                i * 20,
+                color,
+                STAR
+            );
+        }
+        svg += "</svg>";
+        let alt = self
+            .label
+            .replacen("{}", &self.rating.to_string(), 1)
+            .replacen("{}", &self.stars.to_string(), 1);
+        svg_with_alt(&scope_svg_ids(&svg, self.index), &alt)
+    }
+}
+
+impl CustomBlock for RatingBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => vec![Event::Html(self.svg().into())],
+            Format::Md => vec![Event::Code(self.inline.as_str().into())],
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Locale};
+
+    #[test]
+    fn ratings() {
+        assert_eq!(parse_rating("3.5/5"), Some((3.5, 5.0)));
+        assert_eq!(parse_rating("4"), Some((4.0, 5.0)));
+        for rating in ["6/5", "-1", "1/0", "1/11", "1/2.5", "many"] {
+            assert_eq!(parse_rating(rating), None);
+        }
+
+        let mut reader = RatingBlockReader::new(&Default::default());
+        assert!(!reader.can_read_inline("stars:"));
+        let block = reader.read_inline("stars:1.5/3").unwrap().unwrap();
+        let svg = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(svg.starts_with(
+            r#"<svg class="stars" xmlns="http://www.w3.org/2000/svg" width="3em" height="1em" viewBox="0 0 60 20" role="img" aria-label="1.5 out of 3 stars"><title>1.5 out of 3 stars</title>"#
+        ));
+        assert_eq!(svg.matches("<polygon").count(), 3);
+        assert_eq!(svg.matches(r##"fill="#e3a008""##).count(), 1);
+        assert_eq!(svg.matches(r##"fill="#d0d0d0""##).count(), 1);
+        assert!(svg.contains(r#"<stop offset="50%""#));
+        assert_eq!(
+            block.to_events(Format::Md),
+            vec![Event::Code("stars:1.5/3".into())]
+        );
+    }
+
+    #[test]
+    fn star_ratings() {
+        let document = "Rated `stars:4/5`, not `stars:6/5`.\n\nAgain `stars:2.5`, `stars:2.5`.\n";
+        let options = YamdrOptions {
+            locale: Locale::for_lang("de"),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"aria-label="4 von 5 Sternen""#));
+        assert!(html.contains("<code>stars:6/5</code>"));
+        // Identical ratings don't share gradient ids
+        let ids: Vec<_> = html.match_indices("<linearGradient id=\"").collect();
+        assert_eq!(ids.len(), 2);
+        let id = |i: usize| html[i..].split('"').nth(1).unwrap();
+        assert_ne!(id(ids[0].0), id(ids[1].0));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_markdown(&options, document).1,
+            format!("{}\n", document)
+        );
+    }
+}
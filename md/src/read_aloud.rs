@@ -0,0 +1,334 @@
+use crate::{
+    document_locale, frontmatter, parse_markdown,
+    utils::{xml_attribute, xml_unescape},
+    ExtendedEvent, Format, YamdrOptions,
+};
+use pulldown_cmark::{Event, Tag};
+use quick_xml::{escape::escape, events::BytesStart};
+
+/// Format of the script written by `read_aloud`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeechFormat {
+    /// SSML, read by most text-to-speech engines, with a paragraph for each
+    /// block and a pause after each heading.
+    Ssml,
+    /// Plain text, with a paragraph for each block.
+    Text,
+}
+
+impl SpeechFormat {
+    /// The format of a file with the extension `extension`, like `ssml`.
+    pub fn for_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "ssml" | "xml" => Some(SpeechFormat::Ssml),
+            "txt" => Some(SpeechFormat::Text),
+            _ => None,
+        }
+    }
+}
+
+/// Html elements that aren't read, like code and the svgs of charts that
+/// don't have an `alt`.
+const SILENT_ELEMENTS: &[&str] = &[
+    "pre",
+    "script",
+    "style",
+    "svg",
+    "annotation",
+    "template",
+    "button",
+    "input",
+    "select",
+    "textarea",
+];
+
+/// Html elements that end a sentence.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "figcaption",
+    "caption",
+    "dt",
+    "dd",
+    "summary",
+];
+
+/// How a paragraph of the script ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum End {
+    Paragraph,
+    /// A heading, list item or table row, that is read as a sentence even
+    /// if it doesn't end with a period.
+    Sentence,
+    Heading,
+}
+
+/// A script while it is written.
+struct Script {
+    format: SpeechFormat,
+    /// Prefix of descriptions of figures, like `Figure`.
+    figure: String,
+    output: String,
+    paragraph: String,
+    /// Depth of the code blocks and footnote definitions the events are in.
+    silent: usize,
+    /// Name and depth of the html element that isn't read.
+    silent_element: Option<(String, usize)>,
+    /// Alt text of the image the events are in.
+    image: Option<String>,
+    in_math: bool,
+}
+
+/// Value of the attribute `name` of an html tag, like `img alt="x"`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let name_len = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    xml_attribute(&BytesStart::from_content(tag, name_len), name)
+}
+
+impl Script {
+    fn push(&mut self, text: &str) {
+        if self.silent > 0 || self.silent_element.is_some() {
+            return;
+        }
+        match self.image.as_mut() {
+            Some(alt) => alt.push_str(text),
+            None => self.paragraph.push_str(text),
+        }
+    }
+
+    /// Read the description of an image or a chart, like its alt text.
+    fn describe(&mut self, description: &str) {
+        let description = description.trim().trim_end_matches('.');
+        if !description.is_empty() {
+            let description = format!(" {}: {}. ", self.figure, description);
+            self.push(&description);
+        }
+    }
+
+    fn end(&mut self, end: End) {
+        let mut text = self
+            .paragraph
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.paragraph.clear();
+        // Cells are separated by commas
+        let trimmed = text.trim_end_matches([',', ' ']).len();
+        text.truncate(trimmed);
+        if text.is_empty() {
+            return;
+        }
+        if end != End::Paragraph && !text.ends_with(['.', '!', '?', ':', ';']) {
+            text.push('.');
+        }
+        match self.format {
+            SpeechFormat::Text => {
+                self.output += &text;
+                self.output += "\n\n";
+            }
+            SpeechFormat::Ssml if end == End::Heading => {
+                self.output += &format!(
+                    "<p><emphasis level=\"strong\">{}</emphasis></p>\n<break time=\"600ms\"/>\n",
+                    escape(&text)
+                );
+            }
+            SpeechFormat::Ssml => self.output += &format!("<p>{}</p>\n", escape(&text)),
+        }
+    }
+
+    fn event(&mut self, event: &Event) {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::FootnoteDefinition(_)) => self.silent += 1,
+            Event::End(Tag::CodeBlock(_) | Tag::FootnoteDefinition(_)) => self.silent -= 1,
+            _ if self.silent > 0 => {}
+            Event::Start(
+                Tag::Heading(..)
+                | Tag::Paragraph
+                | Tag::Item
+                | Tag::List(_)
+                | Tag::BlockQuote
+                | Tag::TableHead
+                | Tag::TableRow,
+            ) => self.end(End::Sentence),
+            Event::End(Tag::Heading(..)) => self.end(End::Heading),
+            Event::End(Tag::Paragraph | Tag::BlockQuote) => self.end(End::Paragraph),
+            Event::End(Tag::Item | Tag::TableHead | Tag::TableRow) => self.end(End::Sentence),
+            Event::End(Tag::TableCell) => self.push(", "),
+            Event::Start(Tag::Image(..)) => self.image = Some(String::new()),
+            Event::End(Tag::Image(..)) => {
+                if let Some(alt) = self.image.take() {
+                    self.describe(&alt);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => self.push(text),
+            Event::SoftBreak | Event::HardBreak => self.push(" "),
+            Event::Html(html) => self.html(html),
+            Event::Rule => self.end(End::Paragraph),
+            _ => {}
+        }
+    }
+
+    /// Read the text of the html of custom blocks and html blocks, and the
+    /// alt text of their images and charts.
+    fn html(&mut self, html: &str) {
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            self.push(&xml_unescape(&rest[..start]));
+            let end = rest[start..]
+                .find('>')
+                .map_or(rest.len(), |end| start + end + 1);
+            let tag = rest[start + 1..end].trim_end_matches('>');
+            rest = &rest[end..];
+            self.tag(tag);
+        }
+        self.push(&xml_unescape(rest));
+    }
+
+    fn tag(&mut self, tag: &str) {
+        if tag.starts_with(['!', '?']) {
+            return;
+        }
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if let Some((element, depth)) = self.silent_element.as_mut() {
+            if *element == name && !self_closing {
+                match closing {
+                    true => *depth -= 1,
+                    false => *depth += 1,
+                }
+                if *depth == 0 {
+                    self.silent_element = None;
+                }
+            }
+            return;
+        }
+        if closing {
+            match name.as_str() {
+                "td" | "th" => self.push(", "),
+                "math" => self.in_math = false,
+                name if BLOCK_ELEMENTS.contains(&name) => self.end(End::Sentence),
+                _ => {}
+            }
+            return;
+        }
+        let label = attribute(tag, "aria-label")
+            .filter(|_| attribute(tag, "role").as_deref() == Some("img"))
+            .or_else(|| attribute(tag, "alt").filter(|_| name == "img"));
+        let hidden = attribute(tag, "aria-hidden").as_deref() == Some("true")
+            || attribute(tag, "class").is_some_and(|class| class.contains("chart-data"));
+        if let Some(label) = label.as_ref() {
+            self.describe(label);
+        }
+        if (label.is_some() || hidden || SILENT_ELEMENTS.contains(&name.as_str()))
+            && !self_closing
+            && name != "img"
+        {
+            self.silent_element = Some((name, 1));
+            return;
+        }
+        match name.as_str() {
+            "br" => self.push(" "),
+            "math" => self.in_math = true,
+            // The tokens of formulas are read one by one
+            "mi" | "mn" | "mo" | "mtext" if self.in_math => self.push(" "),
+            _ => {}
+        }
+    }
+}
+
+/// A script of a document to be read aloud by a text-to-speech engine, for
+/// an audio version of the document. Headings, paragraphs, lists, quotes
+/// and the rows of tables are read in order, along with the text of custom
+/// blocks as it is rendered to html. Code isn't read, and images, charts
+/// and graphs are described by their `alt` text, or left out without it.
+pub fn read_aloud(options: &YamdrOptions, markdown: &str, format: SpeechFormat) -> String {
+    let options = YamdrOptions {
+        format: Some(Format::Html),
+        ..options.clone()
+    };
+    let parsed_markdown = parse_markdown(&options, markdown);
+    let (frontmatter, _) = frontmatter::split(markdown);
+    let locale = document_locale(&options, frontmatter.as_ref(), &parsed_markdown);
+    let mut script = Script {
+        format,
+        figure: locale.labels.figure.clone(),
+        output: String::new(),
+        paragraph: String::new(),
+        silent: 0,
+        silent_element: None,
+        image: None,
+        in_math: false,
+    };
+    let transformed = Format::Html.transform_extended_events(&parsed_markdown);
+    for (ee, transformed) in parsed_markdown.iter().zip(transformed) {
+        if let ExtendedEvent::Separator(_) = ee {
+            script.end(End::Paragraph);
+            continue;
+        }
+        for event in transformed {
+            script.event(&event);
+        }
+    }
+    script.end(End::Paragraph);
+    match format {
+        SpeechFormat::Text => script.output.trim_end().to_string() + "\n",
+        SpeechFormat::Ssml => format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<speak version=\"1.1\" xmlns=\"http://www.w3.org/2001/10/synthesis\" xml:lang=\"{}\">\n",
+                "{}</speak>\n"
+            ),
+            escape(&locale.lang),
+            script.output
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "# Sales & costs\n\nThe *total* is up.\n\n```rust\nlet x = 1;\n```\n\n- First\n- Second\n\n| Month | Sales |\n|-------|-------|\n| Jan   | 10    |\n\n![A bar chart](chart.png)\n\n```{t: Graph, alt: Flow of orders.}\ndigraph { a -> b }\n```\n\nArea $\\pi r^2$.\n";
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            read_aloud(&Default::default(), DOCUMENT, SpeechFormat::Text),
+            "Sales & costs.\n\nThe total is up.\n\nFirst.\n\nSecond.\n\nMonth, Sales.\n\nJan, 10.\n\nFigure: A bar chart.\n\nFigure: Flow of orders.\n\nArea π r 2.\n"
+        );
+    }
+
+    #[test]
+    fn ssml() {
+        let ssml = read_aloud(
+            &Default::default(),
+            "---\nlang: nb\n---\n# Salg\n\nMer *tekst* <b>her</b>.\n",
+            SpeechFormat::Ssml,
+        );
+        assert_eq!(
+            ssml,
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<speak version=\"1.1\" xmlns=\"http://www.w3.org/2001/10/synthesis\" xml:lang=\"nb\">\n",
+                "<p><emphasis level=\"strong\">Salg.</emphasis></p>\n<break time=\"600ms\"/>\n",
+                "<p>Mer tekst her.</p>\n",
+                "</speak>\n"
+            )
+        );
+    }
+}
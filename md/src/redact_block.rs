@@ -0,0 +1,162 @@
+use crate::{utils::header_field, CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+
+/// A block with a markdown body that is only rendered for an audience, so
+/// the same document can be rendered as an internal and an external version.
+///
+/// ````text
+/// ```{t: Redact, audience: internal, notice: true}
+/// Margins per customer.
+/// ```
+/// ````
+///
+/// The body is left out of html output, unless `audience` is one of
+/// `YamdrOptions::audiences`. With `notice: true` it is replaced with the
+/// `redacted` label instead, so readers know something was left out. The
+/// body is read like the rest of the document, so it can contain other
+/// blocks. When rendering to markdown the block is always kept as it is.
+#[derive(Debug, Clone)]
+pub struct RedactBlock {
+    header: CustomBlockHeader,
+    body: String,
+    visible: bool,
+    notice: Option<String>,
+    /// The body rendered to html, if it is visible.
+    html: String,
+}
+
+impl RedactBlock {
+    /// Read the block, showing the body if its audience is one of
+    /// `audiences`, and otherwise the `label` if it asks for a notice.
+    pub fn read(
+        header: &CustomBlockHeader,
+        body: &str,
+        audiences: &[String],
+        label: &str,
+    ) -> Result<Self, String> {
+        let audience = header_field(header, "audience")
+            .ok_or_else(|| "missing `audience` in Redact block".to_string())?;
+        let notice = header
+            .fields
+            .get("notice")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false);
+        Ok(RedactBlock {
+            header: header.clone(),
+            body: body.into(),
+            visible: audiences.contains(&audience),
+            notice: notice.then(|| label.to_string()),
+            html: String::new(),
+        })
+    }
+
+    /// Render the body to html with `render`, if it is visible, so a body
+    /// that is left out doesn't run any scripts.
+    pub(crate) fn render(&mut self, render: impl FnOnce(&str) -> String) {
+        if self.visible {
+            self.html = render(&self.body);
+        }
+    }
+}
+
+impl CustomBlock for RedactBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html if self.visible => vec![Event::Html(self.html.as_str().into())],
+            Format::Html => match &self.notice {
+                Some(notice) => {
+                    let mut html = r#"<p class="redacted" role="note">"#.to_string();
+                    escape_html(&mut html, notice).unwrap();
+                    html += "</p>\n";
+                    vec![Event::Html(html.into())]
+                }
+                None => Vec::new(),
+            },
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.body.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{custom_block, render_html};
+    use crate::{render_markdown, Result, YamdrOptions};
+
+    fn render(audiences: &[&str], header: &str) -> Result<String> {
+        let options = YamdrOptions {
+            audiences: audiences.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        };
+        render_html(&options, &custom_block(header, "*Margins*\n"))
+    }
+
+    #[test]
+    fn audiences() {
+        let header = "{t: Redact, audience: internal}";
+        assert_eq!(render(&[], header).unwrap(), "");
+        assert_eq!(
+            render(&["external", "internal"], header).unwrap(),
+            "<p><em>Margins</em></p>\n"
+        );
+        assert_eq!(
+            render(
+                &["external"],
+                "{t: Redact, audience: internal, notice: true}"
+            )
+            .unwrap(),
+            "<p class=\"redacted\" role=\"note\">This section is not available.</p>\n"
+        );
+        assert!(render(&[], "{t: Redact}").is_err());
+    }
+
+    #[test]
+    fn redact_blocks() {
+        let document = r#"# Report
+
+````{t: Redact, audience: internal}
+Internal *margins*.
+
+`badge:Q3|blue`
+````
+
+```{t: Redact, audience: legal, notice: true}
+Pending litigation.
+```
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(!html.contains("margins"));
+        assert!(!html.contains("litigation"));
+        assert!(
+            html.contains(r#"<p class="redacted" role="note">This section is not available.</p>"#)
+        );
+
+        let mut options = YamdrOptions {
+            audiences: vec!["internal".into(), "legal".into()],
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains("<p>Internal <em>margins</em>.</p>"));
+        assert!(html.contains(r#"<span class="badge badge-blue">Q3</span>"#));
+        assert!(html.contains("<p>Pending litigation.</p>"));
+        assert!(!html.contains(r#"class="redacted""#));
+
+        options.format = Some(Format::Md);
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("{\"t\":\"Redact\",\"audience\":\"internal\"}\nInternal *margins*.\n"));
+    }
+}
@@ -0,0 +1,497 @@
+use crate::{
+    utils::{build_table, header_field, local_files, xml_attribute, xml_unescape},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+use quick_xml::events::Event as XmlEvent;
+use std::path::PathBuf;
+
+/// Line coverage of a source file, from an lcov report.
+#[derive(Debug, Clone, PartialEq)]
+struct FileCoverage {
+    file: String,
+    lines: u64,
+    covered: u64,
+}
+
+/// A test case of a JUnit report that failed, or had an error.
+#[derive(Debug, Clone, PartialEq)]
+struct Failure {
+    name: String,
+    message: String,
+    output: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TestResults {
+    tests: usize,
+    skipped: usize,
+    failures: Vec<Failure>,
+}
+
+/// Coverage of each file in an lcov report, like the `lcov.info` written
+/// by `cargo llvm-cov` or `c8`. The `LF` and `LH` totals are used when a
+/// record has them, otherwise the `DA` lines are counted.
+fn parse_lcov(report: &str) -> Result<Vec<FileCoverage>> {
+    let mut files = Vec::new();
+    let mut current: Option<(FileCoverage, bool)> = None;
+    for (i, line) in report.lines().enumerate() {
+        let line = line.trim();
+        let (key, value) = line.split_once(':').unwrap_or((line, ""));
+        let number = || {
+            value.trim().parse::<u64>().map_err(|_| {
+                Error::CustomBlockRead(format!(
+                    "invalid `{}` on line {} of lcov report",
+                    key,
+                    i + 1
+                ))
+            })
+        };
+        match (key, current.as_mut()) {
+            ("SF", _) => {
+                let file = FileCoverage {
+                    file: value.into(),
+                    lines: 0,
+                    covered: 0,
+                };
+                current = Some((file, false));
+            }
+            ("DA", Some((file, false))) => {
+                let hits = value.split(',').nth(1).unwrap_or_default();
+                let hits: u64 = hits.trim().parse().map_err(|_| {
+                    Error::CustomBlockRead(format!("invalid `DA` on line {} of lcov report", i + 1))
+                })?;
+                file.lines += 1;
+                file.covered += (hits > 0) as u64;
+            }
+            ("LF", Some((file, totals))) => {
+                // The totals win over the counted lines
+                if !*totals {
+                    file.covered = 0;
+                }
+                file.lines = number()?;
+                *totals = true;
+            }
+            ("LH", Some((file, totals))) => {
+                if !*totals {
+                    file.lines = 0;
+                }
+                file.covered = number()?;
+                *totals = true;
+            }
+            ("end_of_record", Some(_)) => {
+                files.extend(current.take().map(|(file, _)| file));
+            }
+            _ => {}
+        }
+    }
+    files.extend(current.map(|(file, _)| file));
+    Ok(files)
+}
+
+/// The number of tests, skipped tests and failing tests of a JUnit xml
+/// report, with `<testcase>` elements in any number of `<testsuite>`s.
+fn parse_junit(report: &str) -> Result<TestResults> {
+    let mut reader = quick_xml::Reader::from_str(report);
+    let mut results = TestResults::default();
+    // Name of the test case being read, and its failure with the element
+    // the output is read from
+    let mut case: Option<String> = None;
+    let mut failure: Option<(Failure, String)> = None;
+    loop {
+        let event = reader.read_event().map_err(|err| {
+            Error::CustomBlockRead(format!("failed to parse JUnit report: {}", err))
+        })?;
+        match event {
+            XmlEvent::Start(ref element) | XmlEvent::Empty(ref element) => {
+                let empty = matches!(event, XmlEvent::Empty(_));
+                match element.local_name().as_ref() {
+                    "testcase" => {
+                        results.tests += 1;
+                        let name = xml_attribute(element, "name").unwrap_or_default();
+                        let name = match xml_attribute(element, "classname") {
+                            Some(class) if !class.is_empty() => format!("{}::{}", class, name),
+                            _ => name,
+                        };
+                        case = (!empty).then_some(name);
+                    }
+                    "skipped" if case.is_some() => results.skipped += 1,
+                    kind @ ("failure" | "error") if case.is_some() && failure.is_none() => {
+                        let failed = Failure {
+                            name: case.clone().unwrap_or_default(),
+                            message: xml_attribute(element, "message").unwrap_or_default(),
+                            output: String::new(),
+                        };
+                        match empty {
+                            true => results.failures.push(failed),
+                            false => failure = Some((failed, kind.to_string())),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::Text(text) => {
+                if let Some((failure, _)) = failure.as_mut() {
+                    failure.output += &text.xml10_content();
+                }
+            }
+            XmlEvent::CData(text) => {
+                if let Some((failure, _)) = failure.as_mut() {
+                    failure.output += &text.xml10_content();
+                }
+            }
+            XmlEvent::GeneralRef(reference) => {
+                if let Some((failure, _)) = failure.as_mut() {
+                    failure.output += &xml_unescape(&format!("&{};", reference.xml10_content()));
+                }
+            }
+            XmlEvent::End(element) => match element.local_name().as_ref() {
+                "testcase" => case = None,
+                name if failure.as_ref().is_some_and(|(_, kind)| kind == name) => {
+                    let (mut failed, _) = failure.take().unwrap();
+                    failed.output = failed.output.trim().to_string();
+                    results.failures.push(failed);
+                }
+                _ => {}
+            },
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(results)
+}
+
+#[derive(Debug, Clone)]
+enum Report {
+    Coverage(Vec<FileCoverage>),
+    Tests(TestResults),
+}
+
+/// A summary of a report written by a CI job, so the artifacts can be
+/// put next to the markdown and rendered as a dashboard. The report is
+/// read from `path`, relative to `YamdrOptions::base_dir`, or from the body
+/// of the block.
+///
+/// ````text
+/// ```{t: Coverage, path: target/lcov.info}
+/// ```
+///
+/// ```{t: TestReport, path: target/junit.xml}
+/// ```
+/// ````
+///
+/// `Coverage` reads an lcov report, and renders a table of the lines
+/// covered in each file, with the total in the last row. `TestReport`
+/// reads a JUnit xml report, and renders the number of tests, failures and
+/// skipped tests, with a list of the failing tests and their output.
+#[derive(Debug, Clone)]
+pub struct ReportBlock {
+    header: CustomBlockHeader,
+    input: String,
+    report: Report,
+    labels: Labels,
+}
+
+pub struct ReportBlockReader {
+    base_dir: Option<PathBuf>,
+    labels: Labels,
+}
+
+impl ReportBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        ReportBlockReader {
+            base_dir: options.base_dir.clone(),
+            labels: options.locale.labels.clone(),
+        }
+    }
+
+    fn read_report(&self, path: &str) -> Result<String> {
+        let full_path = match &self.base_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => PathBuf::from(path),
+        };
+        if !full_path.is_file() {
+            return Err(Error::MissingFile(path.into()));
+        }
+        std::fs::read_to_string(full_path)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err)))
+    }
+}
+
+impl CustomBlockReader for ReportBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        matches!(header.t.as_str(), "Coverage" | "TestReport")
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Coverage", "TestReport"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[HeaderField::new("path", FieldType::String)];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let report = match header_field(header, "path") {
+            Some(path) => self.read_report(&path)?,
+            None if input.trim().is_empty() => {
+                return Err(Error::CustomBlockRead(format!(
+                    "missing `path` in {} block",
+                    header.t
+                )))
+            }
+            None => input.to_string(),
+        };
+        let report = match header.t.as_str() {
+            "Coverage" => Report::Coverage(parse_lcov(&report)?),
+            _ => Report::Tests(parse_junit(&report)?),
+        };
+        Ok(Some(Box::new(ReportBlock {
+            header: header.clone(),
+            input: input.into(),
+            report,
+            labels: self.labels.clone(),
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["path"])
+    }
+}
+
+fn percentage(covered: u64, lines: u64) -> String {
+    match lines {
+        0 => "-".into(),
+        _ => format!("{:.1}%", covered as f64 * 100.0 / lines as f64),
+    }
+}
+
+impl ReportBlock {
+    fn coverage_table(&self, files: &[FileCoverage]) -> Vec<Event<'_>> {
+        let [file, lines, covered, coverage, total] = &self.labels.coverage;
+        let head = [file, lines, covered, coverage]
+            .map(|label| CowStr::from(label.as_str()))
+            .into();
+        let row = |name: CowStr<'static>, lines: u64, covered: u64| -> Vec<CowStr> {
+            vec![
+                name,
+                lines.to_string().into(),
+                covered.to_string().into(),
+                percentage(covered, lines).into(),
+            ]
+        };
+        let mut rows: Vec<Vec<CowStr>> = files
+            .iter()
+            .map(|f| row(f.file.clone().into(), f.lines, f.covered))
+            .collect();
+        rows.push(row(
+            total.clone().into(),
+            files.iter().map(|f| f.lines).sum(),
+            files.iter().map(|f| f.covered).sum(),
+        ));
+        build_table(head, rows)
+    }
+
+    fn test_summary(&self, results: &TestResults) -> Vec<Event<'_>> {
+        let [summary, failing] = &self.labels.test_report;
+        let summary = summary
+            .replacen("{}", &results.tests.to_string(), 1)
+            .replacen("{}", &results.failures.len().to_string(), 1)
+            .replacen("{}", &results.skipped.to_string(), 1);
+        let status = match results.failures.is_empty() {
+            true => "passed",
+            false => "failed",
+        };
+        let mut html = format!(r#"<div class="test-report {}"><p>"#, status);
+        escape_html(&mut html, &summary).unwrap();
+        html += "</p>\n";
+        if !results.failures.is_empty() {
+            html += "<p><strong>";
+            escape_html(&mut html, failing).unwrap();
+            html += "</strong></p>\n<ul>\n";
+            for failure in results.failures.iter() {
+                html += "<li><code>";
+                escape_html(&mut html, &failure.name).unwrap();
+                html += "</code>";
+                if !failure.message.is_empty() {
+                    html += ": ";
+                    escape_html(&mut html, &failure.message).unwrap();
+                }
+                if !failure.output.is_empty() {
+                    html += "<pre>";
+                    escape_html(&mut html, &failure.output).unwrap();
+                    html += "</pre>";
+                }
+                html += "</li>\n";
+            }
+            html += "</ul>\n";
+        }
+        html += "</div>\n";
+        vec![Event::Html(html.into())]
+    }
+}
+
+impl CustomBlock for ReportBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => match &self.report {
+                Report::Coverage(files) => self.coverage_table(files),
+                Report::Tests(results) => self.test_summary(results),
+            },
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{custom_block, render_html};
+    use crate::{dependencies, render_markdown};
+
+    const LCOV: &str = "TN:
+SF:src/lib.rs
+DA:1,4
+DA:2,0
+DA:3,1
+end_of_record
+SF:src/main.rs
+DA:1,0
+LF:10
+LH:8
+end_of_record
+";
+
+    const JUNIT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="md" tests="4">
+    <testcase classname="md::tests" name="renders"/>
+    <testcase classname="md::tests" name="parses">
+      <failure message="assertion failed">left: 1
+right: 2 &amp; more</failure>
+    </testcase>
+    <testcase name="slow"><skipped/></testcase>
+    <testcase name="io"><error message="timeout"/></testcase>
+  </testsuite>
+</testsuites>
+"#;
+
+    fn render(header: &str, input: &str) -> Result<String> {
+        render_html(&Default::default(), &custom_block(header, input))
+    }
+
+    #[test]
+    fn lcov() {
+        assert_eq!(
+            parse_lcov(LCOV).unwrap(),
+            vec![
+                FileCoverage {
+                    file: "src/lib.rs".into(),
+                    lines: 3,
+                    covered: 2,
+                },
+                FileCoverage {
+                    file: "src/main.rs".into(),
+                    lines: 10,
+                    covered: 8,
+                },
+            ]
+        );
+        assert!(parse_lcov("SF:a.rs\nDA:1,x\n").is_err());
+
+        let html = render("{t: Coverage}", LCOV).unwrap();
+        assert!(html.contains("<th>File</th><th>Lines</th><th>Covered</th><th>Coverage</th>"));
+        assert!(html.contains("<td>src/lib.rs</td><td>3</td><td>2</td><td>66.7%</td>"));
+        assert!(html.contains("<td>Total</td><td>13</td><td>10</td><td>76.9%</td>"));
+    }
+
+    #[test]
+    fn junit() {
+        let results = parse_junit(JUNIT).unwrap();
+        assert_eq!((results.tests, results.skipped), (4, 1));
+        assert_eq!(
+            results.failures,
+            vec![
+                Failure {
+                    name: "md::tests::parses".into(),
+                    message: "assertion failed".into(),
+                    output: "left: 1\nright: 2 & more".into(),
+                },
+                Failure {
+                    name: "io".into(),
+                    message: "timeout".into(),
+                    output: String::new(),
+                },
+            ]
+        );
+        assert!(parse_junit("<testsuite><testcase></testsuite>").is_err());
+
+        let html = render("{t: TestReport}", JUNIT).unwrap();
+        assert!(
+            html.contains(r#"<div class="test-report failed"><p>4 tests, 2 failed, 1 skipped</p>"#)
+        );
+        assert!(html.contains(
+            "<li><code>md::tests::parses</code>: assertion failed<pre>left: 1\nright: 2 &amp; more</pre></li>"
+        ));
+        assert!(render("{t: TestReport}", "").is_err());
+        assert!(matches!(
+            render("{t: TestReport, path: missing.xml}", ""),
+            Err(Error::MissingFile(_))
+        ));
+    }
+
+    #[test]
+    fn report_blocks() {
+        let dir = std::env::temp_dir().join(format!("yamdr-reports-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("lcov.info"),
+            "SF:src/a.rs\nLF:4\nLH:3\nend_of_record\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("junit.xml"),
+            r#"<testsuite><testcase name="a"/><testcase name="b"/></testsuite>"#,
+        )
+        .unwrap();
+        let options = YamdrOptions {
+            base_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let md =
+            "```{t: Coverage, path: lcov.info}\n```\n\n```{t: TestReport, path: junit.xml}\n```\n";
+        let (meta, html) = render_markdown(&options, md);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains("<td>src/a.rs</td><td>4</td><td>3</td><td>75.0%</td>"));
+        assert!(
+            html.contains(r#"<div class="test-report passed"><p>2 tests, 0 failed, 0 skipped</p>"#)
+        );
+        assert_eq!(
+            dependencies(&options, md),
+            vec![dir.join("lcov.info"), dir.join("junit.xml")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+use crate::{
+    code_block::highlight,
+    utils::{header_field, local_files},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Result, YamdrOptions,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use std::path::PathBuf;
+use syn::spanned::Spanned;
+
+/// The signature and documentation of an item in a Rust source file, so
+/// guides embed the real signatures instead of copies that get out of date.
+///
+/// ````text
+/// ```{t: RustDoc, path: src/lib.rs, item: MyStruct}
+/// ```
+/// ````
+///
+/// `item` may be a path, like `module::Item` or `MyStruct::new` for a
+/// method. The file is read relative to `YamdrOptions::base_dir`. The
+/// signature is the source of the item, without its doc comments and
+/// without the body of functions.
+#[derive(Debug, Clone)]
+pub struct RustDocBlock {
+    header: CustomBlockHeader,
+    input: String,
+    signature: String,
+    docs: String,
+}
+
+pub struct RustDocBlockReader {
+    base_dir: Option<PathBuf>,
+}
+
+impl RustDocBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        RustDocBlockReader {
+            base_dir: options.base_dir.clone(),
+        }
+    }
+}
+
+/// An item found in a file: its attributes, the span of its source, and,
+/// for functions, the span of the body that is left out.
+struct Found<'a> {
+    attrs: &'a [syn::Attribute],
+    span: proc_macro2::Span,
+    body: Option<proc_macro2::Span>,
+}
+
+fn item_ident(item: &syn::Item) -> Option<&syn::Ident> {
+    match item {
+        syn::Item::Const(item) => Some(&item.ident),
+        syn::Item::Enum(item) => Some(&item.ident),
+        syn::Item::Fn(item) => Some(&item.sig.ident),
+        syn::Item::Macro(item) => item.ident.as_ref(),
+        syn::Item::Mod(item) => Some(&item.ident),
+        syn::Item::Static(item) => Some(&item.ident),
+        syn::Item::Struct(item) => Some(&item.ident),
+        syn::Item::Trait(item) => Some(&item.ident),
+        syn::Item::Type(item) => Some(&item.ident),
+        syn::Item::Union(item) => Some(&item.ident),
+        _ => None,
+    }
+}
+
+fn item_attrs(item: &syn::Item) -> &[syn::Attribute] {
+    match item {
+        syn::Item::Const(item) => &item.attrs,
+        syn::Item::Enum(item) => &item.attrs,
+        syn::Item::Fn(item) => &item.attrs,
+        syn::Item::Macro(item) => &item.attrs,
+        syn::Item::Mod(item) => &item.attrs,
+        syn::Item::Static(item) => &item.attrs,
+        syn::Item::Struct(item) => &item.attrs,
+        syn::Item::Trait(item) => &item.attrs,
+        syn::Item::Type(item) => &item.attrs,
+        syn::Item::Union(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+/// Whether `ty` is the type called `name`, like the self type of an impl.
+fn is_type(ty: &syn::Type, name: &str) -> bool {
+    matches!(ty, syn::Type::Path(path) if path.path.segments.last().is_some_and(|s| s.ident == name))
+}
+
+fn find<'a>(items: &'a [syn::Item], path: &[&str]) -> Option<Found<'a>> {
+    let (name, rest) = path.split_first()?;
+    if let [method] = rest {
+        let method = items.iter().find_map(|item| match item {
+            syn::Item::Impl(item) if is_type(&item.self_ty, name) => {
+                item.items.iter().find_map(|impl_item| match impl_item {
+                    syn::ImplItem::Fn(f) if f.sig.ident == method => Some(Found {
+                        attrs: &f.attrs,
+                        span: f.span(),
+                        body: Some(f.block.span()),
+                    }),
+                    _ => None,
+                })
+            }
+            syn::Item::Trait(item) if item.ident == name => {
+                item.items.iter().find_map(|trait_item| match trait_item {
+                    syn::TraitItem::Fn(f) if f.sig.ident == method => Some(Found {
+                        attrs: &f.attrs,
+                        span: f.span(),
+                        body: f.default.as_ref().map(|block| block.span()),
+                    }),
+                    _ => None,
+                })
+            }
+            _ => None,
+        });
+        if method.is_some() {
+            return method;
+        }
+    }
+    let item = items
+        .iter()
+        .find(|item| item_ident(item).is_some_and(|ident| ident == name))?;
+    match (item, rest) {
+        (_, []) => Some(Found {
+            attrs: item_attrs(item),
+            span: item.span(),
+            body: match item {
+                syn::Item::Fn(item) => Some(item.block.span()),
+                _ => None,
+            },
+        }),
+        (syn::Item::Mod(module), rest) => find(&module.content.as_ref()?.1, rest),
+        _ => None,
+    }
+}
+
+/// Byte offset of a line and column, as given by `proc_macro2`, in `source`.
+fn offset(source: &str, position: proc_macro2::LineColumn) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(position.line - 1)
+        .map(str::len)
+        .sum();
+    line_start
+        + source[line_start..]
+            .chars()
+            .take(position.column)
+            .map(char::len_utf8)
+            .sum::<usize>()
+}
+
+fn doc_comments(attrs: &[syn::Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(doc),
+                        ..
+                    }),
+                ..
+            }) => Some(doc.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(String::from).unwrap_or(line) + "\n")
+        .collect()
+}
+
+/// The signature and doc comments of the item at `path` in `source`.
+fn extract(source: &str, path: &str) -> Result<(String, String), String> {
+    let file = syn::parse_file(source).map_err(|err| format!("failed to parse file: {}", err))?;
+    let segments: Vec<&str> = path.split("::").collect();
+    let found = find(&file.items, &segments).ok_or_else(|| format!("item `{}` not found", path))?;
+    // The span of an item includes its attributes, the signature starts
+    // after the last doc comment.
+    let start = found
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .map(|attr| offset(source, attr.span().end()))
+        .max()
+        .unwrap_or_else(|| offset(source, found.span.start()));
+    let end = match found.body {
+        Some(body) => offset(source, body.start()),
+        None => offset(source, found.span.end()),
+    };
+    let signature = source[start..end].trim();
+    // Indentation of the following lines is relative to the first line
+    let indent = source[..start]
+        .rsplit('\n')
+        .next()
+        .map(|line| line.len() - line.trim_start().len())
+        .unwrap_or_default();
+    let signature = signature
+        .lines()
+        .map(|line| match line.get(..indent) {
+            Some(prefix) if prefix.trim().is_empty() => &line[indent..],
+            _ => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((signature, doc_comments(found.attrs)))
+}
+
+impl CustomBlockReader for RustDocBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "RustDoc"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["RustDoc"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("path", FieldType::String),
+            HeaderField::new("item", FieldType::String),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let field = |name: &str| {
+            header_field(header, name).ok_or_else(|| {
+                Error::CustomBlockRead(format!("missing `{}` in RustDoc block", name))
+            })
+        };
+        let (path, item) = (field("path")?, field("item")?);
+        let full_path = match &self.base_dir {
+            Some(base_dir) => base_dir.join(&path),
+            None => PathBuf::from(&path),
+        };
+        if !full_path.is_file() {
+            return Err(Error::MissingFile(path));
+        }
+        let source = std::fs::read_to_string(full_path)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err)))?;
+        let (signature, docs) = extract(&source, &item).map_err(Error::CustomBlockRead)?;
+        Ok(Some(Box::new(RustDocBlock {
+            header: header.clone(),
+            input: input.into(),
+            signature,
+            docs,
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["path"])
+    }
+}
+
+impl CustomBlock for RustDocBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut html = String::from(
+                    r#"<div class="rustdoc"><pre class="codeblock language-rust"><code class="">"#,
+                );
+                for line in highlight(&self.signature, Some("rust"), true).lines() {
+                    html += r#"<span class="line">"#;
+                    html += line;
+                    html += "</span>\n";
+                }
+                html += "</code></pre>";
+                let mut events = vec![Event::Html(html.into())];
+                events.extend(Parser::new_ext(&self.docs, Options::all()));
+                events.push(Event::Html("</div>\n".into()));
+                events
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+/// A point.
+///
+/// With *two* coordinates.
+#[derive(Debug)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    /// Distance to `other`.
+    pub fn distance(&self, other: &Point) -> f64 {
+        0.0
+    }
+}
+
+mod shapes {
+    /// Area of a circle.
+    pub fn area(radius: f64)
+        -> f64
+    {
+        3.14 * radius * radius
+    }
+}
+"#;
+
+    #[test]
+    fn extract_items() {
+        assert_eq!(
+            extract(SOURCE, "Point").unwrap(),
+            (
+                "#[derive(Debug)]\npub struct Point {\n    pub x: f64,\n    pub y: f64,\n}".into(),
+                "A point.\n\nWith *two* coordinates.\n".into()
+            )
+        );
+        assert_eq!(
+            extract(SOURCE, "Point::distance").unwrap(),
+            (
+                "pub fn distance(&self, other: &Point) -> f64".into(),
+                "Distance to `other`.\n".into()
+            )
+        );
+        assert_eq!(
+            extract(SOURCE, "shapes::area").unwrap().0,
+            "pub fn area(radius: f64)\n    -> f64"
+        );
+        assert!(extract(SOURCE, "Line").is_err());
+        assert!(extract("fn {", "Line").is_err());
+    }
+
+    #[test]
+    fn read_from_file() {
+        let mut reader = RustDocBlockReader::new(&YamdrOptions {
+            base_dir: Some(PathBuf::from(env!("CARGO_MANIFEST_DIR"))),
+            ..Default::default()
+        });
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: RustDoc, path: src/lib.rs, item: CustomBlock::to_events}")
+                .unwrap();
+        let block = reader.read_block(&header, "").unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains(
+            r#"<span class="_keyword">fn</span> <span class="_function">to_events</span>"#
+        ));
+        assert!(html.contains("<p>Render the block as a list of"));
+
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: RustDoc, path: src/missing.rs, item: A}").unwrap();
+        assert!(matches!(
+            reader.read_block(&header, ""),
+            Err(Error::MissingFile(_))
+        ));
+    }
+}
@@ -0,0 +1,389 @@
+use crate::{
+    utils::{build_table, resolve_ref},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, Format, Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+use serde_yaml::{Mapping, Value};
+
+/// How deep properties and examples are generated, so recursive schemas
+/// terminate.
+const MAX_DEPTH: usize = 8;
+
+/// A property of the schema, as a row of the table. Nested properties are
+/// named by their path, like `address.city` or `tags[].name`.
+#[derive(Debug, Clone, PartialEq)]
+struct Property {
+    name: String,
+    ty: String,
+    required: bool,
+    description: String,
+}
+
+/// A table of the properties of a JSON Schema, and an example payload
+/// generated from it, for protocol documentation. The schema is written as
+/// YAML or JSON in the body of the block:
+///
+/// ````text
+/// ```{t: Schema}
+/// type: object
+/// required: [id]
+/// properties:
+///   id: {type: integer, description: Id of the user}
+///   email: {type: string, format: email}
+/// ```
+/// ````
+///
+/// Examples are taken from `example`, `default` and `enum` where they are
+/// set, and generated from the type otherwise.
+#[derive(Debug, Clone)]
+pub struct SchemaBlock {
+    header: CustomBlockHeader,
+    input: String,
+    properties: Vec<Property>,
+    example: Value,
+    labels: Labels,
+}
+
+pub struct SchemaBlockReader {
+    labels: Labels,
+}
+
+impl SchemaBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        SchemaBlockReader {
+            labels: options.locale.labels.clone(),
+        }
+    }
+}
+
+impl CustomBlockReader for SchemaBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Schema"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Schema"]
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let schema: Value = serde_yaml::from_str(input)
+            .map_err(|err| Error::CustomBlockRead(format!("failed to parse schema: {}", err)))?;
+        let mut props = Vec::new();
+        properties(&schema, &schema, "", 0, &mut props);
+        Ok(Some(Box::new(SchemaBlock {
+            header: header.clone(),
+            input: input.into(),
+            properties: props,
+            example: example(&schema, &schema, 0),
+            labels: self.labels.clone(),
+        })))
+    }
+}
+
+fn items(values: Option<&Value>) -> impl Iterator<Item = &Value> {
+    values.and_then(Value::as_sequence).into_iter().flatten()
+}
+
+fn type_name(schema: &Value, depth: usize) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference.rsplit('/').next().unwrap_or(reference).into();
+    }
+    let alternatives = items(schema.get("oneOf"))
+        .chain(items(schema.get("anyOf")))
+        .map(|schema| type_name(schema, depth + 1))
+        .collect::<Vec<_>>();
+    if !alternatives.is_empty() && depth < MAX_DEPTH {
+        return alternatives.join(" | ");
+    }
+    match schema.get("type") {
+        Some(Value::String(ty)) if ty == "array" && depth < MAX_DEPTH => {
+            match schema.get("items") {
+                Some(items) => format!("{}[]", type_name(items, depth + 1)),
+                None => "array".into(),
+            }
+        }
+        Some(Value::String(ty)) => match schema.get("format").and_then(Value::as_str) {
+            Some(format) => format!("{} ({})", ty, format),
+            None => ty.clone(),
+        },
+        Some(Value::Sequence(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" | "),
+        _ if schema.get("properties").is_some() => "object".into(),
+        _ => String::new(),
+    }
+}
+
+fn properties(root: &Value, schema: &Value, prefix: &str, depth: usize, out: &mut Vec<Property>) {
+    let schema = resolve_ref(root, schema);
+    if depth >= MAX_DEPTH {
+        return;
+    }
+    let required: Vec<&str> = items(schema.get("required"))
+        .filter_map(Value::as_str)
+        .collect();
+    let Some(props) = schema.get("properties").and_then(Value::as_mapping) else {
+        return;
+    };
+    for (name, property) in props {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let name = format!("{}{}", prefix, name);
+        let resolved = resolve_ref(root, property);
+        out.push(Property {
+            name: name.clone(),
+            ty: type_name(property, 0),
+            required: required.contains(&&name[prefix.len()..]),
+            description: resolved
+                .get("description")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .into(),
+        });
+        properties(root, resolved, &format!("{}.", name), depth + 1, out);
+        if let Some(items) = resolved.get("items") {
+            properties(root, items, &format!("{}[].", name), depth + 1, out);
+        }
+    }
+}
+
+/// An example value of `schema`.
+fn example(root: &Value, schema: &Value, depth: usize) -> Value {
+    let schema = resolve_ref(root, schema);
+    if depth >= MAX_DEPTH {
+        return Value::Null;
+    }
+    let given = schema
+        .get("example")
+        .or_else(|| items(schema.get("examples")).next())
+        .or_else(|| schema.get("const"))
+        .or_else(|| schema.get("default"))
+        .or_else(|| items(schema.get("enum")).next());
+    if let Some(given) = given {
+        return given.clone();
+    }
+    if let Some(first) = items(schema.get("oneOf"))
+        .chain(items(schema.get("anyOf")))
+        .next()
+    {
+        return example(root, first, depth + 1);
+    }
+    let ty = match schema.get("type") {
+        Some(Value::Sequence(types)) => types.iter().filter_map(Value::as_str).next(),
+        ty => ty.and_then(Value::as_str),
+    };
+    match ty {
+        Some("string") => Value::String(
+            match schema.get("format").and_then(Value::as_str) {
+                Some("date-time") => "2024-01-01T12:00:00Z",
+                Some("date") => "2024-01-01",
+                Some("email") => "user@example.com",
+                Some("uri" | "url") => "https://example.com",
+                Some("uuid") => "3fa85f64-5717-4562-b3fc-2c963f66afa6",
+                _ => "string",
+            }
+            .into(),
+        ),
+        Some("integer") => Value::Number(0.into()),
+        Some("number") => Value::Number(0.5.into()),
+        Some("boolean") => Value::Bool(true),
+        Some("null") => Value::Null,
+        Some("array") => Value::Sequence(
+            schema
+                .get("items")
+                .map(|items| example(root, items, depth + 1))
+                .into_iter()
+                .collect(),
+        ),
+        _ => {
+            let mut mapping = Mapping::new();
+            let all = std::iter::once(schema).chain(items(schema.get("allOf")));
+            for schema in all.map(|schema| resolve_ref(root, schema)) {
+                let props = schema.get("properties").and_then(Value::as_mapping);
+                for (name, property) in props.into_iter().flatten() {
+                    mapping.insert(name.clone(), example(root, property, depth + 1));
+                }
+            }
+            Value::Mapping(mapping)
+        }
+    }
+}
+
+fn span(class: &str, text: &str, output: &mut String) {
+    output.push_str(r#"<span class="_"#);
+    output.push_str(class);
+    output.push_str(r#"">"#);
+    escape_html(&mut *output, text).unwrap();
+    output.push_str("</span>");
+}
+
+/// Write `value` as pretty printed JSON, highlighted with the same classes
+/// as code blocks.
+fn json_html(value: &Value, indent: usize, output: &mut String) {
+    let newline = |output: &mut String, indent: usize| {
+        output.push('\n');
+        output.push_str(&"  ".repeat(indent));
+    };
+    match value {
+        Value::Null => span("constant", "null", output),
+        Value::Bool(b) => span("constant", &b.to_string(), output),
+        Value::Number(n) => span("constant", &n.to_string(), output),
+        Value::String(s) => span("string", &serde_json::to_string(s).unwrap(), output),
+        Value::Sequence(values) if values.is_empty() => span("punctuation_bracket", "[]", output),
+        Value::Mapping(mapping) if mapping.is_empty() => span("punctuation_bracket", "{}", output),
+        Value::Sequence(values) => {
+            span("punctuation_bracket", "[", output);
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    span("punctuation_delimiter", ",", output);
+                }
+                newline(output, indent + 1);
+                json_html(value, indent + 1, output);
+            }
+            newline(output, indent);
+            span("punctuation_bracket", "]", output);
+        }
+        Value::Mapping(mapping) => {
+            span("punctuation_bracket", "{", output);
+            for (i, (key, value)) in mapping.iter().enumerate() {
+                if i > 0 {
+                    span("punctuation_delimiter", ",", output);
+                }
+                newline(output, indent + 1);
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    key => serde_yaml::to_string(key).unwrap().trim().to_string(),
+                };
+                span("property", &serde_json::to_string(&key).unwrap(), output);
+                span("punctuation_delimiter", ":", output);
+                output.push(' ');
+                json_html(value, indent + 1, output);
+            }
+            newline(output, indent);
+            span("punctuation_bracket", "}", output);
+        }
+        Value::Tagged(tagged) => json_html(&tagged.value, indent, output),
+    }
+}
+
+impl CustomBlock for SchemaBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let head = self.labels.schema.iter().map(|label| label.as_str().into());
+                let rows = self
+                    .properties
+                    .iter()
+                    .map(|property| {
+                        vec![
+                            property.name.as_str().into(),
+                            property.ty.as_str().into(),
+                            if property.required { "✓" } else { "" }.into(),
+                            property.description.as_str().into(),
+                        ]
+                    })
+                    .collect();
+                let mut json = String::new();
+                json_html(&self.example, 0, &mut json);
+                let mut html =
+                    String::from(r#"<div><pre class="codeblock language-json"><code class="">"#);
+                for line in json.lines() {
+                    html += r#"<span class="line">"#;
+                    html += line;
+                    html += "</span>\n";
+                }
+                html += "</code></pre></div>\n";
+                let mut events = vec![Event::Html(r#"<div class="schema">"#.into())];
+                events.extend(build_table(head.collect(), rows));
+                events.push(Event::Html(html.into()));
+                events.push(Event::Html("</div>\n".into()));
+                events
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r##"
+type: object
+required: [id, tags]
+properties:
+  id: {type: integer, description: Id of the <user>}
+  email: {type: string, format: email}
+  role: {enum: [admin, member]}
+  tags:
+    type: array
+    items: {$ref: "#/$defs/tag"}
+  parent: {$ref: "#"}
+$defs:
+  tag:
+    type: object
+    properties:
+      name: {type: string, example: urgent}
+"##;
+
+    #[test]
+    fn properties_and_example() {
+        let schema: Value = serde_yaml::from_str(SCHEMA).unwrap();
+        let mut props = Vec::new();
+        properties(&schema, &schema, "", 0, &mut props);
+        assert_eq!(
+            props[..5]
+                .iter()
+                .map(|p| (p.name.as_str(), p.ty.as_str(), p.required))
+                .collect::<Vec<_>>(),
+            vec![
+                ("id", "integer", true),
+                ("email", "string (email)", false),
+                ("role", "", false),
+                ("tags", "tag[]", true),
+                ("tags[].name", "string", false),
+            ]
+        );
+        assert_eq!(props[5].name, "parent");
+        // The recursive `parent` property stops at the max depth
+        assert!(props.len() < 100);
+
+        let mut json = String::new();
+        json_html(&example(&schema, &schema, 0), 0, &mut json);
+        assert!(json.starts_with(
+            r#"<span class="_punctuation_bracket">{</span>
+  <span class="_property">&quot;id&quot;</span><span class="_punctuation_delimiter">:</span> <span class="_constant">0</span><span class="_punctuation_delimiter">,</span>
+  <span class="_property">&quot;email&quot;</span><span class="_punctuation_delimiter">:</span> <span class="_string">&quot;user@example.com&quot;</span>"#
+        ));
+        assert!(json.contains(r#"<span class="_string">&quot;admin&quot;</span>"#));
+        assert!(json.contains(r#"<span class="_string">&quot;urgent&quot;</span>"#));
+
+        let mut reader = SchemaBlockReader::new(&Default::default());
+        let header = CustomBlockHeader::empty("Schema".into());
+        let block = reader.read_block(&header, SCHEMA).unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(
+            html.contains("<td>id</td><td>integer</td><td>✓</td><td>Id of the &lt;user&gt;</td>")
+        );
+        assert!(reader.read_block(&header, "type: [").is_err());
+    }
+}
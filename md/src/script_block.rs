@@ -1,24 +1,55 @@
 use crate::{
-    plotters_block::PlottersBlock,
-    utils::{dynamic_as_f64, html_hide_with_title},
-    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, Format, Result,
+    ledger_block::LedgerBlock,
+    plotters_block::{linear_fit, ChartTheme, PlottersChart, Trendline},
+    summary_block::{Stats, SummaryBlock},
+    utils::{
+        accessible_svg, build_table, dynamic_as_f64, dynamic_to_json, header_field,
+        html_hide_with_title, json_to_dynamic, local_files, resolve_file, TableLayout,
+        TABLE_SCRIPT,
+    },
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Labels, NumberFormat, Result, YamdrOptions,
 };
-use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
 use rhai::{plugin::Dynamic, Engine, Scope, AST};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct ScriptBlock {
     output: OutputType,
     header: CustomBlockHeader,
+    labels: Labels,
+    numbers: Option<NumberFormat>,
+    layout: TableLayout,
+    /// Size and colors of the chart of `DynamicChart` blocks.
+    theme: ChartTheme,
+    /// Whether `TABLE_SCRIPT` is included after the table, for the first
+    /// interactive table of the document.
+    table_script: bool,
 }
 
 pub struct ScriptBlockReader {
     runtime: Runtime,
     data: BTreeMap<String, DataBlock>,
+    /// Number of `DynamicTable` blocks read so far.
+    tables: usize,
+    labels: Labels,
+    numbers: Option<NumberFormat>,
+    chart_theme: ChartTheme,
+    /// Default of `interactive` for tables, see
+    /// `YamdrOptions::interactive_tables`.
+    interactive_tables: bool,
+    /// Whether `TABLE_SCRIPT` has been included by a block already.
+    table_script: bool,
+    /// Directory the `path` of Data blocks is relative to.
+    base_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -41,37 +72,267 @@ struct DataBlockPredefinedField {
     name: String,
 }
 
+/// Rows of a Data block, either written in its body, or read from the
+/// csv, tsv, json or yaml file in `path` of its header, see
+/// `read_data_file`.
+///
+/// ````text
+/// ```{t: Data, name: sales, path: sales.csv}
+/// ```
+/// ````
+///
+/// The rows of a file aren't written back to the document when rendering
+/// to markdown, only the preview table.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct DataBlock {
+    #[serde(default)]
     name: String,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     fields: Vec<DataBlockPredefinedField>,
     data: Vec<BTreeMap<String, String>>,
+    /// Whether the rows were read from a file.
+    #[serde(skip)]
+    from_file: bool,
+}
+
+/// A value of a data file as a cell, with nested values as json.
+fn data_cell(value: serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::Null => None,
+        serde_yaml::Value::String(s) => Some(s),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        value => serde_json::to_string(&value).ok(),
+    }
+}
+
+/// Rows of a data file, by its extension: a csv or tsv file with a
+/// heading row, or a json or yaml list of objects.
+fn read_data_file(path: &str, content: &str) -> Result<Vec<BTreeMap<String, String>>, String> {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+    match extension.map(str::to_lowercase).as_deref() {
+        Some(extension @ ("csv" | "tsv")) => {
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(if extension == "tsv" { b'\t' } else { b',' })
+                .from_reader(content.as_bytes());
+            let head = reader.headers().map_err(|err| err.to_string())?.clone();
+            reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(|err| err.to_string())?;
+                    Ok(head
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(field, value)| (field.to_string(), value.to_string()))
+                        .collect())
+                })
+                .collect()
+        }
+        // Json is read as yaml, which it is a subset of
+        Some("json" | "yaml" | "yml") => {
+            let rows: Vec<serde_yaml::Mapping> =
+                serde_yaml::from_str(content).map_err(|err| err.to_string())?;
+            Ok(rows
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .filter_map(|(field, value)| Some((data_cell(field)?, data_cell(value)?)))
+                        .collect()
+                })
+                .collect())
+        }
+        _ => Err(format!(
+            "unsupported data file `{}`, expected csv, tsv, json or yaml",
+            path
+        )),
+    }
 }
 
 impl ScriptBlockReader {
-    pub fn initial_state() -> Self {
-        let engine = Engine::new();
-        let scope = Scope::new();
+    pub fn new(options: &YamdrOptions) -> Self {
+        let frozen_time = options.deterministic.then(Instant::now);
+        let mut scope = Scope::new();
+        let vars: rhai::Map = options
+            .variables
+            .iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        scope.push_constant("vars", vars);
+        let previous = match &options.previous {
+            Some(previous) => previous
+                .iter()
+                .map(|(name, value)| (name.into(), json_to_dynamic(value)))
+                .collect::<rhai::Map>()
+                .into(),
+            None => Dynamic::UNIT,
+        };
+        scope.push_constant_dynamic("previous", previous);
+        let outputs = Rc::new(RefCell::new(rhai::Map::new()));
+        let exports = Rc::new(RefCell::new(rhai::Map::new()));
         ScriptBlockReader {
             runtime: Runtime {
-                engine,
+                engine: new_engine(frozen_time, &outputs, &exports),
                 scope,
                 globals: None,
+                frozen_time,
+                outputs,
+                exports,
             },
             data: BTreeMap::new(),
+            tables: 0,
+            labels: options.locale.labels.clone(),
+            numbers: options.locale.numbers,
+            chart_theme: options.chart_theme.clone(),
+            interactive_tables: options.interactive_tables,
+            table_script: false,
+            base_dir: options.base_dir.clone(),
         }
     }
+
+    /// The Data block of `header`, from its body or the file in `path`.
+    fn read_data(&self, header: &CustomBlockHeader, input: &str) -> Result<DataBlock> {
+        let mut data = match header_field(header, "path") {
+            Some(path) => {
+                let full_path = resolve_file(self.base_dir.as_deref(), &path)?;
+                let content = std::fs::read_to_string(&full_path).map_err(|err| {
+                    Error::CustomBlockRead(format!("failed to read `{}`: {}", path, err))
+                })?;
+                let rows = read_data_file(&path, &content).map_err(|err| {
+                    Error::CustomBlockRead(format!("failed to parse `{}`: {}", path, err))
+                })?;
+                DataBlock {
+                    name: String::new(),
+                    fields: Vec::new(),
+                    data: rows,
+                    from_file: true,
+                }
+            }
+            None => serde_yaml::from_str(input)
+                .map_err(|err| Error::CustomBlockRead(format!("failed to parse block: {}", err)))?,
+        };
+        if let Some(name) = header_field(header, "name") {
+            data.name = name;
+        }
+        if data.name.is_empty() {
+            return Err(Error::CustomBlockRead(
+                "missing `name` in Data block".into(),
+            ));
+        }
+        Ok(data)
+    }
+
+    fn block(&self, header: &CustomBlockHeader, output: OutputType) -> Box<ScriptBlock> {
+        Box::new(ScriptBlock {
+            output,
+            header: header.clone(),
+            labels: self.labels.clone(),
+            numbers: NumberFormat::for_header(header, self.numbers),
+            layout: TableLayout::default(),
+            theme: self.chart_theme.clone(),
+            table_script: false,
+        })
+    }
+
+    /// A block rendering a table, with the layout of its header.
+    fn table_block(&mut self, header: &CustomBlockHeader, output: OutputType) -> Box<ScriptBlock> {
+        let mut block = self.block(header, output);
+        block.layout = TableLayout::from_header(header, self.interactive_tables);
+        block.table_script = block.layout.interactive && !self.table_script;
+        self.table_script |= block.layout.interactive;
+        block
+    }
 }
 
 impl CustomBlockReader for ScriptBlockReader {
     fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
         matches!(
             header.t.as_str(),
-            "DynamicTable" | "DynamicChart" | "ScriptGlobals" | "Script" | "Data"
+            "DynamicTable"
+                | "DynamicChart"
+                | "ScriptGlobals"
+                | "Script"
+                | "Data"
+                | "Ledger"
+                | "Summary"
         )
     }
 
+    fn block_types(&self) -> &'static [&'static str] {
+        &[
+            "DynamicTable",
+            "DynamicChart",
+            "ScriptGlobals",
+            "Script",
+            "Data",
+            "Ledger",
+            "Summary",
+        ]
+    }
+
+    fn reads_empty_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Summary" || header.t == "Data" && header.fields.contains_key("path")
+    }
+
+    fn header_schema(&self, header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const HIDDEN: &[HeaderField] = &[
+            HeaderField::new("hidden", FieldType::Bool),
+            HeaderField::new("hidden_title", FieldType::String),
+        ];
+        const TABLE: &[HeaderField] = &[
+            HeaderField::new("hidden", FieldType::Bool),
+            HeaderField::new("hidden_title", FieldType::String),
+            HeaderField::new("locale", FieldType::String),
+            HeaderField::new("stacked", FieldType::Bool),
+            HeaderField::new("max_rows", FieldType::Integer),
+            HeaderField::new("page_size", FieldType::Integer),
+            HeaderField::new("interactive", FieldType::Bool),
+        ];
+        const DATA: &[HeaderField] = &[
+            HeaderField::new("hidden", FieldType::Bool),
+            HeaderField::new("hidden_title", FieldType::String),
+            HeaderField::new("locale", FieldType::String),
+            HeaderField::new("stacked", FieldType::Bool),
+            HeaderField::new("max_rows", FieldType::Integer),
+            HeaderField::new("page_size", FieldType::Integer),
+            HeaderField::new("interactive", FieldType::Bool),
+            HeaderField::new("name", FieldType::String),
+            HeaderField::new("path", FieldType::String),
+        ];
+        const CHART: &[HeaderField] = &[
+            HeaderField::new("hidden", FieldType::Bool),
+            HeaderField::new("hidden_title", FieldType::String),
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("accessibility", FieldType::String),
+            HeaderField::new("locale", FieldType::String),
+            HeaderField::new("trendline", FieldType::String),
+            HeaderField::new("width", FieldType::Integer),
+            HeaderField::new("height", FieldType::Integer),
+            HeaderField::new("palette", FieldType::List),
+            HeaderField::new("background", FieldType::String),
+        ];
+        const SUMMARY: &[HeaderField] = &[
+            HeaderField::new("data", FieldType::String),
+            HeaderField::new("by", FieldType::String),
+            HeaderField::new("field", FieldType::String),
+            HeaderField::new("locale", FieldType::String),
+        ];
+        const LEDGER: &[HeaderField] = &[
+            HeaderField::new("name", FieldType::String),
+            HeaderField::new("account", FieldType::String),
+            HeaderField::new("show", FieldType::String),
+        ];
+        match header.t.as_str() {
+            "ScriptGlobals" => Some(&[]),
+            "DynamicChart" => Some(CHART),
+            "DynamicTable" => Some(TABLE),
+            "Data" => Some(DATA),
+            "Ledger" => Some(LEDGER),
+            "Summary" => Some(SUMMARY),
+            _ => Some(HIDDEN),
+        }
+    }
+
     fn read_block(
         &mut self,
         header: &CustomBlockHeader,
@@ -81,10 +342,7 @@ impl CustomBlockReader for ScriptBlockReader {
             "Script" => {
                 let output = self.runtime.run_block(input);
                 match output {
-                    Ok(output) => Ok(Some(Box::new(ScriptBlock {
-                        output: OutputType::RunningScript(output),
-                        header: header.clone(),
-                    }))),
+                    Ok(output) => Ok(Some(self.block(header, OutputType::RunningScript(output)))),
                     Err(err) => Err(Error::CustomBlockRead(err)),
                 }
             }
@@ -96,29 +354,114 @@ impl CustomBlockReader for ScriptBlockReader {
                 }
             }
             "DynamicTable" => match self.runtime.generate_table(input) {
-                Ok((head, rows)) => Ok(Some(Box::new(ScriptBlock {
-                    output: OutputType::Table((input.into(), head, rows)),
-                    header: header.clone(),
-                }))),
+                Ok((head, rows)) => {
+                    self.tables += 1;
+                    let mut output = rhai::Map::new();
+                    output.insert("columns".into(), head.clone().into());
+                    let values: Vec<Dynamic> = rows.iter().map(|row| row.clone().into()).collect();
+                    output.insert("rows".into(), values.into());
+                    match header_field(header, "id") {
+                        Some(id) => self.runtime.add_output(id, output),
+                        // Tables without an id are only exported, by position
+                        None => {
+                            let name = format!("table-{}", self.tables);
+                            self.runtime
+                                .exports
+                                .borrow_mut()
+                                .insert(name.into(), output.into());
+                        }
+                    }
+                    Ok(Some(self.table_block(
+                        header,
+                        OutputType::Table((input.into(), head, rows)),
+                    )))
+                }
                 Err(err) => Err(Error::CustomBlockRead(err)),
             },
             "DynamicChart" => match self.runtime.generate_chart(input) {
-                Ok(data) => Ok(Some(Box::new(ScriptBlock {
-                    output: OutputType::Chart((input.into(), data)),
-                    header: header.clone(),
-                }))),
+                Ok(data) => {
+                    let trendline = header_field(header, "trendline")
+                        .map(Trendline::try_from)
+                        .transpose()
+                        .map_err(Error::CustomBlockRead)?;
+                    if let Some(id) = header_field(header, "id") {
+                        let points = |points: &[(f32, f32)]| -> Dynamic {
+                            let points: Vec<Dynamic> = points
+                                .iter()
+                                .map(|(x, y)| {
+                                    let point = vec![
+                                        Dynamic::from(*x as rhai::FLOAT),
+                                        Dynamic::from(*y as rhai::FLOAT),
+                                    ];
+                                    point.into()
+                                })
+                                .collect();
+                            points.into()
+                        };
+                        let series: Vec<Dynamic> = data.iter().map(|p| points(p)).collect();
+                        let mut output = rhai::Map::new();
+                        output.insert("series".into(), series.into());
+                        if let Some(trendline) = trendline {
+                            let trendlines: Vec<Dynamic> = data
+                                .iter()
+                                .map(|series| {
+                                    let mut fit = rhai::Map::new();
+                                    fit.insert("points".into(), points(&trendline.points(series)));
+                                    if let (Trendline::Linear, Some(linear)) =
+                                        (trendline, linear_fit(series))
+                                    {
+                                        for (name, value) in [
+                                            ("slope", linear.slope),
+                                            ("intercept", linear.intercept),
+                                            ("r2", linear.r2),
+                                        ] {
+                                            fit.insert(name.into(), (value as rhai::FLOAT).into());
+                                        }
+                                    }
+                                    fit.into()
+                                })
+                                .collect();
+                            output.insert("trendlines".into(), trendlines.into());
+                        }
+                        self.runtime.add_output(id, output);
+                    }
+                    let mut block = self.block(header, OutputType::Chart((input.into(), data)));
+                    block.theme = ChartTheme::for_header(header, &self.chart_theme)
+                        .map_err(Error::CustomBlockRead)?;
+                    Ok(Some(block))
+                }
                 Err(err) => Err(Error::CustomBlockRead(err)),
             },
             "Data" => {
-                let data: DataBlock = serde_yaml::from_str(input).map_err(|err| {
-                    Error::CustomBlockRead(format!("failed to parse block: {}", err))
-                })?;
+                let data = self.read_data(header, input)?;
                 self.runtime.add_constant(data.clone());
                 self.data.insert(data.name.clone(), data.clone());
-                Ok(Some(Box::new(ScriptBlock {
-                    output: OutputType::Data(data),
-                    header: header.clone(),
-                })))
+                Ok(Some(self.table_block(header, OutputType::Data(data))))
+            }
+            "Summary" => {
+                let name = header_field(header, "data").ok_or_else(|| {
+                    Error::CustomBlockRead("missing `data` in Summary block".into())
+                })?;
+                let data = self.data.get(&name).ok_or_else(|| {
+                    Error::CustomBlockRead(format!("no Data block named `{}`", name))
+                })?;
+                let block = SummaryBlock::read(
+                    header,
+                    input,
+                    &data.data,
+                    &self.labels,
+                    NumberFormat::for_header(header, self.numbers),
+                )
+                .map_err(Error::CustomBlockRead)?;
+                Ok(Some(Box::new(block)))
+            }
+            "Ledger" => {
+                let ledger = LedgerBlock::read(header, input, &self.labels)
+                    .map_err(Error::CustomBlockRead)?;
+                self.runtime
+                    .scope
+                    .push_constant(ledger.name(), ledger.totals());
+                Ok(Some(Box::new(ledger)))
             }
             _ => Err(Error::UnsupportedBlockType(header.t.clone())),
         }
@@ -130,27 +473,142 @@ impl CustomBlockReader for ScriptBlockReader {
     fn read_inline(&mut self, inline: &str) -> Result<Option<Box<dyn CustomBlock>>> {
         let input = &inline[1..(inline.len() - 1)];
         match self.runtime.eval_line(input) {
-            Ok(output) => Ok(Some(Box::new(ScriptBlock {
-                output: OutputType::Inline(format!(
+            Ok(output) => Ok(Some(self.block(
+                &CustomBlockHeader::empty("".into()),
+                OutputType::Inline(format!(
                     "{} // > {}",
                     input.split(" // >").next().unwrap_or(""),
                     output
                 )),
-                header: CustomBlockHeader::empty("".into()),
-            }))),
+            ))),
             Err(err) => Err(Error::CustomBlockRead(err)),
         }
     }
+
+    fn runs_scripts(&self) -> bool {
+        true
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        match header.t.as_str() {
+            "Data" => local_files(header, &["path"]),
+            _ => Vec::new(),
+        }
+    }
+
+    fn variable(&self, path: &str) -> Option<String> {
+        let mut segments = path.split('.');
+        let mut value = self.runtime.scope.get(segments.next()?)?.clone();
+        for segment in segments {
+            let field = value.read_lock::<rhai::Map>()?.get(segment)?.clone();
+            value = field;
+        }
+        Some(value.to_string())
+    }
+
+    fn data(&self, name: &str) -> Option<Vec<BTreeMap<String, String>>> {
+        self.data.get(name).map(|data| data.data.clone())
+    }
+
+    fn frontmatter(&mut self, fields: &serde_yaml::Mapping) {
+        let meta =
+            serde_json::to_value(fields).map_or(Dynamic::UNIT, |meta| json_to_dynamic(&meta));
+        self.runtime.scope.push_constant_dynamic("meta", meta);
+    }
+
+    fn exported_data(&self) -> BTreeMap<String, serde_json::Value> {
+        let mut exported: BTreeMap<String, serde_json::Value> = self
+            .runtime
+            .outputs
+            .borrow()
+            .iter()
+            .chain(self.runtime.exports.borrow().iter())
+            .map(|(id, output)| (id.to_string(), dynamic_to_json(output)))
+            .collect();
+        exported.extend(
+            self.data
+                .iter()
+                .map(|(name, data)| (name.clone(), serde_json::to_value(&data.data).unwrap())),
+        );
+        exported
+    }
 }
 
 struct Runtime {
     engine: Engine,
     scope: Scope<'static>,
     globals: Option<AST>,
+    /// Time frozen at creation, when output should be deterministic.
+    frozen_time: Option<Instant>,
+    /// Output of the `DynamicTable` and `DynamicChart` blocks with an `id`
+    /// so far, by id, see `new_engine`.
+    outputs: Rc<RefCell<rhai::Map>>,
+    /// Values exported with `export_data(name, value)`, and the output of
+    /// `DynamicTable` blocks without an `id`, see `Meta::data`.
+    exports: Rc<RefCell<rhai::Map>>,
+}
+
+impl ScriptBlock {
+    /// A table cell, with numbers and dates written in the locale of the
+    /// block in html. The markdown preview table is kept as it is.
+    fn cell<'a>(&self, format: Format, cell: &'a str) -> CowStr<'a> {
+        match (format, &self.numbers) {
+            (Format::Html, Some(numbers)) => numbers
+                .cell(cell)
+                .map_or(CowStr::Borrowed(cell), CowStr::from),
+            _ => cell.into(),
+        }
+    }
+
+    /// Html of a table with the `stacked`, `max_rows`, `page_size` or
+    /// `interactive` layout of the block, from the cells in `values`. The
+    /// markdown preview table is kept as it is.
+    fn layout_table(
+        &self,
+        format: Format,
+        head: &[CowStr],
+        values: &[Vec<CowStr>],
+    ) -> Option<String> {
+        match format {
+            Format::Html => {
+                let rows: Vec<Vec<CowStr>> = values
+                    .iter()
+                    .map(|row| row.iter().map(|cell| self.cell(format, cell)).collect())
+                    .collect();
+                let html = self.layout.html(head, &rows, values, &self.labels)?;
+                match self.table_script {
+                    true => Some(html + TABLE_SCRIPT),
+                    false => Some(html),
+                }
+            }
+            Format::Md => None,
+        }
+    }
+
+    /// Title of the collapsed block, if the block should be hidden, either
+    /// from `hidden_title` or the default label if `hidden` is set.
+    fn hidden_title(&self) -> Option<String> {
+        let fields = &self.header.fields;
+        match fields
+            .get("hidden_title")
+            .and_then(serde_yaml::Value::as_str)
+        {
+            Some(title) => Some(title.to_string()),
+            None => fields
+                .get("hidden")
+                .and_then(serde_yaml::Value::as_bool)
+                .unwrap_or(false)
+                .then(|| self.labels.details.clone()),
+        }
+    }
 }
 
 impl CustomBlock for ScriptBlock {
-    fn to_events(&self, format: Format) -> Vec<Event<'static>> {
+    fn is_expensive(&self) -> bool {
+        matches!(self.output, OutputType::Chart(_))
+    }
+
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
         match (format, &self.output) {
             (Format::Html, OutputType::RunningScript(lines)) => {
                 let mut events = vec![Event::Html(r#"<div class="script"><pre>"#.into())];
@@ -171,13 +629,8 @@ impl CustomBlock for ScriptBlock {
                 }
                 events.push(Event::Html(r#"</pre></div>"#.into()));
 
-                if let Some(title) = self
-                    .header
-                    .fields
-                    .get("hidden_title")
-                    .and_then(serde_yaml::Value::as_str)
-                {
-                    html_hide_with_title(title.to_string(), events)
+                if let Some(title) = self.hidden_title() {
+                    html_hide_with_title(title, events)
                 } else {
                     events
                 }
@@ -206,11 +659,23 @@ impl CustomBlock for ScriptBlock {
                 events
             }
             (format, OutputType::Table((code, head, rows))) => {
+                let head: Vec<CowStr> = head.iter().map(|cell| cell.as_str().into()).collect();
+                let values: Vec<Vec<CowStr>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| cell.as_str().into()).collect())
+                    .collect();
+                if let Some(html) = self.layout_table(format, &head, &values) {
+                    return vec![Event::Html(html.into())];
+                }
+                let rows: Vec<Vec<CowStr>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| self.cell(format, cell)).collect())
+                    .collect();
                 let events = build_table(head, rows);
                 match format {
                     Format::Html => events,
                     Format::Md => {
-                        let table_output = crate::md::render(events.into_iter());
+                        let table_output = crate::md::render(events.into_iter(), 0);
                         let mut code = code
                             .lines()
                             .filter(|line| !line.starts_with("// > "))
@@ -242,40 +707,56 @@ impl CustomBlock for ScriptBlock {
                         fields.insert(field.clone(), true);
                     }
                 }
-                let mut head = vec!["#".to_string()];
-                head.extend(fields.keys().cloned());
-                let rows: Vec<_> = data
+                let mut head: Vec<CowStr> = vec![match format {
+                    Format::Html => self.labels.index_column.as_str().into(),
+                    // The markdown preview table is kept stable across locales
+                    Format::Md => "#".into(),
+                }];
+                head.extend(fields.into_keys().map(CowStr::from));
+                let values: Vec<Vec<CowStr>> = data
                     .data
                     .iter()
                     .enumerate()
                     .map(|(i, data)| {
-                        let mut row = vec![(i + 1).to_string()];
-                        row.extend(
-                            head.iter()
-                                .skip(1)
-                                .map(|field| data.get(field).cloned().unwrap_or_default()),
-                        );
+                        let mut row = vec![(i + 1).to_string().into()];
+                        row.extend(head.iter().skip(1).map(|field| {
+                            data.get(field.as_ref())
+                                .map_or(CowStr::Borrowed(""), |value| value.as_str().into())
+                        }));
                         row
                     })
                     .collect();
-                let events = build_table(&head, &rows);
+                let events = match self.layout_table(format, &head, &values) {
+                    Some(html) => vec![Event::Html(html.into())],
+                    None => {
+                        let rows = values
+                            .into_iter()
+                            .map(|row| {
+                                row.into_iter()
+                                    .map(|cell| match self.cell(format, &cell) {
+                                        CowStr::Borrowed(_) => cell,
+                                        formatted => formatted.into_string().into(),
+                                    })
+                                    .collect()
+                            })
+                            .collect();
+                        build_table(head, rows)
+                    }
+                };
                 match format {
                     Format::Html => {
-                        if let Some(title) = self
-                            .header
-                            .fields
-                            .get("hidden_title")
-                            .and_then(serde_yaml::Value::as_str)
-                        {
-                            html_hide_with_title(title.to_string(), events)
+                        if let Some(title) = self.hidden_title() {
+                            html_hide_with_title(title, events)
                         } else {
                             events
                         }
                     }
                     Format::Md => {
-                        let table_output = crate::md::render(events.into_iter());
-                        let mut output = serde_yaml::to_string(data).unwrap_or("".to_string());
-                        output += "\n";
+                        let table_output = crate::md::render(events.into_iter(), 0);
+                        let mut output = match data.from_file {
+                            true => String::new(),
+                            false => serde_yaml::to_string(data).unwrap_or("".to_string()) + "\n",
+                        };
                         output += &table_output
                             .lines()
                             .filter(|line| !line.is_empty())
@@ -304,13 +785,23 @@ impl CustomBlock for ScriptBlock {
             (Format::Md, OutputType::Inline(output)) => {
                 vec![Event::Code(format!(r#"_{}_"#, output).into())]
             }
-            (Format::Html, OutputType::Chart((_, data))) => PlottersBlock::LineChart {
-                title: "Todo".to_string(),
-                range_x: None,
-                range_y: None,
-                data: data.clone(),
+            (Format::Html, OutputType::Chart((_, data))) => {
+                let chart = PlottersChart::LineChart {
+                    title: header_field(&self.header, "title").unwrap_or_else(|| "Todo".into()),
+                    range_x: None,
+                    range_y: None,
+                    data: data.clone(),
+                    trendline: header_field(&self.header, "trendline")
+                        .and_then(|trendline| Trendline::try_from(trendline).ok()),
+                    x_scale: Default::default(),
+                    y_scale: Default::default(),
+                    x_time: None,
+                };
+                let svg = chart.to_svg(self.numbers.as_ref(), &self.theme);
+                let mut html = accessible_svg(svg, &self.header);
+                html += &chart.data_fallback(&self.header).unwrap_or_default();
+                vec![Event::Html(html.into())]
             }
-            .to_events(Format::Html),
             _ => todo!(),
         }
     }
@@ -389,7 +880,7 @@ impl Runtime {
         Ok(value.to_string())
     }
     fn generate_table(&mut self, script: &str) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
-        let mut engine = Engine::new();
+        let mut engine = new_engine(self.frozen_time, &self.outputs, &self.exports);
 
         let lines = Arc::new(RwLock::new(Vec::<Vec<String>>::new()));
 
@@ -430,7 +921,7 @@ impl Runtime {
         Ok((head.pop().unwrap(), rows))
     }
     fn generate_chart(&mut self, script: &str) -> Result<Vec<Vec<(f32, f32)>>, String> {
-        let mut engine = Engine::new();
+        let mut engine = new_engine(self.frozen_time, &self.outputs, &self.exports);
 
         let data = Arc::new(RwLock::new(Vec::<Vec<(f32, f32)>>::new()));
 
@@ -466,6 +957,9 @@ impl Runtime {
         let data = data.read().unwrap().clone();
         Ok(data)
     }
+    fn add_output(&mut self, id: String, output: rhai::Map) {
+        self.outputs.borrow_mut().insert(id.into(), output.into());
+    }
     fn add_constant(&mut self, data: DataBlock) {
         let values: Vec<rhai::Dynamic> = data.data.into_iter().map(|v| v.into()).collect();
         self.scope
@@ -473,41 +967,145 @@ impl Runtime {
     }
 }
 
-fn build_table(head: &[String], rows: &[Vec<String>]) -> Vec<Event<'static>> {
-    let mut events = Vec::new();
-    events.push(Event::Start(Tag::Table(
-        head.iter()
-            .map(|_| pulldown_cmark::Alignment::None)
-            .collect(),
-    )));
-    events.push(Event::Start(Tag::TableHead));
-    events.extend(head.iter().flat_map(|cell| {
-        vec![
-            Event::Start(Tag::TableCell),
-            Event::Text(cell.clone().into()),
-            Event::End(Tag::TableCell),
-        ]
-    }));
-    events.push(Event::End(Tag::TableHead));
-    events.extend(rows.iter().flat_map(|row| {
-        let mut events = Vec::new();
-        events.push(Event::Start(Tag::TableRow));
-        events.extend(row.iter().flat_map(|cell| {
-            vec![
-                Event::Start(Tag::TableCell),
-                Event::Text(cell.clone().into()),
-                Event::End(Tag::TableCell),
-            ]
-        }));
-        events.push(Event::End(Tag::TableRow));
-        events
-    }));
-    events.push(Event::End(Tag::Table(
-        head.iter()
-            .map(|_| pulldown_cmark::Alignment::None)
-            .collect(),
-    )));
-    events
+/// Rows of `left` joined with the rows of `right` that have the same value
+/// of `key`, for `join` and `left_join` in scripts. With `keep_unmatched`,
+/// rows of `left` without a match are kept as they are, like a left join.
+/// Every row needs a `key`, and columns other than `key` can't be on both
+/// sides, since one would silently replace the other.
+fn join(
+    left: rhai::Array,
+    right: rhai::Array,
+    key: &str,
+    keep_unmatched: bool,
+) -> std::result::Result<rhai::Array, String> {
+    let rows = |side: &str, rows: rhai::Array| -> std::result::Result<Vec<rhai::Map>, String> {
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| match row.try_cast::<rhai::Map>() {
+                Some(row) if row.contains_key(key) => Ok(row),
+                Some(_) => Err(format!(
+                    "row {} on the {} side of the join has no `{}`",
+                    i + 1,
+                    side,
+                    key
+                )),
+                None => Err(format!(
+                    "row {} on the {} side of the join isn't an object map",
+                    i + 1,
+                    side
+                )),
+            })
+            .collect()
+    };
+    let (left, right) = (rows("left", left)?, rows("right", right)?);
+    let columns = |rows: &[rhai::Map]| -> BTreeSet<String> {
+        rows.iter()
+            .flat_map(|row| row.keys().map(|column| column.to_string()))
+            .collect()
+    };
+    if let Some(column) = columns(&left)
+        .intersection(&columns(&right))
+        .find(|column| *column != key)
+    {
+        return Err(format!(
+            "column `{}` is on both sides of the join on `{}`",
+            column, key
+        ));
+    }
+    let mut matches: HashMap<String, Vec<&rhai::Map>> = HashMap::new();
+    for row in right.iter() {
+        matches.entry(row[key].to_string()).or_default().push(row);
+    }
+    let mut joined = rhai::Array::new();
+    for row in left {
+        match matches.get(&row[key].to_string()) {
+            Some(matches) => {
+                for other in matches {
+                    let mut row = row.clone();
+                    row.extend(
+                        other
+                            .iter()
+                            .map(|(column, value)| (column.clone(), value.clone())),
+                    );
+                    joined.push(row.into());
+                }
+            }
+            None if keep_unmatched => joined.push(row.into()),
+            None => {}
+        }
+    }
+    Ok(joined)
+}
+
+/// Create a script engine. With `frozen_time`, `timestamp()` always returns
+/// that time, and no time elapses, so scripts give the same output on every
+/// run.
+///
+/// `block(id)` returns the output of an earlier block with that `id`, from
+/// `outputs`: `columns` and `rows` of a `DynamicTable`, as strings like in
+/// the rendered table, and `series` of `[x, y]` points of a `DynamicChart`,
+/// with the `trendlines` of the series if it has a `trendline`: their
+/// `points`, and the `slope`, `intercept` and `r2` of linear fits.
+/// `export_data(name, value)` adds a value to `exports`, see `Meta::data`.
+/// `join(left, right, key)` and `left_join(left, right, key)` join the
+/// rows of two Data blocks, see `join`. `summary(values)` returns the
+/// `Stats` of an array of numbers as a map, and `mean`, `median` and
+/// `stddev` return one of them.
+fn new_engine(
+    frozen_time: Option<Instant>,
+    outputs: &Rc<RefCell<rhai::Map>>,
+    exports: &Rc<RefCell<rhai::Map>>,
+) -> Engine {
+    let mut engine = Engine::new();
+    let exports = Rc::clone(exports);
+    engine.register_fn("export_data", move |name: &str, value: Dynamic| {
+        exports.borrow_mut().insert(name.into(), value);
+    });
+    let outputs = Rc::clone(outputs);
+    engine.register_fn(
+        "block",
+        move |id: &str| -> std::result::Result<Dynamic, Box<rhai::EvalAltResult>> {
+            match outputs.borrow().get(id) {
+                Some(output) => Ok(output.clone()),
+                None => Err(format!("no output of a block with id `{}`", id).into()),
+            }
+        },
+    );
+    for (name, keep_unmatched) in [("join", false), ("left_join", true)] {
+        engine.register_fn(
+            name,
+            move |left: rhai::Array,
+                  right: rhai::Array,
+                  key: &str|
+                  -> std::result::Result<rhai::Array, Box<rhai::EvalAltResult>> {
+                join(left, right, key, keep_unmatched).map_err(Into::into)
+            },
+        );
+    }
+    engine.register_fn(
+        "summary",
+        |values: rhai::Array| -> std::result::Result<rhai::Map, Box<rhai::EvalAltResult>> {
+            Ok(Stats::of_array(&values)?.to_map())
+        },
+    );
+    for (name, stat) in [
+        ("mean", (|stats| stats.mean) as fn(&Stats) -> f64),
+        ("median", |stats| stats.median),
+        ("stddev", |stats| stats.stddev),
+    ] {
+        engine.register_fn(
+            name,
+            move |values: rhai::Array| -> std::result::Result<rhai::FLOAT, Box<rhai::EvalAltResult>> {
+                Ok(stat(&Stats::of_array(&values)?) as rhai::FLOAT)
+            },
+        );
+    }
+    if let Some(frozen_time) = frozen_time {
+        engine.register_fn("timestamp", move || frozen_time);
+        engine.register_fn("elapsed", |_: &mut Instant| 0.0 as rhai::FLOAT);
+        engine.register_get("elapsed", |_: &mut Instant| 0.0 as rhai::FLOAT);
+    }
+    engine
 }
 
 #[cfg(test)]
@@ -522,7 +1120,7 @@ let x = 4 + 5; // comment b
 debug(x);
 debug(x + 1);
 "#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         let block = state.read_block(&CustomBlockHeader::empty("Script".into()), script);
         let block: ScriptBlock = custom_block_downcast(block.unwrap().unwrap())
             .expect("block should be type ScriptBlock");
@@ -546,7 +1144,7 @@ debug(x + 1);
     #[test]
     fn block_type_inline_script() {
         let script = r#"_4 + 5_"#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         let block = state.read_inline(script);
         let block: ScriptBlock = custom_block_downcast(block.unwrap().unwrap())
             .expect("block should be type ScriptBlock");
@@ -565,7 +1163,7 @@ fn test(n) {
     n + 1
 }
 "#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         let block = state.read_block(&CustomBlockHeader::empty("ScriptGlobals".into()), globals);
         assert!(block.unwrap().is_none(), "output should be None");
 
@@ -586,7 +1184,7 @@ fn test(n) {
         let script = r#"
 let x = 5;
 "#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         let _ = state.read_block(&CustomBlockHeader::empty("Script".into()), script);
 
         let script = r#"_x + 1_"#;
@@ -609,7 +1207,7 @@ row([1, 2, 3]);
 row([4, 5, 6]);
 row([7, 8, 9]);
 "#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         let block = state.read_block(&CustomBlockHeader::empty("DynamicTable".into()), script);
         let block: ScriptBlock =
             custom_block_downcast(block.unwrap().unwrap()).expect("block should be ScriptBlock");
@@ -635,7 +1233,7 @@ data:
 - fieldA: 3
   fieldB: 4
 "#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         state
             .read_block(&CustomBlockHeader::empty("Data".into()), data)
             .unwrap();
@@ -652,13 +1250,160 @@ data:
         assert_eq!(line, r#"testdata[1]["fieldA"] // > 3"#);
     }
 
+    #[test]
+    fn block_type_data_from_file() {
+        let dir = std::env::temp_dir().join(format!("yamdr-data-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("sales.csv"),
+            "region,amount\nnorth,\"1,5\"\nsouth,2\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("sales.tsv"), "region\tamount\nnorth\t3\n").unwrap();
+        std::fs::write(
+            dir.join("sales.json"),
+            r#"[{"region": "east", "amount": 4, "tags": ["a"], "note": null}]"#,
+        )
+        .unwrap();
+        let mut state = ScriptBlockReader::new(&YamdrOptions {
+            base_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+        let read = |state: &mut ScriptBlockReader, header: &str| {
+            let header: CustomBlockHeader = serde_yaml::from_str(header).unwrap();
+            state.read_block(&header, "# | stale |\n")
+        };
+        let block = read(&mut state, "{t: Data, name: sales, path: sales.csv}")
+            .unwrap()
+            .unwrap();
+        let md = crate::md::render(block.to_events(Format::Md).into_iter(), 0);
+        assert_eq!(
+            md,
+            "```{\"t\":\"Data\",\"name\":\"sales\",\"path\":\"sales.csv\"}\n# | # | amount | region |\n# |---|---|---|\n# | 1 | 1,5 | north |\n# | 2 | 2 | south |\n```\n\n"
+        );
+        read(&mut state, "{t: Data, name: tsv, path: sales.tsv}").unwrap();
+        read(&mut state, "{t: Data, name: json, path: sales.json}").unwrap();
+        assert_eq!(
+            state.data("tsv").unwrap(),
+            vec![BTreeMap::from([
+                ("amount".to_string(), "3".to_string()),
+                ("region".to_string(), "north".to_string()),
+            ])]
+        );
+        assert_eq!(
+            state.data("json").unwrap(),
+            vec![BTreeMap::from([
+                ("amount".to_string(), "4".to_string()),
+                ("region".to_string(), "east".to_string()),
+                ("tags".to_string(), r#"["a"]"#.to_string()),
+            ])]
+        );
+        assert!(matches!(
+            read(&mut state, "{t: Data, name: missing, path: missing.csv}"),
+            Err(Error::MissingFile(_))
+        ));
+        assert!(read(&mut state, "{t: Data, path: sales.csv}").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn join_data_blocks() {
+        let mut state = ScriptBlockReader::new(&Default::default());
+        let mut read =
+            |t: &str, input: &str| state.read_block(&CustomBlockHeader::empty(t.into()), input);
+        read(
+            "Data",
+            "name: orders\ndata:\n- {id: 1, customer: a}\n- {id: 2, customer: b}\n- {id: 3, customer: c}\n",
+        )
+        .unwrap();
+        read(
+            "Data",
+            "name: payments\ndata:\n- {id: 1, paid: 10}\n- {id: 1, paid: 5}\n- {id: 3, paid: 7}\n",
+        )
+        .unwrap();
+        read("Data", "name: totals\ndata:\n- {id: 1, customer: x}\n").unwrap();
+        let table = |block: Option<Box<dyn CustomBlock>>| {
+            let block: ScriptBlock = custom_block_downcast(block.unwrap()).unwrap();
+            match block.output {
+                OutputType::Table((_, _, rows)) => rows,
+                _ => panic!("output type should be OutputType::Table"),
+            }
+        };
+        let rows = table(
+            read(
+                "DynamicTable",
+                "row([\"id\", \"customer\", \"paid\"]);\nfor r in join(orders, payments, \"id\") { row([r.id, r.customer, r.paid]); }",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "a", "10"],
+                vec!["1", "a", "5"],
+                vec!["3", "c", "7"]
+            ]
+        );
+        let rows = table(
+            read(
+                "DynamicTable",
+                "row([\"id\", \"paid\"]);\nfor r in left_join(orders, payments, \"id\") { row([r.id, r.paid ?? \"-\"]); }",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "10"],
+                vec!["1", "5"],
+                vec!["2", "-"],
+                vec!["3", "7"]
+            ]
+        );
+
+        let err = read("Script", "join(orders, totals, \"id\")")
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("column `customer` is on both sides"));
+        let err = read("Script", "join(orders, payments, \"key\")")
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("row 1 on the left side of the join has no `key`"));
+    }
+
+    #[test]
+    fn dynamic_chart_trendline() {
+        let mut state = ScriptBlockReader::new(&Default::default());
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: DynamicChart, id: perf, trendline: linear}").unwrap();
+        state
+            .read_block(&header, "plot([[1, 2], [2, 4.5], [3, 6.5]]);")
+            .unwrap();
+        let block: ScriptBlock = custom_block_downcast(
+            state
+                .read_inline("_block(\"perf\").trendlines[0].slope_")
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(block.output, OutputType::Inline(line) if line.ends_with("// > 2.25")));
+
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: DynamicChart, trendline: quadratic}").unwrap();
+        assert!(state.read_block(&header, "plot([[1, 2]]);").is_err());
+    }
+
     #[test]
     fn block_type_dynamic_chart() {
         let script = r#"
 plot([[0, 0], [2, 1], [4, 2]]);
 plot([[4, 2], [2, 3], [0, 4]]);
 "#;
-        let mut state = ScriptBlockReader::initial_state();
+        let mut state = ScriptBlockReader::new(&Default::default());
         let block = state.read_block(&CustomBlockHeader::empty("DynamicChart".into()), script);
         let block: ScriptBlock =
             custom_block_downcast(block.unwrap().unwrap()).expect("block should be ScriptBlock");
@@ -684,7 +1429,7 @@ plot([[4, 2], [2, 3], [0, 4]]);
             (
                 r#"inline code with "output comment" strips comment `_3 + 1 // > abc_`."#,
                 //                  v              v Quotes are changed by pulldown_cmark
-                r#"inline code with “output comment” strips comment `_3 + 1 // > 4_`.
+                r#"inline code with "output comment" strips comment `_3 + 1 // > 4_`.
 
 "#,
             ),
@@ -811,21 +1556,21 @@ let x = 1 + 1;
         ];
         let format = crate::Format::Md;
         for (document, expected) in documents {
-            let parsed_markdown = crate::parse_markdown(document);
+            let parsed_markdown = crate::parse_markdown(&Default::default(), document);
             let events = parsed_markdown
                 .iter()
                 .flat_map(|ee| format.transform_extended_event(ee));
-            let output = format.render(events);
+            let output = format.render(events, 0);
 
             println!("Wanted:\n{}\nGot:\n{}", expected, output);
 
             assert_eq!(expected, output);
 
-            let parsed_markdown = crate::parse_markdown(document);
+            let parsed_markdown = crate::parse_markdown(&Default::default(), document);
             let events = parsed_markdown
                 .iter()
                 .flat_map(|ee| format.transform_extended_event(ee));
-            let output = format.render(events);
+            let output = format.render(events, 0);
 
             assert_eq!(
                 expected, output,
@@ -833,4 +1578,20 @@ let x = 1 + 1;
             );
         }
     }
+
+    #[test]
+    fn deterministic_time() {
+        let options = YamdrOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        let mut state = ScriptBlockReader::new(&options);
+        let block = state.read_inline("_timestamp().elapsed_");
+        let block: ScriptBlock = custom_block_downcast(block.unwrap().unwrap())
+            .expect("block should be type ScriptBlock");
+        let OutputType::Inline(line) = block.output else {
+            panic!("output type should be OutputType::Inline");
+        };
+        assert_eq!(line, "timestamp().elapsed // > 0.0");
+    }
 }
@@ -0,0 +1,238 @@
+use crate::{ExtendedEvent, Format, Meta};
+use pulldown_cmark::{Event, Tag};
+use serde::{Deserialize, Serialize};
+
+/// One searchable block of a document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchEntry {
+    /// Path of the rendered document, relative to the root of the site.
+    pub path: String,
+    /// Id of the block in the document, see `MarkdownBlock::id`.
+    pub block: u16,
+    /// Text of the closest heading before (or of) the block.
+    pub heading: Option<String>,
+    /// Plain text content of the block.
+    pub text: String,
+}
+
+/// Plain text of a top level block of a document.
+pub(crate) struct BlockText {
+    pub id: u16,
+    /// Text of the closest heading before (or of) the block.
+    pub heading: Option<String>,
+    /// Whether the block is a heading, starting a new section.
+    pub is_heading: bool,
+    /// Number of the heading, with `YamdrOptions::number_headings`.
+    pub number: Option<String>,
+    pub text: String,
+}
+
+/// Collects the plain text of each top level block of a document, together
+/// with the heading it belongs to, from the separators and html events of
+/// the document. Blocks without any text are skipped. The output of custom
+/// blocks is included as it would be rendered to html, without the markup.
+#[derive(Default)]
+pub(crate) struct BlockTextCollector {
+    blocks: Vec<BlockText>,
+    heading: Option<String>,
+    in_heading: bool,
+}
+
+impl BlockTextCollector {
+    fn pop_empty(&mut self) {
+        if self.blocks.last().map(|b| b.text.trim().is_empty()) == Some(true) {
+            self.blocks.pop();
+        }
+    }
+
+    pub fn separator(&mut self, id: u16) {
+        self.pop_empty();
+        self.blocks.push(BlockText {
+            id,
+            heading: self.heading.clone(),
+            is_heading: false,
+            number: None,
+            text: String::new(),
+        });
+    }
+
+    /// Set the number of the heading of the current block.
+    pub fn number(&mut self, number: &str) {
+        if let Some(block) = self.blocks.last_mut() {
+            block.number = Some(number.into());
+        }
+    }
+
+    pub fn event(&mut self, event: &Event) {
+        let Some(block) = self.blocks.last_mut() else {
+            return;
+        };
+        match event {
+            Event::Start(Tag::Heading(..)) => {
+                self.in_heading = true;
+                block.is_heading = true;
+                self.heading = Some(String::new());
+            }
+            Event::End(Tag::Heading(..)) => {
+                self.in_heading = false;
+                block.heading = self.heading.clone();
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if self.in_heading {
+                    self.heading.get_or_insert_with(String::new).push_str(text);
+                }
+                block.text.push_str(text);
+            }
+            Event::Html(html) => {
+                block.text.push_str(&strip_tags(html));
+            }
+            Event::SoftBreak | Event::HardBreak | Event::End(_) if !block.text.ends_with(' ') => {
+                block.text.push(' ');
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<BlockText> {
+        self.pop_empty();
+        for block in self.blocks.iter_mut() {
+            block.text = block.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        self.blocks
+    }
+}
+
+/// Extract the plain text of each top level block of a parsed document, see
+/// `BlockTextCollector`.
+pub(crate) fn block_texts(parsed_markdown: &[ExtendedEvent]) -> Vec<BlockText> {
+    let mut collector = BlockTextCollector::default();
+    let transformed = Format::Html.transform_extended_events(parsed_markdown);
+    for (ee, transformed) in parsed_markdown.iter().zip(transformed) {
+        if let ExtendedEvent::Separator(id) = ee {
+            collector.separator(*id);
+            continue;
+        }
+        for event in transformed {
+            collector.event(&event);
+        }
+    }
+    collector.finish()
+}
+
+/// Extract a search entry for each top level block of a rendered document
+/// that contains text, from the `Meta` of `render_markdown`, so the
+/// document isn't read again.
+pub fn search_entries(path: &str, meta: &Meta) -> Vec<SearchEntry> {
+    meta.texts
+        .iter()
+        .map(|block| SearchEntry {
+            path: path.into(),
+            block: block.id,
+            heading: block.heading.clone(),
+            text: block.text.clone(),
+        })
+        .collect()
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Html for a small client side search widget, meant to be used as
+/// `additional_body` of a standalone document. `index_url` should point to
+/// a script that sets `window.YAMDR_SEARCH_INDEX` to a list of
+/// `SearchEntry`s, and `root` is prepended to the paths of the entries
+/// when linking to them. A script is used instead of fetching json, so
+/// the search also works when opening the files directly from disk.
+pub fn search_widget(index_url: &str, root: &str) -> String {
+    format!(
+        r#"
+<div class="search" role="search">
+  <input type="search" id="yamdr-search" placeholder="Search" aria-label="Search" />
+  <ul id="yamdr-search-results"></ul>
+</div>
+<script src="{index_url}"></script>
+<script>
+(function() {{
+  const root = "{root}";
+  const input = document.getElementById("yamdr-search");
+  const results = document.getElementById("yamdr-search-results");
+  input.addEventListener("input", function() {{
+    const terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.innerHTML = "";
+    if (terms.length == 0) {{
+      return;
+    }}
+    const index = window.YAMDR_SEARCH_INDEX || [];
+    index
+      .filter(e => terms.every(t => e.text.toLowerCase().includes(t)))
+      .slice(0, 20)
+      .forEach(e => {{
+        const li = document.createElement("li");
+        const a = document.createElement("a");
+        a.href = root + e.path;
+        a.textContent = (e.heading ? e.heading + " - " : "") + e.path;
+        const p = document.createElement("p");
+        p.textContent = e.text.length > 160 ? e.text.slice(0, 160) + "…" : e.text;
+        li.appendChild(a);
+        li.appendChild(p);
+        results.appendChild(li);
+      }});
+  }});
+}})();
+</script>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn entries_with_heading_context() {
+        let document = r#"
+# Header
+
+A paragraph with `code`.
+
+## Sub header
+
+```{"t":"Script"}
+debug(1 + 1);
+```
+
+```{t: External, meta: true}
+```
+"#;
+        let (meta, _) = render_markdown(&YamdrOptions::default(), document);
+        let entries = search_entries("index.html", &meta);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].heading.as_deref(), Some("Header"));
+        assert_eq!(entries[0].text, "Header");
+        assert_eq!(entries[1].heading.as_deref(), Some("Header"));
+        assert_eq!(entries[1].text, "A paragraph with code.");
+        assert_eq!(entries[2].heading.as_deref(), Some("Sub header"));
+        assert_eq!(entries[3].heading.as_deref(), Some("Sub header"));
+        assert_eq!(entries[3].text, "debug(1 + 1); // > 2");
+        assert!(entries.iter().all(|entry| entry.path == "index.html"));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{interpolation::lookup_yaml, rewrite_asset_url};
+use pulldown_cmark::escape::escape_html;
+use std::collections::BTreeMap;
+
+/// `<title>`, description, Open Graph and Twitter card tags for the head
+/// of a standalone page, from the `description` and `image` fields of the
+/// meta blocks. Relative image urls are prefixed with `asset_base`.
+pub(crate) fn meta_tags(
+    fields: &BTreeMap<String, serde_yaml::Value>,
+    title: Option<&str>,
+    asset_base: Option<&str>,
+) -> String {
+    let mut tags = String::new();
+    let mut tag = |attribute: &str, names: &[&str], content: &str| {
+        for name in names {
+            tags += &format!(r#"<meta {}="{}" content=""#, attribute, name);
+            escape_html(&mut tags, content).unwrap();
+            tags += "\">\n";
+        }
+    };
+    if let Some(title) = title {
+        tag("property", &["og:title"], title);
+        tag("name", &["twitter:title"], title);
+    }
+    if let Some(description) = lookup_yaml(fields, "description") {
+        tag(
+            "name",
+            &["description", "twitter:description"],
+            &description,
+        );
+        tag("property", &["og:description"], &description);
+    }
+    let image = lookup_yaml(fields, "image").map(|image| match asset_base {
+        Some(base) => rewrite_asset_url(base, &image).unwrap_or(image),
+        None => image,
+    });
+    if let Some(image) = &image {
+        tag("property", &["og:image"], image);
+        tag("name", &["twitter:image"], image);
+    }
+    tag("property", &["og:type"], "article");
+    let card = match image {
+        Some(_) => "summary_large_image",
+        None => "summary",
+    };
+    tag("name", &["twitter:card"], card);
+    if let Some(title) = title {
+        tags += "<title>";
+        escape_html(&mut tags, title).unwrap();
+        tags += "</title>\n";
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags() {
+        let fields =
+            serde_yaml::from_str("{description: Fast & small, image: img/cover.png}").unwrap();
+        let tags = meta_tags(&fields, Some("Report"), Some("/assets"));
+        assert!(tags.contains(r#"<meta property="og:title" content="Report">"#));
+        assert!(tags.contains(r#"<meta name="description" content="Fast &amp; small">"#));
+        assert!(tags.contains(r#"<meta property="og:image" content="/assets/img/cover.png">"#));
+        assert!(tags.contains(r#"<meta name="twitter:card" content="summary_large_image">"#));
+        assert!(tags.ends_with("<title>Report</title>\n"));
+
+        let tags = meta_tags(&BTreeMap::new(), None, None);
+        assert_eq!(
+            tags,
+            "<meta property=\"og:type\" content=\"article\">\n<meta name=\"twitter:card\" content=\"summary\">\n"
+        );
+    }
+}
@@ -0,0 +1,613 @@
+use crate::{
+    svg_size::SvgSize,
+    utils::{accessible_svg, data_fallback},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Result,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Tag};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Participant {
+    name: String,
+    label: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    from: usize,
+    to: usize,
+    text: String,
+    dashed: bool,
+    /// Activate `to`, from `A ->+ B`.
+    activate: bool,
+    /// Deactivate `from`, from `B -->- A`.
+    deactivate: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NotePosition {
+    Over(usize, usize),
+    LeftOf(usize),
+    RightOf(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Message(Message),
+    Note(NotePosition, String),
+    Activate(usize),
+    Deactivate(usize),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Diagram {
+    participants: Vec<Participant>,
+    steps: Vec<Step>,
+}
+
+impl Diagram {
+    /// Index of the participant `name`, which is added if it isn't declared.
+    fn participant(&mut self, name: &str) -> std::result::Result<usize, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("missing participant".into());
+        }
+        if let Some(i) = self.participants.iter().position(|p| p.name == name) {
+            return Ok(i);
+        }
+        self.participants.push(Participant {
+            name: name.into(),
+            label: name.into(),
+        });
+        Ok(self.participants.len() - 1)
+    }
+
+    fn parse_line(&mut self, line: &str) -> std::result::Result<(), String> {
+        if let Some(rest) = line.strip_prefix("participant ") {
+            let (name, label) = rest.split_once(" as ").unwrap_or((rest, rest));
+            let i = self.participant(name)?;
+            self.participants[i].label = label.trim().into();
+            return Ok(());
+        }
+        if let Some(name) = line.strip_prefix("activate ") {
+            let i = self.participant(name)?;
+            self.steps.push(Step::Activate(i));
+            return Ok(());
+        }
+        if let Some(name) = line.strip_prefix("deactivate ") {
+            let i = self.participant(name)?;
+            self.steps.push(Step::Deactivate(i));
+            return Ok(());
+        }
+        let (head, text) = line
+            .split_once(':')
+            .ok_or_else(|| format!("expected `A -> B: message`, got `{}`", line))?;
+        let text = text.trim().to_string();
+        if let Some(position) = head.strip_prefix("note ") {
+            let position = match position.trim().split_once(' ') {
+                Some(("over", names)) => match names.split_once(',') {
+                    Some((a, b)) => {
+                        let (a, b) = (self.participant(a)?, self.participant(b)?);
+                        NotePosition::Over(a.min(b), a.max(b))
+                    }
+                    None => {
+                        let a = self.participant(names)?;
+                        NotePosition::Over(a, a)
+                    }
+                },
+                Some(("left", name)) => match name.trim().strip_prefix("of ") {
+                    Some(name) => NotePosition::LeftOf(self.participant(name)?),
+                    None => return Err(format!("expected `note left of A`, got `{}`", head)),
+                },
+                Some(("right", name)) => match name.trim().strip_prefix("of ") {
+                    Some(name) => NotePosition::RightOf(self.participant(name)?),
+                    None => return Err(format!("expected `note right of A`, got `{}`", head)),
+                },
+                _ => return Err(format!("expected `note over A`, got `{}`", head)),
+            };
+            self.steps.push(Step::Note(position, text));
+            return Ok(());
+        }
+        let (arrow, dashed) = match head.find("-->") {
+            Some(i) => (i..i + 3, true),
+            None => match head.find("->") {
+                Some(i) => (i..i + 2, false),
+                None => return Err(format!("expected `A -> B: message`, got `{}`", line)),
+            },
+        };
+        let to = head[arrow.end..].trim_start();
+        let (to, activate, deactivate) = match (to.strip_prefix('+'), to.strip_prefix('-')) {
+            (Some(to), _) => (to, true, false),
+            (_, Some(to)) => (to, false, true),
+            _ => (to, false, false),
+        };
+        let message = Message {
+            from: self.participant(&head[..arrow.start])?,
+            to: self.participant(to)?,
+            text,
+            dashed,
+            activate,
+            deactivate,
+        };
+        self.steps.push(Step::Message(message));
+        Ok(())
+    }
+
+    fn parse(input: &str) -> std::result::Result<Self, String> {
+        let mut diagram = Diagram::default();
+        for (n, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            diagram
+                .parse_line(line)
+                .map_err(|err| format!("line {}: {}", n + 1, err))?;
+        }
+        // Activations are checked here, so errors are reported when reading
+        let mut active = vec![0; diagram.participants.len()];
+        for step in diagram.steps.iter() {
+            let (activate, deactivate) = match step {
+                Step::Activate(i) => (Some(*i), None),
+                Step::Deactivate(i) => (None, Some(*i)),
+                Step::Message(m) => (m.activate.then_some(m.to), m.deactivate.then_some(m.from)),
+                Step::Note(..) => (None, None),
+            };
+            if let Some(i) = deactivate {
+                if active[i] == 0 {
+                    let name = &diagram.participants[i].name;
+                    return Err(format!("`{}` is deactivated, but isn't active", name));
+                }
+                active[i] -= 1;
+            }
+            if let Some(i) = activate {
+                active[i] += 1;
+            }
+        }
+        if diagram.participants.is_empty() {
+            return Err("the diagram has no participants".into());
+        }
+        Ok(diagram)
+    }
+}
+
+const FONT_SIZE: f64 = 13.0;
+const BOX_HEIGHT: f64 = 30.0;
+const ROW_HEIGHT: f64 = 36.0;
+const SELF_ROW_HEIGHT: f64 = 48.0;
+/// Width of an activation bar, and how far nested bars are moved right.
+const BAR_WIDTH: f64 = 10.0;
+
+/// Approximate width of `text` in the diagram font, as text can't be
+/// measured without a font.
+fn text_width(text: &str) -> f64 {
+    (text.chars().count() as f64 * FONT_SIZE * 0.6).ceil()
+}
+
+fn escaped(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).unwrap();
+    escaped
+}
+
+/// The x of the center of each participant, with room for the labels of
+/// the messages and notes between them.
+fn centers(diagram: &Diagram) -> Vec<f64> {
+    let widths: Vec<f64> = diagram
+        .participants
+        .iter()
+        .map(|p| (text_width(&p.label) + 20.0).max(60.0))
+        .collect();
+    let mut gaps: Vec<f64> = widths
+        .windows(2)
+        .map(|w| ((w[0] + w[1]) / 2.0 + 30.0).max(100.0))
+        .collect();
+    let mut require = |from: usize, to: usize, width: f64| {
+        let span: f64 = gaps[from..to].iter().sum();
+        if span < width {
+            gaps[to - 1] += width - span;
+        }
+    };
+    for step in diagram.steps.iter() {
+        match step {
+            Step::Message(m) if m.from != m.to => require(
+                m.from.min(m.to),
+                m.from.max(m.to),
+                text_width(&m.text) + 30.0,
+            ),
+            Step::Message(m) if m.from + 1 < widths.len() => {
+                require(m.from, m.from + 1, text_width(&m.text) + 50.0)
+            }
+            Step::Note(NotePosition::Over(a, b), text) if a != b => {
+                require(*a, *b, text_width(text) - 20.0)
+            }
+            Step::Note(NotePosition::RightOf(a), text) if a + 1 < widths.len() => {
+                require(*a, a + 1, text_width(text) + 40.0)
+            }
+            Step::Note(NotePosition::LeftOf(a), text) if *a > 0 => {
+                require(a - 1, *a, text_width(text) + 40.0)
+            }
+            _ => {}
+        }
+    }
+    let mut centers = vec![widths[0] / 2.0];
+    for gap in gaps {
+        centers.push(centers.last().unwrap() + gap);
+    }
+    centers
+}
+
+/// A sequence diagram, written in a small text language and rendered to an
+/// svg without scripts.
+///
+/// ````text
+/// ```{t: Sequence, title: Login}
+/// participant B as Browser
+/// B ->+ Server: POST /login
+/// note over Server: Check password
+/// Server -->- B: 302 /home
+/// ```
+/// ````
+///
+/// Messages are written as `A -> B: text`, or `A --> B: text` for dashed
+/// replies, and participants are added in the order they are used, unless
+/// declared with `participant`. `->+` activates the receiver, and `-->-`
+/// deactivates the sender, as do `activate A` and `deactivate A`. Notes are
+/// written as `note over A: text`, `note over A, B: text`, `note left of
+/// A: text` or `note right of A: text`. Lines starting with `#` are
+/// comments.
+#[derive(Debug, Clone)]
+pub struct SequenceBlock {
+    header: CustomBlockHeader,
+    input: String,
+    size: SvgSize,
+    diagram: Diagram,
+}
+
+pub struct SequenceBlockReader {}
+
+impl SequenceBlockReader {
+    pub fn initial_state() -> Self {
+        SequenceBlockReader {}
+    }
+}
+
+impl CustomBlockReader for SequenceBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Sequence"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Sequence"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("alt", FieldType::String),
+            HeaderField::new("title", FieldType::String),
+            HeaderField::new("accessibility", FieldType::String),
+            HeaderField::new("scale", FieldType::Scalar),
+            HeaderField::new("max_width", FieldType::Scalar),
+            HeaderField::new("fit", FieldType::Bool),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let size = SvgSize::from_header(header).map_err(Error::CustomBlockRead)?;
+        let diagram = Diagram::parse(input).map_err(Error::CustomBlockRead)?;
+        Ok(Some(Box::new(SequenceBlock {
+            header: header.clone(),
+            input: input.into(),
+            size,
+            diagram,
+        })))
+    }
+}
+
+impl SequenceBlock {
+    fn to_svg(&self) -> String {
+        let diagram = &self.diagram;
+        let centers = centers(diagram);
+        let mut min_x: f64 = 0.0;
+        let mut max_x = centers.last().unwrap() + 30.0;
+        let mut shapes = String::new();
+        let mut bars = String::new();
+        // Start of the open activations of each participant
+        let mut active: Vec<Vec<f64>> = vec![Vec::new(); centers.len()];
+        // Left and right edge of the activation bars of a participant
+        let edges = |i: usize, depth: usize| match depth {
+            0 => (centers[i], centers[i]),
+            depth => (
+                centers[i] - BAR_WIDTH / 2.0,
+                centers[i] + BAR_WIDTH / 2.0 * depth as f64,
+            ),
+        };
+        let bar = |bars: &mut String, i: usize, level: usize, from: f64, to: f64| {
+            *bars += &format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white" stroke="black"/>"#,
+                centers[i] - BAR_WIDTH / 2.0 + level as f64 * BAR_WIDTH / 2.0,
+                from,
+                BAR_WIDTH,
+                to - from
+            );
+        };
+
+        let mut y = BOX_HEIGHT + 20.0;
+        for step in diagram.steps.iter() {
+            match step {
+                Step::Message(m) => {
+                    let (x1, x2) = (centers[m.from], centers[m.to]);
+                    let dash = if m.dashed {
+                        r#" stroke-dasharray="5,3""#
+                    } else {
+                        ""
+                    };
+                    let marker = if m.dashed { "open" } else { "arrow" };
+                    let row_height = if m.from == m.to {
+                        let (_, right) = edges(m.from, active[m.from].len());
+                        shapes += &format!(
+                            r#"<text x="{}" y="{}">{}</text>"#,
+                            x1 + 8.0,
+                            y + 14.0,
+                            escaped(&m.text)
+                        );
+                        shapes += &format!(
+                            r#"<path d="M {right} {} H {} V {} H {right}" fill="none" stroke="black"{dash} marker-end="url(#{marker})"/>"#,
+                            y + 20.0,
+                            x1 + 40.0,
+                            y + 36.0
+                        );
+                        max_x = max_x.max(x1 + 50.0 + text_width(&m.text));
+                        SELF_ROW_HEIGHT
+                    } else {
+                        // Arrows start and end at the edges of activation bars
+                        let (from_left, from_right) = edges(m.from, active[m.from].len());
+                        let to_depth = active[m.to].len() + m.activate as usize;
+                        let (to_left, to_right) = edges(m.to, to_depth);
+                        let (start, end) = if x2 > x1 {
+                            (from_right, to_left)
+                        } else {
+                            (from_left, to_right)
+                        };
+                        shapes += &format!(
+                            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                            (x1 + x2) / 2.0,
+                            y + 14.0,
+                            escaped(&m.text)
+                        );
+                        shapes += &format!(
+                            r#"<line x1="{start}" y1="{0}" x2="{end}" y2="{0}" stroke="black"{dash} marker-end="url(#{marker})"/>"#,
+                            y + 22.0
+                        );
+                        ROW_HEIGHT
+                    };
+                    if m.deactivate {
+                        let start = active[m.from].pop().expect("activations are checked");
+                        let level = active[m.from].len();
+                        bar(&mut bars, m.from, level, start, y + 22.0);
+                    }
+                    if m.activate {
+                        active[m.to].push(y + 22.0);
+                    }
+                    y += row_height;
+                }
+                Step::Note(position, text) => {
+                    let width = text_width(text) + 20.0;
+                    let (left, right) = match *position {
+                        NotePosition::Over(a, b) => {
+                            let (left, right) = (centers[a] - 20.0, centers[b] + 20.0);
+                            let extra = (width - (right - left)).max(0.0) / 2.0;
+                            (left - extra, right + extra)
+                        }
+                        NotePosition::LeftOf(a) => (centers[a] - 10.0 - width, centers[a] - 10.0),
+                        NotePosition::RightOf(a) => (centers[a] + 10.0, centers[a] + 10.0 + width),
+                    };
+                    min_x = min_x.min(left);
+                    max_x = max_x.max(right);
+                    shapes += &format!(
+                        r##"<rect x="{left}" y="{}" width="{}" height="26" fill="#fff8c4" stroke="black"/>"##,
+                        y + 4.0,
+                        right - left
+                    );
+                    shapes += &format!(
+                        r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                        (left + right) / 2.0,
+                        y + 21.0,
+                        escaped(text)
+                    );
+                    y += ROW_HEIGHT;
+                }
+                Step::Activate(i) => active[*i].push(y),
+                Step::Deactivate(i) => {
+                    let start = active[*i].pop().expect("activations are checked");
+                    let level = active[*i].len();
+                    bar(&mut bars, *i, level, start, y);
+                }
+            }
+        }
+        let end = y + 10.0;
+        for (i, starts) in active.iter().enumerate() {
+            for (level, start) in starts.iter().enumerate() {
+                bar(&mut bars, i, level, *start, end);
+            }
+        }
+
+        let mut svg = String::new();
+        for (participant, center) in diagram.participants.iter().zip(centers.iter()) {
+            let width = (text_width(&participant.label) + 20.0).max(60.0);
+            min_x = min_x.min(center - width / 2.0);
+            max_x = max_x.max(center + width / 2.0);
+            svg += &format!(
+                r#"<line x1="{center}" y1="{BOX_HEIGHT}" x2="{center}" y2="{end}" stroke="gray" stroke-dasharray="4,4"/>"#
+            );
+            svg += &format!(
+                r##"<rect x="{}" y="0" width="{width}" height="{BOX_HEIGHT}" fill="#f5f5f5" stroke="black"/>"##,
+                center - width / 2.0
+            );
+            svg += &format!(
+                r#"<text x="{center}" y="{}" text-anchor="middle">{}</text>"#,
+                BOX_HEIGHT / 2.0 + 5.0,
+                escaped(&participant.label)
+            );
+        }
+        let (min_x, width, height) = (min_x - 5.0, max_x - min_x + 10.0, end + 5.0);
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="{min_x} -5 {width} {height}" font-family="sans-serif" font-size="{font_size}">"#,
+                r#"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto"><path d="M 0 0 L 10 5 L 0 10 z"/></marker>"#,
+                r#"<marker id="open" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto"><path d="M 0 0 L 10 5 L 0 10" fill="none" stroke="black"/></marker></defs>"#,
+                "{svg}{bars}{shapes}</svg>"
+            ),
+            width = width,
+            height = height,
+            min_x = min_x,
+            font_size = FONT_SIZE,
+            svg = svg,
+            bars = bars,
+            shapes = shapes,
+        )
+    }
+
+    /// The messages of the diagram as a table, see `utils::data_fallback`.
+    fn data_fallback(&self) -> Option<String> {
+        data_fallback(&self.header, &["From", "To", "Message"], || {
+            let name = |i: usize| self.diagram.participants[i].label.clone();
+            self.diagram
+                .steps
+                .iter()
+                .filter_map(|step| match step {
+                    Step::Message(m) => Some(vec![name(m.from), name(m.to), m.text.clone()]),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+}
+
+impl CustomBlock for SequenceBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut html = accessible_svg(self.size.apply(&self.to_svg()), &self.header);
+                html += &self.data_fallback().unwrap_or_default();
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: pulldown_cmark::CowStr =
+                    serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let diagram = Diagram::parse(
+            "participant B as Browser\n# A comment\nB ->+ Server: POST /login\nnote over Server: Check\nnote left of B, or not: x\nServer -->- B: 302 /home\n",
+        )
+        .unwrap();
+        assert_eq!(
+            diagram.participants,
+            vec![
+                Participant {
+                    name: "B".into(),
+                    label: "Browser".into()
+                },
+                Participant {
+                    name: "Server".into(),
+                    label: "Server".into()
+                },
+                Participant {
+                    name: "B, or not".into(),
+                    label: "B, or not".into()
+                },
+            ]
+        );
+        assert_eq!(
+            diagram.steps[0],
+            Step::Message(Message {
+                from: 0,
+                to: 1,
+                text: "POST /login".into(),
+                dashed: false,
+                activate: true,
+                deactivate: false,
+            })
+        );
+        assert_eq!(
+            diagram.steps[1],
+            Step::Note(NotePosition::Over(1, 1), "Check".into())
+        );
+        assert!(matches!(
+            &diagram.steps[3],
+            Step::Message(Message {
+                from: 1,
+                to: 0,
+                dashed: true,
+                deactivate: true,
+                ..
+            })
+        ));
+
+        assert_eq!(
+            Diagram::parse("A -> B: a\nB -> A"),
+            Err("line 2: expected `A -> B: message`, got `B -> A`".into())
+        );
+        assert_eq!(
+            Diagram::parse("A -> B: a\ndeactivate B"),
+            Err("`B` is deactivated, but isn't active".into())
+        );
+        assert_eq!(
+            Diagram::parse("note under A: a"),
+            Err("line 1: expected `note over A`, got `note under A`".into())
+        );
+        assert!(Diagram::parse("# Nothing\n").is_err());
+    }
+
+    #[test]
+    fn render() {
+        let mut reader = SequenceBlockReader::initial_state();
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Sequence, title: Login}").unwrap();
+        let input = "Alice ->+ Bob: <hello>\nBob -> Bob: think\nnote over Alice, Bob: A long note spanning both\nBob -->- Alice: reply\n";
+        let block = reader.read_block(&header, input).unwrap().unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.starts_with("<svg"));
+        assert!(html.contains("<title"));
+        assert!(html.contains("&lt;hello&gt;</text>"));
+        // A lifeline and box for each participant, and an activation bar
+        assert_eq!(html.matches("stroke-dasharray=\"4,4\"").count(), 2);
+        assert_eq!(html.matches("fill=\"white\"").count(), 1);
+        // The dashed reply uses the open arrowhead, with scoped ids
+        assert!(html.contains("-open)\"/>"));
+
+        let props: pulldown_cmark::CowStr = "{\"t\":\"Sequence\",\"title\":\"Login\"}".into();
+        assert_eq!(
+            block.to_events(Format::Md),
+            vec![
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                Event::Text(input.into()),
+                Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+            ]
+        );
+    }
+}
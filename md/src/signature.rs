@@ -0,0 +1,192 @@
+use crate::{Error, Result};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Options for embedding a hash of the source markdown, and of the rendered
+/// page, in the footer of standalone html, so a rendered document can be
+/// checked against its source with `verify`.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureOptions {
+    /// Key to also sign the source and the page with, using HMAC-SHA256.
+    /// The same key is needed to verify the signature.
+    pub key: Option<Vec<u8>>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Placed where the footer goes while the page is rendered, and replaced
+/// by `sign` once the rest of the page is known.
+pub(crate) const PLACEHOLDER: &str = r#"<footer class="signature"></footer>"#;
+
+/// Signs the hashes of the source and of the page, which have a fixed length.
+fn mac(key: &[u8], source_hash: &str, content_hash: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(source_hash.as_bytes());
+    mac.update(content_hash.as_bytes());
+    mac
+}
+
+/// Footer with the hashes, and signature if there is a key, of `markdown`
+/// and of the page it is placed in, `content`, without the footer.
+fn footer(options: &SignatureOptions, markdown: &str, content: &str) -> String {
+    let hash = to_hex(&Sha256::digest(markdown.as_bytes()));
+    let content_hash = to_hex(&Sha256::digest(content.as_bytes()));
+    let signature = match &options.key {
+        Some(key) => format!(
+            r#" data-signature="{}""#,
+            to_hex(&mac(key, &hash, &content_hash).finalize().into_bytes())
+        ),
+        None => String::new(),
+    };
+    format!(
+        r#"<footer class="signature" data-source-sha256="{0}" data-content-sha256="{1}"{2}><code>sha256:{0}</code></footer>"#,
+        hash, content_hash, signature
+    )
+}
+
+/// Replace the `PLACEHOLDER` in `page` with the footer of `markdown`, or
+/// add the footer to the end if a template left it out.
+pub(crate) fn sign(options: &SignatureOptions, markdown: &str, page: &str) -> String {
+    match page.find(PLACEHOLDER) {
+        Some(start) => {
+            let (before, after) = (&page[..start], &page[start + PLACEHOLDER.len()..]);
+            let content = format!("{}{}", before, after);
+            format!("{}{}{}", before, footer(options, markdown, &content), after)
+        }
+        None => format!("{}{}", page, footer(options, markdown, page)),
+    }
+}
+
+/// The last footer of `html`, and `html` without it.
+fn split_footer(html: &str) -> Option<(&str, String)> {
+    let start = html.rfind(r#"<footer class="signature""#)?;
+    let end = start + html[start..].find("</footer>")? + "</footer>".len();
+    Some((
+        &html[start..end],
+        format!("{}{}", &html[..start], &html[end..]),
+    ))
+}
+
+fn attribute<'a>(footer: &'a str, name: &str) -> Option<&'a str> {
+    let start = footer.find(&format!(r#" {}=""#, name))? + name.len() + 3;
+    let end = footer[start..].find('"')?;
+    Some(&footer[start..start + end])
+}
+
+/// Check that `html` was rendered from `markdown`, with the hashes embedded
+/// with `SignatureOptions`, and that the page hasn't been changed since. If
+/// `key` is set, the document must also be signed with it.
+pub fn verify(html: &str, markdown: &str, key: Option<&[u8]>) -> Result<()> {
+    let (footer, content) = split_footer(html).ok_or(Error::MissingSignature)?;
+    let hash = attribute(footer, "data-source-sha256").ok_or(Error::MissingSignature)?;
+    if hash != to_hex(&Sha256::digest(markdown.as_bytes())) {
+        return Err(Error::SourceMismatch);
+    }
+    let content_hash = attribute(footer, "data-content-sha256").ok_or(Error::MissingSignature)?;
+    if content_hash != to_hex(&Sha256::digest(content.as_bytes())) {
+        return Err(Error::ContentMismatch);
+    }
+    let Some(key) = key else {
+        return Ok(());
+    };
+    let signature = attribute(footer, "data-signature")
+        .and_then(from_hex)
+        .ok_or(Error::MissingSignature)?;
+    mac(key, hash, content_hash)
+        .verify_slice(&signature)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, StandaloneOptions, YamdrOptions};
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(from_hex("000fff"), Some(vec![0, 15, 255]));
+        assert_eq!(from_hex("0f0"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn verify_footer() {
+        let options = SignatureOptions {
+            key: Some(b"secret".to_vec()),
+        };
+        let html = sign(&options, "Report", &format!("<p>Report</p>{}", PLACEHOLDER));
+        assert!(verify(&html, "Report", None).is_ok());
+        assert!(verify(&html, "Report", Some(b"secret")).is_ok());
+        assert!(matches!(
+            verify(&html, "Report!", None),
+            Err(Error::SourceMismatch)
+        ));
+        assert!(matches!(
+            verify(&html, "Report", Some(b"other")),
+            Err(Error::InvalidSignature)
+        ));
+
+        let edited = html.replace("<p>Report</p>", "<p>Edited</p>");
+        assert!(matches!(
+            verify(&edited, "Report", None),
+            Err(Error::ContentMismatch)
+        ));
+
+        let html = sign(&SignatureOptions::default(), "Report", "<p>Report</p>");
+        assert!(html.ends_with("</footer>"));
+        assert!(verify(&html, "Report", None).is_ok());
+        assert!(!html.contains("data-signature"));
+        assert!(matches!(
+            verify(&html, "Report", Some(b"secret")),
+            Err(Error::MissingSignature)
+        ));
+        assert!(matches!(
+            verify("<p>Report</p>", "Report", None),
+            Err(Error::MissingSignature)
+        ));
+    }
+
+    #[test]
+    fn signature() {
+        let document = "# Report\n\nAll *good*.\n";
+        let options = YamdrOptions {
+            standalone: Some(StandaloneOptions {
+                signature: Some(SignatureOptions {
+                    key: Some(b"key".to_vec()),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<footer class="signature""#));
+        assert!(verify(&html, document, Some(b"key")).is_ok());
+        assert!(matches!(
+            verify(&html, "# Report\n\nAll *bad*.\n", None),
+            Err(Error::SourceMismatch)
+        ));
+        assert!(matches!(
+            verify(&html.replace("good", "bad"), document, Some(b"key")),
+            Err(Error::ContentMismatch)
+        ));
+
+        let (_, html) = render_markdown(&Default::default(), document);
+        assert!(matches!(
+            verify(&html, document, None),
+            Err(Error::MissingSignature)
+        ));
+    }
+}
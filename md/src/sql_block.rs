@@ -0,0 +1,263 @@
+use crate::{
+    utils::{build_table, header_field, local_files, TableLayout},
+    CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format, HeaderField,
+    Labels, Result, YamdrOptions,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use std::path::PathBuf;
+
+/// The result of a read only query against a local SQLite database, as a
+/// table, with the `sqlite` feature. Only the databases in
+/// `YamdrOptions::sql_databases` can be queried.
+///
+/// ````text
+/// ```{t: Sql, db: data.sqlite, limit: 20}
+/// SELECT name, count(*) FROM orders GROUP BY name
+/// ```
+/// ````
+///
+/// At most `limit` rows are rendered, 100 if it isn't set. The table can
+/// have the `stacked`, `max_rows` and `page_size` layouts of `TableLayout`.
+#[derive(Debug, Clone)]
+pub struct SqlBlock {
+    header: CustomBlockHeader,
+    input: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    layout: TableLayout,
+    labels: Labels,
+}
+
+pub struct SqlBlockReader {
+    base_dir: Option<PathBuf>,
+    databases: Vec<PathBuf>,
+    labels: Labels,
+}
+
+impl SqlBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        SqlBlockReader {
+            base_dir: options.base_dir.clone(),
+            databases: options
+                .sql_databases
+                .iter()
+                .filter_map(|path| path.canonicalize().ok())
+                .collect(),
+            labels: options.locale.labels.clone(),
+        }
+    }
+
+    /// Path of the database `db`, if it is allowed to be queried.
+    fn database(&self, db: &str) -> Result<PathBuf> {
+        let path = match &self.base_dir {
+            Some(base_dir) => base_dir.join(db),
+            None => PathBuf::from(db),
+        };
+        let path = path
+            .canonicalize()
+            .map_err(|_| Error::MissingFile(db.into()))?;
+        if !self.databases.contains(&path) {
+            return Err(Error::CustomBlockRead(format!(
+                "database `{}` is not allowed to be queried",
+                db
+            )));
+        }
+        Ok(path)
+    }
+}
+
+/// Column names and rows of the result of `query`.
+#[cfg(feature = "sqlite")]
+fn query(
+    path: &std::path::Path,
+    query: &str,
+    limit: usize,
+) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    use rusqlite::{types::ValueRef, Connection, OpenFlags};
+
+    let connection = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|err| err.to_string())?;
+    let mut statement = connection.prepare(query).map_err(|err| err.to_string())?;
+    if !statement.readonly() {
+        return Err("only read only queries are allowed in Sql blocks".into());
+    }
+    let columns: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let mut result = statement.query([]).map_err(|err| err.to_string())?;
+    let mut rows = Vec::new();
+    while let Some(row) = result.next().map_err(|err| err.to_string())? {
+        if rows.len() == limit {
+            break;
+        }
+        let cells = (0..columns.len())
+            .map(|i| match row.get_ref(i) {
+                Ok(ValueRef::Null) | Err(_) => String::new(),
+                Ok(ValueRef::Integer(i)) => i.to_string(),
+                Ok(ValueRef::Real(f)) => f.to_string(),
+                Ok(ValueRef::Text(text)) => String::from_utf8_lossy(text).into(),
+                Ok(ValueRef::Blob(blob)) => format!("<{} bytes>", blob.len()),
+            })
+            .collect();
+        rows.push(cells);
+    }
+    Ok((columns, rows))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn query(
+    _path: &std::path::Path,
+    _query: &str,
+    _limit: usize,
+) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    Err("Sql blocks require the `sqlite` feature".into())
+}
+
+impl CustomBlockReader for SqlBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Sql"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Sql"]
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("db", FieldType::String),
+            HeaderField::new("limit", FieldType::Integer),
+            HeaderField::new("stacked", FieldType::Bool),
+            HeaderField::new("max_rows", FieldType::Integer),
+            HeaderField::new("page_size", FieldType::Integer),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let db = header_field(header, "db")
+            .ok_or_else(|| Error::CustomBlockRead("missing `db` in Sql block".into()))?;
+        let limit = header
+            .fields
+            .get("limit")
+            .and_then(serde_yaml::Value::as_u64)
+            .unwrap_or(100) as usize;
+        let path = self.database(&db)?;
+        let (columns, rows) = query(&path, input, limit).map_err(Error::CustomBlockRead)?;
+        Ok(Some(Box::new(SqlBlock {
+            header: header.clone(),
+            input: input.into(),
+            columns,
+            rows,
+            // The script of interactive tables is included by script blocks
+            layout: TableLayout {
+                interactive: false,
+                ..TableLayout::from_header(header, false)
+            },
+            labels: self.labels.clone(),
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["db"])
+    }
+}
+
+impl CustomBlock for SqlBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let head: Vec<CowStr> = self.columns.iter().map(|c| c.as_str().into()).collect();
+                let rows: Vec<Vec<CowStr>> = self
+                    .rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| cell.as_str().into()).collect())
+                    .collect();
+                match self.layout.html(&head, &rows, &rows, &self.labels) {
+                    Some(html) => vec![Event::Html(html.into())],
+                    None => build_table(head, rows),
+                }
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_databases() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut reader = SqlBlockReader::new(&YamdrOptions {
+            base_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+        let header: CustomBlockHeader = serde_yaml::from_str("{t: Sql, db: Cargo.toml}").unwrap();
+        let err = reader.read_block(&header, "SELECT 1").err().unwrap();
+        assert!(err.to_string().contains("not allowed"));
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Sql, db: missing.sqlite}").unwrap();
+        assert!(matches!(
+            reader.read_block(&header, "SELECT 1"),
+            Err(Error::MissingFile(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn query_database() {
+        let dir = std::env::temp_dir().join(format!("yamdr-sql-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("data.sqlite");
+        let connection = rusqlite::Connection::open(&db).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS t (name TEXT, n INTEGER);
+                 DELETE FROM t;
+                 INSERT INTO t VALUES ('<a>', 1), ('b', NULL), ('c', 3);",
+            )
+            .unwrap();
+
+        let mut reader = SqlBlockReader::new(&YamdrOptions {
+            base_dir: Some(dir.clone()),
+            sql_databases: vec![db.clone()],
+            ..Default::default()
+        });
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Sql, db: data.sqlite, limit: 2}").unwrap();
+        let block = reader
+            .read_block(&header, "SELECT name, n FROM t ORDER BY name")
+            .unwrap()
+            .unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains("<th>name</th><th>n</th>"));
+        assert!(html.contains("<td>&lt;a&gt;</td><td>1</td>"));
+        assert!(html.contains("<td>b</td><td></td>"));
+        assert!(!html.contains("<td>c</td>"));
+
+        assert!(reader.read_block(&header, "DELETE FROM t").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::search::BlockText;
+use serde::{Deserialize, Serialize};
+
+/// Words per minute used to estimate reading time.
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// Word and character counts of some text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextStats {
+    pub words: usize,
+    /// Characters, not counting whitespace.
+    pub characters: usize,
+}
+
+impl TextStats {
+    fn of(text: &str) -> Self {
+        TextStats {
+            words: text.split_whitespace().count(),
+            characters: text.chars().filter(|c| !c.is_whitespace()).count(),
+        }
+    }
+
+    fn add(&mut self, other: TextStats) {
+        self.words += other.words;
+        self.characters += other.characters;
+    }
+
+    /// Estimated reading time in whole minutes, at least one minute if
+    /// there is any text.
+    pub fn reading_time_minutes(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockStats {
+    /// Id of the block, see `MarkdownBlock::id`.
+    pub id: u16,
+    #[serde(flatten)]
+    pub stats: TextStats,
+}
+
+/// Statistics of a section, that is a heading and all blocks until the next
+/// heading. Blocks before the first heading are in a section without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SectionStats {
+    pub heading: Option<String>,
+    /// Number of the heading, like `1.2`, with
+    /// `YamdrOptions::number_headings`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    #[serde(flatten)]
+    pub stats: TextStats,
+    pub reading_time_minutes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentStats {
+    #[serde(flatten)]
+    pub stats: TextStats,
+    pub reading_time_minutes: usize,
+    pub blocks: Vec<BlockStats>,
+    pub sections: Vec<SectionStats>,
+}
+
+pub(crate) fn document_stats(blocks: &[BlockText]) -> DocumentStats {
+    let mut document = DocumentStats::default();
+    for block in blocks {
+        let stats = TextStats::of(&block.text);
+        document.stats.add(stats);
+        document.blocks.push(BlockStats {
+            id: block.id,
+            stats,
+        });
+        match document.sections.last_mut() {
+            Some(section) if !block.is_heading => section.stats.add(stats),
+            _ => document.sections.push(SectionStats {
+                heading: block.heading.clone(),
+                number: block.number.clone(),
+                stats,
+                reading_time_minutes: 0,
+            }),
+        }
+    }
+    for section in document.sections.iter_mut() {
+        section.reading_time_minutes = section.stats.reading_time_minutes();
+    }
+    document.reading_time_minutes = document.stats.reading_time_minutes();
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn sections() {
+        let document = r#"
+Intro text.
+
+# First
+
+One two three.
+
+Four five.
+
+# Second
+
+Six
+"#;
+        let (meta, _) = render_markdown(&YamdrOptions::default(), document);
+        let stats = meta.stats;
+        assert_eq!(stats.stats.words, 10);
+        assert_eq!(stats.reading_time_minutes, 1);
+        assert_eq!(stats.blocks.len(), 6);
+        assert_eq!(stats.blocks[0].stats.words, 2);
+        assert_eq!(stats.blocks[0].stats.characters, 10);
+        assert_eq!(stats.sections.len(), 3);
+        assert_eq!(stats.sections[0].heading, None);
+        assert_eq!(stats.sections[1].heading.as_deref(), Some("First"));
+        assert_eq!(stats.sections[1].stats.words, 6);
+        assert_eq!(stats.sections[2].heading.as_deref(), Some("Second"));
+        assert_eq!(stats.sections[2].stats.words, 2);
+    }
+
+    #[test]
+    fn reading_time_line() {
+        let options = YamdrOptions {
+            reading_time: true,
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, "# Title\n\nText\n\n## Sub\n");
+        assert!(html.contains(
+            r#"</h1>
+<p class="reading-time">1 min read</p>"#
+        ));
+        assert_eq!(html.matches("reading-time").count(), 1);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    utils::{build_table, dynamic_as_f64, header_field},
+    CustomBlock, CustomBlockHeader, Format, Labels, NumberFormat,
+};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use std::collections::BTreeMap;
+
+/// Summary statistics of a list of numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation, which is 0 for a single number.
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    /// Statistics of `values`, or `None` if there are none.
+    pub fn of(values: &[f64]) -> Option<Stats> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let median = match count % 2 {
+            0 => (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0,
+            _ => sorted[count / 2],
+        };
+        let variance = match count {
+            1 => 0.0,
+            _ => sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1) as f64,
+        };
+        Some(Stats {
+            count,
+            mean,
+            median,
+            stddev: variance.sqrt(),
+            min: sorted[0],
+            max: sorted[count - 1],
+        })
+    }
+
+    /// Statistics of the numbers, or strings of numbers, in a script array.
+    pub fn of_array(values: &rhai::Array) -> Result<Stats, String> {
+        let values = values
+            .iter()
+            .map(|value| {
+                dynamic_as_f64(value)
+                    .or_else(|| value.clone().into_string().ok()?.trim().parse().ok())
+                    .ok_or_else(|| format!("`{}` isn't a number", value))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        Stats::of(&values).ok_or_else(|| "no numbers to summarize".to_string())
+    }
+
+    /// The statistics as a script map, with the same names as the fields.
+    pub fn to_map(&self) -> rhai::Map {
+        let mut map = rhai::Map::new();
+        map.insert("count".into(), (self.count as rhai::INT).into());
+        for (name, value) in [
+            ("mean", self.mean),
+            ("median", self.median),
+            ("stddev", self.stddev),
+            ("min", self.min),
+            ("max", self.max),
+        ] {
+            map.insert(name.into(), (value as rhai::FLOAT).into());
+        }
+        map
+    }
+}
+
+/// `value` rounded to 4 significant digits, without trailing zeros.
+fn round(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return value.to_string();
+    }
+    let decimals = (3 - value.abs().log10().floor() as i32).clamp(0, 12) as usize;
+    let rounded = format!("{:.*}", decimals, value);
+    match rounded.contains('.') {
+        true => rounded
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+        false => rounded,
+    }
+}
+
+/// Count, mean, median, standard deviation, min and max of the numeric
+/// fields of a Data block, optionally per group of rows with the same
+/// value of `by`.
+///
+/// ````text
+/// ```{t: Summary, data: measurements, by: group}
+/// ```
+/// ````
+///
+/// Every field where all the values are numbers is summarized, or only
+/// `field` if it is set. Empty values are left out. The same statistics
+/// are available to scripts with `summary(values)`, `mean(values)`,
+/// `median(values)` and `stddev(values)`.
+#[derive(Debug, Clone)]
+pub struct SummaryBlock {
+    header: CustomBlockHeader,
+    input: String,
+    by: Option<String>,
+    /// Statistics of each group and field, in the order the groups first
+    /// appear in the data.
+    stats: Vec<(String, String, Stats)>,
+    labels: Labels,
+    numbers: Option<NumberFormat>,
+}
+
+impl SummaryBlock {
+    pub fn read(
+        header: &CustomBlockHeader,
+        input: &str,
+        data: &[BTreeMap<String, String>],
+        labels: &Labels,
+        numbers: Option<NumberFormat>,
+    ) -> Result<SummaryBlock, String> {
+        let by = header_field(header, "by");
+        let number = |value: &str| value.trim().parse::<f64>().ok();
+        let fields: Vec<String> = match header_field(header, "field") {
+            Some(field) => vec![field],
+            None => {
+                let mut fields: Vec<String> = Vec::new();
+                for row in data {
+                    for field in row.keys() {
+                        if !fields.contains(field) && Some(field) != by.as_ref() {
+                            fields.push(field.clone());
+                        }
+                    }
+                }
+                fields.sort();
+                fields.retain(|field| {
+                    data.iter()
+                        .filter_map(|row| row.get(field))
+                        .all(|value| value.trim().is_empty() || number(value).is_some())
+                });
+                fields
+            }
+        };
+        let mut groups: Vec<(String, BTreeMap<&str, Vec<f64>>)> = Vec::new();
+        for (i, row) in data.iter().enumerate() {
+            let group = by
+                .as_ref()
+                .and_then(|by| row.get(by))
+                .cloned()
+                .unwrap_or_default();
+            let position = match groups.iter().position(|(name, _)| *name == group) {
+                Some(position) => position,
+                None => {
+                    groups.push((group, BTreeMap::new()));
+                    groups.len() - 1
+                }
+            };
+            for field in fields.iter() {
+                let Some(value) = row.get(field).filter(|value| !value.trim().is_empty()) else {
+                    continue;
+                };
+                let value = number(value).ok_or_else(|| {
+                    format!("`{}` of `{}` in row {} isn't a number", value, field, i + 1)
+                })?;
+                groups[position]
+                    .1
+                    .entry(field.as_str())
+                    .or_default()
+                    .push(value);
+            }
+        }
+        let stats = groups
+            .iter()
+            .flat_map(|(group, values)| {
+                fields.iter().filter_map(|field| {
+                    let stats = Stats::of(values.get(field.as_str())?)?;
+                    Some((group.clone(), field.clone(), stats))
+                })
+            })
+            .collect();
+        Ok(SummaryBlock {
+            header: header.clone(),
+            input: input.into(),
+            by,
+            stats,
+            labels: labels.clone(),
+            numbers,
+        })
+    }
+
+    fn cell(&self, value: f64) -> CowStr<'_> {
+        let value = round(value);
+        match &self.numbers {
+            Some(numbers) => numbers.cell(&value).unwrap_or(value).into(),
+            None => value.into(),
+        }
+    }
+}
+
+impl CustomBlock for SummaryBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let [field, count, mean, median, stddev, min, max] = &self.labels.summary;
+                let mut head: Vec<CowStr> = Vec::new();
+                if let Some(by) = &self.by {
+                    head.push(by.as_str().into());
+                }
+                head.push(field.as_str().into());
+                head.extend([count, mean, median, stddev, min, max].map(|l| l.as_str().into()));
+                let rows = self
+                    .stats
+                    .iter()
+                    .map(|(group, field, stats)| {
+                        let mut row: Vec<CowStr> = Vec::new();
+                        if self.by.is_some() {
+                            row.push(group.as_str().into());
+                        }
+                        row.push(field.as_str().into());
+                        row.push(self.cell(stats.count as f64));
+                        row.extend(
+                            [stats.mean, stats.median, stats.stddev, stats.min, stats.max]
+                                .map(|value| self.cell(value)),
+                        );
+                        row
+                    })
+                    .collect();
+                build_table(head, rows)
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error};
+
+    #[test]
+    fn stats() {
+        let stats = Stats::of(&[4.0, 1.0, 3.0, 2.0]).unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.mean, 2.5);
+        assert_eq!(stats.median, 2.5);
+        assert_eq!(round(stats.stddev), "1.291");
+        assert_eq!((stats.min, stats.max), (1.0, 4.0));
+        assert_eq!(Stats::of(&[7.0]).unwrap().stddev, 0.0);
+        assert_eq!(Stats::of(&[]), None);
+        assert_eq!(round(0.000123456), "0.0001235");
+        assert_eq!(round(1234567.8), "1234568");
+        assert_eq!(round(2.0), "2");
+    }
+
+    #[test]
+    fn summary_by_group() {
+        let data: Vec<BTreeMap<String, String>> = serde_yaml::from_str(
+            r#"
+- {group: b, ms: "10", note: slow}
+- {group: a, ms: "1", size: "5"}
+- {group: b, ms: "20"}
+- {group: a, ms: "3", size: ""}
+"#,
+        )
+        .unwrap();
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Summary, data: runs, by: group}").unwrap();
+        let block = SummaryBlock::read(&header, "", &data, &Labels::default(), None).unwrap();
+        let html = crate::html::render(block.to_events(Format::Html).into_iter(), 0);
+        assert!(html.contains("<th>group</th><th>Field</th><th>Count</th><th>Mean</th>"));
+        assert!(html.contains(
+            "<tr><td>b</td><td>ms</td><td>2</td><td>15</td><td>15</td><td>7.071</td><td>10</td><td>20</td></tr>"
+        ));
+        assert!(html.contains("<tr><td>a</td><td>size</td><td>1</td><td>5</td>"));
+        assert!(!html.contains("note"));
+        assert!(html.find("<td>b</td>").unwrap() < html.find("<td>a</td>").unwrap());
+
+        let header: CustomBlockHeader =
+            serde_yaml::from_str("{t: Summary, data: runs, field: note}").unwrap();
+        let err = SummaryBlock::read(&header, "", &data, &Labels::default(), None).unwrap_err();
+        assert_eq!(err, "`slow` of `note` in row 1 isn't a number");
+    }
+
+    #[test]
+    fn summary_block() {
+        let md = r#"
+```{t: Data}
+name: runs
+data:
+  - {group: a, ms: 1}
+  - {group: a, ms: 3}
+```
+
+```{t: Summary, data: runs, by: group}
+```
+
+`_summary(runs.map(|r| r.ms)).median_` `_stddev([1, 2, 3])_`
+"#;
+        let (meta, html) = render_markdown(&Default::default(), md);
+        assert!(meta.errors.is_empty(), "{:?}", meta.errors);
+        assert!(html.contains(
+            "<tr><td>a</td><td>ms</td><td>2</td><td>2</td><td>2</td><td>1.414</td><td>1</td><td>3</td></tr>"
+        ));
+        assert!(html.contains("// &gt; 2.0</code>"));
+        assert!(html.contains("stddev([1, 2, 3]) // &gt; 1.0</code>"));
+
+        let (meta, _) = render_markdown(&Default::default(), "```{t: Summary, data: none}\n```\n");
+        assert!(
+            matches!(&meta.errors[..], [Error::CustomBlockRead(err)] if err.contains("no Data block named `none`"))
+        );
+    }
+}
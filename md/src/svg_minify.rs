@@ -0,0 +1,390 @@
+use crate::utils::stable_hash;
+use std::collections::HashMap;
+
+/// Presentation attributes that are moved to a class, when several
+/// elements have the same combination of them, like the grid lines of a
+/// chart.
+const PRESENTATION: &[&str] = &[
+    "dominant-baseline",
+    "fill",
+    "fill-opacity",
+    "font-family",
+    "font-size",
+    "font-weight",
+    "opacity",
+    "stroke",
+    "stroke-dasharray",
+    "stroke-opacity",
+    "stroke-width",
+    "text-anchor",
+];
+
+/// Attributes whose values are coordinates, sizes or other geometry, so
+/// their numbers can be rounded. Other attributes, like `aria-label` or
+/// `data-*`, can contain numbers that are text, and are kept as they are.
+const ROUNDED: &[&str] = &[
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "dx",
+    "dy",
+    "fx",
+    "fy",
+    "r",
+    "rx",
+    "ry",
+    "d",
+    "points",
+    "width",
+    "height",
+    "viewBox",
+    "transform",
+    "offset",
+    "font-size",
+    "stroke-width",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+    "opacity",
+    "fill-opacity",
+    "stroke-opacity",
+];
+
+#[derive(Debug)]
+struct Element<'a> {
+    name: &'a str,
+    /// Attributes with their values, with the quote they are written with.
+    attributes: Vec<(&'a str, String, char)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+#[derive(Debug)]
+enum Token<'a> {
+    Element(Element<'a>),
+    Text(&'a str),
+}
+
+/// Parse the tag at the start of `tag`, which starts with `<`, returning it
+/// with its length.
+fn parse_element(tag: &str) -> Option<(Element<'_>, usize)> {
+    let closing = tag[1..].starts_with('/');
+    let mut i = 1 + closing as usize;
+    let name_len = tag[i..].find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+    let name = &tag[i..i + name_len];
+    i += name_len;
+    let mut attributes = Vec::new();
+    loop {
+        i += tag[i..].len() - tag[i..].trim_start().len();
+        let rest = &tag[i..];
+        if rest.starts_with("/>") {
+            return Some((
+                Element {
+                    name,
+                    attributes,
+                    closing,
+                    self_closing: true,
+                },
+                i + 2,
+            ));
+        }
+        if rest.starts_with('>') {
+            return Some((
+                Element {
+                    name,
+                    attributes,
+                    closing,
+                    self_closing: false,
+                },
+                i + 1,
+            ));
+        }
+        let name_len = rest.find(|c: char| c.is_whitespace() || c == '=')?;
+        let attribute = &rest[..name_len];
+        let after_name = rest[name_len..]
+            .trim_start()
+            .strip_prefix('=')?
+            .trim_start();
+        let quote = after_name
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))?;
+        let value_len = after_name[1..].find(quote)?;
+        let value = &after_name[1..1 + value_len];
+        attributes.push((attribute, value.to_string(), quote));
+        i = tag.len() - after_name.len() + value_len + 2;
+    }
+}
+
+fn tokenize(svg: &str) -> Option<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = svg;
+    while !rest.is_empty() {
+        // Character data isn't worth parsing for the generated svgs
+        if rest.starts_with("<![CDATA[") {
+            return None;
+        }
+        // Declarations, comments and metadata aren't rendered
+        let skipped = [
+            ("<?", "?>"),
+            ("<!--", "-->"),
+            ("<!DOCTYPE", ">"),
+            ("<metadata", "</metadata>"),
+        ]
+        .iter()
+        .find(|(start, _)| rest.starts_with(start))
+        .map(|(_, end)| rest.find(end).map_or(rest.len(), |i| i + end.len()));
+        if let Some(len) = skipped {
+            rest = &rest[len..];
+            continue;
+        }
+        if rest.starts_with('<') {
+            let (element, len) = parse_element(rest)?;
+            tokens.push(Token::Element(element));
+            rest = &rest[len..];
+        } else {
+            let len = rest.find('<').unwrap_or(rest.len());
+            tokens.push(Token::Text(&rest[..len]));
+            rest = &rest[len..];
+        }
+    }
+    Some(tokens)
+}
+
+/// `value` with numbers rounded to two decimals, like `32.25806451612903`
+/// to `32.26`.
+fn round_numbers(value: &str) -> String {
+    let mut rounded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit() || c == '.') {
+        rounded += &rest[..start];
+        rest = &rest[start..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+        let decimals = number
+            .split_once('.')
+            .map_or(0, |(_, decimals)| decimals.len());
+        match number.parse::<f64>() {
+            Ok(n) if decimals > 2 => {
+                let n = (n * 100.0).round() / 100.0;
+                rounded += &n.to_string();
+            }
+            _ => rounded += number,
+        }
+        rest = &rest[len..];
+    }
+    rounded += rest;
+    rounded
+}
+
+/// Css declarations for presentation attributes.
+fn declarations(attributes: &[(&str, String, char)]) -> String {
+    attributes
+        .iter()
+        .map(|(name, value, _)| {
+            // Unlike the attribute, the css property needs a unit
+            let unit = match *name == "font-size" && value.parse::<f64>().is_ok() {
+                true => "px",
+                false => "",
+            };
+            format!("{}:{}{}", name, value, unit)
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// A smaller version of an svg, or of html containing svgs, that renders
+/// the same: declarations, comments and metadata are removed, whitespace
+/// between elements is dropped, numbers are rounded to two decimals, and
+/// combinations of presentation attributes that are repeated are replaced
+/// with classes, defined in a `<style>` at the start of the first svg. The
+/// classes are prefixed with a hash of the svg, so svgs in the same page
+/// don't affect each other.
+pub(crate) fn minify(svg: &str) -> String {
+    let Some(mut tokens) = tokenize(svg) else {
+        return svg.to_string();
+    };
+    for token in tokens.iter_mut() {
+        if let Token::Element(element) = token {
+            for (name, value, _) in element.attributes.iter_mut() {
+                if ROUNDED.contains(name) {
+                    *value = round_numbers(value);
+                }
+            }
+        }
+    }
+
+    // Combinations of presentation attributes, with how many elements have them
+    let presentation = |element: &Element| -> Option<String> {
+        let attributes: Vec<_> = element
+            .attributes
+            .iter()
+            .filter(|(name, _, _)| PRESENTATION.contains(name))
+            .cloned()
+            .collect();
+        let declarations = declarations(&attributes);
+        // Only worth a class if it's longer than the class attribute
+        (declarations.len() > 24).then_some(declarations)
+    };
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for token in tokens.iter() {
+        if let Token::Element(element) = token {
+            if let Some(declarations) = presentation(element) {
+                let count = counts.entry(declarations.clone()).or_default();
+                if *count == 0 {
+                    order.push(declarations);
+                }
+                *count += 1;
+            }
+        }
+    }
+    let prefix = format!("m{:x}", stable_hash(svg.as_bytes()) as u32);
+    let classes: HashMap<&str, String> = order
+        .iter()
+        .filter(|declarations| counts[*declarations] > 1)
+        .enumerate()
+        .map(|(i, declarations)| (declarations.as_str(), format!("{}-{}", prefix, i)))
+        .collect();
+    let mut style = String::new();
+    for declarations in order.iter() {
+        if let Some(class) = classes.get(declarations.as_str()) {
+            style += &format!(".{}{{{}}}", class, declarations);
+        }
+    }
+
+    let mut output = String::with_capacity(svg.len() / 2);
+    let mut in_text = 0;
+    let mut style_written = style.is_empty();
+    for token in tokens.iter() {
+        let element = match token {
+            Token::Text(text) if in_text == 0 && text.trim().is_empty() => continue,
+            Token::Text(text) => {
+                output += text;
+                continue;
+            }
+            Token::Element(element) => element,
+        };
+        let class =
+            presentation(element).and_then(|declarations| classes.get(declarations.as_str()));
+        output.push('<');
+        if element.closing {
+            output.push('/');
+        }
+        output += element.name;
+        let mut class_written = false;
+        for (name, value, quote) in element.attributes.iter() {
+            match class {
+                Some(_) if PRESENTATION.contains(name) => continue,
+                Some(class) if *name == "class" => {
+                    output += &format!(" class={0}{1} {2}{0}", quote, value, class);
+                    class_written = true;
+                    continue;
+                }
+                _ => {}
+            }
+            output += &format!(" {}={2}{}{2}", name, value, quote);
+        }
+        if let (Some(class), false) = (class, class_written) {
+            output += &format!(" class=\"{}\"", class);
+        }
+        output += if element.self_closing { "/>" } else { ">" };
+        if matches!(
+            element.name,
+            "text" | "tspan" | "textPath" | "style" | "title" | "desc"
+        ) && !element.self_closing
+        {
+            match element.closing {
+                true => in_text -= 1,
+                false => in_text += 1,
+            }
+        }
+        if element.name == "svg" && !element.closing && !style_written {
+            output += &format!("<style>{}</style>", style);
+            style_written = true;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn rounds_numbers() {
+        assert_eq!(
+            round_numbers("M 0.123456 10.5 L -3.14159,2.999"),
+            "M 0.12 10.5 L -3.14,3"
+        );
+        assert_eq!(round_numbers("#ff00aa 1.2.3"), "#ff00aa 1.2.3");
+    }
+
+    #[test]
+    fn minifies() {
+        let svg = r##"<?xml version="1.0" encoding="UTF-8"?>
+<!-- generated -->
+<svg width="600" height="400" xmlns="http://www.w3.org/2000/svg">
+<metadata><rdf>a</rdf></metadata>
+<text x="300.000001" y="15" font-family="sans-serif" font-size="32.25806451612903">
+T
+</text>
+<line opacity="0.1" stroke="#000000" stroke-width="1" x1="30" y1="369"/>
+<line opacity="0.1" stroke="#000000" stroke-width="1" x1="48" y1="369" class='grid' />
+<line stroke="red" x1="0" y1="0"/>
+<g aria-label="v1.125 rollout" data-value="0.123456"/>
+</svg>
+"##;
+        let minified = minify(svg);
+        let prefix = format!("m{:x}", stable_hash(svg.as_bytes()) as u32);
+        assert_eq!(
+            minified,
+            format!(
+                concat!(
+                    r#"<svg width="600" height="400" xmlns="http://www.w3.org/2000/svg">"#,
+                    r#"<style>.{0}-0{{opacity:0.1;stroke:#000000;stroke-width:1}}</style>"#,
+                    r#"<text x="300" y="15" font-family="sans-serif" font-size="32.26">"#,
+                    "\nT\n</text>",
+                    r#"<line x1="30" y1="369" class="{0}-0"/>"#,
+                    r#"<line x1="48" y1="369" class='grid {0}-0'/>"#,
+                    r#"<line stroke="red" x1="0" y1="0"/>"#,
+                    r#"<g aria-label="v1.125 rollout" data-value="0.123456"/>"#,
+                    "</svg>"
+                ),
+                prefix
+            )
+        );
+        assert_eq!(minify("not <svg"), "not <svg");
+    }
+
+    #[test]
+    fn font_size_unit() {
+        let svg = r#"<svg><text font-size="12" font-family="sans-serif">a</text><text font-size="12" font-family="sans-serif">b</text></svg>"#;
+        assert!(minify(svg).contains("{font-size:12px;font-family:sans-serif}"));
+    }
+
+    #[test]
+    fn minify_svg() {
+        let document = "```{t: Graph}\ndigraph { a -> b; b -> c; }\n```\n";
+        let (_, html) = render_markdown(&Default::default(), document);
+        let options = YamdrOptions {
+            minify_svg: true,
+            ..Default::default()
+        };
+        let (_, minified) = render_markdown(&options, document);
+        assert!(minified.len() < html.len());
+        assert!(html.contains("<?xml"));
+        assert!(!minified.contains("<?xml"));
+        assert_eq!(
+            minified.matches("<ellipse").count(),
+            html.matches("<ellipse").count()
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    utils::{css_length, header_field},
+    CustomBlockHeader,
+};
+
+/// Size of the svg rendered by a block, from the `scale`, `max_width` and
+/// `fit` fields of its header.
+///
+/// ````text
+/// ```{t: Graph, scale: 0.5, max_width: 400}
+/// digraph { a -> b }
+/// ```
+/// ````
+///
+/// `scale` multiplies the width and height the svg is laid out with,
+/// `max_width` is a css length the svg is shrunk to if it is wider, and
+/// `fit: true` shrinks it to the width of the content column. The svg keeps
+/// its aspect ratio when it is shrunk.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SvgSize {
+    scale: Option<f64>,
+    max_width: Option<String>,
+    fit: bool,
+}
+
+/// Value of the attribute `name` of an html tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')? + start;
+    Some(&tag[start..end])
+}
+
+/// `tag` with the attribute `name` set to `value`, or removed if `value`
+/// is `None`.
+fn set_attribute(tag: &str, name: &str, value: Option<&str>) -> String {
+    let pattern = format!(" {}=\"", name);
+    let mut tag = match tag.find(&pattern) {
+        Some(start) => {
+            let end = tag[start + pattern.len()..]
+                .find('"')
+                .map_or(tag.len(), |end| start + pattern.len() + end + 1);
+            format!("{}{}", &tag[..start], &tag[end..])
+        }
+        None => tag.to_string(),
+    };
+    if let Some(value) = value {
+        let end = tag.len() - 1 - tag.ends_with("/>") as usize;
+        tag.insert_str(end, &format!(" {}=\"{}\"", name, value));
+    }
+    tag
+}
+
+/// A length attribute of an svg in user units, like `180` or `180pt`.
+fn svg_length(length: &str) -> Option<f64> {
+    length
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .parse()
+        .ok()
+        .filter(|length: &f64| length.is_finite())
+}
+
+impl SvgSize {
+    pub fn from_header(header: &CustomBlockHeader) -> Result<Self, String> {
+        let scale = match header_field(header, "scale") {
+            Some(scale) => Some(
+                scale
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|scale| scale.is_finite() && *scale > 0.0)
+                    .ok_or_else(|| format!("invalid scale `{}` in {} block", scale, header.t))?,
+            ),
+            None => None,
+        };
+        let max_width =
+            match header_field(header, "max_width") {
+                Some(width) => Some(css_length(&width).ok_or_else(|| {
+                    format!("invalid max_width `{}` in {} block", width, header.t)
+                })?),
+                None => None,
+            };
+        let fit = header
+            .fields
+            .get("fit")
+            .and_then(serde_yaml::Value::as_bool)
+            .unwrap_or(false);
+        Ok(SvgSize {
+            scale,
+            max_width,
+            fit,
+        })
+    }
+
+    /// `svg` with the size applied to the attributes of its root element.
+    /// A `viewBox` is added if it doesn't have one, so it can be scaled.
+    pub fn apply(&self, svg: &str) -> String {
+        if *self == SvgSize::default() {
+            return svg.to_string();
+        }
+        let Some(start) = svg.find("<svg") else {
+            return svg.to_string();
+        };
+        let Some(end) = svg[start..].find('>').map(|end| start + end + 1) else {
+            return svg.to_string();
+        };
+        let mut tag = svg[start..end].to_string();
+        let width = attribute(&tag, "width").and_then(svg_length);
+        let height = attribute(&tag, "height").and_then(svg_length);
+        if let (Some(width), Some(height)) = (width, height) {
+            if attribute(&tag, "viewBox").is_none() {
+                tag = set_attribute(&tag, "viewBox", Some(&format!("0 0 {} {}", width, height)));
+            }
+            if let Some(scale) = self.scale {
+                let scaled = |length: f64| format!("{}", (length * scale * 100.0).round() / 100.0);
+                tag = set_attribute(&tag, "width", Some(&scaled(width)));
+                tag = set_attribute(&tag, "height", Some(&scaled(height)));
+            }
+        }
+        let max_width = match (&self.max_width, self.fit) {
+            (Some(max_width), true) => Some(format!("min(100%, {})", max_width)),
+            (Some(max_width), false) => Some(max_width.clone()),
+            (None, true) => Some("100%".to_string()),
+            (None, false) => None,
+        };
+        if let Some(max_width) = max_width {
+            let style = format!("max-width: {}; height: auto", max_width);
+            let style = match attribute(&tag, "style") {
+                Some(existing) => format!("{}; {}", existing.trim_end_matches(';'), style),
+                None => style,
+            };
+            tag = set_attribute(&tag, "style", Some(&style));
+        }
+        format!("{}{}{}", &svg[..start], tag, &svg[end..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = r#"<?xml version="1.0"?><svg width="86" height="180" xmlns="http://www.w3.org/2000/svg"><g/></svg>"#;
+
+    fn size(header: &str) -> Result<SvgSize, String> {
+        SvgSize::from_header(&serde_yaml::from_str(header).unwrap())
+    }
+
+    #[test]
+    fn apply() {
+        assert_eq!(size("{t: Graph}").unwrap().apply(SVG), SVG);
+        assert_eq!(
+            size("{t: Graph, scale: 0.5}").unwrap().apply(SVG),
+            r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 86 180" width="43" height="90"><g/></svg>"#
+        );
+        assert_eq!(
+            size("{t: Graph, max_width: 50%, fit: true}")
+                .unwrap()
+                .apply(SVG),
+            r#"<?xml version="1.0"?><svg width="86" height="180" xmlns="http://www.w3.org/2000/svg" viewBox="0 0 86 180" style="max-width: min(100%, 50%); height: auto"><g/></svg>"#
+        );
+        assert!(size("{t: Graph, fit: true}")
+            .unwrap()
+            .apply(r#"<svg width="10" height="10" viewBox="0 0 5 5" style="color: red;"/>"#)
+            .ends_with(r#"viewBox="0 0 5 5" style="color: red; max-width: 100%; height: auto"/>"#));
+    }
+
+    #[test]
+    fn invalid_fields() {
+        assert!(size("{t: Graph, scale: 0}").is_err());
+        assert!(size("{t: Graph, scale: big}").is_err());
+        assert!(size("{t: Graph, max_width: 10furlongs}").is_err());
+    }
+}
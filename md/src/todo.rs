@@ -0,0 +1,180 @@
+use crate::{CustomBlock, CustomBlockHeader, Format};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+
+/// A TODO in a document, from a `Todo` block or a `TODO:` in text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Todo {
+    pub text: String,
+    /// Heading of the section the TODO is in, if any.
+    pub section: Option<String>,
+}
+
+/// A `Todo` block, with a markdown body, or a `TODO: ...` in text, rendered
+/// highlighted in html, or left out if `YamdrOptions::hide_todos` is set.
+#[derive(Debug, Clone)]
+pub(crate) struct TodoBlock {
+    /// Header of a `Todo` block, `None` for inline TODOs.
+    header: Option<CustomBlockHeader>,
+    text: String,
+    hidden: bool,
+}
+
+impl TodoBlock {
+    pub fn block(header: &CustomBlockHeader, body: &str, hidden: bool) -> Self {
+        TodoBlock {
+            header: Some(header.clone()),
+            text: body.into(),
+            hidden,
+        }
+    }
+
+    pub fn inline(text: &str, hidden: bool) -> Self {
+        TodoBlock {
+            header: None,
+            text: text.into(),
+            hidden,
+        }
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.header.is_none()
+    }
+
+    /// The TODO, without the `TODO:` marker of inline TODOs.
+    pub fn todo(&self, section: Option<String>) -> Todo {
+        let text = match self.header {
+            Some(_) => &self.text,
+            None => self.text.strip_prefix(MARKER).unwrap_or(&self.text),
+        };
+        Todo {
+            text: text.trim().into(),
+            section,
+        }
+    }
+}
+
+impl CustomBlock for TodoBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match (format, &self.header) {
+            (Format::Html, _) if self.hidden => Vec::new(),
+            (Format::Html, Some(_)) => {
+                let mut events = vec![Event::Html(r#"<div class="todo" role="note">"#.into())];
+                events.extend(Parser::new_ext(&self.text, Options::all()));
+                events.push(Event::Html("</div>".into()));
+                events
+            }
+            (Format::Html, None) => vec![
+                Event::Html(r#"<mark class="todo">"#.into()),
+                Event::Text(self.text.as_str().into()),
+                Event::Html("</mark>".into()),
+            ],
+            (Format::Md, Some(header)) => {
+                let props: pulldown_cmark::CowStr = serde_json::to_string(header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.text.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+            (Format::Md, None) => vec![Event::Text(self.text.as_str().into())],
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+const MARKER: &str = "TODO:";
+
+/// Split text at a `TODO:` marker at the start of a word. The TODO runs to
+/// the end of the text.
+pub(crate) fn split_todo(text: &str) -> Option<(&str, &str)> {
+    text.match_indices(MARKER)
+        .map(|(i, _)| i)
+        .find(|i| {
+            text[..*i]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+        })
+        .map(|i| text.split_at(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, YamdrOptions};
+
+    #[test]
+    fn inline_todos() {
+        assert_eq!(
+            split_todo("Some text. TODO: fix this"),
+            Some(("Some text. ", "TODO: fix this"))
+        );
+        assert_eq!(split_todo("TODO: first"), Some(("", "TODO: first")));
+        assert_eq!(split_todo("NOTTODO: and TODOs"), None);
+
+        let todo = TodoBlock::inline("TODO: fix this", false);
+        assert_eq!(todo.todo(None).text, "fix this");
+        assert_eq!(
+            crate::html::render(todo.to_events(Format::Html).into_iter(), 0),
+            r#"<mark class="todo">TODO: fix this</mark>"#
+        );
+        let todo = TodoBlock::inline("TODO: fix this", true);
+        assert!(todo.to_events(Format::Html).is_empty());
+        assert_eq!(
+            todo.to_events(Format::Md),
+            vec![Event::Text("TODO: fix this".into())]
+        );
+    }
+
+    #[test]
+    fn todos() {
+        let document = r#"
+# Introduction
+
+Some text. TODO: write the *introduction*
+
+```{t: Todo}
+Add a **diagram**.
+```
+
+`TODO: not in code`
+"#;
+        let (meta, html) = render_markdown(&Default::default(), document);
+        assert!(html.contains(r#"Some text. <mark class="todo">TODO: write the </mark><em>"#));
+        assert!(html.contains(
+            "<div class=\"todo\" role=\"note\">\n<p>Add a <strong>diagram</strong>.</p>"
+        ));
+        let introduction = Some("Introduction".to_string());
+        assert_eq!(
+            meta.todos,
+            vec![
+                Todo {
+                    text: "write the introduction".into(),
+                    section: introduction.clone()
+                },
+                Todo {
+                    text: "Add a **diagram**.".into(),
+                    section: introduction
+                },
+            ]
+        );
+
+        let mut options = YamdrOptions {
+            hide_todos: true,
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(!html.contains("write the") && !html.contains("introduction"));
+        assert!(!html.contains("diagram"));
+        assert_eq!(meta.todos.len(), 2);
+
+        options.format = Some(Format::Md);
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.contains("Some text. TODO: write the *introduction*\n"));
+        assert!(md.contains("```{\"t\":\"Todo\"}\nAdd a **diagram**.\n```"));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{
+    elements::{elements, ElementKind},
+    utils::{stable_hash, xml_attribute, xml_unescape},
+    Error, Result,
+};
+use quick_xml::{escape::escape, events::Event as XmlEvent};
+use std::{collections::BTreeMap, ops::Range};
+
+/// File format of the text of a document exported for translation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationFormat {
+    /// An object from the key of each block to its text, which most
+    /// translation tools read as "key-value JSON".
+    Json,
+    /// XLIFF 1.2, with a `trans-unit` for each block. XLIFF 2 `unit`s are
+    /// also read when applying translations.
+    Xliff,
+}
+
+impl TranslationFormat {
+    /// The format of a file with the extension `extension`, like `xlf`.
+    pub fn for_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "json" => Some(TranslationFormat::Json),
+            "xlf" | "xliff" => Some(TranslationFormat::Xliff),
+            _ => None,
+        }
+    }
+}
+
+/// The top level elements of a document that should be translated,
+/// leaving out the frontmatter, code blocks, custom blocks, html blocks and
+/// thematic breaks.
+fn units(markdown: &str) -> impl Iterator<Item = Range<usize>> {
+    elements(markdown)
+        .into_iter()
+        .filter(|element| element.kind == ElementKind::Text)
+        .map(|element| element.range)
+}
+
+/// Key of the text of a block, a hash of the text, so the keys of blocks
+/// that haven't changed stay the same when the document is edited.
+fn key(text: &str) -> String {
+    format!("{:08x}", stable_hash(text.as_bytes()) as u32)
+}
+
+/// The markdown of the blocks of a document that should be translated,
+/// with their keys, in the order they are in the document. Blocks with the
+/// same text are only included once.
+fn translatable_texts(markdown: &str) -> Vec<(String, &str)> {
+    let mut texts: Vec<(String, &str)> = Vec::new();
+    for unit in units(markdown) {
+        let text = &markdown[unit.clone()];
+        let key = key(text);
+        if !texts.iter().any(|(k, _)| *k == key) {
+            texts.push((key, text));
+        }
+    }
+    texts
+}
+
+/// Export the text of a document to be translated, a unit for each
+/// paragraph, heading, list, quote or table, with its markdown. Code,
+/// custom and html blocks, and the frontmatter, are left out, and are kept
+/// as they are by `apply_translations`.
+///
+/// `lang` is the language of the document, written as the source language
+/// of XLIFF files.
+pub fn extract_translations(markdown: &str, format: TranslationFormat, lang: &str) -> String {
+    let texts = translatable_texts(markdown);
+    match format {
+        TranslationFormat::Json => {
+            // Written by hand, to keep the blocks in the order of the document
+            let entries: Vec<String> = texts
+                .iter()
+                .map(|(key, text)| {
+                    format!(
+                        "  {}: {}",
+                        serde_json::to_string(key).unwrap(),
+                        serde_json::to_string(text).unwrap()
+                    )
+                })
+                .collect();
+            match entries.is_empty() {
+                true => "{}\n".to_string(),
+                false => format!("{{\n{}\n}}\n", entries.join(",\n")),
+            }
+        }
+        TranslationFormat::Xliff => {
+            let mut xliff = format!(
+                concat!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                    "<xliff version=\"1.2\" xmlns=\"urn:oasis:names:tc:xliff:document:1.2\">\n",
+                    "<file original=\"document.md\" datatype=\"x-markdown\" source-language=\"{}\">\n",
+                    "<body>\n"
+                ),
+                escape(lang)
+            );
+            for (key, text) in texts {
+                xliff += &format!(
+                    "<trans-unit id=\"{}\" xml:space=\"preserve\"><source>{}</source></trans-unit>\n",
+                    key,
+                    escape(text)
+                );
+            }
+            xliff += "</body>\n</file>\n</xliff>\n";
+            xliff
+        }
+    }
+}
+
+/// The targets of the units of an XLIFF file, by id.
+fn parse_xliff(xliff: &str) -> Result<BTreeMap<String, String>> {
+    let mut reader = quick_xml::Reader::from_str(xliff);
+    let mut targets = BTreeMap::new();
+    let mut unit: Option<String> = None;
+    let mut target: Option<String> = None;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|err| Error::Translation(format!("failed to parse XLIFF: {}", err)))?;
+        match event {
+            XmlEvent::Start(ref element) => match element.local_name().as_ref() {
+                "trans-unit" | "unit" => unit = xml_attribute(element, "id"),
+                "target" if unit.is_some() => target = Some(String::new()),
+                _ => {}
+            },
+            XmlEvent::Text(text) => {
+                if let Some(target) = target.as_mut() {
+                    *target += &text.xml10_content();
+                }
+            }
+            XmlEvent::CData(text) => {
+                if let Some(target) = target.as_mut() {
+                    *target += &text.xml10_content();
+                }
+            }
+            XmlEvent::GeneralRef(reference) => {
+                if let Some(target) = target.as_mut() {
+                    *target += &xml_unescape(&format!("&{};", reference.xml10_content()));
+                }
+            }
+            XmlEvent::End(element) => match element.local_name().as_ref() {
+                "target" => {
+                    if let (Some(unit), Some(target)) = (unit.as_ref(), target.take()) {
+                        targets.insert(unit.clone(), target);
+                    }
+                }
+                "trans-unit" | "unit" => unit = None,
+                _ => {}
+            },
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(targets)
+}
+
+/// A document with the translations from `apply_translations`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslatedDocument {
+    pub markdown: String,
+    /// Keys of the blocks that weren't translated, and are kept as they are.
+    pub untranslated: Vec<String>,
+}
+
+/// Replace the text of the blocks of a document with their translations,
+/// exported with `extract_translations` and translated. Blocks without a
+/// translation, like blocks that changed after the text was exported, are
+/// kept as they are, and listed in `TranslatedDocument::untranslated`.
+pub fn apply_translations(
+    markdown: &str,
+    translations: &str,
+    format: TranslationFormat,
+) -> Result<TranslatedDocument> {
+    let translations: BTreeMap<String, String> = match format {
+        TranslationFormat::Json => serde_json::from_str(translations)
+            .map_err(|err| Error::Translation(format!("failed to parse JSON: {}", err)))?,
+        TranslationFormat::Xliff => parse_xliff(translations)?,
+    };
+    let mut translated = TranslatedDocument {
+        markdown: String::new(),
+        untranslated: Vec::new(),
+    };
+    let mut written = 0;
+    for unit in units(markdown) {
+        let text = &markdown[unit.clone()];
+        let key = key(text);
+        translated.markdown += &markdown[written..unit.start];
+        match translations.get(&key).map(|t| t.trim_end()) {
+            Some(translation) if !translation.is_empty() => translated.markdown += translation,
+            _ => {
+                translated.markdown += text;
+                if !translated.untranslated.contains(&key) {
+                    translated.untranslated.push(key);
+                }
+            }
+        }
+        written = unit.end;
+    }
+    translated.markdown += &markdown[written..];
+    Ok(translated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "---\ntitle: Report\n---\n# Report\n\nSome *text*.\n\n```{t: Code, language: rust}\nlet x = 1;\n```\n\n<div>\nhtml\n</div>\n\n- a\n- b\n\n---\n\nSome *text*.\n";
+
+    #[test]
+    fn extract() {
+        let texts: Vec<&str> = translatable_texts(DOCUMENT)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(texts, vec!["# Report", "Some *text*.", "- a\n- b"]);
+
+        let json = extract_translations(DOCUMENT, TranslationFormat::Json, "en");
+        let parsed: BTreeMap<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert!(json.find("Report").unwrap() < json.find("- a").unwrap());
+
+        let xliff = extract_translations("A & <b>\n", TranslationFormat::Xliff, "nb");
+        assert!(xliff.contains(r#"source-language="nb""#));
+        assert!(xliff.contains(&format!(
+            r#"<trans-unit id="{}" xml:space="preserve"><source>A &amp; &lt;b&gt;</source></trans-unit>"#,
+            key("A & <b>")
+        )));
+    }
+
+    #[test]
+    fn apply() {
+        let translations = format!(
+            "{{\"{}\": \"# Rapport\\n\", \"{}\": \"Litt *tekst*.\", \"unused\": \"x\"}}",
+            key("# Report"),
+            key("Some *text*.")
+        );
+        let translated =
+            apply_translations(DOCUMENT, &translations, TranslationFormat::Json).unwrap();
+        assert_eq!(
+            translated.markdown,
+            DOCUMENT
+                .replace("# Report\n\n", "# Rapport\n\n")
+                .replace("Some *text*.", "Litt *tekst*.")
+        );
+        assert_eq!(translated.untranslated, vec![key("- a\n- b")]);
+
+        // A document translated from an exported and translated XLIFF file
+        let xliff = extract_translations(
+            "Hello & bye\n\n```\ncode\n```\n",
+            TranslationFormat::Xliff,
+            "en",
+        )
+        .replace(
+            "</source>",
+            "</source><target>Hei &amp; <![CDATA[<ha det>]]></target>",
+        );
+        let translated = apply_translations(
+            "Hello & bye\n\n```\ncode\n```\n",
+            &xliff,
+            TranslationFormat::Xliff,
+        )
+        .unwrap();
+        assert_eq!(translated.markdown, "Hei & <ha det>\n\n```\ncode\n```\n");
+        assert!(translated.untranslated.is_empty());
+
+        assert!(apply_translations(DOCUMENT, "[1]", TranslationFormat::Json).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// Typographic replacements in the text of html output, like `(c)` with
+/// `©`, see `YamdrOptions::typography`. Text in code is left as it is.
+///
+/// Unlike the smart punctuation of the markdown parser, the replacements
+/// are only made when rendering to html, so rendering to markdown keeps the
+/// text as it is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Typography {
+    /// Pairs of text and what it's replaced with, tried in order at each
+    /// position, so `---` has to come before `--`.
+    pub replacements: Vec<(String, String)>,
+}
+
+impl Default for Typography {
+    fn default() -> Self {
+        let replacements = [
+            ("(c)", "©"),
+            ("(C)", "©"),
+            ("(r)", "®"),
+            ("(R)", "®"),
+            ("(tm)", "™"),
+            ("(TM)", "™"),
+            ("...", "…"),
+            ("---", "—"),
+            ("--", "–"),
+        ];
+        Typography {
+            replacements: replacements
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Typography {
+    fn replace<'a>(&self, text: CowStr<'a>) -> CowStr<'a> {
+        let replacements = || {
+            self.replacements
+                .iter()
+                .filter(|(from, _)| !from.is_empty())
+        };
+        if !replacements().any(|(from, _)| text.contains(from.as_str())) {
+            return text;
+        }
+        let mut replaced = String::with_capacity(text.len());
+        let mut rest = text.as_ref();
+        while let Some(c) = rest.chars().next() {
+            match replacements().find(|(from, _)| rest.starts_with(from.as_str())) {
+                Some((from, to)) => {
+                    replaced += to;
+                    rest = &rest[from.len()..];
+                }
+                None => {
+                    replaced.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        replaced.into()
+    }
+}
+
+/// Makes the replacements of a `Typography` in the text of html events.
+pub(crate) struct TypographyEvents<'t> {
+    typography: &'t Typography,
+    in_code_block: bool,
+}
+
+impl<'t> TypographyEvents<'t> {
+    pub fn new(typography: &'t Typography) -> Self {
+        TypographyEvents {
+            typography,
+            in_code_block: false,
+        }
+    }
+
+    pub fn event<'a>(&mut self, event: Event<'a>) -> Event<'a> {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            Event::Text(text) if !self.in_code_block => {
+                return Event::Text(self.typography.replace(text))
+            }
+            _ => {}
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Format, YamdrOptions};
+
+    #[test]
+    fn replacements() {
+        let typography = Typography::default();
+        assert_eq!(
+            typography.replace("(c) 2024 -- wait... --- done(TM)".into()),
+            "© 2024 – wait… — done™".into()
+        );
+        assert!(matches!(
+            typography.replace("nothing to replace".into()),
+            CowStr::Borrowed(_)
+        ));
+
+        let typography = Typography {
+            replacements: vec![("->".into(), "→".into()), ("".into(), "x".into())],
+        };
+        assert_eq!(typography.replace("a -> b...".into()), "a → b...".into());
+    }
+
+    #[test]
+    fn typography() {
+        let document = "(c) 2024 -- wait...\n\n```\n(c) -- ...\n```\n";
+        let options = YamdrOptions {
+            typography: Some(Typography::default()),
+            ..Default::default()
+        };
+        let (_, html) = render_markdown(&options, document);
+        assert!(html.contains("<p>© 2024 – wait…</p>"));
+        assert!(html.contains("(c) -- ..."));
+
+        let (_, html) = render_markdown(
+            &options,
+            &format!(
+                "```{{t: External, meta: true, typography: false}}\n```\n{}",
+                document
+            ),
+        );
+        assert!(html.contains("<p>(c) 2024 -- wait...</p>"));
+        let (_, html) = render_markdown(
+            &Default::default(),
+            &format!(
+                "```{{t: External, meta: true, typography: true}}\n```\n{}",
+                document
+            ),
+        );
+        assert!(html.contains("<p>© 2024 – wait…</p>"));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..options
+        };
+        let (_, md) = render_markdown(&options, document);
+        assert!(md.starts_with("(c) 2024 -- wait..."));
+    }
+}
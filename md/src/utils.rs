@@ -1,5 +1,8 @@
-use pulldown_cmark::{escape::escape_html, Event};
+use crate::{CustomBlockHeader, Labels};
+use pulldown_cmark::{escape::escape_html, CowStr, Event, Tag};
+use quick_xml::{events::BytesStart, XmlVersion};
 use rhai::plugin::Dynamic;
+use std::path::{Component, Path, PathBuf};
 
 pub fn html_hide_with_title<'a>(
     title: String,
@@ -8,22 +11,770 @@ pub fn html_hide_with_title<'a>(
     let mut title_escaped = String::new();
     escape_html(&mut title_escaped, &title).unwrap();
     let mut e = vec![Event::Html(
-        format!("<details><summary>{}</summary>", title_escaped).into(),
+        format!(
+            r#"<details><summary>{0}</summary><div role="region" aria-label="{0}">"#,
+            title_escaped
+        )
+        .into(),
     )];
     e.extend(events);
-    e.push(Event::Html("</details>".into()));
+    e.push(Event::Html("</div></details>".into()));
     e
 }
 
+/// Make an svg accessible to screen readers, by adding an `img` role and
+/// `aria-label` to the root element, and `alt` as the `<title>` of the svg.
+pub fn svg_with_alt(svg: &str, alt: &str) -> String {
+    svg_with_title(svg, alt, None)
+}
+
+/// Like `svg_with_alt`, with a longer description of the svg as its
+/// `<desc>`.
+pub fn svg_with_title(svg: &str, title: &str, description: Option<&str>) -> String {
+    let Some(start) = svg.find("<svg") else {
+        return svg.to_string();
+    };
+    let Some(end) = svg[start..].find('>').map(|end| start + end) else {
+        return svg.to_string();
+    };
+    let mut title_escaped = String::new();
+    escape_html(&mut title_escaped, title).unwrap();
+    let self_closing = svg[..end].ends_with('/');
+    let tag_end = if self_closing { end - 1 } else { end };
+    let mut output = String::with_capacity(svg.len() + 2 * title_escaped.len() + 48);
+    output += &svg[..tag_end];
+    output += &format!(r#" role="img" aria-label="{}""#, title_escaped);
+    if self_closing {
+        output += "/>";
+    } else {
+        output += ">";
+        output += &format!("<title>{}</title>", title_escaped);
+        if let Some(description) = description {
+            output += "<desc>";
+            escape_html(&mut output, description).unwrap();
+            output += "</desc>";
+        }
+    }
+    output += &svg[end + 1..];
+    output
+}
+
+/// Apply the accessibility related header fields of a chart or graph block
+/// to its rendered svg. The ids in the svg are also made unique, see
+/// `scope_svg_ids`.
+pub fn accessible_svg(svg: String, header: &CustomBlockHeader) -> String {
+    let svg = scope_svg_ids(&svg, header.index);
+    let field = |name| header.fields.get(name).and_then(serde_yaml::Value::as_str);
+    match (field("title"), field("alt")) {
+        (Some(title), alt) => svg_with_title(&svg, title, alt),
+        (None, Some(alt)) => svg_with_alt(&svg, alt),
+        (None, None) => svg,
+    }
+}
+
+/// The data of a chart or graph block as a table, for screen readers, when
+/// its header has `accessibility: verbose`. The table is visually hidden,
+/// and rendered after the svg, with the `title` or `alt` of the block as
+/// its caption.
+pub fn data_fallback(
+    header: &CustomBlockHeader,
+    head: &[&str],
+    rows: impl FnOnce() -> Vec<Vec<String>>,
+) -> Option<String> {
+    if header_field(header, "accessibility").as_deref() != Some("verbose") {
+        return None;
+    }
+    let mut html = r#"<table class="chart-data">"#.to_string();
+    if let Some(caption) = header_field(header, "title").or_else(|| header_field(header, "alt")) {
+        html += "<caption>";
+        escape_html(&mut html, &caption).unwrap();
+        html += "</caption>";
+    }
+    html += "<thead><tr>";
+    for cell in head {
+        html += &format!("<th>{}</th>", cell);
+    }
+    html += "</tr></thead><tbody>";
+    for row in rows() {
+        html += "<tr>";
+        for cell in row {
+            html += "<td>";
+            escape_html(&mut html, &cell).unwrap();
+            html += "</td>";
+        }
+        html += "</tr>";
+    }
+    html += "</tbody></table>\n";
+    Some(html)
+}
+
+/// FNV-1a, used instead of `DefaultHasher` where the hash ends up in the
+/// output, as it's the same on every platform and Rust version.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Prefix the ids in an svg, and the references to them, with a hash of the
+/// svg and `index`, the position of its block in the document. Graph
+/// layouts use fixed ids like `arrow0`, that would collide when there are
+/// multiple svgs in a document, while a prefix based on the content and
+/// the position keeps the output the same between runs, and still differs
+/// for identical svgs.
+pub fn scope_svg_ids(svg: &str, index: usize) -> String {
+    let ids: Vec<&str> = svg
+        .match_indices(r#" id=""#)
+        .filter_map(|(i, m)| {
+            let start = i + m.len();
+            svg[start..].find('"').map(|end| &svg[start..start + end])
+        })
+        .collect();
+    if ids.is_empty() {
+        return svg.to_string();
+    }
+    let hash = stable_hash(svg.as_bytes()) ^ stable_hash(&(index as u64).to_le_bytes());
+    let prefix = format!("s{:x}", hash as u32);
+    let mut output = svg.to_string();
+    for id in ids {
+        for (from, to) in [
+            (format!(r#" id="{id}""#), format!(r#" id="{prefix}-{id}""#)),
+            (format!("url(#{id})"), format!("url(#{prefix}-{id})")),
+            (
+                format!(r##"href="#{id}""##),
+                format!(r##"href="#{prefix}-{id}""##),
+            ),
+        ] {
+            output = output.replace(&from, &to);
+        }
+    }
+    output
+}
+
+/// Events of a table with `head` as the heading row.
+pub fn build_table<'a>(head: Vec<CowStr<'a>>, rows: Vec<Vec<CowStr<'a>>>) -> Vec<Event<'a>> {
+    let alignment = || {
+        head.iter()
+            .map(|_| pulldown_cmark::Alignment::None)
+            .collect()
+    };
+    let mut events = Vec::with_capacity((head.len() + 2) * (rows.len() * 3 + 4));
+    events.push(Event::Start(Tag::Table(alignment())));
+    events.push(Event::Start(Tag::TableHead));
+    for cell in head.iter() {
+        events.push(Event::Start(Tag::TableCell));
+        events.push(Event::Text(cell.clone()));
+        events.push(Event::End(Tag::TableCell));
+    }
+    events.push(Event::End(Tag::TableHead));
+    for row in rows {
+        events.push(Event::Start(Tag::TableRow));
+        for cell in row {
+            events.push(Event::Start(Tag::TableCell));
+            events.push(Event::Text(cell));
+            events.push(Event::End(Tag::TableCell));
+        }
+        events.push(Event::End(Tag::TableRow));
+    }
+    events.push(Event::End(Tag::Table(alignment())));
+    events
+}
+
+/// Layout of a table rendered by a block, from the `stacked`, `max_rows`,
+/// `page_size` and `interactive` fields of its header.
+///
+/// With `stacked: true`, the rows are stacked as cards on narrow screens,
+/// with the heading of each column before its cell. With `max_rows`, only
+/// that many rows are shown, until "show all" is clicked, which works
+/// without scripts. With `page_size`, the rows are split into pages with
+/// links between them, which also work without scripts, and `max_rows` is
+/// ignored. With `interactive: true`, the rows can be sorted by clicking
+/// the heading of a column, and filtered with a text field, with
+/// `TABLE_SCRIPT`, which also pages through the sorted and filtered rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableLayout {
+    pub stacked: bool,
+    pub max_rows: Option<usize>,
+    pub page_size: Option<usize>,
+    pub interactive: bool,
+}
+
+/// Script making the tables with `data-interactive` sortable and
+/// filterable, see `TableLayout`. Numbers are sorted by the value in
+/// `data-value` of the cells, when it differs from the formatted text.
+pub const TABLE_SCRIPT: &str = r#"<script>
+(function() {
+  const collator = new Intl.Collator(document.documentElement.lang || undefined, { numeric: true });
+  const value = (row, i) => {
+    const cell = row.cells[i];
+    return cell ? cell.dataset.value ?? cell.textContent.trim() : "";
+  };
+  const compare = (a, b) => {
+    const [x, y] = [Number(a), Number(b)];
+    return a !== "" && b !== "" && isFinite(x) && isFinite(y) ? x - y : collator.compare(a, b);
+  };
+  const init = () => document.querySelectorAll("table[data-interactive]").forEach(table => {
+    if (table.dataset.interactive == "ready") {
+      return;
+    }
+    table.dataset.interactive = "ready";
+    const body = table.tBodies[0];
+    const maxRows = Number(table.dataset.maxRows) || Infinity;
+    const pageSize = Number(table.dataset.pageSize) || 0;
+    const scroll = table.closest("div.table-scroll");
+    let terms = [];
+    let page = 0;
+    // The pages are joined, and the links between them replaced with buttons
+    for (const other of Array.from(table.tBodies).slice(1)) {
+      body.append(...other.rows);
+      other.remove();
+    }
+    table.classList.remove("paged");
+    const nav = pageSize ? scroll.nextElementSibling : null;
+    const status = document.createElement("span");
+    const button = (text, step) => {
+      const button = document.createElement("button");
+      button.type = "button";
+      button.textContent = text;
+      button.addEventListener("click", () => {
+        page += step;
+        update();
+      });
+      return button;
+    };
+    const previous = button(nav && nav.dataset.previous, -1);
+    const next = button(nav && nav.dataset.next, 1);
+    if (nav) {
+      nav.replaceChildren(previous, status, next);
+    }
+    const update = () => {
+      const matching = Array.from(body.rows).filter(row => {
+        const text = row.textContent.toLowerCase();
+        return terms.every(t => text.includes(t));
+      });
+      const pages = pageSize ? Math.max(1, Math.ceil(matching.length / pageSize)) : 1;
+      page = Math.max(0, Math.min(page, pages - 1));
+      const shown = new Set(
+        pageSize ? matching.slice(page * pageSize, (page + 1) * pageSize) : matching
+      );
+      for (const row of body.rows) {
+        row.hidden = !shown.has(row);
+      }
+      status.textContent = (page + 1) + " / " + pages;
+      previous.disabled = page == 0;
+      next.disabled = page == pages - 1;
+    };
+    const input = document.createElement("input");
+    input.type = "search";
+    input.className = "table-filter";
+    input.placeholder = table.dataset.filter;
+    input.setAttribute("aria-label", table.dataset.filter);
+    (table.closest("div.table-rows") || scroll).before(input);
+    input.addEventListener("input", () => {
+      terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+      table.classList.toggle("filtered", terms.length > 0);
+      page = 0;
+      update();
+    });
+    Array.from(table.tHead.rows[0].cells).forEach((th, i) => {
+      const button = document.createElement("button");
+      button.type = "button";
+      button.append(...th.childNodes);
+      th.append(button);
+      button.addEventListener("click", () => {
+        const ascending = th.getAttribute("aria-sort") != "ascending";
+        for (const other of th.parentElement.cells) {
+          other.removeAttribute("aria-sort");
+        }
+        th.setAttribute("aria-sort", ascending ? "ascending" : "descending");
+        Array.from(body.rows)
+          .sort((a, b) => compare(value(a, i), value(b, i)) * (ascending ? 1 : -1))
+          .forEach((row, n) => {
+            row.classList.toggle("more", n >= maxRows);
+            body.append(row);
+          });
+        page = 0;
+        update();
+      });
+    });
+    update();
+  });
+  if (document.readyState == "loading") {
+    document.addEventListener("DOMContentLoaded", init);
+  } else {
+    init();
+  }
+})();
+</script>
+"#;
+
+impl TableLayout {
+    /// Layout from the header of a block, where `interactive` is used
+    /// unless the header sets it.
+    pub fn from_header(header: &CustomBlockHeader, interactive: bool) -> Self {
+        let count = |name: &str| {
+            header
+                .fields
+                .get(name)
+                .and_then(serde_yaml::Value::as_u64)
+                .map(|count| count as usize)
+        };
+        TableLayout {
+            stacked: header
+                .fields
+                .get("stacked")
+                .and_then(serde_yaml::Value::as_bool)
+                .unwrap_or(false),
+            max_rows: count("max_rows"),
+            page_size: count("page_size").filter(|size| *size > 0),
+            interactive: header
+                .fields
+                .get("interactive")
+                .and_then(serde_yaml::Value::as_bool)
+                .unwrap_or(interactive),
+        }
+    }
+
+    /// Html of a table with the layout, or `None` if it is the default
+    /// layout, which is rendered from the events of `build_table`.
+    /// `values` are the cells of `rows` before they were formatted, which
+    /// interactive tables are sorted by.
+    pub fn html(
+        &self,
+        head: &[CowStr],
+        rows: &[Vec<CowStr>],
+        values: &[Vec<CowStr>],
+        labels: &Labels,
+    ) -> Option<String> {
+        if *self == TableLayout::default() {
+            return None;
+        }
+        let escaped = |text: &str| {
+            let mut escaped = String::new();
+            escape_html(&mut escaped, text).unwrap();
+            escaped
+        };
+        let page_size = self.page_size.filter(|size| rows.len() > *size);
+        let max_rows = match page_size {
+            Some(_) => None,
+            None => self.max_rows,
+        };
+        let mut classes = Vec::new();
+        if self.stacked {
+            classes.push("stacked");
+        }
+        if page_size.is_some() {
+            classes.push("paged");
+        }
+        let mut table = match classes.is_empty() {
+            true => "<table".to_string(),
+            false => format!(r#"<table class="{}""#, classes.join(" ")),
+        };
+        if self.interactive {
+            table += &format!(
+                r#" data-interactive data-filter="{}""#,
+                escaped(&labels.filter_rows)
+            );
+            if let Some(max_rows) = max_rows {
+                table += &format!(r#" data-max-rows="{}""#, max_rows);
+            }
+            if let Some(page_size) = page_size {
+                table += &format!(r#" data-page-size="{}""#, page_size);
+            }
+        }
+        table += "><thead><tr>";
+        for cell in head {
+            table += &format!("<th>{}</th>", escaped(cell));
+        }
+        table += "</tr></thead>";
+        let id = format!(
+            "page-{:x}",
+            stable_hash(format!("{:?}{:?}", head, values).as_bytes()) as u32
+        );
+        let pages = page_size.map_or(1, |size| rows.len().div_ceil(size));
+        for (i, (row, values)) in rows.iter().zip(values).enumerate() {
+            match page_size {
+                Some(page_size) if i % page_size == 0 => {
+                    if i > 0 {
+                        table += "</tbody>";
+                    }
+                    table += &format!(r#"<tbody id="{}-{}">"#, id, i / page_size + 1);
+                }
+                None if i == 0 => table += "<tbody>",
+                _ => {}
+            }
+            match max_rows.is_some_and(|max_rows| i >= max_rows) {
+                true => table += r#"<tr class="more">"#,
+                false => table += "<tr>",
+            }
+            for ((cell, value), label) in row.iter().zip(values).zip(head) {
+                table += "<td";
+                if self.stacked {
+                    table += &format!(r#" data-label="{}""#, escaped(label));
+                }
+                if self.interactive && cell != value {
+                    table += &format!(r#" data-value="{}""#, escaped(value));
+                }
+                table += ">";
+                table += &escaped(cell);
+                table += "</td>";
+            }
+            table += "</tr>";
+        }
+        if rows.is_empty() {
+            table += "<tbody>";
+        }
+        table += "</tbody></table>";
+        let mut table = format!("<div class=\"table-scroll\">{}</div>\n", table);
+        if page_size.is_some() {
+            table += &format!(
+                r#"<nav class="table-pages" aria-label="{}" data-previous="{}" data-next="{}">"#,
+                escaped(&labels.pages[0]),
+                escaped(&labels.pages[1]),
+                escaped(&labels.pages[2]),
+            );
+            for page in 1..=pages {
+                table += &format!(r##"<a href="#{}-{}">{}</a>"##, id, page, page);
+            }
+            table += "</nav>\n";
+        }
+        match max_rows.filter(|max_rows| rows.len() > *max_rows) {
+            Some(_) => {
+                let id = format!("rows-{:x}", stable_hash(table.as_bytes()) as u32);
+                Some(
+                    format!(
+                        r#"<div class="table-rows"><input type="checkbox" id="{0}" class="show-all">{1}<label for="{0}" class="show-all">{2}</label></div>"#,
+                        id,
+                        table,
+                        escaped(&labels.show_all.replace("{}", &rows.len().to_string())),
+                    ) + "\n",
+                )
+            }
+            None => Some(table),
+        }
+    }
+}
+
+/// A string or number field of a custom block header, as a string.
+pub fn header_field(header: &CustomBlockHeader, name: &str) -> Option<String> {
+    match header.fields.get(name)? {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// `path` with `.` and `..` components resolved, without reading the file
+/// system, so paths to the same file written differently can be compared.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Resolve a file `path` of a block relative to `base_dir`, or the current
+/// directory. Files outside of the directory, like absolute paths or paths
+/// with `..`s that leave it, aren't read, as documents can be rendered from
+/// untrusted sources, like by the `serve` api.
+pub fn resolve_file(base_dir: Option<&Path>, path: &str) -> crate::Result<PathBuf> {
+    let root = match base_dir {
+        Some(base_dir) if !base_dir.as_os_str().is_empty() => base_dir,
+        _ => Path::new("."),
+    };
+    let (Ok(root), Ok(file)) = (root.canonicalize(), root.join(path).canonicalize()) else {
+        return Err(crate::Error::MissingFile(path.into()));
+    };
+    if !file.starts_with(&root) {
+        return Err(crate::Error::CustomBlockRead(format!(
+            "`{}` is outside of the document directory",
+            path
+        )));
+    }
+    match file.is_file() {
+        true => Ok(file),
+        false => Err(crate::Error::MissingFile(path.into())),
+    }
+}
+
+/// The fields of a custom block header that are local files, that is
+/// relative urls, without their query and fragment.
+pub fn local_files(header: &CustomBlockHeader, fields: &[&str]) -> Vec<String> {
+    fields
+        .iter()
+        .filter_map(|name| header_field(header, name))
+        .filter(|url| crate::is_relative_url(url))
+        .map(|url| url.split(['?', '#']).next().unwrap_or_default().to_string())
+        .collect()
+}
+
+/// Validate a length for a css property, like `50%` or `20em`. Plain
+/// numbers are pixels.
+pub fn css_length(length: &str) -> Option<String> {
+    let length = length.trim();
+    let number_end = length
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(length.len());
+    let (number, unit) = length.split_at(number_end);
+    number.parse::<f64>().ok()?;
+    match unit {
+        "" => Some(format!("{}px", number)),
+        "%" | "px" | "em" | "rem" | "vw" | "vh" | "ch" => Some(length.to_string()),
+        _ => None,
+    }
+}
+
+/// Follow a local `$ref` in a JSON Schema or OpenAPI spec, like
+/// `#/components/parameters/limit`. Other values are returned as they are.
+pub fn resolve_ref<'a>(
+    root: &'a serde_yaml::Value,
+    value: &'a serde_yaml::Value,
+) -> &'a serde_yaml::Value {
+    let Some(reference) = value.get("$ref").and_then(serde_yaml::Value::as_str) else {
+        return value;
+    };
+    reference
+        .strip_prefix("#/")
+        .and_then(|pointer| {
+            pointer.split('/').try_fold(root, |value, segment| {
+                value.get(segment.replace("~1", "/").replace("~0", "~"))
+            })
+        })
+        .unwrap_or(value)
+}
+
 pub fn dynamic_as_f64(v: &Dynamic) -> Option<f64> {
     v.as_float()
         .ok()
         .or_else(|| v.as_int().map(|v| v as f64).ok())
 }
 
+/// Convert a yaml value, like a field of a meta block, to a script value.
+pub fn yaml_to_dynamic(value: &serde_yaml::Value) -> Dynamic {
+    match value {
+        serde_yaml::Value::Null => Dynamic::UNIT,
+        serde_yaml::Value::Bool(b) => (*b).into(),
+        serde_yaml::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64().unwrap_or_default().into(),
+        },
+        serde_yaml::Value::String(s) => s.clone().into(),
+        serde_yaml::Value::Sequence(values) => values
+            .iter()
+            .map(yaml_to_dynamic)
+            .collect::<Vec<_>>()
+            .into(),
+        serde_yaml::Value::Mapping(mapping) => mapping
+            .iter()
+            .filter_map(|(key, value)| Some((key.as_str()?.into(), yaml_to_dynamic(value))))
+            .collect::<rhai::Map>()
+            .into(),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_dynamic(&tagged.value),
+    }
+}
+
+pub fn json_to_dynamic(value: &serde_json::Value) -> Dynamic {
+    match value {
+        serde_json::Value::Null => Dynamic::UNIT,
+        serde_json::Value::Bool(b) => (*b).into(),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64().unwrap_or_default().into(),
+        },
+        serde_json::Value::String(s) => s.clone().into(),
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(json_to_dynamic)
+            .collect::<Vec<_>>()
+            .into(),
+        serde_json::Value::Object(object) => object
+            .iter()
+            .map(|(key, value)| (key.into(), json_to_dynamic(value)))
+            .collect::<rhai::Map>()
+            .into(),
+    }
+}
+
+/// A script value as json. Values without a json equivalent, like
+/// timestamps, are written as strings.
+pub fn dynamic_to_json(value: &Dynamic) -> serde_json::Value {
+    if value.is_unit() {
+        serde_json::Value::Null
+    } else if let Ok(b) = value.as_bool() {
+        b.into()
+    } else if let Ok(i) = value.as_int() {
+        i.into()
+    } else if let Ok(f) = value.as_float() {
+        serde_json::Number::from_f64(f).map_or(serde_json::Value::Null, Into::into)
+    } else if let Some(values) = value.read_lock::<rhai::Array>() {
+        values.iter().map(dynamic_to_json).collect()
+    } else if let Some(map) = value.read_lock::<rhai::Map>() {
+        map.iter()
+            .map(|(key, value)| (key.to_string(), dynamic_to_json(value)))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    } else {
+        value.to_string().into()
+    }
+}
+
+/// Format a unix timestamp as a `YYYY-MM-DD` date, in UTC.
+pub fn date(timestamp: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = timestamp.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format a unix timestamp as a `YYYY-MM-DD` date, or as an RFC 3339
+/// timestamp in UTC if it isn't midnight, like `2024-03-01T12:30:00Z`.
+pub fn datetime(timestamp: i64) -> String {
+    let seconds = timestamp.rem_euclid(86400);
+    match seconds {
+        0 => date(timestamp),
+        _ => format!(
+            "{}T{:02}:{:02}:{:02}Z",
+            date(timestamp),
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        ),
+    }
+}
+
+/// The unix timestamp of an ISO date, like `2024-03-01`, or of an RFC 3339
+/// timestamp, like `2024-03-01T12:30:00+01:00`. Fractions of a second are
+/// left out, and times without a time zone are in UTC.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = text.get(range)?;
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let separators = [(4, b'-'), (7, b'-')];
+    if separators.iter().any(|(i, c)| text.as_bytes()[*i] != *c)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let rest = &text[10..];
+    if rest.is_empty() {
+        return Some(days * 86400);
+    }
+    if !rest.starts_with(['T', ' ']) || rest.as_bytes().get(3) != Some(&b':') {
+        return None;
+    }
+    let time = |i: usize| number(10 + i..12 + i);
+    let (hours, minutes) = (time(1)?, time(4)?);
+    let (seconds, mut zone) = match rest.as_bytes().get(6) {
+        Some(b':') => (time(7)?, &rest[9..]),
+        _ => (0, &rest[6..]),
+    };
+    if zone.starts_with(['.', ',']) {
+        zone = zone[1..].trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    let offset = match zone.as_bytes() {
+        [] | [b'Z'] => 0,
+        [sign @ (b'+' | b'-'), ..] => {
+            let zone = zone[1..].replace(':', "");
+            let (h, m) = (zone.get(..2)?.parse::<i64>().ok()?, zone.get(2..)?);
+            let m = if m.is_empty() {
+                0
+            } else {
+                m.parse::<i64>().ok()?
+            };
+            let offset = h * 3600 + m * 60;
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+/// Text with xml entities, like `&amp;`, replaced, or the text as it is if
+/// it has references that aren't xml entities.
+pub fn xml_unescape(text: &str) -> String {
+    match quick_xml::escape::unescape(text) {
+        Ok(text) => text.into_owned(),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// Value of the attribute `name` of an xml element, or of a html tag, which
+/// may have attributes without values.
+pub fn xml_attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let attribute = element
+        .html_attributes()
+        .flatten()
+        .find(|attribute| attribute.key.0 == name)?;
+    match attribute.normalized_value(XmlVersion::Implicit1_0) {
+        Ok(value) => Some(value.into_owned()),
+        Err(_) => Some(xml_unescape(&attribute.value)),
+    }
+}
+
 #[cfg(test)]
 pub fn custom_block_downcast<T: crate::CustomBlock + Clone + 'static>(
     block: Box<dyn crate::CustomBlock>,
 ) -> Option<T> {
     block.as_any().downcast_ref::<T>().cloned()
 }
+
+/// A document with a single custom block, with `header` and `input`.
+#[cfg(test)]
+pub fn custom_block(header: &str, input: &str) -> String {
+    match input.is_empty() || input.ends_with('\n') {
+        true => format!("```{}\n{}```\n", header, input),
+        false => format!("```{}\n{}\n```\n", header, input),
+    }
+}
+
+/// The html of the content of a document, or the first error reported
+/// while rendering it.
+#[cfg(test)]
+pub fn render_html(options: &crate::YamdrOptions, markdown: &str) -> crate::Result<String> {
+    let format = crate::Format::Html;
+    let (parsed, mut diagnostics) = crate::parse_markdown_with_diagnostics(options, markdown);
+    if !diagnostics.errors.is_empty() {
+        return Err(diagnostics.errors.remove(0));
+    }
+    let events = parsed
+        .iter()
+        .flat_map(|ee| format.transform_extended_event(ee));
+    Ok(format.render(events, 0))
+}
@@ -0,0 +1,224 @@
+use crate::{
+    is_relative_url,
+    utils::{css_length, header_field, local_files},
+    AssetUrls, CustomBlock, CustomBlockHeader, CustomBlockReader, Error, FieldType, Format,
+    HeaderField, Result, YamdrOptions,
+};
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, CowStr, Event, Tag};
+use std::path::PathBuf;
+
+/// A video, like a screencast, with an optional poster image and subtitles.
+///
+/// ````text
+/// ```{t: Video, src: demo.mp4, poster: demo.png, subtitles: demo.vtt, width: 80%}
+/// ```
+/// ````
+///
+/// The language of the subtitles is `subtitles_lang`, or the language of
+/// the document. If `YamdrOptions::base_dir` is set, the referenced files
+/// must exist.
+#[derive(Debug, Clone)]
+pub struct VideoBlock {
+    header: CustomBlockHeader,
+    input: String,
+    src: String,
+    poster: Option<String>,
+    subtitles: Option<(String, String)>,
+    width: Option<String>,
+}
+
+pub struct VideoBlockReader {
+    assets: AssetUrls,
+    base_dir: Option<PathBuf>,
+    lang: String,
+}
+
+impl VideoBlockReader {
+    pub fn new(options: &YamdrOptions) -> Self {
+        VideoBlockReader {
+            assets: AssetUrls::new(options),
+            base_dir: options.base_dir.clone(),
+            lang: options.locale.lang.clone(),
+        }
+    }
+
+    /// Check that a file referenced by the block exists, and return its url.
+    fn url(&self, url: String) -> Result<String> {
+        if let Some(base_dir) = &self.base_dir {
+            let path = url.split(['?', '#']).next().unwrap_or_default();
+            if is_relative_url(&url) && !base_dir.join(path).is_file() {
+                return Err(Error::MissingFile(url));
+            }
+        }
+        Ok(self.assets.rewrite(&url).unwrap_or(url))
+    }
+}
+
+impl CustomBlockReader for VideoBlockReader {
+    fn can_read_block(&self, header: &CustomBlockHeader) -> bool {
+        header.t == "Video"
+    }
+
+    fn block_types(&self) -> &'static [&'static str] {
+        &["Video"]
+    }
+
+    fn reads_empty_block(&self, _header: &CustomBlockHeader) -> bool {
+        true
+    }
+
+    fn header_schema(&self, _header: &CustomBlockHeader) -> Option<&'static [HeaderField]> {
+        const SCHEMA: &[HeaderField] = &[
+            HeaderField::new("src", FieldType::String),
+            HeaderField::new("poster", FieldType::String),
+            HeaderField::new("subtitles", FieldType::String),
+            HeaderField::new("subtitles_lang", FieldType::String),
+            HeaderField::new("width", FieldType::Scalar),
+        ];
+        Some(SCHEMA)
+    }
+
+    fn read_block(
+        &mut self,
+        header: &CustomBlockHeader,
+        input: &str,
+    ) -> Result<Option<Box<dyn CustomBlock>>> {
+        let src = header_field(header, "src")
+            .ok_or_else(|| Error::CustomBlockRead("missing `src` in Video block".into()))?;
+        let width = match header_field(header, "width") {
+            Some(width) => Some(css_length(&width).ok_or_else(|| {
+                Error::CustomBlockRead(format!("invalid width `{}` in Video block", width))
+            })?),
+            None => None,
+        };
+        let subtitles = match header_field(header, "subtitles") {
+            Some(subtitles) => Some((
+                self.url(subtitles)?,
+                header_field(header, "subtitles_lang").unwrap_or_else(|| self.lang.clone()),
+            )),
+            None => None,
+        };
+        Ok(Some(Box::new(VideoBlock {
+            header: header.clone(),
+            input: input.into(),
+            src: self.url(src)?,
+            poster: header_field(header, "poster")
+                .map(|poster| self.url(poster))
+                .transpose()?,
+            subtitles,
+            width,
+        })))
+    }
+
+    fn files(&self, header: &CustomBlockHeader) -> Vec<String> {
+        local_files(header, &["src", "poster", "subtitles"])
+    }
+}
+
+fn escaped(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).unwrap();
+    escaped
+}
+
+impl CustomBlock for VideoBlock {
+    fn to_events(&self, format: Format) -> Vec<Event<'_>> {
+        match format {
+            Format::Html => {
+                let mut html = String::from(r#"<video class="video" controls preload="metadata""#);
+                if let Some(width) = &self.width {
+                    html += &format!(r#" style="width: {}""#, width);
+                }
+                if let Some(poster) = &self.poster {
+                    html += &format!(r#" poster="{}""#, escaped(poster));
+                }
+                html += &format!(r#"><source src="{}" />"#, escaped(&self.src));
+                if let Some((subtitles, lang)) = &self.subtitles {
+                    html += &format!(
+                        r#"<track kind="subtitles" src="{}" srclang="{}" label="{1}" default />"#,
+                        escaped(subtitles),
+                        escaped(lang)
+                    );
+                }
+                html += &format!(r#"<a href="{0}">{0}</a></video>"#, escaped(&self.src));
+                html += "\n";
+                vec![Event::Html(html.into())]
+            }
+            Format::Md => {
+                let props: CowStr = serde_json::to_string(&self.header).unwrap().into();
+                vec![
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(props.clone()))),
+                    Event::Text(self.input.as_str().into()),
+                    Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(props))),
+                ]
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_markdown;
+    use crate::utils::{custom_block, render_html};
+
+    fn html(options: &YamdrOptions, header: &str) -> Result<String> {
+        render_html(options, &custom_block(header, ""))
+    }
+
+    #[test]
+    fn video() {
+        let options = YamdrOptions::default();
+        assert_eq!(
+            html(
+                &options,
+                "{t: Video, src: demo.mp4, poster: demo.png, subtitles: demo.vtt, width: 80%}"
+            )
+            .unwrap(),
+            r#"<video class="video" controls preload="metadata" style="width: 80%" poster="demo.png"><source src="demo.mp4" /><track kind="subtitles" src="demo.vtt" srclang="en" label="en" default /><a href="demo.mp4">demo.mp4</a></video>
+"#
+        );
+        assert!(html(&options, "{t: Video, poster: demo.png}").is_err());
+    }
+
+    #[test]
+    fn missing_files() {
+        let options = YamdrOptions {
+            base_dir: Some(PathBuf::from(env!("CARGO_MANIFEST_DIR"))),
+            ..Default::default()
+        };
+        assert!(html(&options, "{t: Video, src: Cargo.toml}").is_ok());
+        assert!(html(&options, "{t: Video, src: https://example.com/a.mp4}").is_ok());
+        assert!(matches!(
+            html(&options, "{t: Video, src: Cargo.toml, subtitles: missing.vtt}"),
+            Err(Error::MissingFile(file)) if file == "missing.vtt"
+        ));
+    }
+
+    #[test]
+    fn video_blocks() {
+        let document = r#"
+```{t: Video, src: Cargo.toml, width: 80%}
+```
+
+```{t: Video, src: missing.mp4}
+```
+"#;
+        let options = YamdrOptions {
+            base_dir: Some(env!("CARGO_MANIFEST_DIR").into()),
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains(r#"<source src="Cargo.toml" />"#));
+        assert!(!html.contains("missing.mp4"));
+        assert!(matches!(
+            meta.errors.as_slice(),
+            [Error::MissingFile(file)] if file == "missing.mp4"
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+use pulldown_cmark::{escape::escape_html, CowStr, Event, LinkType, Tag};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+type Resolve = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Resolves the target of a wiki link, like `Page Name` in `[[Page Name]]`,
+/// to an url, see `YamdrOptions::wiki_links`.
+#[derive(Clone)]
+pub struct WikiLinks {
+    resolve: Arc<Resolve>,
+}
+
+impl WikiLinks {
+    /// Links resolved with `resolve`, which returns `None` for targets that
+    /// don't exist.
+    pub fn new(resolve: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        WikiLinks {
+            resolve: Arc::new(resolve),
+        }
+    }
+}
+
+impl Default for WikiLinks {
+    /// Links to `Page Name.html` next to the document, or to the target as
+    /// it is if it has an extension, like `diagram.png`.
+    fn default() -> Self {
+        WikiLinks::new(|target| {
+            let target = target.replace(' ', "%20");
+            match target
+                .rsplit_once('/')
+                .unwrap_or(("", &target))
+                .1
+                .contains('.')
+            {
+                true => Some(target),
+                false => Some(target + ".html"),
+            }
+        })
+    }
+}
+
+fn is_image(target: &str) -> bool {
+    let extension = target.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    matches!(
+        extension.as_deref(),
+        Some("png" | "jpg" | "jpeg" | "gif" | "svg" | "webp")
+    )
+}
+
+/// Events of the wiki links in `text`, and of the text around them. Targets
+/// that can't be resolved are added to `missing`.
+fn split_links(
+    text: &str,
+    links: &WikiLinks,
+    missing: &mut Vec<String>,
+) -> Option<Vec<Event<'static>>> {
+    let mut events = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start + 2..].find("]]").map(|end| start + 2 + end) else {
+            break;
+        };
+        let inner = &rest[start + 2..end];
+        if inner.is_empty() || inner.contains(['[', '\n']) {
+            events.push(Event::Text(rest[..start + 2].to_string().into()));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let embed = rest[..start].ends_with('!');
+        let before = &rest[..start - embed as usize];
+        if !before.is_empty() {
+            events.push(Event::Text(before.to_string().into()));
+        }
+        rest = &rest[end + 2..];
+
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target.trim(), Some(label.trim())),
+            None => (inner.trim(), None),
+        };
+        let (page, heading) = match target.split_once('#') {
+            Some((page, heading)) => (page.trim(), Some(heading.trim())),
+            None => (target, None),
+        };
+        let label = match (label, heading) {
+            (Some(label), _) => label.to_string(),
+            (None, Some(heading)) if page.is_empty() => heading.to_string(),
+            (None, Some(heading)) => format!("{} > {}", page, heading),
+            (None, None) => page.to_string(),
+        };
+        let url = match page {
+            "" => Some(String::new()),
+            page => (links.resolve)(page),
+        };
+        let Some(mut url) = url else {
+            missing.push(page.to_string());
+            let mut html = String::from(r#"<span class="wiki-link-missing">"#);
+            escape_html(&mut html, &label).unwrap();
+            html += "</span>";
+            events.push(Event::Html(html.into()));
+            continue;
+        };
+        if let Some(heading) = heading {
+            url += "#";
+            url += &heading.replace(' ', "%20");
+        }
+        let tag = match embed && is_image(page) {
+            true => Tag::Image(LinkType::Inline, url.into(), CowStr::Borrowed("")),
+            false => Tag::Link(LinkType::Inline, url.into(), CowStr::Borrowed("")),
+        };
+        events.push(Event::Start(tag.clone()));
+        events.push(Event::Text(label.into()));
+        events.push(Event::End(tag));
+    }
+    if events.is_empty() {
+        return None;
+    }
+    if !rest.is_empty() {
+        events.push(Event::Text(rest.to_string().into()));
+    }
+    Some(events)
+}
+
+/// Replaces `[[Page Name]]`, `[[Page Name#Heading|label]]` and
+/// `![[image.png]]` in text with links and images, with
+/// `YamdrOptions::wiki_links`. Embeds of other files than images are
+/// rendered as links. Text in code is left as it is.
+///
+/// The parser splits text at brackets, so consecutive text events are
+/// joined before looking for links.
+pub(crate) struct WikiLinkEvents<'a, I> {
+    events: I,
+    links: Option<WikiLinks>,
+    queue: VecDeque<Event<'a>>,
+    in_code_block: bool,
+    /// Targets of links that couldn't be resolved.
+    pub missing: Vec<String>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> WikiLinkEvents<'a, I> {
+    pub fn new(events: I, links: Option<WikiLinks>) -> Self {
+        WikiLinkEvents {
+            events,
+            links,
+            queue: VecDeque::new(),
+            in_code_block: false,
+            missing: Vec::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &I {
+        &self.events
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for WikiLinkEvents<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.queue.pop_front() {
+            return Some(event);
+        }
+        let event = self.events.next()?;
+        let Some(links) = &self.links else {
+            return Some(event);
+        };
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            _ => {}
+        }
+        let Event::Text(first) = event else {
+            return Some(event);
+        };
+        if self.in_code_block {
+            return Some(Event::Text(first));
+        }
+        let mut texts = vec![first];
+        let mut next = None;
+        for event in self.events.by_ref() {
+            match event {
+                Event::Text(text) => texts.push(text),
+                event => {
+                    next = Some(event);
+                    break;
+                }
+            }
+        }
+        let text: String = texts.iter().map(|text| text.as_ref()).collect();
+        match split_links(&text, links, &mut self.missing) {
+            Some(events) => self.queue.extend(events),
+            None => self.queue.extend(texts.into_iter().map(Event::Text)),
+        }
+        if let Some(next) = next {
+            if matches!(next, Event::Start(Tag::CodeBlock(_))) {
+                self.in_code_block = true;
+            }
+            self.queue.push_back(next);
+        }
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_markdown, Error, Format, YamdrOptions};
+    use pulldown_cmark::{Options, Parser};
+
+    fn render(markdown: &str, links: WikiLinks) -> (String, Vec<String>) {
+        let mut events =
+            WikiLinkEvents::new(Parser::new_ext(markdown, Options::all()), Some(links));
+        let html = crate::html::render(events.by_ref(), 0);
+        (html, events.missing)
+    }
+
+    #[test]
+    fn links() {
+        let (html, missing) = render(
+            "See [[Page Name]], [[Other#Setup|setup]] and ![[img/a.png]] [[x",
+            WikiLinks::default(),
+        );
+        assert_eq!(
+            html,
+            "<p>See <a href=\"Page%20Name.html\">Page Name</a>, <a href=\"Other.html#Setup\">setup</a> and <img src=\"img/a.png\" alt=\"img/a.png\" /> [[x</p>\n"
+        );
+        assert!(missing.is_empty());
+
+        let links = WikiLinks::new(|target| (target == "Known").then(|| "/known".to_string()));
+        let (html, missing) = render("[[Known]] [[Unknown]]\n\n```\n[[Known]]\n```\n", links);
+        assert!(html.contains(
+            r#"<a href="/known">Known</a> <span class="wiki-link-missing">Unknown</span>"#
+        ));
+        assert!(html.contains("<code>[[Known]]\n</code>"));
+        assert_eq!(missing, vec!["Unknown"]);
+    }
+
+    #[test]
+    fn wiki_links() {
+        let document = "See [[Setup#Install|installing]] and [[Missing Page]].\n";
+        let options = YamdrOptions {
+            wiki_links: Some(WikiLinks::new(|target| {
+                (target == "Setup").then(|| "docs/setup.html".to_string())
+            })),
+            ..Default::default()
+        };
+        let (meta, html) = render_markdown(&options, document);
+        assert!(html.contains(
+            r#"<p>See <a href="docs/setup.html#Install">installing</a> and <span class="wiki-link-missing">Missing Page</span>.</p>"#
+        ));
+        assert!(matches!(
+            &meta.errors[..],
+            [Error::MissingFile(target)] if target == "Missing Page"
+        ));
+
+        let options = YamdrOptions {
+            format: Some(Format::Md),
+            ..options
+        };
+        let (meta, md) = render_markdown(&options, document);
+        assert!(meta.errors.is_empty());
+        assert!(md.contains(r"\[\[Missing Page\]\]"));
+    }
+}
@@ -0,0 +1,36 @@
+//! Round trip the examples of the CommonMark spec through markdown to
+//! markdown rendering in strict mode, and check that the output means the
+//! same as the input, and is stable when rendered again.
+//!
+//! `commonmark_spec.json` is the list of examples of version 0.30 of the
+//! spec, from https://spec.commonmark.org/0.30/spec.json.
+
+use md::{render_markdown, Format, YamdrOptions};
+use pulldown_cmark::{html, Options, Parser};
+
+fn to_html(markdown: &str) -> String {
+    let mut output = String::new();
+    html::push_html(&mut output, Parser::new_ext(markdown, Options::empty()));
+    output
+}
+
+#[test]
+fn commonmark_spec_round_trip() {
+    let examples: Vec<serde_json::Value> =
+        serde_json::from_str(include_str!("commonmark_spec.json")).unwrap();
+    let options = YamdrOptions {
+        format: Some(Format::Md),
+        strict: true,
+        ..Default::default()
+    };
+    let mut failed = Vec::new();
+    for example in &examples {
+        let markdown = example["markdown"].as_str().unwrap();
+        let (_, output) = render_markdown(&options, markdown);
+        let (_, rerendered) = render_markdown(&options, &output);
+        if to_html(&output) != to_html(markdown) || rerendered != output {
+            failed.push(example["example"].as_u64().unwrap());
+        }
+    }
+    assert!(failed.is_empty(), "examples {:?} don't round trip", failed);
+}
@@ -0,0 +1,234 @@
+//! Golden file tests for yamdr documents.
+//!
+//! A corpus is a directory of markdown files, where each `name.md` has its
+//! expected output checked in next to it, as `name.html` for html and
+//! `name.expected.md` for markdown. Running the corpus renders every
+//! document and compares the output with the golden files, and blessing it
+//! writes the current output as the new golden files.
+//!
+//! Documents are rendered with `YamdrOptions::deterministic` set, and
+//! without the standalone wrapper, so the golden files only contain the
+//! content of the document.
+use md::{render_markdown, Format, YamdrOptions};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Set this environment variable to bless the corpus in `assert_corpus`.
+pub const BLESS_ENV: &str = "YAMDR_BLESS";
+
+const EXPECTED_MD: &str = "expected.md";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// The golden file was written with the current output.
+    Blessed,
+    /// There are no golden files for the document.
+    Missing,
+    Failed {
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Result of comparing one output format of one document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    /// Path of the document, relative to the corpus directory.
+    pub path: PathBuf,
+    pub format: Format,
+    pub outcome: Outcome,
+}
+
+impl CaseResult {
+    pub fn is_failure(&self) -> bool {
+        matches!(self.outcome, Outcome::Missing | Outcome::Failed { .. })
+    }
+}
+
+impl fmt::Display for CaseResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self.format {
+            Format::Html => "html",
+            Format::Md => "md",
+        };
+        write!(f, "{} ({}): ", self.path.display(), format)?;
+        match &self.outcome {
+            Outcome::Passed => write!(f, "ok"),
+            Outcome::Blessed => write!(f, "blessed"),
+            Outcome::Missing => write!(f, "missing golden files, run with --bless to create them"),
+            Outcome::Failed { expected, actual } => {
+                write!(f, "FAILED\n{}", first_difference(expected, actual))
+            }
+        }
+    }
+}
+
+/// Describe the first line where `expected` and `actual` differ.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (None, None) => return "  outputs differ in line endings".to_string(),
+            (e, a) => {
+                return format!(
+                    "  line {}:\n  - {}\n  + {}",
+                    line,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+}
+
+fn golden_path(document: &Path, format: Format) -> PathBuf {
+    match format {
+        Format::Html => document.with_extension("html"),
+        Format::Md => document.with_extension(EXPECTED_MD),
+    }
+}
+
+/// All markdown documents in `dir`, recursively, relative to `dir`, except
+/// the golden files.
+fn documents(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        let entries = fs::read_dir(dir.join(&relative))
+            .unwrap_or_else(|_| panic!("failed to read directory {}", dir.display()));
+        for entry in entries.flatten() {
+            let path = relative.join(entry.file_name());
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(path),
+                Ok(_) if name.ends_with(".md") && !name.ends_with(&format!(".{}", EXPECTED_MD)) => {
+                    files.push(path)
+                }
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Render every document in the corpus in `dir`, and compare the output
+/// with the golden files. With `bless`, the golden files are written
+/// instead. `options` are used as the base for rendering, so readers can be
+/// configured the same way as in the application being tested.
+pub fn run_corpus(dir: &Path, options: &YamdrOptions, bless: bool) -> Vec<CaseResult> {
+    let mut results = Vec::new();
+    for path in documents(dir) {
+        let document = dir.join(&path);
+        let markdown = fs::read_to_string(&document)
+            .unwrap_or_else(|_| panic!("failed to read file {}", document.display()));
+
+        let goldens: Vec<_> = [Format::Html, Format::Md]
+            .into_iter()
+            .map(|format| (format, golden_path(&document, format)))
+            .filter(|(_, golden)| bless || golden.exists())
+            .collect();
+        if goldens.is_empty() {
+            results.push(CaseResult {
+                path,
+                format: Format::Html,
+                outcome: Outcome::Missing,
+            });
+            continue;
+        }
+
+        for (format, golden) in goldens {
+            let options = YamdrOptions {
+                standalone: None,
+                format: Some(format),
+                deterministic: true,
+                ..options.clone()
+            };
+            let (_, actual) = render_markdown(&options, &markdown);
+            let outcome = if bless {
+                fs::write(&golden, &actual)
+                    .unwrap_or_else(|_| panic!("failed to write {}", golden.display()));
+                Outcome::Blessed
+            } else {
+                let expected = fs::read_to_string(&golden)
+                    .unwrap_or_else(|_| panic!("failed to read file {}", golden.display()));
+                if expected == actual {
+                    Outcome::Passed
+                } else {
+                    Outcome::Failed { expected, actual }
+                }
+            };
+            results.push(CaseResult {
+                path: path.clone(),
+                format,
+                outcome,
+            });
+        }
+    }
+    results
+}
+
+/// Run the corpus in `dir`, and panic with a report if any document
+/// doesn't match its golden files. Meant to be called from a `#[test]`.
+/// Set the `YAMDR_BLESS` environment variable to bless the corpus.
+pub fn assert_corpus(dir: impl AsRef<Path>, options: &YamdrOptions) {
+    let bless = std::env::var_os(BLESS_ENV).is_some();
+    let results = run_corpus(dir.as_ref(), options, bless);
+    let failures: Vec<String> = results
+        .iter()
+        .filter(|result| result.is_failure())
+        .map(ToString::to_string)
+        .collect();
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} golden file tests failed, set {} to bless them:\n{}",
+            failures.len(),
+            results.len(),
+            BLESS_ENV,
+            failures.join("\n")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bless_and_compare() {
+        let dir = std::env::temp_dir().join(format!("yamdr-testing-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.md"), "# Header\n\nText with `_1 + 1_`.\n").unwrap();
+        fs::write(dir.join("sub/b.md"), "Paragraph\n").unwrap();
+        let options = YamdrOptions::default();
+
+        let results = run_corpus(&dir, &options, false);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.outcome == Outcome::Missing));
+
+        let results = run_corpus(&dir, &options, true);
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|r| r.outcome == Outcome::Blessed));
+        assert!(dir.join("a.html").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("a.expected.md")).unwrap(),
+            "# Header\n\nText with `_1 + 1 // > 2_`.\n\n"
+        );
+
+        assert_corpus(&dir, &options);
+
+        fs::write(dir.join("sub/b.md"), "Changed\n").unwrap();
+        let results = run_corpus(&dir, &options, false);
+        let failed: Vec<_> = results.iter().filter(|r| r.is_failure()).collect();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].path, Path::new("sub/b.md"));
+        assert!(failed[0].to_string().contains("- <p>Paragraph</p>"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}